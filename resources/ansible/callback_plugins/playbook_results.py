@@ -0,0 +1,61 @@
+# Copyright (c) 2023, MaidSafe.
+# All rights reserved.
+#
+# This SAFE Network Software is licensed under the BSD-3-Clause license.
+# Please see the LICENSE file for more details.
+
+# Writes the recap for each host to a JSON file at the end of a run, so the deployer can report the
+# results without parsing the console output.
+#
+# The deployer passes a path that is unique to the run in PLAYBOOK_RESULTS_PATH. Without it, e.g.,
+# when a playbook is run by hand, the results are written to results/<playbook>.json.
+
+import json
+import os
+
+from ansible.plugins.callback import CallbackBase
+
+DOCUMENTATION = """
+    name: playbook_results
+    type: aggregate
+    short_description: write the per-host recap to a JSON file
+    description:
+      - Writes the ok, changed, failed and unreachable counts for each host to a JSON file.
+      - The file is given by PLAYBOOK_RESULTS_PATH, or is results/<playbook>.json if it is not set.
+"""
+
+RESULTS_DIR = "results"
+RESULTS_PATH_ENV_VAR = "PLAYBOOK_RESULTS_PATH"
+
+
+class CallbackModule(CallbackBase):
+    CALLBACK_VERSION = 2.0
+    CALLBACK_TYPE = "aggregate"
+    CALLBACK_NAME = "playbook_results"
+    CALLBACK_NEEDS_ENABLED = True
+
+    def __init__(self):
+        super(CallbackModule, self).__init__()
+        self.playbook_name = None
+
+    def v2_playbook_on_start(self, playbook):
+        self.playbook_name = os.path.splitext(os.path.basename(playbook._file_name))[0]
+
+    def v2_playbook_on_stats(self, stats):
+        if self.playbook_name is None:
+            return
+        results = {}
+        for host in sorted(stats.processed.keys()):
+            summary = stats.summarize(host)
+            results[host] = {
+                "ok": summary["ok"],
+                "changed": summary["changed"],
+                "failed": summary["failures"],
+                "unreachable": summary["unreachable"],
+            }
+        path = os.environ.get(RESULTS_PATH_ENV_VAR)
+        if not path:
+            path = os.path.join(RESULTS_DIR, "%s.json" % self.playbook_name)
+        os.makedirs(os.path.dirname(path) or ".", exist_ok=True)
+        with open(path, "w") as f:
+            json.dump(results, f)
@@ -26,6 +26,17 @@ resource "digitalocean_reserved_ip_assignment" "peer_cache_node_ip" {
   droplet_id  = digitalocean_droplet.peer_cache_node[count.index].id
 }
 
+# Door nodes are the first Peer Cache nodes, given stable DNS names for use as a well-known entry
+# point by mobile clients. The reserved IP is used where one has been assigned.
+resource "digitalocean_record" "door_node" {
+  count  = var.door_node_count
+  domain = var.door_node_dns_domain
+  type   = "A"
+  name   = "${lower(terraform.workspace)}-door-${count.index + 1}"
+  value  = length(var.peer_cache_reserved_ips) > count.index ? var.peer_cache_reserved_ips[count.index] : digitalocean_droplet.peer_cache_node[count.index].ipv4_address
+  ttl    = 300
+}
+
 resource "digitalocean_droplet" "build" {
   count    = var.use_custom_bin ? 1 : 0
   image    = var.build_droplet_image_id
@@ -46,16 +57,68 @@ resource "digitalocean_droplet" "genesis_bootstrap" {
   tags     = ["environment:${terraform.workspace}", "type:genesis"]
 }
 
+# The genesis node and the Peer Cache nodes are given names under the environment's subdomain, e.g.,
+# genesis.<name>.<domain> and <anything>.<name>.<domain>, which resolve to the current VMs if they
+# are replaced. The wildcard has a record for each Peer Cache node, so it is resolved round-robin.
+resource "digitalocean_record" "genesis" {
+  count  = var.dns_domain != "" ? var.genesis_vm_count : 0
+  domain = var.dns_domain
+  type   = "A"
+  name   = "genesis.${lower(terraform.workspace)}"
+  value  = digitalocean_droplet.genesis_bootstrap[count.index].ipv4_address
+  ttl    = 300
+}
+
+resource "digitalocean_record" "peer_cache_node" {
+  count  = var.dns_domain != "" ? var.peer_cache_node_vm_count : 0
+  domain = var.dns_domain
+  type   = "A"
+  name   = "*.${lower(terraform.workspace)}"
+  value  = length(var.peer_cache_reserved_ips) > count.index ? var.peer_cache_reserved_ips[count.index] : digitalocean_droplet.peer_cache_node[count.index].ipv4_address
+  ttl    = 300
+}
+
 resource "digitalocean_droplet" "nat_gateway" {
-  count    = var.setup_nat_gateway ? 1 : 0
+  count    = var.setup_nat_gateway ? var.nat_gateway_count : 0
   image    = var.nat_gateway_droplet_image_id
-  name     = "${terraform.workspace}-nat-gateway"
+  # The first gateway keeps the original name, so existing environments are not recreated.
+  name     = count.index == 0 ? "${terraform.workspace}-nat-gateway" : "${terraform.workspace}-nat-gateway-${count.index + 1}"
   region   = var.region
   size     = var.nat_gateway_droplet_size
   ssh_keys = var.droplet_ssh_keys
   tags     = ["environment:${terraform.workspace}", "type:nat_gateway"]
 }
 
+resource "digitalocean_droplet" "artifact_proxy" {
+  count    = var.setup_artifact_proxy ? 1 : 0
+  image    = var.artifact_proxy_droplet_image_id
+  name     = "${terraform.workspace}-artifact-proxy"
+  region   = var.region
+  size     = var.artifact_proxy_droplet_size
+  ssh_keys = var.droplet_ssh_keys
+  tags     = ["environment:${terraform.workspace}", "type:artifact_proxy"]
+}
+
+resource "digitalocean_droplet" "auditor" {
+  count    = var.auditor_vm_count
+  image    = var.auditor_droplet_image_id
+  name     = "${terraform.workspace}-auditor-${count.index + 1}"
+  region   = var.region
+  size     = var.auditor_droplet_size
+  ssh_keys = var.droplet_ssh_keys
+  tags     = ["environment:${terraform.workspace}", "type:auditor"]
+}
+
+resource "digitalocean_droplet" "monitoring" {
+  count    = var.setup_monitoring ? 1 : 0
+  image    = var.monitoring_droplet_image_id
+  name     = "${terraform.workspace}-monitoring"
+  region   = var.region
+  size     = var.monitoring_droplet_size
+  ssh_keys = var.droplet_ssh_keys
+  tags     = ["environment:${terraform.workspace}", "type:monitoring"]
+}
+
 resource "digitalocean_droplet" "node" {
   count    = var.node_vm_count
   image    = var.node_droplet_image_id
@@ -80,10 +143,27 @@ resource "digitalocean_droplet" "uploader" {
   count    = var.uploader_vm_count
   image    = var.uploader_droplet_image_id
   name     = "${terraform.workspace}-uploader-${count.index + 1}"
+  # The uploaders are assigned to the client regions in turn, so that the benchmarks reflect
+  # clients that are not in the same datacenter as the nodes.
+  region   = length(var.uploader_regions) > 0 ? element(var.uploader_regions, count.index) : var.region
+  size     = var.uploader_bandwidth_class == "premium" ? var.uploader_premium_droplet_size : var.uploader_droplet_size
+  ssh_keys = var.droplet_ssh_keys
+  tags = [
+    "environment:${terraform.workspace}",
+    "type:uploader",
+    "bandwidth:${var.uploader_bandwidth_class}",
+    "region:${length(var.uploader_regions) > 0 ? element(var.uploader_regions, count.index) : var.region}",
+  ]
+}
+
+resource "digitalocean_droplet" "downloader" {
+  count    = var.downloader_vm_count
+  image    = var.downloader_droplet_image_id
+  name     = "${terraform.workspace}-downloader-${count.index + 1}"
   region   = var.region
-  size     = var.uploader_droplet_size
+  size     = var.downloader_droplet_size
   ssh_keys = var.droplet_ssh_keys
-  tags     = ["environment:${terraform.workspace}", "type:uploader"]
+  tags     = ["environment:${terraform.workspace}", "type:downloader"]
 }
 
 resource "digitalocean_droplet" "evm_node" {
@@ -0,0 +1,147 @@
+# The VMs in each group, which the deployer reads with 'terraform output -json' rather than
+# querying the Digital Ocean inventory plugin. The public IP is the droplet's own address, not a
+# reserved IP assigned to it.
+
+output "artifact_proxy_vms" {
+  value = [
+    for droplet in digitalocean_droplet.artifact_proxy : {
+      id         = droplet.id
+      name       = droplet.name
+      private_ip = droplet.ipv4_address_private
+      public_ip  = droplet.ipv4_address
+      vpc_uuid   = droplet.vpc_uuid
+    }
+  ]
+}
+
+output "auditor_vms" {
+  value = [
+    for droplet in digitalocean_droplet.auditor : {
+      id         = droplet.id
+      name       = droplet.name
+      private_ip = droplet.ipv4_address_private
+      public_ip  = droplet.ipv4_address
+      vpc_uuid   = droplet.vpc_uuid
+    }
+  ]
+}
+
+output "build_vms" {
+  value = [
+    for droplet in digitalocean_droplet.build : {
+      id         = droplet.id
+      name       = droplet.name
+      private_ip = droplet.ipv4_address_private
+      public_ip  = droplet.ipv4_address
+      vpc_uuid   = droplet.vpc_uuid
+    }
+  ]
+}
+
+output "downloader_vms" {
+  value = [
+    for droplet in digitalocean_droplet.downloader : {
+      id         = droplet.id
+      name       = droplet.name
+      private_ip = droplet.ipv4_address_private
+      public_ip  = droplet.ipv4_address
+      vpc_uuid   = droplet.vpc_uuid
+    }
+  ]
+}
+
+output "evm_node_vms" {
+  value = [
+    for droplet in digitalocean_droplet.evm_node : {
+      id         = droplet.id
+      name       = droplet.name
+      private_ip = droplet.ipv4_address_private
+      public_ip  = droplet.ipv4_address
+      vpc_uuid   = droplet.vpc_uuid
+    }
+  ]
+}
+
+output "genesis_vms" {
+  value = [
+    for droplet in digitalocean_droplet.genesis_bootstrap : {
+      id         = droplet.id
+      name       = droplet.name
+      private_ip = droplet.ipv4_address_private
+      public_ip  = droplet.ipv4_address
+      vpc_uuid   = droplet.vpc_uuid
+    }
+  ]
+}
+
+output "monitoring_vms" {
+  value = [
+    for droplet in digitalocean_droplet.monitoring : {
+      id         = droplet.id
+      name       = droplet.name
+      private_ip = droplet.ipv4_address_private
+      public_ip  = droplet.ipv4_address
+      vpc_uuid   = droplet.vpc_uuid
+    }
+  ]
+}
+
+output "nat_gateway_vms" {
+  value = [
+    for droplet in digitalocean_droplet.nat_gateway : {
+      id         = droplet.id
+      name       = droplet.name
+      private_ip = droplet.ipv4_address_private
+      public_ip  = droplet.ipv4_address
+      vpc_uuid   = droplet.vpc_uuid
+    }
+  ]
+}
+
+output "node_vms" {
+  value = [
+    for droplet in digitalocean_droplet.node : {
+      id         = droplet.id
+      name       = droplet.name
+      private_ip = droplet.ipv4_address_private
+      public_ip  = droplet.ipv4_address
+      vpc_uuid   = droplet.vpc_uuid
+    }
+  ]
+}
+
+output "peer_cache_node_vms" {
+  value = [
+    for droplet in digitalocean_droplet.peer_cache_node : {
+      id         = droplet.id
+      name       = droplet.name
+      private_ip = droplet.ipv4_address_private
+      public_ip  = droplet.ipv4_address
+      vpc_uuid   = droplet.vpc_uuid
+    }
+  ]
+}
+
+output "private_node_vms" {
+  value = [
+    for droplet in digitalocean_droplet.private_node : {
+      id         = droplet.id
+      name       = droplet.name
+      private_ip = droplet.ipv4_address_private
+      public_ip  = droplet.ipv4_address
+      vpc_uuid   = droplet.vpc_uuid
+    }
+  ]
+}
+
+output "uploader_vms" {
+  value = [
+    for droplet in digitalocean_droplet.uploader : {
+      id         = droplet.id
+      name       = droplet.name
+      private_ip = droplet.ipv4_address_private
+      public_ip  = droplet.ipv4_address
+      vpc_uuid   = droplet.vpc_uuid
+    }
+  ]
+}
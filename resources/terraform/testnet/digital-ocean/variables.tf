@@ -36,6 +36,26 @@ variable "uploader_droplet_size" {
   description = "The size of the droplet for uploader VMs"
 }
 
+variable "uploader_bandwidth_class" {
+  description = "The network performance class of the uploader droplets: standard or premium"
+  default     = "standard"
+  validation {
+    condition     = contains(["standard", "premium"], var.uploader_bandwidth_class)
+    error_message = "The uploader bandwidth class must be either standard or premium"
+  }
+}
+
+variable "uploader_premium_droplet_size" {
+  description = "The size of the droplet for uploader VMs when the premium bandwidth class is used"
+  default     = "s-8vcpu-16gb-intel"
+}
+
+variable "uploader_regions" {
+  type        = list(string)
+  description = "The regions the uploader VMs are spread across. They use the node region if empty"
+  default     = []
+}
+
 variable "build_machine_size" {
   default = "s-8vcpu-16gb"
 }
@@ -79,6 +99,11 @@ variable "node_vm_count" {
   description = "The number of droplets to launch for nodes"
 }
 
+variable "nat_gateway_count" {
+  default     = 1
+  description = "The number of NAT gateway droplets the private nodes are spread across"
+}
+
 variable "private_node_vm_count" {
   default     = 1
   description = "The number of droplets to launch for private nodes"
@@ -147,4 +172,82 @@ variable "peer_cache_reserved_ips" {
   type = list(string)
   description = "List of reserved IPs for the peer nodes"
   default = []
-}
\ No newline at end of file
+}
+
+variable "door_node_count" {
+  default     = 0
+  description = "The number of Peer Cache nodes that will be given a stable DNS name as door nodes"
+}
+
+variable "door_node_dns_domain" {
+  default     = ""
+  description = "The DigitalOcean managed domain under which the door node records are created"
+}
+
+variable "dns_domain" {
+  default     = ""
+  description = "The DigitalOcean managed domain under which the genesis and Peer Cache records are created"
+}
+
+variable "setup_monitoring" {
+  type        = bool
+  default     = false
+  description = "A boolean to enable the monitoring VM, which runs Prometheus and Grafana"
+}
+
+variable "monitoring_droplet_size" {
+  description = "The size of the droplet for the monitoring VM"
+  default     = "s-2vcpu-4gb"
+}
+
+variable "monitoring_droplet_image_id" {
+  description = "The image for the monitoring VM. Prometheus and Grafana are installed by Ansible."
+  default     = "ubuntu-22-04-x64"
+}
+
+variable "auditor_vm_count" {
+  type        = number
+  default     = 0
+  description = "The number of VMs that run the auditor against the network"
+}
+
+variable "auditor_droplet_size" {
+  description = "The size of the droplet for the auditor VMs"
+  default     = "s-2vcpu-2gb"
+}
+
+variable "auditor_droplet_image_id" {
+  description = "The image for the auditor VMs. The auditor is installed by Ansible."
+  default     = "ubuntu-22-04-x64"
+}
+
+variable "setup_artifact_proxy" {
+  type        = bool
+  default     = false
+  description = "A boolean to enable the artifact proxy VM, which caches the binary archives from S3"
+}
+
+variable "artifact_proxy_droplet_size" {
+  description = "The size of the droplet for the artifact proxy VM"
+  default     = "s-2vcpu-4gb"
+}
+
+variable "artifact_proxy_droplet_image_id" {
+  description = "The image for the artifact proxy VM. Nginx is installed by Ansible."
+  default     = "ubuntu-22-04-x64"
+}
+
+variable "downloader_vm_count" {
+  default     = 0
+  description = "The number of droplets to launch for downloaders, which fetch and verify uploaded data"
+}
+
+variable "downloader_droplet_size" {
+  description = "The size of the droplet for downloader VMs"
+  default     = "s-2vcpu-4gb"
+}
+
+variable "downloader_droplet_image_id" {
+  description = "The image for downloader VMs. The ant binary is installed by Ansible."
+  default     = "ubuntu-22-04-x64"
+}
@@ -4,24 +4,29 @@
 // This SAFE Network Software is licensed under the BSD-3-Clause license.
 // Please see the LICENSE file for more details.
 
-use crate::inventory::VirtualMachine;
-use crate::NodeType;
-use crate::{ansible::provisioning::ProvisionOptions, CloudProvider, EvmNetwork};
-use crate::{BinaryOption, Error, Result};
+use crate::artifact_repository::{ArtifactKind, ArtifactRepository, ArtifactRepositoryConfig};
+use crate::downloaders::{
+    get_upload_manifest_prefix, DownloaderDeployOptions, UPLOAD_MANIFEST_BUCKET_NAME,
+    UPLOAD_MANIFEST_BUCKET_REGION,
+};
+use crate::inventory::{VirtualMachine, DOOR_NODE_START_PORT};
+use crate::join_rate::JoinRateSchedule;
+use crate::local_binaries::{get_local_archive_filename, LOCAL_BINARIES_URL_PATH};
+use crate::nat_gateway::{get_full_cone_port_ranges, NatPortRange};
+use crate::{ansible::provisioning::ProvisionOptions, Architecture, CloudProvider, EvmNetwork};
+use crate::{BinaryOption, Error, ReachabilityMode, RestartPolicy, Result, TelemetryConfig};
+use crate::{NatType, NodeType};
 use alloy::hex::ToHexExt;
 use alloy::signers::local::PrivateKeySigner;
 use serde_json::Value;
-use std::collections::HashMap;
-
-const ANT_S3_BUCKET_URL: &str = "https://autonomi-cli.s3.eu-west-2.amazonaws.com";
-const ANTCTL_S3_BUCKET_URL: &str = "https://antctl.s3.eu-west-2.amazonaws.com";
-// The old `sn-node` S3 bucket will continue to be used to store custom branch builds.
-// They are stored in here regardless of which binary they are.
-const BRANCH_S3_BUCKET_URL: &str = "https://sn-node.s3.eu-west-2.amazonaws.com";
-const RPC_CLIENT_BUCKET_URL: &str = "https://antnode-rpc-client.s3.eu-west-2.amazonaws.com";
+use std::{collections::HashMap, net::IpAddr, sync::Arc, time::Duration};
 
 #[derive(Default, Clone)]
 pub struct ExtraVarsDocBuilder {
+    architecture: Architecture,
+    artifact_proxy_url: Option<String>,
+    /// Where the binary archives are downloaded from. The main S3 buckets are used if not set.
+    artifact_repository: Option<Arc<dyn ArtifactRepository>>,
     map: serde_json::Map<String, Value>,
 }
 
@@ -30,6 +35,31 @@ impl ExtraVarsDocBuilder {
         Default::default()
     }
 
+    /// Use the binary archives for the given architecture.
+    ///
+    /// This must be set before any of the archive URLs are added.
+    pub fn set_architecture(&mut self, architecture: Architecture) -> &mut Self {
+        self.architecture = architecture;
+        self
+    }
+
+    /// Download the binary archives through the artifact proxy at the given URL, rather than
+    /// directly from S3.
+    ///
+    /// This must be set before any of the archive URLs are added.
+    pub fn set_artifact_proxy_url(&mut self, proxy_url: Option<String>) -> &mut Self {
+        self.artifact_proxy_url = proxy_url;
+        self
+    }
+
+    /// Download the binary archives from the given repository.
+    ///
+    /// This must be set before any of the archive URLs are added.
+    pub fn set_artifact_repository(&mut self, config: &ArtifactRepositoryConfig) -> &mut Self {
+        self.artifact_repository = (!config.is_main()).then(|| config.build());
+        self
+    }
+
     pub fn add_variable(&mut self, name: &str, value: &str) -> &mut Self {
         self.map
             .insert(name.to_owned(), Value::String(value.to_owned()));
@@ -94,7 +124,7 @@ impl ExtraVarsDocBuilder {
                     self.add_variable("payment_forward_pk", &network_keys.3);
                 }
             }
-            BinaryOption::Versioned { .. } => {
+            BinaryOption::Local { .. } | BinaryOption::Versioned { .. } => {
                 self.add_variable("custom_bin", "false");
             }
         }
@@ -112,19 +142,24 @@ impl ExtraVarsDocBuilder {
                 self.add_branch_url_variable(
                     "antnode_rpc_client_archive_url",
                     &format!(
-                        "{}/{}/{}/antnode_rpc_client-{}-x86_64-unknown-linux-musl.tar.gz",
-                        BRANCH_S3_BUCKET_URL, repo_owner, branch, deployment_name
+                        "{}/{}/{}/antnode_rpc_client-{}-{}.tar.gz",
+                        self.get_base_url(ArtifactKind::Branch),
+                        repo_owner,
+                        branch,
+                        deployment_name,
+                        self.architecture.target_triple()
                     ),
                     branch,
                     repo_owner,
                 );
             }
             _ => {
-                self.add_variable(
+                self.add_archive_url_variable(
                     "antnode_rpc_client_archive_url",
                     &format!(
-                        "{}/antnode_rpc_client-latest-x86_64-unknown-linux-musl.tar.gz",
-                        RPC_CLIENT_BUCKET_URL
+                        "{}/antnode_rpc_client-latest-{}.tar.gz",
+                        self.get_base_url(ArtifactKind::RpcClient),
+                        self.architecture.target_triple()
                     ),
                 );
             }
@@ -139,8 +174,12 @@ impl ExtraVarsDocBuilder {
                 self.add_branch_url_variable(
                     "node_archive_url",
                     &format!(
-                        "{}/{}/{}/antnode-{}-x86_64-unknown-linux-musl.tar.gz",
-                        BRANCH_S3_BUCKET_URL, repo_owner, branch, deployment_name
+                        "{}/{}/{}/antnode-{}-{}.tar.gz",
+                        self.get_base_url(ArtifactKind::Branch),
+                        repo_owner,
+                        branch,
+                        deployment_name,
+                        self.architecture.target_triple()
                     ),
                     branch,
                     repo_owner,
@@ -149,7 +188,24 @@ impl ExtraVarsDocBuilder {
             BinaryOption::Versioned {
                 antnode_version, ..
             } => {
-                let _ = self.add_variable("version", &antnode_version.to_string());
+                // The node manager downloads a version from S3 itself, so the URL has to be used
+                // for the download to go through the proxy, or to come from another repository.
+                if self.artifact_proxy_url.is_some() || self.artifact_repository.is_some() {
+                    self.add_archive_url_variable(
+                        "node_archive_url",
+                        &format!(
+                            "{}/antnode-{}-{}.tar.gz",
+                            self.get_base_url(ArtifactKind::Antnode),
+                            antnode_version,
+                            self.architecture.target_triple()
+                        ),
+                    );
+                } else {
+                    let _ = self.add_variable("version", &antnode_version.to_string());
+                }
+            }
+            BinaryOption::Local { .. } => {
+                self.add_local_archive_url_variable("node_archive_url", "antnode");
             }
         }
     }
@@ -162,19 +218,28 @@ impl ExtraVarsDocBuilder {
                 self.add_branch_url_variable(
                     "antctl_archive_url",
                     &format!(
-                        "{}/{}/{}/antctl-{}-x86_64-unknown-linux-musl.tar.gz",
-                        BRANCH_S3_BUCKET_URL, repo_owner, branch, deployment_name
+                        "{}/{}/{}/antctl-{}-{}.tar.gz",
+                        self.get_base_url(ArtifactKind::Branch),
+                        repo_owner,
+                        branch,
+                        deployment_name,
+                        self.architecture.target_triple()
                     ),
                     branch,
                     repo_owner,
                 );
             }
+            BinaryOption::Local { .. } => {
+                self.add_local_archive_url_variable("antctl_archive_url", "antctl");
+            }
             BinaryOption::Versioned { antctl_version, .. } => {
-                self.add_variable(
+                self.add_archive_url_variable(
                     "antctl_archive_url",
                     &format!(
-                        "{}/antctl-{}-x86_64-unknown-linux-musl.tar.gz",
-                        ANTCTL_S3_BUCKET_URL, antctl_version
+                        "{}/antctl-{}-{}.tar.gz",
+                        self.get_base_url(ArtifactKind::Antctl),
+                        antctl_version,
+                        self.architecture.target_triple()
                     ),
                 );
             }
@@ -189,19 +254,28 @@ impl ExtraVarsDocBuilder {
                 self.add_branch_url_variable(
                     "antctld_archive_url",
                     &format!(
-                        "{}/{}/{}/antctld-{}-x86_64-unknown-linux-musl.tar.gz",
-                        BRANCH_S3_BUCKET_URL, repo_owner, branch, deployment_name
+                        "{}/{}/{}/antctld-{}-{}.tar.gz",
+                        self.get_base_url(ArtifactKind::Branch),
+                        repo_owner,
+                        branch,
+                        deployment_name,
+                        self.architecture.target_triple()
                     ),
                     branch,
                     repo_owner,
                 );
             }
+            BinaryOption::Local { .. } => {
+                self.add_local_archive_url_variable("antctld_archive_url", "antctld");
+            }
             BinaryOption::Versioned { antctl_version, .. } => {
-                self.add_variable(
+                self.add_archive_url_variable(
                     "antctld_archive_url",
                     &format!(
-                        "{}/antctld-{}-x86_64-unknown-linux-musl.tar.gz",
-                        ANTCTL_S3_BUCKET_URL, antctl_version
+                        "{}/antctld-{}-{}.tar.gz",
+                        self.get_base_url(ArtifactKind::Antctl),
+                        antctl_version,
+                        self.architecture.target_triple()
                     ),
                 );
             }
@@ -218,11 +292,13 @@ impl ExtraVarsDocBuilder {
         // In that scenario, the safe version in the binary option is not set to the correct value
         // because it is not recorded in the inventory.
         if let Some(version) = ant_version {
-            self.add_variable(
+            self.add_archive_url_variable(
                 "ant_archive_url",
                 &format!(
-                    "{}/ant-{}-x86_64-unknown-linux-musl.tar.gz",
-                    ANT_S3_BUCKET_URL, version
+                    "{}/ant-{}-{}.tar.gz",
+                    self.get_base_url(ArtifactKind::Ant),
+                    version,
+                    self.architecture.target_triple()
                 ),
             );
             return Ok(());
@@ -235,21 +311,34 @@ impl ExtraVarsDocBuilder {
                 self.add_branch_url_variable(
                     "ant_archive_url",
                     &format!(
-                        "{}/{}/{}/ant-{}-x86_64-unknown-linux-musl.tar.gz",
-                        BRANCH_S3_BUCKET_URL, repo_owner, branch, deployment_name
+                        "{}/{}/{}/ant-{}-{}.tar.gz",
+                        self.get_base_url(ArtifactKind::Branch),
+                        repo_owner,
+                        branch,
+                        deployment_name,
+                        self.architecture.target_triple()
                     ),
                     branch,
                     repo_owner,
                 );
                 Ok(())
             }
+            BinaryOption::Local { ant_path, .. } => match ant_path {
+                Some(_) => {
+                    self.add_local_archive_url_variable("ant_archive_url", "ant");
+                    Ok(())
+                }
+                None => Err(Error::NoUploadersError),
+            },
             BinaryOption::Versioned { ant_version, .. } => match ant_version {
                 Some(version) => {
-                    self.add_variable(
+                    self.add_archive_url_variable(
                         "ant_archive_url",
                         &format!(
-                            "{}/ant-{}-x86_64-unknown-linux-musl.tar.gz",
-                            ANT_S3_BUCKET_URL, version
+                            "{}/ant-{}-{}.tar.gz",
+                            self.get_base_url(ArtifactKind::Ant),
+                            version,
+                            self.architecture.target_triple()
                         ),
                     );
                     Ok(())
@@ -259,6 +348,51 @@ impl ExtraVarsDocBuilder {
         }
     }
 
+    pub fn add_telemetry_variables(&mut self, telemetry: &TelemetryConfig) -> &mut Self {
+        self.add_variable("telemetry_backend", telemetry.backend_name());
+        match telemetry {
+            TelemetryConfig::Logstash { stack_name, hosts } => {
+                self.add_variable("logstash_stack_name", stack_name);
+                self.add_list_variable(
+                    "logstash_hosts",
+                    hosts.iter().map(|s| s.to_string()).collect::<Vec<String>>(),
+                );
+            }
+            TelemetryConfig::Loki { url } => {
+                self.add_variable("loki_url", url);
+            }
+            TelemetryConfig::Otlp { endpoint } => {
+                self.add_variable("otlp_endpoint", endpoint);
+            }
+        }
+        self
+    }
+
+    /// Add the S3 location of the upload manifests, along with the AWS credentials used to read and
+    /// write them.
+    ///
+    /// The credentials are taken from the environment. If they are not available, nothing is added
+    /// and `false` is returned.
+    pub fn add_upload_manifest_variables(&mut self, environment_name: &str) -> bool {
+        let (Ok(access_key_id), Ok(secret_access_key)) = (
+            std::env::var("AWS_ACCESS_KEY_ID"),
+            std::env::var("AWS_SECRET_ACCESS_KEY"),
+        ) else {
+            return false;
+        };
+        self.add_variable("aws_access_key_id", &access_key_id);
+        self.add_variable("aws_secret_access_key", &secret_access_key);
+        self.add_variable("aws_region", UPLOAD_MANIFEST_BUCKET_REGION);
+        self.add_variable(
+            "upload_manifest_s3_uri",
+            &format!(
+                "s3://{UPLOAD_MANIFEST_BUCKET_NAME}/{}/",
+                get_upload_manifest_prefix(environment_name)
+            ),
+        );
+        true
+    }
+
     pub fn build(&self) -> String {
         Value::Object(self.map.clone()).to_string()
     }
@@ -266,14 +400,159 @@ impl ExtraVarsDocBuilder {
     fn add_branch_url_variable(&mut self, name: &str, value: &str, branch: &str, repo_owner: &str) {
         self.add_variable("branch", branch);
         self.add_variable("org", repo_owner);
-        self.add_variable(name, value);
+        self.add_archive_url_variable(name, value);
+    }
+
+    /// The local binaries are served by the artifact proxy, which they are uploaded to before any
+    /// of the other VMs are provisioned.
+    fn add_local_archive_url_variable(&mut self, name: &str, bin_name: &str) {
+        let proxy_url = self.artifact_proxy_url.clone().unwrap_or_default();
+        self.add_variable(
+            name,
+            &format!(
+                "{proxy_url}/{LOCAL_BINARIES_URL_PATH}/{}",
+                get_local_archive_filename(bin_name, self.architecture)
+            ),
+        );
+    }
+
+    fn get_base_url(&self, kind: ArtifactKind) -> String {
+        match &self.artifact_repository {
+            Some(repository) => repository.get_base_url(kind),
+            None => ArtifactRepositoryConfig::Main.build().get_base_url(kind),
+        }
+    }
+
+    /// The proxy serves each bucket under a path named after its host, so
+    /// `https://<bucket-host>/<object>` becomes `<proxy-url>/<bucket-host>/<object>`.
+    fn add_archive_url_variable(&mut self, name: &str, url: &str) {
+        let url = match (&self.artifact_proxy_url, url.strip_prefix("https://")) {
+            (Some(proxy_url), Some(bucket_path)) => format!("{proxy_url}/{bucket_path}"),
+            _ => url.to_string(),
+        };
+        self.add_variable(name, &url);
     }
 }
 
-pub fn build_nat_gateway_extra_vars_doc(name: &str, private_ips: Vec<String>) -> String {
+pub fn build_nat_gateway_extra_vars_doc(
+    name: &str,
+    private_ips: Vec<String>,
+    nat_type: NatType,
+    port_ranges: &[NatPortRange],
+    enable_upnp: bool,
+) -> Result<String> {
     let mut extra_vars = ExtraVarsDocBuilder::default();
     extra_vars.add_variable("testnet_name", name);
     extra_vars.add_list_variable("node_private_ips_eth1", private_ips);
+    extra_vars.add_variable("nat_type", nat_type.as_str());
+    if matches!(nat_type, NatType::FullCone) {
+        extra_vars.add_serde_value("nat_port_ranges", serde_json::to_value(port_ranges)?);
+    }
+    if enable_upnp {
+        extra_vars.add_variable("enable_upnp", "true");
+    }
+    Ok(extra_vars.build())
+}
+
+pub fn build_dns_config_extra_vars_doc(
+    dns_resolvers: &[IpAddr],
+    host_entries: &[(String, IpAddr)],
+    ntp_servers: &[IpAddr],
+) -> String {
+    let mut extra_vars = ExtraVarsDocBuilder::default();
+    extra_vars.add_list_variable(
+        "dns_resolvers",
+        dns_resolvers.iter().map(|ip| ip.to_string()).collect(),
+    );
+    extra_vars.add_list_variable(
+        "host_entries",
+        host_entries
+            .iter()
+            .map(|(hostname, ip)| format!("{ip} {hostname}"))
+            .collect(),
+    );
+    extra_vars.add_list_variable(
+        "ntp_servers",
+        ntp_servers.iter().map(|ip| ip.to_string()).collect(),
+    );
+    extra_vars.build()
+}
+
+pub fn build_hardening_extra_vars_doc(ssh_user: &str, disable_root_login: bool) -> String {
+    let mut extra_vars = ExtraVarsDocBuilder::default();
+    extra_vars.add_variable("ssh_user", ssh_user);
+    extra_vars.add_variable("disable_root_login", &disable_root_login.to_string());
+    extra_vars.build()
+}
+
+pub fn build_artifact_proxy_extra_vars_doc(
+    name: &str,
+    artifact_repository: &ArtifactRepositoryConfig,
+) -> String {
+    let mut extra_vars = ExtraVarsDocBuilder::default();
+    extra_vars.add_variable("testnet_name", name);
+    if !artifact_repository.is_main() {
+        extra_vars.add_list_variable(
+            "artifact_proxy_extra_buckets",
+            artifact_repository.get_hosts(),
+        );
+    }
+    extra_vars.build()
+}
+
+pub fn build_auditor_extra_vars_doc(
+    name: &str,
+    genesis_multiaddr: &str,
+    architecture: Architecture,
+) -> String {
+    let mut extra_vars = ExtraVarsDocBuilder::default();
+    extra_vars.add_variable("testnet_name", name);
+    extra_vars.add_variable("genesis_multiaddr", genesis_multiaddr);
+    extra_vars.add_variable("target_triple", architecture.target_triple());
+    extra_vars.build()
+}
+
+pub fn build_infra_services_extra_vars_doc(
+    name: &str,
+    upstream_dns_resolvers: &[IpAddr],
+) -> String {
+    let mut extra_vars = ExtraVarsDocBuilder::default();
+    extra_vars.add_variable("testnet_name", name);
+    if !upstream_dns_resolvers.is_empty() {
+        extra_vars.add_list_variable(
+            "infra_services_upstream_dns",
+            upstream_dns_resolvers
+                .iter()
+                .map(|ip| ip.to_string())
+                .collect(),
+        );
+    }
+    extra_vars.build()
+}
+
+pub fn build_monitoring_extra_vars_doc(
+    name: &str,
+    scrape_targets: Vec<String>,
+    grafana_admin_password: &str,
+) -> String {
+    let mut extra_vars = ExtraVarsDocBuilder::default();
+    extra_vars.add_variable("testnet_name", name);
+    extra_vars.add_variable("grafana_admin_password", grafana_admin_password);
+    extra_vars.add_list_variable("node_metrics_targets", scrape_targets);
+    extra_vars.build()
+}
+
+pub fn build_peer_filter_extra_vars_doc(
+    allowed_peer_ips: Vec<String>,
+    denied_peer_ips: Vec<String>,
+    restart_nodes: bool,
+    interval: Duration,
+) -> String {
+    let mut extra_vars = ExtraVarsDocBuilder::default();
+    extra_vars.add_list_variable("allowed_peer_ips", allowed_peer_ips);
+    extra_vars.add_list_variable("denied_peer_ips", denied_peer_ips);
+    extra_vars.add_variable("restart_nodes", &restart_nodes.to_string());
+    extra_vars.add_variable("interval", &interval.as_millis().to_string());
     extra_vars.build()
 }
 
@@ -286,8 +565,12 @@ pub fn build_node_extra_vars_doc(
     network_contacts_url: Option<String>,
     node_instance_count: u16,
     evm_network: EvmNetwork,
+    join_rate_schedule: Option<&JoinRateSchedule>,
 ) -> Result<String> {
     let mut extra_vars = ExtraVarsDocBuilder::default();
+    extra_vars.set_architecture(options.architecture);
+    extra_vars.set_artifact_proxy_url(options.artifact_proxy_url.clone());
+    extra_vars.set_artifact_repository(&options.artifact_repository);
     extra_vars.add_variable("provider", cloud_provider);
     extra_vars.add_variable("testnet_name", &options.name);
     extra_vars.add_variable("node_type", node_type.telegraf_role());
@@ -299,7 +582,26 @@ pub fn build_node_extra_vars_doc(
     }
 
     extra_vars.add_variable("node_instance_count", &node_instance_count.to_string());
-    extra_vars.add_variable("interval", &options.interval.as_millis().to_string());
+    match join_rate_schedule {
+        Some(schedule) => {
+            extra_vars.add_variable("interval", &schedule.interval.as_millis().to_string());
+            extra_vars.add_serde_value(
+                "join_start_delays",
+                Value::Object(
+                    schedule
+                        .start_delays
+                        .iter()
+                        .map(|(addr, delay)| {
+                            (addr.to_string(), Value::from(delay.as_millis() as u64))
+                        })
+                        .collect(),
+                ),
+            );
+        }
+        None => {
+            extra_vars.add_variable("interval", &options.interval.as_millis().to_string());
+        }
+    }
     if let Some(log_format) = options.log_format {
         extra_vars.add_variable("log_format", log_format.as_str());
     } else {
@@ -321,9 +623,44 @@ pub fn build_node_extra_vars_doc(
             &nat_gateway.private_ip_addr.to_string(),
         );
         extra_vars.add_variable("make_vm_private", "true");
+        extra_vars.add_variable("nat_type", options.nat_type.as_str());
+        if matches!(options.nat_type, NatType::FullCone) {
+            extra_vars.add_serde_value(
+                "nat_port_ranges",
+                serde_json::to_value(get_full_cone_port_ranges(&options.private_node_vms)?)?,
+            );
+        }
     } else if matches!(node_type, NodeType::Private) {
         return Err(Error::NatGatewayNotSupplied);
     }
+
+    if matches!(node_type, NodeType::PeerCache) && !options.door_node_vms.is_empty() {
+        extra_vars.add_list_variable(
+            "door_node_hosts",
+            options
+                .door_node_vms
+                .iter()
+                .map(|vm| vm.public_ip_addr.to_string())
+                .collect(),
+        );
+        extra_vars.add_variable("door_node_start_port", &DOOR_NODE_START_PORT.to_string());
+    }
+
+    let reachability = match node_type {
+        NodeType::Generic => options.node_reachability,
+        NodeType::PeerCache => options.peer_cache_node_reachability,
+        NodeType::Private if options.private_node_upnp => ReachabilityMode::Upnp,
+        NodeType::Genesis | NodeType::Private => ReachabilityMode::Direct,
+    };
+    match reachability {
+        ReachabilityMode::Direct => {}
+        ReachabilityMode::RelayClient => {
+            extra_vars.add_variable("home_network", "true");
+        }
+        ReachabilityMode::Upnp => {
+            extra_vars.add_variable("upnp", "true");
+        }
+    }
     if let Some(network_id) = options.network_id {
         extra_vars.add_variable("network_id", &network_id.to_string());
     }
@@ -336,15 +673,8 @@ pub fn build_node_extra_vars_doc(
         extra_vars.add_env_variable_list("env_variables", env_vars.clone());
     }
 
-    if let Some((logstash_stack_name, logstash_hosts)) = &options.logstash_details {
-        extra_vars.add_variable("logstash_stack_name", logstash_stack_name);
-        extra_vars.add_list_variable(
-            "logstash_hosts",
-            logstash_hosts
-                .iter()
-                .map(|s| s.to_string())
-                .collect::<Vec<String>>(),
-        );
+    if let Some(telemetry) = &options.telemetry {
+        extra_vars.add_telemetry_variables(telemetry);
     }
 
     extra_vars.add_variable("rewards_address", &options.rewards_address);
@@ -370,8 +700,12 @@ pub fn build_uploaders_extra_vars_doc(
     sk_map: &HashMap<VirtualMachine, Vec<PrivateKeySigner>>,
 ) -> Result<String> {
     let mut extra_vars: ExtraVarsDocBuilder = ExtraVarsDocBuilder::default();
+    extra_vars.set_architecture(options.architecture);
+    extra_vars.set_artifact_proxy_url(options.artifact_proxy_url.clone());
+    extra_vars.set_artifact_repository(&options.artifact_repository);
     extra_vars.add_variable("provider", cloud_provider);
     extra_vars.add_variable("testnet_name", &options.name);
+    extra_vars.add_variable("target_triple", options.architecture.target_triple());
     if let Some(genesis_multiaddr) = genesis_multiaddr {
         extra_vars.add_variable("genesis_multiaddr", &genesis_multiaddr);
     }
@@ -418,10 +752,88 @@ pub fn build_uploaders_extra_vars_doc(
     let serde_map = Value::Object(serde_map);
 
     extra_vars.add_serde_value("ant_secret_key_map", serde_map);
+    if !extra_vars.add_upload_manifest_variables(&options.name) {
+        tracing::warn!(
+            "The AWS credentials are not set, so the upload manifest will not be published"
+        );
+    }
 
     Ok(extra_vars.build())
 }
 
+pub fn build_download_verifiers_extra_vars_doc(
+    cloud_provider: &str,
+    options: &DownloaderDeployOptions,
+    artifact_proxy_url: Option<String>,
+    genesis_multiaddr: Option<String>,
+    genesis_network_contacts_url: Option<String>,
+) -> Result<String> {
+    let architecture = options
+        .current_inventory
+        .environment_details
+        .architecture
+        .unwrap_or_default();
+    let mut extra_vars = ExtraVarsDocBuilder::default();
+    extra_vars.set_architecture(architecture);
+    extra_vars.set_artifact_proxy_url(artifact_proxy_url);
+    extra_vars.set_artifact_repository(
+        &options
+            .current_inventory
+            .environment_details
+            .artifact_repository
+            .clone()
+            .unwrap_or_default(),
+    );
+    extra_vars.add_variable("provider", cloud_provider);
+    extra_vars.add_variable("testnet_name", &options.current_inventory.name);
+    extra_vars.add_variable("target_triple", architecture.target_triple());
+    if let Some(genesis_multiaddr) = genesis_multiaddr {
+        extra_vars.add_variable("genesis_multiaddr", &genesis_multiaddr);
+    }
+    if let Some(network_contacts_url) = genesis_network_contacts_url {
+        extra_vars.add_variable("network_contacts_url", &network_contacts_url);
+    }
+    if let Some(network_id) = options.current_inventory.environment_details.network_id {
+        extra_vars.add_variable("network_id", &network_id.to_string());
+    }
+    extra_vars.add_ant_url_or_version(
+        &options.current_inventory.name,
+        &options.current_inventory.binary_option,
+        Some(options.ant_version.clone()),
+    )?;
+    extra_vars.add_variable(
+        "ant_download_verifier_instances",
+        &options.downloaders_per_vm.to_string(),
+    );
+    if !extra_vars.add_upload_manifest_variables(&options.current_inventory.name) {
+        return Err(Error::AwsCredentialsNotSupplied);
+    }
+    Ok(extra_vars.build())
+}
+
+pub fn build_restart_policy_extra_vars_doc(restart_policy: &RestartPolicy) -> String {
+    let mut extra_vars = ExtraVarsDocBuilder::default();
+    if let Some(restart) = restart_policy.restart {
+        extra_vars.add_variable("restart_mode", restart.as_str());
+    }
+    if let Some(restart_sec) = restart_policy.restart_sec {
+        extra_vars.add_variable("restart_sec", &restart_sec.to_string());
+    }
+    if let Some(start_limit_burst) = restart_policy.start_limit_burst {
+        extra_vars.add_variable("start_limit_burst", &start_limit_burst.to_string());
+    }
+    extra_vars.build()
+}
+
+pub fn build_rpc_proxy_extra_vars_doc(auth_token: &str, acme_email: Option<&str>) -> String {
+    let mut extra_vars = ExtraVarsDocBuilder::default();
+    extra_vars.add_variable("rpc_proxy_auth_token", auth_token);
+    if let Some(acme_email) = acme_email {
+        extra_vars.add_variable("rpc_proxy_acme_email", acme_email);
+    }
+    extra_vars.build()
+}
+
 pub fn build_start_or_stop_uploader_extra_vars_doc(
     cloud_provider: &str,
     options: &ProvisionOptions,
@@ -441,6 +853,11 @@ pub fn build_start_or_stop_uploader_extra_vars_doc(
 pub fn build_binaries_extra_vars_doc(options: &ProvisionOptions) -> Result<String> {
     let mut extra_vars = ExtraVarsDocBuilder::default();
     extra_vars.add_build_variables(&options.name, &options.binary_option);
+    extra_vars.add_variable("target_triple", options.architecture.target_triple());
+    if let Some(build_cache_key) = &options.build_cache_key {
+        extra_vars.add_variable("commit_sha", &build_cache_key.commit_sha);
+        extra_vars.add_variable("build_cache_key", &build_cache_key.key);
+    }
     if let Some(chunk_size) = options.chunk_size {
         extra_vars.add_variable("chunk_size", &chunk_size.to_string());
     }
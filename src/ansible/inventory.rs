@@ -6,22 +6,30 @@
 
 use super::AnsibleRunner;
 use crate::{
-    ansible::AnsibleBinary, error::Error, inventory::VirtualMachine, run_external_command, Result,
+    ansible::AnsibleBinary, error::Error, inventory::VirtualMachine, run_external_command,
+    CloudProvider, Result,
 };
-use log::{debug, error, warn};
 use serde::Deserialize;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs::File,
     io::{BufWriter, Write},
     net::IpAddr,
     path::{Path, PathBuf},
     time::Duration,
 };
+use tracing::{debug, error, warn};
 
 /// Represents the inventory types that apply to our own domain.
 #[derive(Clone, Debug, Copy)]
 pub enum AnsibleInventoryType {
+    /// Use to run a playbook against the artifact proxy, which caches the binary archives from S3.
+    ///
+    /// Only one machine will be returned in this inventory.
+    ArtifactProxy,
+    /// Use to run a playbook against the auditor machines, which audit the network from the
+    /// genesis node.
+    Auditor,
     /// Use to run a playbook against the build machine.
     ///
     /// This is a larger machine that is used for building binaries from source.
@@ -30,6 +38,9 @@ pub enum AnsibleInventoryType {
     Build,
     /// Provide a static list of VMs to connect to.
     Custom,
+    /// Use to run a playbook against all the downloader machines, which fetch and verify the data
+    /// uploaded by the uploaders.
+    Downloaders,
     /// Use to run a playbook against all EVM nodes.
     EvmNodes,
     /// Use to run a playbook against the genesis node.
@@ -38,6 +49,10 @@ pub enum AnsibleInventoryType {
     Genesis,
     /// Use to run a playbook against the Logstash servers.
     Logstash,
+    /// Use to run a playbook against the monitoring machine, which runs Prometheus and Grafana.
+    ///
+    /// Only one machine will be returned in this inventory.
+    Monitoring,
     /// Use to run a playbook against the NAT gateway.
     NatGateway,
     /// Use to run a playbook against all nodes except the genesis node.
@@ -57,12 +72,16 @@ pub enum AnsibleInventoryType {
 impl std::fmt::Display for AnsibleInventoryType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s = match self {
+            AnsibleInventoryType::ArtifactProxy => "ArtifactProxy",
+            AnsibleInventoryType::Auditor => "Auditor",
             AnsibleInventoryType::PeerCacheNodes => "PeerCacheNodes",
             AnsibleInventoryType::Build => "Build",
             AnsibleInventoryType::Custom => "Custom",
+            AnsibleInventoryType::Downloaders => "Downloaders",
             AnsibleInventoryType::EvmNodes => "EvmNodes",
             AnsibleInventoryType::Genesis => "Genesis",
             AnsibleInventoryType::Logstash => "Logstash",
+            AnsibleInventoryType::Monitoring => "Monitoring",
             AnsibleInventoryType::NatGateway => "NatGateway",
             AnsibleInventoryType::Nodes => "Nodes",
             AnsibleInventoryType::PrivateNodes => "PrivateNodes",
@@ -76,14 +95,24 @@ impl std::fmt::Display for AnsibleInventoryType {
 impl AnsibleInventoryType {
     pub fn get_inventory_path(&self, name: &str, provider: &str) -> PathBuf {
         match &self {
+            Self::ArtifactProxy => {
+                PathBuf::from(format!(".{name}_artifact_proxy_inventory_{provider}.yml"))
+            }
+            Self::Auditor => PathBuf::from(format!(".{name}_auditor_inventory_{provider}.yml")),
             Self::PeerCacheNodes => {
                 PathBuf::from(format!(".{name}_peer_cache_node_inventory_{provider}.yml"))
             }
             Self::Build => PathBuf::from(format!(".{name}_build_inventory_{provider}.yml")),
             Self::Custom => PathBuf::from(format!(".{name}_custom_inventory_{provider}.ini")),
+            Self::Downloaders => {
+                PathBuf::from(format!(".{name}_downloader_inventory_{provider}.yml"))
+            }
             Self::EvmNodes => PathBuf::from(format!(".{name}_evm_node_inventory_{provider}.yml")),
             Self::Genesis => PathBuf::from(format!(".{name}_genesis_inventory_{provider}.yml")),
             Self::Logstash => PathBuf::from(format!(".{name}_logstash_inventory_{provider}.yml")),
+            Self::Monitoring => {
+                PathBuf::from(format!(".{name}_monitoring_inventory_{provider}.yml"))
+            }
             Self::NatGateway => {
                 PathBuf::from(format!(".{name}_nat_gateway_inventory_{provider}.yml"))
             }
@@ -98,14 +127,43 @@ impl AnsibleInventoryType {
         }
     }
 
+    /// The path of the static inventory written to connect to the VMs over the WireGuard overlay.
+    pub fn get_overlay_inventory_path(&self, name: &str, provider: &str) -> PathBuf {
+        let extension = match self {
+            Self::Custom | Self::PrivateNodesStatic => "ini",
+            _ => "yml",
+        };
+        PathBuf::from(format!(
+            ".{name}_{}_overlay_inventory_{provider}.{extension}",
+            self.tag()
+        ))
+    }
+
+    /// The path of the static inventory written for use in offline mode.
+    ///
+    /// Returns `None` for the types that are not generated from the dynamic inventory.
+    pub fn get_offline_inventory_path(&self, name: &str, provider: &str) -> Option<PathBuf> {
+        match self {
+            Self::Custom | Self::Logstash | Self::PrivateNodesStatic => None,
+            _ => Some(PathBuf::from(format!(
+                ".{name}_{}_offline_inventory_{provider}.yml",
+                self.tag()
+            ))),
+        }
+    }
+
     pub fn tag(&self) -> &str {
         match self {
+            Self::ArtifactProxy => "artifact_proxy",
+            Self::Auditor => "auditor",
             Self::PeerCacheNodes => "peer_cache_node",
             Self::Build => "build",
             Self::Custom => "custom",
+            Self::Downloaders => "downloader",
             Self::EvmNodes => "evm_node",
             Self::Genesis => "genesis",
             Self::Logstash => "logstash",
+            Self::Monitoring => "monitoring",
             Self::NatGateway => "nat_gateway",
             Self::Nodes => "node",
             Self::PrivateNodes => "private_node",
@@ -114,6 +172,25 @@ impl AnsibleInventoryType {
         }
     }
 
+    /// The types that have a static inventory written for offline mode.
+    pub fn iter_offline() -> impl Iterator<Item = Self> {
+        [
+            Self::ArtifactProxy,
+            Self::Auditor,
+            Self::Build,
+            Self::Downloaders,
+            Self::EvmNodes,
+            Self::Genesis,
+            Self::Monitoring,
+            Self::NatGateway,
+            Self::Nodes,
+            Self::PeerCacheNodes,
+            Self::PrivateNodes,
+            Self::Uploaders,
+        ]
+        .into_iter()
+    }
+
     pub fn iter_node_type() -> impl Iterator<Item = Self> {
         [
             Self::Genesis,
@@ -211,9 +288,13 @@ pub fn generate_environment_inventory(
     output_inventory_dir_path: &Path,
 ) -> Result<()> {
     let inventory_types = [
+        AnsibleInventoryType::ArtifactProxy,
+        AnsibleInventoryType::Auditor,
         AnsibleInventoryType::PeerCacheNodes,
         AnsibleInventoryType::Build,
+        AnsibleInventoryType::Downloaders,
         AnsibleInventoryType::Genesis,
+        AnsibleInventoryType::Monitoring,
         AnsibleInventoryType::NatGateway,
         AnsibleInventoryType::Nodes,
         AnsibleInventoryType::PrivateNodes,
@@ -251,9 +332,13 @@ pub fn cleanup_environment_inventory(
     inventory_types: Option<Vec<AnsibleInventoryType>>,
 ) -> Result<()> {
     let default_inventory_types = [
+        AnsibleInventoryType::ArtifactProxy,
+        AnsibleInventoryType::Auditor,
         AnsibleInventoryType::PeerCacheNodes,
         AnsibleInventoryType::Build,
+        AnsibleInventoryType::Downloaders,
         AnsibleInventoryType::Genesis,
+        AnsibleInventoryType::Monitoring,
         AnsibleInventoryType::NatGateway,
         AnsibleInventoryType::Nodes,
         AnsibleInventoryType::PrivateNodes,
@@ -300,9 +385,148 @@ pub fn generate_custom_environment_inventory(
     Ok(())
 }
 
+/// Generate a static inventory from a list of VMs, which is used in place of the dynamic inventory
+/// in offline mode.
+///
+/// Each host has the same variables the Digital Ocean plugin provides, so the inventory can be
+/// listed, and playbooks run against it, without any calls to the Digital Ocean API. The files do
+/// not contain any secrets, so they can be committed alongside the environment's configuration.
+pub fn generate_offline_environment_inventory(
+    environment_name: &str,
+    output_inventory_dir_path: &Path,
+    inventory_type: &AnsibleInventoryType,
+    vms: &[VirtualMachine],
+) -> Result<()> {
+    let Some(path) = inventory_type.get_offline_inventory_path(environment_name, "digital_ocean")
+    else {
+        return Ok(());
+    };
+    let dest_path = output_inventory_dir_path.join(path);
+    let file = File::create(&dest_path)?;
+    let mut writer = BufWriter::new(file);
+
+    writeln!(writer, "---")?;
+    writeln!(writer, "all:")?;
+    if vms.is_empty() {
+        writeln!(writer, "  hosts: {{}}")?;
+    } else {
+        writeln!(writer, "  hosts:")?;
+    }
+    for vm in vms.iter() {
+        writeln!(writer, "    {}:", vm.name)?;
+        writeln!(writer, "      ansible_host: {}", vm.public_ip_addr)?;
+        writeln!(writer, "      do_id: {}", vm.id)?;
+        writeln!(writer, "      do_name: {}", vm.name)?;
+        writeln!(writer, "      do_networks:")?;
+        writeln!(writer, "        v4:")?;
+        writeln!(writer, "          - ip_address: {}", vm.public_ip_addr)?;
+        writeln!(writer, "            type: public")?;
+        writeln!(writer, "          - ip_address: {}", vm.private_ip_addr)?;
+        writeln!(writer, "            type: private")?;
+    }
+
+    debug!("Created offline inventory file at {dest_path:#?}");
+    Ok(())
+}
+
+/// Generate a static inventory that connects to each VM on its WireGuard overlay address.
+///
+/// The hosts keep their names, so they can still be referred to with `--limit`. The public IP is
+/// provided as `public_ip_addr`, for the roles that need it. A VM that is not on the overlay is
+/// connected to on its public IP.
+pub fn generate_overlay_environment_inventory(
+    environment_name: &str,
+    output_inventory_dir_path: &Path,
+    inventory_type: &AnsibleInventoryType,
+    vms: &[VirtualMachine],
+    overlay_addresses: &BTreeMap<IpAddr, IpAddr>,
+) -> Result<PathBuf> {
+    let dest_path = output_inventory_dir_path
+        .join(inventory_type.get_overlay_inventory_path(environment_name, "digital_ocean"));
+    let file = File::create(&dest_path)?;
+    let mut writer = BufWriter::new(file);
+
+    writeln!(writer, "---")?;
+    writeln!(writer, "all:")?;
+    if vms.is_empty() {
+        writeln!(writer, "  hosts: {{}}")?;
+    } else {
+        writeln!(writer, "  hosts:")?;
+    }
+    for vm in vms.iter() {
+        let ansible_host = overlay_addresses
+            .get(&vm.public_ip_addr)
+            .unwrap_or(&vm.public_ip_addr);
+        writeln!(writer, "    {}:", vm.name)?;
+        writeln!(writer, "      ansible_host: {ansible_host}")?;
+        writeln!(writer, "      public_ip_addr: {}", vm.public_ip_addr)?;
+        writeln!(writer, "      do_id: {}", vm.id)?;
+        writeln!(writer, "      do_name: {}", vm.name)?;
+        writeln!(writer, "      do_networks:")?;
+        writeln!(writer, "        v4:")?;
+        writeln!(writer, "          - ip_address: {}", vm.public_ip_addr)?;
+        writeln!(writer, "            type: public")?;
+        writeln!(writer, "          - ip_address: {}", vm.private_ip_addr)?;
+        writeln!(writer, "            type: private")?;
+    }
+
+    debug!("Created overlay inventory file at {dest_path:#?}");
+    Ok(dest_path)
+}
+
+/// Rewrite one of the INI inventories, i.e., the custom or private node inventory, to connect on
+/// the overlay addresses.
+///
+/// The custom inventory lists the VMs by their public IP, which is kept as the host name. The
+/// private node inventory reaches the VMs through the NAT gateway, so the address of the gateway
+/// in the proxy command is replaced.
+pub fn generate_overlay_ini_inventory(
+    environment_name: &str,
+    inventory_path: &Path,
+    inventory_type: &AnsibleInventoryType,
+    overlay_addresses: &BTreeMap<IpAddr, IpAddr>,
+) -> Result<PathBuf> {
+    let dest_path = inventory_path.with_file_name(
+        inventory_type.get_overlay_inventory_path(environment_name, "digital_ocean"),
+    );
+    let mut contents = String::new();
+    for line in std::fs::read_to_string(inventory_path)?.lines() {
+        let line = match line.trim().parse::<IpAddr>() {
+            Ok(ip_addr) => match overlay_addresses.get(&ip_addr) {
+                Some(overlay_addr) => {
+                    format!("{ip_addr} ansible_host={overlay_addr} public_ip_addr={ip_addr}")
+                }
+                None => line.to_string(),
+            },
+            Err(_) => {
+                overlay_addresses
+                    .iter()
+                    .fold(line.to_string(), |line, (ip_addr, overlay_addr)| {
+                        line.replace(&format!("@{ip_addr} "), &format!("@{overlay_addr} "))
+                    })
+            }
+        };
+        contents.push_str(&line);
+        contents.push('\n');
+    }
+    std::fs::write(&dest_path, contents)?;
+
+    debug!("Created overlay inventory file at {dest_path:#?}");
+    Ok(dest_path)
+}
+
+/// The name used for the provider in the inventory file names.
+pub fn get_inventory_provider_name(provider: CloudProvider) -> &'static str {
+    match provider {
+        CloudProvider::Aws => "aws",
+        CloudProvider::DigitalOcean => "digital_ocean",
+    }
+}
+
 /// Generate the static inventory for the private node. This is just used during ansible-playbook.
 pub fn generate_private_node_static_environment_inventory(
     environment_name: &str,
+    provider: CloudProvider,
     output_inventory_dir_path: &Path,
     private_node_vms: &[VirtualMachine],
     nat_gateway_vm: &Option<VirtualMachine>,
@@ -319,7 +543,7 @@ pub fn generate_private_node_static_environment_inventory(
 
     let dest_path = output_inventory_dir_path.join(
         AnsibleInventoryType::PrivateNodesStatic
-            .get_inventory_path(environment_name, "digital_ocean"),
+            .get_inventory_path(environment_name, get_inventory_provider_name(provider)),
     );
     if dest_path.exists() {
         return Ok(());
@@ -335,7 +559,8 @@ pub fn generate_private_node_static_environment_inventory(
     writeln!(file, "[private_nodes:vars]")?;
     writeln!(
         file,
-        "ansible_ssh_common_args='-o ProxyCommand=\"ssh -p 22 -W %h:%p -q root@{} -i \"{}\" -o StrictHostKeyChecking=no -o UserKnownHostsFile=/dev/null\"'",
+        "ansible_ssh_common_args='-o ProxyCommand=\"ssh -p 22 -W %h:%p -q {}@{} -i \"{}\" -o StrictHostKeyChecking=no -o UserKnownHostsFile=/dev/null\"'",
+        provider.get_ssh_user(),
         nat_gateway_vm.public_ip_addr,
         ssh_sk_path.to_string_lossy()
     )?;
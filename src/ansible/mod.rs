@@ -7,14 +7,32 @@
 pub mod extra_vars;
 pub mod inventory;
 pub mod provisioning;
+pub mod results;
 
 use crate::{
     error::{Error, Result},
-    is_binary_on_path, run_external_command, CloudProvider,
+    inventory::VirtualMachine,
+    is_binary_on_path, run_external_command_with_env, CloudProvider,
 };
-use inventory::AnsibleInventoryType;
-use log::debug;
-use std::path::PathBuf;
+use inventory::{
+    generate_overlay_environment_inventory, generate_overlay_ini_inventory,
+    get_inventory_provider_name, AnsibleInventoryType,
+};
+use results::PlaybookResult;
+use std::{
+    collections::BTreeMap,
+    net::IpAddr,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use tracing::debug;
+
+/// The delay before the first retry of the hosts that failed a playbook. It doubles with each
+/// retry.
+const PLAYBOOK_RETRY_BASE_DELAY: Duration = Duration::from_secs(30);
+
+/// The variable the `playbook_results` callback plugin reads the path of its results file from.
+const PLAYBOOK_RESULTS_PATH_ENV_VAR: &str = "PLAYBOOK_RESULTS_PATH";
 
 /// Ansible has multiple 'binaries', e.g., `ansible-playbook`, `ansible-inventory` etc. that are
 /// wrappers around the main `ansible` program. It would be a bit cumbersome to create a different
@@ -49,12 +67,18 @@ impl AnsibleBinary {
 }
 
 /// Represents the playbooks that apply to our own domain.
+#[derive(Clone, Copy)]
 pub enum AnsiblePlaybook {
     /// The antctl inventory playbook will retrieve antctl's inventory from any machines it is run
     /// against.
     ///
     /// Use in combination with `AnsibleInventoryType::Genesis` or `AnsibleInventoryType::Nodes`.
     AntCtlInventory,
+    /// The artifact proxy playbook will setup Nginx as a caching proxy for the S3 buckets that
+    /// store the binary archives, so each archive is only downloaded from S3 once per deployment.
+    ///
+    /// Use in combination with `AnsibleInventoryType::ArtifactProxy`.
+    ArtifactProxy,
     /// The auditor playbook will provision setup the auditor to run as a service. The auditor is
     /// typically running on a separate auditor machine, but can be run from any machine.
     ///
@@ -69,6 +93,11 @@ pub enum AnsiblePlaybook {
     ///
     /// Use in combination with the node machines.
     CleanupLogs,
+    /// The clear node caches playbook will remove the bootstrap caches written by the nodes, without
+    /// touching their keys or data.
+    ///
+    /// Use in combination with the node machines.
+    ClearNodeCaches,
     /// The configure swapfile playbook will configure the swapfile on the machines it is run against.
     ///
     /// Use in combination with `AnsibleInventoryType::Nodes` or `AnsibleInventoryType::PeerCache`.
@@ -77,6 +106,21 @@ pub enum AnsiblePlaybook {
     ///
     /// Use in combination with `AnsibleInventoryType::Genesis` or `AnsibleInventoryType::Nodes`.
     CopyLogs,
+    /// The DNS config playbook will configure the DNS resolvers and add entries to /etc/hosts on
+    /// the machines it is run against.
+    ///
+    /// It is run against each machine type before the binaries are provisioned.
+    DnsConfig,
+    /// The download verifiers playbook will provision services that continuously download the
+    /// files in the upload manifests and verify their content.
+    ///
+    /// Use in combination with `AnsibleInventoryType::Downloaders`.
+    DownloadVerifiers,
+    /// The drain nodes playbook will stop all the node services on the machines it is run against,
+    /// one at a time, in preparation for the machines being removed.
+    ///
+    /// Use in combination with `AnsibleInventoryType::Custom`.
+    DrainNodes,
     /// The EVM node playbook will setup and manage EVM nodes for the deployment.
     ///
     /// Use in combination with `AnsibleInventoryType::EvmNodes`.
@@ -93,6 +137,17 @@ pub enum AnsiblePlaybook {
     Faucet,
     /// This playbook will fund the uploaders using the faucet.
     FundUploaders,
+    /// The hardening playbook will create the SSH user, disable root login when that user is not
+    /// root, and install fail2ban.
+    ///
+    /// It connects as the bootstrap user and is run against each machine type before any
+    /// provisioning.
+    Hardening,
+    /// The infra services playbook will setup a DNS forwarder and an NTP server on the artifact
+    /// proxy, so the other machines can resolve names and sync their clocks without public egress.
+    ///
+    /// Use in combination with `AnsibleInventoryType::ArtifactProxy`.
+    InfraServices,
     /// The genesis playbook will use the node manager to setup the genesis node, which the other
     /// nodes will bootstrap against.
     ///
@@ -102,29 +157,90 @@ pub enum AnsiblePlaybook {
     ///
     /// Use in combination with `AnsibleInventoryType::Logstash`.
     Logstash,
+    /// The monitoring playbook will setup Prometheus, to scrape the metrics from every node in the
+    /// deployment, and Grafana, with a dashboard for those metrics.
+    ///
+    /// Use in combination with `AnsibleInventoryType::Monitoring`.
+    Monitoring,
     /// The NAT gateway playbook will setup the NAT gateway to enable NAT routing with randomization.
     /// It allows us to simulate a private node that is behind a NAT.
     ///
     /// Use in combination with `AnsibleInventoryType::NatGateway`.
     NatGateway,
+    /// The network conditions playbook will use the netem queueing discipline to simulate WAN
+    /// conditions, like latency and packet loss, or remove them.
+    ///
+    /// Use in combination with `AnsibleInventoryType::Custom`.
+    NetworkConditions,
     /// The node playbook will setup any nodes except the genesis node. These nodes will bootstrap
     /// using genesis as a peer reference.
     ///
     /// Use in combination with `AnsibleInventoryType::iter_node_type()`.
     Nodes,
+    /// The partition playbook will use iptables to drop all traffic to and from a list of IP
+    /// addresses. Running it with an empty list heals the partition.
+    ///
+    /// Use in combination with `AnsibleInventoryType::Custom`.
+    Partition,
     /// The node playbook will setup the peer cache nodes. These nodes will bootstrap
     /// using genesis as a peer reference.
     ///
     /// Use in combination with `AnsibleInventoryType::PeerCache`.
     PeerCacheNodes,
+    /// The peer filter playbook will apply allow and deny lists of peer IP addresses with
+    /// iptables, then restart the nodes, so they only connect to the permitted peers.
+    ///
+    /// Running it with empty lists clears the filter.
+    ///
+    /// Use in combination with `AnsibleInventoryType::iter_node_type()` or
+    /// `AnsibleInventoryType::Custom`.
+    PeerFilter,
+    /// The private node route playbook will change the default route of the private nodes to
+    /// another NAT gateway, without reprovisioning the nodes.
+    ///
+    /// Use in combination with `AnsibleInventoryType::PrivateNodes`.
+    PrivateNodeRoute,
+    /// The reset binaries playbook will remove the binaries and services from a previous
+    /// deployment, so the VMs can be provisioned again with different binaries.
+    ///
+    /// Use in combination with the build, node and uploader machines.
+    ResetBinaries,
     /// The reset to n nodes playbook will reset the nodes to the specified number of nodes.
     ///
     /// See the `reset-to-n-nodes` role for more details.
     ResetToNNodes,
+    /// The restart nodes playbook will use the node manager to stop then start the node services
+    /// on any machines it runs against.
+    ///
+    /// Use in combination with `AnsibleInventoryType::iter_node_type()` or
+    /// `AnsibleInventoryType::Custom`.
+    RestartNodes,
+    /// The restart policy playbook will add a systemd drop-in to each node service, overriding
+    /// its restart policy, then reload systemd. The nodes are not restarted.
+    ///
+    /// Use in combination with `AnsibleInventoryType::iter_node_type()` or
+    /// `AnsibleInventoryType::Custom`.
+    RestartPolicy,
     /// The rpc client playbook will setup the `safenode_rpc_client` binary on the genesis node.
     ///
     /// Use in combination with `AnsibleInventoryType::Genesis`.
     RpcClient,
+    /// The rpc proxy playbook will obtain a Let's Encrypt certificate and configure Nginx to
+    /// expose the RPC of each node on a TLS port, requiring a bearer token.
+    ///
+    /// Use in combination with `AnsibleInventoryType::iter_node_type()`, excluding the private
+    /// nodes.
+    RpcProxy,
+    /// The snapshot create playbook will stop the nodes, stream the data directory on each
+    /// machine to S3, then start the nodes again.
+    ///
+    /// Use in combination with `AnsibleInventoryType::iter_node_type()`.
+    SnapshotCreate,
+    /// The snapshot restore playbook will stop the nodes, replace the data directory on each
+    /// machine with its archive from a snapshot in S3, then start the nodes again.
+    ///
+    /// Use in combination with `AnsibleInventoryType::iter_node_type()`.
+    SnapshotRestore,
     /// The start nodes playbook will use the node manager to start any node services on any
     /// machines it runs against.
     ///
@@ -139,6 +255,8 @@ pub enum AnsiblePlaybook {
     Status,
     /// This playbook will start the faucet for the environment.
     StartFaucet,
+    /// This playbook will start the download verifiers on each machine.
+    StartDownloadVerifiers,
     /// This playbook will start the Telegraf service on each machine.
     ///
     /// It can be necessary for running upgrades, since we will want to re-enable Telegraf after the
@@ -148,6 +266,8 @@ pub enum AnsiblePlaybook {
     StartUploaders,
     /// This playbook will stop the faucet for the environment.
     StopFaucet,
+    /// This playbook will stop the download verifiers on each machine.
+    StopDownloadVerifiers,
     /// The stop nodes playbook will use the node manager to stop any node services on any
     /// machines it runs against.
     ///
@@ -160,6 +280,16 @@ pub enum AnsiblePlaybook {
     StopTelegraf,
     /// This playbook will stop the uploaders on each machine.
     StopUploaders,
+    /// The sysstat playbook will start collecting sysstat samples every second for a bounded
+    /// period, or stop the collection.
+    ///
+    /// Use in combination with `AnsibleInventoryType::Custom`.
+    Sysstat,
+    /// The throttle playbook will use wondershaper to limit the upload and download bandwidth of
+    /// the machines it is run against, or remove the limit.
+    ///
+    /// Use in combination with `AnsibleInventoryType::Custom`.
+    Throttle,
     /// The upgrade antctl playbook will upgrade the antctl to the latest version.
     ///
     /// Use in combination with `AnsibleInventoryType::Genesis` or `AnsibleInventoryType::Nodes`.
@@ -170,6 +300,11 @@ pub enum AnsiblePlaybook {
     UpgradeNodes,
     /// Update the node Telegraf configuration to the latest version in the repository.
     UpgradeNodeTelegrafConfig,
+    /// The upload concurrency playbook will limit the number of uploader services that upload on
+    /// each machine, or remove the limit.
+    ///
+    /// Use in combination with `AnsibleInventoryType::Uploaders`.
+    UploadConcurrency,
     /// Upgrade the uploaders to the latest version of the safe client.
     UpgradeUploaders,
     /// Update the uploader Telegraf configuration to the latest version in the repository.
@@ -178,50 +313,89 @@ pub enum AnsiblePlaybook {
     ///
     /// Use in combination with `AnsibleInventoryType::Uploaders`.
     Uploaders,
+    /// The update node env playbook will rewrite the environment of the node services, using a
+    /// forced upgrade to the version that is already running, which restarts each node.
+    UpdateNodeEnv,
     /// The update peer playbook will update the peer multiaddr in all node service definitions.
     UpdatePeer,
+    /// Copy binaries from the local machine to the artifact proxy, which serves them to the other
+    /// VMs.
+    ///
+    /// Use in combination with `AnsibleInventoryType::ArtifactProxy`.
+    UploadLocalBinaries,
+    /// The wireguard playbook will install WireGuard on each VM and configure the interface for
+    /// the overlay, with every other VM and the machine running the deployer as peers.
+    ///
+    /// Use in combination with `AnsibleInventoryType::Custom`, including every VM in one run.
+    WireGuard,
 }
 
 impl AnsiblePlaybook {
     pub fn get_playbook_name(&self) -> String {
         match self {
             AnsiblePlaybook::AntCtlInventory => "antctl_inventory.yml".to_string(),
+            AnsiblePlaybook::ArtifactProxy => "artifact_proxy.yml".to_string(),
             AnsiblePlaybook::Auditor => "auditor.yml".to_string(),
             AnsiblePlaybook::Build => "build.yml".to_string(),
             AnsiblePlaybook::CleanupLogs => "cleanup_logs.yml".to_string(),
+            AnsiblePlaybook::ClearNodeCaches => "clear_node_caches.yml".to_string(),
             AnsiblePlaybook::ConfigureSwapfile => "configure_swapfile.yml".to_string(),
             AnsiblePlaybook::CopyLogs => "copy_logs.yml".to_string(),
+            AnsiblePlaybook::DnsConfig => "dns_config.yml".to_string(),
+            AnsiblePlaybook::DownloadVerifiers => "download_verifiers.yml".to_string(),
+            AnsiblePlaybook::DrainNodes => "drain_nodes.yml".to_string(),
             AnsiblePlaybook::EvmNodes => "evm_nodes.yml".to_string(),
             AnsiblePlaybook::ExtendVolumeSize => "extend_volume_size.yml".to_string(),
             AnsiblePlaybook::Faucet => "faucet.yml".to_string(),
             AnsiblePlaybook::FundUploaders => "fund_uploaders.yml".to_string(),
             AnsiblePlaybook::Genesis => "genesis_node.yml".to_string(),
+            AnsiblePlaybook::Hardening => "hardening.yml".to_string(),
+            AnsiblePlaybook::InfraServices => "infra_services.yml".to_string(),
             AnsiblePlaybook::Logstash => "logstash.yml".to_string(),
+            AnsiblePlaybook::Monitoring => "monitoring.yml".to_string(),
             AnsiblePlaybook::NatGateway => "nat_gateway.yml".to_string(),
+            AnsiblePlaybook::NetworkConditions => "network_conditions.yml".to_string(),
             AnsiblePlaybook::Nodes => "nodes.yml".to_string(),
+            AnsiblePlaybook::Partition => "partition.yml".to_string(),
             AnsiblePlaybook::PeerCacheNodes => "peer_cache_node.yml".to_string(),
+            AnsiblePlaybook::PeerFilter => "peer_filter.yml".to_string(),
+            AnsiblePlaybook::PrivateNodeRoute => "private_node_route.yml".to_string(),
             AnsiblePlaybook::RpcClient => "safenode_rpc_client.yml".to_string(),
+            AnsiblePlaybook::RpcProxy => "rpc_proxy.yml".to_string(),
+            AnsiblePlaybook::ResetBinaries => "reset_binaries.yml".to_string(),
             AnsiblePlaybook::ResetToNNodes => "reset_to_n_nodes.yml".to_string(),
+            AnsiblePlaybook::RestartNodes => "restart_nodes.yml".to_string(),
+            AnsiblePlaybook::RestartPolicy => "restart_policy.yml".to_string(),
+            AnsiblePlaybook::SnapshotCreate => "snapshot_create.yml".to_string(),
+            AnsiblePlaybook::SnapshotRestore => "snapshot_restore.yml".to_string(),
+            AnsiblePlaybook::StartDownloadVerifiers => "start_download_verifiers.yml".to_string(),
             AnsiblePlaybook::StartFaucet => "start_faucet.yml".to_string(),
             AnsiblePlaybook::StartNodes => "start_nodes.yml".to_string(),
             AnsiblePlaybook::StartTelegraf => "start_telegraf.yml".to_string(),
             AnsiblePlaybook::StartUploaders => "start_uploaders.yml".to_string(),
             AnsiblePlaybook::Status => "node_status.yml".to_string(),
+            AnsiblePlaybook::StopDownloadVerifiers => "stop_download_verifiers.yml".to_string(),
             AnsiblePlaybook::StopFaucet => "stop_faucet.yml".to_string(),
             AnsiblePlaybook::StopNodes => "stop_nodes.yml".to_string(),
             AnsiblePlaybook::StopTelegraf => "stop_telegraf.yml".to_string(),
             AnsiblePlaybook::StopUploaders => "stop_uploaders.yml".to_string(),
+            AnsiblePlaybook::Sysstat => "sysstat.yml".to_string(),
+            AnsiblePlaybook::Throttle => "throttle.yml".to_string(),
             AnsiblePlaybook::UpgradeAntctl => "upgrade_antctl.yml".to_string(),
             AnsiblePlaybook::UpgradeNodes => "upgrade_nodes.yml".to_string(),
             AnsiblePlaybook::UpgradeNodeTelegrafConfig => {
                 "upgrade_node_telegraf_config.yml".to_string()
             }
+            AnsiblePlaybook::UploadConcurrency => "upload_concurrency.yml".to_string(),
             AnsiblePlaybook::UpgradeUploaders => "upgrade_uploaders.yml".to_string(),
             AnsiblePlaybook::UpgradeUploaderTelegrafConfig => {
                 "upgrade_uploader_telegraf_config.yml".to_string()
             }
             AnsiblePlaybook::Uploaders => "uploaders.yml".to_string(),
+            AnsiblePlaybook::UpdateNodeEnv => "update_node_env.yml".to_string(),
             AnsiblePlaybook::UpdatePeer => "update_peer.yml".to_string(),
+            AnsiblePlaybook::UploadLocalBinaries => "upload_local_binaries.yml".to_string(),
+            AnsiblePlaybook::WireGuard => "wireguard.yml".to_string(),
         }
     }
 }
@@ -230,7 +404,16 @@ impl AnsiblePlaybook {
 pub struct AnsibleRunner {
     pub ansible_forks: usize,
     pub ansible_verbose_mode: bool,
+    /// When set, playbooks run in check mode, which reports the changes that would be made
+    /// without making them.
+    pub dry_run: bool,
     pub environment_name: String,
+    /// When set, the static inventories written by the last inventory run are used in place of
+    /// the dynamic inventory, so no calls are made to the Digital Ocean API.
+    pub offline_inventory: bool,
+    /// When set, playbooks connect to each VM on its WireGuard overlay address, keyed here by its
+    /// public IP address, rather than the public IP.
+    pub overlay_addresses: Option<BTreeMap<IpAddr, IpAddr>>,
     pub provider: CloudProvider,
     pub ssh_sk_path: PathBuf,
     pub vault_password_file_path: PathBuf,
@@ -253,7 +436,10 @@ impl AnsibleRunner {
         Ok(AnsibleRunner {
             ansible_forks,
             ansible_verbose_mode,
+            dry_run: false,
             environment_name: environment_name.to_string(),
+            offline_inventory: false,
+            overlay_addresses: None,
             provider,
             working_directory_path,
             ssh_sk_path,
@@ -261,21 +447,237 @@ impl AnsibleRunner {
         })
     }
 
+    /// Run a playbook and return the recap for each host.
+    ///
+    /// The results are also recorded for the run, so they can be included in a report.
     pub fn run_playbook(
         &self,
         playbook: AnsiblePlaybook,
-        mut inventory_type: AnsibleInventoryType,
+        inventory_type: AnsibleInventoryType,
+        extra_vars_document: Option<String>,
+    ) -> Result<Vec<PlaybookResult>> {
+        self.run_playbook_as_user(
+            playbook,
+            inventory_type,
+            extra_vars_document,
+            &self.provider.get_ssh_user(),
+        )
+    }
+
+    /// Run a playbook, connecting as a user other than the configured SSH user.
+    ///
+    /// This is used by the hardening stage, which connects as the image's bootstrap user to create
+    /// the SSH user.
+    pub fn run_playbook_as_user(
+        &self,
+        playbook: AnsiblePlaybook,
+        inventory_type: AnsibleInventoryType,
         extra_vars_document: Option<String>,
+        ssh_user: &str,
+    ) -> Result<Vec<PlaybookResult>> {
+        self.run_playbook_with_limit(
+            playbook,
+            inventory_type,
+            extra_vars_document,
+            ssh_user,
+            None,
+            self.ansible_forks,
+        )
+    }
+
+    /// Run a playbook against the VMs, then re-run it against any hosts that failed, up to
+    /// `retries` times.
+    ///
+    /// The failed hosts are read from the retry file Ansible writes at the end of the run. The
+    /// delay before each retry doubles, to give transient problems, like an unavailable package
+    /// mirror, time to clear. If hosts are still failing after the last retry, they are returned
+    /// in the error.
+    pub fn run_playbook_with_retries(
+        &self,
+        playbook: AnsiblePlaybook,
+        inventory_type: AnsibleInventoryType,
+        extra_vars_document: Option<String>,
+        vms: &[VirtualMachine],
+        retries: u8,
     ) -> Result<()> {
-        // prioritize the static private node inventory if it exists. Else fall back to the dynamic one.
-        if matches!(inventory_type, AnsibleInventoryType::PrivateNodes)
-            && self
-                .get_inventory_path(&AnsibleInventoryType::PrivateNodesStatic)
-                .is_ok()
-        {
-            println!("Using static private node inventory to run playbook");
-            inventory_type = AnsibleInventoryType::PrivateNodesStatic;
+        let hosts = self.get_host_patterns(inventory_type, vms);
+        self.run_hosts_with_retries(
+            playbook,
+            inventory_type,
+            extra_vars_document,
+            &hosts,
+            false,
+            self.ansible_forks,
+            retries,
+        )
+    }
+
+    /// Run a playbook against the VMs in batches, one batch after another.
+    ///
+    /// The forks are raised to the batch size, so every VM in a batch is provisioned at the same
+    /// time. The playbook's failure threshold applies to each batch, and the failed hosts in a
+    /// batch are retried before moving on. A batch with hosts that still fail stops the remaining
+    /// batches from running.
+    pub fn run_playbook_in_batches(
+        &self,
+        playbook: AnsiblePlaybook,
+        inventory_type: AnsibleInventoryType,
+        extra_vars_document: Option<String>,
+        vms: &[VirtualMachine],
+        batch_size: usize,
+        retries: u8,
+    ) -> Result<()> {
+        if batch_size == 0 {
+            return Err(Error::InvalidProvisionParallelism);
+        }
+        if vms.len() <= batch_size {
+            return self.run_playbook_with_retries(
+                playbook,
+                inventory_type,
+                extra_vars_document,
+                vms,
+                retries,
+            );
+        }
+
+        let batch_count = vms.len().div_ceil(batch_size);
+        let started = Instant::now();
+        for (i, batch) in vms.chunks(batch_size).enumerate() {
+            println!(
+                "Running {} against batch {}/{batch_count} ({} VMs)",
+                playbook.get_playbook_name(),
+                i + 1,
+                batch.len()
+            );
+            self.run_hosts_with_retries(
+                playbook,
+                inventory_type,
+                extra_vars_document.clone(),
+                &self.get_host_patterns(inventory_type, batch),
+                true,
+                self.ansible_forks.max(batch_size),
+                retries,
+            )?;
+            println!(
+                "Completed batch {}/{batch_count}: {}/{} VMs provisioned after {}s",
+                i + 1,
+                (i * batch_size + batch.len()),
+                vms.len(),
+                started.elapsed().as_secs()
+            );
+        }
+        Ok(())
+    }
+
+    /// Run the playbook against the hosts, which are limited on the first run only if requested,
+    /// then retry the failed hosts.
+    #[allow(clippy::too_many_arguments)]
+    fn run_hosts_with_retries(
+        &self,
+        playbook: AnsiblePlaybook,
+        inventory_type: AnsibleInventoryType,
+        extra_vars_document: Option<String>,
+        hosts: &[String],
+        limit_first_run: bool,
+        forks: usize,
+        retries: u8,
+    ) -> Result<()> {
+        let retry_file_path = self.get_retry_file_path(playbook);
+        let mut limit = limit_first_run.then(|| hosts.join(","));
+        let mut attempt = 0;
+        loop {
+            if retry_file_path.exists() {
+                std::fs::remove_file(&retry_file_path)?;
+            }
+            let result = self
+                .run_playbook_with_limit(
+                    playbook,
+                    inventory_type,
+                    extra_vars_document.clone(),
+                    &self.provider.get_ssh_user(),
+                    limit.clone(),
+                    forks,
+                )
+                .map(|_| ());
+
+            // Only hosts from this run are considered, in case another environment's run of the
+            // same playbook wrote the file.
+            let failed_hosts: Vec<String> = match std::fs::read_to_string(&retry_file_path) {
+                Ok(contents) => contents
+                    .lines()
+                    .map(|line| line.trim().to_string())
+                    .filter(|host| hosts.contains(host))
+                    .collect(),
+                Err(_) => Vec::new(),
+            };
+            if failed_hosts.is_empty() {
+                return result;
+            }
+            if attempt >= retries {
+                return Err(Error::AnsibleHostsFailed {
+                    hosts: failed_hosts,
+                    playbook: playbook.get_playbook_name(),
+                });
+            }
+
+            attempt += 1;
+            let delay = PLAYBOOK_RETRY_BASE_DELAY * 2u32.pow(attempt as u32 - 1);
+            println!(
+                "{} hosts failed {}: {}",
+                failed_hosts.len(),
+                playbook.get_playbook_name(),
+                failed_hosts.join(", ")
+            );
+            println!(
+                "Retrying the failed hosts in {}s (attempt {attempt}/{retries})",
+                delay.as_secs()
+            );
+            std::thread::sleep(delay);
+            limit = Some(failed_hosts.join(","));
         }
+    }
+
+    /// Get the names the hosts are referred to by in the inventory, for use with `--limit`.
+    ///
+    /// The static private node inventory refers to the VMs by their private IP address.
+    fn get_host_patterns(
+        &self,
+        inventory_type: AnsibleInventoryType,
+        vms: &[VirtualMachine],
+    ) -> Vec<String> {
+        let use_private_ip = matches!(
+            self.resolve_inventory_type(inventory_type),
+            AnsibleInventoryType::PrivateNodesStatic
+        );
+        vms.iter()
+            .map(|vm| {
+                if use_private_ip {
+                    vm.private_ip_addr.to_string()
+                } else {
+                    vm.name.clone()
+                }
+            })
+            .collect()
+    }
+
+    /// The retry file Ansible writes for the playbook. The location is set in `ansible.cfg`.
+    fn get_retry_file_path(&self, playbook: AnsiblePlaybook) -> PathBuf {
+        let playbook_name = playbook.get_playbook_name();
+        self.working_directory_path
+            .join("retry")
+            .join(format!("{}.retry", playbook_name.trim_end_matches(".yml")))
+    }
+
+    fn run_playbook_with_limit(
+        &self,
+        playbook: AnsiblePlaybook,
+        inventory_type: AnsibleInventoryType,
+        extra_vars_document: Option<String>,
+        ssh_user: &str,
+        limit: Option<String>,
+        forks: usize,
+    ) -> Result<Vec<PlaybookResult>> {
+        let inventory_type = self.resolve_inventory_type(inventory_type);
         debug!(
             "Running playbook: {:?} on {inventory_type:?} with extra vars: {extra_vars_document:?}",
             playbook.get_playbook_name()
@@ -287,13 +689,13 @@ impl AnsibleRunner {
         // unicode characters in them.
         let mut args = vec![
             "--inventory".to_string(),
-            self.get_inventory_path(&inventory_type)?
+            self.get_playbook_inventory_path(&inventory_type)?
                 .to_string_lossy()
                 .to_string(),
             "--private-key".to_string(),
             self.ssh_sk_path.to_string_lossy().to_string(),
             "--user".to_string(),
-            self.provider.get_ssh_user(),
+            ssh_user.to_string(),
             "--vault-password-file".to_string(),
             self.vault_password_file_path.to_string_lossy().to_string(),
         ];
@@ -304,24 +706,111 @@ impl AnsibleRunner {
         if self.ansible_verbose_mode {
             args.push("-vvvvv".to_string());
         }
+        if self.dry_run {
+            args.push("--check".to_string());
+            args.push("--diff".to_string());
+        }
+        if let Some(limit) = limit {
+            args.push("--limit".to_string());
+            args.push(limit);
+        }
         args.push("--forks".to_string());
-        args.push(self.ansible_forks.to_string());
+        args.push(forks.to_string());
         args.push(playbook.get_playbook_name());
-        run_external_command(
+
+        // The `playbook_results` callback plugin, which is enabled in `ansible.cfg`, writes the
+        // results to the path it is given. Each run gets its own directory, so runs of the same
+        // playbook, e.g., from concurrent deployments, do not read or remove each other's results.
+        let results_dir = tempfile::tempdir()?;
+        let results_file_path = results_dir.path().join(format!(
+            "{}.json",
+            playbook.get_playbook_name().trim_end_matches(".yml")
+        ));
+        let output = run_external_command_with_env(
             PathBuf::from(AnsibleBinary::AnsiblePlaybook.to_string()),
             self.working_directory_path.clone(),
             args,
+            &[(
+                PLAYBOOK_RESULTS_PATH_ENV_VAR,
+                results_file_path.to_string_lossy().to_string(),
+            )],
             false,
             false,
-        )?;
-        Ok(())
+        );
+
+        // The results are recorded even if the playbook failed, since that is when they are most
+        // useful. A problem reading them should not hide the error from the playbook itself.
+        let playbook_results = if results_file_path.exists() {
+            results::read_results_file(&results_file_path).unwrap_or_else(|err| {
+                debug!("Could not read the playbook results: {err}");
+                Vec::new()
+            })
+        } else {
+            Vec::new()
+        };
+        results::record(&playbook.get_playbook_name(), &playbook_results);
+        output?;
+        Ok(playbook_results)
     }
 
-    fn get_inventory_path(&self, inventory_type: &AnsibleInventoryType) -> Result<PathBuf> {
-        let provider = match self.provider {
-            CloudProvider::Aws => "aws",
-            CloudProvider::DigitalOcean => "digital_ocean",
+    /// Prioritize the static private node inventory if it exists. Else fall back to the dynamic
+    /// one.
+    fn resolve_inventory_type(&self, inventory_type: AnsibleInventoryType) -> AnsibleInventoryType {
+        if matches!(inventory_type, AnsibleInventoryType::PrivateNodes)
+            && self
+                .get_inventory_path(&AnsibleInventoryType::PrivateNodesStatic)
+                .is_ok()
+        {
+            progress!("Using static private node inventory to run playbook");
+            return AnsibleInventoryType::PrivateNodesStatic;
+        }
+        inventory_type
+    }
+
+    /// The inventory a playbook runs against. With the WireGuard overlay, this is a static
+    /// inventory generated from the regular one, which connects on the overlay addresses.
+    fn get_playbook_inventory_path(
+        &self,
+        inventory_type: &AnsibleInventoryType,
+    ) -> Result<PathBuf> {
+        let inventory_path = self.get_inventory_path(inventory_type)?;
+        let Some(overlay_addresses) = &self.overlay_addresses else {
+            return Ok(inventory_path);
         };
+        match inventory_type {
+            AnsibleInventoryType::Custom | AnsibleInventoryType::PrivateNodesStatic => {
+                generate_overlay_ini_inventory(
+                    &self.environment_name,
+                    &inventory_path,
+                    inventory_type,
+                    overlay_addresses,
+                )
+            }
+            _ => {
+                let vms = self.get_inventory(*inventory_type, false)?;
+                generate_overlay_environment_inventory(
+                    &self.environment_name,
+                    &self.working_directory_path.join("inventory"),
+                    inventory_type,
+                    &vms,
+                    overlay_addresses,
+                )
+            }
+        }
+    }
+
+    fn get_inventory_path(&self, inventory_type: &AnsibleInventoryType) -> Result<PathBuf> {
+        let provider = get_inventory_provider_name(self.provider);
+        let offline_path = inventory_type
+            .get_offline_inventory_path(&self.environment_name, provider)
+            .filter(|_| self.offline_inventory);
+        if let Some(path) = offline_path {
+            let path = self.working_directory_path.join("inventory").join(path);
+            return match path.exists() {
+                true => Ok(path),
+                false => Err(Error::OfflineInventoryNotFound(path)),
+            };
+        }
         let path = inventory_type.get_inventory_path(&self.environment_name, provider);
         let path = self.working_directory_path.join("inventory").join(path);
         match path.exists() {
@@ -10,29 +10,33 @@ use super::{
 };
 use crate::{
     ansible::inventory::generate_custom_environment_inventory,
+    artifact_repository::ArtifactRepositoryConfig,
     bootstrap::BootstrapOptions,
+    build_cache::BuildCacheKey,
     deploy::DeployOptions,
     error::{Error, Result},
     funding::FundingOptions,
+    grafana,
     inventory::{DeploymentNodeRegistries, VirtualMachine},
-    print_duration, BinaryOption, CloudProvider, EvmNetwork, LogFormat, NodeType, SshClient,
-    UpgradeOptions,
+    join_rate::JoinRateSchedule,
+    nat_gateway::{get_full_cone_port_ranges, NatGatewayShard},
+    Architecture, BinaryOption, CloudProvider, EvmNetwork, LogFormat, NatType, NodeType,
+    ReachabilityMode, RestartPolicy, SshClient, TelemetryConfig, UpgradeOptions,
 };
 use ant_service_management::NodeRegistry;
 use evmlib::common::U256;
-use log::{debug, error, trace};
 use semver::Version;
-use std::{
-    net::SocketAddr,
-    path::PathBuf,
-    time::{Duration, Instant},
-};
+use std::{net::IpAddr, path::PathBuf, time::Duration};
+use tracing::{debug, error, trace};
 use walkdir::WalkDir;
 
 use crate::ansible::extra_vars;
 
 pub const DEFAULT_BETA_ENCRYPTION_KEY: &str =
     "49113d2083f57a976076adbe85decb75115820de1e6e74b47e0429338cef124a";
+/// The metrics port of the first node on each VM. This must match `initial_metrics_start_port` in
+/// the `node` role.
+const NODE_METRICS_START_PORT: u16 = 14000;
 
 #[derive(Clone)]
 pub struct ProvisionOptions {
@@ -40,8 +44,18 @@ pub struct ProvisionOptions {
     /// For the upscale, it needs to be provided explicitly, because currently it is not
     /// recorded in the inventory.
     pub ant_version: Option<String>,
+    pub architecture: Architecture,
+    /// The URL of the artifact proxy, if there is one, through which the binary archives are
+    /// downloaded.
+    pub artifact_proxy_url: Option<String>,
+    pub artifact_repository: ArtifactRepositoryConfig,
     pub binary_option: BinaryOption,
+    /// When set, the build VM checks out the commit in the key and also uploads the archives to
+    /// the build cache, so later deployments of the commit can skip the build.
+    pub build_cache_key: Option<BuildCacheKey>,
     pub chunk_size: Option<u64>,
+    /// The Peer Cache VMs used as door nodes, whose nodes listen on fixed ports.
+    pub door_node_vms: Vec<VirtualMachine>,
     pub downloaders_count: u16,
     pub env_variables: Option<Vec<(String, String)>>,
     pub evm_data_payments_address: Option<String>,
@@ -52,28 +66,44 @@ pub struct ProvisionOptions {
     pub funding_wallet_secret_key: Option<String>,
     pub gas_amount: Option<U256>,
     pub interval: Duration,
+    /// Limit the rate at which the generic and private nodes join, to this many per minute across
+    /// all the VMs. The interval is ignored when this is set.
+    pub join_rate: Option<u16>,
     pub log_format: Option<LogFormat>,
-    pub logstash_details: Option<(String, Vec<SocketAddr>)>,
     pub name: String,
     pub nat_gateway: Option<VirtualMachine>,
+    pub nat_type: NatType,
     pub network_id: Option<u8>,
     pub node_count: u16,
+    pub node_reachability: ReachabilityMode,
     pub max_archived_log_files: u16,
     pub max_log_files: u16,
     pub output_inventory_dir_path: PathBuf,
     pub peer_cache_node_count: u16,
+    pub peer_cache_node_reachability: ReachabilityMode,
     pub private_node_count: u16,
+    pub private_node_upnp: bool,
     pub private_node_vms: Vec<VirtualMachine>,
+    /// Provision the node VMs in batches of this size, rather than all of them in a single run.
+    pub provision_parallelism: Option<usize>,
+    /// The number of times to re-run the node playbooks against the hosts that failed.
+    pub provision_retries: u8,
     pub public_rpc: bool,
     pub uploaders_count: Option<u16>,
     pub rewards_address: String,
+    pub telemetry: Option<TelemetryConfig>,
 }
 
 impl From<BootstrapOptions> for ProvisionOptions {
     fn from(bootstrap_options: BootstrapOptions) -> Self {
         ProvisionOptions {
+            architecture: Architecture::default(),
+            artifact_proxy_url: None,
+            artifact_repository: ArtifactRepositoryConfig::default(),
             binary_option: bootstrap_options.binary_option,
+            build_cache_key: None,
             chunk_size: bootstrap_options.chunk_size,
+            door_node_vms: Vec::new(),
             downloaders_count: 0,
             env_variables: bootstrap_options.env_variables,
             evm_data_payments_address: bootstrap_options.evm_data_payments_address,
@@ -83,21 +113,28 @@ impl From<BootstrapOptions> for ProvisionOptions {
             funding_wallet_secret_key: None,
             gas_amount: None,
             interval: bootstrap_options.interval,
+            join_rate: bootstrap_options.join_rate,
             log_format: bootstrap_options.log_format,
-            logstash_details: None,
             max_archived_log_files: bootstrap_options.max_archived_log_files,
             max_log_files: bootstrap_options.max_log_files,
             name: bootstrap_options.name,
             nat_gateway: None,
+            nat_type: bootstrap_options.nat_type,
             network_id: bootstrap_options.network_id,
             node_count: bootstrap_options.node_count,
+            node_reachability: ReachabilityMode::Direct,
             output_inventory_dir_path: bootstrap_options.output_inventory_dir_path,
             peer_cache_node_count: 0,
+            peer_cache_node_reachability: ReachabilityMode::Direct,
             private_node_count: bootstrap_options.private_node_count,
+            private_node_upnp: bootstrap_options.private_node_upnp,
             private_node_vms: Vec::new(),
+            provision_parallelism: None,
+            provision_retries: 0,
             public_rpc: false,
             rewards_address: bootstrap_options.rewards_address,
             ant_version: None,
+            telemetry: None,
             uploaders_count: None,
         }
     }
@@ -106,8 +143,13 @@ impl From<BootstrapOptions> for ProvisionOptions {
 impl From<DeployOptions> for ProvisionOptions {
     fn from(deploy_options: DeployOptions) -> Self {
         ProvisionOptions {
+            architecture: deploy_options.architecture,
+            artifact_proxy_url: None,
+            artifact_repository: deploy_options.artifact_repository,
             binary_option: deploy_options.binary_option,
+            build_cache_key: None,
             chunk_size: deploy_options.chunk_size,
+            door_node_vms: Vec::new(),
             downloaders_count: deploy_options.downloaders_count,
             env_variables: deploy_options.env_variables,
             evm_data_payments_address: deploy_options.evm_data_payments_address,
@@ -117,22 +159,29 @@ impl From<DeployOptions> for ProvisionOptions {
             funding_wallet_secret_key: deploy_options.funding_wallet_secret_key,
             gas_amount: None,
             interval: deploy_options.interval,
+            join_rate: None,
             log_format: deploy_options.log_format,
-            logstash_details: deploy_options.logstash_details,
             name: deploy_options.name,
             nat_gateway: None,
+            nat_type: deploy_options.nat_type,
             network_id: deploy_options.network_id,
             node_count: deploy_options.node_count,
+            node_reachability: deploy_options.node_reachability,
             max_archived_log_files: deploy_options.max_archived_log_files,
             max_log_files: deploy_options.max_log_files,
             output_inventory_dir_path: deploy_options.output_inventory_dir_path,
             peer_cache_node_count: deploy_options.peer_cache_node_count,
+            peer_cache_node_reachability: deploy_options.peer_cache_node_reachability,
             public_rpc: deploy_options.public_rpc,
             private_node_count: deploy_options.private_node_count,
+            private_node_upnp: deploy_options.private_node_upnp,
             private_node_vms: Vec::new(),
+            provision_parallelism: deploy_options.provision_parallelism,
+            provision_retries: deploy_options.provision_retries,
             ant_version: None,
             uploaders_count: Some(deploy_options.uploaders_count),
             rewards_address: deploy_options.rewards_address,
+            telemetry: deploy_options.telemetry,
         }
     }
 }
@@ -158,7 +207,6 @@ impl AnsibleProvisioner {
     }
 
     pub fn build_safe_network_binaries(&self, options: &ProvisionOptions) -> Result<()> {
-        let start = Instant::now();
         println!("Obtaining IP address for build VM...");
         let build_inventory = self
             .ansible_runner
@@ -174,7 +222,6 @@ impl AnsibleProvisioner {
             AnsibleInventoryType::Build,
             Some(extra_vars),
         )?;
-        print_duration(start.elapsed());
         Ok(())
     }
 
@@ -190,6 +237,17 @@ impl AnsibleProvisioner {
         Ok(())
     }
 
+    pub fn clear_node_caches(&self) -> Result<()> {
+        for node_inv_type in AnsibleInventoryType::iter_node_type() {
+            self.ansible_runner.run_playbook(
+                AnsiblePlaybook::ClearNodeCaches,
+                node_inv_type,
+                None,
+            )?;
+        }
+        Ok(())
+    }
+
     pub fn copy_logs(&self, name: &str, resources_only: bool) -> Result<()> {
         for node_inv_type in AnsibleInventoryType::iter_node_type() {
             self.ansible_runner.run_playbook(
@@ -269,7 +327,6 @@ impl AnsibleProvisioner {
     }
 
     pub fn provision_evm_nodes(&self, options: &ProvisionOptions) -> Result<()> {
-        let start = Instant::now();
         println!("Obtaining IP address for EVM nodes...");
         let evm_node_inventory = self
             .ansible_runner
@@ -287,12 +344,10 @@ impl AnsibleProvisioner {
                 &self.cloud_provider,
             )),
         )?;
-        print_duration(start.elapsed());
         Ok(())
     }
 
     pub fn provision_genesis_node(&self, options: &ProvisionOptions) -> Result<()> {
-        let start = Instant::now();
         let genesis_inventory = self
             .ansible_runner
             .get_inventory(AnsibleInventoryType::Genesis, true)?;
@@ -310,43 +365,325 @@ impl AnsibleProvisioner {
                 None,
                 1,
                 options.evm_network.clone(),
+                None,
             )?),
         )?;
 
-        print_duration(start.elapsed());
-
         Ok(())
     }
 
-    pub fn provision_nat_gateway(&self, options: &ProvisionOptions) -> Result<()> {
-        let start = Instant::now();
-        let nat_gateway_inventory = self
-            .ansible_runner
-            .get_inventory(AnsibleInventoryType::NatGateway, true)?;
-        let nat_gateway_ip = nat_gateway_inventory[0].public_ip_addr;
-        self.ssh_client
-            .wait_for_ssh_availability(&nat_gateway_ip, &self.cloud_provider.get_ssh_user())?;
-        let private_ips = options
-            .private_node_vms
-            .iter()
-            .map(|vm| vm.private_ip_addr.to_string())
-            .collect::<Vec<_>>();
-
-        if private_ips.is_empty() {
+    /// Configure each NAT gateway to masquerade the traffic from its shard of private node VMs.
+    pub fn provision_nat_gateway(
+        &self,
+        options: &ProvisionOptions,
+        shards: &[NatGatewayShard],
+    ) -> Result<()> {
+        if options.private_node_vms.is_empty() {
             println!("There are no private node VM available to be routed through the NAT Gateway");
             return Err(Error::EmptyInventory(AnsibleInventoryType::PrivateNodes));
         }
 
+        let port_ranges = match options.nat_type {
+            NatType::FullCone => get_full_cone_port_ranges(&options.private_node_vms)?,
+            NatType::Restricted | NatType::Symmetric => Vec::new(),
+        };
+        for shard in shards.iter() {
+            if shard.private_node_vms.is_empty() {
+                debug!(
+                    "No private node VMs are routed through {}",
+                    shard.gateway.name
+                );
+                continue;
+            }
+            self.ssh_client.wait_for_ssh_availability(
+                &shard.gateway.public_ip_addr,
+                &self.cloud_provider.get_ssh_user(),
+            )?;
+            self.ansible_runner.run_playbook_with_retries(
+                AnsiblePlaybook::NatGateway,
+                AnsibleInventoryType::NatGateway,
+                Some(extra_vars::build_nat_gateway_extra_vars_doc(
+                    &options.name,
+                    shard.get_private_ips(),
+                    options.nat_type,
+                    &port_ranges,
+                    options.private_node_upnp,
+                )?),
+                std::slice::from_ref(&shard.gateway),
+                options.provision_retries,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Harden every machine in the deployment before anything else is provisioned.
+    ///
+    /// The playbook connects as the bootstrap user and creates the SSH user that will be used
+    /// from then on. Root login is only disabled if the SSH user is not root.
+    pub fn provision_hardening(&self) -> Result<()> {
+        let bootstrap_user = self.cloud_provider.get_ssh_bootstrap_user();
+        let ssh_user = self.cloud_provider.get_ssh_user();
+        for inventory_type in [
+            AnsibleInventoryType::ArtifactProxy,
+            AnsibleInventoryType::Build,
+            AnsibleInventoryType::EvmNodes,
+            AnsibleInventoryType::Genesis,
+            AnsibleInventoryType::Monitoring,
+            AnsibleInventoryType::NatGateway,
+            AnsibleInventoryType::Nodes,
+            AnsibleInventoryType::PeerCacheNodes,
+            AnsibleInventoryType::PrivateNodes,
+            AnsibleInventoryType::Uploaders,
+        ] {
+            let vms = self.ansible_runner.get_inventory(inventory_type, false)?;
+            if vms.is_empty() {
+                debug!("No {inventory_type} VMs to harden");
+                continue;
+            }
+            for vm in vms.iter() {
+                self.ssh_client
+                    .wait_for_ssh_availability(&vm.public_ip_addr, &bootstrap_user)?;
+            }
+            self.ansible_runner.run_playbook_as_user(
+                AnsiblePlaybook::Hardening,
+                inventory_type,
+                Some(extra_vars::build_hardening_extra_vars_doc(
+                    &ssh_user,
+                    ssh_user != "root",
+                )),
+                &bootstrap_user,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Provision Nginx on the artifact proxy VM, as a caching proxy for the S3 buckets.
+    pub fn provision_artifact_proxy(&self, options: &ProvisionOptions) -> Result<()> {
+        let artifact_proxy_inventory = self
+            .ansible_runner
+            .get_inventory(AnsibleInventoryType::ArtifactProxy, true)?;
+        let artifact_proxy_vm = artifact_proxy_inventory
+            .first()
+            .ok_or_else(|| Error::EmptyInventory(AnsibleInventoryType::ArtifactProxy))?;
+        self.ssh_client.wait_for_ssh_availability(
+            &artifact_proxy_vm.public_ip_addr,
+            &self.cloud_provider.get_ssh_user(),
+        )?;
+
         self.ansible_runner.run_playbook(
-            AnsiblePlaybook::NatGateway,
+            AnsiblePlaybook::ArtifactProxy,
+            AnsibleInventoryType::ArtifactProxy,
+            Some(extra_vars::build_artifact_proxy_extra_vars_doc(
+                &options.name,
+                &options.artifact_repository,
+            )),
+        )?;
+
+        Ok(())
+    }
+
+    /// Provision the auditor on each of the auditor VMs, with the genesis node as its peer.
+    pub fn provision_auditors(
+        &self,
+        options: &ProvisionOptions,
+        genesis_multiaddr: &str,
+    ) -> Result<()> {
+        let auditor_inventory = self
+            .ansible_runner
+            .get_inventory(AnsibleInventoryType::Auditor, true)?;
+        if auditor_inventory.is_empty() {
+            return Err(Error::EmptyInventory(AnsibleInventoryType::Auditor));
+        }
+        for vm in auditor_inventory.iter() {
+            self.ssh_client.wait_for_ssh_availability(
+                &vm.public_ip_addr,
+                &self.cloud_provider.get_ssh_user(),
+            )?;
+        }
+
+        self.ansible_runner.run_playbook(
+            AnsiblePlaybook::Auditor,
+            AnsibleInventoryType::Auditor,
+            Some(extra_vars::build_auditor_extra_vars_doc(
+                &options.name,
+                genesis_multiaddr,
+                options.architecture,
+            )),
+        )?;
+
+        Ok(())
+    }
+
+    /// Provision the infra services on the artifact proxy VM: a DNS forwarder and an NTP server,
+    /// both listening on its private IP.
+    ///
+    /// Along with the artifact cache, these are the only services the other machines need from
+    /// outside the VPC.
+    pub fn provision_infra_services(
+        &self,
+        options: &ProvisionOptions,
+        upstream_dns_resolvers: &[IpAddr],
+    ) -> Result<()> {
+        let artifact_proxy_inventory = self
+            .ansible_runner
+            .get_inventory(AnsibleInventoryType::ArtifactProxy, true)?;
+        let artifact_proxy_vm = artifact_proxy_inventory
+            .first()
+            .ok_or_else(|| Error::EmptyInventory(AnsibleInventoryType::ArtifactProxy))?;
+        self.ssh_client.wait_for_ssh_availability(
+            &artifact_proxy_vm.public_ip_addr,
+            &self.cloud_provider.get_ssh_user(),
+        )?;
+
+        self.ansible_runner.run_playbook(
+            AnsiblePlaybook::InfraServices,
+            AnsibleInventoryType::ArtifactProxy,
+            Some(extra_vars::build_infra_services_extra_vars_doc(
+                &options.name,
+                upstream_dns_resolvers,
+            )),
+        )?;
+
+        Ok(())
+    }
+
+    /// Get the IP the DNS forwarder and NTP server on the artifact proxy listen on, if the
+    /// deployment has one.
+    pub fn get_infra_services_ip(&self) -> Result<Option<IpAddr>> {
+        let artifact_proxy_inventory = self
+            .ansible_runner
+            .get_inventory(AnsibleInventoryType::ArtifactProxy, false)?;
+        Ok(artifact_proxy_inventory
+            .first()
+            .map(|vm| vm.private_ip_addr))
+    }
+
+    /// Get the URL of the artifact proxy, if the deployment has one.
+    ///
+    /// The proxy only listens on its private IP, so it is not reachable from outside the VPC.
+    pub fn get_artifact_proxy_url(&self) -> Result<Option<String>> {
+        let artifact_proxy_inventory = self
+            .ansible_runner
+            .get_inventory(AnsibleInventoryType::ArtifactProxy, false)?;
+        Ok(artifact_proxy_inventory
+            .first()
+            .map(|vm| format!("http://{}", vm.private_ip_addr)))
+    }
+
+    /// Configure the DNS resolvers, NTP servers and add the host entries on every machine in the
+    /// deployment.
+    ///
+    /// This runs before any of the binaries are downloaded, so the host entries can be used to
+    /// redirect those downloads.
+    pub fn provision_dns_config(
+        &self,
+        dns_resolvers: &[IpAddr],
+        host_entries: &[(String, IpAddr)],
+        ntp_servers: &[IpAddr],
+    ) -> Result<()> {
+        let ssh_user = self.cloud_provider.get_ssh_user();
+        for inventory_type in [
+            AnsibleInventoryType::ArtifactProxy,
+            AnsibleInventoryType::Build,
+            AnsibleInventoryType::EvmNodes,
+            AnsibleInventoryType::Genesis,
+            AnsibleInventoryType::Monitoring,
             AnsibleInventoryType::NatGateway,
-            Some(extra_vars::build_nat_gateway_extra_vars_doc(
+            AnsibleInventoryType::Nodes,
+            AnsibleInventoryType::PeerCacheNodes,
+            AnsibleInventoryType::PrivateNodes,
+            AnsibleInventoryType::Uploaders,
+        ] {
+            let vms = self.ansible_runner.get_inventory(inventory_type, false)?;
+            if vms.is_empty() {
+                debug!("No {inventory_type} VMs to configure DNS on");
+                continue;
+            }
+            for vm in vms.iter() {
+                self.ssh_client
+                    .wait_for_ssh_availability(&vm.public_ip_addr, &ssh_user)?;
+            }
+            self.ansible_runner.run_playbook(
+                AnsiblePlaybook::DnsConfig,
+                inventory_type,
+                Some(extra_vars::build_dns_config_extra_vars_doc(
+                    dns_resolvers,
+                    host_entries,
+                    ntp_servers,
+                )),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Provision Prometheus and Grafana on the monitoring VM.
+    ///
+    /// The scrape targets are generated from the node inventory. Each node on a VM gets the next
+    /// metrics port, so the number of nodes per VM determines the targets. The private IP is used
+    /// because it is also reachable for the private nodes.
+    pub fn provision_monitoring(&self, options: &ProvisionOptions) -> Result<()> {
+        let monitoring_inventory = self
+            .ansible_runner
+            .get_inventory(AnsibleInventoryType::Monitoring, true)?;
+        let monitoring_vm = monitoring_inventory
+            .first()
+            .ok_or_else(|| Error::EmptyInventory(AnsibleInventoryType::Monitoring))?;
+        self.ssh_client.wait_for_ssh_availability(
+            &monitoring_vm.public_ip_addr,
+            &self.cloud_provider.get_ssh_user(),
+        )?;
+
+        let mut scrape_targets = Vec::new();
+        for (inventory_type, nodes_per_vm) in [
+            (AnsibleInventoryType::Genesis, 1),
+            (
+                AnsibleInventoryType::PeerCacheNodes,
+                options.peer_cache_node_count,
+            ),
+            (AnsibleInventoryType::Nodes, options.node_count),
+            (
+                AnsibleInventoryType::PrivateNodes,
+                options.private_node_count,
+            ),
+        ] {
+            let vms = self.ansible_runner.get_inventory(inventory_type, false)?;
+            for vm in vms.iter() {
+                for port in NODE_METRICS_START_PORT..NODE_METRICS_START_PORT + nodes_per_vm {
+                    scrape_targets.push(format!("{}:{port}", vm.private_ip_addr));
+                }
+            }
+        }
+        debug!(
+            "Generated {} scrape targets for monitoring",
+            scrape_targets.len()
+        );
+
+        // A redeploy keeps the password from the previous deployment.
+        let admin_password = match grafana::read_admin_password(&options.name)? {
+            Some(admin_password) => admin_password,
+            None => grafana::generate_admin_password(),
+        };
+        let admin_password_path = grafana::save_admin_password(&options.name, &admin_password)?;
+
+        self.ansible_runner.run_playbook(
+            AnsiblePlaybook::Monitoring,
+            AnsibleInventoryType::Monitoring,
+            Some(extra_vars::build_monitoring_extra_vars_doc(
                 &options.name,
-                private_ips,
+                scrape_targets,
+                &admin_password,
             )),
         )?;
+        println!(
+            "Grafana is available at http://{}:3000",
+            monitoring_vm.public_ip_addr
+        );
+        println!(
+            "The Grafana admin password is saved at {}",
+            admin_password_path.display()
+        );
 
-        print_duration(start.elapsed());
         Ok(())
     }
 
@@ -357,7 +694,32 @@ impl AnsibleProvisioner {
         initial_network_contacts_url: Option<String>,
         node_type: NodeType,
     ) -> Result<()> {
-        let start = Instant::now();
+        if matches!(node_type, NodeType::PeerCache | NodeType::Genesis) {
+            return Err(Error::InvalidNodeType(node_type));
+        }
+
+        println!("Obtaining IP addresses for nodes...");
+        let inventory = self
+            .ansible_runner
+            .get_inventory(node_type.to_ansible_inventory_type(), true)?;
+        self.provision_node_vms(
+            options,
+            initial_contact_peer,
+            initial_network_contacts_url,
+            node_type,
+            &inventory,
+        )
+    }
+
+    /// Provision the generic or private nodes on the given VMs.
+    fn provision_node_vms(
+        &self,
+        options: &ProvisionOptions,
+        initial_contact_peer: Option<String>,
+        initial_network_contacts_url: Option<String>,
+        node_type: NodeType,
+        inventory: &[VirtualMachine],
+    ) -> Result<()> {
         let (inventory_type, node_count) = match &node_type {
             NodeType::PeerCache => return Err(Error::InvalidNodeType(node_type)),
             NodeType::Generic => (node_type.to_ansible_inventory_type(), options.node_count),
@@ -372,9 +734,6 @@ impl AnsibleProvisioner {
         // For a new deployment, it's quite probable that SSH is available, because this part occurs
         // after the genesis node has been provisioned. However, for a bootstrap deploy, we need to
         // check that SSH is available before proceeding.
-        println!("Obtaining IP addresses for nodes...");
-        let inventory = self.ansible_runner.get_inventory(inventory_type, true)?;
-
         println!("Waiting for SSH availability on {node_type:?} nodes...");
         for vm in inventory.iter() {
             println!(
@@ -391,21 +750,35 @@ impl AnsibleProvisioner {
 
         println!("SSH is available on all nodes. Proceeding with provisioning...");
 
-        self.ansible_runner.run_playbook(
+        let join_rate_schedule = match options.join_rate {
+            Some(join_rate) => {
+                let schedule = JoinRateSchedule::new(join_rate, &node_type, inventory)?;
+                println!(
+                    "Limiting the join rate to {join_rate} nodes per minute: each VM starts a node every {:?}",
+                    schedule.interval
+                );
+                Some(schedule)
+            }
+            None => None,
+        };
+        let extra_vars = extra_vars::build_node_extra_vars_doc(
+            &self.cloud_provider.to_string(),
+            options,
+            node_type.clone(),
+            initial_contact_peer,
+            initial_network_contacts_url,
+            node_count,
+            options.evm_network.clone(),
+            join_rate_schedule.as_ref(),
+        )?;
+        self.run_node_playbook(
             AnsiblePlaybook::Nodes,
             inventory_type,
-            Some(extra_vars::build_node_extra_vars_doc(
-                &self.cloud_provider.to_string(),
-                options,
-                node_type.clone(),
-                initial_contact_peer,
-                initial_network_contacts_url,
-                node_count,
-                options.evm_network.clone(),
-            )?),
+            extra_vars,
+            inventory,
+            options,
         )?;
 
-        print_duration(start.elapsed());
         Ok(())
     }
 
@@ -415,7 +788,6 @@ impl AnsibleProvisioner {
         initial_contact_peer: Option<String>,
         initial_network_contacts_url: Option<String>,
     ) -> Result<()> {
-        let start = Instant::now();
         let node_type = NodeType::PeerCache;
 
         // For a new deployment, it's quite probable that SSH is available, because this part occurs
@@ -442,63 +814,102 @@ impl AnsibleProvisioner {
 
         println!("SSH is available on peer cache nodes. Proceeding with provisioning...");
 
-        self.ansible_runner.run_playbook(
+        let extra_vars = extra_vars::build_node_extra_vars_doc(
+            &self.cloud_provider.to_string(),
+            options,
+            node_type.clone(),
+            initial_contact_peer,
+            initial_network_contacts_url,
+            options.peer_cache_node_count,
+            options.evm_network.clone(),
+            None,
+        )?;
+        self.run_node_playbook(
             AnsiblePlaybook::PeerCacheNodes,
             node_type.to_ansible_inventory_type(),
-            Some(extra_vars::build_node_extra_vars_doc(
-                &self.cloud_provider.to_string(),
-                options,
-                node_type.clone(),
-                initial_contact_peer,
-                initial_network_contacts_url,
-                options.peer_cache_node_count,
-                options.evm_network.clone(),
-            )?),
+            extra_vars,
+            &inventory,
+            options,
         )?;
 
-        print_duration(start.elapsed());
         Ok(())
     }
 
+    /// Run a node playbook against all the VMs at once, or in batches if a parallelism was given,
+    /// retrying any hosts that fail.
+    fn run_node_playbook(
+        &self,
+        playbook: AnsiblePlaybook,
+        inventory_type: AnsibleInventoryType,
+        extra_vars: String,
+        vms: &[VirtualMachine],
+        options: &ProvisionOptions,
+    ) -> Result<()> {
+        match options.provision_parallelism {
+            Some(batch_size) => self.ansible_runner.run_playbook_in_batches(
+                playbook,
+                inventory_type,
+                Some(extra_vars),
+                vms,
+                batch_size,
+                options.provision_retries,
+            ),
+            None => self.ansible_runner.run_playbook_with_retries(
+                playbook,
+                inventory_type,
+                Some(extra_vars),
+                vms,
+                options.provision_retries,
+            ),
+        }
+    }
+
+    /// Provision the private nodes, a shard at a time, so each VM is routed through its own NAT
+    /// gateway.
+    ///
+    /// The first gateway is used as the SSH jump host for all the private node VMs.
     pub fn provision_private_nodes(
         &self,
         options: &mut ProvisionOptions,
+        shards: &[NatGatewayShard],
         initial_contact_peer: Option<String>,
         initial_network_contacts_url: Option<String>,
     ) -> Result<()> {
-        let start = Instant::now();
-
-        let nat_gateway_inventory = self
-            .ansible_runner
-            .get_inventory(AnsibleInventoryType::NatGateway, true)
-            .map_err(|err| {
-                println!("Failed to get NAT Gateway inventory {err:?}");
-                err
-            })?
-            .first()
-            .ok_or_else(|| Error::EmptyInventory(AnsibleInventoryType::NatGateway))?
-            .clone();
+        let Some(first_shard) = shards.first() else {
+            return Err(Error::EmptyInventory(AnsibleInventoryType::NatGateway));
+        };
 
-        options.nat_gateway = Some(nat_gateway_inventory.clone());
         generate_private_node_static_environment_inventory(
             &options.name,
+            self.cloud_provider,
             &options.output_inventory_dir_path,
             &options.private_node_vms,
-            &Some(nat_gateway_inventory),
+            &Some(first_shard.gateway.clone()),
             &self.ssh_client.private_key_path,
         )
         .inspect_err(|err| {
             error!("Failed to generate private node static inv with err: {err:?}")
         })?;
 
-        self.provision_nodes(
-            options,
-            initial_contact_peer,
-            initial_network_contacts_url,
-            NodeType::Private,
-        )?;
+        for shard in shards.iter() {
+            if shard.private_node_vms.is_empty() {
+                continue;
+            }
+            debug!(
+                "Provisioning {} private node VMs routed through {}",
+                shard.private_node_vms.len(),
+                shard.gateway.name
+            );
+            options.nat_gateway = Some(shard.gateway.clone());
+            self.provision_node_vms(
+                options,
+                initial_contact_peer.clone(),
+                initial_network_contacts_url.clone(),
+                NodeType::Private,
+                &shard.private_node_vms,
+            )?;
+        }
 
-        print_duration(start.elapsed());
         Ok(())
     }
 
@@ -508,8 +919,6 @@ impl AnsibleProvisioner {
         genesis_multiaddr: Option<String>,
         genesis_network_contacts_url: Option<String>,
     ) -> Result<()> {
-        let start = Instant::now();
-
         let sk_map = self
             .deposit_funds_to_uploaders(&FundingOptions {
                 evm_data_payments_address: options.evm_data_payments_address.clone(),
@@ -537,7 +946,6 @@ impl AnsibleProvisioner {
                 &sk_map,
             )?),
         )?;
-        print_duration(start.elapsed());
         Ok(())
     }
 
@@ -638,6 +1046,100 @@ impl AnsibleProvisioner {
         Ok(())
     }
 
+    /// Stop the node services on the given VMs, before they are removed in a downscale.
+    pub fn drain_nodes(
+        &self,
+        environment_name: &str,
+        interval: Duration,
+        vms: &[VirtualMachine],
+    ) -> Result<()> {
+        let mut extra_vars = ExtraVarsDocBuilder::default();
+        extra_vars.add_variable("interval", &interval.as_millis().to_string());
+
+        generate_custom_environment_inventory(
+            vms,
+            environment_name,
+            &self.ansible_runner.working_directory_path.join("inventory"),
+        )?;
+        self.ansible_runner.run_playbook(
+            AnsiblePlaybook::DrainNodes,
+            AnsibleInventoryType::Custom,
+            Some(extra_vars.build()),
+        )?;
+        Ok(())
+    }
+
+    pub fn apply_restart_policy(
+        &self,
+        environment_name: &str,
+        restart_policy: &RestartPolicy,
+        node_type: Option<NodeType>,
+        custom_inventory: Option<Vec<VirtualMachine>>,
+    ) -> Result<()> {
+        let extra_vars = extra_vars::build_restart_policy_extra_vars_doc(restart_policy);
+
+        if let Some(node_type) = node_type {
+            println!("Running the restart policy playbook for {node_type:?} nodes");
+            self.ansible_runner.run_playbook(
+                AnsiblePlaybook::RestartPolicy,
+                node_type.to_ansible_inventory_type(),
+                Some(extra_vars),
+            )?;
+            return Ok(());
+        }
+
+        if let Some(custom_inventory) = custom_inventory {
+            println!("Running the restart policy playbook with a custom inventory");
+            generate_custom_environment_inventory(
+                &custom_inventory,
+                environment_name,
+                &self.ansible_runner.working_directory_path.join("inventory"),
+            )?;
+            self.ansible_runner.run_playbook(
+                AnsiblePlaybook::RestartPolicy,
+                AnsibleInventoryType::Custom,
+                Some(extra_vars),
+            )?;
+            return Ok(());
+        }
+
+        println!("Running the restart policy playbook for all node types");
+        for node_inv_type in AnsibleInventoryType::iter_node_type() {
+            self.ansible_runner.run_playbook(
+                AnsiblePlaybook::RestartPolicy,
+                node_inv_type,
+                Some(extra_vars.clone()),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Expose the RPC of the nodes through a TLS reverse proxy on each VM.
+    ///
+    /// The private nodes are skipped, because they can't be reached from outside.
+    pub fn provision_rpc_proxy(&self, auth_token: &str, acme_email: Option<&str>) -> Result<()> {
+        let extra_vars = extra_vars::build_rpc_proxy_extra_vars_doc(auth_token, acme_email);
+        for node_inv_type in AnsibleInventoryType::iter_node_type()
+            .filter(|inv_type| !matches!(inv_type, AnsibleInventoryType::PrivateNodes))
+        {
+            if self
+                .ansible_runner
+                .get_inventory(node_inv_type, false)?
+                .is_empty()
+            {
+                debug!("No {node_inv_type} VMs to provision the RPC proxy on");
+                continue;
+            }
+            self.ansible_runner.run_playbook(
+                AnsiblePlaybook::RpcProxy,
+                node_inv_type,
+                Some(extra_vars.clone()),
+            )?;
+        }
+        Ok(())
+    }
+
     pub fn stop_nodes(
         &self,
         environment_name: &str,
@@ -690,6 +1192,113 @@ impl AnsibleProvisioner {
         Ok(())
     }
 
+    /// Restart the nodes, one VM batch at a time.
+    ///
+    /// The batch size is applied with the playbook's `serial` keyword, so each batch finishes
+    /// restarting before the next begins.
+    pub fn restart_nodes(
+        &self,
+        environment_name: &str,
+        interval: Duration,
+        node_type: Option<NodeType>,
+        custom_inventory: Option<Vec<VirtualMachine>>,
+        vm_batch_size: Option<usize>,
+    ) -> Result<()> {
+        let mut extra_vars = ExtraVarsDocBuilder::default();
+        extra_vars.add_variable("interval", &interval.as_millis().to_string());
+        if let Some(vm_batch_size) = vm_batch_size {
+            extra_vars.add_variable("vm_batch_size", &vm_batch_size.to_string());
+        }
+        let extra_vars = extra_vars.build();
+
+        if let Some(node_type) = node_type {
+            println!("Running the restart nodes playbook for {node_type:?} nodes");
+            self.ansible_runner.run_playbook(
+                AnsiblePlaybook::RestartNodes,
+                node_type.to_ansible_inventory_type(),
+                Some(extra_vars),
+            )?;
+            return Ok(());
+        }
+
+        if let Some(custom_inventory) = custom_inventory {
+            println!("Running the restart nodes playbook with a custom inventory");
+            generate_custom_environment_inventory(
+                &custom_inventory,
+                environment_name,
+                &self.ansible_runner.working_directory_path.join("inventory"),
+            )?;
+            self.ansible_runner.run_playbook(
+                AnsiblePlaybook::RestartNodes,
+                AnsibleInventoryType::Custom,
+                Some(extra_vars),
+            )?;
+            return Ok(());
+        }
+
+        println!("Running the restart nodes playbook for all node types");
+        for node_inv_type in AnsibleInventoryType::iter_node_type() {
+            self.ansible_runner.run_playbook(
+                AnsiblePlaybook::RestartNodes,
+                node_inv_type,
+                Some(extra_vars.clone()),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Replace the environment variables of the node services, then restart them.
+    pub fn update_node_env(
+        &self,
+        environment_name: &str,
+        env_variables: Vec<(String, String)>,
+        interval: Duration,
+        node_type: Option<NodeType>,
+        custom_inventory: Option<Vec<VirtualMachine>>,
+    ) -> Result<()> {
+        let mut extra_vars = ExtraVarsDocBuilder::default();
+        extra_vars.add_variable("interval", &interval.as_millis().to_string());
+        extra_vars.add_env_variable_list("env_variables", env_variables);
+        let extra_vars = extra_vars.build();
+
+        if let Some(node_type) = node_type {
+            println!("Running the update node env playbook for {node_type:?} nodes");
+            self.ansible_runner.run_playbook(
+                AnsiblePlaybook::UpdateNodeEnv,
+                node_type.to_ansible_inventory_type(),
+                Some(extra_vars),
+            )?;
+            return Ok(());
+        }
+
+        if let Some(custom_inventory) = custom_inventory {
+            println!("Running the update node env playbook with a custom inventory");
+            generate_custom_environment_inventory(
+                &custom_inventory,
+                environment_name,
+                &self.ansible_runner.working_directory_path.join("inventory"),
+            )?;
+            self.ansible_runner.run_playbook(
+                AnsiblePlaybook::UpdateNodeEnv,
+                AnsibleInventoryType::Custom,
+                Some(extra_vars),
+            )?;
+            return Ok(());
+        }
+
+        println!("Running the update node env playbook for all node types");
+        for node_inv_type in AnsibleInventoryType::iter_node_type() {
+            self.ansible_runner.run_playbook(
+                AnsiblePlaybook::UpdateNodeEnv,
+                node_inv_type,
+                Some(extra_vars.clone()),
+            )?;
+        }
+
+        Ok(())
+    }
+
     pub fn stop_telegraf(
         &self,
         environment_name: &str,
@@ -730,6 +1339,53 @@ impl AnsibleProvisioner {
         Ok(())
     }
 
+    /// Apply allow and deny lists of peer IP addresses to the node VMs, then restart the nodes.
+    ///
+    /// Both lists being empty clears any filter that was applied previously.
+    pub fn apply_peer_filter(
+        &self,
+        environment_name: &str,
+        node_type: Option<NodeType>,
+        custom_inventory: Option<Vec<VirtualMachine>>,
+        extra_vars: String,
+    ) -> Result<()> {
+        if let Some(node_type) = node_type {
+            println!("Running the peer filter playbook for {node_type:?} nodes");
+            self.ansible_runner.run_playbook(
+                AnsiblePlaybook::PeerFilter,
+                node_type.to_ansible_inventory_type(),
+                Some(extra_vars),
+            )?;
+            return Ok(());
+        }
+
+        if let Some(custom_inventory) = custom_inventory {
+            println!("Running the peer filter playbook with a custom inventory");
+            generate_custom_environment_inventory(
+                &custom_inventory,
+                environment_name,
+                &self.ansible_runner.working_directory_path.join("inventory"),
+            )?;
+            self.ansible_runner.run_playbook(
+                AnsiblePlaybook::PeerFilter,
+                AnsibleInventoryType::Custom,
+                Some(extra_vars),
+            )?;
+            return Ok(());
+        }
+
+        println!("Running the peer filter playbook for all node types");
+        for node_inv_type in AnsibleInventoryType::iter_node_type() {
+            self.ansible_runner.run_playbook(
+                AnsiblePlaybook::PeerFilter,
+                node_inv_type,
+                Some(extra_vars.clone()),
+            )?;
+        }
+
+        Ok(())
+    }
+
     pub fn upgrade_node_telegraf(&self, name: &str) -> Result<()> {
         self.ansible_runner.run_playbook(
             AnsiblePlaybook::UpgradeNodeTelegrafConfig,
@@ -781,7 +1437,7 @@ impl AnsibleProvisioner {
                 AnsibleInventoryType::Custom,
                 Some(options.get_ansible_vars()),
             ) {
-                Ok(()) => println!("All nodes were successfully upgraded"),
+                Ok(_) => println!("All nodes were successfully upgraded"),
                 Err(_) => {
                     println!("WARNING: some nodes may not have been upgraded or restarted");
                 }
@@ -796,7 +1452,7 @@ impl AnsibleProvisioner {
                 node_type.to_ansible_inventory_type(),
                 Some(options.get_ansible_vars()),
             ) {
-                Ok(()) => println!("All {node_type:?} nodes were successfully upgraded"),
+                Ok(_) => println!("All {node_type:?} nodes were successfully upgraded"),
                 Err(_) => {
                     println!(
                         "WARNING: some {node_type:?} nodes may not have been upgraded or restarted"
@@ -813,7 +1469,7 @@ impl AnsibleProvisioner {
             AnsibleInventoryType::PeerCacheNodes,
             Some(options.get_ansible_vars()),
         ) {
-            Ok(()) => println!("All Peer Cache nodes were successfully upgraded"),
+            Ok(_) => println!("All Peer Cache nodes were successfully upgraded"),
             Err(_) => {
                 println!("WARNING: some Peer Cacche nodes may not have been upgraded or restarted");
             }
@@ -823,7 +1479,7 @@ impl AnsibleProvisioner {
             AnsibleInventoryType::Nodes,
             Some(options.get_ansible_vars()),
         ) {
-            Ok(()) => println!("All generic nodes were successfully upgraded"),
+            Ok(_) => println!("All generic nodes were successfully upgraded"),
             Err(_) => {
                 println!("WARNING: some nodes may not have been upgraded or restarted");
             }
@@ -833,7 +1489,7 @@ impl AnsibleProvisioner {
             AnsibleInventoryType::PrivateNodes,
             Some(options.get_ansible_vars()),
         ) {
-            Ok(()) => println!("All private nodes were successfully upgraded"),
+            Ok(_) => println!("All private nodes were successfully upgraded"),
             Err(_) => {
                 println!("WARNING: some nodes may not have been upgraded or restarted");
             }
@@ -844,7 +1500,7 @@ impl AnsibleProvisioner {
             AnsibleInventoryType::Genesis,
             Some(options.get_ansible_vars()),
         ) {
-            Ok(()) => println!("The genesis nodes was successfully upgraded"),
+            Ok(_) => println!("The genesis nodes was successfully upgraded"),
             Err(_) => {
                 println!("WARNING: the genesis node may not have been upgraded or restarted");
             }
@@ -856,14 +1512,16 @@ impl AnsibleProvisioner {
         &self,
         environment_name: &str,
         version: &Version,
+        architecture: Architecture,
         node_type: Option<NodeType>,
         custom_inventory: Option<Vec<VirtualMachine>>,
     ) -> Result<()> {
         let mut extra_vars = ExtraVarsDocBuilder::default();
         extra_vars.add_variable("version", &version.to_string());
+        extra_vars.add_variable("target_triple", architecture.target_triple());
 
         if let Some(node_type) = node_type {
-            println!("Running the upgrade safenode-manager playbook for {node_type:?} nodes");
+            println!("Running the upgrade antctl playbook for {node_type:?} nodes");
             self.ansible_runner.run_playbook(
                 AnsiblePlaybook::UpgradeAntctl,
                 node_type.to_ansible_inventory_type(),
@@ -873,7 +1531,7 @@ impl AnsibleProvisioner {
         }
 
         if let Some(custom_inventory) = custom_inventory {
-            println!("Running the upgrade safenode-manager playbook with a custom inventory");
+            println!("Running the upgrade antctl playbook with a custom inventory");
             generate_custom_environment_inventory(
                 &custom_inventory,
                 environment_name,
@@ -887,7 +1545,7 @@ impl AnsibleProvisioner {
             return Ok(());
         }
 
-        println!("Running the upgrade safenode-manager playbook for all node types");
+        println!("Running the upgrade antctl playbook for all node types");
         for node_inv_type in AnsibleInventoryType::iter_node_type() {
             self.ansible_runner.run_playbook(
                 AnsiblePlaybook::UpgradeAntctl,
@@ -0,0 +1,157 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tracing::debug;
+
+/// The results of every playbook run are recorded here, so a command can produce a report at the
+/// end without having to thread the results back through each of the provisioning functions.
+static PLAYBOOK_RESULTS: Mutex<Vec<HostPlaybookResult>> = Mutex::new(Vec::new());
+
+/// The recap for a single host at the end of a playbook run.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PlaybookResult {
+    pub host: String,
+    pub ok: u32,
+    pub changed: u32,
+    pub failed: u32,
+    pub unreachable: u32,
+}
+
+impl PlaybookResult {
+    pub fn succeeded(&self) -> bool {
+        self.failed == 0 && self.unreachable == 0
+    }
+}
+
+/// The counts as they are written by the `playbook_results` callback plugin, keyed by host.
+#[derive(Deserialize)]
+struct HostCounts {
+    ok: u32,
+    changed: u32,
+    failed: u32,
+    unreachable: u32,
+}
+
+/// Read the results file written by the `playbook_results` callback plugin.
+pub fn read_results_file(path: &Path) -> Result<Vec<PlaybookResult>> {
+    let contents = std::fs::read_to_string(path)?;
+    let counts: BTreeMap<String, HostCounts> =
+        serde_json::from_str(&contents).map_err(|err| Error::PlaybookResultsParseError {
+            path: path.to_path_buf(),
+            error: err.to_string(),
+        })?;
+    Ok(counts
+        .into_iter()
+        .map(|(host, counts)| PlaybookResult {
+            host,
+            ok: counts.ok,
+            changed: counts.changed,
+            failed: counts.failed,
+            unreachable: counts.unreachable,
+        })
+        .collect())
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HostPlaybookResult {
+    pub playbook: String,
+    #[serde(flatten)]
+    pub result: PlaybookResult,
+}
+
+pub fn record(playbook: &str, results: &[PlaybookResult]) {
+    let mut recorded = match PLAYBOOK_RESULTS.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    for result in results {
+        debug!("{playbook} on {}: {result:?}", result.host);
+        recorded.push(HostPlaybookResult {
+            playbook: playbook.to_string(),
+            result: result.clone(),
+        });
+    }
+}
+
+pub fn get_records() -> Vec<HostPlaybookResult> {
+    match PLAYBOOK_RESULTS.lock() {
+        Ok(guard) => guard.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    }
+}
+
+/// A machine-readable report of the playbook results for each host during a deployment.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProvisioningReport {
+    pub environment_name: String,
+    /// The hosts that failed or were unreachable in the final run of any playbook.
+    pub failed_hosts: Vec<String>,
+    /// The time the report was generated, in the form `%Y-%m-%dT%H:%M:%SZ`.
+    pub generated_at: String,
+    pub results: Vec<HostPlaybookResult>,
+}
+
+impl ProvisioningReport {
+    /// Build the report from the results recorded during this run.
+    ///
+    /// When a playbook was run against a host more than once, e.g., because the host was retried,
+    /// only the final result is kept.
+    pub fn from_recorded_results(environment_name: &str) -> Self {
+        let mut latest: HashMap<(String, String), usize> = HashMap::new();
+        let mut results: Vec<HostPlaybookResult> = Vec::new();
+        for record in get_records() {
+            let key = (record.playbook.clone(), record.result.host.clone());
+            match latest.get(&key) {
+                Some(index) => results[*index] = record,
+                None => {
+                    latest.insert(key, results.len());
+                    results.push(record);
+                }
+            }
+        }
+
+        let mut failed_hosts: Vec<String> = results
+            .iter()
+            .filter(|r| !r.result.succeeded())
+            .map(|r| r.result.host.clone())
+            .collect();
+        failed_hosts.sort();
+        failed_hosts.dedup();
+        Self {
+            environment_name: environment_name.to_string(),
+            failed_hosts,
+            generated_at: chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            results,
+        }
+    }
+
+    /// Save the report to the data directory, returning the path it was saved to.
+    pub fn save(&self) -> Result<PathBuf> {
+        let path = dirs_next::data_dir()
+            .ok_or_else(|| Error::CouldNotRetrieveDataDirectory)?
+            .join("safe")
+            .join("testnet-deploy");
+        if !path.exists() {
+            std::fs::create_dir_all(&path)?;
+        }
+        let path = path.join(format!(
+            "{}-provisioning-report.json",
+            self.environment_name
+        ));
+        let mut file = File::create(&path)?;
+        file.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        Ok(path)
+    }
+}
@@ -0,0 +1,246 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+const DEFAULT_S3_REGION: &str = "eu-west-2";
+
+/// The groups of binary archives, each of which is stored in its own bucket.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ArtifactKind {
+    Ant,
+    Antctl,
+    Antnode,
+    /// Custom branch builds, which are stored together regardless of which binary they are.
+    Branch,
+    RpcClient,
+}
+
+impl ArtifactKind {
+    /// The name of the bucket used for this kind of archive by the main repository.
+    pub fn bucket_name(&self) -> &'static str {
+        match self {
+            ArtifactKind::Ant => "autonomi-cli",
+            ArtifactKind::Antctl => "antctl",
+            ArtifactKind::Antnode => "antnode",
+            // The old `sn-node` bucket continues to be used to store custom branch builds.
+            ArtifactKind::Branch => "sn-node",
+            ArtifactKind::RpcClient => "antnode-rpc-client",
+        }
+    }
+
+    pub fn iter() -> impl Iterator<Item = Self> {
+        [
+            Self::Ant,
+            Self::Antctl,
+            Self::Antnode,
+            Self::Branch,
+            Self::RpcClient,
+        ]
+        .into_iter()
+    }
+}
+
+/// Somewhere the VMs can download the binary archives from.
+pub trait ArtifactRepository: std::fmt::Debug + Send + Sync {
+    /// The URL the archives of the given kind are under, without a trailing slash.
+    fn get_base_url(&self, kind: ArtifactKind) -> String;
+}
+
+/// Archives in S3 buckets, which is where the released binaries are published.
+#[derive(Clone, Debug)]
+pub struct S3ArtifactRepository {
+    /// Prepended to the name of each bucket, so a fork can use its own set of buckets.
+    pub bucket_prefix: Option<String>,
+    pub region: String,
+}
+
+impl Default for S3ArtifactRepository {
+    fn default() -> Self {
+        Self {
+            bucket_prefix: None,
+            region: DEFAULT_S3_REGION.to_string(),
+        }
+    }
+}
+
+impl ArtifactRepository for S3ArtifactRepository {
+    fn get_base_url(&self, kind: ArtifactKind) -> String {
+        format!(
+            "https://{}{}.s3.{}.amazonaws.com",
+            self.bucket_prefix.as_deref().unwrap_or_default(),
+            kind.bucket_name(),
+            self.region
+        )
+    }
+}
+
+/// Archives in Digital Ocean Spaces, with the same bucket layout as S3.
+#[derive(Clone, Debug)]
+pub struct DoSpacesArtifactRepository {
+    pub bucket_prefix: Option<String>,
+    pub region: String,
+}
+
+impl ArtifactRepository for DoSpacesArtifactRepository {
+    fn get_base_url(&self, kind: ArtifactKind) -> String {
+        format!(
+            "https://{}{}.{}.digitaloceanspaces.com",
+            self.bucket_prefix.as_deref().unwrap_or_default(),
+            kind.bucket_name(),
+            self.region
+        )
+    }
+}
+
+/// Archives on any web server, e.g., a mirror inside an air-gapped network.
+///
+/// Each kind of archive is in a directory named after its bucket, so a mirror can be populated by
+/// syncing each bucket to a directory of the same name.
+#[derive(Clone, Debug)]
+pub struct HttpArtifactRepository {
+    pub base_url: String,
+}
+
+impl ArtifactRepository for HttpArtifactRepository {
+    fn get_base_url(&self, kind: ArtifactKind) -> String {
+        format!(
+            "{}/{}",
+            self.base_url.trim_end_matches('/'),
+            kind.bucket_name()
+        )
+    }
+}
+
+/// The artifact repository for a deployment.
+///
+/// This is recorded in the environment details, so VMs added to the environment later download
+/// their binaries from the same place.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum ArtifactRepositoryConfig {
+    DoSpaces {
+        bucket_prefix: Option<String>,
+        region: String,
+    },
+    Http {
+        base_url: String,
+    },
+    /// The buckets the binaries are released to.
+    #[default]
+    Main,
+    S3 {
+        bucket_prefix: Option<String>,
+        region: String,
+    },
+}
+
+impl ArtifactRepositoryConfig {
+    /// Parse the repository from one of these forms:
+    ///
+    /// * `s3`, for the main buckets.
+    /// * `s3:<region>[:<bucket-prefix>]`, for S3 buckets in another region or with a prefix.
+    /// * `spaces:<region>[:<bucket-prefix>]`, for Digital Ocean Spaces.
+    /// * An `http://` or `https://` URL, for a web server.
+    pub fn parse_from_str(val: &str) -> Result<Self> {
+        if val.starts_with("http://") || val.starts_with("https://") {
+            return Ok(ArtifactRepositoryConfig::Http {
+                base_url: val.to_string(),
+            });
+        }
+        let mut parts = val.split(':');
+        let backend = parts.next().unwrap_or_default();
+        let region = parts.next().map(|region| region.to_string());
+        let bucket_prefix = parts.next().map(|prefix| prefix.to_string());
+        if parts.next().is_some() || region.as_deref() == Some("") {
+            return Err(Error::InvalidArtifactRepository(val.to_string()));
+        }
+        match (backend, region) {
+            ("s3", None) => Ok(ArtifactRepositoryConfig::Main),
+            ("s3", Some(region)) => Ok(ArtifactRepositoryConfig::S3 {
+                bucket_prefix,
+                region,
+            }),
+            ("spaces", Some(region)) => Ok(ArtifactRepositoryConfig::DoSpaces {
+                bucket_prefix,
+                region,
+            }),
+            _ => Err(Error::InvalidArtifactRepository(val.to_string())),
+        }
+    }
+
+    pub fn build(&self) -> Arc<dyn ArtifactRepository> {
+        match self {
+            ArtifactRepositoryConfig::DoSpaces {
+                bucket_prefix,
+                region,
+            } => Arc::new(DoSpacesArtifactRepository {
+                bucket_prefix: bucket_prefix.clone(),
+                region: region.clone(),
+            }),
+            ArtifactRepositoryConfig::Http { base_url } => Arc::new(HttpArtifactRepository {
+                base_url: base_url.clone(),
+            }),
+            ArtifactRepositoryConfig::Main => Arc::new(S3ArtifactRepository::default()),
+            ArtifactRepositoryConfig::S3 {
+                bucket_prefix,
+                region,
+            } => Arc::new(S3ArtifactRepository {
+                bucket_prefix: bucket_prefix.clone(),
+                region: region.clone(),
+            }),
+        }
+    }
+
+    pub fn is_main(&self) -> bool {
+        matches!(self, ArtifactRepositoryConfig::Main)
+    }
+
+    /// The hosts of the repository, which need to be allowed by the artifact proxy.
+    pub fn get_hosts(&self) -> Vec<String> {
+        let repository = self.build();
+        let mut hosts = Vec::new();
+        for kind in ArtifactKind::iter() {
+            let url = repository.get_base_url(kind);
+            let host = url
+                .split_once("://")
+                .map(|(_, rest)| rest)
+                .unwrap_or(&url)
+                .split('/')
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            if !hosts.contains(&host) {
+                hosts.push(host);
+            }
+        }
+        hosts
+    }
+}
+
+impl std::fmt::Display for ArtifactRepositoryConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ArtifactRepositoryConfig::DoSpaces {
+                bucket_prefix,
+                region,
+            } => match bucket_prefix {
+                Some(prefix) => write!(f, "spaces:{region}:{prefix}"),
+                None => write!(f, "spaces:{region}"),
+            },
+            ArtifactRepositoryConfig::Http { base_url } => write!(f, "{base_url}"),
+            ArtifactRepositoryConfig::Main => write!(f, "s3"),
+            ArtifactRepositoryConfig::S3 {
+                bucket_prefix,
+                region,
+            } => match bucket_prefix {
+                Some(prefix) => write!(f, "s3:{region}:{prefix}"),
+                None => write!(f, "s3:{region}"),
+            },
+        }
+    }
+}
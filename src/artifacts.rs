@@ -0,0 +1,324 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    build_cache::BUILD_BUCKET_NAME,
+    error::{Error, Result},
+    s3::S3Object,
+    Architecture, TestnetDeployer,
+};
+use reqwest::StatusCode;
+use semver::Version;
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+use tracing::debug;
+
+pub struct ArtifactGcOptions {
+    /// Only report the artifacts that would be deleted.
+    pub dry_run: bool,
+    /// Artifacts last modified longer ago than this are deleted.
+    pub retention: Duration,
+}
+
+/// Why a build artifact is due to be deleted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GcReason {
+    BranchDeleted,
+    EnvironmentDeleted,
+    Expired,
+}
+
+impl std::fmt::Display for GcReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            GcReason::BranchDeleted => write!(f, "branch deleted"),
+            GcReason::EnvironmentDeleted => write!(f, "environment deleted"),
+            GcReason::Expired => write!(f, "expired"),
+        }
+    }
+}
+
+/// A branch build archive in the build bucket.
+///
+/// The archives are stored as `<repo_owner>/<branch>/<bin_name>-<name>-<target>.tar.gz`, where the
+/// name is either the environment the build was for, or a build cache key.
+#[derive(Clone, Debug)]
+pub struct BuildArtifact {
+    pub branch: String,
+    pub name: String,
+    pub object: S3Object,
+    pub repo_owner: String,
+}
+
+impl BuildArtifact {
+    pub fn parse(object: S3Object) -> Option<Self> {
+        let (repo_owner, rest) = object.key.split_once('/')?;
+        let (branch, filename) = rest.rsplit_once('/')?;
+        let mut stem = filename.strip_suffix(".tar.gz")?;
+        for architecture in [Architecture::X86_64, Architecture::Aarch64] {
+            if let Some(stripped) = stem.strip_suffix(&format!("-{}", architecture.target_triple()))
+            {
+                stem = stripped;
+            }
+        }
+        // The binary names use underscores rather than hyphens, so the name begins after the
+        // first hyphen.
+        let (_, name) = stem.split_once('-')?;
+        Some(Self {
+            branch: branch.to_string(),
+            name: name.to_string(),
+            repo_owner: repo_owner.to_string(),
+            object,
+        })
+    }
+
+    /// A release archive is named with a version rather than an environment, e.g., `0.3.1`, or
+    /// `latest`. These are never deleted.
+    pub fn is_release(&self) -> bool {
+        self.name == "latest" || Version::parse(self.name.trim_start_matches('v')).is_ok()
+    }
+
+    /// A build cache archive is named with the commit SHA and a hash of the build inputs, so it
+    /// does not belong to an environment.
+    pub fn is_build_cache(&self) -> bool {
+        self.name
+            .split_once('-')
+            .is_some_and(|(sha, hash)| is_hex(sha, 40) && is_hex(hash, 8))
+    }
+}
+
+pub struct ArtifactGcReport {
+    pub candidates: Vec<(BuildArtifact, GcReason)>,
+    pub dry_run: bool,
+    pub protected_count: usize,
+    /// The artifacts kept because they were built for an environment that still exists.
+    pub in_use_count: usize,
+}
+
+impl ArtifactGcReport {
+    pub fn print(&self) {
+        for (artifact, reason) in self.candidates.iter() {
+            println!(
+                "{} ({}, {})",
+                artifact.object.key,
+                format_size(artifact.object.size),
+                reason
+            );
+        }
+        let total_size = self
+            .candidates
+            .iter()
+            .map(|(artifact, _)| artifact.object.size)
+            .sum::<i64>();
+        println!(
+            "{} {} artifacts ({}). {} release artifacts and {} artifacts for existing environments \
+            were protected.",
+            if self.dry_run {
+                "Would delete"
+            } else {
+                "Deleted"
+            },
+            self.candidates.len(),
+            format_size(total_size),
+            self.protected_count,
+            self.in_use_count
+        );
+    }
+}
+
+impl TestnetDeployer {
+    /// Delete the branch build artifacts that are no longer needed.
+    ///
+    /// An artifact is deleted if it is older than the retention window, if its branch no longer
+    /// exists on GitHub, or if it was built for an environment that no longer has a Terraform
+    /// workspace. Release artifacts, the artifacts of environments that still have a workspace,
+    /// whatever their age, and anything outside the build bucket are never deleted.
+    ///
+    /// If the existence of a branch can't be determined, its artifacts are kept.
+    pub async fn gc_artifacts(&self, options: &ArtifactGcOptions) -> Result<ArtifactGcReport> {
+        self.terraform_runner.init()?;
+        let workspaces = self
+            .terraform_runner
+            .workspace_list()?
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let objects = self
+            .s3_repository
+            .list_objects(BUILD_BUCKET_NAME, "")
+            .await?;
+        println!(
+            "Found {} objects in the {BUILD_BUCKET_NAME} bucket",
+            objects.len()
+        );
+
+        let now = chrono::Utc::now().timestamp();
+        let mut branch_exists = HashMap::new();
+        let mut candidates = Vec::new();
+        let mut protected_count = 0;
+        let mut in_use_count = 0;
+        for object in objects {
+            let Some(artifact) = BuildArtifact::parse(object) else {
+                continue;
+            };
+            if artifact.is_release() {
+                protected_count += 1;
+                continue;
+            }
+            // An environment can run for longer than the retention window, and its binaries are
+            // needed to upscale it or to replace a node.
+            if !artifact.is_build_cache() && workspaces.contains(&artifact.name) {
+                in_use_count += 1;
+                continue;
+            }
+
+            let age = artifact
+                .object
+                .last_modified
+                .map(|modified| now - modified)
+                .unwrap_or_default();
+            let reason = if age > options.retention.as_secs() as i64 {
+                Some(GcReason::Expired)
+            } else if !artifact.is_build_cache() {
+                Some(GcReason::EnvironmentDeleted)
+            } else {
+                let branch_key = (artifact.repo_owner.clone(), artifact.branch.clone());
+                let exists = match branch_exists.get(&branch_key) {
+                    Some(exists) => *exists,
+                    None => {
+                        let exists = does_branch_exist(&artifact.repo_owner, &artifact.branch)
+                            .await
+                            .unwrap_or_else(|err| {
+                                debug!("Could not check {}/{}: {err}", branch_key.0, branch_key.1);
+                                true
+                            });
+                        branch_exists.insert(branch_key, exists);
+                        exists
+                    }
+                };
+                (!exists).then_some(GcReason::BranchDeleted)
+            };
+            if let Some(reason) = reason {
+                candidates.push((artifact, reason));
+            }
+        }
+
+        if !options.dry_run {
+            for (artifact, _) in candidates.iter() {
+                self.s3_repository
+                    .delete_object(BUILD_BUCKET_NAME, &artifact.object.key)
+                    .await?;
+            }
+        }
+
+        Ok(ArtifactGcReport {
+            candidates,
+            dry_run: options.dry_run,
+            in_use_count,
+            protected_count,
+        })
+    }
+}
+
+/// Check whether a branch still exists in the `autonomi` repository.
+async fn does_branch_exist(repo_owner: &str, branch: &str) -> Result<bool> {
+    let url = format!("https://api.github.com/repos/{repo_owner}/autonomi/branches/{branch}");
+    let response = reqwest::Client::new()
+        .get(&url)
+        .header("User-Agent", "testnet-deploy")
+        .send()
+        .await?;
+    match response.status() {
+        status if status.is_success() => Ok(true),
+        StatusCode::NOT_FOUND => Ok(false),
+        status => Err(Error::GitHubCommitLookupFailed {
+            branch: branch.to_string(),
+            error: format!("{status}: {}", response.text().await?),
+        }),
+    }
+}
+
+fn is_hex(value: &str, len: usize) -> bool {
+    value.len() == len && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn format_size(bytes: i64) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color_eyre::{eyre::eyre, Result};
+
+    fn get_object(key: &str) -> S3Object {
+        S3Object {
+            key: key.to_string(),
+            last_modified: Some(1700000000),
+            size: 1024,
+        }
+    }
+
+    #[test]
+    fn test_parse_artifact_with_target_triple() -> Result<()> {
+        let artifact = BuildArtifact::parse(get_object(
+            "maidsafe/feat/xyz/antnode-alpha-x86_64-unknown-linux-musl.tar.gz",
+        ))
+        .ok_or_else(|| eyre!("the artifact should have been parsed"))?;
+        assert_eq!("maidsafe", artifact.repo_owner);
+        assert_eq!("feat/xyz", artifact.branch);
+        assert_eq!("alpha", artifact.name);
+        assert!(!artifact.is_release());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_artifact_with_hyphenated_name() -> Result<()> {
+        let artifact = BuildArtifact::parse(get_object(
+            "jacderida/main/ant_node_manager-feat-xyz-0412a-aarch64-unknown-linux-musl.tar.gz",
+        ))
+        .ok_or_else(|| eyre!("the artifact should have been parsed"))?;
+        assert_eq!("main", artifact.branch);
+        assert_eq!("feat-xyz-0412a", artifact.name);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_artifact_with_invalid_keys() -> Result<()> {
+        for key in [
+            "antnode-alpha.tar.gz",
+            "maidsafe/antnode-alpha.tar.gz",
+            "maidsafe/main/antnode-alpha.zip",
+            "maidsafe/main/antnode.tar.gz",
+        ] {
+            assert!(
+                BuildArtifact::parse(get_object(key)).is_none(),
+                "expected '{key}' not to be parsed"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_release() -> Result<()> {
+        for (name, expected) in [
+            ("latest", true),
+            ("0.3.1", true),
+            ("v0.3.1", true),
+            ("0.3.1-rc.1", true),
+            ("alpha", false),
+            ("0.3", false),
+        ] {
+            let artifact = BuildArtifact::parse(get_object(&format!(
+                "maidsafe/main/antnode-{name}-x86_64-unknown-linux-musl.tar.gz"
+            )))
+            .ok_or_else(|| eyre!("the artifact should have been parsed"))?;
+            assert_eq!(expected, artifact.is_release(), "for the name '{name}'");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,482 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    ansible::{inventory::AnsibleInventoryType, AnsiblePlaybook},
+    artifact_repository::ArtifactRepositoryConfig,
+    deploy::DeployOptions,
+    error::Error,
+    inventory::{DeploymentInventory, DeploymentInventoryService},
+    smoke_test::{run_smoke_test, SmokeTestOptions},
+    validate_environment_name, Architecture, BinaryOption, CleanOptions, CloudProvider,
+    EnvironmentType, NatType, TestnetDeployBuilder, TestnetDeployer,
+};
+use color_eyre::{
+    eyre::{bail, eyre},
+    Result,
+};
+use serde::Deserialize;
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+use tracing::debug;
+
+/// The GitHub compare API returns at most this many commits.
+const MAX_COMPARE_COMMITS: usize = 250;
+/// As with `git bisect run`, a health script exits with this code if the commit can't be tested.
+const SKIP_EXIT_CODE: i32 = 125;
+
+pub struct BisectOptions {
+    /// A commit where the regression is present.
+    pub bad: String,
+    /// A commit where the regression is not present. It must be an ancestor of the bad commit.
+    pub good: String,
+    /// Run this script to decide whether each commit is good, rather than the smoke test.
+    ///
+    /// It exits with 0 if the commit is good, 125 if it can't be tested, or any other code if it
+    /// is bad.
+    pub health_script: Option<PathBuf>,
+    /// Each environment is named `<prefix>-<short sha>`, or just `<prefix>` if the infrastructure
+    /// is reused.
+    pub name_prefix: String,
+    /// The number of nodes on each node VM.
+    pub node_count: u16,
+    pub node_vm_count: u16,
+    pub provider: CloudProvider,
+    pub repo_owner: String,
+    /// Deploy every commit to the same environment, removing the binaries of the previous commit
+    /// before the next is deployed, rather than creating an environment for each commit.
+    pub reuse_infra: bool,
+    pub rewards_address: String,
+    pub smoke_test_file_size_kb: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CommitOutcome {
+    Good,
+    Bad,
+    Skip,
+}
+
+#[derive(Clone, Debug)]
+pub struct BisectStep {
+    pub commit: String,
+    pub outcome: CommitOutcome,
+    /// Why the commit was marked bad or skipped. It is `None` for good commits.
+    pub reason: Option<String>,
+}
+
+pub struct BisectReport {
+    pub commit_count: usize,
+    pub first_bad_commit: String,
+    pub repo_owner: String,
+    /// Skipped commits between the last good commit and the first bad commit, any of which could
+    /// be the first bad commit instead.
+    pub skipped_candidates: Vec<String>,
+    pub steps: Vec<BisectStep>,
+}
+
+impl BisectReport {
+    pub fn print(&self) {
+        println!("=============");
+        println!("Bisect Report");
+        println!("=============");
+        println!(
+            "Tested {} of {} commits",
+            self.steps.len(),
+            self.commit_count
+        );
+        for step in self.steps.iter() {
+            match &step.reason {
+                Some(reason) => println!("{} {:?}: {reason}", step.commit, step.outcome),
+                None => println!("{} {:?}", step.commit, step.outcome),
+            }
+        }
+        println!("The first bad commit is {}", self.first_bad_commit);
+        println!(
+            "https://github.com/{}/autonomi/commit/{}",
+            self.repo_owner, self.first_bad_commit
+        );
+        if !self.skipped_candidates.is_empty() {
+            println!("These skipped commits could also be the first bad commit:");
+            for commit in self.skipped_candidates.iter() {
+                println!("{commit}");
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CompareResponse {
+    commits: Vec<CompareCommit>,
+    total_commits: usize,
+}
+
+#[derive(Deserialize)]
+struct CompareCommit {
+    sha: String,
+}
+
+/// Get the commits after `good`, up to and including `bad`, oldest first.
+pub async fn get_commits_between(repo_owner: &str, good: &str, bad: &str) -> Result<Vec<String>> {
+    let url = format!("https://api.github.com/repos/{repo_owner}/autonomi/compare/{good}...{bad}");
+    let response = reqwest::Client::new()
+        .get(&url)
+        .query(&[("per_page", MAX_COMPARE_COMMITS)])
+        .header("Accept", "application/vnd.github+json")
+        .header("User-Agent", "testnet-deploy")
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        bail!(
+            "Failed to compare {good} with {bad}: {}",
+            response.text().await?
+        );
+    }
+    let comparison: CompareResponse = response.json().await?;
+    if comparison.total_commits > comparison.commits.len() {
+        bail!(
+            "There are {} commits between {good} and {bad}, but only {MAX_COMPARE_COMMITS} can be \
+            bisected",
+            comparison.total_commits
+        );
+    }
+    Ok(comparison.commits.into_iter().map(|c| c.sha).collect())
+}
+
+/// Find the first bad commit between a good and a bad commit of the `autonomi` repository.
+///
+/// Each commit that is tested is built, then deployed to a small throwaway environment, where
+/// the smoke test or the health script is run. Unless the infrastructure is reused, the
+/// environment is cleaned up before the next commit is tested. A commit is bad if the deployment
+/// or the check fails. As with `git bisect`, the good and bad commits themselves are not tested.
+///
+/// A commit the health script skips is removed from the search, and the next test uses one of the
+/// remaining commits.
+pub async fn bisect(options: &BisectOptions) -> Result<BisectReport> {
+    let all_commits = get_commits_between(&options.repo_owner, &options.good, &options.bad).await?;
+    if all_commits.is_empty() {
+        bail!(
+            "There are no commits after {} up to {}",
+            options.good,
+            options.bad
+        );
+    }
+
+    let shared_deployer = if options.reuse_infra {
+        validate_environment_name(&options.name_prefix)?;
+        Some(
+            TestnetDeployBuilder::default()
+                .environment_name(&options.name_prefix)
+                .provider(options.provider)
+                .build()?,
+        )
+    } else {
+        None
+    };
+
+    // The commits that can still be tested, with their position in the full range. Every commit
+    // before `low` is good, and the commit at `high` is bad.
+    let mut commits = all_commits.iter().enumerate().collect::<Vec<_>>();
+    let mut low = 0;
+    let mut high = commits.len() - 1;
+    let mut steps = Vec::new();
+    let mut result = Ok(());
+    while low < high {
+        let mid = (low + high) / 2;
+        let remaining = high - low;
+        let commit = commits[mid].1;
+        println!(
+            "Testing {commit} ({remaining} commits left to test, about {} steps)",
+            (remaining as f64).log2().ceil()
+        );
+        let step = match &shared_deployer {
+            Some(testnet_deployer) => {
+                // Only the first commit is deployed to a new environment.
+                match test_commit_on(testnet_deployer, options, commit, !steps.is_empty()).await {
+                    Ok(step) => step,
+                    Err(err) => {
+                        result = Err(err);
+                        break;
+                    }
+                }
+            }
+            None => test_commit(options, commit).await?,
+        };
+        println!("{} is {:?}", step.commit, step.outcome);
+        match step.outcome {
+            CommitOutcome::Good => low = mid + 1,
+            CommitOutcome::Bad => high = mid,
+            CommitOutcome::Skip => {
+                commits.remove(mid);
+                high -= 1;
+            }
+        }
+        steps.push(step);
+    }
+
+    if let Some(testnet_deployer) = &shared_deployer {
+        clean_environment(testnet_deployer).await?;
+    }
+    result?;
+
+    // Any skipped commit after the last good commit, and before the first bad commit, could be
+    // the one that introduced the regression.
+    let (first_bad_index, first_bad_commit) = commits[high];
+    let last_good_index = high.checked_sub(1).map(|i| commits[i].0);
+    let skipped_candidates = steps
+        .iter()
+        .filter(|step| step.outcome == CommitOutcome::Skip)
+        .filter_map(|step| {
+            let index = all_commits.iter().position(|c| *c == step.commit)?;
+            let after_last_good = !matches!(last_good_index, Some(good) if index <= good);
+            (index < first_bad_index && after_last_good).then(|| step.commit.clone())
+        })
+        .collect();
+
+    Ok(BisectReport {
+        commit_count: all_commits.len(),
+        first_bad_commit: first_bad_commit.clone(),
+        repo_owner: options.repo_owner.clone(),
+        skipped_candidates,
+        steps,
+    })
+}
+
+/// Deploy the commit to its own environment and check it, then clean the environment.
+///
+/// An error is only returned if the environment could not be cleaned, since continuing would leave
+/// it running.
+async fn test_commit(options: &BisectOptions, commit: &str) -> Result<BisectStep> {
+    let name = format!("{}-{}", options.name_prefix, &commit[..7.min(commit.len())]);
+    validate_environment_name(&name)?;
+    let testnet_deployer = TestnetDeployBuilder::default()
+        .environment_name(&name)
+        .provider(options.provider)
+        .build()?;
+
+    let result = deploy_and_check(&testnet_deployer, options, commit).await;
+    clean_environment(&testnet_deployer).await?;
+    Ok(get_step(commit, result))
+}
+
+/// Deploy the commit to the shared environment and check it.
+///
+/// If a previous commit was deployed, its binaries and services are removed first. Terraform
+/// leaves the existing VMs in place, so only the build and provisioning are repeated. An error is
+/// only returned if the environment could not be reset, since later results would not be reliable.
+async fn test_commit_on(
+    testnet_deployer: &TestnetDeployer,
+    options: &BisectOptions,
+    commit: &str,
+    reset: bool,
+) -> Result<BisectStep> {
+    if reset {
+        println!("Removing the binaries of the previous commit");
+        let ansible_runner = &testnet_deployer.ansible_provisioner.ansible_runner;
+        for inventory_type in [AnsibleInventoryType::Build, AnsibleInventoryType::Uploaders]
+            .into_iter()
+            .chain(AnsibleInventoryType::iter_node_type())
+        {
+            ansible_runner
+                .run_playbook(AnsiblePlaybook::ResetBinaries, inventory_type, None)
+                .map_err(|err| {
+                    eyre!(
+                        "Failed to reset the {} environment: {err}",
+                        testnet_deployer.environment_name
+                    )
+                })?;
+        }
+    }
+    let result = deploy_and_check(testnet_deployer, options, commit).await;
+    Ok(get_step(commit, result))
+}
+
+async fn clean_environment(testnet_deployer: &TestnetDeployer) -> Result<()> {
+    let name = &testnet_deployer.environment_name;
+    println!("Cleaning the {name} environment");
+    match testnet_deployer.clean(&CleanOptions::default()).await {
+        // The deployment can fail before any infrastructure is created.
+        Ok(()) | Err(Error::EnvironmentDoesNotExist(_)) => Ok(()),
+        Err(err) => Err(eyre!("Failed to clean the {name} environment: {err}")),
+    }
+}
+
+fn get_step(commit: &str, result: Result<CommitOutcome>) -> BisectStep {
+    match result {
+        Ok(CommitOutcome::Skip) => BisectStep {
+            commit: commit.to_string(),
+            outcome: CommitOutcome::Skip,
+            reason: Some("the health script skipped it".to_string()),
+        },
+        Ok(outcome) => BisectStep {
+            commit: commit.to_string(),
+            outcome,
+            reason: None,
+        },
+        Err(err) => {
+            debug!("{commit} is bad: {err:?}");
+            BisectStep {
+                commit: commit.to_string(),
+                outcome: CommitOutcome::Bad,
+                reason: Some(err.to_string()),
+            }
+        }
+    }
+}
+
+/// Deploy the commit, then run the health script if there is one, or the smoke test otherwise.
+///
+/// An error means the commit is bad.
+async fn deploy_and_check(
+    testnet_deployer: &TestnetDeployer,
+    options: &BisectOptions,
+    commit: &str,
+) -> Result<CommitOutcome> {
+    let name = &testnet_deployer.environment_name;
+    let binary_option = BinaryOption::BuildFromSource {
+        antnode_features: None,
+        // The commit is used in place of a branch. The build VM checks out the commit, and the
+        // archives are uploaded under a folder for the commit.
+        branch: commit.to_string(),
+        network_keys: None,
+        repo_owner: options.repo_owner.clone(),
+    };
+
+    let inventory_service = DeploymentInventoryService::from(testnet_deployer);
+    let inventory = inventory_service
+        .generate_or_retrieve_inventory(name, true, Some(binary_option.clone()))
+        .await?;
+    testnet_deployer.init().await?;
+
+    let environment_type = EnvironmentType::Development;
+    testnet_deployer
+        .deploy(&DeployOptions {
+            architecture: Architecture::default(),
+            artifact_repository: ArtifactRepositoryConfig::default(),
+            auditor_vm_count: None,
+            binary_option: binary_option.clone(),
+            chunk_size: None,
+            current_inventory: inventory,
+            disable_build_cache: false,
+            dns_domain: None,
+            dns_resolvers: Vec::new(),
+            door_node_count: 0,
+            door_node_dns_domain: None,
+            downloaders_count: 0,
+            environment_type: environment_type.clone(),
+            env_variables: None,
+            evm_data_payments_address: None,
+            evm_network: Default::default(),
+            evm_node_vm_size: None,
+            evm_payment_token_address: None,
+            evm_rpc_url: None,
+            firewall: None,
+            funding_wallet_secret_key: None,
+            genesis_node_volume_size: None,
+            harden: false,
+            host_entries: Vec::new(),
+            interval: Duration::from_millis(2000),
+            log_format: None,
+            max_archived_log_files: 5,
+            max_log_files: 10,
+            name: name.clone(),
+            nat_gateway_count: None,
+            nat_type: NatType::default(),
+            network_id: None,
+            node_count: options.node_count,
+            node_reachability: Default::default(),
+            node_restart_policy: Default::default(),
+            node_vm_count: Some(options.node_vm_count),
+            node_vm_size: None,
+            node_volume_size: None,
+            output_inventory_dir_path: inventory_service
+                .working_directory_path
+                .join("ansible")
+                .join("inventory"),
+            peer_cache_node_count: environment_type.get_default_peer_cache_node_count(),
+            peer_cache_node_reachability: Default::default(),
+            peer_cache_node_vm_count: Some(1),
+            peer_cache_node_vm_size: None,
+            peer_cache_node_volume_size: None,
+            pinned_commit_sha: None,
+            private_node_count: 0,
+            private_node_upnp: false,
+            private_node_vm_count: Some(0),
+            private_node_volume_size: None,
+            notification: None,
+            provision_parallelism: None,
+            provision_retries: 0,
+            public_rpc: false,
+            release: None,
+            resume: false,
+            rewards_address: options.rewards_address.clone(),
+            rpc_proxy_acme_email: None,
+            rpc_proxy_auth_token: None,
+            setup_artifact_proxy: false,
+            setup_infra_services: false,
+            setup_monitoring: false,
+            sysstat_duration: None,
+            telemetry: None,
+            ttl: None,
+            uploader_bandwidth_class: Default::default(),
+            uploader_regions: Vec::new(),
+            uploader_vm_count: Some(1),
+            uploader_vm_size: None,
+            uploaders_count: 1,
+            wireguard: false,
+        })
+        .await?;
+
+    let inventory = inventory_service
+        .generate_or_retrieve_inventory(name, true, Some(binary_option))
+        .await?;
+    if let Some(health_script) = &options.health_script {
+        return run_health_script(health_script, &inventory, &options.repo_owner, commit);
+    }
+    let report = run_smoke_test(
+        &inventory,
+        &testnet_deployer.ssh_client,
+        &SmokeTestOptions {
+            file_size_kb: options.smoke_test_file_size_kb,
+            local_client_path: None,
+        },
+    )
+    .await?;
+    report.print();
+    if !report.hash_matched {
+        bail!("the downloaded file did not match the uploaded file");
+    }
+    Ok(CommitOutcome::Good)
+}
+
+/// Run the health script, with the details of the deployment in the environment.
+///
+/// Its output is not captured, so it appears in the output of the bisect.
+fn run_health_script(
+    health_script: &Path,
+    inventory: &DeploymentInventory,
+    repo_owner: &str,
+    commit: &str,
+) -> Result<CommitOutcome> {
+    println!("Running {}", health_script.display());
+    let status = Command::new(health_script)
+        .env("BISECT_COMMIT", commit)
+        .env("BISECT_ENVIRONMENT_NAME", &inventory.name)
+        .env(
+            "BISECT_GENESIS_MULTIADDR",
+            inventory.genesis_multiaddr.clone().unwrap_or_default(),
+        )
+        .env("BISECT_REPO_OWNER", repo_owner)
+        .status()?;
+    match status.code() {
+        Some(0) => Ok(CommitOutcome::Good),
+        Some(SKIP_EXIT_CODE) => Ok(CommitOutcome::Skip),
+        _ => bail!("the health script exited with {status}"),
+    }
+}
@@ -8,9 +8,9 @@ use std::{path::PathBuf, time::Duration};
 
 use crate::{
     ansible::{inventory::AnsibleInventoryType, provisioning::ProvisionOptions},
-    error::Result,
+    error::{Error, Result},
     write_environment_details, BinaryOption, DeploymentType, EnvironmentDetails, EnvironmentType,
-    EvmNetwork, InfraRunOptions, LogFormat, NodeType, TestnetDeployer,
+    EvmNetwork, InfraRunOptions, LogFormat, NatType, NodeType, TestnetDeployer,
 };
 use colored::Colorize;
 
@@ -27,16 +27,24 @@ pub struct BootstrapOptions {
     pub evm_payment_token_address: Option<String>,
     pub evm_rpc_url: Option<String>,
     pub interval: Duration,
+    /// Limit the rate at which the nodes join the network, to this many per minute across all the
+    /// VMs, so the bootstrap peers are not overwhelmed.
+    pub join_rate: Option<u16>,
     pub log_format: Option<LogFormat>,
     pub max_archived_log_files: u16,
     pub max_log_files: u16,
     pub name: String,
+    /// The number of NAT gateways the private node VMs are spread across.
+    pub nat_gateway_count: Option<u16>,
+    pub nat_type: NatType,
     pub network_id: Option<u8>,
     pub node_count: u16,
     pub node_vm_count: Option<u16>,
     pub node_volume_size: Option<u16>,
     pub output_inventory_dir_path: PathBuf,
     pub private_node_count: u16,
+    /// Run a UPnP daemon on the NAT gateways and have the private nodes use it to open their ports.
+    pub private_node_upnp: bool,
     pub private_node_vm_count: Option<u16>,
     pub private_node_volume_size: Option<u16>,
     pub rewards_address: String,
@@ -48,7 +56,7 @@ impl TestnetDeployer {
         let build_custom_binaries = {
             match &options.binary_option {
                 BinaryOption::BuildFromSource { .. } => true,
-                BinaryOption::Versioned { .. } => false,
+                BinaryOption::Local { .. } | BinaryOption::Versioned { .. } => false,
             }
         };
 
@@ -62,26 +70,52 @@ impl TestnetDeployer {
             &self.s3_repository,
             &options.name,
             &EnvironmentDetails {
+                architecture: None,
+                artifact_repository: None,
                 deployment_type: DeploymentType::Bootstrap,
+                dns_domain: None,
+                door_node_count: None,
+                door_node_dns_domain: None,
                 environment_type: options.environment_type.clone(),
                 evm_network: options.evm_network.clone(),
                 evm_data_payments_address: options.evm_data_payments_address.clone(),
                 evm_payment_token_address: options.evm_payment_token_address.clone(),
                 evm_rpc_url: options.evm_rpc_url.clone(),
+                expires_at: None,
+                firewall: None,
                 funding_wallet_address: None,
+                nat_gateway_shards: None,
+                nat_type: Some(options.nat_type),
+                network_contacts_url: None,
                 network_id: options.network_id,
+                node_count: Some(options.node_count),
+                node_reachability: None,
+                peer_cache_node_count: None,
+                peer_cache_node_reachability: None,
+                private_node_count: Some(options.private_node_count),
+                private_node_upnp: Some(options.private_node_upnp),
+                release: None,
                 rewards_address: options.rewards_address.clone(),
+                rpc_proxy: None,
+                uploader_bandwidth_class: None,
+                uploader_regions: None,
             },
         )
         .await?;
 
         self.create_or_update_infra(&InfraRunOptions {
+            auditor_vm_count: Some(0),
+            dns_domain: None,
+            door_node_count: Some(0),
+            door_node_dns_domain: None,
+            downloader_vm_count: Some(0),
             enable_build_vm: build_custom_binaries,
             evm_node_count: Some(0),
             evm_node_vm_size: None,
             genesis_vm_count: Some(0),
             genesis_node_volume_size: None,
             name: options.name.clone(),
+            nat_gateway_count: options.nat_gateway_count,
             node_vm_count: options.node_vm_count,
             node_vm_size: options.node_vm_size.clone(),
             node_volume_size: options.node_volume_size,
@@ -90,11 +124,15 @@ impl TestnetDeployer {
             peer_cache_node_volume_size: None,
             private_node_vm_count: options.private_node_vm_count,
             private_node_volume_size: options.private_node_volume_size,
+            setup_artifact_proxy: Some(false),
+            setup_monitoring: Some(false),
             tfvars_filename: options
                 .environment_type
                 .get_tfvars_filename(&options.name)
                 .to_string(),
             uploader_vm_count: Some(0),
+            uploader_bandwidth_class: None,
+            uploader_regions: None,
             uploader_vm_size: None,
         })
         .map_err(|err| {
@@ -115,6 +153,7 @@ impl TestnetDeployer {
         }
 
         let mut failed_to_provision = false;
+        let mut failed_hosts = Vec::new();
 
         self.ansible_provisioner
             .print_ansible_run_banner("Provision Normal Nodes");
@@ -129,6 +168,9 @@ impl TestnetDeployer {
             }
             Err(e) => {
                 println!("Failed to provision normal nodes: {e:?}");
+                if let Error::AnsibleHostsFailed { hosts, .. } = e {
+                    failed_hosts.extend(hosts);
+                }
                 failed_to_provision = true;
             }
         }
@@ -144,10 +186,12 @@ impl TestnetDeployer {
                 })?;
 
             provision_options.private_node_vms = private_nodes;
+            let shards = self.get_nat_gateway_shards().await?;
+            self.record_nat_gateway_shards(&shards).await?;
             self.ansible_provisioner
                 .print_ansible_run_banner("Provision NAT Gateway");
             self.ansible_provisioner
-                .provision_nat_gateway(&provision_options)
+                .provision_nat_gateway(&provision_options, &shards)
                 .map_err(|err| {
                     println!("Failed to provision NAT gateway {err:?}");
                     err
@@ -157,6 +201,7 @@ impl TestnetDeployer {
                 .print_ansible_run_banner("Provision Private Nodes");
             match self.ansible_provisioner.provision_private_nodes(
                 &mut provision_options,
+                &shards,
                 options.bootstrap_peer.clone(),
                 options.bootstrap_network_contacts_url.clone(),
             ) {
@@ -164,7 +209,10 @@ impl TestnetDeployer {
                     println!("Provisioned private nodes");
                 }
                 Err(err) => {
-                    log::error!("Failed to provision private nodes: {err}");
+                    tracing::error!("Failed to provision private nodes: {err}");
+                    if let Error::AnsibleHostsFailed { hosts, .. } = err {
+                        failed_hosts.extend(hosts);
+                    }
                     failed_to_provision = true;
                 }
             }
@@ -175,7 +223,11 @@ impl TestnetDeployer {
             println!("Some nodes failed to provision without error.");
             println!("This usually means a small number of nodes failed to start on a few VMs.");
             println!("However, most of the time the deployment will still be usable.");
-            println!("See the output from Ansible to determine which VMs had failures.");
+            if failed_hosts.is_empty() {
+                println!("See the output from Ansible to determine which VMs had failures.");
+            } else {
+                self.print_failed_hosts(&failed_hosts).await;
+            }
         }
 
         Ok(())
@@ -0,0 +1,194 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    error::{Error, Result},
+    Architecture, BinaryOption, TestnetDeployer,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+/// The bucket the build VM uploads the binary archives to.
+pub(crate) const BUILD_BUCKET_NAME: &str = "sn-node";
+
+/// The binaries produced by the build playbook.
+const BUILT_BINARIES: [&str; 4] = ["ant", "antctl", "antctld", "antnode"];
+
+/// Identifies the binaries produced by a build, so they can be reused by a later deployment of
+/// the same commit.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BuildCacheKey {
+    /// The build VM checks out this commit rather than the head of the branch, which may have
+    /// moved on since the key was computed.
+    pub commit_sha: String,
+    /// The commit SHA, followed by a hash of the other inputs to the build, such as the features
+    /// and network keys.
+    pub key: String,
+}
+
+impl BuildCacheKey {
+    pub fn new(
+        commit_sha: &str,
+        binary_option: &BinaryOption,
+        chunk_size: Option<u64>,
+        architecture: Architecture,
+    ) -> Self {
+        let mut hasher = Sha256::new();
+        if let BinaryOption::BuildFromSource {
+            antnode_features,
+            network_keys,
+            ..
+        } = binary_option
+        {
+            hasher.update(antnode_features.as_deref().unwrap_or_default());
+            hasher.update(b"|");
+            if let Some((foundation_pk, genesis_pk, network_royalties_pk, payment_forward_pk)) =
+                network_keys
+            {
+                hasher.update(
+                    format!(
+                        "{foundation_pk},{genesis_pk},{network_royalties_pk},{payment_forward_pk}"
+                    )
+                    .as_bytes(),
+                );
+            }
+        }
+        hasher.update(b"|");
+        hasher.update(chunk_size.map(|c| c.to_string()).unwrap_or_default());
+        hasher.update(b"|");
+        hasher.update(architecture.target_triple());
+        let inputs_hash: String = hasher
+            .finalize()
+            .iter()
+            .take(4)
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        Self {
+            commit_sha: commit_sha.to_string(),
+            key: format!("{commit_sha}-{inputs_hash}"),
+        }
+    }
+}
+
+/// Get the SHA of the commit at the head of a branch in the `autonomi` repository.
+pub async fn get_branch_commit_sha(repo_owner: &str, branch: &str) -> Result<String> {
+    let url = format!("https://api.github.com/repos/{repo_owner}/autonomi/commits/{branch}");
+    let response = reqwest::Client::new()
+        .get(&url)
+        .header("Accept", "application/vnd.github.sha")
+        .header("User-Agent", "testnet-deploy")
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(Error::GitHubCommitLookupFailed {
+            branch: branch.to_string(),
+            error: format!("{status}: {}", response.text().await?),
+        });
+    }
+    Ok(response.text().await?.trim().to_string())
+}
+
+fn get_archive_key(
+    repo_owner: &str,
+    branch: &str,
+    bin_name: &str,
+    name: &str,
+    architecture: Architecture,
+) -> String {
+    format!(
+        "{repo_owner}/{branch}/{bin_name}-{name}-{}.tar.gz",
+        architecture.target_triple()
+    )
+}
+
+impl TestnetDeployer {
+    /// Compute the key the binaries for a build from source would be cached under.
+    ///
+    /// The head of the branch is used, unless a commit is pinned.
+    ///
+    /// Returns `None` if the binaries are not being built from source.
+    pub async fn get_build_cache_key(
+        &self,
+        binary_option: &BinaryOption,
+        pinned_commit_sha: Option<&str>,
+        chunk_size: Option<u64>,
+        architecture: Architecture,
+    ) -> Result<Option<BuildCacheKey>> {
+        let BinaryOption::BuildFromSource {
+            repo_owner, branch, ..
+        } = binary_option
+        else {
+            return Ok(None);
+        };
+        let commit_sha = match pinned_commit_sha {
+            Some(commit_sha) => commit_sha.to_string(),
+            None => {
+                let commit_sha = get_branch_commit_sha(repo_owner, branch).await?;
+                debug!("The head of {repo_owner}/{branch} is {commit_sha}");
+                commit_sha
+            }
+        };
+        Ok(Some(BuildCacheKey::new(
+            &commit_sha,
+            binary_option,
+            chunk_size,
+            architecture,
+        )))
+    }
+
+    /// If the archives for every binary are cached under the key, copy them to the names the
+    /// deployment downloads them from, so the build can be skipped.
+    ///
+    /// Returns `false` if any of the archives are missing, in which case nothing is copied.
+    pub async fn restore_cached_build(
+        &self,
+        binary_option: &BinaryOption,
+        cache_key: &BuildCacheKey,
+        architecture: Architecture,
+    ) -> Result<bool> {
+        let BinaryOption::BuildFromSource {
+            repo_owner, branch, ..
+        } = binary_option
+        else {
+            return Ok(false);
+        };
+
+        for bin_name in BUILT_BINARIES {
+            let cached_key =
+                get_archive_key(repo_owner, branch, bin_name, &cache_key.key, architecture);
+            if !self
+                .s3_repository
+                .object_exists(BUILD_BUCKET_NAME, &cached_key)
+                .await?
+            {
+                debug!("{cached_key} is not in the build cache");
+                return Ok(false);
+            }
+        }
+
+        for bin_name in BUILT_BINARIES {
+            let cached_key =
+                get_archive_key(repo_owner, branch, bin_name, &cache_key.key, architecture);
+            let dest_key = get_archive_key(
+                repo_owner,
+                branch,
+                bin_name,
+                &self.environment_name,
+                architecture,
+            );
+            println!(
+                "Using the cached {bin_name} archive for {}",
+                cache_key.commit_sha
+            );
+            self.s3_repository
+                .copy_object(BUILD_BUCKET_NAME, &cached_key, &dest_key)
+                .await?;
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,639 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    error::{Error, Result},
+    inventory::{DeploymentInventory, NodeVirtualMachine, VirtualMachine},
+    throttle::expand_vm_selector,
+    NodeType, TestnetDeployer,
+};
+use ant_service_management::ServiceStatus;
+use libp2p::PeerId;
+use rand::{seq::SliceRandom, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{net::IpAddr, path::PathBuf, time::Duration};
+use tracing::debug;
+
+/// A fault that can be applied to a node service.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum Fault {
+    /// Kill the node process with SIGKILL. The service will be restarted by systemd.
+    Kill,
+    /// Temporarily constrain the memory of the node service so that it is killed by the OOM killer.
+    Oom,
+    /// Freeze the node process with SIGSTOP. It remains frozen until it receives SIGCONT.
+    Sigstop,
+}
+
+impl Fault {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Fault::Kill => "kill",
+            Fault::Oom => "oom",
+            Fault::Sigstop => "sigstop",
+        }
+    }
+
+    fn get_command(&self, service_name: &str) -> String {
+        match self {
+            Fault::Kill => format!("sudo systemctl kill --signal=SIGKILL {service_name}"),
+            // The memory limit is removed again once the OOM killer has had time to act, otherwise
+            // the service would be killed again each time it was restarted.
+            Fault::Oom => format!(
+                "sudo systemctl set-property --runtime {service_name} MemoryMax=1M && sleep 5 && \
+                sudo systemctl set-property --runtime {service_name} MemoryMax=infinity"
+            ),
+            Fault::Sigstop => format!("sudo systemctl kill --signal=SIGSTOP {service_name}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FaultRecord {
+    pub applied_at: String,
+    pub error: Option<String>,
+    pub peer_id: Option<String>,
+    pub public_ip_addr: IpAddr,
+    pub service_name: String,
+    pub vm_name: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FaultReport {
+    pub environment_name: String,
+    pub fault: Fault,
+    pub records: Vec<FaultRecord>,
+}
+
+impl FaultReport {
+    /// Write the report to `logs/<name>/chaos-<fault>-<timestamp>.json`, for correlation with the
+    /// network metrics.
+    pub fn save(&self) -> Result<PathBuf> {
+        let dir = std::env::current_dir()?
+            .join("logs")
+            .join(&self.environment_name);
+        std::fs::create_dir_all(&dir)?;
+        let timestamp = chrono::Utc::now().format("%Y%m%dT%H%M%S");
+        let path = dir.join(format!("chaos-{}-{timestamp}.json", self.fault.as_str()));
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+
+    pub fn print(&self) {
+        for record in self.records.iter() {
+            match &record.error {
+                Some(err) => println!(
+                    "Failed to apply {} to {} on {}: {err}",
+                    self.fault.as_str(),
+                    record.service_name,
+                    record.vm_name
+                ),
+                None => println!(
+                    "Applied {} to {} on {} at {} (PeerId: {})",
+                    self.fault.as_str(),
+                    record.service_name,
+                    record.vm_name,
+                    record.applied_at,
+                    record.peer_id.as_deref().unwrap_or("-")
+                ),
+            }
+        }
+    }
+}
+
+/// Apply a fault to `count` running nodes, selected at random from VMs of the given node type.
+///
+/// A failure to apply the fault to one node is recorded in the report rather than aborting, so
+/// the report always accounts for every node that was selected.
+pub async fn inject_fault(
+    testnet_deployer: &TestnetDeployer,
+    inventory: &DeploymentInventory,
+    fault: Fault,
+    count: usize,
+    node_type: NodeType,
+) -> Result<FaultReport> {
+    let mut running_nodes = get_running_nodes(testnet_deployer, inventory, &[node_type])?;
+    if running_nodes.is_empty() {
+        return Err(Error::ChaosTargetsUnavailable);
+    }
+
+    running_nodes.shuffle(&mut rand::thread_rng());
+    running_nodes.truncate(count);
+    Ok(FaultReport {
+        environment_name: inventory.name.clone(),
+        fault,
+        records: apply_fault(testnet_deployer, fault, running_nodes).await,
+    })
+}
+
+/// Get the running nodes on the VMs of the given node types, along with the VM they are on.
+fn get_running_nodes(
+    testnet_deployer: &TestnetDeployer,
+    inventory: &DeploymentInventory,
+    node_types: &[NodeType],
+) -> Result<Vec<(VirtualMachine, String, Option<PeerId>)>> {
+    let vm_list = inventory.vm_list();
+    let mut running_nodes = Vec::new();
+    for node_type in node_types {
+        let registries = testnet_deployer
+            .ansible_provisioner
+            .get_node_registries(&node_type.to_ansible_inventory_type())?;
+        for (name, registry) in registries.retrieved_registries.iter() {
+            // For private nodes, the registry is keyed by the private IP address of the VM.
+            let Some(vm) = vm_list
+                .iter()
+                .find(|vm| &vm.name == name || &vm.private_ip_addr.to_string() == name)
+            else {
+                continue;
+            };
+            for node in registry.nodes.iter() {
+                if matches!(node.status, ServiceStatus::Running) {
+                    running_nodes.push((vm.clone(), node.service_name.clone(), node.peer_id));
+                }
+            }
+        }
+    }
+    Ok(running_nodes)
+}
+
+async fn apply_fault(
+    testnet_deployer: &TestnetDeployer,
+    fault: Fault,
+    nodes: Vec<(VirtualMachine, String, Option<PeerId>)>,
+) -> Vec<FaultRecord> {
+    let ssh_user = testnet_deployer.cloud_provider.get_ssh_user();
+    let mut records = Vec::new();
+    for (vm, service_name, peer_id) in nodes {
+        debug!(
+            "Applying {} to {service_name} on {}",
+            fault.as_str(),
+            vm.name
+        );
+        let applied_at = chrono::Utc::now().to_rfc3339();
+        let result = testnet_deployer
+            .ssh_client
+            .execute(
+                &vm.public_ip_addr,
+                &ssh_user,
+                &fault.get_command(&service_name),
+            )
+            .await
+            .and_then(|output| output.into_stdout_lines());
+        records.push(FaultRecord {
+            applied_at,
+            error: result.err().map(|err| err.to_string()),
+            peer_id: peer_id.map(|peer_id| peer_id.to_string()),
+            public_ip_addr: vm.public_ip_addr,
+            service_name,
+            vm_name: vm.name,
+        });
+    }
+    records
+}
+
+/// The number of nodes responsible for a record, which are the nodes closest to its address.
+const CLOSE_GROUP_SIZE: usize = 5;
+
+/// The systemd unit on each VM that reverts a template once its duration has elapsed.
+const REVERT_UNIT_NAME: &str = "ant-chaos-revert";
+
+/// The proportion of the node VMs affected by the VM-level templates.
+const VM_TEMPLATE_PCT: usize = 10;
+
+/// The bandwidth the disk of each node service is limited to by the `slow-disk-10pct` template.
+const SLOW_DISK_BANDWIDTH: &str = "1M";
+
+/// The file used to fill the data partition of a node VM.
+const DISK_FILL_PATH: &str = "/mnt/antnode-storage/ant-chaos-disk-fill";
+/// The mount point of the volumes the node data is stored on.
+const NODE_STORAGE_PATH: &str = "/mnt/antnode-storage";
+
+/// A named chaos experiment, which affects a fixed part of the network and is reverted
+/// automatically.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum ChaosTemplate {
+    /// Kill the nodes closest to a random address, so the records at that address lose their
+    /// whole close group at once. The services are restarted by systemd.
+    KillCloseGroup,
+    /// Cut a group of node VMs off from every other machine, as if a region had dropped off the
+    /// network.
+    PartitionRegion,
+    /// Limit the disk bandwidth of every node on a tenth of the node VMs.
+    SlowDisk10Pct,
+}
+
+impl std::str::FromStr for ChaosTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "kill-close-group" => Ok(ChaosTemplate::KillCloseGroup),
+            "partition-region" => Ok(ChaosTemplate::PartitionRegion),
+            "slow-disk-10pct" => Ok(ChaosTemplate::SlowDisk10Pct),
+            _ => Err(format!("Invalid chaos template: {}", s)),
+        }
+    }
+}
+
+impl ChaosTemplate {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChaosTemplate::KillCloseGroup => "kill-close-group",
+            ChaosTemplate::PartitionRegion => "partition-region",
+            ChaosTemplate::SlowDisk10Pct => "slow-disk-10pct",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TemplateReport {
+    pub environment_name: String,
+    /// The proportion of the nodes affected, excluding the genesis node.
+    pub affected_pct: f64,
+    pub records: Vec<FaultRecord>,
+    /// When the template will be reverted. Not used for templates that recover by themselves.
+    pub revert_at: Option<String>,
+    pub template: ChaosTemplate,
+}
+
+impl TemplateReport {
+    /// Write the report to `logs/<name>/chaos-<template>-<timestamp>.json`, for correlation with
+    /// the network metrics.
+    pub fn save(&self) -> Result<PathBuf> {
+        let dir = std::env::current_dir()?
+            .join("logs")
+            .join(&self.environment_name);
+        std::fs::create_dir_all(&dir)?;
+        let timestamp = chrono::Utc::now().format("%Y%m%dT%H%M%S");
+        let path = dir.join(format!("chaos-{}-{timestamp}.json", self.template.as_str()));
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+
+    pub fn print(&self) {
+        println!(
+            "Applied the {} template to {:.1}% of the nodes",
+            self.template.as_str(),
+            self.affected_pct
+        );
+        for record in self.records.iter() {
+            match &record.error {
+                Some(err) => println!(
+                    "Failed to apply to {} on {}: {err}",
+                    record.service_name, record.vm_name
+                ),
+                None => println!(
+                    "Applied to {} on {} at {} (PeerId: {})",
+                    record.service_name,
+                    record.vm_name,
+                    record.applied_at,
+                    record.peer_id.as_deref().unwrap_or("-")
+                ),
+            }
+        }
+        if let Some(revert_at) = &self.revert_at {
+            println!("The template will be reverted at {revert_at}");
+        }
+    }
+}
+
+/// Apply a chaos template to the network.
+///
+/// The genesis node is never affected. If the template would affect more than `max_fleet_pct` of
+/// the other nodes, nothing is applied. Templates that do not recover by themselves are reverted
+/// by a timer on each affected VM once the duration has elapsed, so they are reverted even if this
+/// machine goes away.
+pub async fn apply_template(
+    testnet_deployer: &TestnetDeployer,
+    inventory: &DeploymentInventory,
+    template: ChaosTemplate,
+    max_fleet_pct: u8,
+    duration: Duration,
+) -> Result<TemplateReport> {
+    if max_fleet_pct == 0 || max_fleet_pct > 100 {
+        return Err(Error::InvalidBlastRadius(max_fleet_pct));
+    }
+    if duration.as_secs() == 0 {
+        return Err(Error::InvalidChaosDuration);
+    }
+
+    // The genesis VM is not part of the fleet, so it can never be selected.
+    let fleet: Vec<&NodeVirtualMachine> = inventory
+        .peer_cache_node_vms
+        .iter()
+        .chain(inventory.node_vms.iter())
+        .collect();
+    let fleet_node_count: usize = fleet.iter().map(|vm| vm.node_count).sum();
+    if fleet_node_count == 0 {
+        return Err(Error::ChaosTargetsUnavailable);
+    }
+    let check_blast_radius = |affected: usize| -> Result<f64> {
+        if affected * 100 > fleet_node_count * max_fleet_pct as usize {
+            return Err(Error::ChaosBlastRadiusExceeded {
+                affected,
+                limit_pct: max_fleet_pct,
+                total: fleet_node_count,
+            });
+        }
+        Ok(affected as f64 * 100.0 / fleet_node_count as f64)
+    };
+
+    match template {
+        ChaosTemplate::KillCloseGroup => {
+            let running_nodes = get_running_nodes(
+                testnet_deployer,
+                inventory,
+                &[NodeType::PeerCache, NodeType::Generic],
+            )?;
+            let close_group = get_close_group(running_nodes);
+            if close_group.is_empty() {
+                return Err(Error::ChaosTargetsUnavailable);
+            }
+            let affected_pct = check_blast_radius(close_group.len())?;
+            Ok(TemplateReport {
+                affected_pct,
+                environment_name: inventory.name.clone(),
+                records: apply_fault(testnet_deployer, Fault::Kill, close_group).await,
+                revert_at: None,
+                template,
+            })
+        }
+        ChaosTemplate::PartitionRegion | ChaosTemplate::SlowDisk10Pct => {
+            let mut vms = fleet.clone();
+            vms.shuffle(&mut rand::thread_rng());
+            let vm_count = (fleet.len() * VM_TEMPLATE_PCT).div_ceil(100);
+            vms.truncate(vm_count);
+            let affected_pct = check_blast_radius(vms.iter().map(|vm| vm.node_count).sum())?;
+
+            let (apply_commands, revert_commands) =
+                get_vm_template_commands(template, inventory, &vms);
+            let revert_at =
+                chrono::Utc::now() + chrono::Duration::seconds(duration.as_secs() as i64);
+            let mut records = Vec::new();
+            for vm in vms.iter() {
+                debug!("Applying {} to {}", template.as_str(), vm.vm.name);
+                let applied_at = chrono::Utc::now().to_rfc3339();
+                let result = run_vm_template_script(
+                    testnet_deployer,
+                    &vm.vm,
+                    &apply_commands,
+                    &revert_commands,
+                    duration,
+                )
+                .await;
+                records.push(FaultRecord {
+                    applied_at,
+                    error: result.err().map(|err| err.to_string()),
+                    peer_id: None,
+                    public_ip_addr: vm.vm.public_ip_addr,
+                    service_name: "all".to_string(),
+                    vm_name: vm.vm.name.clone(),
+                });
+            }
+            Ok(TemplateReport {
+                affected_pct,
+                environment_name: inventory.name.clone(),
+                records,
+                revert_at: Some(revert_at.to_rfc3339()),
+                template,
+            })
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DiskFillReport {
+    pub environment_name: String,
+    pub fill_pct: u8,
+    pub records: Vec<FaultRecord>,
+    pub release_at: String,
+}
+
+impl DiskFillReport {
+    /// Write the report to `logs/<name>/chaos-disk-full-<timestamp>.json`, for correlation with
+    /// the network metrics.
+    pub fn save(&self) -> Result<PathBuf> {
+        let dir = std::env::current_dir()?
+            .join("logs")
+            .join(&self.environment_name);
+        std::fs::create_dir_all(&dir)?;
+        let timestamp = chrono::Utc::now().format("%Y%m%dT%H%M%S");
+        let path = dir.join(format!("chaos-disk-full-{timestamp}.json"));
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+
+    pub fn print(&self) {
+        for record in self.records.iter() {
+            match &record.error {
+                Some(err) => println!("Failed to fill the disk on {}: {err}", record.vm_name),
+                None => println!(
+                    "Filled the disk on {} to {}% at {}",
+                    record.vm_name, self.fill_pct, record.applied_at
+                ),
+            }
+        }
+        println!("The space will be released at {}", self.release_at);
+    }
+}
+
+/// Fill the data partition of the selected node VMs to a percentage of its size, then release the
+/// space once the duration has elapsed.
+///
+/// The space is taken by a single file allocated with `fallocate`, so it is claimed immediately
+/// without writing any data. If the partition is already used beyond the percentage, nothing is
+/// allocated on that VM. The nodes keep running, so their handling of running out of space can be
+/// observed.
+///
+/// The file is removed by the same timer the VM-level templates use, so any template revert still
+/// pending on a selected VM is run first.
+pub async fn fill_disks(
+    testnet_deployer: &TestnetDeployer,
+    inventory: &DeploymentInventory,
+    vm_selector: &str,
+    fill_pct: u8,
+    duration: Duration,
+) -> Result<DiskFillReport> {
+    if fill_pct == 0 || fill_pct > 100 {
+        return Err(Error::InvalidDiskFillPct(fill_pct));
+    }
+    if duration.as_secs() == 0 {
+        return Err(Error::InvalidChaosDuration);
+    }
+
+    let node_vms = inventory.node_vm_list();
+    let mut vms = Vec::new();
+    for name in expand_vm_selector(&inventory.name, vm_selector)? {
+        let vm = node_vms
+            .iter()
+            .find(|node_vm| node_vm.vm.name == name)
+            .ok_or_else(|| Error::VmNotFound(name.clone()))?;
+        vms.push(vm.vm.clone());
+    }
+
+    let apply_commands = format!(
+        "rm -f {DISK_FILL_PATH}\n\
+        read -r size used <<< \"$(df --block-size=1 --output=size,used {NODE_STORAGE_PATH} | tail -n 1)\"\n\
+        fill=$(( size * {fill_pct} / 100 - used ))\n\
+        if [ \"$fill\" -gt 0 ]; then fallocate -l \"$fill\" {DISK_FILL_PATH}; fi\n"
+    );
+    let revert_commands = format!("rm -f {DISK_FILL_PATH}");
+    let release_at = chrono::Utc::now() + chrono::Duration::seconds(duration.as_secs() as i64);
+    let mut records = Vec::new();
+    for vm in vms {
+        debug!("Filling the disk on {} to {fill_pct}%", vm.name);
+        let applied_at = chrono::Utc::now().to_rfc3339();
+        let result = run_vm_template_script(
+            testnet_deployer,
+            &vm,
+            &apply_commands,
+            &revert_commands,
+            duration,
+        )
+        .await;
+        records.push(FaultRecord {
+            applied_at,
+            error: result.err().map(|err| err.to_string()),
+            peer_id: None,
+            public_ip_addr: vm.public_ip_addr,
+            service_name: "all".to_string(),
+            vm_name: vm.name,
+        });
+    }
+    Ok(DiskFillReport {
+        environment_name: inventory.name.clone(),
+        fill_pct,
+        records,
+        release_at: release_at.to_rfc3339(),
+    })
+}
+
+/// Select the nodes closest to a random address.
+///
+/// This approximates the close group of a record by using the SHA-256 hash of each peer ID as the
+/// address of the node, and XOR distance.
+fn get_close_group(
+    nodes: Vec<(VirtualMachine, String, Option<PeerId>)>,
+) -> Vec<(VirtualMachine, String, Option<PeerId>)> {
+    let mut target = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut target);
+    let mut nodes_with_distance: Vec<_> = nodes
+        .into_iter()
+        .filter_map(|node| {
+            let address = Sha256::digest(node.2?.to_bytes());
+            let distance: Vec<u8> = address
+                .iter()
+                .zip(target.iter())
+                .map(|(a, b)| a ^ b)
+                .collect();
+            Some((distance, node))
+        })
+        .collect();
+    nodes_with_distance.sort_by(|a, b| a.0.cmp(&b.0));
+    nodes_with_distance
+        .into_iter()
+        .take(CLOSE_GROUP_SIZE)
+        .map(|(_, node)| node)
+        .collect()
+}
+
+/// Get the commands that apply and revert a VM-level template on each affected VM.
+fn get_vm_template_commands(
+    template: ChaosTemplate,
+    inventory: &DeploymentInventory,
+    affected_vms: &[&NodeVirtualMachine],
+) -> (String, String) {
+    match template {
+        ChaosTemplate::PartitionRegion => {
+            // The rules use the same chains as the 'partition' command, so 'heal' also removes
+            // them. Only the affected VMs need rules, since they drop traffic in both directions.
+            let blocked_ips: Vec<String> = inventory
+                .vm_list()
+                .iter()
+                .filter(|vm| !affected_vms.iter().any(|a| a.vm.name == vm.name))
+                .flat_map(|vm| {
+                    [
+                        vm.public_ip_addr.to_string(),
+                        vm.private_ip_addr.to_string(),
+                    ]
+                })
+                .collect();
+            let mut apply = String::from(
+                "iptables -N ANT_PARTITION_IN 2>/dev/null || true\n\
+                iptables -N ANT_PARTITION_OUT 2>/dev/null || true\n\
+                iptables -C INPUT -j ANT_PARTITION_IN 2>/dev/null || iptables -I INPUT -j ANT_PARTITION_IN\n\
+                iptables -C OUTPUT -j ANT_PARTITION_OUT 2>/dev/null || iptables -I OUTPUT -j ANT_PARTITION_OUT\n",
+            );
+            for ip in blocked_ips {
+                apply.push_str(&format!(
+                    "iptables -A ANT_PARTITION_IN -s {ip} -j DROP\n\
+                    iptables -A ANT_PARTITION_OUT -d {ip} -j DROP\n"
+                ));
+            }
+            let revert = "iptables -F ANT_PARTITION_IN; iptables -F ANT_PARTITION_OUT".to_string();
+            (apply, revert)
+        }
+        ChaosTemplate::SlowDisk10Pct => {
+            let services =
+                "$(systemctl list-units 'antnode*' --plain --no-legend | awk '{print $1}')";
+            let device = "$(findmnt -no SOURCE --target /mnt/antnode-storage)";
+            let apply = format!(
+                "for service in {services}; do\n  \
+                systemctl set-property --runtime \"$service\" \
+                \"IOReadBandwidthMax={device} {SLOW_DISK_BANDWIDTH}\" \
+                \"IOWriteBandwidthMax={device} {SLOW_DISK_BANDWIDTH}\"\n\
+                done\n"
+            );
+            let revert = format!(
+                "for service in {services}; do \
+                systemctl set-property --runtime \"$service\" IOReadBandwidthMax= IOWriteBandwidthMax=; \
+                done"
+            );
+            (apply, revert)
+        }
+        ChaosTemplate::KillCloseGroup => (String::new(), String::new()),
+    }
+}
+
+/// Apply a template on a VM, then schedule a timer on the VM which reverts it.
+///
+/// Any revert that is still pending from a previous template is run first.
+async fn run_vm_template_script(
+    testnet_deployer: &TestnetDeployer,
+    vm: &VirtualMachine,
+    apply_commands: &str,
+    revert_commands: &str,
+    duration: Duration,
+) -> Result<()> {
+    let script = format!(
+        r#"#!/usr/bin/env bash
+set -e
+if systemctl is-active --quiet {REVERT_UNIT_NAME}.timer; then
+  systemctl stop {REVERT_UNIT_NAME}.timer
+  systemctl start {REVERT_UNIT_NAME}.service
+fi
+systemctl reset-failed {REVERT_UNIT_NAME}.service 2>/dev/null || true
+cat > /usr/local/bin/{REVERT_UNIT_NAME}.sh <<'REVERT'
+#!/usr/bin/env bash
+{revert_commands}
+REVERT
+chmod +x /usr/local/bin/{REVERT_UNIT_NAME}.sh
+systemd-run --unit={REVERT_UNIT_NAME} --on-active={} /usr/local/bin/{REVERT_UNIT_NAME}.sh
+{apply_commands}
+"#,
+        duration.as_secs()
+    );
+    let temp_dir = tempfile::tempdir()?;
+    let script_path = temp_dir.path().join("chaos_template.sh");
+    std::fs::write(&script_path, script)?;
+    testnet_deployer
+        .ssh_client
+        .execute_script(&vm.public_ip_addr, "root", &script_path, None)
+        .await?
+        .into_stdout_lines()?;
+    Ok(())
+}
@@ -0,0 +1,192 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    ansible::inventory::AnsibleInventoryType, error::Result, EnvironmentDetails, TestnetDeployer,
+};
+use std::collections::BTreeMap;
+use tracing::{debug, warn};
+
+/// The label used for uploaders that are in the same region as the nodes.
+pub const NODE_REGION_LABEL: &str = "node region";
+
+/// Get the region an uploader VM was placed in, using the index at the end of its name.
+///
+/// This mirrors the Terraform, which assigns the client regions to the uploader VMs in turn.
+/// Returns `None` if no client regions were used, in which case the uploader is in the same region
+/// as the nodes.
+pub fn get_uploader_region(uploader_regions: &[String], vm_name: &str) -> Option<String> {
+    if uploader_regions.is_empty() {
+        return None;
+    }
+    let index: usize = vm_name.rsplit('-').next()?.parse().ok()?;
+    uploader_regions
+        .get((index.checked_sub(1)?) % uploader_regions.len())
+        .cloned()
+}
+
+/// The result of a single upload, read from the metrics file written by the uploader script.
+#[derive(Clone, Debug)]
+pub struct UploadSample {
+    pub duration_secs: u64,
+    pub succeeded: bool,
+}
+
+/// Parse the lines of an `uploader_metrics.csv` file.
+///
+/// A successful upload has the chunk count and store cost recorded, whereas a failed upload only
+/// has the time and file size. The header line is skipped.
+pub fn parse_uploader_metrics(lines: &[String]) -> Vec<UploadSample> {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.trim().split(',').collect();
+            let duration_secs = fields.first()?.parse().ok()?;
+            let succeeded = fields.len() >= 4 && !fields[2].is_empty();
+            Some(UploadSample {
+                duration_secs,
+                succeeded,
+            })
+        })
+        .collect()
+}
+
+/// The upload latencies observed by the uploaders in a single region.
+#[derive(Clone, Debug)]
+pub struct RegionLatency {
+    pub failed_count: usize,
+    pub mean_secs: f64,
+    pub p50_secs: u64,
+    pub p95_secs: u64,
+    pub region: String,
+    pub succeeded_count: usize,
+    pub uploader_vm_count: usize,
+}
+
+impl RegionLatency {
+    /// Calculate the latencies from the samples of all the uploaders in the region.
+    ///
+    /// Only the successful uploads are used for the latencies, since a failed upload can return
+    /// quickly and would make the region look faster than it is.
+    pub fn new(region: &str, uploader_vm_count: usize, samples: &[UploadSample]) -> Self {
+        let mut durations: Vec<u64> = samples
+            .iter()
+            .filter(|s| s.succeeded)
+            .map(|s| s.duration_secs)
+            .collect();
+        durations.sort_unstable();
+        let mean_secs = if durations.is_empty() {
+            0.0
+        } else {
+            durations.iter().sum::<u64>() as f64 / durations.len() as f64
+        };
+        Self {
+            failed_count: samples.len() - durations.len(),
+            mean_secs,
+            p50_secs: percentile(&durations, 50),
+            p95_secs: percentile(&durations, 95),
+            region: region.to_string(),
+            succeeded_count: durations.len(),
+            uploader_vm_count,
+        }
+    }
+}
+
+fn percentile(sorted: &[u64], percentile: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let index = (percentile * (sorted.len() - 1) + 50) / 100;
+    sorted[index]
+}
+
+/// The upload latencies of the uploaders, grouped by the region they were placed in.
+#[derive(Clone, Debug)]
+pub struct ClientLatencyReport {
+    pub regions: Vec<RegionLatency>,
+}
+
+impl ClientLatencyReport {
+    pub fn print(&self) {
+        println!("=====================");
+        println!("Client Latency Report");
+        println!("=====================");
+        if self.regions.is_empty() {
+            println!("No uploader metrics were found");
+            return;
+        }
+        for region in self.regions.iter() {
+            println!("{}:", region.region);
+            println!("  Uploader VMs: {}", region.uploader_vm_count);
+            println!(
+                "  Uploads: {} succeeded, {} failed",
+                region.succeeded_count, region.failed_count
+            );
+            println!("  Mean: {:.1}s", region.mean_secs);
+            println!("  p50: {}s", region.p50_secs);
+            println!("  p95: {}s", region.p95_secs);
+        }
+    }
+}
+
+impl TestnetDeployer {
+    /// Collect the upload metrics from every uploader VM and report the latencies for each of the
+    /// regions the uploaders were placed in.
+    ///
+    /// Uploader VMs that cannot be reached are skipped, with a warning.
+    pub fn get_client_latency_report(
+        &self,
+        environment_details: &EnvironmentDetails,
+    ) -> Result<ClientLatencyReport> {
+        let uploader_regions = environment_details
+            .uploader_regions
+            .clone()
+            .unwrap_or_default();
+        let uploader_vms = self
+            .ansible_provisioner
+            .ansible_runner
+            .get_inventory(AnsibleInventoryType::Uploaders, false)?;
+
+        debug!(
+            "Retrieving the uploader metrics from {} VMs",
+            uploader_vms.len()
+        );
+        let results = self.ssh_client.run_command_on_vms(
+            &uploader_vms,
+            "root",
+            "cat /home/ant*/uploader_metrics.csv",
+            true,
+        );
+        let mut samples_by_region: BTreeMap<String, (usize, Vec<UploadSample>)> = BTreeMap::new();
+        for (vm, result) in results {
+            let region = get_uploader_region(&uploader_regions, &vm.name)
+                .unwrap_or_else(|| NODE_REGION_LABEL.to_string());
+            let lines = match result {
+                Ok(lines) => lines,
+                Err(err) => {
+                    warn!(
+                        "Failed to retrieve the uploader metrics from {}: {err}",
+                        vm.name
+                    );
+                    println!(
+                        "Skipping {}: the uploader metrics could not be retrieved",
+                        vm.name
+                    );
+                    continue;
+                }
+            };
+            let entry = samples_by_region.entry(region).or_default();
+            entry.0 += 1;
+            entry.1.extend(parse_uploader_metrics(&lines));
+        }
+
+        let regions = samples_by_region
+            .iter()
+            .map(|(region, (vm_count, samples))| RegionLatency::new(region, *vm_count, samples))
+            .collect();
+        Ok(ClientLatencyReport { regions })
+    }
+}
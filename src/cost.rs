@@ -0,0 +1,166 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    digital_ocean::{DigitalOceanClient, DIGITAL_OCEAN_API_BASE_URL, DIGITAL_OCEAN_API_PAGE_SIZE},
+    error::{Error, Result},
+    CloudProvider, TestnetDeployer,
+};
+use std::collections::BTreeMap;
+
+/// Digital Ocean does not publish block storage prices through its API, so this is the list
+/// price.
+const VOLUME_PRICE_PER_GB_MONTHLY: f64 = 0.10;
+/// Digital Ocean bills a month as 672 hours, after which the monthly price applies.
+const HOURS_PER_MONTH: f64 = 672.0;
+
+/// The projected cost of the resources in an environment, grouped by droplet size, plus the
+/// attached volumes.
+#[derive(Debug, Default)]
+pub struct CostEstimate {
+    /// The number of droplets of each size, and the hourly price of that size.
+    pub droplets: BTreeMap<String, (usize, f64)>,
+    /// The droplet sizes that could not be priced.
+    pub unpriced_sizes: Vec<String>,
+    pub volume_count: usize,
+    pub volume_gb: u64,
+}
+
+impl CostEstimate {
+    pub fn hourly_total(&self) -> f64 {
+        let droplets = self
+            .droplets
+            .values()
+            .map(|(count, hourly)| *count as f64 * hourly)
+            .sum::<f64>();
+        droplets + self.volume_gb as f64 * VOLUME_PRICE_PER_GB_MONTHLY / HOURS_PER_MONTH
+    }
+
+    pub fn print(&self) {
+        println!("=============");
+        println!("Cost Estimate");
+        println!("=============");
+        for (size, (count, hourly)) in self.droplets.iter() {
+            println!("{count} x {size}: ${:.2}/hour", *count as f64 * hourly);
+        }
+        if self.volume_count > 0 {
+            println!(
+                "{} volumes ({} GB): ${:.2}/hour",
+                self.volume_count,
+                self.volume_gb,
+                self.volume_gb as f64 * VOLUME_PRICE_PER_GB_MONTHLY / HOURS_PER_MONTH
+            );
+        }
+        for size in self.unpriced_sizes.iter() {
+            println!("No price was found for the {size} size, so it is not included");
+        }
+        let hourly = self.hourly_total();
+        println!("Total: ${hourly:.2}/hour, ${:.2}/day", hourly * 24.0);
+    }
+}
+
+/// The spend on the droplets of an environment so far, based on how long each one has existed.
+#[derive(Debug, Default)]
+pub struct EnvironmentSpend {
+    /// The usage for the whole account in the current month, as reported by the billing API.
+    pub account_month_to_date: Option<String>,
+    pub droplet_count: usize,
+    pub droplets_total: f64,
+    pub hourly_rate: f64,
+}
+
+impl EnvironmentSpend {
+    pub fn print(&self, name: &str) {
+        println!(
+            "{name}: {} droplets, ${:.2} spent so far, currently ${:.2}/hour",
+            self.droplet_count, self.droplets_total, self.hourly_rate
+        );
+        if let Some(usage) = &self.account_month_to_date {
+            println!("Account usage for this month: ${usage}");
+        }
+    }
+}
+
+impl TestnetDeployer {
+    /// Estimate the cost of the resources in the environment's Terraform state, using the current
+    /// droplet prices from the Digital Ocean API.
+    pub async fn estimate_infra_cost(&self) -> Result<CostEstimate> {
+        let client = get_digital_ocean_client(&self.cloud_provider)?;
+        let prices = client.list_size_prices().await?;
+        let resources = self.terraform_runner.show(&self.environment_name)?;
+
+        let mut estimate = CostEstimate::default();
+        for resource in resources.iter() {
+            match resource.resource_type.as_str() {
+                "digitalocean_droplet" => {
+                    let Some(size) = resource.values.get("size").and_then(|v| v.as_str()) else {
+                        continue;
+                    };
+                    match prices.get(size) {
+                        Some(hourly) => {
+                            estimate
+                                .droplets
+                                .entry(size.to_string())
+                                .or_insert((0, *hourly))
+                                .0 += 1;
+                        }
+                        None if !estimate.unpriced_sizes.contains(&size.to_string()) => {
+                            estimate.unpriced_sizes.push(size.to_string());
+                        }
+                        None => {}
+                    }
+                }
+                "digitalocean_volume" => {
+                    estimate.volume_count += 1;
+                    estimate.volume_gb += resource
+                        .values
+                        .get("size")
+                        .and_then(|v| v.as_u64())
+                        .unwrap_or_default();
+                }
+                _ => {}
+            }
+        }
+        Ok(estimate)
+    }
+
+    /// Get the spend on the droplets tagged with the environment.
+    ///
+    /// The billing API only reports usage for the whole account, so the spend for the environment
+    /// is derived from how long each droplet has been running at its hourly price. Volumes and
+    /// bandwidth are not included.
+    pub async fn get_environment_spend(&self) -> Result<EnvironmentSpend> {
+        let client = get_digital_ocean_client(&self.cloud_provider)?;
+        let now = chrono::Utc::now();
+        let droplets = client
+            .list_droplet_billing(&format!("environment:{}", self.environment_name))
+            .await?;
+
+        let mut spend = EnvironmentSpend {
+            droplet_count: droplets.len(),
+            ..Default::default()
+        };
+        for droplet in droplets.iter() {
+            let hours = (now - droplet.created_at).num_minutes() as f64 / 60.0;
+            spend.droplets_total += hours.min(HOURS_PER_MONTH) * droplet.price_hourly;
+            spend.hourly_rate += droplet.price_hourly;
+        }
+        spend.account_month_to_date = client.get_month_to_date_usage().await.ok();
+        Ok(spend)
+    }
+}
+
+fn get_digital_ocean_client(provider: &CloudProvider) -> Result<DigitalOceanClient> {
+    if !matches!(provider, CloudProvider::DigitalOcean) {
+        return Err(Error::CloudProviderNotSupported(provider.to_string()));
+    }
+    Ok(DigitalOceanClient {
+        base_url: DIGITAL_OCEAN_API_BASE_URL.to_string(),
+        access_token: std::env::var("DO_PAT")
+            .map_err(|_| Error::CloudProviderCredentialsNotSupplied("DO_PAT".to_string()))?,
+        page_size: DIGITAL_OCEAN_API_PAGE_SIZE,
+    })
+}
@@ -5,22 +5,55 @@
 // Please see the LICENSE file for more details.
 
 use crate::{
-    ansible::{inventory::AnsibleInventoryType, provisioning::ProvisionOptions},
-    error::Result,
+    ansible::{
+        inventory::AnsibleInventoryType, provisioning::ProvisionOptions,
+        results::ProvisioningReport,
+    },
+    artifact_repository::ArtifactRepositoryConfig,
+    error::{Error, Result},
+    firewall::{apply_firewall, FirewallProfile},
     funding::get_address_from_sk,
-    get_anvil_node_data, get_bootstrap_cache_url, get_genesis_multiaddr, write_environment_details,
-    BinaryOption, DeploymentInventory, DeploymentType, EnvironmentDetails, EnvironmentType,
-    EvmNetwork, InfraRunOptions, LogFormat, NodeType, TestnetDeployer,
+    get_anvil_node_data, get_bootstrap_cache_url, get_environment_details, get_genesis_multiaddr,
+    inventory::get_door_node_number,
+    notifications::{DeploySummary, NotificationConfig},
+    reaper::get_expiry_timestamp,
+    release_channel::ResolvedRelease,
+    run_log,
+    versions_file::{get_versions_file_path, VersionsFile},
+    write_environment_details, Architecture, BandwidthClass, BinaryOption, DeploymentInventory,
+    DeploymentType, EnvironmentDetails, EnvironmentType, EvmNetwork, InfraRunOptions, LogFormat,
+    NatType, NodeType, ReachabilityMode, RestartPolicy, TelemetryConfig, TestnetDeployer,
 };
 use alloy::hex::ToHexExt;
-use colored::Colorize;
-use std::{net::SocketAddr, path::PathBuf, time::Duration};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::Write,
+    net::IpAddr,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use tracing::{error, info, info_span, warn, Instrument, Span};
 
 #[derive(Clone)]
 pub struct DeployOptions {
+    pub architecture: Architecture,
+    /// Where the binary archives are downloaded from.
+    pub artifact_repository: ArtifactRepositoryConfig,
+    /// The number of VMs that run the auditor, which is pointed at the genesis node.
+    pub auditor_vm_count: Option<u16>,
     pub binary_option: BinaryOption,
     pub chunk_size: Option<u64>,
     pub current_inventory: DeploymentInventory,
+    /// Always build the binaries, rather than reusing the archives from a previous build of the
+    /// same commit.
+    pub disable_build_cache: bool,
+    /// The domain under which the genesis and Peer Cache nodes are given DNS records.
+    pub dns_domain: Option<String>,
+    /// Replace the provider's DNS resolvers on every VM. They are left unchanged if empty.
+    pub dns_resolvers: Vec<IpAddr>,
+    pub door_node_count: u16,
+    pub door_node_dns_domain: Option<String>,
     pub downloaders_count: u16,
     pub environment_type: EnvironmentType,
     pub env_variables: Option<Vec<(String, String)>>,
@@ -29,71 +62,388 @@ pub struct DeployOptions {
     pub evm_node_vm_size: Option<String>,
     pub evm_payment_token_address: Option<String>,
     pub evm_rpc_url: Option<String>,
+    /// Create a cloud firewall for the environment from this profile.
+    pub firewall: Option<FirewallProfile>,
     pub funding_wallet_secret_key: Option<String>,
     pub genesis_node_volume_size: Option<u16>,
+    /// Run the hardening playbook against every machine before any provisioning.
+    pub harden: bool,
+    /// Extra entries for /etc/hosts on every VM.
+    pub host_entries: Vec<(String, IpAddr)>,
     pub interval: Duration,
     pub log_format: Option<LogFormat>,
-    pub logstash_details: Option<(String, Vec<SocketAddr>)>,
     pub max_archived_log_files: u16,
     pub max_log_files: u16,
     pub name: String,
+    /// The number of NAT gateways the private node VMs are spread across.
+    pub nat_gateway_count: Option<u16>,
+    pub nat_type: NatType,
     pub network_id: Option<u8>,
     pub node_count: u16,
+    pub node_reachability: ReachabilityMode,
+    /// Override the restart policy of the node services once they have been provisioned.
+    pub node_restart_policy: RestartPolicy,
     pub node_vm_count: Option<u16>,
     pub node_vm_size: Option<String>,
     pub node_volume_size: Option<u16>,
     pub output_inventory_dir_path: PathBuf,
     pub peer_cache_node_count: u16,
+    pub peer_cache_node_reachability: ReachabilityMode,
     pub peer_cache_node_vm_count: Option<u16>,
     pub peer_cache_node_vm_size: Option<String>,
     pub peer_cache_node_volume_size: Option<u16>,
+    /// Build this commit, rather than the head of the branch, when building from source.
+    pub pinned_commit_sha: Option<String>,
     pub private_node_count: u16,
+    /// Run a UPnP daemon on the NAT gateways and have the private nodes use it to open their ports.
+    pub private_node_upnp: bool,
     pub private_node_vm_count: Option<u16>,
     pub private_node_volume_size: Option<u16>,
+    /// Provision the node VMs in batches of this size, rather than all of them in a single run.
+    pub provision_parallelism: Option<usize>,
+    /// Post a summary of the deployment to a webhook when it finishes.
+    pub notification: Option<NotificationConfig>,
+    /// The number of times to re-run the node playbooks against the hosts that failed.
+    pub provision_retries: u8,
     pub public_rpc: bool,
+    /// The versions a release channel resolved to, if the binaries were selected by a channel.
+    pub release: Option<ResolvedRelease>,
+    /// Skip the phases that were completed by a previous, failed run for the same environment.
+    pub resume: bool,
     pub rewards_address: String,
+    /// Used to register with Let's Encrypt for the RPC proxy certificates.
+    pub rpc_proxy_acme_email: Option<String>,
+    /// If set, the node RPC is exposed through a TLS reverse proxy on each VM, which requires this
+    /// token.
+    pub rpc_proxy_auth_token: Option<String>,
+    /// Create an artifact proxy VM in the same region, through which all the VMs download the
+    /// binary archives, rather than each of them downloading from S3.
+    pub setup_artifact_proxy: bool,
+    /// Run a DNS forwarder and an NTP server on the artifact proxy VM and configure every other
+    /// VM to use them, so they do not depend on public DNS or NTP servers.
+    pub setup_infra_services: bool,
+    /// Create a monitoring VM running Prometheus and Grafana, which scrapes the node metrics.
+    pub setup_monitoring: bool,
+    /// Collect sysstat samples every second on the node VMs, for this duration, once they have
+    /// been provisioned.
+    pub sysstat_duration: Option<Duration>,
+    pub telemetry: Option<TelemetryConfig>,
+    /// Record an expiry this long after the deployment, after which the reaper destroys the
+    /// environment.
+    pub ttl: Option<Duration>,
     pub uploader_vm_count: Option<u16>,
+    pub uploader_bandwidth_class: BandwidthClass,
+    /// Spread the uploader VMs across these regions, rather than placing them with the nodes.
+    pub uploader_regions: Vec<String>,
     pub uploader_vm_size: Option<String>,
     pub uploaders_count: u16,
+    /// Join the VMs and the machine running the deployer to a WireGuard overlay.
+    pub wireguard: bool,
+}
+
+/// The phases of a deployment that are recorded in the checkpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeployPhase {
+    Infra,
+    Hardening,
+    InfraServices,
+    DnsConfig,
+    ArtifactProxy,
+    EvmNodes,
+    Build,
+    Genesis,
+    PeerCacheNodes,
+    Nodes,
+    NatGateway,
+    PrivateNodes,
+    RestartPolicy,
+    RpcProxy,
+    WireGuard,
+    Uploaders,
+    Auditors,
+    Monitoring,
+}
+
+/// Records the phases of a deployment that have completed, so that a failed deployment can be
+/// resumed without repeating them.
+///
+/// The checkpoint is saved locally in the same data directory as the inventory, and it is removed
+/// when a deployment completes.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DeployCheckpoint {
+    pub completed_phases: Vec<DeployPhase>,
+    /// For a dry run, completed phases are tracked but not saved.
+    #[serde(skip)]
+    pub dry_run: bool,
+    pub name: String,
+    /// When the phases currently running were started, used to log their durations.
+    #[serde(skip)]
+    started_phases: Vec<(DeployPhase, Instant)>,
+}
+
+impl DeployCheckpoint {
+    pub fn load(name: &str) -> Result<Self> {
+        let path = Self::get_path(name)?;
+        if !path.exists() {
+            return Ok(Self {
+                name: name.to_string(),
+                ..Default::default()
+            });
+        }
+        let data = std::fs::read_to_string(path)?;
+        let checkpoint: Self = serde_json::from_str(&data)?;
+        for phase in checkpoint.completed_phases.iter() {
+            info!("Skipped the {phase:?} phase: it was completed by a previous run");
+        }
+        Ok(checkpoint)
+    }
+
+    pub fn clear(name: &str) -> Result<()> {
+        let path = Self::get_path(name)?;
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Check whether a phase was completed, either by a previous run or earlier in this one.
+    pub fn is_complete(&self, phase: DeployPhase) -> bool {
+        self.completed_phases.contains(&phase)
+    }
+
+    /// Record that a phase is starting, so its duration can be logged when it completes, or it
+    /// can be reported as incomplete if it doesn't.
+    ///
+    /// The returned span should be entered for the work done in the phase, so the events logged
+    /// during it are attributed to the phase.
+    pub fn start(&mut self, phase: DeployPhase) -> Span {
+        let span = info_span!("phase", name = ?phase);
+        if !self.started_phases.iter().any(|(p, _)| *p == phase) {
+            span.in_scope(|| info!("Starting the {phase:?} phase"));
+            self.started_phases.push((phase, Instant::now()));
+        }
+        span
+    }
+
+    /// The phases that were started by this run but did not complete, e.g., because they failed.
+    pub fn incomplete_phases(&self) -> Vec<DeployPhase> {
+        self.started_phases
+            .iter()
+            .map(|(phase, _)| *phase)
+            .collect()
+    }
+
+    pub fn complete(&mut self, phase: DeployPhase) -> Result<()> {
+        if !self.completed_phases.contains(&phase) {
+            self.completed_phases.push(phase);
+        }
+        if let Some(index) = self.started_phases.iter().position(|(p, _)| *p == phase) {
+            let (_, started) = self.started_phases.remove(index);
+            info!(
+                "Completed the {phase:?} phase in {}",
+                run_log::format_duration(started.elapsed())
+            );
+        }
+        if self.dry_run {
+            return Ok(());
+        }
+        let serialized_data = serde_json::to_string_pretty(self)?;
+        let mut file = File::create(Self::get_path(&self.name)?)?;
+        file.write_all(serialized_data.as_bytes())?;
+        Ok(())
+    }
+
+    fn get_path(name: &str) -> Result<PathBuf> {
+        let path = dirs_next::data_dir()
+            .ok_or_else(|| Error::CouldNotRetrieveDataDirectory)?
+            .join("safe")
+            .join("testnet-deploy");
+        if !path.exists() {
+            std::fs::create_dir_all(&path)?;
+        }
+        Ok(path.join(format!("{name}-deploy-checkpoint.json")))
+    }
 }
 
 impl TestnetDeployer {
+    /// Deploy the environment.
+    ///
+    /// If a notification webhook is configured, a summary is posted to it once the deployment
+    /// finishes, whether or not it succeeded. A failure to post the summary is not treated as a
+    /// failure of the deployment.
     pub async fn deploy(&self, options: &DeployOptions) -> Result<()> {
+        let started = Instant::now();
+        let mut summary = DeploySummary::new(&options.name);
+        let mut checkpoint = DeployCheckpoint {
+            name: options.name.clone(),
+            ..Default::default()
+        };
+        let result = self
+            .run_deploy(options, &mut checkpoint, &mut summary)
+            .await;
+
+        if let Some(notification) = &options.notification {
+            summary.duration_secs = started.elapsed().as_secs();
+            summary.succeeded = result.is_ok();
+            summary.failed_phases = checkpoint
+                .incomplete_phases()
+                .iter()
+                .map(|phase| format!("{phase:?}"))
+                .collect();
+            if let Err(err) = notification.notify_deploy(&summary).await {
+                warn!("Failed to post the deployment summary: {err}");
+            }
+        }
+        result
+    }
+
+    async fn run_deploy(
+        &self,
+        options: &DeployOptions,
+        checkpoint: &mut DeployCheckpoint,
+        summary: &mut DeploySummary,
+    ) -> Result<()> {
         let build_custom_binaries = {
             match &options.binary_option {
                 BinaryOption::BuildFromSource { .. } => true,
-                BinaryOption::Versioned { .. } => false,
+                BinaryOption::Local { .. } | BinaryOption::Versioned { .. } => false,
             }
         };
+        if matches!(options.binary_option, BinaryOption::Local { .. })
+            && !options.setup_artifact_proxy
+        {
+            return Err(Error::LocalBinariesRequireArtifactProxy);
+        }
 
-        self.create_or_update_infra(&InfraRunOptions {
-            enable_build_vm: build_custom_binaries,
-            evm_node_count: match options.evm_network {
-                EvmNetwork::Anvil => Some(1),
-                EvmNetwork::ArbitrumOne => Some(0),
-                EvmNetwork::ArbitrumSepolia => Some(0),
-                EvmNetwork::Custom => Some(0),
-            },
-            evm_node_vm_size: options.evm_node_vm_size.clone(),
-            genesis_vm_count: Some(1),
-            genesis_node_volume_size: options.genesis_node_volume_size,
-            name: options.name.clone(),
-            node_vm_count: options.node_vm_count,
-            node_vm_size: options.node_vm_size.clone(),
-            node_volume_size: options.node_volume_size,
-            peer_cache_node_vm_count: options.peer_cache_node_vm_count,
-            peer_cache_node_vm_size: options.peer_cache_node_vm_size.clone(),
-            peer_cache_node_volume_size: options.peer_cache_node_volume_size,
-            private_node_vm_count: options.private_node_vm_count,
-            private_node_volume_size: options.private_node_volume_size,
-            tfvars_filename: options.environment_type.get_tfvars_filename(&options.name),
-            uploader_vm_count: options.uploader_vm_count,
-            uploader_vm_size: options.uploader_vm_size.clone(),
-        })
-        .map_err(|err| {
-            println!("Failed to create infra {err:?}");
-            err
-        })?;
+        if self.is_dry_run() {
+            info!("Dry run: Terraform will plan changes and Ansible will run in check mode");
+        } else if !options.resume {
+            DeployCheckpoint::clear(&options.name)?;
+        }
+        *checkpoint = DeployCheckpoint::load(&options.name)?;
+        checkpoint.dry_run = self.is_dry_run();
+        let expires_at = options.ttl.map(get_expiry_timestamp);
+
+        let mut build_cache_key = None;
+        let mut built_commit_sha = None;
+        // A pinned commit is always checked out through the key, even if the cache is disabled.
+        if build_custom_binaries
+            && (!options.disable_build_cache || options.pinned_commit_sha.is_some())
+            && !self.is_dry_run()
+            && !checkpoint.is_complete(DeployPhase::Build)
+        {
+            match self
+                .get_build_cache_key(
+                    &options.binary_option,
+                    options.pinned_commit_sha.as_deref(),
+                    options.chunk_size,
+                    options.architecture,
+                )
+                .await
+            {
+                Ok(Some(cache_key)) => {
+                    built_commit_sha = Some(cache_key.commit_sha.clone());
+                    if !options.disable_build_cache
+                        && self
+                            .restore_cached_build(
+                                &options.binary_option,
+                                &cache_key,
+                                options.architecture,
+                            )
+                            .await?
+                    {
+                        info!(
+                            "The binaries for commit {} are cached; the build will be skipped",
+                            cache_key.commit_sha
+                        );
+                        checkpoint.complete(DeployPhase::Build)?;
+                    } else {
+                        build_cache_key = Some(cache_key);
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    warn!("Failed to compute the build cache key: {err}");
+                    info!("The build cache could not be used, so the binaries will be built");
+                }
+            }
+        }
+
+        if !checkpoint.is_complete(DeployPhase::Infra) {
+            let phase = checkpoint.start(DeployPhase::Infra);
+            phase
+                .in_scope(|| {
+                    self.create_or_update_infra(&InfraRunOptions {
+                        auditor_vm_count: options.auditor_vm_count,
+                        dns_domain: options.dns_domain.clone(),
+                        door_node_count: Some(options.door_node_count),
+                        door_node_dns_domain: options.door_node_dns_domain.clone(),
+                        downloader_vm_count: None,
+                        enable_build_vm: build_custom_binaries
+                            && !checkpoint.is_complete(DeployPhase::Build),
+                        evm_node_count: match options.evm_network {
+                            EvmNetwork::Anvil => Some(1),
+                            EvmNetwork::ArbitrumOne => Some(0),
+                            EvmNetwork::ArbitrumSepolia => Some(0),
+                            EvmNetwork::Custom => Some(0),
+                        },
+                        evm_node_vm_size: options.evm_node_vm_size.clone(),
+                        genesis_vm_count: Some(1),
+                        genesis_node_volume_size: options.genesis_node_volume_size,
+                        name: options.name.clone(),
+                        nat_gateway_count: options.nat_gateway_count,
+                        node_vm_count: options.node_vm_count,
+                        node_vm_size: options.node_vm_size.clone(),
+                        node_volume_size: options.node_volume_size,
+                        peer_cache_node_vm_count: options.peer_cache_node_vm_count,
+                        peer_cache_node_vm_size: options.peer_cache_node_vm_size.clone(),
+                        peer_cache_node_volume_size: options.peer_cache_node_volume_size,
+                        private_node_vm_count: options.private_node_vm_count,
+                        private_node_volume_size: options.private_node_volume_size,
+                        setup_artifact_proxy: Some(options.setup_artifact_proxy),
+                        setup_monitoring: Some(options.setup_monitoring),
+                        tfvars_filename: options
+                            .environment_type
+                            .get_tfvars_filename(&options.name),
+                        uploader_vm_count: options.uploader_vm_count,
+                        uploader_bandwidth_class: Some(options.uploader_bandwidth_class),
+                        uploader_regions: Some(options.uploader_regions.clone()),
+                        uploader_vm_size: options.uploader_vm_size.clone(),
+                    })
+                })
+                .map_err(|err| {
+                    error!("Failed to create infra {err:?}");
+                    err
+                })?;
+            if let Some(profile) = &options.firewall {
+                if self.is_dry_run() {
+                    info!("Dry run: skipping the creation of the firewall");
+                } else {
+                    apply_firewall(self.cloud_provider, &options.name, profile)
+                        .instrument(phase)
+                        .await?;
+                }
+            }
+            checkpoint.complete(DeployPhase::Infra)?;
+
+            if !self.is_dry_run() {
+                match self.estimate_infra_cost().await {
+                    Ok(estimate) => estimate.print(),
+                    Err(err) => warn!("Failed to estimate the cost of the infrastructure: {err}"),
+                }
+            }
+        }
+
+        if self.is_dry_run()
+            && self
+                .ansible_provisioner
+                .ansible_runner
+                .get_inventory(AnsibleInventoryType::Genesis, false)?
+                .is_empty()
+        {
+            info!("Dry run: the environment has no VMs yet, so there are no playbooks to check");
+            return Ok(());
+        }
 
         // All the environment types set private_node_vm count to >0 if not specified.
         let should_provision_private_nodes = options
@@ -101,41 +451,167 @@ impl TestnetDeployer {
             .map(|count| count > 0)
             .unwrap_or(true);
 
-        write_environment_details(
-            &self.s3_repository,
-            &options.name,
-            &EnvironmentDetails {
-                deployment_type: DeploymentType::New,
-                environment_type: options.environment_type.clone(),
-                evm_network: options.evm_network.clone(),
-                evm_data_payments_address: options.evm_data_payments_address.clone(),
-                evm_payment_token_address: options.evm_payment_token_address.clone(),
-                evm_rpc_url: options.evm_rpc_url.clone(),
-                funding_wallet_address: None,
-                network_id: options.network_id,
-                rewards_address: options.rewards_address.clone(),
-            },
-        )
-        .await?;
+        if !self.is_dry_run() {
+            write_environment_details(
+                &self.s3_repository,
+                &options.name,
+                &EnvironmentDetails {
+                    architecture: Some(options.architecture),
+                    artifact_repository: Some(options.artifact_repository.clone()),
+                    deployment_type: DeploymentType::New,
+                    dns_domain: options.dns_domain.clone(),
+                    door_node_count: Some(options.door_node_count),
+                    door_node_dns_domain: options.door_node_dns_domain.clone(),
+                    environment_type: options.environment_type.clone(),
+                    evm_network: options.evm_network.clone(),
+                    evm_data_payments_address: options.evm_data_payments_address.clone(),
+                    evm_payment_token_address: options.evm_payment_token_address.clone(),
+                    evm_rpc_url: options.evm_rpc_url.clone(),
+                    expires_at,
+                    firewall: options.firewall.clone(),
+                    funding_wallet_address: None,
+                    nat_gateway_shards: options
+                        .current_inventory
+                        .environment_details
+                        .nat_gateway_shards
+                        .clone(),
+                    nat_type: Some(options.nat_type),
+                    network_contacts_url: None,
+                    network_id: options.network_id,
+                    node_count: Some(options.node_count),
+                    node_reachability: Some(options.node_reachability),
+                    peer_cache_node_count: Some(options.peer_cache_node_count),
+                    peer_cache_node_reachability: Some(options.peer_cache_node_reachability),
+                    private_node_count: Some(options.private_node_count),
+                    private_node_upnp: Some(options.private_node_upnp),
+                    release: options.release.clone(),
+                    rewards_address: options.rewards_address.clone(),
+                    rpc_proxy: Some(options.rpc_proxy_auth_token.is_some()),
+                    uploader_bandwidth_class: Some(options.uploader_bandwidth_class),
+                    uploader_regions: Some(options.uploader_regions.clone()),
+                },
+            )
+            .await?;
+
+            let commit_sha = built_commit_sha.or_else(|| options.pinned_commit_sha.clone());
+            if let Some(versions) = VersionsFile::new(options, commit_sha) {
+                let path = get_versions_file_path(&options.name)?;
+                versions.write(&path)?;
+                info!("The deployed binaries were recorded in {}", path.display());
+            }
+        }
+
+        if options.harden && !checkpoint.is_complete(DeployPhase::Hardening) {
+            let _phase = checkpoint.start(DeployPhase::Hardening).entered();
+            self.ansible_provisioner
+                .print_ansible_run_banner("Harden Machines");
+            self.ansible_provisioner
+                .provision_hardening()
+                .map_err(|err| {
+                    error!("Failed to harden machines {err:?}");
+                    err
+                })?;
+            checkpoint.complete(DeployPhase::Hardening)?;
+        }
 
         let mut provision_options = ProvisionOptions::from(options.clone());
-        let anvil_node_data = if options.evm_network == EvmNetwork::Anvil {
+        provision_options.build_cache_key = build_cache_key;
+
+        let mut dns_resolvers = options.dns_resolvers.clone();
+        let mut ntp_servers = Vec::new();
+        if options.setup_infra_services {
+            if !checkpoint.is_complete(DeployPhase::InfraServices) {
+                let _phase = checkpoint.start(DeployPhase::InfraServices).entered();
+                self.ansible_provisioner
+                    .print_ansible_run_banner("Provision Infra Services");
+                self.ansible_provisioner
+                    .provision_infra_services(&provision_options, &options.dns_resolvers)
+                    .map_err(|err| {
+                        error!("Failed to provision infra services {err:?}");
+                        err
+                    })?;
+                checkpoint.complete(DeployPhase::InfraServices)?;
+            }
+            // Any resolvers that were supplied are used upstream of the forwarder instead.
+            let infra_services_ip = self
+                .ansible_provisioner
+                .get_infra_services_ip()?
+                .ok_or(Error::EmptyInventory(AnsibleInventoryType::ArtifactProxy))?;
+            dns_resolvers = vec![infra_services_ip];
+            ntp_servers = vec![infra_services_ip];
+        }
+
+        if (!dns_resolvers.is_empty() || !options.host_entries.is_empty())
+            && !checkpoint.is_complete(DeployPhase::DnsConfig)
+        {
+            let _phase = checkpoint.start(DeployPhase::DnsConfig).entered();
             self.ansible_provisioner
-                .print_ansible_run_banner("Provision Anvil Node");
+                .print_ansible_run_banner("Configure DNS");
             self.ansible_provisioner
-                .provision_evm_nodes(&provision_options)
+                .provision_dns_config(&dns_resolvers, &options.host_entries, &ntp_servers)
                 .map_err(|err| {
-                    println!("Failed to provision evm node {err:?}");
+                    error!("Failed to configure DNS {err:?}");
                     err
                 })?;
+            checkpoint.complete(DeployPhase::DnsConfig)?;
+        }
 
-            Some(
-                get_anvil_node_data(&self.ansible_provisioner.ansible_runner, &self.ssh_client)
+        if options.setup_artifact_proxy {
+            if !checkpoint.is_complete(DeployPhase::ArtifactProxy) {
+                let _phase = checkpoint.start(DeployPhase::ArtifactProxy).entered();
+                self.ansible_provisioner
+                    .print_ansible_run_banner("Provision Artifact Proxy");
+                self.ansible_provisioner
+                    .provision_artifact_proxy(&provision_options)
                     .map_err(|err| {
-                        println!("Failed to get evm testnet data {err:?}");
+                        error!("Failed to provision artifact proxy {err:?}");
                         err
-                    })?,
-            )
+                    })?;
+                checkpoint.complete(DeployPhase::ArtifactProxy)?;
+            }
+            provision_options.artifact_proxy_url =
+                self.ansible_provisioner.get_artifact_proxy_url()?;
+
+            if matches!(options.binary_option, BinaryOption::Local { .. }) {
+                // The binaries are uploaded on every run, including a resumed run, since they
+                // are likely to have been rebuilt in the meantime.
+                self.ansible_provisioner
+                    .print_ansible_run_banner("Upload Local Binaries");
+                self.upload_local_binaries(&options.binary_option, options.architecture)
+                    .map_err(|err| {
+                        error!("Failed to upload the local binaries {err:?}");
+                        err
+                    })?;
+            }
+        }
+
+        let anvil_node_data = if options.evm_network == EvmNetwork::Anvil {
+            if !checkpoint.is_complete(DeployPhase::EvmNodes) {
+                let _phase = checkpoint.start(DeployPhase::EvmNodes).entered();
+                self.ansible_provisioner
+                    .print_ansible_run_banner("Provision Anvil Node");
+                self.ansible_provisioner
+                    .provision_evm_nodes(&provision_options)
+                    .map_err(|err| {
+                        error!("Failed to provision evm node {err:?}");
+                        err
+                    })?;
+                checkpoint.complete(DeployPhase::EvmNodes)?;
+            }
+
+            if self.is_dry_run() {
+                info!("Dry run: skipping the retrieval of the EVM testnet data");
+                None
+            } else {
+                Some(
+                    get_anvil_node_data(&self.ansible_provisioner.ansible_runner, &self.ssh_client)
+                        .await
+                        .map_err(|err| {
+                            error!("Failed to get evm testnet data {err:?}");
+                            err
+                        })?,
+                )
+            }
         } else {
             None
         };
@@ -147,7 +623,7 @@ impl TestnetDeployer {
             let address = get_address_from_sk(&emv_data.deployer_wallet_private_key)?;
             Some(address.encode_hex())
         } else {
-            log::error!("Funding wallet address not provided");
+            tracing::error!("Funding wallet address not provided");
             None
         };
 
@@ -161,124 +637,266 @@ impl TestnetDeployer {
                 Some(custom_evm.deployer_wallet_private_key.clone());
         };
 
-        write_environment_details(
-            &self.s3_repository,
-            &options.name,
-            &EnvironmentDetails {
-                deployment_type: DeploymentType::New,
-                environment_type: options.environment_type.clone(),
-                evm_network: options.evm_network.clone(),
-                evm_data_payments_address: provision_options.evm_data_payments_address.clone(),
-                evm_payment_token_address: provision_options.evm_payment_token_address.clone(),
-                evm_rpc_url: provision_options.evm_rpc_url.clone(),
-                funding_wallet_address,
-                network_id: options.network_id,
-                rewards_address: options.rewards_address.clone(),
-            },
-        )
-        .await?;
-
-        if build_custom_binaries {
+        if !self.is_dry_run() {
+            write_environment_details(
+                &self.s3_repository,
+                &options.name,
+                &EnvironmentDetails {
+                    architecture: Some(options.architecture),
+                    artifact_repository: Some(options.artifact_repository.clone()),
+                    deployment_type: DeploymentType::New,
+                    dns_domain: options.dns_domain.clone(),
+                    door_node_count: Some(options.door_node_count),
+                    door_node_dns_domain: options.door_node_dns_domain.clone(),
+                    environment_type: options.environment_type.clone(),
+                    evm_network: options.evm_network.clone(),
+                    evm_data_payments_address: provision_options.evm_data_payments_address.clone(),
+                    evm_payment_token_address: provision_options.evm_payment_token_address.clone(),
+                    evm_rpc_url: provision_options.evm_rpc_url.clone(),
+                    expires_at,
+                    firewall: options.firewall.clone(),
+                    funding_wallet_address,
+                    nat_gateway_shards: options
+                        .current_inventory
+                        .environment_details
+                        .nat_gateway_shards
+                        .clone(),
+                    nat_type: Some(options.nat_type),
+                    network_contacts_url: None,
+                    network_id: options.network_id,
+                    node_count: Some(options.node_count),
+                    node_reachability: Some(options.node_reachability),
+                    peer_cache_node_count: Some(options.peer_cache_node_count),
+                    peer_cache_node_reachability: Some(options.peer_cache_node_reachability),
+                    private_node_count: Some(options.private_node_count),
+                    private_node_upnp: Some(options.private_node_upnp),
+                    release: options.release.clone(),
+                    rewards_address: options.rewards_address.clone(),
+                    rpc_proxy: Some(options.rpc_proxy_auth_token.is_some()),
+                    uploader_bandwidth_class: Some(options.uploader_bandwidth_class),
+                    uploader_regions: Some(options.uploader_regions.clone()),
+                },
+            )
+            .await?;
+        }
+
+        if build_custom_binaries && !checkpoint.is_complete(DeployPhase::Build) {
+            let _phase = checkpoint.start(DeployPhase::Build).entered();
             self.ansible_provisioner
                 .print_ansible_run_banner("Build Custom Binaries");
             self.ansible_provisioner
                 .build_safe_network_binaries(&provision_options)
                 .map_err(|err| {
-                    println!("Failed to build safe network binaries {err:?}");
+                    error!("Failed to build safe network binaries {err:?}");
                     err
                 })?;
+            checkpoint.complete(DeployPhase::Build)?;
         }
 
-        self.ansible_provisioner
-            .print_ansible_run_banner("Provision Genesis Node");
-        self.ansible_provisioner
-            .provision_genesis_node(&provision_options)
-            .map_err(|err| {
-                println!("Failed to provision genesis node {err:?}");
-                err
-            })?;
+        if !checkpoint.is_complete(DeployPhase::Genesis) {
+            let _phase = checkpoint.start(DeployPhase::Genesis).entered();
+            self.ansible_provisioner
+                .print_ansible_run_banner("Provision Genesis Node");
+            self.ansible_provisioner
+                .provision_genesis_node(&provision_options)
+                .map_err(|err| {
+                    error!("Failed to provision genesis node {err:?}");
+                    err
+                })?;
+            checkpoint.complete(DeployPhase::Genesis)?;
+        }
+        if self.is_dry_run() {
+            // The genesis multiaddr is read from the node over SSH, and every remaining phase
+            // depends on it.
+            info!("Dry run: the remaining phases need the genesis multiaddr, so they are skipped");
+            return Ok(());
+        }
         let (genesis_multiaddr, genesis_ip) =
             get_genesis_multiaddr(&self.ansible_provisioner.ansible_runner, &self.ssh_client)
+                .await
                 .map_err(|err| {
-                    println!("Failed to get genesis multiaddr {err:?}");
+                    error!("Failed to get genesis multiaddr {err:?}");
                     err
                 })?;
 
+        summary.genesis_multiaddr = Some(genesis_multiaddr.clone());
         let genesis_network_contacts = get_bootstrap_cache_url(&genesis_ip);
-        println!("Obtained multiaddr for genesis node: {genesis_multiaddr}, network contact: {genesis_network_contacts}");
+        info!("Obtained multiaddr for genesis node: {genesis_multiaddr}, network contact: {genesis_network_contacts}");
 
         let mut node_provision_failed = false;
-        self.ansible_provisioner
-            .print_ansible_run_banner("Provision Peer Cache Nodes");
-        match self.ansible_provisioner.provision_peer_cache_nodes(
-            &provision_options,
-            Some(genesis_multiaddr.clone()),
-            Some(genesis_network_contacts.clone()),
-        ) {
-            Ok(()) => {
-                println!("Provisioned Peer Cache nodes");
-            }
-            Err(err) => {
-                log::error!("Failed to provision Peer Cache nodes: {err}");
-                node_provision_failed = true;
+        let mut failed_hosts = Vec::new();
+        if options.door_node_count > 0 {
+            provision_options.door_node_vms = self
+                .ansible_provisioner
+                .ansible_runner
+                .get_inventory(AnsibleInventoryType::PeerCacheNodes, true)?
+                .into_iter()
+                .filter(|vm| get_door_node_number(&vm.name, options.door_node_count).is_some())
+                .collect();
+        }
+        if !checkpoint.is_complete(DeployPhase::PeerCacheNodes) {
+            let _phase = checkpoint.start(DeployPhase::PeerCacheNodes).entered();
+            self.ansible_provisioner
+                .print_ansible_run_banner("Provision Peer Cache Nodes");
+            match self.ansible_provisioner.provision_peer_cache_nodes(
+                &provision_options,
+                Some(genesis_multiaddr.clone()),
+                Some(genesis_network_contacts.clone()),
+            ) {
+                Ok(()) => {
+                    info!("Provisioned Peer Cache nodes");
+                    checkpoint.complete(DeployPhase::PeerCacheNodes)?;
+                }
+                Err(err) => {
+                    tracing::error!("Failed to provision Peer Cache nodes: {err}");
+                    if let Error::AnsibleHostsFailed { hosts, .. } = err {
+                        failed_hosts.extend(hosts);
+                    }
+                    node_provision_failed = true;
+                }
             }
         }
 
-        self.ansible_provisioner
-            .print_ansible_run_banner("Provision Normal Nodes");
-        match self.ansible_provisioner.provision_nodes(
-            &provision_options,
-            Some(genesis_multiaddr.clone()),
-            Some(genesis_network_contacts.clone()),
-            NodeType::Generic,
-        ) {
-            Ok(()) => {
-                println!("Provisioned normal nodes");
-            }
-            Err(err) => {
-                log::error!("Failed to provision normal nodes: {err}");
-                node_provision_failed = true;
+        if !checkpoint.is_complete(DeployPhase::Nodes) {
+            let _phase = checkpoint.start(DeployPhase::Nodes).entered();
+            self.ansible_provisioner
+                .print_ansible_run_banner("Provision Normal Nodes");
+            match self.ansible_provisioner.provision_nodes(
+                &provision_options,
+                Some(genesis_multiaddr.clone()),
+                Some(genesis_network_contacts.clone()),
+                NodeType::Generic,
+            ) {
+                Ok(()) => {
+                    info!("Provisioned normal nodes");
+                    checkpoint.complete(DeployPhase::Nodes)?;
+                }
+                Err(err) => {
+                    tracing::error!("Failed to provision normal nodes: {err}");
+                    if let Error::AnsibleHostsFailed { hosts, .. } = err {
+                        failed_hosts.extend(hosts);
+                    }
+                    node_provision_failed = true;
+                }
             }
         }
 
-        if should_provision_private_nodes {
+        if should_provision_private_nodes && !checkpoint.is_complete(DeployPhase::PrivateNodes) {
+            let phase = checkpoint.start(DeployPhase::PrivateNodes);
             let private_nodes = self
                 .ansible_provisioner
                 .ansible_runner
                 .get_inventory(AnsibleInventoryType::PrivateNodes, true)
                 .map_err(|err| {
-                    println!("Failed to obtain the inventory of private node: {err:?}");
+                    error!("Failed to obtain the inventory of private node: {err:?}");
                     err
                 })?;
 
             provision_options.private_node_vms = private_nodes;
-            self.ansible_provisioner
-                .print_ansible_run_banner("Provision NAT Gateway");
-            self.ansible_provisioner
-                .provision_nat_gateway(&provision_options)
-                .map_err(|err| {
-                    println!("Failed to provision NAT gateway {err:?}");
-                    err
-                })?;
+            let shards = self
+                .get_nat_gateway_shards()
+                .instrument(phase.clone())
+                .await?;
+            self.record_nat_gateway_shards(&shards)
+                .instrument(phase.clone())
+                .await?;
+            // There is nothing else to wait for in this phase.
+            let _phase = phase.entered();
+            if !checkpoint.is_complete(DeployPhase::NatGateway) {
+                let _phase = checkpoint.start(DeployPhase::NatGateway).entered();
+                self.ansible_provisioner
+                    .print_ansible_run_banner("Provision NAT Gateway");
+                self.ansible_provisioner
+                    .provision_nat_gateway(&provision_options, &shards)
+                    .map_err(|err| {
+                        error!("Failed to provision NAT gateway {err:?}");
+                        err
+                    })?;
+                checkpoint.complete(DeployPhase::NatGateway)?;
+            }
 
             self.ansible_provisioner
                 .print_ansible_run_banner("Provision Private Nodes");
             match self.ansible_provisioner.provision_private_nodes(
                 &mut provision_options,
+                &shards,
                 Some(genesis_multiaddr.clone()),
                 Some(genesis_network_contacts.clone()),
             ) {
                 Ok(()) => {
-                    println!("Provisioned private nodes");
+                    info!("Provisioned private nodes");
+                    checkpoint.complete(DeployPhase::PrivateNodes)?;
                 }
                 Err(err) => {
-                    log::error!("Failed to provision private nodes: {err}");
+                    tracing::error!("Failed to provision private nodes: {err}");
+                    if let Error::AnsibleHostsFailed { hosts, .. } = err {
+                        failed_hosts.extend(hosts);
+                    }
                     node_provision_failed = true;
                 }
             }
         }
 
-        if options.current_inventory.is_empty() {
+        if !options.node_restart_policy.is_empty()
+            && !checkpoint.is_complete(DeployPhase::RestartPolicy)
+        {
+            let _phase = checkpoint.start(DeployPhase::RestartPolicy).entered();
+            self.ansible_provisioner
+                .print_ansible_run_banner("Apply Node Restart Policy");
+            match self.apply_restart_policy(&options.node_restart_policy, None, None) {
+                Ok(()) => {
+                    info!("Applied the restart policy to the node services");
+                    checkpoint.complete(DeployPhase::RestartPolicy)?;
+                }
+                Err(err) => {
+                    tracing::error!("Failed to apply the node restart policy: {err}");
+                    node_provision_failed = true;
+                }
+            }
+        }
+
+        if let Some(auth_token) = &options.rpc_proxy_auth_token {
+            if !checkpoint.is_complete(DeployPhase::RpcProxy) {
+                let _phase = checkpoint.start(DeployPhase::RpcProxy).entered();
+                self.ansible_provisioner
+                    .print_ansible_run_banner("Provision RPC Proxy");
+                match self
+                    .ansible_provisioner
+                    .provision_rpc_proxy(auth_token, options.rpc_proxy_acme_email.as_deref())
+                {
+                    Ok(()) => {
+                        info!("Provisioned the RPC proxy on the node VMs");
+                        checkpoint.complete(DeployPhase::RpcProxy)?;
+                    }
+                    Err(err) => {
+                        tracing::error!("Failed to provision the RPC proxy: {err}");
+                        node_provision_failed = true;
+                    }
+                }
+            }
+        }
+
+        if options.wireguard && !checkpoint.is_complete(DeployPhase::WireGuard) {
+            let _phase = checkpoint.start(DeployPhase::WireGuard).entered();
+            self.ansible_provisioner
+                .print_ansible_run_banner("Set Up WireGuard Overlay");
+            let config_path = self.setup_wireguard().map_err(|err| {
+                error!("Failed to set up the WireGuard overlay {err:?}");
+                err
+            })?;
+            info!(
+                "Bring the overlay up on this machine with: sudo wg-quick up {}",
+                config_path.to_string_lossy()
+            );
+            checkpoint.complete(DeployPhase::WireGuard)?;
+        }
+
+        // When resuming, the inventory will not be empty, because nodes were already deployed by
+        // the previous run, so the checkpoint determines whether the uploaders are needed.
+        if (options.current_inventory.is_empty() || options.resume)
+            && !checkpoint.is_complete(DeployPhase::Uploaders)
+        {
+            let phase = checkpoint.start(DeployPhase::Uploaders);
             self.ansible_provisioner
                 .print_ansible_run_banner("Provision Uploaders");
             self.ansible_provisioner
@@ -287,22 +905,149 @@ impl TestnetDeployer {
                     Some(genesis_multiaddr.clone()),
                     Some(genesis_network_contacts.clone()),
                 )
+                .instrument(phase)
                 .await
                 .map_err(|err| {
-                    println!("Failed to provision uploaders {err:?}");
+                    error!("Failed to provision uploaders {err:?}");
+                    err
+                })?;
+            checkpoint.complete(DeployPhase::Uploaders)?;
+        }
+
+        if options.auditor_vm_count.is_some_and(|count| count > 0)
+            && !checkpoint.is_complete(DeployPhase::Auditors)
+        {
+            let _phase = checkpoint.start(DeployPhase::Auditors).entered();
+            self.ansible_provisioner
+                .print_ansible_run_banner("Provision Auditors");
+            self.ansible_provisioner
+                .provision_auditors(&provision_options, &genesis_multiaddr)
+                .map_err(|err| {
+                    error!("Failed to provision auditors {err:?}");
+                    err
+                })?;
+            checkpoint.complete(DeployPhase::Auditors)?;
+        }
+
+        if options.setup_monitoring && !checkpoint.is_complete(DeployPhase::Monitoring) {
+            let _phase = checkpoint.start(DeployPhase::Monitoring).entered();
+            self.ansible_provisioner
+                .print_ansible_run_banner("Provision Monitoring");
+            self.ansible_provisioner
+                .provision_monitoring(&provision_options)
+                .map_err(|err| {
+                    error!("Failed to provision monitoring {err:?}");
+                    err
+                })?;
+            checkpoint.complete(DeployPhase::Monitoring)?;
+        }
+
+        if let Some(sysstat_duration) = options.sysstat_duration {
+            self.ansible_provisioner
+                .print_ansible_run_banner("Start Sysstat Collection");
+            self.start_sysstat_collection(sysstat_duration)
+                .map_err(|err| {
+                    error!("Failed to start the sysstat collection {err:?}");
                     err
                 })?;
         }
 
+        self.ansible_provisioner
+            .print_ansible_run_banner("Reconcile Node Counts");
+        let reconciliation = match get_environment_details(&options.name, &self.s3_repository)
+            .await
+            .and_then(|details| self.reconcile_node_counts(&details))
+        {
+            Ok(reconciliation) => Some(reconciliation),
+            Err(err) => {
+                error!("Failed to reconcile node counts: {err:?}");
+                None
+            }
+        };
+        if let Some(reconciliation) = reconciliation {
+            summary.peer_count = Some(reconciliation.running_total);
+            reconciliation.print();
+            if !reconciliation.is_complete() {
+                warn!(
+                    "{} VMs are running fewer nodes than expected",
+                    reconciliation.shortfalls.len()
+                );
+            }
+        }
+
+        let report = ProvisioningReport::from_recorded_results(&options.name);
+        if !report.results.is_empty() {
+            let report_path = report.save()?;
+            info!(
+                "Provisioning report saved to {}",
+                report_path.to_string_lossy()
+            );
+        }
+
         if node_provision_failed {
-            println!();
-            println!("{}", "WARNING!".yellow());
-            println!("Some nodes failed to provision without error.");
-            println!("This usually means a small number of nodes failed to start on a few VMs.");
-            println!("However, most of the time the deployment will still be usable.");
-            println!("See the output from Ansible to determine which VMs had failures.");
+            warn!(
+                "Some nodes failed to provision without error. This usually means a small number \
+                of nodes failed to start on a few VMs. However, most of the time the deployment \
+                will still be usable."
+            );
+            if failed_hosts.is_empty() {
+                warn!("See the output from Ansible to determine which VMs had failures.");
+            } else {
+                self.print_failed_hosts(&failed_hosts).await;
+            }
+            warn!("Use the --resume argument to retry the phases that failed.");
+        } else {
+            DeployCheckpoint::clear(&options.name)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color_eyre::Result;
+
+    fn get_checkpoint() -> DeployCheckpoint {
+        // A dry run checkpoint is not saved, so nothing is written to the data directory.
+        DeployCheckpoint {
+            dry_run: true,
+            name: "alpha".to_string(),
+            ..Default::default()
         }
+    }
+
+    #[test]
+    fn test_is_complete_does_not_start_the_phase() -> Result<()> {
+        let checkpoint = get_checkpoint();
+        assert!(!checkpoint.is_complete(DeployPhase::Build));
+        assert!(!checkpoint.is_complete(DeployPhase::Build));
+        assert!(checkpoint.incomplete_phases().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_completed_phases_are_not_reported_as_incomplete() -> Result<()> {
+        let mut checkpoint = get_checkpoint();
+        checkpoint.start(DeployPhase::Infra);
+        checkpoint.start(DeployPhase::Genesis);
+        checkpoint.complete(DeployPhase::Infra)?;
+
+        assert!(checkpoint.is_complete(DeployPhase::Infra));
+        assert!(!checkpoint.is_complete(DeployPhase::Genesis));
+        assert_eq!(vec![DeployPhase::Genesis], checkpoint.incomplete_phases());
+        Ok(())
+    }
 
+    #[test]
+    fn test_completing_a_phase_that_was_not_started() -> Result<()> {
+        // The build phase is completed without being started when the binaries are restored from
+        // the build cache.
+        let mut checkpoint = get_checkpoint();
+        checkpoint.complete(DeployPhase::Build)?;
+        assert!(checkpoint.is_complete(DeployPhase::Build));
+        assert!(checkpoint.incomplete_phases().is_empty());
         Ok(())
     }
 }
@@ -5,12 +5,14 @@
 // Please see the LICENSE file for more details.
 
 use crate::error::{Error, Result};
-use log::debug;
+use chrono::{DateTime, Utc};
 use reqwest::Client;
-use std::{net::Ipv4Addr, str::FromStr};
+use std::{collections::HashMap, net::Ipv4Addr, str::FromStr};
+use tracing::debug;
 
 pub const DIGITAL_OCEAN_API_BASE_URL: &str = "https://api.digitalocean.com";
 pub const DIGITAL_OCEAN_API_PAGE_SIZE: usize = 200;
+pub const DIGITAL_OCEAN_CLOUD_BASE_URL: &str = "https://cloud.digitalocean.com";
 
 pub struct Droplet {
     pub id: usize,
@@ -18,6 +20,29 @@ pub struct Droplet {
     pub ip_address: Ipv4Addr,
 }
 
+/// An action performed on a droplet, e.g., a power cycle or a resize, including those initiated by
+/// Digital Ocean itself.
+pub struct DropletAction {
+    pub action_type: String,
+    pub completed_at: Option<String>,
+    pub started_at: String,
+    pub status: String,
+}
+
+/// The price of a droplet, as it is needed to work out what it has cost so far.
+pub struct DropletBilling {
+    pub created_at: DateTime<Utc>,
+    pub name: String,
+    pub price_hourly: f64,
+}
+
+/// Get the links to the page for a droplet and its web console in the Digital Ocean control panel.
+pub fn get_droplet_links(droplet_id: usize) -> (String, String) {
+    let droplet_url = format!("{DIGITAL_OCEAN_CLOUD_BASE_URL}/droplets/{droplet_id}");
+    let console_url = format!("{droplet_url}/terminal/ui/");
+    (droplet_url, console_url)
+}
+
 pub struct DigitalOceanClient {
     pub base_url: String,
     pub access_token: String,
@@ -25,6 +50,252 @@ pub struct DigitalOceanClient {
 }
 
 impl DigitalOceanClient {
+    /// Check the access token is accepted by the API.
+    pub async fn check_access_token(&self) -> Result<()> {
+        let response = Client::new()
+            .get(format!("{}/v2/account", self.base_url))
+            .header("Authorization", format!("Bearer {}", self.access_token))
+            .send()
+            .await?;
+        if response.status().as_u16() == 401 {
+            debug!("Error response body: {}", response.text().await?);
+            return Err(Error::DigitalOceanUnauthorized);
+        } else if !response.status().is_success() {
+            let status_code = response.status().as_u16();
+            let response_body = response.text().await?;
+            return Err(Error::DigitalOceanUnexpectedResponse(
+                status_code,
+                response_body,
+            ));
+        }
+        Ok(())
+    }
+
+    /// List the most recent actions on a droplet, newest first.
+    pub async fn list_droplet_actions(
+        &self,
+        droplet_id: usize,
+        count: usize,
+    ) -> Result<Vec<DropletAction>> {
+        let url = format!(
+            "{}/v2/droplets/{droplet_id}/actions?page=1&per_page={count}",
+            self.base_url
+        );
+        debug!("Executing droplet actions request with {url}");
+        let response = Client::new()
+            .get(url)
+            .header("Authorization", format!("Bearer {}", self.access_token))
+            .send()
+            .await?;
+        if response.status().as_u16() == 401 {
+            debug!("Error response body: {}", response.text().await?);
+            return Err(Error::DigitalOceanUnauthorized);
+        } else if !response.status().is_success() {
+            let status_code = response.status().as_u16();
+            let response_body = response.text().await?;
+            return Err(Error::DigitalOceanUnexpectedResponse(
+                status_code,
+                response_body,
+            ));
+        }
+
+        let json: serde_json::Value = serde_json::from_str(&response.text().await?)?;
+        let action_array =
+            json["actions"]
+                .as_array()
+                .ok_or(Error::MalformedDigitalOceanApiRespose(
+                    "actions".to_string(),
+                ))?;
+        let mut actions = Vec::new();
+        for action_json in action_array {
+            let get_str = |key: &str| -> Result<String> {
+                Ok(action_json[key]
+                    .as_str()
+                    .ok_or(Error::MalformedDigitalOceanApiRespose(key.to_string()))?
+                    .to_string())
+            };
+            actions.push(DropletAction {
+                action_type: get_str("type")?,
+                completed_at: action_json["completed_at"].as_str().map(|s| s.to_string()),
+                started_at: get_str("started_at")?,
+                status: get_str("status")?,
+            });
+        }
+        // The API returns the newest actions first, but the order is not documented.
+        actions.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+        Ok(actions)
+    }
+
+    /// Get the hourly price of each droplet size, keyed by its slug.
+    pub async fn list_size_prices(&self) -> Result<HashMap<String, f64>> {
+        let json = self
+            .get_json(&format!(
+                "{}/v2/sizes?page=1&per_page={}",
+                self.base_url, self.page_size
+            ))
+            .await?;
+        let size_array = json["sizes"]
+            .as_array()
+            .ok_or(Error::MalformedDigitalOceanApiRespose("sizes".to_string()))?;
+        let mut prices = HashMap::new();
+        for size_json in size_array {
+            if let (Some(slug), Some(price_hourly)) = (
+                size_json["slug"].as_str(),
+                size_json["price_hourly"].as_f64(),
+            ) {
+                prices.insert(slug.to_string(), price_hourly);
+            }
+        }
+        Ok(prices)
+    }
+
+    /// List the creation time and hourly price of the droplets with a tag.
+    pub async fn list_droplet_billing(&self, tag: &str) -> Result<Vec<DropletBilling>> {
+        let mut droplets = Vec::new();
+        let mut page = 1;
+        loop {
+            let json = self
+                .get_json(&format!(
+                    "{}/v2/droplets?tag_name={tag}&page={page}&per_page={}",
+                    self.base_url, self.page_size
+                ))
+                .await?;
+            let droplet_array =
+                json["droplets"]
+                    .as_array()
+                    .ok_or(Error::MalformedDigitalOceanApiRespose(
+                        "droplets".to_string(),
+                    ))?;
+            for droplet_json in droplet_array {
+                let name = droplet_json["name"]
+                    .as_str()
+                    .ok_or(Error::MalformedDigitalOceanApiRespose("name".to_string()))?;
+                let created_at = droplet_json["created_at"]
+                    .as_str()
+                    .and_then(|created_at| created_at.parse::<DateTime<Utc>>().ok())
+                    .ok_or(Error::MalformedDigitalOceanApiRespose(
+                        "created_at".to_string(),
+                    ))?;
+                let price_hourly = droplet_json["size"]["price_hourly"].as_f64().ok_or(
+                    Error::MalformedDigitalOceanApiRespose("price_hourly".to_string()),
+                )?;
+                droplets.push(DropletBilling {
+                    created_at,
+                    name: name.to_string(),
+                    price_hourly,
+                });
+            }
+            if json["links"]["pages"]["next"].is_string() {
+                page += 1;
+            } else {
+                break;
+            }
+        }
+        Ok(droplets)
+    }
+
+    /// Get the usage for the whole account in the current billing period, in US dollars.
+    pub async fn get_month_to_date_usage(&self) -> Result<String> {
+        let json = self
+            .get_json(&format!("{}/v2/customers/my/balance", self.base_url))
+            .await?;
+        Ok(json["month_to_date_usage"]
+            .as_str()
+            .ok_or(Error::MalformedDigitalOceanApiRespose(
+                "month_to_date_usage".to_string(),
+            ))?
+            .to_string())
+    }
+
+    /// Get the ID of the cloud firewall with the given name, if there is one.
+    pub async fn find_firewall(&self, name: &str) -> Result<Option<String>> {
+        let mut page = 1;
+        loop {
+            let json = self
+                .get_json(&format!(
+                    "{}/v2/firewalls?page={page}&per_page={}",
+                    self.base_url, self.page_size
+                ))
+                .await?;
+            let firewall_array =
+                json["firewalls"]
+                    .as_array()
+                    .ok_or(Error::MalformedDigitalOceanApiRespose(
+                        "firewalls".to_string(),
+                    ))?;
+            for firewall_json in firewall_array {
+                if firewall_json["name"].as_str() == Some(name) {
+                    let id = firewall_json["id"]
+                        .as_str()
+                        .ok_or(Error::MalformedDigitalOceanApiRespose("id".to_string()))?;
+                    return Ok(Some(id.to_string()));
+                }
+            }
+            if json["links"]["pages"]["next"].is_string() {
+                page += 1;
+            } else {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Create a cloud firewall from a firewall object, as described in the API documentation.
+    pub async fn create_firewall(&self, firewall: &serde_json::Value) -> Result<()> {
+        let url = format!("{}/v2/firewalls", self.base_url);
+        self.send_json(Client::new().post(url).json(firewall)).await
+    }
+
+    /// Replace the rules and tags of an existing cloud firewall.
+    pub async fn update_firewall(&self, id: &str, firewall: &serde_json::Value) -> Result<()> {
+        let url = format!("{}/v2/firewalls/{id}", self.base_url);
+        self.send_json(Client::new().put(url).json(firewall)).await
+    }
+
+    pub async fn delete_firewall(&self, id: &str) -> Result<()> {
+        let url = format!("{}/v2/firewalls/{id}", self.base_url);
+        self.send_json(Client::new().delete(url)).await
+    }
+
+    async fn send_json(&self, request: reqwest::RequestBuilder) -> Result<()> {
+        let response = request
+            .header("Authorization", format!("Bearer {}", self.access_token))
+            .send()
+            .await?;
+        if response.status().as_u16() == 401 {
+            debug!("Error response body: {}", response.text().await?);
+            return Err(Error::DigitalOceanUnauthorized);
+        } else if !response.status().is_success() {
+            let status_code = response.status().as_u16();
+            let response_body = response.text().await?;
+            return Err(Error::DigitalOceanUnexpectedResponse(
+                status_code,
+                response_body,
+            ));
+        }
+        Ok(())
+    }
+
+    async fn get_json(&self, url: &str) -> Result<serde_json::Value> {
+        debug!("Executing request with {url}");
+        let response = Client::new()
+            .get(url)
+            .header("Authorization", format!("Bearer {}", self.access_token))
+            .send()
+            .await?;
+        if response.status().as_u16() == 401 {
+            debug!("Error response body: {}", response.text().await?);
+            return Err(Error::DigitalOceanUnauthorized);
+        } else if !response.status().is_success() {
+            let status_code = response.status().as_u16();
+            let response_body = response.text().await?;
+            return Err(Error::DigitalOceanUnexpectedResponse(
+                status_code,
+                response_body,
+            ));
+        }
+        Ok(serde_json::from_str(&response.text().await?)?)
+    }
+
     pub async fn list_droplets(&self, skip_if_no_ip: bool) -> Result<Vec<Droplet>> {
         let client = Client::new();
         let mut has_next_page = true;
@@ -0,0 +1,98 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    ansible::{extra_vars, inventory::AnsibleInventoryType, AnsiblePlaybook},
+    error::Result,
+    get_bootstrap_cache_url, get_genesis_multiaddr,
+    infra::InfraRunOptions,
+    inventory::DeploymentInventory,
+    TestnetDeployer,
+};
+use tracing::debug;
+
+/// The bucket the uploaders publish their manifests to. Each uploader service writes its own
+/// manifest, with a line for each file it uploaded, containing the address and the SHA-256 of the
+/// content.
+pub const UPLOAD_MANIFEST_BUCKET_NAME: &str = "sn-testnet";
+pub const UPLOAD_MANIFEST_BUCKET_REGION: &str = "eu-west-2";
+
+pub fn get_upload_manifest_prefix(environment_name: &str) -> String {
+    format!("upload-manifests/{environment_name}")
+}
+
+#[derive(Clone)]
+pub struct DownloaderDeployOptions {
+    pub ant_version: String,
+    pub current_inventory: DeploymentInventory,
+    pub downloaders_per_vm: u16,
+    pub vm_count: u16,
+}
+
+impl TestnetDeployer {
+    /// Create the downloader VMs and provision the download verifiers on them.
+    ///
+    /// Each verifier continuously downloads random files from the upload manifests and checks
+    /// their content against the recorded checksums. The result of each download is written to a
+    /// metrics file in the home directory of the verifier's user.
+    pub async fn deploy_downloaders(&self, options: &DownloaderDeployOptions) -> Result<()> {
+        let mut infra_run_options = InfraRunOptions::generate_existing(
+            &options.current_inventory.name,
+            &self.terraform_runner,
+            &options.current_inventory.environment_details,
+        )
+        .await?;
+        infra_run_options.downloader_vm_count = Some(options.vm_count);
+        self.create_or_update_infra(&infra_run_options)
+            .map_err(|err| {
+                println!("Failed to create infra {err:?}");
+                err
+            })?;
+
+        let (genesis_multiaddr, genesis_ip_addr) =
+            get_genesis_multiaddr(&self.ansible_provisioner.ansible_runner, &self.ssh_client)
+                .await
+                .map_err(|err| {
+                    println!("Failed to get genesis multiaddr {err:?}");
+                    err
+                })?;
+        let network_contacts_url = get_bootstrap_cache_url(&genesis_ip_addr);
+        debug!("Retrieved initial peer {genesis_multiaddr} and initial network contacts {network_contacts_url}");
+
+        self.ansible_provisioner
+            .print_ansible_run_banner("Provision Download Verifiers");
+        self.ansible_provisioner.ansible_runner.run_playbook(
+            AnsiblePlaybook::DownloadVerifiers,
+            AnsibleInventoryType::Downloaders,
+            Some(extra_vars::build_download_verifiers_extra_vars_doc(
+                &self.cloud_provider.to_string(),
+                options,
+                self.ansible_provisioner.get_artifact_proxy_url()?,
+                Some(genesis_multiaddr),
+                Some(network_contacts_url),
+            )?),
+        )?;
+        Ok(())
+    }
+
+    pub fn start_downloaders(&self) -> Result<()> {
+        self.ansible_provisioner.ansible_runner.run_playbook(
+            AnsiblePlaybook::StartDownloadVerifiers,
+            AnsibleInventoryType::Downloaders,
+            None,
+        )?;
+        Ok(())
+    }
+
+    pub fn stop_downloaders(&self) -> Result<()> {
+        self.ansible_provisioner.ansible_runner.run_playbook(
+            AnsiblePlaybook::StopDownloadVerifiers,
+            AnsibleInventoryType::Downloaders,
+            None,
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    error::{Error, Result},
+    inventory::{NodeVirtualMachine, VirtualMachine},
+    DeploymentInventory, InfraRunOptions, TestnetDeployer,
+};
+use std::time::Duration;
+use tracing::debug;
+
+#[derive(Clone)]
+pub struct DownscaleOptions {
+    pub current_inventory: DeploymentInventory,
+    pub desired_node_vm_count: Option<u16>,
+    pub desired_peer_cache_node_vm_count: Option<u16>,
+    pub interval: Duration,
+    pub plan: bool,
+}
+
+impl TestnetDeployer {
+    /// Reduce the number of VMs in an environment.
+    ///
+    /// Terraform removes the VMs with the highest indexes when a count is reduced, so those are the
+    /// VMs selected for removal. Before Terraform runs, the node services on those VMs are stopped
+    /// to give the network a chance to replicate their data.
+    pub async fn downscale(&self, options: &DownscaleOptions) -> Result<()> {
+        let current_peer_cache_node_vm_count =
+            options.current_inventory.peer_cache_node_vms.len() as u16;
+        let desired_peer_cache_node_vm_count = options
+            .desired_peer_cache_node_vm_count
+            .unwrap_or(current_peer_cache_node_vm_count);
+        if desired_peer_cache_node_vm_count > current_peer_cache_node_vm_count {
+            return Err(Error::InvalidDownscaleDesiredPeerCacheVmCount);
+        }
+        debug!("Using {desired_peer_cache_node_vm_count} for desired Peer Cache node VM count");
+
+        let current_node_vm_count = options.current_inventory.node_vms.len() as u16;
+        let desired_node_vm_count = options
+            .desired_node_vm_count
+            .unwrap_or(current_node_vm_count);
+        if desired_node_vm_count > current_node_vm_count {
+            return Err(Error::InvalidDownscaleDesiredNodeVmCount);
+        }
+        debug!("Using {desired_node_vm_count} for desired node VM count");
+
+        let mut vms_to_drain = get_highest_numbered_vms(
+            &options.current_inventory.peer_cache_node_vms,
+            (current_peer_cache_node_vm_count - desired_peer_cache_node_vm_count) as usize,
+        );
+        vms_to_drain.extend(get_highest_numbered_vms(
+            &options.current_inventory.node_vms,
+            (current_node_vm_count - desired_node_vm_count) as usize,
+        ));
+
+        if options.plan {
+            let vars = vec![
+                (
+                    "peer_cache_node_vm_count".to_string(),
+                    desired_peer_cache_node_vm_count.to_string(),
+                ),
+                (
+                    "node_vm_count".to_string(),
+                    desired_node_vm_count.to_string(),
+                ),
+            ];
+            println!("The following VMs would be drained and removed:");
+            for vm in vms_to_drain.iter() {
+                println!("{}: {}", vm.name, vm.public_ip_addr);
+            }
+            self.plan(Some(vars), &options.current_inventory.get_tfvars_filename())?;
+            return Ok(());
+        }
+
+        if vms_to_drain.is_empty() {
+            println!("The desired counts match the current deployment. Nothing to do.");
+            return Ok(());
+        }
+
+        self.ansible_provisioner
+            .print_ansible_run_banner("Drain Nodes");
+        for vm in vms_to_drain.iter() {
+            println!("{}: {}", vm.name, vm.public_ip_addr);
+        }
+        self.ansible_provisioner
+            .drain_nodes(
+                &options.current_inventory.name,
+                options.interval,
+                &vms_to_drain,
+            )
+            .map_err(|err| {
+                println!("Failed to drain nodes {err:?}");
+                err
+            })?;
+
+        let mut infra_run_options = InfraRunOptions::generate_existing(
+            &options.current_inventory.name,
+            &self.terraform_runner,
+            &options.current_inventory.environment_details,
+        )
+        .await?;
+        infra_run_options.peer_cache_node_vm_count = Some(desired_peer_cache_node_vm_count);
+        infra_run_options.node_vm_count = Some(desired_node_vm_count);
+        self.create_or_update_infra(&infra_run_options)
+            .map_err(|err| {
+                println!("Failed to update infra {err:?}");
+                err
+            })?;
+
+        Ok(())
+    }
+}
+
+/// Select the `count` VMs with the highest numeric suffix in their names, e.g., `beta-node-12`.
+fn get_highest_numbered_vms(node_vms: &[NodeVirtualMachine], count: usize) -> Vec<VirtualMachine> {
+    let mut vms: Vec<(u32, VirtualMachine)> = node_vms
+        .iter()
+        .map(|node_vm| {
+            let number = node_vm
+                .vm
+                .name
+                .rsplit('-')
+                .next()
+                .and_then(|suffix| suffix.parse::<u32>().ok())
+                .unwrap_or(0);
+            (number, node_vm.vm.clone())
+        })
+        .collect();
+    vms.sort_by_key(|(number, _)| std::cmp::Reverse(*number));
+    vms.into_iter().take(count).map(|(_, vm)| vm).collect()
+}
@@ -6,6 +6,7 @@
 
 use crate::{ansible::inventory::AnsibleInventoryType, NodeType};
 use evmlib::contract::network_token;
+use std::path::PathBuf;
 use thiserror::Error;
 use tokio::task::JoinError;
 
@@ -16,16 +17,35 @@ pub type Result<T, E = Error> = std::result::Result<T, E>;
 pub enum Error {
     #[error(transparent)]
     AddrParseError(#[from] std::net::AddrParseError),
+    #[error("These hosts failed {playbook}: {}", hosts.join(", "))]
+    AnsibleHostsFailed {
+        hosts: Vec<String>,
+        playbook: String,
+    },
     #[error("Could not determine content length for asset")]
     AssetContentLengthUndetermined,
+    #[error("The AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY variables must be set")]
+    AwsCredentialsNotSupplied,
     #[error(transparent)]
     AwsS3Error(#[from] Box<aws_sdk_s3::Error>),
+    #[error(
+        "The template would affect {affected} of {total} nodes, which is more than the {limit_pct}% limit"
+    )]
+    ChaosBlastRadiusExceeded {
+        affected: usize,
+        limit_pct: u8,
+        total: usize,
+    },
+    #[error("There are no running nodes to apply the fault to")]
+    ChaosTargetsUnavailable,
     #[error("The {0} environment variable must be set to use your cloud provider")]
     CloudProviderCredentialsNotSupplied(String),
     #[error("The {0} cloud provider is not supported yet")]
     CloudProviderNotSupported(String),
     #[error("The home data directory could not be retrieved")]
     CouldNotRetrieveDataDirectory,
+    #[error("Failed to copy '{0}' in '{1}'")]
+    CopyS3ObjectError(String, String),
     #[error("Failed to delete '{0}' from '{1}")]
     DeleteS3ObjectError(String, String),
     #[error("Authorization failed for the Digital Ocean API")]
@@ -40,6 +60,8 @@ pub enum Error {
     EnvironmentDetailsNotFound(String),
     #[error("The '{0}' environment does not exist")]
     EnvironmentDoesNotExist(String),
+    #[error("The '{name}' environment is locked by {holder}")]
+    EnvironmentLocked { name: String, holder: String },
     #[error("The environment name is required")]
     EnvironmentNameRequired,
     #[error("Could not convert '{0}' to an EnvironmentType variant")]
@@ -61,16 +83,88 @@ pub enum Error {
     FilenameNotRetrieved,
     #[error(transparent)]
     FsExtraError(#[from] fs_extra::error::Error),
+    #[error("There is no backup of the genesis node for the {0} environment")]
+    GenesisBackupNotFound(String),
     #[error("Could not obtain Genesis multiaddr")]
     GenesisListenAddress,
     #[error("To provision the remaining nodes the multiaddr of the genesis node must be supplied")]
     GenesisMultiAddrNotSupplied,
+    #[error("Failed to restore the genesis node backup: {0}")]
+    GenesisRestoreFailed(String),
     #[error("Failed to retrieve '{0}' from '{1}")]
     GetS3ObjectError(String, String),
+    #[error("The Grafana admin password for the '{0}' environment is not in the data directory. The monitoring VM may still use the default password, so it must be provisioned again from this machine.")]
+    GrafanaAdminPasswordNotFound(String),
+    #[error("The Grafana admin password for the '{0}' environment is the default. Provision the monitoring VM again to generate a new one.")]
+    GrafanaDefaultAdminPassword(String),
+    #[error("Failed to retrieve the commit for the '{branch}' branch from GitHub: {error}")]
+    GitHubCommitLookupFailed { branch: String, error: String },
+    #[error("Failed to check whether '{object_key}' exists in S3: {error}")]
+    HeadS3ObjectError { object_key: String, error: String },
     #[error(transparent)]
     InquireError(#[from] inquire::InquireError),
+    #[error("The desired node VM count is larger than the current count. This is invalid for a downscale operation.")]
+    InvalidDownscaleDesiredNodeVmCount,
+    #[error("The desired Peer Cache VM count is larger than the current count. This is invalid for a downscale operation.")]
+    InvalidDownscaleDesiredPeerCacheVmCount,
+    #[error("The architecture '{0}' is invalid. Valid values are 'x86_64' or 'aarch64'")]
+    InvalidArchitecture(String),
+    #[error("The artifact repository '{0}' is invalid. Valid values are 's3', 's3:<region>[:<bucket-prefix>]', 'spaces:<region>[:<bucket-prefix>]' or an HTTP URL")]
+    InvalidArtifactRepository(String),
+    #[error("The bandwidth class '{0}' is invalid. Valid values are 'standard' or 'premium'")]
+    InvalidBandwidthClass(String),
+    #[error("The bandwidth rate '{0}' is invalid. It must be a number followed by 'kbit', 'mbit' or 'gbit', e.g., '10mbit'")]
+    InvalidBandwidthRate(String),
+    #[error("The blast radius limit must be between 1 and 100 percent, not {0}")]
+    InvalidBlastRadius(u8),
+    #[error("The chaos template duration must be greater than zero")]
+    InvalidChaosDuration,
+    #[error("The digest window '{0}' is not valid. It must be a whole number of minutes.")]
+    InvalidDigestWindow(String),
+    #[error("The disk fill percentage must be between 1 and 100, not {0}")]
+    InvalidDiskFillPct(u8),
+    #[error("The environment name '{name}' is invalid: {reason}")]
+    InvalidEnvironmentName { name: String, reason: String },
+    #[error("The environment variable '{name}' is invalid: {reason}")]
+    InvalidEnvironmentVariable { name: String, reason: String },
+    #[error(
+        "The faucet amount '{0}' is invalid. It must be a number of tokens greater than zero."
+    )]
+    InvalidFaucetAmount(String),
+    #[error("The join rate of {0} nodes per minute is invalid. It must be greater than zero.")]
+    InvalidJoinRate(u16),
+    #[error("The network conditions are invalid: {0}")]
+    InvalidNetworkCondition(String),
+    #[error("The notification mode '{0}' is not valid. Use 'immediate' or 'digest'.")]
+    InvalidNotificationMode(String),
+    #[error("Cannot split {1} VMs into {0} partition groups. At least 2 groups are required, and no more than the number of VMs")]
+    InvalidPartitionGroupCount(usize, usize),
     #[error("The node type '{0:?}' is not supported")]
     InvalidNodeType(NodeType),
+    #[error("Shard {0} does not exist. There are {1} NAT gateway shards, numbered from 1.")]
+    InvalidNatGatewayShard(usize, usize),
+    #[error("The NAT type '{0}' is not supported. Use 'full-cone', 'restricted' or 'symmetric'.")]
+    InvalidNatType(String),
+    #[error("The provision parallelism must be greater than zero")]
+    InvalidProvisionParallelism,
+    #[error("The reachability mode '{0}' is invalid. Valid values are 'direct', 'relay-client' or 'upnp'")]
+    InvalidReachabilityMode(String),
+    #[error("The release channel '{0}' is invalid. Valid values are 'latest', 'stable', 'rc' or 'nightly'")]
+    InvalidReleaseChannel(String),
+    #[error("The replay speed must be greater than zero")]
+    InvalidReplaySpeed,
+    #[error("The restart mode '{0}' is invalid. Valid values are 'always' or 'on-failure'")]
+    InvalidRestartMode(String),
+    #[error("The sysstat collection duration must be greater than zero")]
+    InvalidSysstatDuration,
+    #[error("The Terraform provider mirror URL '{0}' must use HTTPS and end with a '/'")]
+    InvalidTerraformProviderMirrorUrl(String),
+    #[error("The Terraform output for the {name} VM has an invalid {field}: {value}")]
+    InvalidTerraformVmOutput {
+        field: String,
+        name: String,
+        value: String,
+    },
     #[error(
         "The '{0}' deployment type for the environment is not supported for upscaling uploaders"
     )]
@@ -95,6 +189,12 @@ pub enum Error {
     InvalidUpscaleDesiredUploaderVmCount,
     #[error("Options were used that are not applicable to a bootstrap deployment")]
     InvalidUpscaleOptionsForBootstrapDeployment,
+    #[error("The VM selector '{0}' is invalid. It should be like 'node-[5:10],peer-cache-node-1'")]
+    InvalidVmSelector(String),
+    #[error("The wallet address '{0}' is invalid. It must be hex encoded.")]
+    InvalidWalletAddress(String),
+    #[error("The webhook format '{0}' is not valid. Use 'slack', 'discord' or 'json'.")]
+    InvalidWebhookFormat(String),
     #[error(transparent)]
     Io(#[from] std::io::Error),
     #[error("Could not obtain IpDetails")]
@@ -103,6 +203,12 @@ pub enum Error {
     JoinError(#[from] JoinError),
     #[error("Failed to list objects in S3 bucket with prefix '{prefix}': {error}")]
     ListS3ObjectsError { prefix: String, error: String },
+    #[error(
+        "Local binaries are served by the artifact proxy, which is not enabled for this deployment"
+    )]
+    LocalBinariesRequireArtifactProxy,
+    #[error("The local binary at '{0}' does not exist")]
+    LocalBinaryNotFound(PathBuf),
     #[error("Could not configure logging: {0}")]
     LoggingConfiguration(String),
     #[error("Logs for a '{0}' testnet already exist")]
@@ -111,12 +217,18 @@ pub enum Error {
     LogsNotRetrievedError(String),
     #[error("The API response did not contain the expected '{0}' value")]
     MalformedDigitalOceanApiRespose(String),
+    #[error("Failed to push metrics to the Pushgateway: {0}")]
+    MetricsPushFailed(String),
     #[error("Could not convert from DeployOptions to ProvisionOptions: peer cache node count must have a value")]
     MissingPeerCacheNodeCount,
     #[error(
         "Could not convert from DeployOptions to ProvisionOptions: node count must have a value"
     )]
     MissingNodeCount,
+    #[error("The NAT gateway {0} already routes the shard")]
+    NatGatewayAlreadyRoutesShard(String),
+    #[error("The NAT gateway {0} was not found")]
+    NatGatewayNotFound(String),
     #[error("The NAT gateway VM was not supplied")]
     NatGatewayNotSupplied,
     #[error(transparent)]
@@ -125,18 +237,54 @@ pub enum Error {
     NoAuditorError,
     #[error("This deployment does not have a faucet. It may be a bootstrap deployment.")]
     NoFaucetError,
+    #[error("None of the other NAT gateways are healthy")]
+    NoHealthyNatGateway,
+    #[error("No sysstat samples could be retrieved from any of the node VMs")]
+    NoSysstatSamples,
     #[error("This deployment does not have any uploaders. It may be a bootstrap deployment.")]
     NoUploadersError,
     #[error("The node count for the provided custom vms are not equal")]
     NodeCountMismatch,
     #[error("Could not obtain a multiaddr from the node inventory")]
     NodeAddressNotFound,
+    #[error("The '{0}' service was not found in the node registry on {1}")]
+    NodeServiceNotFound(String, String),
+    #[error(
+        "The offline inventory at {0} does not exist. Run a command that retrieves the inventory \
+        without --offline-inventory to generate it."
+    )]
+    OfflineInventoryNotFound(PathBuf),
+    #[error(transparent)]
+    ParquetError(#[from] parquet::errors::ParquetError),
+    #[error("Failed to parse the playbook results at {path:?}: {error}")]
+    PlaybookResultsParseError { path: PathBuf, error: String },
     #[error("Failed to upload {0} to S3 bucket {1}")]
     PutS3ObjectError(String, String),
     #[error(transparent)]
     RegexError(#[from] regex::Error),
+    #[error("Failed to resolve the '{channel}' release channel: {error}")]
+    ReleaseChannelResolutionFailed { channel: String, error: String },
+    #[error("The trace could not be replayed: {0}")]
+    ReplayFailed(String),
+    #[error("The trace is invalid: {0}")]
+    ReplayTraceInvalid(String),
+    #[error("Failed to parse line {line} of the trace: {error}")]
+    ReplayTraceParseError { line: usize, error: String },
     #[error(transparent)]
     ReqwestError(#[from] reqwest::Error),
+    #[error(
+        "The RPC proxy auth token for '{0}' was not found. The environment must be upscaled from \
+        the machine it was deployed from."
+    )]
+    RpcProxyAuthTokenNotFound(String),
+    #[error("The RUN_SUMMARY_BUCKET_NAME variable was not set")]
+    RunSummaryBucketNameNotSupplied,
+    #[error("There is no run summary for the '{0}' environment")]
+    RunSummaryNotFound(String),
+    #[error(transparent)]
+    RusshError(#[from] russh::Error),
+    #[error(transparent)]
+    RusshKeysError(#[from] russh_keys::Error),
     #[error("Safe client command failed: {0}")]
     SafeCmdError(String),
     #[error("Failed to download the safe or safenode binary")]
@@ -151,8 +299,21 @@ pub enum Error {
     SetupError,
     #[error("The SLACK_WEBHOOK_URL variable was not set")]
     SlackWebhookUrlNotSupplied,
+    #[error("The smoke test failed: {0}")]
+    SmokeTestFailed(String),
+    #[error("The {0} snapshot does not exist")]
+    SnapshotNotFound(String),
+    #[error("The SSH key was not accepted for {0}")]
+    SshAuthenticationFailed(String),
     #[error("SSH command failed: {0}")]
     SshCommandFailed(String),
+    #[error("The SSH command on {ip_address} did not complete within {timeout_secs}s")]
+    SshCommandTimedOut {
+        ip_address: std::net::IpAddr,
+        timeout_secs: u64,
+    },
+    #[error("The limiter for the SSH sessions was closed")]
+    SshSessionLimiterClosed,
     #[error("Failed to obtain lock to update SSH settings")]
     SshSettingsRwLockError,
     #[error("After several retry attempts an SSH connection could not be established")]
@@ -161,6 +322,10 @@ pub enum Error {
     StripPrefixError(#[from] std::path::StripPrefixError),
     #[error(transparent)]
     TemplateError(#[from] indicatif::style::TemplateError),
+    #[error("Could not find the state key in the s3 backend block of {0}")]
+    TerraformBackendKeyNotFound(PathBuf),
+    #[error("Terraform output failed")]
+    TerraformOutputFailed,
     #[error("Terraform show failed")]
     TerraformShowFailed,
     #[error("Terraform resource not found {0}")]
@@ -169,10 +334,28 @@ pub enum Error {
     TerraformResourceFieldMissing(String),
     #[error("Mismatch of a terraform resource value {expected} != {actual}")]
     TerraformResourceValueMismatch { expected: String, actual: String },
+    #[error(
+        "Terraform {version} is installed, but {minimum} or later is required to lock the state \
+        in S3. Upgrade Terraform to continue."
+    )]
+    TerraformVersionUnsupported { version: String, minimum: String },
+    #[error(
+        "The tfvars file {0} does not exist. It specifies the VM sizes and counts for the \
+        environment type. A production environment needs a '<name>.tfvars' file."
+    )]
+    TfvarsFileNotFound(PathBuf),
     #[error("The '{0}' binary was not found. It is required for the deploy process. Make sure it is installed.")]
     ToolBinaryNotFound(String),
+    #[error("There are too many private node VMs ({0}) to give each its own port range behind a full cone NAT")]
+    TooManyVmsForFullConeNat(usize),
     #[error("The {0} type is not yet supported for an upscaling provision")]
     UpscaleInventoryTypeNotSupported(String),
     #[error(transparent)]
     VarError(#[from] std::env::VarError),
+    #[error("The versions file is invalid: {0}")]
+    VersionsFileInvalid(String),
+    #[error("The '{0}' VM was not found in the node inventory")]
+    VmNotFound(String),
+    #[error("Failed to generate a WireGuard key: {0}")]
+    WireGuardKeyGenerationFailed(String),
 }
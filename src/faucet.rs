@@ -0,0 +1,66 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    error::{Error, Result},
+    get_genesis_multiaddr, TestnetDeployer,
+};
+use std::net::IpAddr;
+
+/// The port the faucet server listens on, on the genesis VM.
+const FAUCET_SERVER_PORT: u16 = 8000;
+
+impl TestnetDeployer {
+    /// Send tokens from the faucet wallet on the genesis VM to a wallet address.
+    ///
+    /// This uses the faucet binary directly, rather than the faucet server, so any amount can be
+    /// sent, and the faucet does not need to be running.
+    pub async fn fund_wallet_from_faucet(
+        &self,
+        address: &str,
+        amount: &str,
+    ) -> Result<Vec<String>> {
+        if address.is_empty() || !address.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(Error::InvalidWalletAddress(address.to_string()));
+        }
+        if !amount.parse::<f64>().is_ok_and(|amount| amount > 0.0) {
+            return Err(Error::InvalidFaucetAmount(amount.to_string()));
+        }
+
+        let (genesis_multiaddr, genesis_ip) =
+            get_genesis_multiaddr(&self.ansible_provisioner.ansible_runner, &self.ssh_client)
+                .await?;
+        self.run_faucet_command(
+            &genesis_ip,
+            &format!("faucet --peer {genesis_multiaddr} send {amount} {address}"),
+        )
+        .await
+    }
+
+    /// Get the balance of the faucet wallet on the genesis VM, from the faucet server.
+    pub async fn get_faucet_balance(&self) -> Result<Vec<String>> {
+        let (_, genesis_ip) =
+            get_genesis_multiaddr(&self.ansible_provisioner.ansible_runner, &self.ssh_client)
+                .await?;
+        self.run_faucet_command(
+            &genesis_ip,
+            &format!("curl --silent --fail http://localhost:{FAUCET_SERVER_PORT}/balance"),
+        )
+        .await
+    }
+
+    async fn run_faucet_command(&self, genesis_ip: &IpAddr, command: &str) -> Result<Vec<String>> {
+        // The faucet wallet belongs to the root user, which the faucet service runs as.
+        self.ssh_client
+            .execute(
+                genesis_ip,
+                &self.cloud_provider.get_ssh_user(),
+                &format!("sudo {command}"),
+            )
+            .await?
+            .into_stdout_lines()
+    }
+}
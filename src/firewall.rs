@@ -0,0 +1,228 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    digital_ocean::{DigitalOceanClient, DIGITAL_OCEAN_API_BASE_URL, DIGITAL_OCEAN_API_PAGE_SIZE},
+    error::{Error, Result},
+    rpc_proxy::RPC_PROXY_PORT_OFFSET,
+    CloudProvider,
+};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// The RPC port of the first node on each VM. This must match `initial_rpc_start_port` in the
+/// `node` role.
+const NODE_RPC_START_PORT: u16 = 13000;
+/// The RPC ports are opened as a range, large enough for any number of nodes on a VM.
+const NODE_RPC_PORT_COUNT: u16 = 1000;
+const ALL_ADDRESSES: [&str; 2] = ["0.0.0.0/0", "::/0"];
+/// This must match the port Grafana listens on in the `monitoring` role.
+const GRAFANA_PORT: u16 = 3000;
+
+/// The rules of the firewall for an environment.
+///
+/// All traffic between the VMs in the environment is allowed, as is all outbound traffic. Inbound
+/// traffic from elsewhere is limited to the node connections, which use QUIC on ports that are
+/// assigned by the node manager, SSH, and the ports in the profile.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FirewallProfile {
+    /// Open the Grafana port, for environments that have a monitoring VM.
+    #[serde(default)]
+    pub grafana: bool,
+    /// TCP ports open to all addresses, e.g., for the Peer Cache webservers.
+    pub open_tcp_ports: Vec<u16>,
+    /// Open the node RPC ports, for deployments that use `--public-rpc`.
+    pub public_rpc: bool,
+    /// Open the ports of the TLS reverse proxy for the node RPC.
+    pub rpc_proxy: bool,
+    /// The CIDRs SSH is allowed from. SSH is open to all addresses if it is empty.
+    pub ssh_allowlist: Vec<String>,
+    /// Close SSH on the public IPs, so the VMs can only be reached over the WireGuard overlay.
+    ///
+    /// The overlay traffic itself is UDP, which is open for the node connections.
+    #[serde(default)]
+    pub ssh_over_wireguard: bool,
+}
+
+impl Default for FirewallProfile {
+    fn default() -> Self {
+        Self {
+            grafana: false,
+            open_tcp_ports: vec![80],
+            public_rpc: false,
+            rpc_proxy: false,
+            ssh_allowlist: Vec::new(),
+            ssh_over_wireguard: false,
+        }
+    }
+}
+
+impl FirewallProfile {
+    pub fn print(&self) {
+        if self.ssh_over_wireguard {
+            println!("SSH: WireGuard overlay only");
+        } else if self.ssh_allowlist.is_empty() {
+            println!("SSH: open to all addresses");
+        } else {
+            println!("SSH: {}", self.ssh_allowlist.join(", "));
+        }
+        let open_tcp_ports = self
+            .open_tcp_ports
+            .iter()
+            .map(|port| port.to_string())
+            .collect::<Vec<_>>();
+        println!("Open TCP ports: {}", open_tcp_ports.join(", "));
+        println!("Grafana: {}", if self.grafana { "open" } else { "closed" });
+        println!(
+            "Node RPC: {}",
+            if self.public_rpc { "open" } else { "closed" }
+        );
+        println!(
+            "RPC proxy: {}",
+            if self.rpc_proxy { "open" } else { "closed" }
+        );
+    }
+
+    /// Build the firewall object for the DigitalOcean API.
+    ///
+    /// The firewall applies to every droplet with the environment's tag, so VMs added by an
+    /// upscale are covered without updating it.
+    fn to_digital_ocean_firewall(&self, environment_name: &str) -> serde_json::Value {
+        let environment_tag = format!("environment:{environment_name}");
+        let ssh_addresses = if self.ssh_allowlist.is_empty() {
+            ALL_ADDRESSES.iter().map(|addr| addr.to_string()).collect()
+        } else {
+            self.ssh_allowlist.clone()
+        };
+
+        let mut inbound_rules = Vec::new();
+        if !self.ssh_over_wireguard {
+            inbound_rules.push(json!({
+                "protocol": "tcp",
+                "ports": "22",
+                "sources": { "addresses": ssh_addresses }
+            }));
+        }
+        inbound_rules.extend([
+            json!({
+                "protocol": "udp",
+                "ports": "1-65535",
+                "sources": { "addresses": ALL_ADDRESSES }
+            }),
+            json!({
+                "protocol": "icmp",
+                "sources": { "addresses": ALL_ADDRESSES }
+            }),
+            json!({
+                "protocol": "tcp",
+                "ports": "1-65535",
+                "sources": { "tags": [environment_tag] }
+            }),
+        ]);
+        let mut open_tcp_ports = self
+            .open_tcp_ports
+            .iter()
+            .map(|port| port.to_string())
+            .collect::<Vec<_>>();
+        if self.grafana {
+            open_tcp_ports.push(GRAFANA_PORT.to_string());
+        }
+        if self.public_rpc {
+            open_tcp_ports.push(get_port_range(NODE_RPC_START_PORT));
+        }
+        if self.rpc_proxy {
+            open_tcp_ports.push(get_port_range(NODE_RPC_START_PORT + RPC_PROXY_PORT_OFFSET));
+        }
+        for ports in open_tcp_ports {
+            inbound_rules.push(json!({
+                "protocol": "tcp",
+                "ports": ports,
+                "sources": { "addresses": ALL_ADDRESSES }
+            }));
+        }
+
+        let outbound_rules = ["tcp", "udp"]
+            .iter()
+            .map(|protocol| {
+                json!({
+                    "protocol": protocol,
+                    "ports": "1-65535",
+                    "destinations": { "addresses": ALL_ADDRESSES }
+                })
+            })
+            .chain(std::iter::once(json!({
+                "protocol": "icmp",
+                "destinations": { "addresses": ALL_ADDRESSES }
+            })))
+            .collect::<Vec<_>>();
+
+        json!({
+            "name": get_firewall_name(environment_name),
+            "inbound_rules": inbound_rules,
+            "outbound_rules": outbound_rules,
+            "tags": [environment_tag],
+        })
+    }
+}
+
+/// Create the firewall for an environment, or replace its rules if it already exists.
+pub async fn apply_firewall(
+    provider: CloudProvider,
+    environment_name: &str,
+    profile: &FirewallProfile,
+) -> Result<()> {
+    let client = get_digital_ocean_client(provider)?;
+    if profile.ssh_allowlist.is_empty() && !profile.ssh_over_wireguard {
+        println!("{}", "WARNING!".yellow());
+        println!("No SSH allowlist was provided, so SSH is open to all addresses.");
+        println!("Use --ssh-allowlist to restrict SSH to the operators' addresses.");
+    }
+    let firewall = profile.to_digital_ocean_firewall(environment_name);
+    let name = get_firewall_name(environment_name);
+    match client.find_firewall(&name).await? {
+        Some(id) => {
+            println!("Updating the {name} firewall");
+            client.update_firewall(&id, &firewall).await?;
+        }
+        None => {
+            println!("Creating the {name} firewall");
+            client.create_firewall(&firewall).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove the firewall for an environment. It is not an error if there isn't one.
+pub async fn delete_firewall(provider: CloudProvider, environment_name: &str) -> Result<()> {
+    let client = get_digital_ocean_client(provider)?;
+    let name = get_firewall_name(environment_name);
+    if let Some(id) = client.find_firewall(&name).await? {
+        println!("Deleting the {name} firewall");
+        client.delete_firewall(&id).await?;
+    }
+    Ok(())
+}
+
+fn get_firewall_name(environment_name: &str) -> String {
+    format!("{environment_name}-firewall")
+}
+
+fn get_port_range(start_port: u16) -> String {
+    format!("{start_port}-{}", start_port + NODE_RPC_PORT_COUNT - 1)
+}
+
+fn get_digital_ocean_client(provider: CloudProvider) -> Result<DigitalOceanClient> {
+    if !matches!(provider, CloudProvider::DigitalOcean) {
+        return Err(Error::CloudProviderNotSupported(provider.to_string()));
+    }
+    Ok(DigitalOceanClient {
+        base_url: DIGITAL_OCEAN_API_BASE_URL.to_string(),
+        access_token: std::env::var("DO_PAT")
+            .map_err(|_| Error::CloudProviderCredentialsNotSupplied("DO_PAT".to_string()))?,
+        page_size: DIGITAL_OCEAN_API_PAGE_SIZE,
+    })
+}
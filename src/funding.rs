@@ -14,9 +14,9 @@ use crate::{
 use alloy::primitives::Address;
 use alloy::{network::EthereumWallet, signers::local::PrivateKeySigner};
 use evmlib::{common::U256, wallet::Wallet, Network};
-use log::{debug, error, warn};
 use std::collections::HashMap;
 use std::str::FromStr;
+use tracing::{debug, error, warn};
 
 /// 1 token (1e18)
 const DEFAULT_TOKEN_AMOUNT: &str = "1_000_000_000_000_000_000";
@@ -44,10 +44,10 @@ pub struct FundingOptions {
 
 impl AnsibleProvisioner {
     /// Retrieve the uploader secret keys for all uploader VMs
-    pub fn get_uploader_secret_keys(
+    pub async fn get_uploader_secret_keys(
         &self,
     ) -> Result<HashMap<VirtualMachine, Vec<PrivateKeySigner>>> {
-        let uploaders_count = self.get_current_uploader_count()?;
+        let uploaders_count = self.get_current_uploader_count().await?;
 
         debug!("Fetching uploader secret keys");
         let mut uploader_secret_keys = HashMap::new();
@@ -62,7 +62,7 @@ impl AnsibleProvisioner {
                 warn!("No uploader instances found for {:?}, ", vm.name);
                 uploader_secret_keys.insert(vm.clone(), Vec::new());
             } else {
-                let sks = self.get_uploader_secret_key_per_vm(&vm, count)?;
+                let sks = self.get_uploader_secret_key_per_vm(&vm, count).await?;
                 uploader_secret_keys.insert(vm.clone(), sks);
             }
         }
@@ -78,7 +78,7 @@ impl AnsibleProvisioner {
         options: &FundingOptions,
     ) -> Result<HashMap<VirtualMachine, Vec<PrivateKeySigner>>> {
         debug!("Funding all the uploader wallets");
-        let mut uploader_secret_keys = self.get_uploader_secret_keys()?;
+        let mut uploader_secret_keys = self.get_uploader_secret_keys().await?;
 
         for (vm, keys) in uploader_secret_keys.iter_mut() {
             if let Some(provided_count) = options.uploaders_count {
@@ -126,7 +126,7 @@ impl AnsibleProvisioner {
     ) -> Result<()> {
         debug!("Draining all the uploader wallets to {to_address:?}");
         println!("Draining all the uploader wallets to {to_address:?}");
-        let uploader_secret_keys = self.get_uploader_secret_keys()?;
+        let uploader_secret_keys = self.get_uploader_secret_keys().await?;
 
         for (vm, keys) in uploader_secret_keys.iter() {
             debug!(
@@ -200,7 +200,7 @@ impl AnsibleProvisioner {
     }
 
     /// Return the (vm name, uploader count) for all uploader VMs
-    fn get_current_uploader_count(&self) -> Result<HashMap<VirtualMachine, usize>> {
+    async fn get_current_uploader_count(&self) -> Result<HashMap<VirtualMachine, usize>> {
         let uploader_inventories = self
             .ansible_runner
             .get_inventory(AnsibleInventoryType::Uploaders, true)?;
@@ -219,9 +219,15 @@ impl AnsibleProvisioner {
             let cmd = "systemctl list-units --type=service --all | grep ant_uploader_ | wc -l";
             let result = self
                 .ssh_client
-                .run_command(&vm.public_ip_addr, "root", cmd, true);
+                .execute(&vm.public_ip_addr, &self.cloud_provider.get_ssh_user(), cmd)
+                .await;
             match result {
-                Ok(count) => {
+                Ok(output) if output.exit_status == Some(1) => {
+                    debug!("No uploaders found for {:?}", vm.public_ip_addr);
+                    uploader_count.insert(vm.clone(), 0);
+                }
+                Ok(output) => {
+                    let count = output.into_stdout_lines()?;
                     debug!("Count found to be {count:?}, parsing");
                     let count = count
                         .first()
@@ -234,21 +240,6 @@ impl AnsibleProvisioner {
                         .map_err(|_| Error::FailedToParseKey)?;
                     uploader_count.insert(vm.clone(), count);
                 }
-                Err(Error::ExternalCommandRunFailed {
-                    binary,
-                    exit_status,
-                }) => {
-                    if let Some(1) = exit_status.code() {
-                        debug!("No uploaders found for {:?}", vm.public_ip_addr);
-                        uploader_count.insert(vm.clone(), 0);
-                    } else {
-                        debug!("Error while fetching uploader count with different exit code {exit_status:?}",);
-                        return Err(Error::ExternalCommandRunFailed {
-                            binary,
-                            exit_status,
-                        });
-                    }
-                }
                 Err(err) => {
                     debug!("Error while fetching uploader count: {err:?}",);
                     return Err(err);
@@ -259,7 +250,7 @@ impl AnsibleProvisioner {
         Ok(uploader_count)
     }
 
-    fn get_uploader_secret_key_per_vm(
+    async fn get_uploader_secret_key_per_vm(
         &self,
         vm: &VirtualMachine,
         instance_count: usize,
@@ -279,7 +270,13 @@ impl AnsibleProvisioner {
             debug!("Fetching secret key for {} instance {count}", vm.name);
             let result = self
                 .ssh_client
-                .run_command(&vm.public_ip_addr, "root", &cmd, true);
+                .execute(
+                    &vm.public_ip_addr,
+                    &self.cloud_provider.get_ssh_user(),
+                    &cmd,
+                )
+                .await
+                .and_then(|output| output.into_stdout_lines());
             match result {
                 Ok(secret_keys) => {
                     let sk_str = secret_keys
@@ -0,0 +1,251 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    ansible::{inventory::AnsibleInventoryType, provisioning::ProvisionOptions},
+    error::{Error, Result},
+    get_genesis_multiaddr,
+    infra::InfraRunOptions,
+    inventory::DeploymentInventory,
+    CloudProvider, TestnetDeployer,
+};
+use std::{net::IpAddr, time::Duration};
+use tracing::debug;
+
+pub const GENESIS_BACKUP_BUCKET: &str = "sn-testnet-genesis-backups";
+/// The Terraform address of the genesis droplet.
+const GENESIS_DROPLET_ADDRESS: &str = "digitalocean_droplet.genesis_bootstrap[0]";
+/// The directory the genesis node's data directory is created in, on the attached volume.
+const NODE_STORAGE_PATH: &str = "/mnt/antnode-storage";
+const REMOTE_BACKUP_PATH: &str = "/tmp/genesis-backup.tar.gz";
+
+pub struct GenesisRebuildOptions {
+    pub current_inventory: DeploymentInventory,
+    pub interval: Duration,
+    pub max_archived_log_files: u16,
+    pub max_log_files: u16,
+    pub public_rpc: bool,
+    /// Restore the data directory from the last backup, so the node keeps its peer ID.
+    pub restore_backup: bool,
+}
+
+impl TestnetDeployer {
+    /// Back up the data directory of the genesis node to S3.
+    ///
+    /// The record store is excluded, since the records are replicated by the rest of the network.
+    /// What remains is mainly the node's secret key, which is what gives it the same peer ID when
+    /// it is restored. Each backup replaces the previous one.
+    pub async fn backup_genesis_node(&self, inventory: &DeploymentInventory) -> Result<()> {
+        let genesis_vm = inventory
+            .genesis_vm
+            .as_ref()
+            .ok_or(Error::EmptyInventory(AnsibleInventoryType::Genesis))?;
+        let genesis_ip = genesis_vm.vm.public_ip_addr;
+        let ssh_user = self.cloud_provider.get_ssh_user();
+
+        println!("Creating a backup of the genesis node data on {genesis_ip}...");
+        let output = self
+            .ssh_client
+            .execute(
+                &genesis_ip,
+                &ssh_user,
+                &format!(
+                    "sudo tar -czf {REMOTE_BACKUP_PATH} --exclude=record_store \
+                    -C {NODE_STORAGE_PATH} data"
+                ),
+            )
+            .await?;
+        if !output.success() {
+            return Err(Error::SshCommandFailed(format!(
+                "Failed to create the genesis node backup: {}",
+                output.stderr.trim()
+            )));
+        }
+
+        let temp_dir = tempfile::tempdir()?;
+        let backup_path = temp_dir
+            .path()
+            .join(get_backup_object_key(&self.environment_name));
+        self.ssh_client
+            .download_file(&genesis_ip, &ssh_user, REMOTE_BACKUP_PATH, &backup_path)
+            .await?;
+        self.ssh_client
+            .execute(
+                &genesis_ip,
+                &ssh_user,
+                &format!("sudo rm -f {REMOTE_BACKUP_PATH}"),
+            )
+            .await?;
+        self.s3_repository
+            .upload_file(GENESIS_BACKUP_BUCKET, &backup_path, false)
+            .await?;
+        Ok(())
+    }
+
+    /// Recreate the genesis VM and provision it again, returning the multiaddr of the new genesis
+    /// node.
+    ///
+    /// The droplet is replaced through Terraform, so the rest of the environment is untouched. Its
+    /// attached volume is kept and reattached to the new droplet. If the backup is restored, the
+    /// node is stopped after provisioning, its data directory is replaced with the backup, and it
+    /// is started again.
+    ///
+    /// The new VM has a different IP address, so the nodes that were given the old genesis
+    /// multiaddr as a peer will need to be updated.
+    pub async fn rebuild_genesis_node(&self, options: &GenesisRebuildOptions) -> Result<String> {
+        if !matches!(self.cloud_provider, CloudProvider::DigitalOcean) {
+            return Err(Error::CloudProviderNotSupported(
+                self.cloud_provider.to_string(),
+            ));
+        }
+        let name = &options.current_inventory.name;
+
+        // Retrieve the backup first, so nothing is destroyed if there isn't one.
+        let temp_dir = tempfile::tempdir()?;
+        let backup_path = temp_dir.path().join(get_backup_object_key(name));
+        if options.restore_backup {
+            if !self
+                .s3_repository
+                .object_exists(GENESIS_BACKUP_BUCKET, &get_backup_object_key(name))
+                .await?
+            {
+                return Err(Error::GenesisBackupNotFound(name.clone()));
+            }
+            self.s3_repository
+                .download_object(
+                    GENESIS_BACKUP_BUCKET,
+                    &get_backup_object_key(name),
+                    &backup_path,
+                )
+                .await?;
+        }
+
+        let infra_run_options = InfraRunOptions::generate_existing(
+            name,
+            &self.terraform_runner,
+            &options.current_inventory.environment_details,
+        )
+        .await?;
+        println!("Selecting {name} workspace...");
+        self.terraform_runner.workspace_select(name)?;
+        println!("Recreating the genesis VM...");
+        self.terraform_runner.apply_with_replace(
+            infra_run_options.get_terraform_vars()?,
+            Some(infra_run_options.tfvars_filename.clone()),
+            &[GENESIS_DROPLET_ADDRESS.to_string()],
+        )?;
+
+        let provision_options = self.get_genesis_provision_options(options)?;
+        self.ansible_provisioner
+            .provision_genesis_node(&provision_options)?;
+
+        if options.restore_backup {
+            let genesis_inventory = self
+                .ansible_provisioner
+                .ansible_runner
+                .get_inventory(AnsibleInventoryType::Genesis, true)?;
+            let genesis_ip = genesis_inventory
+                .first()
+                .ok_or(Error::EmptyInventory(AnsibleInventoryType::Genesis))?
+                .public_ip_addr;
+            self.restore_genesis_backup(&genesis_ip, &backup_path)
+                .await?;
+        }
+
+        let (multiaddr, _) =
+            get_genesis_multiaddr(&self.ansible_provisioner.ansible_runner, &self.ssh_client)
+                .await?;
+        debug!("The new genesis multiaddr is {multiaddr}");
+        Ok(multiaddr)
+    }
+
+    async fn restore_genesis_backup(
+        &self,
+        genesis_ip: &IpAddr,
+        backup_path: &std::path::Path,
+    ) -> Result<()> {
+        let ssh_user = self.cloud_provider.get_ssh_user();
+        println!("Restoring the genesis node data on {genesis_ip}...");
+        self.ssh_client
+            .upload_file(genesis_ip, &ssh_user, backup_path, REMOTE_BACKUP_PATH)
+            .await?;
+        let output = self
+            .ssh_client
+            .execute(
+                genesis_ip,
+                &ssh_user,
+                &format!(
+                    "sudo antctl stop && \
+                    sudo tar -xzf {REMOTE_BACKUP_PATH} -C {NODE_STORAGE_PATH} && \
+                    sudo rm -f {REMOTE_BACKUP_PATH} && \
+                    sudo antctl start"
+                ),
+            )
+            .await?;
+        if !output.success() {
+            return Err(Error::GenesisRestoreFailed(
+                output.stderr.trim().to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn get_genesis_provision_options(
+        &self,
+        options: &GenesisRebuildOptions,
+    ) -> Result<ProvisionOptions> {
+        let inventory = &options.current_inventory;
+        let details = &inventory.environment_details;
+        Ok(ProvisionOptions {
+            ant_version: None,
+            architecture: details.architecture.unwrap_or_default(),
+            artifact_proxy_url: self.ansible_provisioner.get_artifact_proxy_url()?,
+            artifact_repository: details.artifact_repository.clone().unwrap_or_default(),
+            binary_option: inventory.binary_option.clone(),
+            build_cache_key: None,
+            chunk_size: None,
+            door_node_vms: Vec::new(),
+            downloaders_count: 0,
+            env_variables: None,
+            evm_data_payments_address: details.evm_data_payments_address.clone(),
+            evm_network: details.evm_network.clone(),
+            evm_payment_token_address: details.evm_payment_token_address.clone(),
+            evm_rpc_url: details.evm_rpc_url.clone(),
+            funding_wallet_secret_key: None,
+            gas_amount: None,
+            interval: options.interval,
+            join_rate: None,
+            log_format: None,
+            max_archived_log_files: options.max_archived_log_files,
+            max_log_files: options.max_log_files,
+            name: inventory.name.clone(),
+            nat_gateway: None,
+            nat_type: details.nat_type.unwrap_or_default(),
+            network_id: details.network_id,
+            node_count: 1,
+            node_reachability: details.node_reachability.unwrap_or_default(),
+            output_inventory_dir_path: self
+                .working_directory_path
+                .join("ansible")
+                .join("inventory"),
+            peer_cache_node_count: 0,
+            peer_cache_node_reachability: details.peer_cache_node_reachability.unwrap_or_default(),
+            private_node_count: 0,
+            private_node_upnp: false,
+            private_node_vms: Vec::new(),
+            provision_parallelism: None,
+            provision_retries: 0,
+            public_rpc: options.public_rpc,
+            rewards_address: details.rewards_address.clone(),
+            telemetry: None,
+            uploaders_count: None,
+        })
+    }
+}
+
+fn get_backup_object_key(environment_name: &str) -> String {
+    format!("{environment_name}-genesis-backup.tar.gz")
+}
@@ -0,0 +1,208 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    ansible::inventory::AnsibleInventoryType,
+    error::{Error, Result},
+    TestnetDeployer,
+};
+use rand::{distributions::Alphanumeric, Rng};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::json;
+use std::path::PathBuf;
+use tracing::debug;
+
+/// The dashboard the monitoring role provisions for the node metrics.
+pub const DEFAULT_DASHBOARD_UID: &str = "safenode";
+const GRAFANA_ADMIN_USER: &str = "admin";
+/// The password Grafana uses for the admin user when none is configured.
+const GRAFANA_DEFAULT_ADMIN_PASSWORD: &str = "admin";
+const GRAFANA_ADMIN_PASSWORD_LENGTH: usize = 32;
+const GRAFANA_PORT: u16 = 3000;
+
+/// A dashboard that can be viewed without logging in to Grafana.
+#[derive(Clone, Debug)]
+pub struct PublicDashboard {
+    pub dashboard_uid: String,
+    pub url: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PublicDashboardConfig {
+    access_token: String,
+    is_enabled: bool,
+    uid: String,
+}
+
+impl TestnetDeployer {
+    /// Make dashboards on the monitoring VM viewable by anyone with the link.
+    ///
+    /// Each dashboard is shared with Grafana's public dashboards feature, which gives it a separate,
+    /// read-only URL that needs no credentials. The viewers can change the time range, but not the
+    /// queries, and cannot reach any other part of Grafana.
+    ///
+    /// Publishing a dashboard that is already public re-enables it if it was paused, and returns
+    /// the same URL.
+    pub async fn publish_grafana_dashboards(
+        &self,
+        dashboard_uids: &[String],
+    ) -> Result<Vec<PublicDashboard>> {
+        let admin_password = self.get_grafana_admin_password()?;
+        let base_url = self.get_grafana_base_url()?;
+        let client = reqwest::Client::new();
+        let mut public_dashboards = Vec::new();
+        for dashboard_uid in dashboard_uids.iter() {
+            let url = format!("{base_url}/api/dashboards/uid/{dashboard_uid}/public-dashboards");
+            let config = match get_public_dashboard_config(&client, &url, &admin_password).await? {
+                Some(config) if config.is_enabled => config,
+                Some(config) => {
+                    debug!("Re-enabling the public dashboard for {dashboard_uid}");
+                    client
+                        .patch(format!("{url}/{}", config.uid))
+                        .basic_auth(GRAFANA_ADMIN_USER, Some(&admin_password))
+                        .json(&json!({ "isEnabled": true }))
+                        .send()
+                        .await?
+                        .error_for_status()?
+                        .json::<PublicDashboardConfig>()
+                        .await?
+                }
+                None => {
+                    debug!("Creating a public dashboard for {dashboard_uid}");
+                    client
+                        .post(&url)
+                        .basic_auth(GRAFANA_ADMIN_USER, Some(&admin_password))
+                        .json(&json!({
+                            "isEnabled": true,
+                            "share": "public",
+                            "timeSelectionEnabled": true,
+                        }))
+                        .send()
+                        .await?
+                        .error_for_status()?
+                        .json::<PublicDashboardConfig>()
+                        .await?
+                }
+            };
+            public_dashboards.push(PublicDashboard {
+                dashboard_uid: dashboard_uid.clone(),
+                url: format!("{base_url}/public-dashboards/{}", config.access_token),
+            });
+        }
+        Ok(public_dashboards)
+    }
+
+    /// Remove public access to dashboards on the monitoring VM.
+    ///
+    /// The public URLs are revoked. Publishing a dashboard again gives it a new URL.
+    pub async fn unpublish_grafana_dashboards(&self, dashboard_uids: &[String]) -> Result<()> {
+        let admin_password = self.get_grafana_admin_password()?;
+        let base_url = self.get_grafana_base_url()?;
+        let client = reqwest::Client::new();
+        for dashboard_uid in dashboard_uids.iter() {
+            let url = format!("{base_url}/api/dashboards/uid/{dashboard_uid}/public-dashboards");
+            let Some(config) = get_public_dashboard_config(&client, &url, &admin_password).await?
+            else {
+                println!("The {dashboard_uid} dashboard is not public");
+                continue;
+            };
+            client
+                .delete(format!("{url}/{}", config.uid))
+                .basic_auth(GRAFANA_ADMIN_USER, Some(&admin_password))
+                .send()
+                .await?
+                .error_for_status()?;
+            println!("Removed public access to the {dashboard_uid} dashboard");
+        }
+        Ok(())
+    }
+
+    /// The dashboards are not published while Grafana could still have the default admin password,
+    /// because anyone given the URL would then be able to log in as the admin.
+    fn get_grafana_admin_password(&self) -> Result<String> {
+        let admin_password = read_admin_password(&self.environment_name)?
+            .ok_or_else(|| Error::GrafanaAdminPasswordNotFound(self.environment_name.clone()))?;
+        if admin_password.is_empty() || admin_password == GRAFANA_DEFAULT_ADMIN_PASSWORD {
+            return Err(Error::GrafanaDefaultAdminPassword(
+                self.environment_name.clone(),
+            ));
+        }
+        Ok(admin_password)
+    }
+
+    fn get_grafana_base_url(&self) -> Result<String> {
+        let monitoring_inventory = self
+            .ansible_provisioner
+            .ansible_runner
+            .get_inventory(AnsibleInventoryType::Monitoring, true)?;
+        let monitoring_vm = monitoring_inventory
+            .first()
+            .ok_or_else(|| Error::EmptyInventory(AnsibleInventoryType::Monitoring))?;
+        Ok(format!(
+            "http://{}:{GRAFANA_PORT}",
+            monitoring_vm.public_ip_addr
+        ))
+    }
+}
+
+/// Get the public dashboard configuration for a dashboard, if it has one.
+async fn get_public_dashboard_config(
+    client: &reqwest::Client,
+    url: &str,
+    admin_password: &str,
+) -> Result<Option<PublicDashboardConfig>> {
+    let response = client
+        .get(url)
+        .basic_auth(GRAFANA_ADMIN_USER, Some(admin_password))
+        .send()
+        .await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let config = response
+        .error_for_status()?
+        .json::<PublicDashboardConfig>()
+        .await?;
+    Ok(Some(config))
+}
+
+pub fn generate_admin_password() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(GRAFANA_ADMIN_PASSWORD_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+/// The admin password is kept in the data directory, alongside the inventory, rather than in the
+/// environment details, which are publicly readable.
+pub fn get_admin_password_path(environment_name: &str) -> Result<PathBuf> {
+    let path = dirs_next::data_dir()
+        .ok_or_else(|| Error::CouldNotRetrieveDataDirectory)?
+        .join("safe")
+        .join("testnet-deploy");
+    if !path.exists() {
+        std::fs::create_dir_all(&path)?;
+    }
+    Ok(path.join(format!("{environment_name}-grafana-admin-password")))
+}
+
+pub fn save_admin_password(environment_name: &str, password: &str) -> Result<PathBuf> {
+    let path = get_admin_password_path(environment_name)?;
+    std::fs::write(&path, password)?;
+    Ok(path)
+}
+
+/// Returns `None` if the monitoring VM was not provisioned from this machine.
+pub fn read_admin_password(environment_name: &str) -> Result<Option<String>> {
+    let path = get_admin_password_path(environment_name)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(std::fs::read_to_string(path)?.trim().to_string()))
+}
@@ -0,0 +1,142 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    ansible::inventory::AnsibleInventoryType,
+    digital_ocean::{
+        get_droplet_links, DigitalOceanClient, DropletAction, DIGITAL_OCEAN_API_BASE_URL,
+        DIGITAL_OCEAN_API_PAGE_SIZE,
+    },
+    error::Result,
+    CloudProvider, TestnetDeployer,
+};
+use tracing::debug;
+
+/// The number of recent droplet actions shown for each failed host.
+const RECENT_ACTION_COUNT: usize = 5;
+
+/// A host that failed a playbook, resolved to the VM it runs on.
+pub struct FailedHostDetails {
+    /// The name the host is referred to by in the inventory. Private nodes are referred to by
+    /// their private IP address.
+    pub host: String,
+    pub console_url: Option<String>,
+    pub droplet_id: Option<usize>,
+    pub droplet_url: Option<String>,
+    pub recent_actions: Vec<DropletAction>,
+    pub vm_name: Option<String>,
+}
+
+impl FailedHostDetails {
+    fn new(host: &str) -> Self {
+        Self {
+            host: host.to_string(),
+            console_url: None,
+            droplet_id: None,
+            droplet_url: None,
+            recent_actions: Vec::new(),
+            vm_name: None,
+        }
+    }
+}
+
+impl TestnetDeployer {
+    /// Print the hosts that failed, with links to each VM in the cloud provider's console and its
+    /// recent events, e.g., a power cycle or a host migration.
+    ///
+    /// Anything that cannot be resolved is left out, so the host names are always printed.
+    pub async fn print_failed_hosts(&self, hosts: &[String]) {
+        let details = match self.get_failed_host_details(hosts).await {
+            Ok(details) => details,
+            Err(err) => {
+                debug!("Could not resolve the failed hosts: {err}");
+                hosts
+                    .iter()
+                    .map(|host| FailedHostDetails::new(host))
+                    .collect()
+            }
+        };
+
+        println!("These hosts failed to provision:");
+        for host in details.iter() {
+            match &host.vm_name {
+                Some(vm_name) if vm_name != &host.host => println!("  {} ({vm_name})", host.host),
+                _ => println!("  {}", host.host),
+            }
+            if let Some(droplet_url) = &host.droplet_url {
+                println!("    Droplet: {droplet_url}");
+            }
+            if let Some(console_url) = &host.console_url {
+                println!("    Console: {console_url}");
+            }
+            for action in host.recent_actions.iter() {
+                println!(
+                    "    {} {} ({}){}",
+                    action.started_at,
+                    action.action_type,
+                    action.status,
+                    action
+                        .completed_at
+                        .as_ref()
+                        .map(|completed_at| format!(", completed {completed_at}"))
+                        .unwrap_or_default()
+                );
+            }
+        }
+    }
+
+    async fn get_failed_host_details(&self, hosts: &[String]) -> Result<Vec<FailedHostDetails>> {
+        // The private nodes are referred to by their private IP address, so they are resolved to
+        // their VM names using the inventory.
+        let private_node_vms = self
+            .ansible_provisioner
+            .ansible_runner
+            .get_inventory(AnsibleInventoryType::PrivateNodes, true)?;
+        let mut details = hosts
+            .iter()
+            .map(|host| FailedHostDetails {
+                vm_name: Some(
+                    private_node_vms
+                        .iter()
+                        .find(|vm| &vm.private_ip_addr.to_string() == host)
+                        .map(|vm| vm.name.clone())
+                        .unwrap_or_else(|| host.clone()),
+                ),
+                ..FailedHostDetails::new(host)
+            })
+            .collect::<Vec<_>>();
+
+        if !matches!(self.cloud_provider, CloudProvider::DigitalOcean) {
+            return Ok(details);
+        }
+        let client = DigitalOceanClient {
+            base_url: DIGITAL_OCEAN_API_BASE_URL.to_string(),
+            access_token: std::env::var("DO_PAT").unwrap_or_default(),
+            page_size: DIGITAL_OCEAN_API_PAGE_SIZE,
+        };
+        let droplets = client.list_droplets(true).await?;
+        for host in details.iter_mut() {
+            let Some(droplet) = droplets
+                .iter()
+                .find(|droplet| Some(&droplet.name) == host.vm_name.as_ref())
+            else {
+                continue;
+            };
+            let (droplet_url, console_url) = get_droplet_links(droplet.id);
+            host.droplet_id = Some(droplet.id);
+            host.droplet_url = Some(droplet_url);
+            host.console_url = Some(console_url);
+            match client
+                .list_droplet_actions(droplet.id, RECENT_ACTION_COUNT)
+                .await
+            {
+                Ok(actions) => host.recent_actions = actions,
+                Err(err) => debug!("Could not list the actions for {}: {err}", droplet.name),
+            }
+        }
+        Ok(details)
+    }
+}
@@ -6,20 +6,27 @@
 
 use crate::{
     error::{Error, Result},
-    print_duration,
-    terraform::TerraformRunner,
-    EnvironmentDetails, TestnetDeployer,
+    get_environment_details,
+    terraform::{ResourceDrift, TerraformRunner},
+    BandwidthClass, EnvironmentDetails, TestnetDeployer,
 };
-use std::time::Instant;
+use serde::Serialize;
 
 #[derive(Clone, Debug)]
 pub struct InfraRunOptions {
+    pub auditor_vm_count: Option<u16>,
+    pub dns_domain: Option<String>,
+    pub door_node_count: Option<u16>,
+    pub door_node_dns_domain: Option<String>,
+    pub downloader_vm_count: Option<u16>,
     pub enable_build_vm: bool,
     pub evm_node_count: Option<u16>,
     pub evm_node_vm_size: Option<String>,
     pub genesis_vm_count: Option<u16>,
     pub genesis_node_volume_size: Option<u16>,
     pub name: String,
+    /// The number of NAT gateways the private node VMs are spread across.
+    pub nat_gateway_count: Option<u16>,
     pub node_vm_count: Option<u16>,
     pub node_vm_size: Option<String>,
     pub node_volume_size: Option<u16>,
@@ -28,7 +35,12 @@ pub struct InfraRunOptions {
     pub peer_cache_node_volume_size: Option<u16>,
     pub private_node_vm_count: Option<u16>,
     pub private_node_volume_size: Option<u16>,
+    pub setup_artifact_proxy: Option<bool>,
+    pub setup_monitoring: Option<bool>,
     pub tfvars_filename: String,
+    pub uploader_bandwidth_class: Option<BandwidthClass>,
+    /// The regions the uploader VMs are spread across, if they should not be in the node region.
+    pub uploader_regions: Option<Vec<String>>,
     pub uploader_vm_count: Option<u16>,
     pub uploader_vm_size: Option<String>,
 }
@@ -50,12 +62,14 @@ impl InfraRunOptions {
                 .filter(|r| r.resource_name == resource_name)
                 .try_fold(None, |vm_size: Option<serde_json::Value>, r| {
                     let Some(size) = r.values.get(field_name) else {
-                        log::error!("Failed to obtain '{field_name}' value for {resource_name}");
+                        tracing::error!(
+                            "Failed to obtain '{field_name}' value for {resource_name}"
+                        );
                         return Err(Error::TerraformResourceFieldMissing(field_name.to_string()));
                     };
                     match vm_size {
                         Some(ref existing_size) if existing_size != size => {
-                            log::error!("Expected value: {existing_size}, got value: {size}");
+                            tracing::error!("Expected value: {existing_size}, got value: {size}");
                             Err(Error::TerraformResourceValueMismatch {
                                 expected: existing_size.to_string(),
                                 actual: size.to_string(),
@@ -80,7 +94,7 @@ impl InfraRunOptions {
             let volume_size = get_value_for_a_resource("peer_cache_node_attached_volume", "size")?
                 .as_u64()
                 .ok_or_else(|| {
-                    log::error!(
+                    tracing::error!(
                         "Failed to obtain u64 'size' value for peer_cache_node_attached_volume"
                     );
                     Error::TerraformResourceFieldMissing("size".to_string())
@@ -96,7 +110,7 @@ impl InfraRunOptions {
                 get_value_for_a_resource("genesis_node_attached_volume", "size")?
                     .as_u64()
                     .ok_or_else(|| {
-                        log::error!(
+                        tracing::error!(
                             "Failed to obtain u64 'size' value for genesis_node_attached_volume"
                         );
                         Error::TerraformResourceFieldMissing("size".to_string())
@@ -111,7 +125,7 @@ impl InfraRunOptions {
             let node_volume_size = get_value_for_a_resource("node_attached_volume", "size")?
                 .as_u64()
                 .ok_or_else(|| {
-                    log::error!("Failed to obtain u64 'size' value for node_attached_volume");
+                    tracing::error!("Failed to obtain u64 'size' value for node_attached_volume");
                     Error::TerraformResourceFieldMissing("size".to_string())
                 })?;
             Some(node_volume_size as u16)
@@ -125,7 +139,7 @@ impl InfraRunOptions {
                 get_value_for_a_resource("private_node_attached_volume", "size")?
                     .as_u64()
                     .ok_or_else(|| {
-                        log::error!(
+                        tracing::error!(
                             "Failed to obtain u64 'size' value for private_node_attached_volume"
                         );
                         Error::TerraformResourceFieldMissing("size".to_string())
@@ -141,12 +155,18 @@ impl InfraRunOptions {
         let enable_build_vm = build_vm_count > 0;
 
         let options = Self {
+            auditor_vm_count: Some(resource_count("auditor")),
+            dns_domain: environment_details.dns_domain.clone(),
+            door_node_count: Some(resource_count("door_node")),
+            door_node_dns_domain: environment_details.door_node_dns_domain.clone(),
+            downloader_vm_count: Some(resource_count("downloader")),
             enable_build_vm,
             evm_node_count,
             evm_node_vm_size: None, // vm_size is obtained from the tfvars file
             genesis_vm_count: Some(genesis_vm_count),
             genesis_node_volume_size,
             name: name.to_string(),
+            nat_gateway_count: Some(resource_count("nat_gateway")).filter(|count| *count > 0),
             node_vm_count: Some(node_vm_count),
             node_vm_size: None, // vm_size is obtained from the tfvars file
             node_volume_size,
@@ -155,44 +175,46 @@ impl InfraRunOptions {
             peer_cache_node_volume_size,
             private_node_vm_count: Some(private_node_vm_count),
             private_node_volume_size,
+            setup_artifact_proxy: Some(resource_count("artifact_proxy") > 0),
+            setup_monitoring: Some(resource_count("monitoring") > 0),
             tfvars_filename: environment_details
                 .environment_type
                 .get_tfvars_filename(name),
+            uploader_bandwidth_class: environment_details.uploader_bandwidth_class,
+            uploader_regions: environment_details.uploader_regions.clone(),
             uploader_vm_count,
             uploader_vm_size: None, // vm_size is obtained from the tfvars file
         };
 
         Ok(options)
     }
-}
-
-impl TestnetDeployer {
-    /// Create or update the infrastructure for a deployment.
-    pub fn create_or_update_infra(&self, options: &InfraRunOptions) -> Result<()> {
-        let start = Instant::now();
-        println!("Selecting {} workspace...", options.name);
-        self.terraform_runner.workspace_select(&options.name)?;
 
+    /// Get the variables for a Terraform run from the options.
+    pub fn get_terraform_vars(&self) -> Result<Vec<(String, String)>> {
         let mut args = Vec::new();
 
-        if let Some(reserved_ips) = crate::reserved_ip::get_reserved_ips_args(&options.name) {
+        if let Some(reserved_ips) = crate::reserved_ip::get_reserved_ips_args(&self.name) {
             args.push(("peer_cache_reserved_ips".to_string(), reserved_ips));
         }
 
-        if let Some(genesis_vm_count) = options.genesis_vm_count {
+        if let Some(auditor_vm_count) = self.auditor_vm_count {
+            args.push(("auditor_vm_count".to_string(), auditor_vm_count.to_string()));
+        }
+
+        if let Some(genesis_vm_count) = self.genesis_vm_count {
             args.push(("genesis_vm_count".to_string(), genesis_vm_count.to_string()));
         }
 
-        if let Some(peer_cache_node_vm_count) = options.peer_cache_node_vm_count {
+        if let Some(peer_cache_node_vm_count) = self.peer_cache_node_vm_count {
             args.push((
                 "peer_cache_node_vm_count".to_string(),
                 peer_cache_node_vm_count.to_string(),
             ));
         }
-        if let Some(node_vm_count) = options.node_vm_count {
+        if let Some(node_vm_count) = self.node_vm_count {
             args.push(("node_vm_count".to_string(), node_vm_count.to_string()));
         }
-        if let Some(private_node_vm_count) = options.private_node_vm_count {
+        if let Some(private_node_vm_count) = self.private_node_vm_count {
             args.push((
                 "private_node_vm_count".to_string(),
                 private_node_vm_count.to_string(),
@@ -202,74 +224,216 @@ impl TestnetDeployer {
                 (private_node_vm_count > 0).to_string(),
             ));
         }
+        if let Some(nat_gateway_count) = self.nat_gateway_count {
+            args.push((
+                "nat_gateway_count".to_string(),
+                nat_gateway_count.to_string(),
+            ));
+        }
 
-        if let Some(evm_node_count) = options.evm_node_count {
+        if let Some(evm_node_count) = self.evm_node_count {
             args.push(("evm_node_vm_count".to_string(), evm_node_count.to_string()));
         }
 
-        if let Some(uploader_vm_count) = options.uploader_vm_count {
+        if let Some(uploader_vm_count) = self.uploader_vm_count {
             args.push((
                 "uploader_vm_count".to_string(),
                 uploader_vm_count.to_string(),
             ));
         }
 
+        if let Some(downloader_vm_count) = self.downloader_vm_count {
+            args.push((
+                "downloader_vm_count".to_string(),
+                downloader_vm_count.to_string(),
+            ));
+        }
+
         args.push((
             "use_custom_bin".to_string(),
-            options.enable_build_vm.to_string(),
+            self.enable_build_vm.to_string(),
         ));
 
-        if let Some(node_vm_size) = &options.node_vm_size {
+        if let Some(dns_domain) = &self.dns_domain {
+            args.push(("dns_domain".to_string(), dns_domain.clone()));
+        }
+        if let Some(door_node_count) = self.door_node_count {
+            args.push(("door_node_count".to_string(), door_node_count.to_string()));
+        }
+        if let Some(door_node_dns_domain) = &self.door_node_dns_domain {
+            args.push((
+                "door_node_dns_domain".to_string(),
+                door_node_dns_domain.clone(),
+            ));
+        }
+
+        if let Some(setup_artifact_proxy) = self.setup_artifact_proxy {
+            args.push((
+                "setup_artifact_proxy".to_string(),
+                setup_artifact_proxy.to_string(),
+            ));
+        }
+
+        if let Some(setup_monitoring) = self.setup_monitoring {
+            args.push(("setup_monitoring".to_string(), setup_monitoring.to_string()));
+        }
+
+        if let Some(node_vm_size) = &self.node_vm_size {
             args.push(("node_droplet_size".to_string(), node_vm_size.clone()));
         }
 
-        if let Some(peer_cache_vm_size) = &options.peer_cache_node_vm_size {
+        if let Some(peer_cache_vm_size) = &self.peer_cache_node_vm_size {
             args.push((
                 "peer_cache_droplet_size".to_string(),
                 peer_cache_vm_size.clone(),
             ));
         }
 
-        if let Some(uploader_vm_size) = &options.uploader_vm_size {
+        if let Some(uploader_bandwidth_class) = self.uploader_bandwidth_class {
             args.push((
-                "uploader_droplet_size".to_string(),
-                uploader_vm_size.clone(),
+                "uploader_bandwidth_class".to_string(),
+                uploader_bandwidth_class.as_str().to_string(),
             ));
         }
 
-        if let Some(evm_node_vm_size) = &options.evm_node_vm_size {
+        if let Some(uploader_regions) = &self.uploader_regions {
+            args.push((
+                "uploader_regions".to_string(),
+                serde_json::to_string(uploader_regions)?,
+            ));
+        }
+
+        if let Some(uploader_vm_size) = &self.uploader_vm_size {
+            // The premium droplets have their own size variable, so that the standard size from
+            // the tfvars file is left alone.
+            let var_name = match self.uploader_bandwidth_class {
+                Some(BandwidthClass::Premium) => "uploader_premium_droplet_size",
+                _ => "uploader_droplet_size",
+            };
+            args.push((var_name.to_string(), uploader_vm_size.clone()));
+        }
+
+        if let Some(evm_node_vm_size) = &self.evm_node_vm_size {
             args.push((
                 "evm_node_droplet_size".to_string(),
                 evm_node_vm_size.clone(),
             ));
         }
 
-        if let Some(peer_cache_node_volume_size) = options.peer_cache_node_volume_size {
+        if let Some(peer_cache_node_volume_size) = self.peer_cache_node_volume_size {
             args.push((
                 "peer_cache_node_volume_size".to_string(),
                 peer_cache_node_volume_size.to_string(),
             ));
         }
-        if let Some(genesis_node_volume_size) = options.genesis_node_volume_size {
+        if let Some(genesis_node_volume_size) = self.genesis_node_volume_size {
             args.push((
                 "genesis_node_volume_size".to_string(),
                 genesis_node_volume_size.to_string(),
             ));
         }
-        if let Some(node_volume_size) = options.node_volume_size {
+        if let Some(node_volume_size) = self.node_volume_size {
             args.push(("node_volume_size".to_string(), node_volume_size.to_string()));
         }
-        if let Some(private_node_volume_size) = options.private_node_volume_size {
+        if let Some(private_node_volume_size) = self.private_node_volume_size {
             args.push((
                 "private_node_volume_size".to_string(),
                 private_node_volume_size.to_string(),
             ));
         }
+        Ok(args)
+    }
+}
+
+impl TestnetDeployer {
+    /// Create or update the infrastructure for a deployment.
+    pub fn create_or_update_infra(&self, options: &InfraRunOptions) -> Result<()> {
+        // Check this before Terraform does, since its error does not say how to fix it.
+        let tfvars_path = self
+            .terraform_runner
+            .working_directory_path
+            .join(&options.tfvars_filename);
+        if !tfvars_path.exists() {
+            return Err(Error::TfvarsFileNotFound(tfvars_path));
+        }
+
+        println!("Selecting {} workspace...", options.name);
+        self.terraform_runner.workspace_select(&options.name)?;
+        let args = options.get_terraform_vars()?;
 
         println!("Running terraform apply...");
         self.terraform_runner
             .apply(args, Some(options.tfvars_filename.clone()))?;
-        print_duration(start.elapsed());
         Ok(())
     }
+
+    /// Compare the real infrastructure for the environment with the Terraform state, e.g., to find
+    /// VMs that were deleted or resized outside of Terraform.
+    ///
+    /// A refresh-only plan is used, with the variables derived from the existing resources, so
+    /// only changes made outside of Terraform are reported.
+    pub async fn detect_infra_drift(&self) -> Result<DriftReport> {
+        let environment_details =
+            get_environment_details(&self.environment_name, &self.s3_repository).await?;
+        self.terraform_runner.init()?;
+        let workspaces = self.terraform_runner.workspace_list()?;
+        if !workspaces.contains(&self.environment_name) {
+            return Err(Error::EnvironmentDoesNotExist(
+                self.environment_name.clone(),
+            ));
+        }
+        let options = InfraRunOptions::generate_existing(
+            &self.environment_name,
+            &self.terraform_runner,
+            &environment_details,
+        )
+        .await?;
+
+        println!("Running terraform plan to detect drift...");
+        let drifted_resources = self.terraform_runner.plan_drift(
+            options.get_terraform_vars()?,
+            Some(options.tfvars_filename.clone()),
+        )?;
+        Ok(DriftReport {
+            environment_name: self.environment_name.clone(),
+            drifted_resources,
+        })
+    }
+}
+
+/// The resources that have changed outside of Terraform.
+#[derive(Clone, Debug, Serialize)]
+pub struct DriftReport {
+    pub environment_name: String,
+    pub drifted_resources: Vec<ResourceDrift>,
+}
+
+impl DriftReport {
+    pub fn has_drift(&self) -> bool {
+        !self.drifted_resources.is_empty()
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn print(&self) {
+        println!("============");
+        println!("Drift Report");
+        println!("============");
+        if !self.has_drift() {
+            println!(
+                "The infrastructure for {} matches the Terraform state",
+                self.environment_name
+            );
+            return;
+        }
+        println!(
+            "{} resources have changed outside of Terraform:",
+            self.drifted_resources.len()
+        );
+        for resource in self.drifted_resources.iter() {
+            println!("  {}: {}", resource.address, resource.actions.join(", "));
+        }
+    }
 }
@@ -7,22 +7,26 @@
 use crate::{
     ansible::{
         inventory::{
-            generate_environment_inventory, generate_private_node_static_environment_inventory,
+            generate_environment_inventory, generate_offline_environment_inventory,
+            generate_private_node_static_environment_inventory, get_inventory_provider_name,
             AnsibleInventoryType,
         },
         provisioning::AnsibleProvisioner,
         AnsibleRunner,
     },
     get_bootstrap_cache_url, get_environment_details, get_genesis_multiaddr,
+    rpc_proxy::{
+        get_auth_token_path, get_rpc_proxy_hostname, read_auth_token, RPC_PROXY_PORT_OFFSET,
+    },
     s3::S3Repository,
     ssh::SshClient,
-    terraform::TerraformRunner,
-    BinaryOption, CloudProvider, DeploymentType, EnvironmentDetails, Error, TestnetDeployer,
+    terraform::{InfraOutputs, TerraformRunner},
+    write_environment_details, BinaryOption, CloudProvider, DeploymentType, EnvironmentDetails,
+    Error, TestnetDeployer,
 };
 use alloy::hex::ToHexExt;
 use ant_service_management::{NodeRegistry, ServiceStatus};
 use color_eyre::{eyre::eyre, Result};
-use log::debug;
 use rand::seq::{IteratorRandom, SliceRandom};
 use semver::Version;
 use serde::{Deserialize, Serialize};
@@ -34,10 +38,14 @@ use std::{
     net::{IpAddr, SocketAddr},
     path::PathBuf,
 };
+use tracing::debug;
 
 const DEFAULT_CONTACTS_COUNT: usize = 100;
 const UNAVAILABLE_NODE: &str = "-";
 const TESTNET_BUCKET_NAME: &str = "sn-testnet";
+/// The nodes on a door node VM listen on consecutive ports from this one, rather than ports
+/// assigned by the node manager, so their addresses stay the same when they are redeployed.
+pub const DOOR_NODE_START_PORT: u16 = 12000;
 
 pub struct DeploymentInventoryService {
     pub ansible_runner: AnsibleRunner,
@@ -55,10 +63,7 @@ pub struct DeploymentInventoryService {
 
 impl From<&TestnetDeployer> for DeploymentInventoryService {
     fn from(item: &TestnetDeployer) -> Self {
-        let provider = match item.cloud_provider {
-            CloudProvider::Aws => "aws",
-            CloudProvider::DigitalOcean => "digital_ocean",
-        };
+        let provider = get_inventory_provider_name(item.cloud_provider);
         DeploymentInventoryService {
             ansible_runner: item.ansible_provisioner.ansible_runner.clone(),
             ansible_provisioner: item.ansible_provisioner.clone(),
@@ -97,9 +102,9 @@ impl DeploymentInventoryService {
         force: bool,
         binary_option: Option<BinaryOption>,
     ) -> Result<DeploymentInventory> {
-        println!("======================================");
-        println!("  Generating or Retrieving Inventory  ");
-        println!("======================================");
+        progress!("======================================");
+        progress!("  Generating or Retrieving Inventory  ");
+        progress!("======================================");
         let inventory_path = get_data_directory()?.join(format!("{name}-inventory.json"));
         if inventory_path.exists() && !force {
             let inventory = DeploymentInventory::read(&inventory_path)?;
@@ -132,7 +137,7 @@ impl DeploymentInventoryService {
         let environment_details = match get_environment_details(name, &self.s3_repository).await {
             Ok(details) => details,
             Err(Error::EnvironmentDetailsNotFound(_)) => {
-                println!("Environment details not found: treating this as a new deployment");
+                progress!("Environment details not found: treating this as a new deployment");
                 return Ok(DeploymentInventory::empty(
                     name,
                     binary_option.ok_or_else(|| {
@@ -143,33 +148,81 @@ impl DeploymentInventoryService {
             Err(e) => return Err(e.into()),
         };
 
-        let genesis_vm = self
-            .ansible_runner
-            .get_inventory(AnsibleInventoryType::Genesis, false)?;
-
-        let mut misc_vms = Vec::new();
-        let build_vm = self
-            .ansible_runner
-            .get_inventory(AnsibleInventoryType::Build, false)?;
-        misc_vms.extend(build_vm);
+        // The VMs are read from the Terraform outputs. An environment that has not been applied
+        // since the outputs were added does not have them, so the inventory plugin is used instead.
+        // In offline mode, the runner reads the static inventories from a previous run.
+        let offline_inventory = self.ansible_runner.offline_inventory;
+        let infra_outputs = match offline_inventory {
+            true => None,
+            false => match self.terraform_runner.output::<InfraOutputs>(name) {
+                Ok(outputs) => Some(outputs),
+                Err(err) => {
+                    debug!("Could not read the Terraform outputs for {name}: {err}");
+                    None
+                }
+            },
+        };
+        let fetch_vms = |inventory_type: AnsibleInventoryType| -> Result<Vec<VirtualMachine>> {
+            match infra_outputs
+                .as_ref()
+                .and_then(|outputs| outputs.get_vms(&inventory_type))
+            {
+                Some(vms) => Ok(vms?),
+                None => Ok(self.ansible_runner.get_inventory(inventory_type, false)?),
+            }
+        };
 
-        let nat_gateway_vm = self
-            .ansible_runner
-            .get_inventory(AnsibleInventoryType::NatGateway, false)?
-            .first()
-            .cloned();
+        // Each group is retrieved once. The static inventories for offline mode are refreshed
+        // from the same lists.
+        let mut vms_by_type = HashMap::new();
+        for inventory_type in AnsibleInventoryType::iter_offline() {
+            let vms = fetch_vms(inventory_type)?;
+            if !offline_inventory {
+                generate_offline_environment_inventory(
+                    name,
+                    &output_inventory_dir_path,
+                    &inventory_type,
+                    &vms,
+                )?;
+            }
+            vms_by_type.insert(inventory_type.tag().to_string(), vms);
+        }
+        let get_vms = |inventory_type: AnsibleInventoryType| -> Vec<VirtualMachine> {
+            vms_by_type
+                .get(inventory_type.tag())
+                .cloned()
+                .unwrap_or_default()
+        };
 
-        let generic_node_vms = self
-            .ansible_runner
-            .get_inventory(AnsibleInventoryType::Nodes, false)?;
+        let genesis_vm = get_vms(AnsibleInventoryType::Genesis);
 
-        let private_node_vms = self
-            .ansible_runner
-            .get_inventory(AnsibleInventoryType::PrivateNodes, false)?;
+        let mut misc_vms = Vec::new();
+        let build_vm = get_vms(AnsibleInventoryType::Build);
+        misc_vms.extend(build_vm);
+        let artifact_proxy_vm = get_vms(AnsibleInventoryType::ArtifactProxy);
+        misc_vms.extend(artifact_proxy_vm);
+        let downloader_vms = get_vms(AnsibleInventoryType::Downloaders);
+        misc_vms.extend(downloader_vms);
+        let monitoring_vm = get_vms(AnsibleInventoryType::Monitoring);
+        misc_vms.extend(monitoring_vm);
+        let auditor_vms = get_vms(AnsibleInventoryType::Auditor);
+        misc_vms.extend(auditor_vms);
+
+        // Any gateways beyond the first are listed with the other VMs. The first is the SSH jump
+        // host for the private nodes.
+        let mut nat_gateway_vms = get_vms(AnsibleInventoryType::NatGateway);
+        nat_gateway_vms.sort_by(|a, b| a.name.cmp(&b.name));
+        let nat_gateway_vm = nat_gateway_vms.first().cloned();
+        misc_vms.extend(nat_gateway_vms.into_iter().skip(1));
+
+        let generic_node_vms = get_vms(AnsibleInventoryType::Nodes);
+
+        let private_node_vms = get_vms(AnsibleInventoryType::PrivateNodes);
 
         // Create static inventory for private nodes. Will be used during ansible-playbook run.
         generate_private_node_static_environment_inventory(
             name,
+            self.cloud_provider,
             &output_inventory_dir_path,
             &private_node_vms,
             &nat_gateway_vm,
@@ -182,12 +235,10 @@ impl DeploymentInventoryService {
                 .set_routed_vms(private_node_vms.clone(), nat_gateway.public_ip_addr)?;
         }
 
-        let peer_cache_node_vms = self
-            .ansible_runner
-            .get_inventory(AnsibleInventoryType::PeerCacheNodes, false)?;
+        let peer_cache_node_vms = get_vms(AnsibleInventoryType::PeerCacheNodes);
 
         let uploader_vms = if environment_details.deployment_type != DeploymentType::Bootstrap {
-            let uploader_and_sks = self.ansible_provisioner.get_uploader_secret_keys()?;
+            let uploader_and_sks = self.ansible_provisioner.get_uploader_secret_keys().await?;
             uploader_and_sks
                 .iter()
                 .map(|(vm, sks)| UploaderVirtualMachine {
@@ -203,7 +254,7 @@ impl DeploymentInventoryService {
             Vec::new()
         };
 
-        println!("Retrieving node registries from all VMs...");
+        progress!("Retrieving node registries from all VMs...");
         let mut failed_node_registry_vms = Vec::new();
 
         let peer_cache_node_registries = self
@@ -281,27 +332,31 @@ impl DeploymentInventoryService {
                     ));
                 };
 
-                self.get_bin_version(&random_vm.vm, "antctl --version", "Autonomi Node Manager v")?
+                self.get_bin_version(&random_vm.vm, "antctl --version", "Autonomi Node Manager v")
+                    .await?
             };
 
             let ant_version = if environment_details.deployment_type != DeploymentType::Bootstrap {
                 let random_uploader_vm = uploader_vms
                     .choose(&mut rand::thread_rng())
                     .ok_or_else(|| eyre!("No uploader VMs available to retrieve ant version"))?;
-                Some(self.get_bin_version(
-                    &random_uploader_vm.vm,
-                    "ant --version",
-                    "Autonomi Client v",
-                )?)
+                Some(
+                    self.get_bin_version(
+                        &random_uploader_vm.vm,
+                        "ant --version",
+                        "Autonomi Client v",
+                    )
+                    .await?,
+                )
             } else {
                 None
             };
 
-            println!("Retrieved binary versions from previous deployment:");
-            println!("  antnode: {}", antnode_version);
-            println!("  antctl: {}", antctl_version);
+            progress!("Retrieved binary versions from previous deployment:");
+            progress!("  antnode: {}", antnode_version);
+            progress!("  antctl: {}", antctl_version);
             if let Some(version) = &ant_version {
-                println!("  ant: {}", version);
+                progress!("  ant: {}", version);
             }
 
             BinaryOption::Versioned {
@@ -313,7 +368,7 @@ impl DeploymentInventoryService {
 
         let (genesis_multiaddr, genesis_ip) =
             if environment_details.deployment_type == DeploymentType::New {
-                match get_genesis_multiaddr(&self.ansible_runner, &self.ssh_client) {
+                match get_genesis_multiaddr(&self.ansible_runner, &self.ssh_client).await {
                     Ok((multiaddr, ip)) => (Some(multiaddr), Some(ip)),
                     Err(_) => (None, None),
                 }
@@ -359,8 +414,8 @@ impl DeploymentInventoryService {
         let nat_gateway_vm = self
             .ansible_runner
             .get_inventory(AnsibleInventoryType::NatGateway, false)?
-            .first()
-            .cloned();
+            .into_iter()
+            .min_by(|a, b| a.name.cmp(&b.name));
 
         let private_node_vms = self
             .ansible_runner
@@ -369,6 +424,7 @@ impl DeploymentInventoryService {
         // Create static inventory for private nodes. Will be used during ansible-playbook run.
         generate_private_node_static_environment_inventory(
             name,
+            self.cloud_provider,
             &output_inventory_dir_path,
             &private_node_vms,
             &nat_gateway_vm,
@@ -384,11 +440,22 @@ impl DeploymentInventoryService {
         Ok(())
     }
 
+    /// Publish a sample of the peers in the deployment, so clients and other networks can join
+    /// it without being given a peer.
+    ///
+    /// Only the addresses of nodes that could be reached when the inventory was generated are
+    /// included. The file is uploaded to the `sn-testnet` bucket unless another is supplied, and
+    /// its public URL is recorded in the environment details, so a bootstrap deployment can refer
+    /// to the environment by name.
+    ///
+    /// Returns the URL of the published file.
     pub async fn upload_network_contacts(
         &self,
         inventory: &DeploymentInventory,
         contacts_file_name: Option<String>,
-    ) -> Result<()> {
+        contacts_bucket_name: Option<String>,
+    ) -> Result<String> {
+        let bucket_name = contacts_bucket_name.unwrap_or_else(|| TESTNET_BUCKET_NAME.to_string());
         let temp_dir_path = tempfile::tempdir()?.into_path();
         let temp_file_path = if let Some(file_name) = contacts_file_name {
             temp_dir_path.join(file_name)
@@ -402,6 +469,7 @@ impl DeploymentInventoryService {
         let peer_cache_peers = inventory
             .peer_cache_node_vms
             .iter()
+            .filter(|vm| !inventory.is_door_node_vm(&vm.vm))
             .flat_map(|vm| vm.get_quic_addresses())
             .collect::<Vec<_>>();
         let peer_cache_peers_len = peer_cache_peers.len();
@@ -431,20 +499,82 @@ impl DeploymentInventoryService {
         }
 
         self.s3_repository
-            .upload_file(TESTNET_BUCKET_NAME, &temp_file_path, true)
+            .upload_file(&bucket_name, &temp_file_path, true)
             .await?;
+        let contacts_url = format!(
+            "https://{bucket_name}.s3.eu-west-2.amazonaws.com/{}",
+            temp_file_path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| Error::FilenameNotRetrieved)?
+        );
+        progress!("Published the network contacts to {contacts_url}");
+
+        // The door nodes are published in their own file, using their DNS names rather than
+        // their IP addresses, so clients can rely on them as a stable entry point.
+        let door_node_vms = inventory.get_door_node_vms();
+        if !door_node_vms.is_empty() {
+            let mut door_nodes_file_path = temp_file_path.clone().into_os_string();
+            door_nodes_file_path.push("-door-nodes");
+            let door_nodes_file_path = PathBuf::from(door_nodes_file_path);
+            let mut file = std::fs::File::create(&door_nodes_file_path)?;
+            for (dns_name, node_vm) in door_node_vms.iter() {
+                let ip_prefix = format!("/ip4/{}", node_vm.vm.public_ip_addr);
+                for addr in node_vm
+                    .get_quic_addresses()
+                    .iter()
+                    .filter(|addr| addr.starts_with(&ip_prefix))
+                {
+                    writeln!(
+                        file,
+                        "{}",
+                        addr.replacen(&ip_prefix, &format!("/dns4/{dns_name}"), 1)
+                    )?;
+                }
+            }
+            self.s3_repository
+                .upload_file(&bucket_name, &door_nodes_file_path, true)
+                .await?;
+        }
 
-        Ok(())
+        // Environments deployed before the details were recorded don't have any to update.
+        match get_environment_details(&inventory.name, &self.s3_repository).await {
+            Ok(mut environment_details) => {
+                environment_details.network_contacts_url = Some(contacts_url.clone());
+                write_environment_details(
+                    &self.s3_repository,
+                    &inventory.name,
+                    &environment_details,
+                )
+                .await?;
+            }
+            Err(err) => {
+                debug!(
+                    "Not recording the network contacts URL for {}: {err}",
+                    inventory.name
+                );
+            }
+        }
+
+        Ok(contacts_url)
     }
 
     /// Connects to a VM with SSH and runs a command to retrieve the version of a binary.
-    fn get_bin_version(&self, vm: &VirtualMachine, command: &str, prefix: &str) -> Result<Version> {
-        let output = self.ssh_client.run_command(
-            &vm.public_ip_addr,
-            &self.cloud_provider.get_ssh_user(),
-            command,
-            true,
-        )?;
+    async fn get_bin_version(
+        &self,
+        vm: &VirtualMachine,
+        command: &str,
+        prefix: &str,
+    ) -> Result<Version> {
+        let output = self
+            .ssh_client
+            .execute(
+                &vm.public_ip_addr,
+                &self.cloud_provider.get_ssh_user(),
+                command,
+            )
+            .await?
+            .into_stdout_lines()?;
         let version_line = output
             .first()
             .ok_or_else(|| eyre!("No output from {} command", command))?;
@@ -595,6 +725,27 @@ impl DeploymentNodeRegistries {
         }
     }
 
+    /// Get the number of nodes in each state on each VM whose registry was retrieved.
+    ///
+    /// Nodes that have been removed are not counted.
+    pub fn get_health_counts(&self) -> Vec<(String, NodeHealthCounts)> {
+        self.retrieved_registries
+            .iter()
+            .map(|(vm_name, registry)| {
+                let mut counts = NodeHealthCounts::default();
+                for node in registry.nodes.iter() {
+                    match node.status {
+                        ServiceStatus::Running => counts.running += 1,
+                        ServiceStatus::Stopped => counts.stopped += 1,
+                        ServiceStatus::Added => counts.added += 1,
+                        ServiceStatus::Removed => {}
+                    }
+                }
+                (vm_name.clone(), counts)
+            })
+            .collect()
+    }
+
     fn format_status(status: &ServiceStatus) -> String {
         match status {
             ServiceStatus::Running => "RUNNING".to_string(),
@@ -617,6 +768,154 @@ impl DeploymentNodeRegistries {
     }
 }
 
+/// The number of nodes in each state on a VM.
+#[derive(Clone, Debug, Default)]
+pub struct NodeHealthCounts {
+    /// Nodes that were added but have never been started.
+    pub added: usize,
+    pub running: usize,
+    pub stopped: usize,
+}
+
+impl NodeHealthCounts {
+    pub fn total(&self) -> usize {
+        self.added + self.running + self.stopped
+    }
+
+    pub fn failed(&self) -> usize {
+        self.added + self.stopped
+    }
+}
+
+/// Print the number of running and failed nodes on each VM, with the totals for the deployment.
+///
+/// Returns the percentage of nodes that are not running. As with the uptime history, each VM whose
+/// registry could not be retrieved is counted as a single failed node.
+pub fn print_health_summary(registries: &[DeploymentNodeRegistries]) -> f64 {
+    println!("==============");
+    println!("Health Summary");
+    println!("==============");
+    let mut totals = NodeHealthCounts::default();
+    let mut unreachable_vms = 0;
+    for deployment_registries in registries.iter() {
+        for (vm_name, counts) in deployment_registries.get_health_counts() {
+            println!(
+                "{vm_name}: {} running, {} stopped, {} added",
+                counts.running, counts.stopped, counts.added
+            );
+            totals.added += counts.added;
+            totals.running += counts.running;
+            totals.stopped += counts.stopped;
+        }
+        for vm_name in deployment_registries.failed_vms.iter() {
+            println!("{vm_name}: unreachable");
+            unreachable_vms += 1;
+        }
+    }
+
+    let total = totals.total() + unreachable_vms;
+    let failed = totals.failed() + unreachable_vms;
+    let failure_pct = if total == 0 {
+        0.0
+    } else {
+        failed as f64 / total as f64 * 100.0
+    };
+    println!(
+        "Total: {} running, {} stopped, {} added, {unreachable_vms} unreachable VMs",
+        totals.running, totals.stopped, totals.added
+    );
+    println!("Failure: {failure_pct:.2}%");
+    failure_pct
+}
+
+/// A VM that is running fewer nodes than expected.
+#[derive(Clone, Debug)]
+pub struct NodeCountShortfall {
+    pub vm_name: String,
+    pub expected: usize,
+    pub running: usize,
+}
+
+/// The expected number of nodes for the deployment, compared with the number actually running.
+#[derive(Clone, Debug, Default)]
+pub struct NodeCountReconciliation {
+    pub expected_total: usize,
+    pub running_total: usize,
+    pub shortfalls: Vec<NodeCountShortfall>,
+}
+
+impl NodeCountReconciliation {
+    pub fn is_complete(&self) -> bool {
+        self.shortfalls.is_empty()
+    }
+
+    pub fn print(&self) {
+        println!("=========================");
+        println!("Node Count Reconciliation");
+        println!("=========================");
+        println!(
+            "Expected {} nodes, {} running",
+            self.expected_total, self.running_total
+        );
+        for shortfall in self.shortfalls.iter() {
+            println!(
+                "{}: {} of {} running (short by {})",
+                shortfall.vm_name,
+                shortfall.running,
+                shortfall.expected,
+                shortfall.expected - shortfall.running
+            );
+        }
+    }
+}
+
+/// Compare the number of running nodes on each VM against the number recorded in the environment
+/// details when the deployment was created or upscaled.
+///
+/// Each VM whose registry could not be retrieved is treated as running no nodes. Node types with
+/// no recorded count, e.g., for environments created before the counts were recorded, are skipped.
+pub fn reconcile_node_counts(
+    registries: &[DeploymentNodeRegistries],
+    environment_details: &EnvironmentDetails,
+) -> NodeCountReconciliation {
+    let mut reconciliation = NodeCountReconciliation::default();
+    for deployment_registries in registries.iter() {
+        let expected = match deployment_registries.inventory_type {
+            AnsibleInventoryType::Genesis => Some(1),
+            AnsibleInventoryType::Nodes => environment_details.node_count,
+            AnsibleInventoryType::PeerCacheNodes => environment_details.peer_cache_node_count,
+            AnsibleInventoryType::PrivateNodes => environment_details.private_node_count,
+            _ => None,
+        };
+        let Some(expected) = expected.map(|count| count as usize) else {
+            continue;
+        };
+
+        let running_counts = deployment_registries
+            .get_health_counts()
+            .into_iter()
+            .map(|(vm_name, counts)| (vm_name, counts.running))
+            .chain(
+                deployment_registries
+                    .failed_vms
+                    .iter()
+                    .map(|vm_name| (vm_name.clone(), 0)),
+            );
+        for (vm_name, running) in running_counts {
+            reconciliation.expected_total += expected;
+            reconciliation.running_total += running;
+            if running < expected {
+                reconciliation.shortfalls.push(NodeCountShortfall {
+                    vm_name,
+                    expected,
+                    running,
+                });
+            }
+        }
+    }
+    reconciliation
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DeploymentInventory {
     pub binary_option: BinaryOption,
@@ -661,6 +960,107 @@ impl DeploymentInventory {
         }
     }
 
+    /// Stitch the inventory of another part of the same environment into this one.
+    ///
+    /// This is intended for environments whose VMs are spread across more than one Terraform
+    /// workspace, e.g., one per cloud provider. The binary option, environment details, genesis
+    /// and SSH settings of `self` are retained, so `self` should be the inventory for the part of
+    /// the environment that hosts the genesis node.
+    pub fn merge(&mut self, other: DeploymentInventory) {
+        if self.genesis_vm.is_none() {
+            self.genesis_vm = other.genesis_vm;
+            self.genesis_multiaddr = other.genesis_multiaddr;
+            self.faucet_address = other.faucet_address;
+        }
+        if self.nat_gateway_vm.is_none() {
+            self.nat_gateway_vm = other.nat_gateway_vm;
+        }
+        self.failed_node_registry_vms
+            .extend(other.failed_node_registry_vms);
+        self.misc_vms.extend(other.misc_vms);
+        self.node_vms.extend(other.node_vms);
+        self.peer_cache_node_vms.extend(other.peer_cache_node_vms);
+        self.private_node_vms.extend(other.private_node_vms);
+        self.uploaded_files.extend(other.uploaded_files);
+        self.uploader_vms.extend(other.uploader_vms);
+    }
+
+    /// Get the Peer Cache VMs that are used as door nodes, along with their DNS names.
+    pub fn get_door_node_vms(&self) -> Vec<(String, &NodeVirtualMachine)> {
+        let (Some(door_node_count), Some(domain)) = (
+            self.environment_details.door_node_count,
+            &self.environment_details.door_node_dns_domain,
+        ) else {
+            return Vec::new();
+        };
+        self.peer_cache_node_vms
+            .iter()
+            .filter_map(|node_vm| {
+                let number = get_door_node_number(&node_vm.vm.name, door_node_count)?;
+                let dns_name = format!("{}-door-{number}.{domain}", self.name.to_lowercase());
+                Some((dns_name, node_vm))
+            })
+            .collect()
+    }
+
+    /// Get the DNS names of the genesis node and the Peer Cache nodes, if records were created for
+    /// the environment.
+    pub fn get_dns_names(&self) -> Option<(String, String)> {
+        let domain = self.environment_details.dns_domain.as_ref()?;
+        let name = self.name.to_lowercase();
+        Some((
+            format!("genesis.{name}.{domain}"),
+            format!("peers.{name}.{domain}"),
+        ))
+    }
+
+    /// Print the TLS endpoints of the RPC proxy on each public node VM.
+    ///
+    /// Each node is served on its own port, so the range of ports on each VM is listed.
+    pub fn print_rpc_proxy_endpoints(&self) -> Result<()> {
+        println!("===================");
+        println!("RPC Proxy Endpoints");
+        println!("===================");
+        for node_vm in self
+            .genesis_vm
+            .iter()
+            .chain(self.peer_cache_node_vms.iter())
+            .chain(self.node_vms.iter())
+        {
+            let (Some(first_port), Some(last_port)) = (
+                node_vm.rpc_endpoint.values().map(|addr| addr.port()).min(),
+                node_vm.rpc_endpoint.values().map(|addr| addr.port()).max(),
+            ) else {
+                println!("{}: no nodes", node_vm.vm.name);
+                continue;
+            };
+            println!(
+                "{}: https://{}:{}-{}",
+                node_vm.vm.name,
+                get_rpc_proxy_hostname(&node_vm.vm.public_ip_addr),
+                first_port + RPC_PROXY_PORT_OFFSET,
+                last_port + RPC_PROXY_PORT_OFFSET
+            );
+        }
+        match read_auth_token(&self.name)? {
+            Some(_) => println!(
+                "Auth token: {}",
+                get_auth_token_path(&self.name)?.to_string_lossy()
+            ),
+            None => println!(
+                "Auth token: not available, because the environment was deployed elsewhere"
+            ),
+        }
+        println!();
+        Ok(())
+    }
+
+    pub fn is_door_node_vm(&self, vm: &VirtualMachine) -> bool {
+        self.get_door_node_vms()
+            .iter()
+            .any(|(_, node_vm)| node_vm.vm == *vm)
+    }
+
     pub fn get_tfvars_filename(&self) -> String {
         let filename = self
             .environment_details
@@ -735,12 +1135,21 @@ impl DeploymentInventory {
 
     pub fn save(&self) -> Result<()> {
         let path = get_data_directory()?.join(format!("{}-inventory.json", self.name));
-        let serialized_data = serde_json::to_string_pretty(self)?;
+        let serialized_data = self.to_json()?;
         let mut file = File::create(path)?;
         file.write_all(serialized_data.as_bytes())?;
         Ok(())
     }
 
+    /// Serialize the inventory as JSON, for use by other tools.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_yaml(&self) -> Result<String> {
+        Ok(serde_yaml::to_string(self)?)
+    }
+
     pub fn read(file_path: &PathBuf) -> Result<Self> {
         let data = std::fs::read_to_string(file_path)?;
         let deserialized_data: DeploymentInventory = serde_json::from_str(&data)?;
@@ -824,6 +1233,32 @@ impl DeploymentInventory {
                 );
                 println!("safenode version: {}", safenode_version);
                 println!("safenode-manager version: {}", safenode_manager_version);
+                if let Some(release) = &self.environment_details.release {
+                    match &release.tag {
+                        Some(tag) => println!("Release channel: {} ({tag})", release.channel),
+                        None => println!("Release channel: {}", release.channel),
+                    }
+                }
+                println!();
+            }
+            BinaryOption::Local {
+                ant_path,
+                antctl_path,
+                antctld_path,
+                antnode_path,
+            } => {
+                println!("==============");
+                println!("Local Binaries");
+                println!("==============");
+                println!(
+                    "ant: {}",
+                    ant_path
+                        .as_ref()
+                        .map_or("N/A".to_string(), |p| p.display().to_string())
+                );
+                println!("antctl: {}", antctl_path.display());
+                println!("antctld: {}", antctld_path.display());
+                println!("antnode: {}", antnode_path.display());
                 println!();
             }
         }
@@ -839,6 +1274,36 @@ impl DeploymentInventory {
             println!("SSH user: {}", self.ssh_user);
             println!();
 
+            let door_node_vms = self.get_door_node_vms();
+            if !door_node_vms.is_empty() {
+                println!("==========");
+                println!("Door Nodes");
+                println!("==========");
+                for (dns_name, node_vm) in door_node_vms.iter() {
+                    println!("{dns_name}: {}", node_vm.vm.public_ip_addr);
+                }
+                let node_count = self.peer_cache_node_count() as u16;
+                if node_count > 0 {
+                    println!(
+                        "Node ports: {}-{}",
+                        DOOR_NODE_START_PORT,
+                        DOOR_NODE_START_PORT + node_count - 1
+                    );
+                }
+                println!();
+            }
+
+            if let Some((genesis_dns_name, peers_dns_name)) = self.get_dns_names() {
+                println!("===========");
+                println!("DNS Records");
+                println!("===========");
+                if self.genesis_vm.is_some() {
+                    println!("Genesis: {genesis_dns_name}");
+                }
+                println!("Peer Cache nodes: {peers_dns_name}");
+                println!();
+            }
+
             self.print_peer_cache_webserver();
         }
 
@@ -855,6 +1320,10 @@ impl DeploymentInventory {
         println!("SSH user: {}", self.ssh_user);
         println!();
 
+        if self.environment_details.rpc_proxy == Some(true) {
+            self.print_rpc_proxy_endpoints()?;
+        }
+
         println!("=================");
         println!("Private Node VMs");
         println!("=================");
@@ -863,13 +1332,19 @@ impl DeploymentInventory {
             if let Some(nat_gateway_vm) = &self.nat_gateway_vm {
                 let ssh = if let Some(ssh_key_path) = self.ssh_private_key_path.to_str() {
                     format!(
-                        "ssh -i {ssh_key_path} -o ProxyCommand=\"ssh -W %h:%p root@{} -i {ssh_key_path}\" root@{}",
-                        nat_gateway_vm.public_ip_addr, node_vm.vm.private_ip_addr
+                        "ssh -i {ssh_key_path} -o ProxyCommand=\"ssh -W %h:%p {}@{} -i {ssh_key_path}\" {}@{}",
+                        self.ssh_user,
+                        nat_gateway_vm.public_ip_addr,
+                        self.ssh_user,
+                        node_vm.vm.private_ip_addr
                     )
                 } else {
                     format!(
-                        "ssh -o ProxyCommand=\"ssh -W %h:%p root@{}\" root@{}",
-                        nat_gateway_vm.public_ip_addr, node_vm.vm.private_ip_addr
+                        "ssh -o ProxyCommand=\"ssh -W %h:%p {}@{}\" {}@{}",
+                        self.ssh_user,
+                        nat_gateway_vm.public_ip_addr,
+                        self.ssh_user,
+                        node_vm.vm.private_ip_addr
                     )
                 };
                 println!("SSH using NAT gateway: {ssh}");
@@ -888,6 +1363,13 @@ impl DeploymentInventory {
             for uploader_vm in self.uploader_vms.iter() {
                 println!("{}: {}", uploader_vm.vm.name, uploader_vm.vm.public_ip_addr);
             }
+            println!(
+                "Bandwidth class: {}",
+                self.environment_details
+                    .uploader_bandwidth_class
+                    .unwrap_or_default()
+                    .as_str()
+            );
             println!();
 
             println!("===========================");
@@ -914,6 +1396,20 @@ impl DeploymentInventory {
         if let Some(nat_gateway_vm) = &self.nat_gateway_vm {
             println!("{}: {}", nat_gateway_vm.name, nat_gateway_vm.public_ip_addr);
         }
+        if let Some(monitoring_vm) = self
+            .misc_vms
+            .iter()
+            .find(|vm| vm.name.ends_with("-monitoring"))
+        {
+            println!("Grafana: http://{}:3000", monitoring_vm.public_ip_addr);
+        }
+        for auditor_vm in self
+            .misc_vms
+            .iter()
+            .filter(|vm| vm.name.contains("-auditor-"))
+        {
+            println!("Auditor: http://{}:4242", auditor_vm.public_ip_addr);
+        }
 
         println!("SSH user: {}", self.ssh_user);
         println!();
@@ -1043,6 +1539,18 @@ impl DeploymentInventory {
     }
 }
 
+/// Get the door node number of a Peer Cache VM, or `None` if it isn't one of the door nodes.
+///
+/// The door nodes are the lowest-numbered Peer Cache VMs, which is the same selection the DNS
+/// records are created for in Terraform.
+pub fn get_door_node_number(vm_name: &str, door_node_count: u16) -> Option<u16> {
+    let number = vm_name
+        .rsplit('-')
+        .next()
+        .and_then(|suffix| suffix.parse::<u16>().ok())?;
+    (number > 0 && number <= door_node_count).then_some(number)
+}
+
 pub fn get_data_directory() -> Result<PathBuf> {
     let path = dirs_next::data_dir()
         .ok_or_else(|| eyre!("Could not retrieve data directory"))?
@@ -1053,3 +1561,117 @@ pub fn get_data_directory() -> Result<PathBuf> {
     }
     Ok(path)
 }
+
+/// Get the names of the environments that have a cached inventory, most recently used first.
+pub fn get_cached_environment_names() -> Result<Vec<String>> {
+    let mut environments = Vec::new();
+    for entry in std::fs::read_dir(get_data_directory()?)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if let Some(name) = file_name.strip_suffix("-inventory.json") {
+            let modified = entry.metadata()?.modified()?;
+            environments.push((modified, name.to_string()));
+        }
+    }
+    environments.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    Ok(environments.into_iter().map(|(_, name)| name).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color_eyre::Result;
+    use std::net::Ipv4Addr;
+
+    fn get_node_vm(name: &str, last_octet: u8) -> NodeVirtualMachine {
+        NodeVirtualMachine {
+            vm: VirtualMachine {
+                id: last_octet as u64,
+                name: name.to_string(),
+                public_ip_addr: IpAddr::V4(Ipv4Addr::new(10, 0, 0, last_octet)),
+                private_ip_addr: IpAddr::V4(Ipv4Addr::new(10, 1, 0, last_octet)),
+            },
+            node_count: 1,
+            node_listen_addresses: Vec::new(),
+            rpc_endpoint: HashMap::new(),
+            safenodemand_endpoint: None,
+        }
+    }
+
+    fn get_binary_option() -> Result<BinaryOption> {
+        Ok(BinaryOption::Versioned {
+            ant_version: None,
+            antctl_version: "0.11.3".parse()?,
+            antnode_version: "0.3.1".parse()?,
+        })
+    }
+
+    #[test]
+    fn test_merge_keeps_the_genesis_of_self() -> Result<()> {
+        let mut inventory = DeploymentInventory::empty("alpha", get_binary_option()?);
+        inventory.genesis_vm = Some(get_node_vm("alpha-genesis", 1));
+        inventory.genesis_multiaddr = Some("/ip4/10.0.0.1/udp/12000/quic-v1".to_string());
+        inventory.node_vms.push(get_node_vm("alpha-node-1", 2));
+
+        let mut other = DeploymentInventory::empty("alpha", get_binary_option()?);
+        other.genesis_vm = Some(get_node_vm("alpha-aws-genesis", 3));
+        other.node_vms.push(get_node_vm("alpha-aws-node-1", 4));
+        other
+            .peer_cache_node_vms
+            .push(get_node_vm("alpha-aws-peer-cache-node-1", 5));
+        other
+            .failed_node_registry_vms
+            .push("alpha-aws-node-2".to_string());
+
+        inventory.merge(other);
+
+        assert_eq!(
+            Some("alpha-genesis".to_string()),
+            inventory.genesis_vm.as_ref().map(|vm| vm.vm.name.clone())
+        );
+        assert_eq!(
+            Some("/ip4/10.0.0.1/udp/12000/quic-v1".to_string()),
+            inventory.genesis_multiaddr
+        );
+        assert_eq!(
+            vec!["alpha-node-1", "alpha-aws-node-1"],
+            inventory
+                .node_vms
+                .iter()
+                .map(|vm| vm.vm.name.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(1, inventory.peer_cache_node_vms.len());
+        assert_eq!(
+            vec!["alpha-aws-node-2".to_string()],
+            inventory.failed_node_registry_vms
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_takes_the_genesis_and_gateway_of_other_when_self_has_none() -> Result<()> {
+        let mut inventory = DeploymentInventory::empty("alpha", get_binary_option()?);
+        inventory.node_vms.push(get_node_vm("alpha-node-1", 2));
+
+        let mut other = DeploymentInventory::empty("alpha", get_binary_option()?);
+        other.genesis_vm = Some(get_node_vm("alpha-genesis", 1));
+        other.genesis_multiaddr = Some("/ip4/10.0.0.1/udp/12000/quic-v1".to_string());
+        other.faucet_address = Some("10.0.0.1:8000".to_string());
+        other.nat_gateway_vm = Some(get_node_vm("alpha-nat-gateway-1", 9).vm);
+
+        inventory.merge(other);
+
+        assert_eq!(
+            Some("alpha-genesis".to_string()),
+            inventory.genesis_vm.as_ref().map(|vm| vm.vm.name.clone())
+        );
+        assert_eq!(Some("10.0.0.1:8000".to_string()), inventory.faucet_address);
+        assert_eq!(
+            Some("alpha-nat-gateway-1".to_string()),
+            inventory.nat_gateway_vm.map(|vm| vm.name)
+        );
+        assert_eq!(1, inventory.node_vms.len());
+        Ok(())
+    }
+}
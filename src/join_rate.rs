@@ -0,0 +1,61 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    error::{Error, Result},
+    inventory::VirtualMachine,
+    NodeType,
+};
+use std::{collections::BTreeMap, net::IpAddr, time::Duration};
+
+/// Spreads the node starts over a set of VMs, so that the set as a whole joins the network at a
+/// fixed rate, rather than each VM starting its nodes at its own pace.
+///
+/// Every VM starts a node at the same interval, but the first start on each VM is offset, so the
+/// starts are evenly spaced across all the VMs.
+#[derive(Clone, Debug)]
+pub struct JoinRateSchedule {
+    /// The time between each node start on a VM.
+    pub interval: Duration,
+    /// How long each VM waits before it starts its first node, keyed by the address Ansible
+    /// connects to it on.
+    pub start_delays: BTreeMap<IpAddr, Duration>,
+}
+
+impl JoinRateSchedule {
+    /// Create the schedule for the VMs of a node type, with the given number of nodes joining
+    /// across all of them each minute.
+    ///
+    /// Ansible connects to the private node VMs through the NAT gateway, using their private
+    /// addresses, so those are used to key their delays.
+    pub fn new(
+        nodes_per_minute: u16,
+        node_type: &NodeType,
+        vms: &[VirtualMachine],
+    ) -> Result<Self> {
+        if nodes_per_minute == 0 {
+            return Err(Error::InvalidJoinRate(nodes_per_minute));
+        }
+        let spacing = Duration::from_secs(60) / nodes_per_minute as u32;
+        let mut vms = vms.to_vec();
+        vms.sort_by(|a, b| a.name.cmp(&b.name));
+        let start_delays = vms
+            .iter()
+            .enumerate()
+            .map(|(i, vm)| {
+                let addr = match node_type {
+                    NodeType::Private => vm.private_ip_addr,
+                    _ => vm.public_ip_addr,
+                };
+                (addr, spacing * i as u32)
+            })
+            .collect();
+        Ok(Self {
+            interval: spacing * vms.len().max(1) as u32,
+            start_delays,
+        })
+    }
+}
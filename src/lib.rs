@@ -4,28 +4,104 @@
 // This SAFE Network Software is licensed under the BSD-3-Clause license.
 // Please see the LICENSE file for more details.
 
+/// Print a progress message.
+///
+/// The message is written to the standard error rather than the standard output when the
+/// standard output has been reserved for machine-readable output.
+#[macro_export]
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        if $crate::is_stdout_reserved() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
 pub mod ansible;
+pub mod artifact_repository;
+pub mod artifacts;
+pub mod bisect;
 pub mod bootstrap;
+pub mod build_cache;
+pub mod chaos;
+pub mod client_regions;
+pub mod cost;
 pub mod deploy;
 pub mod digital_ocean;
+pub mod downloaders;
+pub mod downscale;
 pub mod error;
+pub mod faucet;
+pub mod firewall;
 pub mod funding;
+pub mod genesis;
+pub mod grafana;
+pub mod host_failures;
 pub mod infra;
 pub mod inventory;
+pub mod join_rate;
+pub mod local_binaries;
+pub mod lock;
+pub mod logging;
 pub mod logs;
 pub mod logstash;
+pub mod metrics;
+pub mod nat_gateway;
 pub mod network_commands;
+pub mod network_conditions;
+pub mod network_restart;
+pub mod node_rpc;
+pub mod notifications;
+pub mod partition;
+pub mod preflight;
+pub mod reaper;
+pub mod redact;
+pub mod release_channel;
+pub mod replay;
 pub mod reserved_ip;
 pub mod rpc_client;
+pub mod rpc_proxy;
+pub mod run_log;
 pub mod s3;
 pub mod safe;
+pub mod search;
 pub mod setup;
+pub mod slo;
+pub mod smoke_test;
+pub mod snapshot;
 pub mod ssh;
+pub mod status_badge;
+pub mod support_bundle;
+pub mod sysstat;
 pub mod terraform;
+pub mod throttle;
+pub mod trends;
+pub mod upload_backpressure;
 pub mod upscale;
+pub mod versions_file;
+pub mod wireguard;
 
 const STORAGE_REQUIRED_PER_NODE: u16 = 7;
 
+/// Environment variables that are known to be read by antnode or the libraries it uses.
+///
+/// Variables outside this list are still passed through to the node services, but a warning is
+/// given, because a typo in a variable name will otherwise go unnoticed.
+pub const KNOWN_NODE_ENV_VARIABLES: &[&str] = &[
+    "ANT_LOG",
+    "ANT_PEERS",
+    "DATA_PAYMENTS_ADDRESS",
+    "EVM_NETWORK",
+    "PAYMENT_TOKEN_ADDRESS",
+    "RPC_URL",
+    "RUST_BACKTRACE",
+    "RUST_LOG",
+    "RUST_MIN_STACK",
+    "SN_LOG",
+];
+
 use crate::{
     ansible::{
         extra_vars::ExtraVarsDocBuilder,
@@ -33,35 +109,60 @@ use crate::{
         provisioning::AnsibleProvisioner,
         AnsibleRunner,
     },
+    artifact_repository::ArtifactRepositoryConfig,
     error::{Error, Result},
-    inventory::{DeploymentInventory, VirtualMachine},
+    firewall::{delete_firewall, FirewallProfile},
+    inventory::{
+        reconcile_node_counts, DeploymentInventory, DeploymentNodeRegistries,
+        NodeCountReconciliation, VirtualMachine,
+    },
+    notifications::{EventSeverity, NotificationEvent, SlackNotifier},
+    release_channel::ResolvedRelease,
     rpc_client::RpcClient,
     s3::S3Repository,
+    slo::UptimeHistory,
     ssh::SshClient,
+    status_badge::EnvironmentStatus,
     terraform::TerraformRunner,
+    wireguard::{WireGuardOverlay, WIREGUARD_OVERLAY_ENV_VAR},
 };
 use alloy::primitives::Address;
 use evmlib::Network;
 use flate2::read::GzDecoder;
 use indicatif::{ProgressBar, ProgressStyle};
 use infra::InfraRunOptions;
-use log::{debug, trace};
 use semver::Version;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
 use std::{
+    collections::BTreeMap,
     fs::File,
     io::{BufRead, BufReader, BufWriter, Write},
     net::{IpAddr, SocketAddr},
     path::{Path, PathBuf},
     process::{Command, Stdio},
     str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
     time::Duration,
 };
 use tar::Archive;
+use tracing::{debug, trace};
 
 const ANSIBLE_DEFAULT_FORKS: usize = 50;
 
+static STDOUT_RESERVED: AtomicBool = AtomicBool::new(false);
+
+/// Reserve the standard output for machine-readable output, e.g., an inventory printed as JSON.
+///
+/// From then on, progress messages and the output of external commands are written to the
+/// standard error.
+pub fn reserve_stdout() {
+    STDOUT_RESERVED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_stdout_reserved() -> bool {
+    STDOUT_RESERVED.load(Ordering::Relaxed)
+}
+
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub enum DeploymentType {
     /// The deployment has been bootstrapped from an existing network.
@@ -178,15 +279,51 @@ impl std::str::FromStr for EvmNetwork {
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct EnvironmentDetails {
+    /// Recorded so that VMs added by an upscale run binaries for the same architecture.
+    pub architecture: Option<Architecture>,
+    /// Recorded so that VMs added by an upscale download binaries from the same place.
+    pub artifact_repository: Option<ArtifactRepositoryConfig>,
     pub deployment_type: DeploymentType,
+    /// The domain the genesis and Peer Cache DNS records are created under.
+    pub dns_domain: Option<String>,
+    /// The number of Peer Cache nodes that are used as door nodes, with stable DNS names.
+    pub door_node_count: Option<u16>,
+    pub door_node_dns_domain: Option<String>,
     pub environment_type: EnvironmentType,
     pub evm_network: EvmNetwork,
     pub evm_data_payments_address: Option<String>,
     pub evm_payment_token_address: Option<String>,
     pub evm_rpc_url: Option<String>,
+    /// The time after which the environment can be destroyed by the reaper, as a Unix timestamp.
+    pub expires_at: Option<i64>,
+    /// The profile the environment's firewall was created from, if it has one.
+    pub firewall: Option<FirewallProfile>,
     pub funding_wallet_address: Option<String>,
+    /// The name of the NAT gateway each private node VM is routed through, keyed by the name of
+    /// the VM. Recorded so the shards are not reshuffled when VMs are added, and so a failover is
+    /// kept when the private nodes are provisioned again.
+    pub nat_gateway_shards: Option<BTreeMap<String, String>>,
+    /// Recorded so that a gateway taking over a shard of private nodes uses the same NAT type.
+    pub nat_type: Option<NatType>,
+    /// The public URL of the network contacts file published for the environment.
+    pub network_contacts_url: Option<String>,
     pub network_id: Option<u8>,
+    /// The number of nodes per VM, recorded so the running nodes can be reconciled against it.
+    pub node_count: Option<u16>,
+    /// Recorded so that nodes added by an upscale use the same mode as the rest of the group.
+    pub node_reachability: Option<ReachabilityMode>,
+    pub peer_cache_node_count: Option<u16>,
+    pub peer_cache_node_reachability: Option<ReachabilityMode>,
+    pub private_node_count: Option<u16>,
+    /// Recorded so that a gateway taking over a shard of private nodes also runs a UPnP daemon.
+    pub private_node_upnp: Option<bool>,
+    /// The release channel the binary versions were resolved from, if one was used.
+    pub release: Option<ResolvedRelease>,
     pub rewards_address: String,
+    /// Set if the node RPC is exposed through a TLS reverse proxy on each VM.
+    pub rpc_proxy: Option<bool>,
+    pub uploader_bandwidth_class: Option<BandwidthClass>,
+    pub uploader_regions: Option<Vec<String>>,
 }
 
 #[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
@@ -259,7 +396,7 @@ pub struct DeployOptions {
     pub evm_network: EvmNetwork,
     pub evm_node_vm_size: Option<String>,
     pub log_format: Option<LogFormat>,
-    pub logstash_details: Option<(String, Vec<SocketAddr>)>,
+    pub telemetry: Option<TelemetryConfig>,
     pub name: String,
     pub node_count: u16,
     pub node_vm_count: Option<u16>,
@@ -296,6 +433,17 @@ pub enum BinaryOption {
         network_keys: Option<(String, String, String, String)>,
         repo_owner: String,
     },
+    /// Binaries built on the local machine will be uploaded to the artifact proxy, which serves
+    /// them to the other VMs.
+    ///
+    /// This avoids the build VM and S3, which shortens the loop of editing and deploying changes.
+    Local {
+        /// The uploaders can only be provisioned if the `ant` binary is supplied.
+        ant_path: Option<PathBuf>,
+        antctl_path: PathBuf,
+        antctld_path: PathBuf,
+        antnode_path: PathBuf,
+    },
     /// Pre-built, versioned binaries will be fetched from S3.
     Versioned {
         ant_version: Option<Version>,
@@ -320,7 +468,39 @@ impl std::fmt::Display for CloudProvider {
 }
 
 impl CloudProvider {
+    /// The user for SSH connections to the VMs.
+    ///
+    /// The default user for the provider's images can be overridden with the provider's variable,
+    /// e.g., `DO_SSH_USER`, for an image that disallows root login.
     pub fn get_ssh_user(&self) -> String {
+        std::env::var(self.get_ssh_user_env_var()).unwrap_or_else(|_| self.get_default_ssh_user())
+    }
+
+    /// The user for the first connection to a new VM, before it has been hardened.
+    ///
+    /// The default user for the provider's images can be overridden with the provider's variable,
+    /// e.g., `DO_SSH_BOOTSTRAP_USER`.
+    pub fn get_ssh_bootstrap_user(&self) -> String {
+        std::env::var(self.get_ssh_bootstrap_user_env_var())
+            .unwrap_or_else(|_| self.get_default_ssh_user())
+    }
+
+    /// The images differ between the providers, so each has its own variable for the SSH user.
+    pub fn get_ssh_user_env_var(&self) -> &'static str {
+        match self {
+            CloudProvider::Aws => "AWS_SSH_USER",
+            CloudProvider::DigitalOcean => "DO_SSH_USER",
+        }
+    }
+
+    pub fn get_ssh_bootstrap_user_env_var(&self) -> &'static str {
+        match self {
+            CloudProvider::Aws => "AWS_SSH_BOOTSTRAP_USER",
+            CloudProvider::DigitalOcean => "DO_SSH_BOOTSTRAP_USER",
+        }
+    }
+
+    fn get_default_ssh_user(&self) -> String {
         match self {
             CloudProvider::Aws => "ubuntu".to_string(),
             CloudProvider::DigitalOcean => "root".to_string(),
@@ -353,6 +533,209 @@ impl LogFormat {
     }
 }
 
+/// The CPU architecture the binaries are built for and the VMs run on.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Architecture {
+    /// 64-bit ARM, which is supported by cheaper instance types on some providers.
+    Aarch64,
+    #[default]
+    X86_64,
+}
+
+impl Architecture {
+    pub fn parse_from_str(val: &str) -> Result<Self> {
+        match val {
+            "aarch64" | "arm64" => Ok(Architecture::Aarch64),
+            "x86_64" | "amd64" => Ok(Architecture::X86_64),
+            _ => Err(Error::InvalidArchitecture(val.to_string())),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Architecture::Aarch64 => "aarch64",
+            Architecture::X86_64 => "x86_64",
+        }
+    }
+
+    /// The Rust target triple used for building the binaries and naming their archives.
+    pub fn target_triple(&self) -> &'static str {
+        match self {
+            Architecture::Aarch64 => "aarch64-unknown-linux-musl",
+            Architecture::X86_64 => "x86_64-unknown-linux-musl",
+        }
+    }
+}
+
+/// The network performance class of the droplets used for the uploader VMs.
+///
+/// Standard droplets can cap the outbound bandwidth of the uploaders, which then limits the load
+/// that can be applied to the network under test.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum BandwidthClass {
+    /// Use premium droplets, which have a higher outbound bandwidth limit.
+    Premium,
+    /// Use the droplet size from the tfvars file for the environment type.
+    #[default]
+    Standard,
+}
+
+impl BandwidthClass {
+    pub fn parse_from_str(val: &str) -> Result<Self> {
+        match val {
+            "premium" => Ok(BandwidthClass::Premium),
+            "standard" => Ok(BandwidthClass::Standard),
+            _ => Err(Error::InvalidBandwidthClass(val.to_string())),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BandwidthClass::Premium => "premium",
+            BandwidthClass::Standard => "standard",
+        }
+    }
+}
+
+/// How the nodes in a VM group make themselves reachable to the rest of the network.
+///
+/// This allows mixed topologies to be composed by using a different mode for each group. Private
+/// nodes are always relay clients, since they run behind the NAT gateway.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum ReachabilityMode {
+    /// The node is publicly reachable and acts as a relay server for relay clients.
+    #[default]
+    Direct,
+    /// The node is started with `--home-network` and is reached through a relay server.
+    RelayClient,
+    /// The node is started with `--upnp` and tries to open its port on the router.
+    Upnp,
+}
+
+impl ReachabilityMode {
+    pub fn parse_from_str(val: &str) -> Result<Self> {
+        match val {
+            "direct" | "relay-server" => Ok(ReachabilityMode::Direct),
+            "relay-client" | "home-network" => Ok(ReachabilityMode::RelayClient),
+            "upnp" => Ok(ReachabilityMode::Upnp),
+            _ => Err(Error::InvalidReachabilityMode(val.to_string())),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReachabilityMode::Direct => "direct",
+            ReachabilityMode::RelayClient => "relay-client",
+            ReachabilityMode::Upnp => "upnp",
+        }
+    }
+}
+
+/// The behaviour of the NAT gateway the private nodes are routed through, so hole punching can be
+/// tested against each type of NAT a home node could be behind.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum NatType {
+    /// The source port of a private node is preserved, and any host can send packets to it
+    /// through the gateway once the port is in use.
+    FullCone,
+    /// The source port of a private node is preserved, but only hosts it has sent packets to can
+    /// reply through the gateway.
+    Restricted,
+    /// A random port is used for each destination, so the mapping seen by one peer cannot be used
+    /// by another.
+    #[default]
+    Symmetric,
+}
+
+impl NatType {
+    pub fn parse_from_str(val: &str) -> Result<Self> {
+        match val {
+            "full-cone" => Ok(NatType::FullCone),
+            "restricted" | "port-restricted" => Ok(NatType::Restricted),
+            "symmetric" => Ok(NatType::Symmetric),
+            _ => Err(Error::InvalidNatType(val.to_string())),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NatType::FullCone => "full-cone",
+            NatType::Restricted => "restricted",
+            NatType::Symmetric => "symmetric",
+        }
+    }
+}
+
+/// When systemd restarts a node service after its process exits.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum RestartMode {
+    /// Restart the service whenever the process exits, including a clean exit.
+    Always,
+    /// Only restart the service if the process exits with an error or is killed by a signal.
+    #[default]
+    OnFailure,
+}
+
+impl RestartMode {
+    pub fn parse_from_str(val: &str) -> Result<Self> {
+        match val {
+            "always" => Ok(RestartMode::Always),
+            "on-failure" => Ok(RestartMode::OnFailure),
+            _ => Err(Error::InvalidRestartMode(val.to_string())),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RestartMode::Always => "always",
+            RestartMode::OnFailure => "on-failure",
+        }
+    }
+}
+
+/// The systemd restart policy for the node services.
+///
+/// Any value that is not set keeps the value from the unit file written by the node manager.
+#[derive(Clone, Debug, Default)]
+pub struct RestartPolicy {
+    pub restart: Option<RestartMode>,
+    /// The time to wait before restarting the service, in seconds.
+    pub restart_sec: Option<u32>,
+    /// The number of starts allowed within the start limit interval before systemd stops trying.
+    pub start_limit_burst: Option<u32>,
+}
+
+impl RestartPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.restart.is_none() && self.restart_sec.is_none() && self.start_limit_burst.is_none()
+    }
+}
+
+/// The backend the node logs and metrics are shipped to.
+#[derive(Clone, Debug)]
+pub enum TelemetryConfig {
+    /// Forward logs to the hosts of a Logstash stack.
+    Logstash {
+        stack_name: String,
+        hosts: Vec<SocketAddr>,
+    },
+    /// Push logs to a Loki instance at the given push API URL, using Promtail on each VM.
+    Loki { url: String },
+    /// Send logs and metrics to an OpenTelemetry collector at the given OTLP/HTTP endpoint, using
+    /// a local collector on each VM.
+    Otlp { endpoint: String },
+}
+
+impl TelemetryConfig {
+    pub fn backend_name(&self) -> &'static str {
+        match self {
+            TelemetryConfig::Logstash { .. } => "logstash",
+            TelemetryConfig::Loki { .. } => "loki",
+            TelemetryConfig::Otlp { .. } => "otlp",
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct UpgradeOptions {
     pub ansible_verbose: bool,
@@ -393,12 +776,17 @@ pub struct TestnetDeployBuilder {
     ansible_forks: Option<usize>,
     ansible_verbose_mode: bool,
     deployment_type: EnvironmentType,
+    dry_run: bool,
     environment_name: String,
+    offline_inventory: bool,
     provider: Option<CloudProvider>,
     ssh_secret_key_path: Option<PathBuf>,
     state_bucket_name: Option<String>,
     terraform_binary_path: Option<PathBuf>,
+    terraform_plugin_cache_dir: Option<PathBuf>,
+    terraform_provider_mirror_url: Option<String>,
     vault_password_path: Option<PathBuf>,
+    wireguard_overlay: bool,
     working_directory_path: Option<PathBuf>,
 }
 
@@ -422,11 +810,25 @@ impl TestnetDeployBuilder {
         self
     }
 
+    /// Terraform will only plan changes and Ansible will run playbooks in check mode.
+    pub fn dry_run(&mut self, dry_run: bool) -> &mut Self {
+        self.dry_run = dry_run;
+        self
+    }
+
     pub fn environment_name(&mut self, name: &str) -> &mut Self {
         self.environment_name = name.to_string();
         self
     }
 
+    /// Use the static inventories from the last inventory run rather than the dynamic inventory.
+    ///
+    /// This is also enabled by setting `ANSIBLE_OFFLINE_INVENTORY`.
+    pub fn offline_inventory(&mut self, offline_inventory: bool) -> &mut Self {
+        self.offline_inventory = offline_inventory;
+        self
+    }
+
     pub fn provider(&mut self, provider: CloudProvider) -> &mut Self {
         self.provider = Some(provider);
         self
@@ -442,6 +844,19 @@ impl TestnetDeployBuilder {
         self
     }
 
+    /// Overrides the `TERRAFORM_PLUGIN_CACHE_DIR` variable and the default cache in the data
+    /// directory.
+    pub fn terraform_plugin_cache_dir(&mut self, plugin_cache_dir: PathBuf) -> &mut Self {
+        self.terraform_plugin_cache_dir = Some(plugin_cache_dir);
+        self
+    }
+
+    /// Overrides the `TERRAFORM_PROVIDER_MIRROR_URL` variable.
+    pub fn terraform_provider_mirror_url(&mut self, mirror_url: String) -> &mut Self {
+        self.terraform_provider_mirror_url = Some(mirror_url);
+        self
+    }
+
     pub fn working_directory(&mut self, working_directory_path: PathBuf) -> &mut Self {
         self.working_directory_path = Some(working_directory_path);
         self
@@ -457,19 +872,38 @@ impl TestnetDeployBuilder {
         self
     }
 
+    /// Connect to the VMs over the environment's WireGuard overlay rather than their public IPs.
+    ///
+    /// This is also enabled by setting `WIREGUARD_OVERLAY`.
+    pub fn wireguard_overlay(&mut self, wireguard_overlay: bool) -> &mut Self {
+        self.wireguard_overlay = wireguard_overlay;
+        self
+    }
+
     pub fn build(&self) -> Result<TestnetDeployer> {
         let provider = self.provider.unwrap_or(CloudProvider::DigitalOcean);
+        let offline_inventory =
+            self.offline_inventory || std::env::var("ANSIBLE_OFFLINE_INVENTORY").is_ok();
         match provider {
-            CloudProvider::DigitalOcean => {
-                let digital_ocean_pat = std::env::var("DO_PAT").map_err(|_| {
-                    Error::CloudProviderCredentialsNotSupplied("DO_PAT".to_string())
-                })?;
-                // The DO_PAT variable is not actually read by either Terraform or Ansible.
-                // Each tool uses a different variable, so instead we set each of those variables
-                // to the value of DO_PAT. This means the user only needs to set one variable.
-                std::env::set_var("DIGITALOCEAN_TOKEN", digital_ocean_pat.clone());
-                std::env::set_var("DO_API_TOKEN", digital_ocean_pat);
-            }
+            CloudProvider::DigitalOcean => match std::env::var("DO_PAT") {
+                Ok(digital_ocean_pat) => {
+                    // The DO_PAT variable is not actually read by either Terraform or Ansible.
+                    // Each tool uses a different variable, so instead we set each of those
+                    // variables to the value of DO_PAT. This means the user only needs to set one
+                    // variable.
+                    std::env::set_var("DIGITALOCEAN_TOKEN", digital_ocean_pat.clone());
+                    std::env::set_var("DO_API_TOKEN", digital_ocean_pat);
+                }
+                // The offline inventory is used so the API does not need to be called.
+                Err(_) if offline_inventory => {
+                    debug!("DO_PAT is not set, but it is not required for the offline inventory")
+                }
+                Err(_) => {
+                    return Err(Error::CloudProviderCredentialsNotSupplied(
+                        "DO_PAT".to_string(),
+                    ));
+                }
+            },
             _ => {
                 return Err(Error::CloudProviderNotSupported(provider.to_string()));
             }
@@ -501,7 +935,7 @@ impl TestnetDeployBuilder {
             None => PathBuf::from(std::env::var("ANSIBLE_VAULT_PASSWORD_PATH")?),
         };
 
-        let terraform_runner = TerraformRunner::new(
+        let mut terraform_runner = TerraformRunner::new(
             terraform_binary_path.to_path_buf(),
             working_directory_path
                 .join("terraform")
@@ -510,7 +944,14 @@ impl TestnetDeployBuilder {
             provider,
             &state_bucket_name,
         )?;
-        let ansible_runner = AnsibleRunner::new(
+        terraform_runner.dry_run = self.dry_run;
+        if let Some(plugin_cache_dir) = &self.terraform_plugin_cache_dir {
+            terraform_runner.plugin_cache_dir = Some(plugin_cache_dir.clone());
+        }
+        if let Some(mirror_url) = &self.terraform_provider_mirror_url {
+            terraform_runner.provider_mirror_url = Some(mirror_url.clone());
+        }
+        let mut ansible_runner = AnsibleRunner::new(
             self.ansible_forks.unwrap_or(ANSIBLE_DEFAULT_FORKS),
             self.ansible_verbose_mode,
             &self.environment_name,
@@ -519,7 +960,22 @@ impl TestnetDeployBuilder {
             vault_password_path,
             working_directory_path.join("ansible"),
         )?;
+        ansible_runner.dry_run = self.dry_run;
+        ansible_runner.offline_inventory = offline_inventory;
         let ssh_client = SshClient::new(ssh_secret_key_path);
+        if self.wireguard_overlay || std::env::var(WIREGUARD_OVERLAY_ENV_VAR).is_ok() {
+            let overlay = WireGuardOverlay::load(&self.environment_name)?;
+            if overlay.addresses.is_empty() {
+                progress!(
+                    "The {} environment has no WireGuard overlay. The public IPs will be used.",
+                    self.environment_name
+                );
+            } else {
+                let addresses = overlay.get_management_addresses();
+                ssh_client.set_overlay_addresses(addresses.clone())?;
+                ansible_runner.overlay_addresses = Some(addresses);
+            }
+        }
         let ansible_provisioner =
             AnsibleProvisioner::new(ansible_runner, provider, ssh_client.clone());
         let rpc_client = RpcClient::new(
@@ -598,6 +1054,10 @@ impl TestnetDeployer {
         })
     }
 
+    pub fn is_dry_run(&self) -> bool {
+        self.terraform_runner.dry_run
+    }
+
     pub async fn init(&self) -> Result<()> {
         if self
             .s3_repository
@@ -653,6 +1113,21 @@ impl TestnetDeployer {
         Ok(())
     }
 
+    pub fn apply_peer_filter(
+        &self,
+        node_type: Option<NodeType>,
+        custom_inventory: Option<Vec<VirtualMachine>>,
+        extra_vars: String,
+    ) -> Result<()> {
+        self.ansible_provisioner.apply_peer_filter(
+            &self.environment_name,
+            node_type,
+            custom_inventory,
+            extra_vars,
+        )?;
+        Ok(())
+    }
+
     pub fn start(
         &self,
         interval: Duration,
@@ -668,34 +1143,48 @@ impl TestnetDeployer {
         Ok(())
     }
 
-    /// Get the status of all nodes in a network.
+    /// Print the status of all the nodes in the environment.
     ///
     /// First, a playbook runs `safenode-manager status` against all the machines, to get the
     /// current state of all the nodes. Then all the node registry files are retrieved and
     /// deserialized to a `NodeRegistry`, allowing us to output the status of each node on each VM.
-    pub fn status(&self) -> Result<()> {
+    /// The node registries are returned so they can be used for further reporting.
+    pub fn status(&self) -> Result<Vec<DeploymentNodeRegistries>> {
         self.ansible_provisioner.status()?;
 
-        let peer_cache_node_registries = self
-            .ansible_provisioner
-            .get_node_registries(&AnsibleInventoryType::PeerCacheNodes)?;
-        let generic_node_registries = self
-            .ansible_provisioner
-            .get_node_registries(&AnsibleInventoryType::Nodes)?;
-        let private_node_registries = self
-            .ansible_provisioner
-            .get_node_registries(&AnsibleInventoryType::PrivateNodes)?;
-        let genesis_node_registry = self
-            .ansible_provisioner
-            .get_node_registries(&AnsibleInventoryType::Genesis)?
-            .clone();
-
-        peer_cache_node_registries.print();
-        generic_node_registries.print();
-        private_node_registries.print();
-        genesis_node_registry.print();
+        let registries = self.get_all_node_registries()?;
+        for deployment_registries in registries.iter() {
+            deployment_registries.print();
+        }
+        Ok(registries)
+    }
 
-        Ok(())
+    /// Compare the number of running nodes against the expected number for the deployment.
+    ///
+    /// As with the status, the node registries are refreshed before they are retrieved.
+    pub fn reconcile_node_counts(
+        &self,
+        environment_details: &EnvironmentDetails,
+    ) -> Result<NodeCountReconciliation> {
+        self.ansible_provisioner.status()?;
+        let registries = self.get_all_node_registries()?;
+        Ok(reconcile_node_counts(&registries, environment_details))
+    }
+
+    fn get_all_node_registries(&self) -> Result<Vec<DeploymentNodeRegistries>> {
+        let mut registries = Vec::new();
+        for inventory_type in [
+            AnsibleInventoryType::PeerCacheNodes,
+            AnsibleInventoryType::Nodes,
+            AnsibleInventoryType::PrivateNodes,
+            AnsibleInventoryType::Genesis,
+        ] {
+            registries.push(
+                self.ansible_provisioner
+                    .get_node_registries(&inventory_type)?,
+            );
+        }
+        Ok(registries)
     }
 
     pub fn cleanup_node_logs(&self, setup_cron: bool) -> Result<()> {
@@ -733,6 +1222,63 @@ impl TestnetDeployer {
         Ok(())
     }
 
+    /// Restart the nodes, stopping then starting each one with the interval between them.
+    ///
+    /// The VMs can be restarted in batches, so the whole environment is not restarted at once.
+    pub fn restart(
+        &self,
+        interval: Duration,
+        node_type: Option<NodeType>,
+        custom_inventory: Option<Vec<VirtualMachine>>,
+        vm_batch_size: Option<usize>,
+    ) -> Result<()> {
+        self.ansible_provisioner.restart_nodes(
+            &self.environment_name,
+            interval,
+            node_type,
+            custom_inventory,
+            vm_batch_size,
+        )?;
+        Ok(())
+    }
+
+    /// Change the environment variables of the node services on a running network.
+    ///
+    /// The variables replace those given at deploy time. Each node is restarted to pick them up,
+    /// with the interval between each restart.
+    pub fn update_node_env(
+        &self,
+        env_variables: Vec<(String, String)>,
+        interval: Duration,
+        node_type: Option<NodeType>,
+        custom_inventory: Option<Vec<VirtualMachine>>,
+    ) -> Result<()> {
+        self.ansible_provisioner.update_node_env(
+            &self.environment_name,
+            env_variables,
+            interval,
+            node_type,
+            custom_inventory,
+        )?;
+        Ok(())
+    }
+
+    /// Apply the restart policy to the node services. The nodes do not need to be restarted.
+    pub fn apply_restart_policy(
+        &self,
+        restart_policy: &RestartPolicy,
+        node_type: Option<NodeType>,
+        custom_inventory: Option<Vec<VirtualMachine>>,
+    ) -> Result<()> {
+        self.ansible_provisioner.apply_restart_policy(
+            &self.environment_name,
+            restart_policy,
+            node_type,
+            custom_inventory,
+        )?;
+        Ok(())
+    }
+
     pub fn stop_telegraf(
         &self,
         node_type: Option<NodeType>,
@@ -751,15 +1297,20 @@ impl TestnetDeployer {
         Ok(())
     }
 
+    /// Replace the antctl binary on the node VMs, without redeploying the nodes.
+    ///
+    /// The archive for the architecture the environment was deployed with is used.
     pub fn upgrade_antctl(
         &self,
         version: Version,
+        architecture: Architecture,
         node_type: Option<NodeType>,
         custom_inventory: Option<Vec<VirtualMachine>>,
     ) -> Result<()> {
         self.ansible_provisioner.upgrade_antctl(
             &self.environment_name,
             &version,
+            architecture,
             node_type,
             custom_inventory,
         )?;
@@ -776,7 +1327,7 @@ impl TestnetDeployer {
         Ok(())
     }
 
-    pub async fn clean(&self) -> Result<()> {
+    pub async fn clean(&self, options: &CleanOptions) -> Result<()> {
         let environment_details =
             get_environment_details(&self.environment_name, &self.s3_repository).await?;
 
@@ -803,20 +1354,35 @@ impl TestnetDeployer {
                 .ansible_provisioner
                 .drain_funds_from_uploaders(
                     Address::from_str(address).map_err(|err| {
-                        log::error!("Invalid funding wallet public key: {err:?}");
+                        tracing::error!("Invalid funding wallet public key: {err:?}");
                         Error::FailedToParseKey
                     })?,
                     network,
                 )
                 .await
             {
-                log::error!("Failed to drain funds from uploaders: {err:?}");
+                tracing::error!("Failed to drain funds from uploaders: {err:?}");
             }
         } else {
             println!("Custom network provided. Not draining funds.");
-            log::info!("Custom network provided. Not draining funds.");
+            tracing::info!("Custom network provided. Not draining funds.");
+        }
+
+        // Provide the end-of-life availability report before the history is removed along with
+        // the rest of the environment.
+        let uptime_history =
+            UptimeHistory::retrieve(&self.s3_repository, &self.environment_name).await?;
+        if !uptime_history.samples.is_empty() {
+            uptime_history.print_report();
+        }
+
+        if options.retains_resources() {
+            return self
+                .clean_with_retention(options, &environment_details)
+                .await;
         }
 
+        let has_firewall = environment_details.firewall.is_some();
         do_clean(
             &self.environment_name,
             Some(environment_details),
@@ -825,18 +1391,143 @@ impl TestnetDeployer {
             None,
         )
         .await?;
+        if has_firewall {
+            delete_firewall(self.cloud_provider, &self.environment_name).await?;
+        }
         self.s3_repository
             .delete_object("sn-environment-type", &self.environment_name)
             .await?;
+        self.s3_repository
+            .delete_folder(
+                downloaders::UPLOAD_MANIFEST_BUCKET_NAME,
+                &downloaders::get_upload_manifest_prefix(&self.environment_name),
+            )
+            .await?;
+        if !uptime_history.samples.is_empty() {
+            UptimeHistory::delete(&self.s3_repository, &self.environment_name).await?;
+        }
+        EnvironmentStatus::delete(&self.s3_repository, &self.environment_name).await?;
+
+        // The overlay keys are only useful for this environment's VMs, which no longer exist.
+        let wireguard_dir_path = wireguard::get_wireguard_dir_path(&self.environment_name)?;
+        if wireguard_dir_path.exists() {
+            std::fs::remove_dir_all(wireguard_dir_path)?;
+        }
+        Ok(())
+    }
+
+    /// Remove all the VMs in the environment except those that should be retained.
+    ///
+    /// The workspace and the environment details are kept, so a subsequent clean without any
+    /// retention flags will remove the remaining resources.
+    async fn clean_with_retention(
+        &self,
+        options: &CleanOptions,
+        environment_details: &EnvironmentDetails,
+    ) -> Result<()> {
+        self.terraform_runner.init()?;
+        let workspaces = self.terraform_runner.workspace_list()?;
+        if !workspaces.contains(&self.environment_name) {
+            return Err(Error::EnvironmentDoesNotExist(
+                self.environment_name.clone(),
+            ));
+        }
+        self.terraform_runner
+            .workspace_select(&self.environment_name)?;
+
+        let mut infra_run_options = InfraRunOptions::generate_existing(
+            &self.environment_name,
+            &self.terraform_runner,
+            environment_details,
+        )
+        .await?;
+        infra_run_options.enable_build_vm =
+            options.keep_build_vm && infra_run_options.enable_build_vm;
+        if !options.keep_genesis_vm {
+            infra_run_options.genesis_vm_count = Some(0);
+        }
+        infra_run_options.auditor_vm_count = Some(0);
+        infra_run_options.door_node_count = Some(0);
+        infra_run_options.downloader_vm_count = Some(0);
+        infra_run_options.evm_node_count = Some(0);
+        infra_run_options.nat_gateway_count = Some(0);
+        infra_run_options.node_vm_count = Some(0);
+        infra_run_options.peer_cache_node_vm_count = Some(0);
+        infra_run_options.private_node_vm_count = Some(0);
+        infra_run_options.setup_artifact_proxy = Some(false);
+        infra_run_options.setup_monitoring = Some(false);
+        infra_run_options.uploader_vm_count = Some(0);
+
+        // Terraform stops managing the volumes, so they are not destroyed along with the VM. The
+        // droplet is detached from them when it is deleted.
+        let mut retained_volumes = Vec::new();
+        if options.keep_logs_volume && !options.keep_genesis_vm {
+            retained_volumes = self
+                .terraform_runner
+                .show(&self.environment_name)?
+                .into_iter()
+                .filter(|resource| resource.resource_name == "genesis_node_attached_volume")
+                .filter_map(|resource| {
+                    resource
+                        .values
+                        .get("name")
+                        .and_then(|name| name.as_str())
+                        .map(|name| name.to_string())
+                })
+                .collect::<Vec<_>>();
+            if !retained_volumes.is_empty() && !self.terraform_runner.dry_run {
+                self.terraform_runner.state_rm(&[
+                    "digitalocean_volume_attachment.genesis_node_volume_attachment".to_string(),
+                    "digitalocean_volume.genesis_node_attached_volume".to_string(),
+                ])?;
+            }
+        }
+        self.create_or_update_infra(&infra_run_options)?;
+
+        if options.keep_build_vm {
+            println!("Retained the build VM for {}", self.environment_name);
+        }
+        if options.keep_genesis_vm {
+            println!(
+                "Retained the genesis VM and its volumes for {}",
+                self.environment_name
+            );
+        }
+        if !retained_volumes.is_empty() {
+            println!(
+                "Retained the genesis node volumes for {}: {}",
+                self.environment_name,
+                retained_volumes.join(", ")
+            );
+            println!("These are no longer managed by Terraform and must be deleted manually.");
+        }
         Ok(())
     }
 }
 
+/// Resources that should be retained when an environment is cleaned.
+///
+/// Retaining the build VM allows a redeploy to skip provisioning a new build machine. Retaining
+/// the genesis VM keeps its attached volumes, which hold the node data and logs, for post-mortem
+/// analysis. Retaining the logs volume keeps only the genesis volumes, without the VM.
+#[derive(Clone, Debug, Default)]
+pub struct CleanOptions {
+    pub keep_build_vm: bool,
+    pub keep_genesis_vm: bool,
+    pub keep_logs_volume: bool,
+}
+
+impl CleanOptions {
+    pub fn retains_resources(&self) -> bool {
+        self.keep_build_vm || self.keep_genesis_vm || self.keep_logs_volume
+    }
+}
+
 //
 // Shared Helpers
 //
 
-pub fn get_genesis_multiaddr(
+pub async fn get_genesis_multiaddr(
     ansible_runner: &AnsibleRunner,
     ssh_client: &SshClient,
 ) -> Result<(String, IpAddr)> {
@@ -847,15 +1538,16 @@ pub fn get_genesis_multiaddr(
     // started with the `--first` flag.
     // First attempt: try to find node with first=true
     let multiaddr = ssh_client
-        .run_command(
+        .execute(
             &genesis_ip,
-            "root",
+            &ansible_runner.provider.get_ssh_user(),
             "jq -r '.nodes[] | select(.peers_args.first == true) | .listen_addr[] | select(contains(\"127.0.0.1\") | not) | select(contains(\"quic-v1\"))' /var/antctl/node_registry.json | head -n 1",
-            false,
         )
+        .await
+        .and_then(|output| output.into_stdout_lines())
         .map(|output| output.first().cloned())
         .unwrap_or_else(|err| {
-            log::error!("Failed to find first node with quic-v1 protocol: {err:?}");
+            tracing::error!("Failed to find first node with quic-v1 protocol: {err:?}");
             None
         });
 
@@ -863,12 +1555,13 @@ pub fn get_genesis_multiaddr(
     let multiaddr = match multiaddr {
         Some(addr) => addr,
         None => ssh_client
-            .run_command(
+            .execute(
                 &genesis_ip,
-                "root",
+                &ansible_runner.provider.get_ssh_user(),
                 "jq -r '.nodes[] | .listen_addr[] | select(contains(\"127.0.0.1\") | not) | select(contains(\"quic-v1\"))' /var/antctl/node_registry.json | head -n 1",
-                false,
-            )?
+            )
+            .await?
+            .into_stdout_lines()?
             .first()
             .cloned()
             .ok_or_else(|| Error::GenesisListenAddress)?,
@@ -877,7 +1570,7 @@ pub fn get_genesis_multiaddr(
     Ok((multiaddr, genesis_ip))
 }
 
-pub fn get_anvil_node_data(
+pub async fn get_anvil_node_data(
     ansible_runner: &AnsibleRunner,
     ssh_client: &SshClient,
 ) -> Result<AnvilNodeData> {
@@ -894,7 +1587,11 @@ pub fn get_anvil_node_data(
     const RETRY_DELAY: Duration = Duration::from_secs(5);
 
     for attempt in 1..=MAX_ATTEMPTS {
-        match ssh_client.run_command(&evm_ip, "ant", &format!("cat {}", csv_file_path), false) {
+        match ssh_client
+            .execute(&evm_ip, "ant", &format!("cat {}", csv_file_path))
+            .await
+            .and_then(|output| output.into_stdout_lines())
+        {
             Ok(output) => {
                 if let Some(csv_contents) = output.first() {
                     let parts: Vec<&str> = csv_contents.split(',').collect();
@@ -924,13 +1621,13 @@ pub fn get_anvil_node_data(
                 );
             }
         }
-        std::thread::sleep(RETRY_DELAY);
+        tokio::time::sleep(RETRY_DELAY).await;
     }
 
     Err(Error::EvmTestnetDataNotFound)
 }
 
-pub fn get_multiaddr(
+pub async fn get_multiaddr(
     ansible_runner: &AnsibleRunner,
     ssh_client: &SshClient,
 ) -> Result<(String, IpAddr)> {
@@ -947,13 +1644,15 @@ pub fn get_multiaddr(
 
     let multiaddr =
         ssh_client
-        .run_command(
+        .execute(
             &node_ip,
-            "root",
+            &ansible_runner.provider.get_ssh_user(),
             // fetch the first multiaddr which does not contain the localhost addr.
             "jq -r '.nodes[] | .listen_addr[] | select(contains(\"127.0.0.1\") | not)' /var/antctl/node_registry.json | head -n 1",
-            false,
-        )?.first()
+        )
+        .await?
+        .into_stdout_lines()?
+        .first()
         .cloned()
         .ok_or_else(|| Error::NodeAddressNotFound)?;
 
@@ -970,7 +1669,7 @@ pub async fn get_and_extract_archive_from_s3(
 ) -> Result<()> {
     // In this case, not using unwrap leads to having to provide a very trivial error variant that
     // doesn't seem very valuable.
-    let archive_file_name = archive_bucket_path.split('/').last().unwrap();
+    let archive_file_name = archive_bucket_path.split('/').next_back().unwrap();
     let archive_dest_path = dest_path.join(archive_file_name);
     s3_repository
         .download_object(bucket_name, archive_bucket_path, &archive_dest_path)
@@ -1004,18 +1703,47 @@ pub fn run_external_command(
     args: Vec<String>,
     suppress_stdout: bool,
     suppress_stderr: bool,
+) -> Result<Vec<String>> {
+    run_external_command_with_env(
+        binary_path,
+        working_directory_path,
+        args,
+        &[],
+        suppress_stdout,
+        suppress_stderr,
+    )
+}
+
+/// Run an external command with additional environment variables set on its process.
+pub fn run_external_command_with_env(
+    binary_path: PathBuf,
+    working_directory_path: PathBuf,
+    args: Vec<String>,
+    envs: &[(&str, String)],
+    suppress_stdout: bool,
+    suppress_stderr: bool,
 ) -> Result<Vec<String>> {
     let mut command = Command::new(binary_path.clone());
     for arg in &args {
         command.arg(arg);
     }
+    for (key, value) in envs {
+        command.env(key, value);
+    }
     command.stdout(Stdio::piped());
     command.stderr(Stdio::piped());
     command.current_dir(working_directory_path.clone());
     debug!("Running {binary_path:#?} with args {args:#?}");
     debug!("Working directory set to {working_directory_path:#?}");
 
-    let mut child = command.spawn()?;
+    let timer = run_log::CommandTimer::start(&binary_path.to_string_lossy(), &args);
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(err) => {
+            timer.finish(None);
+            return Err(err.into());
+        }
+    };
     let mut output_lines = Vec::new();
 
     if let Some(ref mut stdout) = child.stdout {
@@ -1023,7 +1751,7 @@ pub fn run_external_command(
         for line in reader.lines() {
             let line = line?;
             if !suppress_stdout {
-                println!("{line}");
+                progress!("{}", redact::redact(&line));
             }
             output_lines.push(line);
         }
@@ -1034,13 +1762,14 @@ pub fn run_external_command(
         for line in reader.lines() {
             let line = line?;
             if !suppress_stderr {
-                eprintln!("{line}");
+                eprintln!("{}", redact::redact(&line));
             }
             output_lines.push(line);
         }
     }
 
     let output = child.wait()?;
+    timer.finish(output.code());
     if !output.success() {
         // Using `unwrap` here avoids introducing another error variant, which seems excessive.
         let binary_path = binary_path.to_str().unwrap();
@@ -1145,8 +1874,7 @@ pub fn get_wallet_directory() -> Result<PathBuf> {
 }
 
 pub async fn notify_slack(inventory: DeploymentInventory) -> Result<()> {
-    let webhook_url =
-        std::env::var("SLACK_WEBHOOK_URL").map_err(|_| Error::SlackWebhookUrlNotSupplied)?;
+    let notifier = SlackNotifier::from_env()?;
 
     let mut message = String::new();
     message.push_str("*Testnet Details*\n");
@@ -1179,6 +1907,10 @@ pub async fn notify_slack(inventory: DeploymentInventory) -> Result<()> {
             message.push_str(&format!("safenode version: {}\n", safenode_version));
             message.push_str(&format!("antctl version: {}\n", safenode_manager_version));
         }
+        BinaryOption::Local { .. } => {
+            message.push_str("*Binary Details*\n");
+            message.push_str("Binaries were uploaded from a local machine\n");
+        }
     }
 
     message.push_str("*Sample Peers*\n");
@@ -1194,24 +1926,14 @@ pub async fn notify_slack(inventory: DeploymentInventory) -> Result<()> {
     }
     message.push_str("```\n");
 
-    let payload = json!({
-        "text": message,
-    });
-    reqwest::Client::new()
-        .post(webhook_url)
-        .json(&payload)
-        .send()
-        .await?;
     println!("{message}");
-    println!("Posted notification to Slack");
-    Ok(())
-}
-
-fn print_duration(duration: Duration) {
-    let total_seconds = duration.as_secs();
-    let minutes = total_seconds / 60;
-    let seconds = total_seconds % 60;
-    debug!("Time taken: {} minutes and {} seconds", minutes, seconds);
+    notifier
+        .notify(NotificationEvent::new(
+            &inventory.name,
+            EventSeverity::Info,
+            message,
+        ))
+        .await
 }
 
 pub fn get_progress_bar(length: u64) -> Result<ProgressBar> {
@@ -1243,7 +1965,7 @@ pub async fn get_environment_details(
                 let content = match std::fs::read_to_string(temp_file.path()) {
                     Ok(content) => content,
                     Err(err) => {
-                        log::error!("Could not read the environment details file: {err:?}");
+                        tracing::error!("Could not read the environment details file: {err:?}");
                         if retries < max_retries {
                             debug!("Retrying to read the environment details file");
                             retries += 1;
@@ -1260,7 +1982,7 @@ pub async fn get_environment_details(
                 match serde_json::from_str(&content) {
                     Ok(environment_details) => break environment_details,
                     Err(err) => {
-                        log::error!("Could not parse the environment details file: {err:?}");
+                        tracing::error!("Could not parse the environment details file: {err:?}");
                         if retries < max_retries {
                             debug!("Retrying to parse the environment details file");
                             retries += 1;
@@ -1274,7 +1996,7 @@ pub async fn get_environment_details(
                 }
             }
             Err(err) => {
-                log::error!(
+                tracing::error!(
                     "Could not download the environment details file for {environment_name} from S3: {err:?}"
                 );
                 if retries < max_retries {
@@ -1323,3 +2045,240 @@ pub fn calculate_size_per_attached_volume(node_count: u16) -> u16 {
 pub fn get_bootstrap_cache_url(ip_addr: &IpAddr) -> String {
     format!("http://{ip_addr}/bootstrap_cache.json")
 }
+
+/// The longest permitted environment name.
+///
+/// The name is used as a prefix for the VM names, e.g., `<name>-peer-cache-node-10`, which also
+/// become DNS labels and are limited to 63 characters.
+pub const MAX_ENVIRONMENT_NAME_LENGTH: usize = 40;
+
+/// Validate the name for a new environment.
+///
+/// The name is used for the Terraform workspace, the VM names, DNS records and S3 object keys, so
+/// an invalid name would otherwise fail deep inside one of those with an unhelpful error.
+pub fn validate_environment_name(name: &str) -> Result<()> {
+    let invalid = |reason: &str| {
+        Err(Error::InvalidEnvironmentName {
+            name: name.to_string(),
+            reason: reason.to_string(),
+        })
+    };
+    if name.is_empty() {
+        return invalid("the name must not be empty");
+    }
+    if name.len() > MAX_ENVIRONMENT_NAME_LENGTH {
+        return invalid(&format!(
+            "the name must not be longer than {MAX_ENVIRONMENT_NAME_LENGTH} characters"
+        ));
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return invalid("the name must only contain letters, digits or hyphens");
+    }
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return invalid("the name must start with a letter");
+    }
+    if name.ends_with('-') || name.contains("--") {
+        return invalid("the name must not end with a hyphen or contain consecutive hyphens");
+    }
+    Ok(())
+}
+
+/// Generate a name for a new environment from a branch name and the current date.
+///
+/// The name has the form `<branch>-<MMDD><suffix>`, e.g., `feat-xyz-0412a`. The suffix is the first
+/// letter for which there is no existing environment, either in the local inventory cache or in
+/// S3, so generating a name on the same day for the same branch is deterministic until that
+/// environment is deployed.
+pub async fn generate_environment_name(
+    s3_repository: &S3Repository,
+    branch: Option<&str>,
+    existing_names: &[String],
+) -> Result<String> {
+    let date = chrono::Utc::now().format("%m%d").to_string();
+    let prefix = get_environment_name_prefix(branch);
+    for suffix in 'a'..='z' {
+        let name = format!("{prefix}-{date}{suffix}");
+        if existing_names
+            .iter()
+            .any(|existing| existing.eq_ignore_ascii_case(&name))
+        {
+            continue;
+        }
+        let temp_file = tempfile::NamedTempFile::new()?;
+        match s3_repository
+            .download_object("sn-environment-type", &name, temp_file.path())
+            .await
+        {
+            Ok(_) => continue,
+            Err(Error::GetS3ObjectError(_, _)) => {
+                validate_environment_name(&name)?;
+                return Ok(name);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Err(Error::InvalidEnvironmentName {
+        name: format!("{prefix}-{date}"),
+        reason: "every suffix for today's date is already in use".to_string(),
+    })
+}
+
+/// Convert a branch name into the prefix for a generated environment name.
+///
+/// Any character that is not valid in an environment name becomes a hyphen, and the prefix is
+/// truncated to leave room for the separator, the `MMDD` date and the suffix.
+fn get_environment_name_prefix(branch: Option<&str>) -> String {
+    const DATE_LENGTH: usize = 4;
+    let max_prefix_length = MAX_ENVIRONMENT_NAME_LENGTH - DATE_LENGTH - 2;
+
+    let mut prefix = String::new();
+    for c in branch.unwrap_or("env").to_lowercase().chars() {
+        let c = if c.is_ascii_alphanumeric() { c } else { '-' };
+        if c == '-' && (prefix.is_empty() || prefix.ends_with('-')) {
+            continue;
+        }
+        prefix.push(c);
+    }
+    prefix.truncate(max_prefix_length);
+    let mut prefix = prefix.trim_end_matches('-').to_string();
+    if !prefix.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        prefix = format!("env-{prefix}");
+        prefix.truncate(max_prefix_length);
+        prefix = prefix.trim_end_matches('-').to_string();
+    }
+    prefix
+}
+
+/// Validate an environment variable that will be passed to the node manager.
+///
+/// The variables are joined into a single comma-separated `--env` argument and some of the
+/// playbooks build the node manager invocation as a shell command, so a name or value containing a
+/// comma, quote, whitespace or other shell metacharacter would corrupt the invocation.
+pub fn validate_env_variable(name: &str, value: &str) -> Result<()> {
+    let mut chars = name.chars();
+    let valid_name = match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    };
+    if !valid_name {
+        return Err(Error::InvalidEnvironmentVariable {
+            name: name.to_string(),
+            reason: "names must only contain letters, digits or underscores, and must not start \
+                     with a digit"
+                .to_string(),
+        });
+    }
+
+    const ILLEGAL_VALUE_CHARS: &[char] = &[',', '"', '\'', '`', '$', ';', '&', '|', '\\'];
+    if let Some(c) = value
+        .chars()
+        .find(|c| ILLEGAL_VALUE_CHARS.contains(c) || c.is_whitespace())
+    {
+        return Err(Error::InvalidEnvironmentVariable {
+            name: name.to_string(),
+            reason: format!("the value contains the illegal character {c:?}"),
+        });
+    }
+
+    Ok(())
+}
+
+pub fn is_known_node_env_variable(name: &str) -> bool {
+    KNOWN_NODE_ENV_VARIABLES.contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color_eyre::Result;
+
+    #[test]
+    fn test_validate_environment_name_accepts_valid_names() -> Result<()> {
+        validate_environment_name("alpha")?;
+        validate_environment_name("feat-xyz-0412a")?;
+        validate_environment_name(&"a".repeat(MAX_ENVIRONMENT_NAME_LENGTH))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_environment_name_rejects_invalid_names() -> Result<()> {
+        let too_long = "a".repeat(MAX_ENVIRONMENT_NAME_LENGTH + 1);
+        for name in [
+            "",
+            too_long.as_str(),
+            "feat_xyz",
+            "feat.xyz",
+            "1alpha",
+            "-alpha",
+            "alpha-",
+            "alpha--beta",
+        ] {
+            let result = validate_environment_name(name);
+            assert!(
+                matches!(result, Err(Error::InvalidEnvironmentName { .. })),
+                "expected '{name}' to be rejected"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_environment_name_prefix_sanitises_the_branch_name() -> Result<()> {
+        assert_eq!("env", get_environment_name_prefix(None));
+        assert_eq!("feat-xyz", get_environment_name_prefix(Some("feat/xyz")));
+        assert_eq!("feat-xyz", get_environment_name_prefix(Some("Feat__XYZ")));
+        assert_eq!("fix-abc", get_environment_name_prefix(Some("/fix-abc/")));
+        assert_eq!("env-123", get_environment_name_prefix(Some("123")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_environment_name_prefix_leaves_room_for_the_date_and_suffix() -> Result<()> {
+        let prefix = get_environment_name_prefix(Some(&"a".repeat(100)));
+        assert_eq!(MAX_ENVIRONMENT_NAME_LENGTH - 6, prefix.len());
+        validate_environment_name(&format!("{prefix}-0412a"))?;
+
+        // A hyphen left at the end by the truncation is removed.
+        let branch = format!("{}-b", "a".repeat(MAX_ENVIRONMENT_NAME_LENGTH - 7));
+        let prefix = get_environment_name_prefix(Some(&branch));
+        assert_eq!("a".repeat(MAX_ENVIRONMENT_NAME_LENGTH - 7), prefix);
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_env_variable_accepts_valid_variables() -> Result<()> {
+        validate_env_variable("ANT_LOG", "v")?;
+        validate_env_variable("_private", "all")?;
+        validate_env_variable("RUST_LOG", "ant_node=debug:libp2p=info")?;
+        validate_env_variable("EMPTY", "")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_env_variable_rejects_invalid_names() -> Result<()> {
+        for name in ["", "1VAR", "MY-VAR", "MY VAR", "MY=VAR"] {
+            let result = validate_env_variable(name, "value");
+            assert!(
+                matches!(result, Err(Error::InvalidEnvironmentVariable { .. })),
+                "expected '{name}' to be rejected"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_env_variable_rejects_illegal_value_characters() -> Result<()> {
+        for value in [
+            "a,b", "a\"b", "a'b", "a`b", "$HOME", "a;b", "a&b", "a|b", "a\\b", "a b", "a\tb",
+        ] {
+            let result = validate_env_variable("VAR", value);
+            assert!(
+                matches!(result, Err(Error::InvalidEnvironmentVariable { .. })),
+                "expected {value:?} to be rejected"
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    ansible::{extra_vars::ExtraVarsDocBuilder, inventory::AnsibleInventoryType, AnsiblePlaybook},
+    error::{Error, Result},
+    Architecture, BinaryOption, TestnetDeployer,
+};
+use serde_json::json;
+use tracing::debug;
+
+/// The path under the artifact proxy URL where the local binaries are served. This must match the
+/// location in the `artifact_proxy` role.
+pub const LOCAL_BINARIES_URL_PATH: &str = "local";
+
+/// Get the name of the archive the local binary is packaged in on the artifact proxy.
+pub fn get_local_archive_filename(bin_name: &str, architecture: Architecture) -> String {
+    format!("{bin_name}-local-{}.tar.gz", architecture.target_triple())
+}
+
+impl TestnetDeployer {
+    /// Upload the local binaries to the artifact proxy, where they are packaged as archives so
+    /// they can be downloaded in the same way as the archives from S3.
+    ///
+    /// The binaries must have been built for the architecture of the VMs. Nothing is done if the
+    /// deployment does not use local binaries.
+    pub fn upload_local_binaries(
+        &self,
+        binary_option: &BinaryOption,
+        architecture: Architecture,
+    ) -> Result<()> {
+        let BinaryOption::Local {
+            ant_path,
+            antctl_path,
+            antctld_path,
+            antnode_path,
+        } = binary_option
+        else {
+            return Ok(());
+        };
+
+        let mut binaries = vec![
+            ("antctl", antctl_path),
+            ("antctld", antctld_path),
+            ("antnode", antnode_path),
+        ];
+        if let Some(ant_path) = ant_path {
+            binaries.push(("ant", ant_path));
+        }
+
+        let mut local_binaries = Vec::new();
+        for (bin_name, path) in binaries {
+            if !path.is_file() {
+                return Err(Error::LocalBinaryNotFound(path.clone()));
+            }
+            let path = path.canonicalize()?;
+            debug!("Uploading {} as the {bin_name} binary", path.display());
+            local_binaries.push(json!({
+                "name": bin_name,
+                "path": path.to_string_lossy(),
+                "archive_filename": get_local_archive_filename(bin_name, architecture),
+            }));
+        }
+
+        let mut extra_vars = ExtraVarsDocBuilder::default();
+        extra_vars.add_serde_value("local_binaries", json!(local_binaries));
+        self.ansible_provisioner.ansible_runner.run_playbook(
+            AnsiblePlaybook::UploadLocalBinaries,
+            AnsibleInventoryType::ArtifactProxy,
+            Some(extra_vars.build()),
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,338 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    error::{Error, Result},
+    s3::S3Repository,
+};
+use serde::{Deserialize, Serialize};
+use std::{fs::File, io::Write, path::PathBuf, time::Duration};
+use tracing::debug;
+
+/// The bucket where the lock for each environment is stored.
+pub const LOCK_BUCKET: &str = "sn-testnet-locks";
+const LOCK_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Details of whoever is holding the lock for an environment.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LockHolder {
+    pub acquired_at: String,
+    pub command: String,
+    pub host: String,
+    pub pid: u32,
+    pub user: String,
+}
+
+impl std::fmt::Display for LockHolder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}@{} (pid {}) running '{}' since {}",
+            self.user, self.host, self.pid, self.command, self.acquired_at
+        )
+    }
+}
+
+impl LockHolder {
+    fn current(command: &str) -> Self {
+        Self {
+            acquired_at: chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            command: command.to_string(),
+            host: get_host_name(),
+            pid: std::process::id(),
+            user: std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .unwrap_or_else(|_| "unknown".to_string()),
+        }
+    }
+
+    /// Whether this describes the same run as `other`.
+    fn is_same_holder(&self, other: &LockHolder) -> bool {
+        self.host == other.host && self.pid == other.pid && self.acquired_at == other.acquired_at
+    }
+
+    /// A lock held locally by a process that no longer exists was left behind by a run that did
+    /// not exit cleanly.
+    fn is_stale_local_lock(&self) -> bool {
+        self.host == get_host_name()
+            && cfg!(target_os = "linux")
+            && !PathBuf::from(format!("/proc/{}", self.pid)).exists()
+    }
+}
+
+/// An advisory lock that prevents two operational commands running against the same environment
+/// at the same time.
+///
+/// The lock is held both locally, in the data directory, and remotely, in S3, so that it applies
+/// to runs from other machines, such as CI. It is advisory, so it only protects against other
+/// runs of the deployer.
+pub struct EnvironmentLock {
+    environment_name: String,
+    /// The holder recorded when the lock was acquired, used to check the lock is still ours
+    /// before it is released.
+    holder: Option<LockHolder>,
+    s3_repository: S3Repository,
+}
+
+impl EnvironmentLock {
+    /// Acquire the lock for an environment.
+    ///
+    /// The remote lock is only written if there is no lock in S3 already, with the check made by
+    /// S3 as part of the write, so two runs racing for the lock cannot both acquire it.
+    ///
+    /// If the lock is held by someone else, `wait` will poll until it is released, and `steal`
+    /// will take it over. Otherwise an error describing the holder is returned.
+    pub async fn acquire(
+        s3_repository: &S3Repository,
+        environment_name: &str,
+        command: &str,
+        wait: bool,
+        steal: bool,
+    ) -> Result<Self> {
+        let mut lock = Self {
+            environment_name: environment_name.to_string(),
+            holder: None,
+            s3_repository: s3_repository.clone(),
+        };
+        let holder = LockHolder::current(command);
+        let json = serde_json::to_string(&holder)?;
+        let object_key = get_object_key(environment_name);
+        loop {
+            let local_holder = lock.get_local_holder()?.filter(|local_holder| {
+                let is_stale = local_holder.is_stale_local_lock();
+                if is_stale {
+                    debug!("Ignoring stale local lock held by {local_holder}");
+                }
+                !is_stale
+            });
+            let current_holder = match local_holder {
+                Some(local_holder) => local_holder,
+                None => {
+                    if s3_repository
+                        .put_object_if_absent(LOCK_BUCKET, &object_key, json.clone().into_bytes())
+                        .await?
+                    {
+                        break;
+                    }
+                    match lock.get_remote_holder().await? {
+                        Some(remote_holder) => remote_holder,
+                        // The lock was released between the write and the read.
+                        None => continue,
+                    }
+                }
+            };
+            match current_holder {
+                current_holder if steal => {
+                    println!("Stealing the lock for {environment_name} from {current_holder}");
+                    let temp_dir = tempfile::tempdir()?;
+                    let path = temp_dir.path().join(&object_key);
+                    let mut file = File::create(&path)?;
+                    file.write_all(json.as_bytes())?;
+                    s3_repository.upload_file(LOCK_BUCKET, &path, false).await?;
+                    break;
+                }
+                current_holder if wait => {
+                    println!(
+                        "The {environment_name} environment is locked by {current_holder}. Waiting {}s...",
+                        LOCK_POLL_INTERVAL.as_secs()
+                    );
+                    tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+                }
+                current_holder => {
+                    return Err(Error::EnvironmentLocked {
+                        name: environment_name.to_string(),
+                        holder: current_holder.to_string(),
+                    });
+                }
+            }
+        }
+
+        let mut file = File::create(lock.get_local_path()?)?;
+        file.write_all(json.as_bytes())?;
+        lock.holder = Some(holder);
+
+        debug!("Acquired the lock for {environment_name}");
+        Ok(lock)
+    }
+
+    /// Release the lock.
+    ///
+    /// The lock in S3 is only removed if it is still ours. If it was stolen in the meantime, it
+    /// now belongs to the other run, and it is left in place.
+    pub async fn release(self) -> Result<()> {
+        if let Some(holder) = &self.holder {
+            match self.get_remote_holder().await? {
+                Some(remote_holder) if !remote_holder.is_same_holder(holder) => {
+                    println!(
+                        "The lock for {} was taken over by {remote_holder}, so it was not released",
+                        self.environment_name
+                    );
+                    if self
+                        .get_local_holder()?
+                        .is_some_and(|local_holder| local_holder.is_same_holder(holder))
+                    {
+                        self.remove_local()?;
+                    }
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+        self.remove().await
+    }
+
+    /// Remove the lock for an environment, whoever is holding it, returning the holder.
+    ///
+    /// Unlike `steal`, this does not take the lock, so it can be used to clear the lock without
+    /// running anything.
+    pub async fn force_unlock(
+        s3_repository: &S3Repository,
+        environment_name: &str,
+    ) -> Result<Option<LockHolder>> {
+        let lock = Self {
+            environment_name: environment_name.to_string(),
+            holder: None,
+            s3_repository: s3_repository.clone(),
+        };
+        let holder = match lock.get_local_holder()? {
+            Some(holder) => Some(holder),
+            None => lock.get_remote_holder().await?,
+        };
+        lock.remove().await?;
+        Ok(holder)
+    }
+
+    /// Remove both the local and remote lock, without checking who is holding them.
+    async fn remove(&self) -> Result<()> {
+        self.remove_local()?;
+        self.s3_repository
+            .delete_object(LOCK_BUCKET, &get_object_key(&self.environment_name))
+            .await?;
+        debug!("Released the lock for {}", self.environment_name);
+        Ok(())
+    }
+
+    fn remove_local(&self) -> Result<()> {
+        let path = self.get_local_path()?;
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    fn get_local_holder(&self) -> Result<Option<LockHolder>> {
+        let path = self.get_local_path()?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let data = std::fs::read_to_string(path)?;
+        Ok(Some(serde_json::from_str(&data)?))
+    }
+
+    async fn get_remote_holder(&self) -> Result<Option<LockHolder>> {
+        let temp_file = tempfile::NamedTempFile::new()?;
+        match self
+            .s3_repository
+            .download_object(
+                LOCK_BUCKET,
+                &get_object_key(&self.environment_name),
+                temp_file.path(),
+            )
+            .await
+        {
+            Ok(_) => {
+                let data = std::fs::read_to_string(temp_file.path())?;
+                Ok(Some(serde_json::from_str(&data)?))
+            }
+            Err(Error::GetS3ObjectError(_, _)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn get_local_path(&self) -> Result<PathBuf> {
+        let path = dirs_next::data_dir()
+            .ok_or_else(|| Error::CouldNotRetrieveDataDirectory)?
+            .join("safe")
+            .join("testnet-deploy");
+        if !path.exists() {
+            std::fs::create_dir_all(&path)?;
+        }
+        Ok(path.join(get_object_key(&self.environment_name)))
+    }
+}
+
+fn get_object_key(environment_name: &str) -> String {
+    format!("{environment_name}.lock")
+}
+
+fn get_host_name() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| {
+            std::fs::read_to_string("/etc/hostname")
+                .ok()
+                .map(|name| name.trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color_eyre::Result;
+
+    fn get_holder(pid: u32) -> LockHolder {
+        LockHolder {
+            acquired_at: "2024-04-12T10:00:00Z".to_string(),
+            command: "upscale --name alpha".to_string(),
+            host: get_host_name(),
+            pid,
+            user: "chriso".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_lock_holder_display() -> Result<()> {
+        let mut holder = get_holder(1234);
+        holder.host = "ci-runner".to_string();
+        assert_eq!(
+            "chriso@ci-runner (pid 1234) running 'upscale --name alpha' since 2024-04-12T10:00:00Z",
+            holder.to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_same_holder() -> Result<()> {
+        let holder = get_holder(1234);
+        assert!(holder.is_same_holder(&get_holder(1234)));
+
+        // The same process can acquire the lock again later, after releasing it.
+        let mut later = get_holder(1234);
+        later.acquired_at = "2024-04-12T11:00:00Z".to_string();
+        assert!(!holder.is_same_holder(&later));
+
+        let mut other_host = get_holder(1234);
+        other_host.host = format!("{}-other", other_host.host);
+        assert!(!holder.is_same_holder(&other_host));
+
+        assert!(!holder.is_same_holder(&get_holder(1235)));
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_is_stale_local_lock() -> Result<()> {
+        assert!(!get_holder(std::process::id()).is_stale_local_lock());
+        // The maximum PID on Linux is well below this.
+        assert!(get_holder(u32::MAX).is_stale_local_lock());
+
+        let mut remote = get_holder(u32::MAX);
+        remote.host = format!("{}-other", remote.host);
+        assert!(!remote.is_stale_local_lock());
+        Ok(())
+    }
+}
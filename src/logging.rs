@@ -0,0 +1,198 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    error::{Error, Result},
+    redact::redact,
+};
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::PathBuf,
+    str::FromStr,
+    sync::Mutex,
+};
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{
+    filter::{EnvFilter, Targets},
+    fmt::{self, MakeWriter},
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+    Layer,
+};
+
+/// The level at which the deployer's own messages are written to the run log file, regardless of
+/// the level selected for the terminal with `RUST_LOG`.
+const FILE_LOG_LEVEL: LevelFilter = LevelFilter::DEBUG;
+/// The name of the log file written for each run.
+pub const RUN_LOG_FILE_NAME: &str = "testnet-deploy.log";
+/// The log targets for the library and the binary.
+const FILE_LOG_TARGETS: [&str; 2] = ["sn_testnet_deploy", "testnet_deploy"];
+/// The filter for the terminal when `RUST_LOG` is not set. The deployer reports its progress at
+/// the info level, so those messages are shown, but only errors are shown for dependencies.
+const DEFAULT_TERMINAL_FILTER: &str = "error,sn_testnet_deploy=info,testnet_deploy=info";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RunLogFormat {
+    Json,
+    #[default]
+    Text,
+}
+
+impl FromStr for RunLogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(RunLogFormat::Json),
+            "text" => Ok(RunLogFormat::Text),
+            _ => Err(format!("Invalid run log format: {s}")),
+        }
+    }
+}
+
+/// Masks any registered secret in the output of another writer.
+///
+/// Each event is formatted in full before it is written, so a secret is never split across two
+/// writes.
+struct RedactingWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        self.inner.write_all(redact(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct RedactingMakeWriter<M> {
+    inner: M,
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for RedactingMakeWriter<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            inner: self.inner.make_writer(),
+        }
+    }
+}
+
+/// The directory the run logs for an environment are written to, in the data directory, with a
+/// subdirectory for each run named by its timestamp.
+///
+/// Keeping them in the data directory means they are found regardless of where the deployer is
+/// run from.
+pub fn get_run_log_directory(environment_name: &str) -> Result<PathBuf> {
+    let path = dirs_next::data_dir()
+        .ok_or_else(|| Error::CouldNotRetrieveDataDirectory)?
+        .join("safe")
+        .join("testnet-deploy")
+        .join("run-logs")
+        .join(environment_name);
+    Ok(path)
+}
+
+/// Initialise logging for a run.
+///
+/// Events are written to the terminal, filtered by `RUST_LOG`. If an environment name is
+/// supplied, the deployer's own events are also written to
+/// `run-logs/<name>/<timestamp>/testnet-deploy.log` in the data directory, and the path of that
+/// file is returned. The file means a failed run can still be debugged once the terminal
+/// scrollback is gone.
+///
+/// Secrets, such as the values of some environment variables, are masked in both the terminal and
+/// the file.
+pub fn init_logging(
+    environment_name: Option<&str>,
+    format: RunLogFormat,
+) -> Result<Option<PathBuf>> {
+    let (file_layer, path) = match environment_name {
+        Some(name) => {
+            let timestamp = chrono::Utc::now().format("%Y%m%dT%H%M%S").to_string();
+            let dir = get_run_log_directory(name)?.join(timestamp);
+            std::fs::create_dir_all(&dir)?;
+            let path = dir.join(RUN_LOG_FILE_NAME);
+            let writer = RedactingMakeWriter {
+                inner: Mutex::new(File::create(&path)?),
+            };
+            let targets = FILE_LOG_TARGETS
+                .iter()
+                .fold(Targets::new(), |targets, target| {
+                    targets.with_target(*target, FILE_LOG_LEVEL)
+                });
+            let layer = match format {
+                RunLogFormat::Json => fmt::layer()
+                    .json()
+                    .with_writer(writer)
+                    .with_filter(targets)
+                    .boxed(),
+                RunLogFormat::Text => fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(writer)
+                    .with_filter(targets)
+                    .boxed(),
+            };
+            (Some(layer), Some(path))
+        }
+        None => (None, None),
+    };
+
+    let terminal_filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(DEFAULT_TERMINAL_FILTER));
+    let terminal_layer = fmt::layer()
+        .without_time()
+        .with_target(false)
+        .with_writer(RedactingMakeWriter { inner: io::stderr })
+        .with_filter(terminal_filter);
+
+    tracing_subscriber::registry()
+        .with(file_layer)
+        .with(terminal_layer)
+        .try_init()
+        .map_err(|err| Error::LoggingConfiguration(err.to_string()))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redact::register_secret;
+    use color_eyre::{eyre::eyre, Result};
+
+    #[test]
+    fn test_redacting_writer_masks_secrets() -> Result<()> {
+        register_secret("hunter2-logging-test");
+        let mut writer = RedactingWriter { inner: Vec::new() };
+        writer.write_all(b"INFO Using the key hunter2-logging-test\n")?;
+        assert_eq!(
+            "INFO Using the key [REDACTED]\n",
+            String::from_utf8(writer.inner)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_run_log_format() -> Result<()> {
+        assert_eq!(
+            RunLogFormat::Json,
+            "JSON".parse::<RunLogFormat>().map_err(|err| eyre!(err))?
+        );
+        assert_eq!(
+            RunLogFormat::Text,
+            "text".parse::<RunLogFormat>().map_err(|err| eyre!(err))?
+        );
+        assert!("yaml".parse::<RunLogFormat>().is_err());
+        Ok(())
+    }
+}
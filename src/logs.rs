@@ -8,19 +8,27 @@ use crate::{
     error::{Error, Result},
     get_progress_bar,
     inventory::VirtualMachine,
+    logging::RUN_LOG_FILE_NAME,
     run_external_command,
     s3::S3Repository,
     TestnetDeployer,
 };
+use chrono::{DateTime, Utc};
 use fs_extra::dir::{copy, remove, CopyOptions};
-use log::debug;
 use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use std::{
     fs::File,
     io::{Cursor, Read, Write},
     net::IpAddr,
     path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
 };
+use tracing::debug;
+
+/// The period covered by each log segment Logstash writes to S3. This must match `time_file` in
+/// the Logstash configuration for log forwarding.
+const LOG_SEGMENT_DURATION: Duration = Duration::from_secs(5 * 60);
 
 impl TestnetDeployer {
     pub fn rsync_logs(
@@ -72,11 +80,12 @@ impl TestnetDeployer {
         rsync_args.extend(vec![
             "-e".to_string(),
             format!(
-                "ssh -i {} -q -o StrictHostKeyChecking=no -o BatchMode=yes -o ConnectTimeout=30",
+                "ssh -i {} -l {} -q -o StrictHostKeyChecking=no -o BatchMode=yes -o ConnectTimeout=30",
                 self.ssh_client
                     .get_private_key_path()
                     .to_string_lossy()
-                    .as_ref()
+                    .as_ref(),
+                self.cloud_provider.get_ssh_user()
             ),
         ]);
 
@@ -133,7 +142,7 @@ impl TestnetDeployer {
         let vm_path = log_abs_dest.join(vm_name);
         let mut rsync_args_clone = rsync_args.to_vec();
 
-        rsync_args_clone.push(format!("root@{ip_address}:/mnt/antnode-storage/log/"));
+        rsync_args_clone.push(format!("{ip_address}:/mnt/antnode-storage/log/"));
         rsync_args_clone.push(vm_path.to_string_lossy().to_string());
 
         debug!("Rsync logs to our machine for {vm_name:?} : {ip_address}");
@@ -156,21 +165,27 @@ impl TestnetDeployer {
         let log_abs_dest = create_initial_log_dir_setup(&root_dir, name, &all_node_inventory)?;
 
         let rg_cmd = format!("rg {rg_args} /mnt/antnode-storage/log//");
+        let ssh_user = self.cloud_provider.get_ssh_user();
         println!("Running ripgrep with command: {rg_cmd}");
 
         // Get current date and time
         let now = chrono::Utc::now();
         let timestamp = now.format("%Y%m%dT%H%M%S").to_string();
         let progress_bar = get_progress_bar(all_node_inventory.len() as u64)?;
-        let _failed_inventory = all_node_inventory
+        let all_matches = Mutex::new(Vec::new());
+        let failed_inventory = all_node_inventory
             .par_iter()
             .filter_map(|vm| {
                 let op =
                     match self
                         .ssh_client
-                        .run_command(&vm.public_ip_addr, "root", &rg_cmd, true)
+                        .execute_blocking(&vm.public_ip_addr, &ssh_user, &rg_cmd)
                     {
-                        Ok(output) => {
+                        Ok(output) if output.success() => {
+                            let output = output.stdout_lines();
+                            if let Ok(mut all_matches) = all_matches.lock() {
+                                all_matches.push((vm.name.clone(), output.clone()));
+                            }
                             match Self::store_rg_output(
                                 &timestamp,
                                 &rg_cmd,
@@ -188,36 +203,33 @@ impl TestnetDeployer {
                                 }
                             }
                         }
-                        Err(Error::ExternalCommandRunFailed {
-                            binary,
-                            exit_status,
-                        }) => {
-                            if let Some(1) = exit_status.code() {
-                                debug!("No matches found for {:?}", vm.public_ip_addr);
-                                match Self::store_rg_output(
-                                    &timestamp,
-                                    &rg_cmd,
-                                    &["No matches found".to_string()],
-                                    &log_abs_dest,
-                                    &vm.name,
-                                ) {
-                                    Ok(_) => None,
-                                    Err(err) => {
-                                        println!(
-                                            "Failed store output for {:?} with: {err:?}",
-                                            vm.public_ip_addr
-                                        );
-                                        Some(vm)
-                                    }
+                        Ok(output) if output.exit_status == Some(1) => {
+                            debug!("No matches found for {:?}", vm.public_ip_addr);
+                            match Self::store_rg_output(
+                                &timestamp,
+                                &rg_cmd,
+                                &["No matches found".to_string()],
+                                &log_abs_dest,
+                                &vm.name,
+                            ) {
+                                Ok(_) => None,
+                                Err(err) => {
+                                    println!(
+                                        "Failed store output for {:?} with: {err:?}",
+                                        vm.public_ip_addr
+                                    );
+                                    Some(vm)
                                 }
-                            } else {
-                                println!(
-                                    "Failed to run rg query for {:?} with: {binary}",
-                                    vm.public_ip_addr
-                                );
-                                Some(vm)
                             }
                         }
+                        Ok(output) => {
+                            println!(
+                                "Failed to run rg query for {:?} with: {}",
+                                vm.public_ip_addr,
+                                output.stderr.trim()
+                            );
+                            Some(vm)
+                        }
                         Err(err) => {
                             println!(
                                 "Failed to run rg query for {:?} with: {err:?}",
@@ -234,6 +246,32 @@ impl TestnetDeployer {
         progress_bar.finish_and_clear();
         println!("Ripgrep completed!");
 
+        // Aggregate the matches from every VM into a single file, with each line prefixed by the
+        // VM name. Each match already includes the log path, which identifies the node.
+        let mut all_matches = all_matches.into_inner().unwrap_or_default();
+        all_matches.sort_by(|a, b| a.0.cmp(&b.0));
+        let aggregated_path = log_abs_dest.join(format!("rg-{timestamp}.log"));
+        let mut file = File::create(&aggregated_path)?;
+        writeln!(file, "Command: {rg_cmd}")?;
+        let mut match_count = 0;
+        for (vm_name, output) in all_matches.iter() {
+            for line in output.iter() {
+                writeln!(file, "{vm_name}: {line}")?;
+                match_count += 1;
+            }
+        }
+        println!(
+            "Found {match_count} matches on {} VMs. Aggregated results written to {}",
+            all_matches.len(),
+            aggregated_path.to_string_lossy()
+        );
+        if !failed_inventory.is_empty() {
+            println!("Failed to run the query on the following VMs:");
+            for vm in failed_inventory.iter() {
+                println!("- {}: {}", vm.name, vm.public_ip_addr);
+            }
+        }
+
         Ok(())
     }
 
@@ -268,8 +306,23 @@ impl TestnetDeployer {
     pub fn copy_logs(&self, name: &str, resources_only: bool) -> Result<()> {
         let dest = PathBuf::from(".").join("logs").join(name);
         if dest.exists() {
-            println!("Removing existing {} directory", dest.to_string_lossy());
-            remove(dest.clone())?;
+            println!(
+                "Removing previously copied logs from {}",
+                dest.to_string_lossy()
+            );
+            for entry in std::fs::read_dir(&dest)? {
+                let path = entry?.path();
+                // Earlier versions of the deployer wrote their own run logs here, so they are
+                // retained.
+                if path.join(RUN_LOG_FILE_NAME).exists() {
+                    continue;
+                }
+                if path.is_dir() {
+                    remove(&path)?;
+                } else {
+                    std::fs::remove_file(&path)?;
+                }
+            }
         }
         std::fs::create_dir_all(&dest)?;
         self.ansible_provisioner.copy_logs(name, resources_only)?;
@@ -286,12 +339,34 @@ impl TestnetDeployer {
     }
 }
 
-pub async fn get_logs(name: &str) -> Result<()> {
+/// Retrieve the logs that were forwarded to S3 by Logstash.
+///
+/// If `since` or `until` are used, only the log segments that overlap that window are retrieved.
+/// Each segment is an S3 object covering the `LOG_SEGMENT_DURATION` before it was last modified.
+pub async fn get_logs(
+    name: &str,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+) -> Result<()> {
     let dest_path = std::env::current_dir()?.join("logs").join(name);
     std::fs::create_dir_all(dest_path.clone())?;
     let s3_repository = S3Repository {};
+    if since.is_none() && until.is_none() {
+        s3_repository
+            .download_folder("sn-testnet", &format!("testnet-logs/{name}"), &dest_path)
+            .await?;
+        return Ok(());
+    }
+
+    let from = since.map(|since| since.timestamp()).unwrap_or(0);
+    let to = until.unwrap_or_else(Utc::now).timestamp() + LOG_SEGMENT_DURATION.as_secs() as i64;
     s3_repository
-        .download_folder("sn-testnet", &format!("testnet-logs/{name}"), &dest_path)
+        .download_folder_modified_within(
+            "sn-testnet",
+            &format!("testnet-logs/{name}"),
+            &dest_path,
+            (from, to),
+        )
         .await?;
     Ok(())
 }
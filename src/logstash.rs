@@ -5,7 +5,10 @@
 // Please see the LICENSE file for more details.
 
 use crate::{
-    ansible::{inventory::AnsibleInventoryType, AnsiblePlaybook, AnsibleRunner},
+    ansible::{
+        extra_vars::ExtraVarsDocBuilder, inventory::AnsibleInventoryType, AnsiblePlaybook,
+        AnsibleRunner,
+    },
     digital_ocean::{DigitalOceanClient, DIGITAL_OCEAN_API_BASE_URL, DIGITAL_OCEAN_API_PAGE_SIZE},
     do_clean,
     error::{Error, Result},
@@ -13,11 +16,11 @@ use crate::{
     terraform::TerraformRunner,
     CloudProvider, ANSIBLE_DEFAULT_FORKS,
 };
-use log::debug;
 use std::{
     net::{IpAddr, SocketAddr},
     path::PathBuf,
 };
+use tracing::debug;
 
 pub const LOGSTASH_PORT: u16 = 5044;
 
@@ -225,21 +228,42 @@ impl LogstashDeploy {
         Ok(())
     }
 
+    /// Provision all the VMs in the stack.
+    ///
+    /// The stack shares a single certificate, so it names the address of every VM.
     pub fn provision(&self, name: &str) -> Result<()> {
-        println!("Obtaining IP address for Logstash VM...");
+        println!("Obtaining IP addresses for the Logstash VMs...");
         let logstash_inventory = self
             .ansible_runner
-            .get_inventory(AnsibleInventoryType::Logstash, false)?;
-        let logstash_ip = logstash_inventory[0].public_ip_addr;
-        self.ssh_client
-            .wait_for_ssh_availability(&logstash_ip, &self.cloud_provider.get_ssh_user())?;
+            .get_inventory(AnsibleInventoryType::Logstash, true)?;
+        if logstash_inventory.is_empty() {
+            return Err(Error::EmptyInventory(AnsibleInventoryType::Logstash));
+        }
+        for vm in logstash_inventory.iter() {
+            self.ssh_client.wait_for_ssh_availability(
+                &vm.public_ip_addr,
+                &self.cloud_provider.get_ssh_user(),
+            )?;
+        }
+
+        let mut extra_vars = ExtraVarsDocBuilder::default();
+        extra_vars.add_variable("provider", &self.cloud_provider.to_string());
+        extra_vars.add_variable("stack_name", name);
+        extra_vars.add_variable(
+            "logstash_host_ip_address",
+            &logstash_inventory[0].public_ip_addr.to_string(),
+        );
+        extra_vars.add_list_variable(
+            "logstash_host_ip_addresses",
+            logstash_inventory
+                .iter()
+                .map(|vm| vm.public_ip_addr.to_string())
+                .collect(),
+        );
         self.ansible_runner.run_playbook(
             AnsiblePlaybook::Logstash,
             AnsibleInventoryType::Logstash,
-            Some(format!(
-                "{{ \"provider\": \"{}\", \"stack_name\": \"{name}\", \"logstash_host_ip_address\": \"{logstash_ip}\" }}",
-                self.cloud_provider
-            )),
+            Some(extra_vars.build()),
         )?;
         Ok(())
     }
@@ -5,6 +5,7 @@
 
 use alloy::primitives::{Address, U256};
 use ant_releases::{AntReleaseRepoActions, ReleaseType};
+use chrono::{DateTime, Utc};
 use clap::{Parser, Subcommand};
 use color_eyre::{
     eyre::{bail, eyre, OptionExt},
@@ -12,45 +13,227 @@ use color_eyre::{
 };
 use dotenv::dotenv;
 use evmlib::Network;
-use log::debug;
+use inquire::Select;
+use libp2p::{multiaddr::Protocol, Multiaddr};
+use regex::Regex;
 use semver::Version;
 use sn_testnet_deploy::{
     ansible::{
-        extra_vars::ExtraVarsDocBuilder,
+        extra_vars::{build_peer_filter_extra_vars_doc, ExtraVarsDocBuilder},
         inventory::{generate_custom_environment_inventory, AnsibleInventoryType},
         AnsiblePlaybook,
     },
+    artifact_repository::ArtifactRepositoryConfig,
+    artifacts::ArtifactGcOptions,
+    bisect::{bisect, BisectOptions},
     bootstrap::BootstrapOptions,
+    build_cache::get_branch_commit_sha,
     calculate_size_per_attached_volume,
+    chaos::{apply_template, fill_disks, inject_fault, ChaosTemplate, Fault},
     deploy::DeployOptions,
+    downloaders::DownloaderDeployOptions,
+    downscale::DownscaleOptions,
     error::Error,
+    firewall::{apply_firewall, FirewallProfile},
     funding::FundingOptions,
+    generate_environment_name,
+    genesis::GenesisRebuildOptions,
     get_environment_details,
+    grafana::DEFAULT_DASHBOARD_UID,
     infra::InfraRunOptions,
     inventory::{
-        get_data_directory, DeploymentInventory, DeploymentInventoryService, VirtualMachine,
+        get_cached_environment_names, get_data_directory, print_health_summary,
+        reconcile_node_counts, DeploymentInventory, DeploymentInventoryService, VirtualMachine,
     },
+    is_known_node_env_variable,
+    lock::EnvironmentLock,
+    logging::{init_logging, RunLogFormat},
     logstash::LogstashDeployBuilder,
-    network_commands, notify_slack,
+    metrics::export_metrics,
+    network_commands,
+    network_conditions::NetworkConditions,
+    network_restart::NetworkRestartOptions,
+    notifications::{
+        EventSeverity, NotificationConfig, NotificationEvent, SlackNotifier, WebhookFormat,
+    },
+    notify_slack,
+    preflight::{run_preflight_checks, DEFAULT_MIN_CREDENTIAL_VALIDITY},
+    progress,
+    reaper::{reap_expired_environments, ReapOptions},
+    redact,
+    release_channel::{resolve_release_channel, ReleaseChannel},
+    replay::{read_replay_trace, replay_trace, ReplayOptions},
+    reserve_stdout, rpc_proxy, run_log,
+    s3::S3Repository,
+    search::{print_search_matches, search_cached_inventories},
     setup::setup_dotenv_file,
+    slo::{UptimeHistory, UptimeSample},
+    smoke_test::{run_smoke_test, SmokeTestOptions},
+    snapshot::SnapshotOptions,
+    status_badge::EnvironmentStatus,
+    support_bundle::{create_support_bundle, save_last_error},
+    trends::{
+        get_run_summaries, get_run_summary_bucket_name, print_trend_report, record_run_metrics,
+        upload_run_summary, RunSummary,
+    },
+    upload_backpressure::UploadBackpressureOptions,
     upscale::UpscaleOptions,
-    BinaryOption, CloudProvider, EnvironmentType, EvmNetwork, LogFormat, NodeType,
-    TestnetDeployBuilder, UpgradeOptions,
+    validate_env_variable, validate_environment_name,
+    versions_file::VersionsFile,
+    wireguard::WIREGUARD_OVERLAY_ENV_VAR,
+    write_environment_details, Architecture, BandwidthClass, BinaryOption, CleanOptions,
+    CloudProvider, EnvironmentType, EvmNetwork, LogFormat, NatType, NodeType, ReachabilityMode,
+    RestartMode, RestartPolicy, TelemetryConfig, TestnetDeployBuilder, UpgradeOptions,
 };
-use std::{env, net::IpAddr};
-use std::{str::FromStr, time::Duration};
+use std::{env, io::IsTerminal, net::IpAddr, path::PathBuf};
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use tracing::debug;
+
+const ROLE_ENV_VAR: &str = "TESTNET_DEPLOY_ROLE";
 
 #[derive(Parser, Debug)]
-#[clap(name = "sn-testnet-deploy", version = env!("CARGO_PKG_VERSION"))]
+#[clap(
+    name = "sn-testnet-deploy",
+    version = env!("CARGO_PKG_VERSION"),
+    after_help = "Setting TESTNET_DEPLOY_ROLE=observer restricts the tool to the commands that \
+    don't change an environment. This guards against mistakes; it is not access control, since \
+    anyone can unset the variable. Observers must also be given read-only cloud and S3 \
+    credentials."
+)]
 struct Opt {
     #[command(subcommand)]
     command: Commands,
+    /// Remove the lock for the environment and the lock on its Terraform state, whoever holds
+    /// them, before running the command.
+    ///
+    /// Use this to recover from a run that was killed while it was holding the locks.
+    #[clap(long, global = true, conflicts_with_all = ["steal", "wait"])]
+    force_unlock: bool,
+    /// Send a notification to Slack when the command completes.
+    ///
+    /// This applies to commands run against an environment. Use SLACK_NOTIFICATION_MODE=digest to
+    /// batch the notifications for an environment rather than posting one for each command.
+    #[clap(long, global = true)]
+    notify: bool,
+    /// Use the static inventories written by the last run that retrieved the inventory, rather
+    /// than querying the Digital Ocean API for the VMs.
+    ///
+    /// The static inventories are written to 'resources/ansible/inventory' whenever the inventory
+    /// is retrieved, e.g., at the end of a deploy. With this flag, the DO_PAT variable is not
+    /// required for commands that only run playbooks against the VMs, like the logs, chaos or
+    /// upgrade commands. It can also be enabled by setting ANSIBLE_OFFLINE_INVENTORY.
+    #[clap(long, global = true)]
+    offline_inventory: bool,
+    /// The format of the log file written for a run against an environment.
+    ///
+    /// The file is written to 'run-logs/<name>/<timestamp>/testnet-deploy.log', in the data
+    /// directory, e.g., '~/.local/share/safe/testnet-deploy' on Linux.
+    ///
+    /// Valid values are "text" or "json".
+    #[clap(long, global = true, default_value = "text")]
+    run_log_format: RunLogFormat,
+    /// Skip the checks of the local clock and the credentials that run before a deploy, bootstrap
+    /// or upscale.
+    ///
+    /// The checks refuse to start if the clock is too far out to sign requests to S3, or the
+    /// credentials will expire before the command is likely to finish.
+    #[clap(long, global = true)]
+    skip_preflight: bool,
+    /// Take over the lock for the environment, even if it is held by another run.
+    ///
+    /// Use this to clear a lock left behind by a run that did not exit cleanly.
+    #[clap(long, global = true, conflicts_with = "wait")]
+    steal: bool,
+    /// If the environment is locked by another run, wait for the lock to be released rather
+    /// than exiting.
+    #[clap(long, global = true)]
+    wait: bool,
+    /// Connect to the VMs over the environment's WireGuard overlay, rather than their public IPs.
+    ///
+    /// This applies to the SSH commands and the Ansible playbooks. The overlay must have been
+    /// set up with the 'wireguard setup' command from this machine and be up. VMs that are not on
+    /// the overlay are still reached on their public IPs. It can also be enabled by setting
+    /// WIREGUARD_OVERLAY.
+    #[clap(long, global = true)]
+    over_wireguard: bool,
 }
 
 #[allow(clippy::large_enum_variant)]
 #[derive(Subcommand, Debug)]
 enum Commands {
+    /// Manage the branch build artifacts.
+    #[clap(name = "artifacts", subcommand)]
+    Artifacts(ArtifactsCommands),
+    /// Find the commit of the autonomi repository that introduced a regression.
+    ///
+    /// The commits between the good and bad commits are bisected. Each commit that is tested is
+    /// built and deployed to a small throwaway environment, named '<name-prefix>-<short sha>',
+    /// where the smoke test is run. A commit is bad if the deployment or the smoke test fails. The
+    /// environment is cleaned before the next commit is tested.
+    ///
+    /// With --reuse-infra, every commit is deployed to a single environment, named
+    /// '<name-prefix>'. The binaries and services of the previous commit are removed before the
+    /// next is deployed, so the VMs are only created once and the builds are incremental.
+    ///
+    /// With --health-script, the script decides whether each commit is good, rather than the
+    /// smoke test. As with 'git bisect run', it exits with 0 for a good commit, 125 for a commit
+    /// that can't be tested, or any other code for a bad commit. These variables are set for it:
+    ///   * BISECT_COMMIT
+    ///   * BISECT_ENVIRONMENT_NAME
+    ///   * BISECT_GENESIS_MULTIADDR
+    ///   * BISECT_REPO_OWNER
+    ///
+    /// Up to 250 commits can be bisected.
+    #[clap(name = "bisect", verbatim_doc_comment)]
+    Bisect {
+        /// A commit where the regression is present.
+        ///
+        /// If not supplied, the head of the --branch argument is used.
+        #[arg(long, required_unless_present = "branch", verbatim_doc_comment)]
+        bad: Option<String>,
+        /// The branch whose head is the bad commit, if the --bad argument is not supplied.
+        #[arg(long, conflicts_with = "bad")]
+        branch: Option<String>,
+        /// A commit where the regression is not present. It must be an ancestor of the bad commit.
+        #[arg(long)]
+        good: String,
+        /// A script that decides whether each commit is good, in place of the smoke test.
+        #[arg(long)]
+        health_script: Option<PathBuf>,
+        /// The prefix for the names of the environments that are deployed for each commit.
+        #[arg(long, default_value = "bisect")]
+        name_prefix: String,
+        /// The number of nodes to run on each node VM.
+        #[clap(long, default_value_t = 5)]
+        node_count: u16,
+        /// The number of node VMs to deploy for each commit.
+        #[clap(long, default_value_t = 2)]
+        node_vm_count: u16,
+        /// The cloud provider to deploy the environments on.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+        /// The owner of the autonomi repository to bisect.
+        #[arg(long, default_value = "maidsafe")]
+        repo_owner: String,
+        /// Deploy every commit to the same environment, rather than one for each commit.
+        #[arg(long)]
+        reuse_infra: bool,
+        /// The rewards address for each of the antnode services.
+        #[arg(long, required = true)]
+        rewards_address: String,
+        /// The size of the random file uploaded by the smoke test, in kilobytes.
+        #[clap(long, default_value_t = 1024)]
+        smoke_test_file_size_kb: u64,
+    },
     /// Bootstrap a new network from an existing deployment.
+    ///
+    /// Only node and private node VMs are created. They join the existing network through the
+    /// bootstrap peer or network contacts URL, so there are no genesis, peer cache, uploader or
+    /// faucet VMs.
+    #[clap(verbatim_doc_comment)]
     Bootstrap {
         /// Supply a version number for the antctl binary.
         ///
@@ -89,14 +272,25 @@ enum Commands {
         /// arguments. You can only supply version numbers or a custom branch, not both.
         #[arg(long, verbatim_doc_comment)]
         branch: Option<String>,
+        /// The name of an existing environment to bootstrap from.
+        ///
+        /// The network contacts URL that was published for the environment is used.
+        #[arg(long, conflicts_with_all = ["bootstrap_network_contacts_url", "bootstrap_peer"])]
+        bootstrap_environment: Option<String>,
         /// The network contacts URL to bootstrap from.
         ///
-        /// Either this or the `bootstrap-peer` argument must be provided.
+        /// Either this, the `bootstrap-peer` or the `bootstrap-environment` argument must be
+        /// provided.
+        #[arg(long, visible_alias = "network-contacts-url")]
         bootstrap_network_contacts_url: Option<String>,
         /// The peer from an existing network that we can bootstrap from.
         ///
-        /// Either this or the `bootstrap-network-contacts-url` argument must be provided.
-        #[arg(long)]
+        /// This must be a multiaddr that includes the peer ID, e.g.,
+        /// /ip4/<ip>/udp/<port>/quic-v1/p2p/<peer id>.
+        ///
+        /// Either this, the `bootstrap-network-contacts-url` or the `bootstrap-environment`
+        /// argument must be provided.
+        #[arg(long, value_parser = parse_bootstrap_peer, verbatim_doc_comment)]
         bootstrap_peer: Option<String>,
         /// Specify the chunk size for the custom binaries using a 64-bit integer.
         ///
@@ -110,6 +304,10 @@ enum Commands {
         /// them. The specification will increase in size from development, to staging, to
         /// production.
         ///
+        /// The VM sizes and counts are read from 'dev.tfvars' or 'staging.tfvars' in the
+        /// Terraform directory for the provider. For production, they are read from
+        /// '<name>.tfvars', which must be added for each production environment.
+        ///
         /// The default is 'development'.
         #[clap(long, default_value_t = EnvironmentType::Development, value_parser = parse_deployment_type, verbatim_doc_comment)]
         environment_type: EnvironmentType,
@@ -149,6 +347,15 @@ enum Commands {
         /// The interval between starting each node in milliseconds.
         #[clap(long, value_parser = |t: &str| -> Result<Duration> { Ok(t.parse().map(Duration::from_millis)?)}, default_value = "2000")]
         interval: Duration,
+        /// Limit the rate at which the nodes join the network to this many per minute, across all
+        /// the VMs.
+        ///
+        /// Use this when bootstrapping a large number of nodes into a live network, to avoid
+        /// overwhelming its bootstrap peers. The starts are spread evenly over the VMs, and the
+        /// --interval argument is ignored. The rate can be lower than requested if there are more
+        /// VMs than Ansible forks.
+        #[clap(long, value_parser = clap::value_parser!(u16).range(1..), verbatim_doc_comment)]
+        join_rate: Option<u16>,
         /// Specify the logging format for the nodes.
         ///
         /// Valid values are "default" or "json".
@@ -165,6 +372,26 @@ enum Commands {
         /// The name of the environment
         #[arg(short = 'n', long)]
         name: String,
+        /// The number of NAT gateways to spread the private node VMs across.
+        ///
+        /// The private node VMs are assigned to the gateways in turn, so each gateway routes a shard
+        /// of them. Use 'nat-gateway failover' to move a shard to another gateway.
+        ///
+        /// If the argument is not used, a single gateway is created.
+        #[clap(long, value_parser = clap::value_parser!(u16).range(1..), verbatim_doc_comment)]
+        nat_gateway_count: Option<u16>,
+        /// The type of NAT the gateway simulates for the private nodes.
+        ///
+        /// Valid values are "full-cone", "restricted" or "symmetric".
+        ///
+        /// - full-cone: any host can reach a private node on a port it has used.
+        /// - restricted: the source port is preserved, but only the hosts and ports a private node
+        ///   has sent to can reach it.
+        /// - symmetric: a random source port is used for each destination.
+        ///
+        /// The default is symmetric.
+        #[clap(long, default_value = "symmetric", value_parser = NatType::parse_from_str, verbatim_doc_comment)]
+        nat_type: NatType,
         /// Specify the network ID to use for the node services. This is used to partition the network and will not allow
         /// nodes with different network IDs to join.
         ///
@@ -201,13 +428,27 @@ enum Commands {
         /// argument.
         #[clap(long, verbatim_doc_comment)]
         private_node_count: Option<u16>,
+        /// Run a UPnP daemon (miniupnpd) on the NAT gateways, and start the private nodes with
+        /// '--upnp', so they open their ports through port mappings on the gateway.
+        ///
+        /// This tests the UPnP path a home node would use with a router that supports it.
+        #[clap(long, verbatim_doc_comment)]
+        private_node_upnp: bool,
         /// The number of private node VMs to create.
         ///
-        /// Each VM will run many antnode services.
+        /// Each VM will run many antnode services. All the private node VMs are routed through the
+        /// NAT gateway.
         ///
         /// If the argument is not used, the value will be determined by the 'environment-type'
         #[clap(long, verbatim_doc_comment)]
         private_node_vm_count: Option<u16>,
+        /// The percentage of the --node-vm-count VMs to place behind the NAT gateway as private
+        /// node VMs, rather than specifying the count with --private-node-vm-count.
+        ///
+        /// The rest are generic node VMs. If the percentage is above zero, at least one VM is made
+        /// private.
+        #[clap(long, requires = "node_vm_count", conflicts_with = "private_node_vm_count", value_parser = clap::value_parser!(u8).range(0..=100), verbatim_doc_comment)]
+        private_node_vm_percent: Option<u8>,
         /// The size of the volumes to attach to each private node VM. This argument will set the size of all the
         /// 7 attached volumes.
         ///
@@ -235,8 +476,30 @@ enum Commands {
         #[arg(long, required = true)]
         rewards_address: String,
     },
+    /// Inject faults into randomly selected nodes.
+    ///
+    /// The affected nodes are recorded, with timestamps, in a JSON report under the logs
+    /// directory, for correlation with the network metrics.
+    #[clap(name = "chaos", subcommand)]
+    Chaos(ChaosCommands),
     /// Clean a deployed testnet environment.
+    ///
+    /// By default all the resources for the environment are removed. The retention flags can be
+    /// used to keep some resources around, for faster redeploys or post-mortem analysis. Running
+    /// the command again without the flags will remove the retained resources.
     Clean {
+        /// Retain the build VM.
+        #[clap(long)]
+        keep_build_vm: bool,
+        /// Retain the genesis VM, along with its attached volumes, which hold its data and logs.
+        #[clap(long)]
+        keep_genesis_vm: bool,
+        /// Retain the volumes attached to the genesis VM, which hold its data and logs, but not
+        /// the VM itself.
+        ///
+        /// The volumes are no longer managed by Terraform, so they must be deleted manually.
+        #[clap(long, verbatim_doc_comment)]
+        keep_logs_volume: bool,
         /// The name of the environment.
         #[arg(short = 'n', long)]
         name: String,
@@ -259,6 +522,23 @@ enum Commands {
         #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
         provider: CloudProvider,
     },
+    /// Report the cost of an environment.
+    ///
+    /// The spend so far is worked out from how long each droplet tagged with the environment has
+    /// been running, at its hourly price. The billing API only reports usage for the whole
+    /// account, which is also printed.
+    Cost {
+        /// Also print the projected hourly and daily cost of the resources in the Terraform state,
+        /// including the attached volumes.
+        #[clap(long, verbatim_doc_comment)]
+        estimate: bool,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
     /// Deploy a new testnet environment using the latest version of the antnode binary.
     Deploy {
         /// Set to run Ansible with more verbose output.
@@ -295,6 +575,39 @@ enum Commands {
         /// arguments. You can only supply version numbers or a custom branch, not both.
         #[arg(long, verbatim_doc_comment)]
         antnode_version: Option<String>,
+        /// The CPU architecture to build the binaries for and run them on.
+        ///
+        /// Valid values are "x86_64" or "aarch64". When building from source, the build VM
+        /// cross-compiles for the selected architecture. The VM sizes and images must be ARM
+        /// instances when using "aarch64"; DigitalOcean does not currently provide any.
+        #[clap(long, default_value = "x86_64", value_parser = Architecture::parse_from_str, verbatim_doc_comment)]
+        arch: Architecture,
+        /// Where the VMs download the binary archives from.
+        ///
+        /// Valid values are:
+        ///   * "s3": the buckets the binaries are released to
+        ///   * "s3:<region>[:<bucket-prefix>]": S3 buckets in another region, or with a prefix
+        ///   * "spaces:<region>[:<bucket-prefix>]": Digital Ocean Spaces
+        ///   * An http:// or https:// URL: a web server with a directory for each bucket
+        ///
+        /// A repository other than "s3" must contain the same buckets as the main one, e.g.,
+        /// "antnode" and "sn-node", each with the same object names. The repository is recorded
+        /// in the environment details, so an upscale uses it too.
+        #[clap(long, default_value = "s3", value_parser = ArtifactRepositoryConfig::parse_from_str, verbatim_doc_comment)]
+        artifact_repository: ArtifactRepositoryConfig,
+        /// The number of VMs that run the auditor.
+        ///
+        /// Each auditor uses the genesis node as its peer and serves its web interface on port
+        /// 4242. No auditor VMs are created by default.
+        #[clap(long, verbatim_doc_comment)]
+        auditor_vm_count: Option<u16>,
+        /// Generate the environment name from the --branch argument and the current date, e.g.,
+        /// 'feat-xyz-0412a'.
+        ///
+        /// The final letter is chosen so that the name does not collide with an existing
+        /// environment. Use this instead of --name.
+        #[arg(long, verbatim_doc_comment)]
+        auto_name: bool,
         /// The branch of the Github repository to build from.
         ///
         /// If used, all binaries will be built from this branch. It is typically used for testing
@@ -306,12 +619,34 @@ enum Commands {
         /// arguments. You can only supply version numbers or a custom branch, not both.
         #[arg(long, verbatim_doc_comment)]
         branch: Option<String>,
+        /// Use the binary versions from a release channel.
+        ///
+        /// Valid values are "latest", "stable", "rc" or "nightly". The "latest" channel uses the
+        /// latest version of each binary. The others use the versions from the most recent GitHub
+        /// release on the channel.
+        ///
+        /// The channel is resolved when the deployment starts, and the versions it resolved to are
+        /// recorded in the environment details.
+        ///
+        /// This argument is mutually exclusive with the version arguments and the --branch and
+        /// --repo-owner arguments.
+        #[clap(long, value_parser = ReleaseChannel::parse_from_str, verbatim_doc_comment,
+            conflicts_with_all = ["ant_version", "antctl_version", "antnode_version", "branch", "local_antnode_path", "repo_owner"])]
+        channel: Option<ReleaseChannel>,
         /// The number of antnode services to run on each Peer Cache VM.
         ///
         /// If the argument is not used, the value will be determined by the 'environment-type'
         /// argument.
         #[clap(long)]
         peer_cache_node_count: Option<u16>,
+        /// How the Peer Cache nodes make themselves reachable.
+        ///
+        /// Valid values are 'direct', 'relay-client' or 'upnp'. See --node-reachability for a
+        /// description of each mode.
+        ///
+        /// The default is 'direct'.
+        #[clap(long, default_value = "direct", value_parser = ReachabilityMode::parse_from_str, verbatim_doc_comment)]
+        peer_cache_node_reachability: ReachabilityMode,
         /// The number of Peer Cache node VMs to create.
         ///
         /// Each VM will run many antnode services.
@@ -335,6 +670,49 @@ enum Commands {
         /// This option only applies if the --branch and --repo-owner arguments are used.
         #[clap(long, value_parser = parse_chunk_size)]
         chunk_size: Option<u64>,
+        /// Always build the binaries, rather than reusing the archives from a previous build.
+        ///
+        /// By default, the commit at the head of the branch is looked up, and if a previous
+        /// deployment already built it with the same features and network keys, its archives are
+        /// used and the build is skipped.
+        ///
+        /// This option only applies if the --branch and --repo-owner arguments are used.
+        #[clap(long, verbatim_doc_comment)]
+        disable_build_cache: bool,
+        /// Use these DNS resolvers on every VM, rather than the ones supplied by the provider.
+        ///
+        /// Multiple resolvers are separated by commas, e.g., '1.1.1.1,8.8.8.8'.
+        #[clap(
+            name = "dns-resolver",
+            long,
+            use_value_delimiter = true,
+            verbatim_doc_comment
+        )]
+        dns_resolvers: Option<Vec<IpAddr>>,
+        /// The DigitalOcean managed domain under which DNS records are created for the network.
+        ///
+        /// The genesis node is given the name genesis.<name>.<domain>, and a wildcard record,
+        /// *.<name>.<domain>, resolves to the Peer Cache nodes. The records follow the VMs if they
+        /// are replaced, and they are removed when the environment is destroyed.
+        ///
+        /// This is only supported on DigitalOcean.
+        #[clap(long, verbatim_doc_comment)]
+        dns_domain: Option<String>,
+        /// The number of Peer Cache VMs to use as door nodes.
+        ///
+        /// Door nodes are given stable DNS names under the domain supplied by
+        /// --door-node-dns-domain, so they can be used as a well-known entry point by mobile
+        /// clients. Their nodes listen on fixed ports from 12000, they are excluded from churn and
+        /// they are published in a separate contacts file.
+        ///
+        /// The machines must be hardened, using --harden.
+        #[clap(long, default_value_t = 0)]
+        door_node_count: u16,
+        /// The DigitalOcean managed domain under which the door node DNS records are created.
+        ///
+        /// This is required if --door-node-count is used.
+        #[clap(long)]
+        door_node_dns_domain: Option<String>,
         /// If set to a non-zero value, the uploaders will also be accompanied by the specified
         /// number of downloaders.
         ///
@@ -342,6 +720,17 @@ enum Commands {
         /// 5 uploader VMs, there will be 10 downloaders across the 5 VMs.
         #[clap(long, default_value_t = 0)]
         downloaders_count: u16,
+        /// Set to preview the deployment without making any changes.
+        ///
+        /// Terraform will run a plan rather than an apply, and Ansible will run the playbooks in
+        /// check mode with diffs. This is most useful for reviewing the changes that would be made
+        /// to an existing environment.
+        ///
+        /// Nothing is read from the VMs over SSH, so a dry run stops after the plan if the
+        /// environment has no VMs yet, and otherwise stops after checking the genesis node,
+        /// because the remaining playbooks need its multiaddr.
+        #[clap(long, default_value_t = false, verbatim_doc_comment)]
+        dry_run: bool,
         /// Provide environment variables for the antnode service.
         ///
         /// This is useful to set the antnode's log levels. Each variable should be comma
@@ -357,6 +746,10 @@ enum Commands {
         /// them. The specification will increase in size from development, to staging, to
         /// production.
         ///
+        /// The VM sizes and counts are read from 'dev.tfvars' or 'staging.tfvars' in the
+        /// Terraform directory for the provider. For production, they are read from
+        /// '<name>.tfvars', which must be added for each production environment.
+        ///
         /// The default is 'development'.
         #[clap(long, default_value_t = EnvironmentType::Development, value_parser = parse_deployment_type, verbatim_doc_comment)]
         environment_type: EnvironmentType,
@@ -381,6 +774,16 @@ enum Commands {
         /// This argument only applies if the EVM network type is 'custom'.
         #[arg(long)]
         evm_rpc_url: Option<String>,
+        /// Create a cloud firewall for the environment.
+        ///
+        /// Inbound traffic is limited to the node connections, SSH and the Peer Cache webservers,
+        /// along with Grafana if --setup-monitoring is used and the node RPC if --public-rpc or
+        /// --rpc-tls is used. Traffic between the VMs in the environment is not restricted.
+        ///
+        /// The rules can be changed later with the 'firewall apply' command. This is only
+        /// supported on DigitalOcean.
+        #[clap(long, verbatim_doc_comment)]
+        firewall: bool,
         /// Override the maximum number of forks Ansible will use to execute tasks on target hosts.
         ///
         /// The default value from ansible.cfg is 50.
@@ -393,6 +796,17 @@ enum Commands {
         /// If one of the new keys is supplied, all must be supplied.
         #[arg(long)]
         foundation_pk: Option<String>,
+        /// Deploy the same binaries as a previous deployment, using its versions file.
+        ///
+        /// Every deployment records the binaries it used in '<name>-versions.json', in the data
+        /// directory. For a build from source, the file pins the commit that was built, so the
+        /// same commit is built again even if the branch has moved on. The architecture and chunk
+        /// size are also taken from the file.
+        ///
+        /// This argument is mutually exclusive with the arguments for selecting the binaries.
+        #[clap(long, verbatim_doc_comment,
+            conflicts_with_all = ["ant_version", "antctl_version", "antnode_features", "antnode_version", "branch", "channel", "chunk_size", "local_antnode_path", "repo_owner"])]
+        from_versions_file: Option<PathBuf>,
         /// The secret key for the wallet that will fund all the uploaders.
         ///
         /// This argument only applies when Arbitrum or Sepolia networks are used.
@@ -412,9 +826,54 @@ enum Commands {
         /// If one of the new keys is supplied, all must be supplied.
         #[arg(long)]
         genesis_pk: Option<String>,
+        /// Harden the machines before any provisioning.
+        ///
+        /// The hardening playbook connects as the bootstrap user, creates the SSH user with
+        /// passwordless sudo, disables root login if the SSH user is not root, and installs fail2ban.
+        ///
+        /// The users are configured per provider, with the DO_SSH_BOOTSTRAP_USER and DO_SSH_USER
+        /// variables for Digital Ocean, or AWS_SSH_BOOTSTRAP_USER and AWS_SSH_USER for AWS. Both
+        /// default to the provider's image user, e.g., 'root' for Digital Ocean.
+        #[clap(long, default_value_t = false, verbatim_doc_comment)]
+        harden: bool,
+        /// Add entries to /etc/hosts on every VM, in the form HOSTNAME=IP.
+        ///
+        /// This can be used to point hostnames at a different address, like the artifact proxy,
+        /// or at an unroutable address to simulate a DNS failure. Multiple entries are separated by
+        /// commas.
+        ///
+        /// Example: --host-entry sn-node.s3.eu-west-2.amazonaws.com=10.106.0.2
+        #[clap(name = "host-entry", long, use_value_delimiter = true, value_parser = parse_host_entry, verbatim_doc_comment)]
+        host_entries: Option<Vec<(String, IpAddr)>>,
         /// The interval between starting each node in milliseconds.
         #[clap(long, value_parser = |t: &str| -> Result<Duration> { Ok(t.parse().map(Duration::from_millis)?)}, default_value = "2000")]
         interval: Duration,
+        /// The path of an ant binary built on the local machine, for use by the uploaders.
+        ///
+        /// This only applies when local binaries are used.
+        #[clap(long, requires = "local_antnode_path", verbatim_doc_comment)]
+        local_ant_path: Option<PathBuf>,
+        /// The path of an antctl binary built on the local machine.
+        #[clap(long, requires = "local_antnode_path")]
+        local_antctl_path: Option<PathBuf>,
+        /// The path of an antctld binary built on the local machine.
+        #[clap(long, requires = "local_antnode_path")]
+        local_antctld_path: Option<PathBuf>,
+        /// The path of an antnode binary built on the local machine.
+        ///
+        /// Use this with the other --local-* arguments to deploy binaries built on your own machine,
+        /// which skips the build VM and S3. The binaries are uploaded to the artifact proxy, which
+        /// serves them to the other VMs, so the artifact proxy is always created. The binaries must
+        /// be built for the architecture of the VMs, e.g., the x86_64-unknown-linux-musl target.
+        ///
+        /// The local binaries are mutually exclusive with the version and branch arguments.
+        #[clap(
+            long,
+            requires_all = ["local_antctl_path", "local_antctld_path"],
+            conflicts_with_all = ["ant_version", "antctl_version", "antnode_features", "antnode_version", "branch", "repo_owner"],
+            verbatim_doc_comment
+        )]
+        local_antnode_path: Option<PathBuf>,
         /// Specify the logging format for the nodes.
         ///
         /// Valid values are "default" or "json".
@@ -425,6 +884,12 @@ enum Commands {
         /// The name of the Logstash stack to forward logs to.
         #[clap(long, default_value = "main")]
         logstash_stack_name: String,
+        /// Push the node logs to the Loki instance at this URL, rather than to the Logstash stack.
+        ///
+        /// This is the push API URL, e.g., 'https://loki.example.com/loki/api/v1/push'. Promtail is
+        /// installed on the node VMs to ship the logs.
+        #[clap(long, verbatim_doc_comment)]
+        loki_url: Option<String>,
         /// The maximum of archived log files to keep. After reaching this limit, the older files are deleted.
         #[clap(long, default_value = "5")]
         max_archived_log_files: u16,
@@ -434,12 +899,37 @@ enum Commands {
         /// The name of the environment
         #[arg(short = 'n', long)]
         name: String,
+        /// The number of NAT gateways to spread the private node VMs across.
+        ///
+        /// The private node VMs are assigned to the gateways in turn, so each gateway routes a shard
+        /// of them. Use 'nat-gateway failover' to move a shard to another gateway.
+        ///
+        /// If the argument is not used, a single gateway is created.
+        #[clap(long, value_parser = clap::value_parser!(u16).range(1..), verbatim_doc_comment)]
+        nat_gateway_count: Option<u16>,
+        /// The type of NAT the gateway simulates for the private nodes.
+        ///
+        /// Valid values are "full-cone", "restricted" or "symmetric".
+        ///
+        /// - full-cone: any host can reach a private node on a port it has used.
+        /// - restricted: the source port is preserved, but only the hosts and ports a private node
+        ///   has sent to can reach it.
+        /// - symmetric: a random source port is used for each destination.
+        ///
+        /// The default is symmetric.
+        #[clap(long, default_value = "symmetric", value_parser = NatType::parse_from_str, verbatim_doc_comment)]
+        nat_type: NatType,
         /// Specify the network ID to use for the node services. This is used to partition the network and will not allow
         /// nodes with different network IDs to join.
         ///
         /// By default, the network ID is set to 1, which represents the mainnet.
         #[clap(long, verbatim_doc_comment)]
         network_id: Option<u8>,
+        /// The S3 bucket the network contacts file is published to.
+        ///
+        /// If not used, the file is published to the sn-testnet bucket.
+        #[arg(long)]
+        network_contacts_bucket: Option<String>,
         /// Provide a name for the network contacts file to be uploaded to S3.
         ///
         /// If not used, the contacts file will have the same name as the environment.
@@ -458,6 +948,30 @@ enum Commands {
         /// argument.
         #[clap(long)]
         node_count: Option<u16>,
+        /// How the generic nodes make themselves reachable.
+        ///
+        /// Valid values are:
+        /// * direct: the nodes are publicly reachable and act as relay servers
+        /// * relay-client: the nodes use '--home-network' and are reached through relay servers
+        /// * upnp: the nodes use '--upnp' to try and open their ports
+        ///
+        /// The default is 'direct'. The private nodes are always relay clients.
+        #[clap(long, default_value = "direct", value_parser = ReachabilityMode::parse_from_str, verbatim_doc_comment)]
+        node_reachability: ReachabilityMode,
+        /// Override when systemd restarts the node services.
+        ///
+        /// Valid values are 'always' or 'on-failure'.
+        ///
+        /// The restart policy can be changed later with the 'restart-policy' command.
+        #[clap(long, value_parser = RestartMode::parse_from_str, verbatim_doc_comment)]
+        node_restart: Option<RestartMode>,
+        /// Override the time, in seconds, that systemd waits before restarting a node service.
+        #[clap(long)]
+        node_restart_sec: Option<u32>,
+        /// Override the number of times systemd will start a node service within the start limit
+        /// interval before it stops trying.
+        #[clap(long)]
+        node_start_limit_burst: Option<u32>,
         /// The number of node VMs to create.
         ///
         /// Each VM will run many antnode services.
@@ -476,6 +990,26 @@ enum Commands {
         /// argument.
         #[clap(long)]
         node_volume_size: Option<u16>,
+        /// The format of the payload for the '--notify-webhook-url' argument.
+        ///
+        /// Valid values are "slack", "discord" or "json". The "json" format posts the summary as
+        /// a JSON object, for a webhook that does its own formatting.
+        #[clap(long, default_value = "slack", value_parser = WebhookFormat::parse_from_str, requires = "notify_webhook_url", verbatim_doc_comment)]
+        notify_webhook_format: WebhookFormat,
+        /// Post a summary of the deployment to this webhook URL when it finishes, whether or not it
+        /// succeeded.
+        ///
+        /// The summary has the environment name, the duration, the number of running nodes, the
+        /// genesis multiaddr and any phases that failed.
+        #[clap(long, verbatim_doc_comment)]
+        notify_webhook_url: Option<String>,
+        /// Send the node logs and metrics to an OpenTelemetry collector at this OTLP endpoint,
+        /// rather than to the Logstash stack.
+        ///
+        /// The endpoint uses OTLP over HTTP, e.g., 'https://otel.example.com:4318'. An OpenTelemetry
+        /// collector is installed on the node VMs to read the logs and scrape the node metrics.
+        #[clap(long, conflicts_with = "loki_url", verbatim_doc_comment)]
+        otlp_endpoint: Option<String>,
         /// Optionally set the payment forward public key for a custom antnode binary.
         ///
         /// This argument only applies if the '--branch' and '--repo-owner' arguments are used.
@@ -489,13 +1023,27 @@ enum Commands {
         /// argument.
         #[clap(long, verbatim_doc_comment)]
         private_node_count: Option<u16>,
+        /// Run a UPnP daemon (miniupnpd) on the NAT gateways, and start the private nodes with
+        /// '--upnp', so they open their ports through port mappings on the gateway.
+        ///
+        /// This tests the UPnP path a home node would use with a router that supports it.
+        #[clap(long, verbatim_doc_comment)]
+        private_node_upnp: bool,
         /// The number of private node VMs to create.
         ///
-        /// Each VM will run many antnode services.
+        /// Each VM will run many antnode services. All the private node VMs are routed through the
+        /// NAT gateway.
         ///
         /// If the argument is not used, the value will be determined by the 'environment-type'
         #[clap(long, verbatim_doc_comment)]
         private_node_vm_count: Option<u16>,
+        /// The percentage of the --node-vm-count VMs to place behind the NAT gateway as private
+        /// node VMs, rather than specifying the count with --private-node-vm-count.
+        ///
+        /// The rest are generic node VMs. If the percentage is above zero, at least one VM is made
+        /// private.
+        #[clap(long, requires = "node_vm_count", conflicts_with = "private_node_vm_count", value_parser = clap::value_parser!(u8).range(0..=100), verbatim_doc_comment)]
+        private_node_vm_percent: Option<u8>,
         /// The size of the volumes to attach to each private node VM. This argument will set the size of all the
         /// 7 attached volumes.
         ///
@@ -508,12 +1056,31 @@ enum Commands {
         /// Valid values are "aws" or "digital-ocean".
         #[clap(long, default_value_t = CloudProvider::DigitalOcean, value_parser = parse_provider, verbatim_doc_comment)]
         provider: CloudProvider,
+        /// Provision the node VMs in batches of this many VMs, one batch after another, rather
+        /// than all of them in a single Ansible run.
+        ///
+        /// Every VM in a batch is provisioned at the same time, and progress is reported after
+        /// each batch. This keeps large environments from being held up by the Ansible fork limit.
+        #[clap(long, verbatim_doc_comment)]
+        provision_parallelism: Option<usize>,
+        /// The number of times to re-run the node playbooks against the hosts that failed.
+        ///
+        /// The failed hosts are retried with an exponential backoff, starting at 30 seconds. Any
+        /// hosts that still fail are reported at the end.
+        #[clap(long, default_value_t = 2, verbatim_doc_comment)]
+        provision_retries: u8,
         /// If set to true, the RPC of the node will be accessible remotely.
         ///
         /// By default, the antnode RPC is only accessible via the 'localhost' and is not exposed for
         /// security reasons.
         #[clap(long, default_value_t = false, verbatim_doc_comment)]
         public_rpc: bool,
+        /// Set to resume a previous deployment of the same environment that failed.
+        ///
+        /// The phases that completed in the previous run, e.g., creating the infrastructure or
+        /// provisioning the genesis node, will be skipped.
+        #[clap(long, default_value_t = false, verbatim_doc_comment)]
+        resume: bool,
         /// The owner/org of the Github repository to build from.
         ///
         /// If used, all binaries will be built from this repository. It is typically used for
@@ -528,9 +1095,83 @@ enum Commands {
         /// The rewards address for each of the antnode services.
         #[arg(long, required = true)]
         rewards_address: String,
+        /// Expose the RPC of each node through a TLS reverse proxy on its VM.
+        ///
+        /// Nginx is installed on every public node VM, with a Let's Encrypt certificate for the
+        /// sslip.io name of the VM. The proxy for each node listens on its RPC port plus 2000,
+        /// e.g., 15000 for the first node, and requires a bearer token. The RPC itself remains
+        /// bound to localhost.
+        ///
+        /// The token is generated and saved in the data directory, and the endpoints are listed
+        /// in the inventory report.
+        #[clap(long, conflicts_with = "public_rpc", verbatim_doc_comment)]
+        rpc_tls: bool,
+        /// The email address used to register with Let's Encrypt for the RPC proxy certificates.
+        ///
+        /// Expiry notices are sent to this address. Only applies if --rpc-tls is used.
+        #[clap(long, requires = "rpc_tls", verbatim_doc_comment)]
+        rpc_tls_email: Option<String>,
+        /// Create an artifact proxy VM in the same region as the deployment.
+        ///
+        /// It runs Nginx as a caching proxy for the S3 buckets that store the binary archives, and
+        /// all the VMs download the archives through it. This reduces the egress from S3 and speeds
+        /// up the provisioning of large deployments.
+        #[clap(long, default_value_t = false, verbatim_doc_comment)]
+        setup_artifact_proxy: bool,
+        /// Provide the infra services for a deployment whose VMs have no public egress.
+        ///
+        /// The artifact proxy VM is created, and along with the artifact cache, it runs a DNS
+        /// forwarder and an NTP server. Every other VM is configured to use them. Any resolvers
+        /// supplied with --dns-resolvers are used upstream of the forwarder.
+        #[clap(long, default_value_t = false, verbatim_doc_comment)]
+        setup_infra_services: bool,
+        /// Create a monitoring VM that runs Prometheus and Grafana.
+        ///
+        /// Prometheus is configured to scrape the metrics from every node in the deployment, and
+        /// Grafana is provisioned with a dashboard for those metrics.
+        #[clap(long, default_value_t = false, verbatim_doc_comment)]
+        setup_monitoring: bool,
+        /// The CIDRs SSH is allowed from, when the environment has a firewall.
+        ///
+        /// Multiple CIDRs are separated by commas, e.g., '203.0.113.0/24,198.51.100.7/32'. If
+        /// not used, SSH is open to all addresses and a warning is printed.
+        #[clap(
+            long,
+            requires = "firewall",
+            use_value_delimiter = true,
+            verbatim_doc_comment
+        )]
+        ssh_allowlist: Option<Vec<String>>,
+        /// Collect sysstat samples every second on the node VMs for this number of seconds, once
+        /// they have been provisioned.
+        ///
+        /// Use the 'sysstat retrieve' command to merge the samples into a parquet file.
+        #[clap(long, verbatim_doc_comment)]
+        sysstat_duration: Option<u64>,
+        /// Record an expiry for the environment this long after the deployment, e.g., '48h',
+        /// '30m' or '7d'.
+        ///
+        /// Once it has expired, the environment is destroyed by the next run of the 'reap'
+        /// command. Environments deployed without a TTL are never reaped.
+        #[clap(long, value_parser = parse_ttl, verbatim_doc_comment)]
+        ttl: Option<Duration>,
         /// The desired number of uploaders per VM.
         #[clap(long, default_value_t = 1)]
         uploaders_count: u16,
+        /// The network performance class of the uploader VMs.
+        ///
+        /// Valid values are "standard" or "premium". Premium droplets have a higher outbound
+        /// bandwidth limit, which can be necessary for the uploaders to put enough load on the
+        /// network. When used with 'uploader-vm-size', the size must be a premium droplet size.
+        #[clap(long, default_value = "standard", value_parser = BandwidthClass::parse_from_str, verbatim_doc_comment)]
+        uploader_bandwidth_class: BandwidthClass,
+        /// A comma-separated list of regions to spread the uploader VMs across, e.g., 'nyc1,sgp1'.
+        ///
+        /// This places the clients away from the node fleet, so the upload benchmarks reflect real
+        /// user geography rather than same-datacenter numbers. The uploaders are assigned to the
+        /// regions in turn. If not used, the uploaders are in the same region as the nodes.
+        #[clap(long, value_delimiter = ',', verbatim_doc_comment)]
+        uploader_regions: Option<Vec<String>>,
         /// The number of uploader VMs to create.
         ///
         /// If the argument is not used, the value will be determined by the 'environment-type'
@@ -540,6 +1181,58 @@ enum Commands {
         /// Override the size of the uploader VMs.
         #[clap(long)]
         uploader_vm_size: Option<String>,
+        /// Join the VMs and this machine to a WireGuard overlay once they have been provisioned.
+        ///
+        /// Every VM with a public IP gets an address in 10.200.0.0/16, and this machine is
+        /// 10.200.0.1, so management traffic such as SSH, RPC and metrics can use the overlay
+        /// rather than the public IPs. The keys for the VMs are generated on the VMs.
+        ///
+        /// The 'wg' tool must be installed. The configuration for this machine is written to the
+        /// data directory, and it is brought up with 'wg-quick up <path>'. Later commands connect
+        /// over the overlay when --over-wireguard is used.
+        #[clap(long, verbatim_doc_comment)]
+        wireguard: bool,
+    },
+    /// Manage the downloaders for an environment.
+    ///
+    /// The downloaders continuously fetch the files published by the uploaders and verify their
+    /// content.
+    #[clap(name = "downloaders", subcommand)]
+    Downloaders(DownloadersCommands),
+    /// Reduce the number of node VMs in an environment.
+    ///
+    /// The VMs with the highest numbers are selected for removal. The node services on those VMs
+    /// are stopped before Terraform removes them.
+    Downscale {
+        /// Set to run Ansible with more verbose output.
+        #[arg(long)]
+        ansible_verbose: bool,
+        /// The desired number of node VMs to be running after the scale.
+        ///
+        /// If there are currently 25 VMs running, and you want there to be 10, the value used
+        /// should be 10, rather than 15 as a delta.
+        #[clap(long, verbatim_doc_comment)]
+        desired_node_vm_count: Option<u16>,
+        /// The desired number of Peer Cache VMs to be running after the scale.
+        ///
+        /// If there are currently 20 VMs running, and you want there to be 5, use 5 as the value,
+        /// not 15 as a delta.
+        #[clap(long, verbatim_doc_comment)]
+        desired_peer_cache_node_vm_count: Option<u16>,
+        /// The interval between stopping each node in milliseconds.
+        #[clap(long, value_parser = |t: &str| -> Result<Duration> { Ok(t.parse().map(Duration::from_millis)?)}, default_value = "2000")]
+        interval: Duration,
+        /// The name of the existing network to downscale.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// Set to only run the Terraform plan rather than applying the changes.
+        ///
+        /// The VMs that would be removed are also printed.
+        #[clap(long, default_value_t = false)]
+        plan: bool,
+        /// The cloud provider for the network.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
     },
     ExtendVolumeSize {
         /// Set to run Ansible with more verbose output.
@@ -583,21 +1276,43 @@ enum Commands {
     /// Manage the faucet for an environment
     #[clap(name = "faucet", subcommand)]
     Faucet(FaucetCommands),
+    /// Manage the cloud firewall for an environment.
+    #[clap(name = "firewall", subcommand)]
+    Firewall(FirewallCommands),
     /// Manage the funds in the network
     #[clap(name = "funds", subcommand)]
     Funds(FundsCommand),
+    /// Back up or rebuild the genesis node for an environment.
+    #[clap(name = "genesis", subcommand)]
+    Genesis(GenesisCommands),
+    /// Inspect the infrastructure for an environment.
+    #[clap(name = "infra", subcommand)]
+    Infra(InfraCommands),
     Inventory {
-        /// If set to true, the inventory will be regenerated.
+        /// If set to true, the inventory will be regenerated by querying the cloud provider.
         ///
-        /// This is useful if the testnet was created on another machine.
-        #[clap(long, default_value_t = false)]
+        /// Otherwise the inventory cached in the data directory by the last deploy or inventory
+        /// run is used, if there is one. Regenerating is useful if the testnet was created on
+        /// another machine or has been changed since the inventory was cached.
+        #[clap(long, alias = "force-refresh", default_value_t = false)]
         force_regeneration: bool,
         /// If set to true, all non-local listener addresses will be printed for each peer.
         #[clap(long, default_value_t = false)]
         full: bool,
+        /// If set to true, the inventory will be printed as JSON rather than as a report.
+        ///
+        /// This is intended for consumption by CI jobs or other tools. Only the JSON is written
+        /// to stdout; any progress output, e.g., from regenerating the inventory, goes to stderr.
+        #[clap(long, default_value_t = false, conflicts_with_all = ["full", "peer_cache", "yaml"])]
+        json: bool,
         /// The name of the environment
         #[arg(short = 'n', long)]
         name: String,
+        /// The S3 bucket the network contacts file is published to.
+        ///
+        /// If not used, the file is published to the sn-testnet bucket.
+        #[arg(long)]
+        network_contacts_bucket: Option<String>,
         /// Provide a name for the network contacts file to be uploaded to S3.
         ///
         /// If not used, the contacts file will have the same name as the environment.
@@ -609,15 +1324,41 @@ enum Commands {
         /// The cloud provider that was used.
         #[clap(long, default_value_t = CloudProvider::DigitalOcean, value_parser = parse_provider, verbatim_doc_comment)]
         provider: CloudProvider,
+        /// If set to true, the inventory will be printed as YAML rather than as a report.
+        ///
+        /// As with the JSON output, only the YAML is written to stdout.
+        #[clap(long, default_value_t = false, conflicts_with_all = ["full", "peer_cache"])]
+        yaml: bool,
     },
     #[clap(name = "logs", subcommand)]
     Logs(LogCommands),
     #[clap(name = "logstash", subcommand)]
     Logstash(LogstashCommands),
+    /// Manage the monitoring VM for an environment.
+    #[clap(name = "monitoring", subcommand)]
+    Monitoring(MonitoringCommands),
+    /// Manage the NAT gateways the private nodes are routed through.
+    #[clap(name = "nat-gateway", subcommand)]
+    NatGateway(NatGatewayCommands),
     #[clap(name = "network", subcommand)]
     Network(NetworkCommands),
+    /// Simulate WAN conditions, like latency and packet loss, on the node VMs.
+    #[clap(name = "network-conditions", subcommand)]
+    NetworkConditions(NetworkConditionsCommands),
     /// Send a notification to Slack with testnet inventory details
+    ///
+    /// If SLACK_NOTIFICATION_MODE is set to 'digest', the details are added to the digest for the
+    /// environment, which is posted once SLACK_DIGEST_WINDOW_MINS have passed (30 by default).
+    /// Failures are always posted immediately.
+    #[clap(verbatim_doc_comment)]
     Notify {
+        /// Post the pending digest for the environment now, rather than sending the testnet
+        /// details.
+        ///
+        /// Use this at the end of a pipeline, so the last events are not left waiting for the
+        /// window to pass.
+        #[clap(long, verbatim_doc_comment)]
+        flush: bool,
         /// The name of the environment.
         #[arg(short = 'n', long)]
         name: String,
@@ -633,7 +1374,121 @@ enum Commands {
         #[clap(long, default_value_t = CloudProvider::DigitalOcean, value_parser = parse_provider, verbatim_doc_comment)]
         provider: CloudProvider,
     },
+    /// Destroy the environments whose TTL has passed.
+    ///
+    /// Every Terraform workspace is checked for an expiry, which is recorded when an environment
+    /// is deployed with '--ttl'. This is intended to be run on a schedule, e.g., from a cron or CI
+    /// job.
+    ///
+    /// If SLACK_WEBHOOK_URL is set, a warning is posted for each environment that will expire
+    /// within the grace period, and a message for each environment that is destroyed.
+    #[clap(verbatim_doc_comment)]
+    Reap {
+        /// Only list the expired environments, without destroying them.
+        #[clap(long)]
+        dry_run: bool,
+        /// Warn about the environments that will expire within this many hours.
+        #[clap(long, default_value_t = 12)]
+        grace_period_hours: u64,
+        /// The cloud provider the environments were deployed to.
+        #[clap(long, default_value_t = CloudProvider::DigitalOcean, value_parser = parse_provider, verbatim_doc_comment)]
+        provider: CloudProvider,
+    },
+    /// Replay a recorded trace of client operations against an environment, from an uploader VM.
+    ///
+    /// The trace is a file with a JSON object on each line, giving the time of the operation in
+    /// milliseconds from the start of the trace, and the operation itself, e.g.:
+    ///
+    /// {"offset_ms": 0, "op": "upload", "id": "photo", "size_kb": 2048}
+    /// {"offset_ms": 1500, "op": "download", "upload_id": "photo"}
+    /// {"offset_ms": 3000, "op": "download", "address": "<address>"}
+    ///
+    /// Uploads use random data of the recorded size, and the 'id' lets a later download fetch the
+    /// same file. The genesis node is used as the contact peer.
+    #[clap(verbatim_doc_comment)]
+    Replay {
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+        /// Replay the trace this many times faster than it was recorded, e.g., 2.0 for double
+        /// speed. Operations never run concurrently, so a slow operation delays the rest.
+        #[clap(long, default_value_t = 1.0, verbatim_doc_comment)]
+        speed: f64,
+        /// The path of the trace file.
+        #[clap(long)]
+        trace_path: PathBuf,
+        /// Run the client on this uploader VM, rather than the first one.
+        #[clap(long)]
+        uploader_vm_name: Option<String>,
+    },
+    /// Run a command against a node's RPC service and print the output as JSON.
+    ///
+    /// For example, 'rpc --name DEV-01 --vm node-3 --service antnode5 info'.
+    ///
+    /// The RPC client is run from this machine when the nodes have a public RPC address, otherwise
+    /// it is run on the VM over SSH.
+    #[clap(verbatim_doc_comment)]
+    Rpc {
+        /// The RPC client command and its arguments, e.g., 'info' or 'netinfo'.
+        #[arg(required = true, trailing_var_arg = true)]
+        command: Vec<String>,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+        /// The name of the node service, e.g., 'antnode5'.
+        #[arg(long)]
+        service: String,
+        /// The name of the VM the node runs on. The environment prefix can be omitted, e.g.,
+        /// 'node-3'.
+        #[arg(long)]
+        vm: String,
+    },
+    /// Find which environment a resource belongs to, by searching all the cached inventories.
+    ///
+    /// The query can be an IP address, which must match the public or private address of a VM
+    /// exactly. Otherwise it can be all or part of a VM name, a peer ID, or a wallet or rewards
+    /// address.
+    ///
+    /// Only the inventories cached on this machine are searched. Use the 'inventory' command to
+    /// refresh the inventory for an environment.
+    #[clap(verbatim_doc_comment)]
+    Search {
+        /// The IP address, VM name, peer ID or address to search for.
+        query: String,
+    },
     Setup {},
+    /// Verify a deployment works by uploading a file of random data, downloading it again, and
+    /// checking its hash.
+    ///
+    /// The genesis node is used as the contact peer. By default the client runs on the first
+    /// uploader VM, using the wallet of its first uploader.
+    #[clap(name = "smoke-test")]
+    SmokeTest {
+        /// The size of the random file to upload, in kilobytes.
+        #[clap(long, default_value_t = 1024)]
+        file_size_kb: u64,
+        /// Run the client on this machine, using the `ant` binary at this path, rather than on an
+        /// uploader VM.
+        ///
+        /// The wallet used to pay for the upload is taken from the SECRET_KEY variable.
+        #[clap(long, verbatim_doc_comment)]
+        local_client_path: Option<PathBuf>,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
+    /// Take snapshots of the node data in an environment, or restore them.
+    #[clap(name = "snapshot", subcommand)]
+    Snapshot(SnapshotCommands),
     /// Start all nodes in an environment.
     ///
     /// This can be useful if all nodes did not upgrade successfully.
@@ -662,6 +1517,11 @@ enum Commands {
         /// The cloud provider for the environment.
         #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
         provider: CloudProvider,
+        /// A regular expression matching the names of the VMs to start nodes on, e.g., 'node-[1-5]$'.
+        ///
+        /// This is mutually exclusive with the '--custom-inventory' and '--node-type' arguments.
+        #[arg(long, conflicts_with_all = ["custom-inventory", "node_type"])]
+        vms: Option<String>,
     },
     /// Get the status of all nodes in the environment.
     #[clap(name = "status")]
@@ -669,12 +1529,32 @@ enum Commands {
         /// Maximum number of forks Ansible will use to execute tasks on target hosts.
         #[clap(long, default_value_t = 50)]
         forks: usize,
+        /// Exit with an error if the percentage of nodes that are not running exceeds this value.
+        ///
+        /// A VM whose node registry could not be retrieved is counted as a single failed node.
+        #[clap(long, verbatim_doc_comment)]
+        max_failure_pct: Option<f64>,
         /// The name of the environment.
         #[arg(short = 'n', long)]
         name: String,
         /// The cloud provider for the environment.
         #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
         provider: CloudProvider,
+        /// Publish a status summary and badge for the environment to a public S3 bucket.
+        ///
+        /// The JSON summary has the state (up, degraded or down), the node counts and the antnode
+        /// version. It is written to '<name>-status.json', and the SVG badge is written to
+        /// '<name>-status.svg', so dashboards and READMEs can use them without credentials for
+        /// this tool.
+        #[clap(long, verbatim_doc_comment)]
+        publish_status: bool,
+        /// Record the current node availability in the uptime history for the environment, then
+        /// report the availability for each VM and for the whole fleet over the environment's
+        /// lifetime.
+        ///
+        /// Running this periodically builds up the history that the percentages are based on.
+        #[clap(long)]
+        slo: bool,
     },
     /// Start the Telegraf service on all machines in the environment.
     ///
@@ -733,6 +1613,11 @@ enum Commands {
         /// The cloud provider for the environment.
         #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
         provider: CloudProvider,
+        /// A regular expression matching the names of the VMs to stop nodes on, e.g., 'node-[1-5]$'.
+        ///
+        /// This is mutually exclusive with the '--custom-inventory' and '--node-type' arguments.
+        #[arg(long, conflicts_with_all = ["custom-inventory", "node_type"])]
+        vms: Option<String>,
     },
     /// Stop the Telegraf service on all machines in the environment.
     ///
@@ -760,6 +1645,58 @@ enum Commands {
         #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
         provider: CloudProvider,
     },
+    /// Create an archive of the information needed to diagnose a problem with the deployer, to
+    /// attach to an issue.
+    ///
+    /// The archive contains the tool versions, the .env configuration with the values of anything
+    /// that could be a credential removed, and the error from the last failed run. If an
+    /// environment is named, its recent run logs, environment details and provisioning report are
+    /// also included. Registered secrets are redacted throughout.
+    ///
+    /// Check the contents before attaching the archive to a public issue.
+    #[clap(name = "support-bundle", verbatim_doc_comment)]
+    SupportBundle {
+        /// The name of the environment the problem occurred with.
+        #[arg(short = 'n', long)]
+        name: Option<String>,
+        /// The directory to write the archive to. The current directory is used by default.
+        #[arg(long)]
+        output_dir: Option<PathBuf>,
+    },
+    /// Collect fine-grained sysstat samples on the node VMs, for offline performance analysis.
+    #[clap(name = "sysstat", subcommand)]
+    Sysstat(SysstatCommands),
+    /// Limit the upload and download bandwidth of node VMs, using wondershaper.
+    ///
+    /// The limit replaces any network conditions previously applied to the same VMs.
+    Throttle {
+        /// Remove the bandwidth limit from the selected VMs, rather than applying one.
+        #[clap(long, conflicts_with = "rate")]
+        clear: bool,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+        /// The bandwidth limit, applied to both the upload and download, e.g., '10mbit'.
+        ///
+        /// The units 'kbit', 'mbit' and 'gbit' are supported.
+        #[clap(long, required_unless_present = "clear", verbatim_doc_comment)]
+        rate: Option<String>,
+        /// The VMs to throttle, as a comma-separated list of names without the environment
+        /// prefix, e.g., 'node-[5:10],peer-cache-node-1'. A range is inclusive.
+        ///
+        /// If not used, all the node VMs are selected.
+        #[clap(long, verbatim_doc_comment)]
+        vms: Option<String>,
+    },
+    /// Record and report trends across the summaries of previous deployment runs stored in S3.
+    ///
+    /// A summary is recorded at the end of each deployment, if the RUN_SUMMARY_BUCKET_NAME
+    /// variable is set.
+    #[clap(name = "trends", subcommand)]
+    Trends(TrendsCommands),
     /// Upgrade the node binaries of a testnet environment to the latest version.
     Upgrade {
         /// Set to run Ansible with more verbose output.
@@ -824,7 +1761,10 @@ enum Commands {
     },
     /// Upgrade antctl binaries to a particular version.
     ///
-    /// Simple mechanism that copies over the existing binary.
+    /// Simple mechanism that copies over the existing binary. The node services are not touched,
+    /// so fixes to the node manager can be rolled out without redeploying the nodes.
+    ///
+    /// The binary for the architecture the environment was deployed with is used.
     #[clap(name = "upgrade-antctl")]
     UpgradeAntctl {
         /// Provide a list of VM names to use as a custom inventory.
@@ -994,12 +1934,31 @@ enum Commands {
         /// The cloud provider for the network.
         #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
         provider: CloudProvider,
+        /// Provision the node VMs in batches of this many VMs, one batch after another, rather
+        /// than all of them in a single Ansible run.
+        ///
+        /// Every VM in a batch is provisioned at the same time, and progress is reported after
+        /// each batch. This keeps large environments from being held up by the Ansible fork limit.
+        #[clap(long, verbatim_doc_comment)]
+        provision_parallelism: Option<usize>,
+        /// The number of times to re-run the node playbooks against the hosts that failed.
+        ///
+        /// The failed hosts are retried with an exponential backoff, starting at 30 seconds. Any
+        /// hosts that still fail are reported at the end.
+        #[clap(long, default_value_t = 2, verbatim_doc_comment)]
+        provision_retries: u8,
         /// If set to true, for new VMs the RPC of the node will be accessible remotely.
         ///
         /// By default, the antnode RPC is only accessible via the 'localhost' and is not exposed for
         /// security reasons.
         #[clap(long, default_value_t = false, verbatim_doc_comment)]
         public_rpc: bool,
+        /// The email address used to register with Let's Encrypt for the RPC proxy certificates.
+        ///
+        /// Only applies if the environment was deployed with --rpc-tls, in which case the RPC proxy
+        /// is also provisioned on the new VMs, using the token saved when it was deployed.
+        #[clap(long, verbatim_doc_comment)]
+        rpc_tls_email: Option<String>,
         /// Supply a version number for the safe binary to be used for new uploader VMs.
         ///
         /// There should be no 'v' prefix.
@@ -1022,6 +1981,44 @@ enum Commands {
         #[arg(long, verbatim_doc_comment)]
         antnode_version: Option<String>,
     },
+    /// Change the environment variables of the antnode services on a running environment.
+    ///
+    /// The node manager rewrites each service definition with the new variables, which replace
+    /// those provided at deploy time, then restarts the node. The nodes keep the version they are
+    /// running.
+    #[clap(name = "update-env")]
+    UpdateEnv {
+        /// Provide a list of VM names to use as a custom inventory.
+        ///
+        /// This will update the nodes on a particular subset of VMs.
+        #[clap(name = "custom-inventory", long, use_value_delimiter = true)]
+        custom_inventory: Option<Vec<String>>,
+        /// The environment variables for the antnode services.
+        ///
+        /// Each variable should be comma separated without any space.
+        ///
+        /// Example: --env SN_LOG=all,RUST_LOG=libp2p=debug
+        #[clap(name = "env", long, required = true, use_value_delimiter = true, value_parser = parse_environment_variables, verbatim_doc_comment)]
+        env_variables: Vec<(String, String)>,
+        /// Maximum number of forks Ansible will use to execute tasks on target hosts.
+        #[clap(long, default_value_t = 50)]
+        forks: usize,
+        /// The interval between each node restart in milliseconds.
+        #[clap(long, value_parser = |t: &str| -> Result<Duration> { Ok(t.parse().map(Duration::from_millis)?)}, default_value = "2000")]
+        interval: Duration,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// Specify the type of node VM to update the antnode services on. If not provided, the antnode services on
+        /// all the node VMs will be updated. This is mutually exclusive with the '--custom-inventory' argument.
+        ///
+        /// Valid values are "peer-cache", "genesis", "generic" and "private".
+        #[arg(long, conflicts_with = "custom-inventory")]
+        node_type: Option<NodeType>,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
     /// Update the peer multiaddr in the node registry.
     ///
     /// This will then cause the service definitions to be updated when an upgrade is performed.
@@ -1047,7 +2044,91 @@ enum Commands {
         #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
         provider: CloudProvider,
     },
-    /// Reset nodes to a specified count.
+    /// Restart the nodes in an environment.
+    ///
+    /// The nodes on each VM are stopped then started, one at a time, with the interval between each
+    /// stop and start. To avoid restarting the whole environment at once, the VMs can be restarted
+    /// in batches, with each batch finishing before the next begins.
+    #[clap(name = "restart")]
+    Restart {
+        /// Provide a list of VM names to use as a custom inventory.
+        ///
+        /// This will restart nodes on a particular subset of VMs.
+        #[clap(name = "custom-inventory", long, use_value_delimiter = true)]
+        custom_inventory: Option<Vec<String>>,
+        /// Maximum number of forks Ansible will use to execute tasks on target hosts.
+        #[clap(long, default_value_t = 50)]
+        forks: usize,
+        /// The interval between each node stop and start in milliseconds.
+        #[clap(long, value_parser = |t: &str| -> Result<Duration> { Ok(t.parse().map(Duration::from_millis)?)}, default_value = "2000")]
+        interval: Duration,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// Specify the type of node VM to restart the antnode services on. If not provided, the antnode services on
+        /// all the node VMs will be restarted. This is mutually exclusive with the '--custom-inventory' argument.
+        ///
+        /// Valid values are "peer-cache", "genesis", "generic" and "private".
+        #[arg(long, conflicts_with = "custom-inventory")]
+        node_type: Option<NodeType>,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+        /// The number of VMs to restart nodes on at the same time.
+        ///
+        /// If not provided, all the VMs are restarted at the same time.
+        #[clap(long)]
+        vm_batch_size: Option<usize>,
+        /// A regular expression matching the names of the VMs to restart nodes on, e.g., 'node-[1-5]$'.
+        ///
+        /// This is mutually exclusive with the '--custom-inventory' and '--node-type' arguments.
+        #[arg(long, conflicts_with_all = ["custom-inventory", "node_type"])]
+        vms: Option<String>,
+    },
+    /// Change the systemd restart policy of the node services.
+    ///
+    /// A drop-in is added to each node service to override the values from its unit file. The
+    /// nodes do not need to be restarted for the new policy to apply.
+    ///
+    /// Any nodes added by an upscale will use the unit file values, so the command should be run
+    /// again after an upscale.
+    #[clap(name = "restart-policy")]
+    RestartPolicy {
+        /// Provide a list of VM names to use as a custom inventory.
+        ///
+        /// This will change the restart policy on a particular subset of VMs.
+        #[clap(name = "custom-inventory", long, use_value_delimiter = true)]
+        custom_inventory: Option<Vec<String>>,
+        /// Maximum number of forks Ansible will use to execute tasks on target hosts.
+        #[clap(long, default_value_t = 50)]
+        forks: usize,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// Specify the type of node VM to change the restart policy on. If not provided, the
+        /// policy will be changed on all the node VMs. This is mutually exclusive with the
+        /// '--custom-inventory' argument.
+        ///
+        /// Valid values are "peer-cache", "genesis", "generic" and "private".
+        #[arg(long, conflicts_with = "custom-inventory")]
+        node_type: Option<NodeType>,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+        /// When systemd restarts the node services.
+        ///
+        /// Valid values are 'always' or 'on-failure'.
+        #[clap(long, value_parser = RestartMode::parse_from_str, verbatim_doc_comment)]
+        restart: Option<RestartMode>,
+        /// The time, in seconds, that systemd waits before restarting a node service.
+        #[clap(long)]
+        restart_sec: Option<u32>,
+        /// The number of times systemd will start a node service within the start limit interval
+        /// before it stops trying.
+        #[clap(long)]
+        start_limit_burst: Option<u32>,
+    },
+    /// Reset nodes to a specified count.
     ///
     /// This will stop all nodes, clear their data, and start the specified number of nodes.
     #[clap(name = "reset-to-n-nodes")]
@@ -1092,6 +2173,53 @@ enum Commands {
         #[arg(long)]
         version: Option<String>,
     },
+    /// Manage the WireGuard overlay for an environment.
+    #[clap(name = "wireguard", subcommand)]
+    WireGuard(WireGuardCommands),
+}
+
+#[derive(Subcommand, Debug)]
+enum ArtifactsCommands {
+    /// Delete branch build artifacts that are no longer needed.
+    ///
+    /// An artifact is deleted if it is older than the retention window, if its branch has been
+    /// deleted from GitHub, or if the environment it was built for no longer exists.
+    ///
+    /// Versioned release artifacts, the artifacts of environments that still exist, whatever their
+    /// age, and anything outside the build bucket are never deleted.
+    Gc {
+        /// List the artifacts that would be deleted, without deleting them.
+        #[clap(long)]
+        dry_run: bool,
+        /// The cloud provider the environments were deployed to.
+        #[clap(long, default_value_t = CloudProvider::DigitalOcean, value_parser = parse_provider, verbatim_doc_comment)]
+        provider: CloudProvider,
+        /// The number of days to keep an artifact for.
+        #[clap(long, default_value_t = 30)]
+        retention_days: u64,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum InfraCommands {
+    /// Detect whether the infrastructure has drifted from the Terraform state.
+    ///
+    /// A refresh-only plan is run in the environment's workspace, which reports the resources
+    /// that were changed outside of Terraform, e.g., a VM that was deleted manually.
+    ///
+    /// The command fails if there is drift, so it can be used in CI jobs.
+    #[clap(verbatim_doc_comment)]
+    Plan {
+        /// Print the report as JSON, for consumption by CI jobs or other tools.
+        #[clap(long)]
+        json: bool,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -1129,6 +2257,16 @@ enum LogCommands {
         /// The name of the environment
         #[arg(short = 'n', long)]
         name: String,
+        /// Only retrieve the log segments that overlap the window starting at this time.
+        ///
+        /// The time should be in RFC 3339 format, e.g., 2024-11-20T14:30:00Z.
+        #[clap(long, value_parser = parse_timestamp, verbatim_doc_comment)]
+        since: Option<DateTime<Utc>>,
+        /// Only retrieve the log segments that overlap the window ending at this time.
+        ///
+        /// The time should be in RFC 3339 format, e.g., 2024-11-20T15:00:00Z.
+        #[clap(long, value_parser = parse_timestamp, verbatim_doc_comment)]
+        until: Option<DateTime<Utc>>,
     },
     /// Reassemble retrieved logs from their parts.
     ///
@@ -1213,12 +2351,250 @@ enum LogstashCommands {
     },
 }
 
+#[derive(Subcommand, Debug)]
+enum MonitoringCommands {
+    /// Make Grafana dashboards viewable by anyone, without credentials for the monitoring VM.
+    ///
+    /// Each dashboard is given a separate, read-only URL, which can be shared with the community
+    /// during a public test event. The URLs are printed when the command completes.
+    ///
+    /// The monitoring VM must have been provisioned from this machine, with a generated admin
+    /// password. The command refuses to publish if Grafana could still have the default password.
+    Publish {
+        /// The UID of a dashboard to publish.
+        ///
+        /// This argument can be used more than once. If not used, the node metrics dashboard is
+        /// published.
+        #[clap(long = "dashboard", verbatim_doc_comment)]
+        dashboards: Vec<String>,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
+    /// Remove public access to Grafana dashboards.
+    ///
+    /// The URLs given out when the dashboards were published stop working.
+    Unpublish {
+        /// The UID of a dashboard to unpublish.
+        ///
+        /// This argument can be used more than once. If not used, the node metrics dashboard is
+        /// unpublished.
+        #[clap(long = "dashboard", verbatim_doc_comment)]
+        dashboards: Vec<String>,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum NatGatewayCommands {
+    /// Route a shard of private node VMs through another NAT gateway.
+    ///
+    /// This simulates the upstream router of a home node changing. The private node VMs are
+    /// assigned to the gateways in turn, and the shards are printed before the failover.
+    ///
+    /// Reprovisioning the private nodes restores the original assignment.
+    Failover {
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+        /// The number of the shard to move, starting from 1.
+        #[clap(long)]
+        shard: usize,
+        /// The name of the gateway to move the shard to.
+        ///
+        /// If not used, the first other gateway that is reachable and forwarding packets is used.
+        #[clap(long, verbatim_doc_comment)]
+        to: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum GenesisCommands {
+    /// Back up the data directory of the genesis node to S3.
+    ///
+    /// The record store is not included. The backup is what lets a rebuilt genesis node keep its
+    /// peer ID. Each backup replaces the previous one.
+    Backup {
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
+    /// Recreate the genesis VM and provision it again.
+    ///
+    /// This is for when the genesis VM has died. The droplet is replaced through Terraform, the
+    /// genesis node is provisioned on the new VM, and its data directory is restored from the
+    /// last backup. The cached inventory is then regenerated with the new genesis multiaddr.
+    ///
+    /// The other nodes still have the old genesis multiaddr, which can be changed with the
+    /// 'update-peer' command.
+    Rebuild {
+        /// The interval between starting each node in milliseconds.
+        #[clap(long, value_parser = |t: &str| -> Result<Duration> { Ok(t.parse().map(Duration::from_millis)?)}, default_value = "2000")]
+        interval: Duration,
+        /// The maximum of archived log files to keep. After reaching this limit, the older files are deleted.
+        #[clap(long, default_value = "5")]
+        max_archived_log_files: u16,
+        /// The maximum number of log files to keep. After reaching this limit, the older files are archived.
+        #[clap(long, default_value = "10")]
+        max_log_files: u16,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// Provision a genesis node with a new data directory, rather than restoring the backup.
+        ///
+        /// The node will have a new peer ID.
+        #[clap(long, default_value_t = false, verbatim_doc_comment)]
+        no_restore: bool,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+        /// If set to true, the RPC of the genesis node will be accessible remotely.
+        #[clap(long, default_value_t = false, verbatim_doc_comment)]
+        public_rpc: bool,
+    },
+}
+
 // Administer or perform activities on a deployed network.
 #[derive(Subcommand, Debug)]
 enum NetworkCommands {
     /// Restart nodes in the testnet to simulate the churn of nodes.
     #[clap(name = "churn", subcommand)]
     ChurnCommands(ChurnCommands),
+    /// Remove a partition that was applied with the 'partition' command.
+    Heal {
+        /// Maximum number of forks Ansible will use to execute tasks on target hosts.
+        #[clap(long, default_value_t = 50)]
+        forks: usize,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
+    /// Split the node VMs into groups that cannot reach each other, to test split-brain behaviour.
+    ///
+    /// The VMs are assigned to the groups in turn, and iptables rules are applied on each VM to
+    /// drop all traffic to and from the VMs in the other groups. The private nodes are not
+    /// included. Any previous partition is replaced. Use the 'heal' command to remove it.
+    #[clap(verbatim_doc_comment)]
+    Partition {
+        /// Maximum number of forks Ansible will use to execute tasks on target hosts.
+        #[clap(long, default_value_t = 50)]
+        forks: usize,
+        /// The number of groups to split the node VMs into.
+        #[clap(long, default_value_t = 2)]
+        groups: usize,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
+    /// Apply allow or deny lists of peers to the node VMs, then restart the nodes.
+    ///
+    /// The filter is applied with iptables, so the nodes on the selected VMs can only communicate
+    /// with the allowed peers, or cannot communicate with the denied peers. This allows experiments
+    /// with partially connected or adversarially filtered peers.
+    ///
+    /// Peers are specified by VM name or IP address. Use --clear to remove the filter.
+    #[clap(name = "peer-filter", verbatim_doc_comment)]
+    PeerFilter {
+        /// The peers the nodes are allowed to communicate with. Any other node traffic is dropped.
+        ///
+        /// This is a comma-separated list of VM names or IP addresses.
+        #[clap(long, use_value_delimiter = true, verbatim_doc_comment)]
+        allow: Option<Vec<String>>,
+        /// Remove any filter that was previously applied.
+        #[clap(long, conflicts_with_all = ["allow", "deny"])]
+        clear: bool,
+        /// Provide a list of VM names to use as a custom inventory.
+        ///
+        /// This will apply the filter to a particular subset of VMs.
+        #[clap(name = "custom-inventory", long, use_value_delimiter = true)]
+        custom_inventory: Option<Vec<String>>,
+        /// The peers the nodes are not allowed to communicate with.
+        ///
+        /// This is a comma-separated list of VM names or IP addresses.
+        #[clap(long, use_value_delimiter = true, verbatim_doc_comment)]
+        deny: Option<Vec<String>>,
+        /// Maximum number of forks Ansible will use to execute tasks on target hosts.
+        #[clap(long, default_value_t = 50)]
+        forks: usize,
+        /// The interval between stopping or starting each node in milliseconds.
+        #[clap(long, value_parser = |t: &str| -> Result<Duration> { Ok(t.parse().map(Duration::from_millis)?)}, default_value = "2000")]
+        interval: Duration,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// Specify the type of node VM to apply the filter to. If not provided, the filter is
+        /// applied to all the node VMs. This is mutually exclusive with the '--custom-inventory'
+        /// argument.
+        ///
+        /// Valid values are "peer-cache", "genesis", "generic" and "private".
+        #[arg(long, conflicts_with = "custom-inventory")]
+        node_type: Option<NodeType>,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+        /// Apply the filter without restarting the nodes.
+        ///
+        /// Connections that were established before the filter was applied will remain open.
+        #[clap(long, verbatim_doc_comment)]
+        skip_restart: bool,
+    },
+    /// Restart every node in the network, keeping their keys and data.
+    ///
+    /// All the nodes are stopped. They are then started a node type at a time: the genesis node
+    /// first, then the peer cache nodes, then the generic and private nodes. Finally, the node
+    /// status is polled until the network has reconverged.
+    ///
+    /// The command fails if the network does not reconverge before the timeout.
+    #[clap(verbatim_doc_comment)]
+    Restart {
+        /// Remove the bootstrap caches while the nodes are stopped.
+        ///
+        /// The nodes will then rediscover their peers through the genesis and peer cache nodes.
+        #[clap(long, verbatim_doc_comment)]
+        clear_caches: bool,
+        /// Maximum number of forks Ansible will use to execute tasks on target hosts.
+        #[clap(long, default_value_t = 50)]
+        forks: usize,
+        /// The interval between stopping or starting each node in milliseconds.
+        #[clap(long, value_parser = |t: &str| -> Result<Duration> { Ok(t.parse().map(Duration::from_millis)?)}, default_value = "2000")]
+        interval: Duration,
+        /// The network has reconverged when the percentage of nodes that are not running is at or
+        /// below this value.
+        #[clap(long, default_value_t = 5.0)]
+        max_failure_pct: f64,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+        /// The maximum time to wait for the network to reconverge, in seconds.
+        #[clap(long, default_value_t = 600)]
+        reconvergence_timeout: u64,
+        /// The time to wait after starting each node type before starting the next, in seconds.
+        #[clap(long, default_value_t = 60)]
+        stage_delay: u64,
+    },
     /// Modifies the log levels for all the antnode services through RPC requests.
     UpdateNodeLogLevel {
         /// The number of nodes to update concurrently.
@@ -1236,6 +2612,49 @@ enum NetworkCommands {
     },
 }
 
+#[derive(Subcommand, Debug)]
+enum NetworkConditionsCommands {
+    /// Apply latency and packet loss to the outgoing traffic of the selected node VMs.
+    ///
+    /// Any conditions previously applied to those VMs are replaced.
+    Apply {
+        /// A regular expression to select the node VMs by name, e.g., 'node-[1-5]$'.
+        ///
+        /// If not used, all the node VMs are selected.
+        #[clap(long, verbatim_doc_comment)]
+        hosts: Option<String>,
+        /// The variation in the latency, e.g., '20ms'.
+        #[clap(long, requires = "latency")]
+        jitter: Option<String>,
+        /// The latency to add to outgoing packets, e.g., '200ms'.
+        #[clap(long)]
+        latency: Option<String>,
+        /// The percentage of outgoing packets to drop, e.g., '2%'.
+        #[clap(long)]
+        loss: Option<String>,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
+    /// Remove the simulated network conditions from the selected node VMs.
+    Clear {
+        /// A regular expression to select the node VMs by name, e.g., 'node-[1-5]$'.
+        ///
+        /// If not used, all the node VMs are selected.
+        #[clap(long, verbatim_doc_comment)]
+        hosts: Option<String>,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 enum ChurnCommands {
     /// Churn nodes at fixed intervals.
@@ -1284,37 +2703,422 @@ enum ChurnCommands {
 }
 
 #[derive(Subcommand, Debug)]
-enum UploadersCommands {
-    /// Start all uploaders for an environment
-    Start {
-        /// The name of the environment
-        #[arg(long)]
-        name: String,
-        /// The cloud provider that was used.
-        #[clap(long, default_value_t = CloudProvider::DigitalOcean, value_parser = parse_provider, verbatim_doc_comment)]
-        provider: CloudProvider,
-    },
-    /// Stop all uploaders for an environment.
-    Stop {
-        /// The name of the environment
-        #[arg(long)]
-        name: String,
-        /// The cloud provider that was used.
-        #[clap(long, default_value_t = CloudProvider::DigitalOcean, value_parser = parse_provider, verbatim_doc_comment)]
-        provider: CloudProvider,
-    },
-    /// Upgrade the uploaders for a given environment.
-    Upgrade {
+enum ChaosCommands {
+    /// Fill the data partition of the selected node VMs, then release the space.
+    ///
+    /// The space is claimed with fallocate, up to the given percentage of the partition, and
+    /// released by a timer on each VM once the duration has elapsed. The nodes keep running, so
+    /// their handling of running out of storage can be observed.
+    DiskFull {
+        /// The number of seconds after which the space is released.
+        #[clap(long, value_parser = |t: &str| -> Result<Duration> { Ok(t.parse().map(Duration::from_secs)?)}, default_value = "600")]
+        duration: Duration,
+        /// The percentage of the data partition to fill.
+        #[clap(long, default_value_t = 98)]
+        fill_pct: u8,
         /// The name of the environment.
         #[arg(short = 'n', long)]
         name: String,
-
         /// The cloud provider for the environment.
         #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
         provider: CloudProvider,
-
-        /// Optionally supply a version for the safe client binary to upgrade to.
-        ///
+        /// The VMs to fill, as a comma-separated list of names without the environment prefix,
+        /// e.g., 'node-[5:10],peer-cache-node-1'. A range is inclusive.
+        #[clap(long, verbatim_doc_comment)]
+        vms: String,
+    },
+    /// Kill randomly selected nodes with SIGKILL.
+    ///
+    /// The node services will be restarted by systemd.
+    Kill {
+        /// The number of nodes to apply the fault to.
+        #[clap(long, default_value_t = 1)]
+        count: usize,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The type of node VM to select the nodes from.
+        ///
+        /// Valid values are "peer-cache", "genesis", "generic" and "private".
+        #[clap(long, default_value = "generic", verbatim_doc_comment)]
+        node_type: NodeType,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
+    /// Cause randomly selected nodes to be killed by the OOM killer.
+    ///
+    /// The memory of each node service is briefly limited, so the kernel kills the node process.
+    Oom {
+        /// The number of nodes to apply the fault to.
+        #[clap(long, default_value_t = 1)]
+        count: usize,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The type of node VM to select the nodes from.
+        ///
+        /// Valid values are "peer-cache", "genesis", "generic" and "private".
+        #[clap(long, default_value = "generic", verbatim_doc_comment)]
+        node_type: NodeType,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
+    /// Freeze randomly selected nodes with SIGSTOP.
+    ///
+    /// The node processes remain frozen, but their services still appear to be running. They can
+    /// be resumed by sending SIGCONT or restarting the service.
+    Sigstop {
+        /// The number of nodes to apply the fault to.
+        #[clap(long, default_value_t = 1)]
+        count: usize,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The type of node VM to select the nodes from.
+        ///
+        /// Valid values are "peer-cache", "genesis", "generic" and "private".
+        #[clap(long, default_value = "generic", verbatim_doc_comment)]
+        node_type: NodeType,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
+    /// Run a named chaos experiment, which is limited in how much of the network it affects and
+    /// is reverted automatically.
+    ///
+    /// The templates are:
+    ///   kill-close-group: kill the 5 nodes closest to a random address
+    ///   partition-region: cut a tenth of the node VMs off from every other machine
+    ///   slow-disk-10pct: limit the disk bandwidth of the nodes on a tenth of the node VMs
+    ///
+    /// The genesis node is never affected. If the template would affect more than the limit, it
+    /// is not applied. The partition and disk templates are reverted by a timer on each affected
+    /// VM; the killed nodes are restarted by systemd.
+    #[clap(verbatim_doc_comment)]
+    Template {
+        /// The number of seconds after which the template is reverted.
+        #[clap(long, value_parser = |t: &str| -> Result<Duration> { Ok(t.parse().map(Duration::from_secs)?)}, default_value = "600")]
+        duration: Duration,
+        /// The maximum percentage of the nodes, excluding the genesis node, the template is allowed
+        /// to affect.
+        #[clap(long, default_value_t = 20)]
+        max_fleet_pct: u8,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+        /// The template to run.
+        ///
+        /// Valid values are "kill-close-group", "partition-region" and "slow-disk-10pct".
+        #[clap(long, verbatim_doc_comment)]
+        template: ChaosTemplate,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum DownloadersCommands {
+    /// Deploy a fleet of downloader VMs to an existing network.
+    ///
+    /// Each downloader reads the upload manifests the uploaders publish to S3, then downloads
+    /// random files from them and compares their content with the checksums recorded at upload
+    /// time. The result of each download is written to 'downloader_metrics.csv' in the home
+    /// directory of each downloader user.
+    ///
+    /// The AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY variables are required, because they are
+    /// used by the downloaders to read the manifests.
+    Deploy {
+        /// Supply a version number for the autonomi binary used by the downloaders.
+        ///
+        /// There should be no 'v' prefix. If not provided, the latest version will be used.
+        #[arg(long, verbatim_doc_comment)]
+        autonomi_version: Option<String>,
+        /// The number of downloader services to run on each VM.
+        #[clap(long, default_value_t = 1)]
+        downloaders_per_vm: u16,
+        /// The name of the environment
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+        /// The number of downloader VMs to create.
+        #[clap(long)]
+        vm_count: u16,
+    },
+    /// Start all the downloaders for an environment.
+    Start {
+        /// The name of the environment
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
+    /// Stop all the downloaders for an environment.
+    Stop {
+        /// The name of the environment
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum TrendsCommands {
+    /// Record metrics from later stages of a pipeline against the most recent run of an
+    /// environment.
+    ///
+    /// Only the values that are supplied are changed.
+    Record {
+        /// The number of distinct error signatures found in the node logs.
+        #[clap(long)]
+        error_signature_count: Option<u64>,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The upload throughput measured against the environment, in MB/s.
+        #[clap(long)]
+        upload_throughput: Option<f64>,
+    },
+    /// Report the deploy time, failed commands, error signatures and upload throughput for each
+    /// run, and highlight regressions between consecutive runs.
+    Report {
+        /// Only include the most recent runs.
+        #[clap(long)]
+        limit: Option<usize>,
+        /// Only include runs for the given release, e.g., '0.3.1' or 'maidsafe/main'.
+        #[clap(long)]
+        release: Option<String>,
+        /// The percentage by which the deploy time or upload throughput must worsen before it is
+        /// reported as a regression.
+        #[clap(long, default_value_t = 20)]
+        threshold: u8,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SnapshotCommands {
+    /// Take a snapshot of the data directory of every node VM in an environment.
+    ///
+    /// The nodes on each VM are stopped while the data directory is streamed to S3, then started
+    /// again. The ID of the snapshot is printed at the end.
+    ///
+    /// The AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY variables are required, because they are
+    /// used by the VMs to upload their archives.
+    Create {
+        /// The interval between stopping or starting each node in milliseconds.
+        #[clap(long, value_parser = |t: &str| -> Result<Duration> { Ok(t.parse().map(Duration::from_millis)?)}, default_value = "2000")]
+        interval: Duration,
+        /// Leave the nodes stopped after the snapshot is taken.
+        #[clap(long, default_value_t = false)]
+        leave_stopped: bool,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
+    /// List the snapshots that have been taken.
+    List {
+        /// Only list the snapshots taken from this environment.
+        #[arg(short = 'n', long)]
+        name: Option<String>,
+    },
+    /// Restore a snapshot to an environment.
+    ///
+    /// The environment can be a different one from where the snapshot was taken. The data for
+    /// each VM is found by its name, e.g., 'node-3', so the environment should have the same
+    /// number of VMs and nodes. The current data on each VM is replaced.
+    ///
+    /// The AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY variables are required.
+    Restore {
+        /// The ID of the snapshot.
+        #[clap(long)]
+        id: String,
+        /// The interval between stopping or starting each node in milliseconds.
+        #[clap(long, value_parser = |t: &str| -> Result<Duration> { Ok(t.parse().map(Duration::from_millis)?)}, default_value = "2000")]
+        interval: Duration,
+        /// The name of the environment to restore the snapshot to.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SysstatCommands {
+    /// Retrieve the samples from all the node VMs and merge them into a single parquet file.
+    ///
+    /// The file has one row for each value, with the VM name, timestamp, metric, device and value
+    /// columns. The CPU, memory, block device and network interface reports are included.
+    Retrieve {
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The path of the parquet file to write.
+        ///
+        /// If not used, the file is written to '<name>-sysstat.parquet' in the current directory.
+        #[clap(long, verbatim_doc_comment)]
+        output: Option<PathBuf>,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
+    /// Start collecting sysstat samples every second on all the node VMs.
+    ///
+    /// The collection runs for a bounded period and then stops by itself. Any samples from a
+    /// previous collection are removed.
+    Start {
+        /// The number of seconds to collect samples for.
+        #[clap(long, default_value_t = 3600)]
+        duration: u64,
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
+    /// Stop the collection before its duration has elapsed.
+    ///
+    /// The samples collected so far are kept, so they can still be retrieved.
+    Stop {
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum UploadersCommands {
+    /// Reduce the number of active uploaders while the network is struggling, and resume them
+    /// once it recovers.
+    ///
+    /// The node status and the disk usage on the node VMs are checked at an interval. When either
+    /// crosses its threshold, the uploaders on each VM above the reduced count pause between
+    /// uploads, rather than being stopped. This runs until it is interrupted, or for the given
+    /// duration.
+    ///
+    /// If it is interrupted while the uploaders are limited, use the --clear argument to remove
+    /// the limit.
+    Backpressure {
+        /// Remove any limit on the uploaders and exit, without checking the network.
+        #[clap(long)]
+        clear: bool,
+        /// Stop checking after this many minutes, removing any limit that is in place.
+        #[clap(long)]
+        duration: Option<u64>,
+        /// The time between each check of the network, in seconds.
+        #[clap(long, default_value_t = 120)]
+        interval: u64,
+        /// The network is under pressure when the disk usage on any node VM is above this
+        /// percentage.
+        #[clap(long, default_value_t = 90, value_parser = clap::value_parser!(u8).range(1..=100))]
+        max_disk_usage_pct: u8,
+        /// The network is under pressure when the percentage of nodes that are not running is
+        /// above this value.
+        #[clap(long, default_value_t = 10.0)]
+        max_failure_pct: f64,
+        /// The name of the environment
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+        /// The number of uploaders that keep uploading on each VM while the network is under
+        /// pressure.
+        #[clap(long, default_value_t = 1)]
+        reduced_uploaders_per_vm: u16,
+    },
+    /// Deploy a fleet of uploader VMs to an existing network.
+    ///
+    /// Each uploader runs as a systemd service that continuously uploads random data to the
+    /// network. Use the 'upscale' command to add uploaders to a network that already has them.
+    Deploy {
+        /// Supply a version number for the autonomi binary used by the uploaders.
+        ///
+        /// There should be no 'v' prefix. If not provided, the latest version will be used.
+        #[arg(long, verbatim_doc_comment)]
+        autonomi_version: Option<String>,
+        /// The secret key for the wallet that will fund all the uploaders.
+        ///
+        /// This argument only applies when Arbitrum or Sepolia networks are used.
+        #[clap(long)]
+        funding_wallet_secret_key: Option<String>,
+        /// The name of the environment
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+        /// A comma-separated list of regions to spread the uploader VMs across, e.g.,
+        /// 'nyc1,sgp1'.
+        ///
+        /// The uploaders are assigned to the regions in turn. If not used, the uploaders are in the
+        /// same region as the nodes.
+        #[clap(long, value_delimiter = ',', verbatim_doc_comment)]
+        regions: Option<Vec<String>>,
+        /// The number of uploader services to run on each VM.
+        #[clap(long, default_value_t = 1)]
+        uploads_per_vm: u16,
+        /// The number of uploader VMs to create.
+        #[clap(long)]
+        vm_count: u16,
+    },
+    /// Report the upload latencies observed by the uploaders, grouped by the region they are in.
+    ///
+    /// The latencies are calculated from the metrics file written by each uploader service. Only
+    /// successful uploads are included in the latencies.
+    LatencyReport {
+        /// The name of the environment
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider that was used.
+        #[clap(long, default_value_t = CloudProvider::DigitalOcean, value_parser = parse_provider, verbatim_doc_comment)]
+        provider: CloudProvider,
+    },
+    /// Start all uploaders for an environment
+    Start {
+        /// The name of the environment
+        #[arg(long)]
+        name: String,
+        /// The cloud provider that was used.
+        #[clap(long, default_value_t = CloudProvider::DigitalOcean, value_parser = parse_provider, verbatim_doc_comment)]
+        provider: CloudProvider,
+    },
+    /// Stop all uploaders for an environment.
+    Stop {
+        /// The name of the environment
+        #[arg(long)]
+        name: String,
+        /// The cloud provider that was used.
+        #[clap(long, default_value_t = CloudProvider::DigitalOcean, value_parser = parse_provider, verbatim_doc_comment)]
+        provider: CloudProvider,
+    },
+    /// Upgrade the uploaders for a given environment.
+    Upgrade {
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+
+        /// Optionally supply a version for the safe client binary to upgrade to.
+        ///
         /// If not provided, the latest version will be used.
         #[arg(long)]
         version: Option<String>,
@@ -1376,8 +3180,92 @@ enum UploadersCommands {
     },
 }
 
+#[derive(Subcommand, Debug)]
+enum FirewallCommands {
+    /// Create or update the firewall for an environment.
+    ///
+    /// The profile recorded when the environment was deployed is used, with any of the arguments
+    /// below replacing its settings. The updated profile is recorded for the next time.
+    ///
+    /// A firewall is created for an environment that was deployed without one.
+    #[clap(verbatim_doc_comment)]
+    Apply {
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+        /// Open or close the Grafana port on the monitoring VM.
+        #[clap(long)]
+        grafana: Option<bool>,
+        /// Open or close the node RPC ports.
+        #[clap(long)]
+        public_rpc: Option<bool>,
+        /// The CIDRs SSH is allowed from.
+        ///
+        /// Multiple CIDRs are separated by commas. Use 'all' to open SSH to all addresses.
+        #[clap(long, use_value_delimiter = true, verbatim_doc_comment)]
+        ssh_allowlist: Option<Vec<String>>,
+        /// Close or open SSH on the public IPs, so the VMs can only be reached over the WireGuard
+        /// overlay.
+        ///
+        /// Set up the overlay with 'wireguard setup' first. VMs added by an upscale can't be
+        /// provisioned until SSH is opened again, since they are not on the overlay yet.
+        #[clap(long, verbatim_doc_comment)]
+        ssh_over_wireguard: Option<bool>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum WireGuardCommands {
+    /// Join the VMs in an environment, and this machine, to a WireGuard overlay.
+    ///
+    /// Run this after an upscale to add the new VMs. The existing VMs keep their addresses and
+    /// keys.
+    ///
+    /// The `wg` tool must be installed on this machine.
+    #[clap(verbatim_doc_comment)]
+    Setup {
+        /// The name of the environment.
+        #[arg(short = 'n', long)]
+        name: String,
+        /// The cloud provider for the environment.
+        #[clap(long, value_parser = parse_provider, verbatim_doc_comment, default_value_t = CloudProvider::DigitalOcean)]
+        provider: CloudProvider,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 enum FaucetCommands {
+    /// Print the balance of the faucet wallet on the genesis VM.
+    ///
+    /// This command requires the faucet to be running, so run the 'faucet start' command first.
+    Balance {
+        /// The name of the environment
+        #[arg(long)]
+        name: String,
+        /// The cloud provider that was used
+        #[clap(long, default_value_t = CloudProvider::DigitalOcean, value_parser = parse_provider, verbatim_doc_comment)]
+        provider: CloudProvider,
+    },
+    /// Send tokens from the faucet wallet on the genesis VM to a wallet.
+    ///
+    /// Use this to fund test wallets without logging in to the genesis VM.
+    Fund {
+        /// The hex-encoded address of the wallet to fund.
+        #[clap(long)]
+        address: String,
+        /// The number of tokens to send, e.g., '1.5'.
+        #[clap(long)]
+        amount: String,
+        /// The name of the environment
+        #[arg(long)]
+        name: String,
+        /// The cloud provider that was used
+        #[clap(long, default_value_t = CloudProvider::DigitalOcean, value_parser = parse_provider, verbatim_doc_comment)]
+        provider: CloudProvider,
+    },
     /// Fund the uploaders from the faucet
     ///
     /// This command requires the faucet to be running, so run the 'faucet start' command first.
@@ -1456,19 +3344,219 @@ enum FundsCommand {
     },
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    color_eyre::install()?;
-    dotenv().ok();
-    env_logger::init();
+#[tokio::main]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+    dotenv().ok();
+
+    let mut args = env::args().collect::<Vec<_>>();
+    // The generated name is supplied as the --name argument, so it is available for the run log
+    // and the lock, in the same way as a name supplied by the user.
+    if args.iter().any(|arg| arg == "--auto-name") && get_name_arg(&args).is_none() {
+        let existing_names = get_cached_environment_names().unwrap_or_default();
+        let name = generate_environment_name(
+            &S3Repository {},
+            get_arg_value(&args, "--branch", None).as_deref(),
+            &existing_names,
+        )
+        .await?;
+        println!("Generated environment name: {name}");
+        args.push("--name".to_string());
+        args.push(name);
+    }
+    let opt = match Opt::try_parse_from(&args) {
+        Ok(opt) => opt,
+        Err(err) if is_missing_name_error(&err) && std::io::stdin().is_terminal() => {
+            let name = pick_environment()?;
+            args.push("--name".to_string());
+            args.push(name);
+            Opt::parse_from(&args)
+        }
+        Err(err) => err.exit(),
+    };
+    if let Some(path) = init_logging(get_name_arg(&args).as_deref(), opt.run_log_format)? {
+        debug!("Writing the log for this run to {}", path.to_string_lossy());
+    }
+    ensure_command_permitted(&opt)?;
+    if opt.offline_inventory {
+        // The deployer is built separately by each command, so the mode is passed through the
+        // environment.
+        env::set_var("ANSIBLE_OFFLINE_INVENTORY", "true");
+    }
+    if opt.over_wireguard {
+        env::set_var(WIREGUARD_OVERLAY_ENV_VAR, "true");
+    }
+    if matches!(
+        opt.command,
+        Commands::Bootstrap { .. } | Commands::Deploy { .. }
+    ) {
+        if let Some(name) = get_name_arg(&args) {
+            validate_environment_name(&name).map_err(|err| {
+                eyre!(err).suggestion(
+                    "Use --auto-name to generate a valid name from the branch and the date.",
+                )
+            })?;
+        }
+    }
+
+    if opt.force_unlock {
+        let Some(name) = get_name_arg(&args) else {
+            return Err(eyre!(
+                "The --force-unlock argument requires an environment name"
+            ));
+        };
+        force_unlock(&args, &name).await?;
+    }
+
+    if !opt.skip_preflight
+        && matches!(
+            opt.command,
+            Commands::Bootstrap { .. } | Commands::Deploy { .. } | Commands::Upscale { .. }
+        )
+    {
+        let provider = match get_arg_value(&args, "--provider", None) {
+            Some(provider) => parse_provider(&provider)?,
+            None => CloudProvider::DigitalOcean,
+        };
+        let report = run_preflight_checks(provider, DEFAULT_MIN_CREDENTIAL_VALIDITY).await;
+        report.print();
+        if report.has_fatal_issues() {
+            return Err(eyre!("The pre-flight checks failed")
+                .suggestion("Fix the problems above, or use --skip-preflight to run anyway."));
+        }
+    }
+
+    let lock = match get_name_arg(&args) {
+        Some(name) if is_operational_command(&opt.command) => {
+            match EnvironmentLock::acquire(
+                &S3Repository {},
+                &name,
+                &describe_command(&args),
+                opt.wait,
+                opt.steal,
+            )
+            .await
+            {
+                Ok(lock) => Some(lock),
+                Err(err @ Error::EnvironmentLocked { .. }) => {
+                    return Err(eyre!(err).suggestion(
+                        "Use --wait to wait for the other run to finish, or --steal if you are \
+                        sure it is no longer running.",
+                    ));
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        _ => None,
+    };
+
+    let started = Instant::now();
+    let notify = opt.notify;
+    let result = run_command(opt.command).await;
+    if let Err(err) = &result {
+        if let Err(save_err) = save_last_error(&describe_command(&args), &format!("{err:#}")) {
+            debug!("Failed to save the error for the support bundle: {save_err}");
+        }
+    }
+    if let (true, Some(name)) = (notify, get_name_arg(&args)) {
+        if let Err(err) =
+            notify_command_result(&name, &describe_command(&args), &result, started.elapsed()).await
+        {
+            progress!("Failed to send the notification: {err}");
+        }
+    }
+    let active_environments = get_cached_environment_names()
+        .map(|names| names.len())
+        .unwrap_or_default();
+    if let Err(err) = export_metrics(
+        &describe_command(&args),
+        result.is_ok(),
+        started.elapsed(),
+        active_environments,
+    )
+    .await
+    {
+        progress!("Failed to export metrics: {err}");
+    }
+    if let Some(lock) = lock {
+        if let Err(err) = lock.release().await {
+            progress!("Failed to release the environment lock: {err}");
+        }
+    }
+    run_log::print_summary();
+    result
+}
 
-    let opt = Opt::parse();
-    match opt.command {
+async fn run_command(command: Commands) -> Result<()> {
+    match command {
+        Commands::Artifacts(artifacts_cmd) => match artifacts_cmd {
+            ArtifactsCommands::Gc {
+                dry_run,
+                provider,
+                retention_days,
+            } => {
+                // The deployer is not used for a particular environment here, but it requires a
+                // name.
+                let testnet_deployer = TestnetDeployBuilder::default()
+                    .environment_name("artifacts-gc")
+                    .provider(provider)
+                    .build()?;
+                let report = testnet_deployer
+                    .gc_artifacts(&ArtifactGcOptions {
+                        dry_run,
+                        retention: Duration::from_secs(retention_days * 24 * 60 * 60),
+                    })
+                    .await?;
+                report.print();
+                Ok(())
+            }
+        },
+        Commands::Bisect {
+            bad,
+            branch,
+            good,
+            health_script,
+            name_prefix,
+            node_count,
+            node_vm_count,
+            provider,
+            repo_owner,
+            reuse_infra,
+            rewards_address,
+            smoke_test_file_size_kb,
+        } => {
+            let bad = match (bad, branch) {
+                (Some(bad), _) => bad,
+                (None, Some(branch)) => {
+                    let bad = get_branch_commit_sha(&repo_owner, &branch).await?;
+                    println!("Using the head of {repo_owner}/{branch}, {bad}, as the bad commit");
+                    bad
+                }
+                (None, None) => return Err(eyre!("Either --bad or --branch must be supplied")),
+            };
+            let report = bisect(&BisectOptions {
+                bad,
+                good,
+                health_script,
+                name_prefix,
+                node_count,
+                node_vm_count,
+                provider,
+                repo_owner,
+                reuse_infra,
+                rewards_address,
+                smoke_test_file_size_kb,
+            })
+            .await?;
+            report.print();
+            Ok(())
+        }
         Commands::Bootstrap {
             ansible_verbose,
             antctl_version,
             antnode_features,
             antnode_version,
+            bootstrap_environment,
             bootstrap_network_contacts_url,
             bootstrap_peer,
             branch,
@@ -1481,8 +3569,11 @@ async fn main() -> Result<()> {
             evm_rpc_url,
             forks,
             interval,
+            join_rate,
             log_format,
             name,
+            nat_gateway_count,
+            nat_type,
             network_id,
             node_count,
             node_vm_count,
@@ -1491,15 +3582,37 @@ async fn main() -> Result<()> {
             max_archived_log_files,
             max_log_files,
             private_node_count,
+            private_node_upnp,
             private_node_vm_count,
+            private_node_vm_percent,
             private_node_volume_size,
             provider,
             repo_owner,
             rewards_address,
         } => {
+            let (node_vm_count, private_node_vm_count) = match private_node_vm_percent {
+                Some(percent) => split_node_vms(node_vm_count.unwrap_or_default(), percent),
+                None => (node_vm_count, private_node_vm_count),
+            };
+            let bootstrap_network_contacts_url = match bootstrap_environment {
+                Some(bootstrap_environment) => {
+                    let details =
+                        get_environment_details(&bootstrap_environment, &S3Repository {}).await?;
+                    let url = details.network_contacts_url.ok_or_else(|| {
+                        eyre!(
+                            "No network contacts have been published for {bootstrap_environment}. \
+                            Run the `inventory` command for it to publish them."
+                        )
+                    })?;
+                    println!("Bootstrapping from the {bootstrap_environment} contacts at {url}");
+                    Some(url)
+                }
+                None => bootstrap_network_contacts_url,
+            };
             if bootstrap_network_contacts_url.is_none() && bootstrap_peer.is_none() {
                 return Err(eyre!(
-                    "Either bootstrap-peer or bootstrap-network-contacts-url must be provided"
+                    "One of bootstrap-peer, bootstrap-network-contacts-url or \
+                    bootstrap-environment must be provided"
                 ));
             }
 
@@ -1590,8 +3703,11 @@ async fn main() -> Result<()> {
                     evm_payment_token_address,
                     evm_rpc_url,
                     interval,
+                    join_rate,
                     log_format,
                     name: name.clone(),
+                    nat_gateway_count,
+                    nat_type,
                     network_id,
                     node_count,
                     node_vm_count,
@@ -1606,6 +3722,7 @@ async fn main() -> Result<()> {
                         .join("inventory"),
                     private_node_vm_count,
                     private_node_count,
+                    private_node_upnp,
                     private_node_volume_size: private_node_volume_size
                         .or_else(|| Some(calculate_size_per_attached_volume(private_node_count))),
                     rewards_address,
@@ -1621,13 +3738,96 @@ async fn main() -> Result<()> {
             new_inventory.save()?;
             Ok(())
         }
-        Commands::Clean { name, provider } => {
+        Commands::Chaos(chaos_cmd) => {
+            let (name, provider) = match &chaos_cmd {
+                ChaosCommands::DiskFull { name, provider, .. }
+                | ChaosCommands::Kill { name, provider, .. }
+                | ChaosCommands::Oom { name, provider, .. }
+                | ChaosCommands::Sigstop { name, provider, .. }
+                | ChaosCommands::Template { name, provider, .. } => (name.clone(), *provider),
+            };
+            let testnet_deployer = TestnetDeployBuilder::default()
+                .environment_name(&name)
+                .provider(provider)
+                .build()?;
+            let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+            let inventory = inventory_service
+                .generate_or_retrieve_inventory(&name, true, None)
+                .await?;
+            if inventory.is_empty() {
+                return Err(eyre!("The {name} environment does not exist"));
+            }
+
+            let (fault, count, node_type) = match chaos_cmd {
+                ChaosCommands::DiskFull {
+                    duration,
+                    fill_pct,
+                    vms,
+                    ..
+                } => {
+                    let report =
+                        fill_disks(&testnet_deployer, &inventory, &vms, fill_pct, duration).await?;
+                    report.print();
+                    let report_path = report.save()?;
+                    println!("Chaos report written to {}", report_path.display());
+                    return Ok(());
+                }
+                ChaosCommands::Kill {
+                    count, node_type, ..
+                } => (Fault::Kill, count, node_type),
+                ChaosCommands::Oom {
+                    count, node_type, ..
+                } => (Fault::Oom, count, node_type),
+                ChaosCommands::Sigstop {
+                    count, node_type, ..
+                } => (Fault::Sigstop, count, node_type),
+                ChaosCommands::Template {
+                    duration,
+                    max_fleet_pct,
+                    template,
+                    ..
+                } => {
+                    let report = apply_template(
+                        &testnet_deployer,
+                        &inventory,
+                        template,
+                        max_fleet_pct,
+                        duration,
+                    )
+                    .await?;
+                    report.print();
+                    let report_path = report.save()?;
+                    println!("Chaos report written to {}", report_path.display());
+                    return Ok(());
+                }
+            };
+
+            let report =
+                inject_fault(&testnet_deployer, &inventory, fault, count, node_type).await?;
+            report.print();
+            let report_path = report.save()?;
+            println!("Fault report written to {}", report_path.display());
+            Ok(())
+        }
+        Commands::Clean {
+            keep_build_vm,
+            keep_genesis_vm,
+            keep_logs_volume,
+            name,
+            provider,
+        } => {
             let testnet_deployer = TestnetDeployBuilder::default()
                 .environment_name(&name)
                 .provider(provider)
                 .build()?;
 
-            testnet_deployer.clean().await?;
+            testnet_deployer
+                .clean(&CleanOptions {
+                    keep_build_vm,
+                    keep_genesis_vm,
+                    keep_logs_volume,
+                })
+                .await?;
             Ok(())
         }
         Commands::Deploy {
@@ -1636,9 +3836,21 @@ async fn main() -> Result<()> {
             antctl_version,
             antnode_features,
             antnode_version,
+            arch,
+            artifact_repository,
+            auditor_vm_count,
+            // The name has already been generated and supplied as the --name argument.
+            auto_name: _,
             branch,
+            channel,
             chunk_size,
+            disable_build_cache,
+            dns_domain,
+            dns_resolvers,
+            door_node_count,
+            door_node_dns_domain,
             downloaders_count,
+            dry_run,
             env_variables,
             environment_type,
             evm_data_payments_address,
@@ -1646,40 +3858,80 @@ async fn main() -> Result<()> {
             evm_node_vm_size,
             evm_payment_token_address,
             evm_rpc_url,
+            firewall,
             forks,
             foundation_pk,
+            from_versions_file,
             funding_wallet_secret_key,
             genesis_node_volume_size,
             genesis_pk,
+            harden,
+            host_entries,
             interval,
+            local_ant_path,
+            local_antctl_path,
+            local_antctld_path,
+            local_antnode_path,
             log_format,
             logstash_stack_name,
+            loki_url,
             max_archived_log_files,
             max_log_files,
             name,
+            nat_gateway_count,
+            nat_type,
             network_id,
+            network_contacts_bucket,
             network_contacts_file_name,
             network_royalties_pk,
             node_count,
+            node_reachability,
+            node_restart,
+            node_restart_sec,
+            node_start_limit_burst,
             node_vm_count,
             node_vm_size,
             node_volume_size,
+            notify_webhook_format,
+            notify_webhook_url,
+            otlp_endpoint,
             payment_forward_pk,
             peer_cache_node_count,
+            peer_cache_node_reachability,
             peer_cache_node_vm_count,
             peer_cache_node_vm_size,
             peer_cache_node_volume_size,
             private_node_count,
+            private_node_upnp,
             private_node_vm_count,
+            private_node_vm_percent,
             private_node_volume_size,
             provider,
+            provision_parallelism,
+            provision_retries,
             public_rpc,
             repo_owner,
+            resume,
             rewards_address,
+            rpc_tls,
+            rpc_tls_email,
+            setup_artifact_proxy,
+            setup_infra_services,
+            setup_monitoring,
+            ssh_allowlist,
+            sysstat_duration,
+            ttl,
+            uploader_bandwidth_class,
+            uploader_regions,
             uploader_vm_count,
             uploader_vm_size,
             uploaders_count,
+            wireguard,
         } => {
+            let (node_vm_count, private_node_vm_count) = match private_node_vm_percent {
+                Some(percent) => split_node_vms(node_vm_count.unwrap_or_default(), percent),
+                None => (node_vm_count, private_node_vm_count),
+            };
             if evm_network_type == EvmNetwork::Custom {
                 if evm_data_payments_address.is_none() {
                     return Err(eyre!(
@@ -1696,6 +3948,27 @@ async fn main() -> Result<()> {
                 }
             }
 
+            if dns_domain.is_some() && !matches!(provider, CloudProvider::DigitalOcean) {
+                return Err(eyre!("DNS records can only be created on DigitalOcean"));
+            }
+            if firewall && !matches!(provider, CloudProvider::DigitalOcean) {
+                return Err(eyre!("A firewall can only be created on DigitalOcean"));
+            }
+
+            if door_node_count > 0 {
+                if door_node_dns_domain.is_none() {
+                    return Err(eyre!("A DNS domain must be provided for door nodes")
+                        .suggestion("Use the --door-node-dns-domain argument"));
+                }
+                if !harden {
+                    return Err(eyre!(
+                        "Door nodes are an entry point for clients, so the \
+                        machines must be hardened"
+                    )
+                    .suggestion("Use the --harden argument"));
+                }
+            }
+
             let network_keys = validate_and_get_pks(
                 foundation_pk,
                 genesis_pk,
@@ -1709,21 +3982,79 @@ async fn main() -> Result<()> {
                 ));
             }
 
-            let binary_option = get_binary_option(
-                branch,
-                repo_owner,
-                ant_version,
-                antnode_version,
-                antctl_version,
-                antnode_features,
-                network_keys,
-            )
-            .await?;
+            let release = match channel {
+                Some(channel) => {
+                    print_with_banner(&format!("Resolving the {channel} release channel"));
+                    let release = resolve_release_channel(channel).await?;
+                    release.print();
+                    Some(release)
+                }
+                None => None,
+            };
+            let (ant_version, antnode_version, antctl_version) = match &release {
+                Some(release) => (
+                    Some(release.ant_version.to_string()),
+                    Some(release.antnode_version.to_string()),
+                    Some(release.antctl_version.to_string()),
+                ),
+                None => (ant_version, antnode_version, antctl_version),
+            };
+
+            let versions_file = from_versions_file
+                .map(|path| VersionsFile::read(&path))
+                .transpose()?;
+            let (arch, chunk_size, pinned_commit_sha) = match &versions_file {
+                Some(versions) => {
+                    let build = versions.build.as_ref();
+                    (
+                        versions.architecture,
+                        build.and_then(|build| build.chunk_size),
+                        build.and_then(|build| build.commit_sha.clone()),
+                    )
+                }
+                None => (arch, chunk_size, None),
+            };
+
+            let use_local_binaries = local_antnode_path.is_some();
+            let binary_option = match (
+                &versions_file,
+                local_antnode_path,
+                local_antctl_path,
+                local_antctld_path,
+            ) {
+                (Some(versions), _, _, _) => {
+                    print_with_banner("Binaries will be the same as a previous deployment");
+                    versions.print();
+                    versions.get_binary_option()?
+                }
+                (None, Some(antnode_path), Some(antctl_path), Some(antctld_path)) => {
+                    print_with_banner("Binaries will be uploaded from the local machine");
+                    BinaryOption::Local {
+                        ant_path: local_ant_path,
+                        antctl_path,
+                        antctld_path,
+                        antnode_path,
+                    }
+                }
+                _ => {
+                    get_binary_option(
+                        branch,
+                        repo_owner,
+                        ant_version,
+                        antnode_version,
+                        antctl_version,
+                        antnode_features,
+                        network_keys,
+                    )
+                    .await?
+                }
+            };
 
             let mut builder = TestnetDeployBuilder::default();
             builder
                 .ansible_verbose_mode(ansible_verbose)
                 .deployment_type(environment_type.clone())
+                .dry_run(dry_run)
                 .environment_name(&name)
                 .provider(provider);
             if let Some(forks) = forks {
@@ -1731,6 +4062,30 @@ async fn main() -> Result<()> {
             }
             let testnet_deployer = builder.build()?;
 
+            if door_node_count > 0 {
+                // Without the argument, the count comes from the tfvars file for the environment
+                // type.
+                let resolved_peer_cache_node_vm_count = match peer_cache_node_vm_count {
+                    Some(count) => Some(count),
+                    None => testnet_deployer
+                        .terraform_runner
+                        .get_tfvars_variable(
+                            &environment_type.get_tfvars_filename(&name),
+                            "peer_cache_node_vm_count",
+                        )?
+                        .and_then(|count| count.parse::<u16>().ok()),
+                };
+                if let Some(resolved_peer_cache_node_vm_count) = resolved_peer_cache_node_vm_count {
+                    if door_node_count > resolved_peer_cache_node_vm_count {
+                        return Err(eyre!(
+                            "The door node count cannot exceed the Peer Cache VM count of \
+                            {resolved_peer_cache_node_vm_count}"
+                        )
+                        .suggestion("Use the --peer-cache-node-vm-count argument"));
+                    }
+                }
+            }
+
             let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
             let inventory = inventory_service
                 .generate_or_retrieve_inventory(&name, true, Some(binary_option.clone()))
@@ -1756,7 +4111,11 @@ async fn main() -> Result<()> {
                 }
             }
 
-            let logstash_details = {
+            let telemetry = if let Some(url) = loki_url {
+                Some(TelemetryConfig::Loki { url })
+            } else if let Some(endpoint) = otlp_endpoint {
+                Some(TelemetryConfig::Otlp { endpoint })
+            } else {
                 let logstash_deploy = LogstashDeployBuilder::default()
                     .environment_name(&name)
                     .provider(provider)
@@ -1767,7 +4126,10 @@ async fn main() -> Result<()> {
                 if stack_hosts.is_empty() {
                     None
                 } else {
-                    Some((logstash_stack_name, stack_hosts))
+                    Some(TelemetryConfig::Logstash {
+                        stack_name: logstash_stack_name,
+                        hosts: stack_hosts,
+                    })
                 }
             };
 
@@ -1777,11 +4139,34 @@ async fn main() -> Result<()> {
             let private_node_count =
                 private_node_count.unwrap_or(environment_type.get_default_private_node_count());
 
+            // A redeploy keeps the token from the previous deployment, so clients don't need to
+            // be updated.
+            let rpc_proxy_auth_token = if rpc_tls {
+                let auth_token = match rpc_proxy::read_auth_token(&name)? {
+                    Some(auth_token) => auth_token,
+                    None => rpc_proxy::generate_auth_token(),
+                };
+                let path = rpc_proxy::save_auth_token(&name, &auth_token)?;
+                println!("The RPC proxy auth token is saved at {}", path.display());
+                Some(auth_token)
+            } else {
+                None
+            };
+
+            let deploy_started = Instant::now();
             testnet_deployer
                 .deploy(&DeployOptions {
+                    architecture: arch,
+                    artifact_repository,
+                    auditor_vm_count,
                     binary_option: binary_option.clone(),
                     chunk_size,
                     current_inventory: inventory,
+                    disable_build_cache,
+                    dns_domain,
+                    dns_resolvers: dns_resolvers.unwrap_or_default(),
+                    door_node_count,
+                    door_node_dns_domain,
                     downloaders_count,
                     environment_type: environment_type.clone(),
                     env_variables,
@@ -1789,16 +4174,32 @@ async fn main() -> Result<()> {
                     evm_network: evm_network_type,
                     evm_payment_token_address,
                     evm_rpc_url,
+                    firewall: firewall.then(|| FirewallProfile {
+                        grafana: setup_monitoring,
+                        public_rpc,
+                        rpc_proxy: rpc_tls,
+                        ssh_allowlist: ssh_allowlist.unwrap_or_default(),
+                        ..Default::default()
+                    }),
                     evm_node_vm_size,
                     funding_wallet_secret_key,
                     genesis_node_volume_size: genesis_node_volume_size
                         .or_else(|| Some(calculate_size_per_attached_volume(1))),
+                    harden,
+                    host_entries: host_entries.unwrap_or_default(),
                     interval,
                     log_format,
-                    logstash_details,
                     name: name.clone(),
+                    nat_gateway_count,
+                    nat_type,
                     network_id,
                     node_count,
+                    node_reachability,
+                    node_restart_policy: RestartPolicy {
+                        restart: node_restart,
+                        restart_sec: node_restart_sec,
+                        start_limit_burst: node_start_limit_burst,
+                    },
                     node_vm_count,
                     node_volume_size: node_volume_size
                         .or_else(|| Some(calculate_size_per_attached_volume(node_count))),
@@ -1809,6 +4210,7 @@ async fn main() -> Result<()> {
                         .join("ansible")
                         .join("inventory"),
                     peer_cache_node_count,
+                    peer_cache_node_reachability,
                     peer_cache_node_vm_count,
                     peer_cache_node_volume_size: peer_cache_node_volume_size.or_else(|| {
                         Some(calculate_size_per_attached_volume(peer_cache_node_count))
@@ -1816,16 +4218,44 @@ async fn main() -> Result<()> {
                     peer_cache_node_vm_size,
                     private_node_vm_count,
                     private_node_count,
+                    private_node_upnp,
                     private_node_volume_size: private_node_volume_size
                         .or_else(|| Some(calculate_size_per_attached_volume(private_node_count))),
+                    notification: notify_webhook_url.map(|webhook_url| NotificationConfig {
+                        format: notify_webhook_format,
+                        webhook_url,
+                    }),
+                    provision_parallelism,
+                    pinned_commit_sha,
+                    provision_retries,
                     public_rpc,
+                    release,
+                    resume,
                     uploaders_count,
                     uploader_vm_count,
                     rewards_address,
+                    rpc_proxy_acme_email: rpc_tls_email,
+                    rpc_proxy_auth_token,
                     node_vm_size,
+                    setup_artifact_proxy: setup_artifact_proxy
+                        || use_local_binaries
+                        || setup_infra_services,
+                    setup_infra_services,
+                    setup_monitoring,
+                    sysstat_duration: sysstat_duration.map(Duration::from_secs),
+                    telemetry,
+                    ttl,
+                    uploader_bandwidth_class,
+                    uploader_regions: uploader_regions.unwrap_or_default(),
                     uploader_vm_size,
+                    wireguard,
                 })
                 .await?;
+            let deploy_duration = deploy_started.elapsed();
+
+            if dry_run {
+                return Ok(());
+            }
 
             let max_retries = 3;
             let mut retries = 0;
@@ -1854,9 +4284,141 @@ async fn main() -> Result<()> {
             inventory.save()?;
 
             inventory_service
-                .upload_network_contacts(&inventory, network_contacts_file_name)
+                .upload_network_contacts(
+                    &inventory,
+                    network_contacts_file_name,
+                    network_contacts_bucket,
+                )
+                .await?;
+
+            if let Some(bucket_name) = get_run_summary_bucket_name() {
+                let summary = RunSummary::new(&name, &binary_option, deploy_duration);
+                if let Err(err) = upload_run_summary(&S3Repository {}, &bucket_name, &summary).await
+                {
+                    println!("Failed to upload the run summary: {err:?}");
+                }
+            }
+
+            Ok(())
+        }
+        Commands::Downloaders(downloaders_cmd) => match downloaders_cmd {
+            DownloadersCommands::Deploy {
+                autonomi_version,
+                downloaders_per_vm,
+                name,
+                provider,
+                vm_count,
+            } => {
+                if vm_count == 0 || downloaders_per_vm == 0 {
+                    return Err(eyre!(
+                        "The VM count and the downloaders per VM must be greater than zero"
+                    ));
+                }
+                let autonomi_version =
+                    get_version_from_option(autonomi_version, &ReleaseType::Ant).await?;
+
+                let testnet_deployer = TestnetDeployBuilder::default()
+                    .environment_name(&name)
+                    .provider(provider)
+                    .build()?;
+                testnet_deployer.init().await?;
+
+                let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+                let inventory = inventory_service
+                    .generate_or_retrieve_inventory(&name, true, None)
+                    .await?;
+                if inventory.is_empty() {
+                    return Err(eyre!("The '{}' environment does not exist", name));
+                }
+
+                testnet_deployer
+                    .deploy_downloaders(&DownloaderDeployOptions {
+                        ant_version: autonomi_version.to_string(),
+                        current_inventory: inventory,
+                        downloaders_per_vm,
+                        vm_count,
+                    })
+                    .await?;
+
+                let inventory = inventory_service
+                    .generate_or_retrieve_inventory(&name, true, None)
+                    .await?;
+                inventory.print_report(false)?;
+                inventory.save()?;
+
+                Ok(())
+            }
+            DownloadersCommands::Start { name, provider } => {
+                let testnet_deployer = TestnetDeployBuilder::default()
+                    .environment_name(&name)
+                    .provider(provider)
+                    .build()?;
+                let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+                inventory_service
+                    .generate_or_retrieve_inventory(&name, true, None)
+                    .await?;
+                testnet_deployer.start_downloaders()?;
+                Ok(())
+            }
+            DownloadersCommands::Stop { name, provider } => {
+                let testnet_deployer = TestnetDeployBuilder::default()
+                    .environment_name(&name)
+                    .provider(provider)
+                    .build()?;
+                let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+                inventory_service
+                    .generate_or_retrieve_inventory(&name, true, None)
+                    .await?;
+                testnet_deployer.stop_downloaders()?;
+                Ok(())
+            }
+        },
+        Commands::Downscale {
+            ansible_verbose,
+            desired_node_vm_count,
+            desired_peer_cache_node_vm_count,
+            interval,
+            name,
+            plan,
+            provider,
+        } => {
+            println!("Downscaling deployment...");
+            let testnet_deployer = TestnetDeployBuilder::default()
+                .ansible_verbose_mode(ansible_verbose)
+                .environment_name(&name)
+                .provider(provider)
+                .build()?;
+            testnet_deployer.init().await?;
+
+            let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+            let inventory = inventory_service
+                .generate_or_retrieve_inventory(&name, true, None)
+                .await?;
+            if inventory.is_empty() {
+                return Err(eyre!("The {name} environment does not exist"));
+            }
+
+            testnet_deployer
+                .downscale(&DownscaleOptions {
+                    current_inventory: inventory,
+                    desired_node_vm_count,
+                    desired_peer_cache_node_vm_count,
+                    interval,
+                    plan,
+                })
                 .await?;
 
+            if plan {
+                return Ok(());
+            }
+
+            println!("Generating new inventory after downscale...");
+            let inventory = inventory_service
+                .generate_or_retrieve_inventory(&name, true, None)
+                .await?;
+            inventory.print_report(false)?;
+            inventory.save()?;
+
             Ok(())
         }
         Commands::ExtendVolumeSize {
@@ -1929,9 +4491,48 @@ async fn main() -> Result<()> {
                     .run_playbook(AnsiblePlaybook::ExtendVolumeSize, node_type, None)?;
             }
 
-            Ok(())
-        }
-        Commands::Faucet(uploaders_cmd) => match uploaders_cmd {
+            Ok(())
+        }
+        Commands::Faucet(uploaders_cmd) => match uploaders_cmd {
+            FaucetCommands::Balance { name, provider } => {
+                let testnet_deployer = TestnetDeployBuilder::default()
+                    .environment_name(&name)
+                    .provider(provider)
+                    .build()?;
+                let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+                inventory_service
+                    .generate_or_retrieve_inventory(&name, true, None)
+                    .await?;
+
+                for line in testnet_deployer.get_faucet_balance().await? {
+                    println!("{line}");
+                }
+                Ok(())
+            }
+            FaucetCommands::Fund {
+                address,
+                amount,
+                name,
+                provider,
+            } => {
+                let testnet_deployer = TestnetDeployBuilder::default()
+                    .environment_name(&name)
+                    .provider(provider)
+                    .build()?;
+                let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+                inventory_service
+                    .generate_or_retrieve_inventory(&name, true, None)
+                    .await?;
+
+                for line in testnet_deployer
+                    .fund_wallet_from_faucet(&address, &amount)
+                    .await?
+                {
+                    println!("{line}");
+                }
+                println!("Sent {amount} tokens from the faucet to {address}");
+                Ok(())
+            }
             FaucetCommands::FundUploaders {
                 name,
                 provider,
@@ -2106,14 +4707,86 @@ async fn main() -> Result<()> {
                 }
             }
         }
+        Commands::Firewall(firewall_cmd) => match firewall_cmd {
+            FirewallCommands::Apply {
+                grafana,
+                name,
+                provider,
+                public_rpc,
+                ssh_allowlist,
+                ssh_over_wireguard,
+            } => {
+                let s3_repository = S3Repository {};
+                let mut environment_details =
+                    get_environment_details(&name, &s3_repository).await?;
+                let mut profile = match environment_details.firewall.take() {
+                    Some(profile) => profile,
+                    None => FirewallProfile {
+                        rpc_proxy: environment_details.rpc_proxy.unwrap_or(false),
+                        ..Default::default()
+                    },
+                };
+                if let Some(grafana) = grafana {
+                    profile.grafana = grafana;
+                }
+                if let Some(public_rpc) = public_rpc {
+                    profile.public_rpc = public_rpc;
+                }
+                if let Some(ssh_allowlist) = ssh_allowlist {
+                    profile.ssh_allowlist = ssh_allowlist
+                        .into_iter()
+                        .filter(|cidr| cidr != "all")
+                        .collect();
+                }
+                if let Some(ssh_over_wireguard) = ssh_over_wireguard {
+                    profile.ssh_over_wireguard = ssh_over_wireguard;
+                }
+
+                apply_firewall(provider, &name, &profile).await?;
+                profile.print();
+                environment_details.firewall = Some(profile);
+                write_environment_details(&s3_repository, &name, &environment_details).await?;
+                Ok(())
+            }
+        },
+        Commands::Infra(infra_cmd) => match infra_cmd {
+            InfraCommands::Plan {
+                json,
+                name,
+                provider,
+            } => {
+                let testnet_deployer = TestnetDeployBuilder::default()
+                    .environment_name(&name)
+                    .provider(provider)
+                    .build()?;
+                let report = testnet_deployer.detect_infra_drift().await?;
+                if json {
+                    println!("{}", report.to_json()?);
+                } else {
+                    report.print();
+                }
+                if report.has_drift() {
+                    return Err(eyre!(
+                        "The infrastructure for {name} has drifted from the Terraform state"
+                    ));
+                }
+                Ok(())
+            }
+        },
         Commands::Inventory {
             force_regeneration,
             full,
+            json,
             name,
+            network_contacts_bucket,
             network_contacts_file_name,
             peer_cache,
             provider,
+            yaml,
         } => {
+            if json || yaml {
+                reserve_stdout();
+            }
             let testnet_deployer = TestnetDeployBuilder::default()
                 .environment_name(&name)
                 .provider(provider)
@@ -2124,7 +4797,11 @@ async fn main() -> Result<()> {
                 .generate_or_retrieve_inventory(&name, force_regeneration, None)
                 .await?;
 
-            if peer_cache {
+            if json {
+                println!("{}", inventory.to_json()?);
+            } else if yaml {
+                print!("{}", inventory.to_yaml()?);
+            } else if peer_cache {
                 inventory.print_peer_cache_webserver();
             } else {
                 inventory.print_report(full)?;
@@ -2132,9 +4809,16 @@ async fn main() -> Result<()> {
 
             inventory.save()?;
 
-            inventory_service
-                .upload_network_contacts(&inventory, network_contacts_file_name)
-                .await?;
+            // Observers cannot publish anything, so the network contacts file is left as it is.
+            if !is_observer() {
+                inventory_service
+                    .upload_network_contacts(
+                        &inventory,
+                        network_contacts_file_name,
+                        network_contacts_bucket,
+                    )
+                    .await?;
+            }
 
             Ok(())
         }
@@ -2171,8 +4855,13 @@ async fn main() -> Result<()> {
                 testnet_deployer.copy_logs(&name, resources_only)?;
                 Ok(())
             }
-            LogCommands::Get { name } => {
-                sn_testnet_deploy::logs::get_logs(&name).await?;
+            LogCommands::Get { name, since, until } => {
+                if let (Some(since), Some(until)) = (since, until) {
+                    if since > until {
+                        return Err(eyre!("The --since time must be before the --until time"));
+                    }
+                }
+                sn_testnet_deploy::logs::get_logs(&name, since, until).await?;
                 Ok(())
             }
             LogCommands::Reassemble { name } => {
@@ -2221,6 +4910,7 @@ async fn main() -> Result<()> {
         Commands::Logstash(logstash_cmd) => match logstash_cmd {
             LogstashCommands::Clean { name, provider } => {
                 let logstash_deploy = LogstashDeployBuilder::default()
+                    .environment_name(&name)
                     .provider(provider)
                     .build()?;
                 logstash_deploy.clean(&name).await?;
@@ -2232,13 +4922,160 @@ async fn main() -> Result<()> {
                 vm_count,
             } => {
                 let logstash_deploy = LogstashDeployBuilder::default()
+                    .environment_name(&name)
                     .provider(provider)
                     .build()?;
                 logstash_deploy.init(&name).await?;
                 logstash_deploy.deploy(&name, vm_count)?;
+
+                let stack_hosts = logstash_deploy.get_stack_hosts(&name).await?;
+                println!(
+                    "Use '--logstash-stack-name {name}' to forward the logs of a deployment to these {} hosts",
+                    stack_hosts.len()
+                );
+                Ok(())
+            }
+        },
+        Commands::Monitoring(monitoring_cmd) => match monitoring_cmd {
+            MonitoringCommands::Publish {
+                mut dashboards,
+                name,
+                provider,
+            } => {
+                if dashboards.is_empty() {
+                    dashboards.push(DEFAULT_DASHBOARD_UID.to_string());
+                }
+                let testnet_deployer = TestnetDeployBuilder::default()
+                    .environment_name(&name)
+                    .provider(provider)
+                    .build()?;
+                testnet_deployer.init().await?;
+                let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+                inventory_service.setup_environment_inventory(&name)?;
+
+                let public_dashboards = testnet_deployer
+                    .publish_grafana_dashboards(&dashboards)
+                    .await?;
+                println!("The dashboards can be viewed without logging in at:");
+                for public_dashboard in public_dashboards.iter() {
+                    println!(
+                        "{}: {}",
+                        public_dashboard.dashboard_uid, public_dashboard.url
+                    );
+                }
+                Ok(())
+            }
+            MonitoringCommands::Unpublish {
+                mut dashboards,
+                name,
+                provider,
+            } => {
+                if dashboards.is_empty() {
+                    dashboards.push(DEFAULT_DASHBOARD_UID.to_string());
+                }
+                let testnet_deployer = TestnetDeployBuilder::default()
+                    .environment_name(&name)
+                    .provider(provider)
+                    .build()?;
+                testnet_deployer.init().await?;
+                let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+                inventory_service.setup_environment_inventory(&name)?;
+
+                testnet_deployer
+                    .unpublish_grafana_dashboards(&dashboards)
+                    .await?;
                 Ok(())
             }
         },
+        Commands::Genesis(GenesisCommands::Backup { name, provider }) => {
+            let testnet_deployer = TestnetDeployBuilder::default()
+                .environment_name(&name)
+                .provider(provider)
+                .build()?;
+            let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+            let inventory = inventory_service
+                .generate_or_retrieve_inventory(&name, true, None)
+                .await?;
+            testnet_deployer.backup_genesis_node(&inventory).await?;
+            println!("The genesis node for {name} has been backed up");
+            Ok(())
+        }
+        Commands::Genesis(GenesisCommands::Rebuild {
+            interval,
+            max_archived_log_files,
+            max_log_files,
+            name,
+            no_restore,
+            provider,
+            public_rpc,
+        }) => {
+            let testnet_deployer = TestnetDeployBuilder::default()
+                .environment_name(&name)
+                .provider(provider)
+                .build()?;
+            testnet_deployer.init().await?;
+            let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+            let inventory = inventory_service
+                .generate_or_retrieve_inventory(&name, false, None)
+                .await?;
+            if inventory.is_empty() {
+                return Err(eyre!("The {name} environment does not exist"));
+            }
+
+            let old_multiaddr = inventory.genesis_multiaddr.clone();
+            let multiaddr = testnet_deployer
+                .rebuild_genesis_node(&GenesisRebuildOptions {
+                    current_inventory: inventory,
+                    interval,
+                    max_archived_log_files,
+                    max_log_files,
+                    public_rpc,
+                    restore_backup: !no_restore,
+                })
+                .await?;
+
+            // Regenerating replaces the cached inventory, which has the old genesis VM.
+            inventory_service
+                .generate_or_retrieve_inventory(&name, true, None)
+                .await?;
+            if let Some(old_multiaddr) = old_multiaddr {
+                println!("The old genesis multiaddr was {old_multiaddr}");
+            }
+            println!("The new genesis multiaddr is {multiaddr}");
+            println!("Use the 'update-peer' command to give it to the other nodes");
+            Ok(())
+        }
+        Commands::NatGateway(NatGatewayCommands::Failover {
+            name,
+            provider,
+            shard,
+            to,
+        }) => {
+            let testnet_deployer = TestnetDeployBuilder::default()
+                .environment_name(&name)
+                .provider(provider)
+                .build()?;
+            testnet_deployer.init().await?;
+            let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+            inventory_service.setup_environment_inventory(&name)?;
+
+            let environment_details =
+                get_environment_details(&name, &testnet_deployer.s3_repository).await?;
+            let shard = testnet_deployer
+                .failover_nat_gateway(
+                    shard,
+                    to.as_deref(),
+                    environment_details.nat_type.unwrap_or_default(),
+                    environment_details.private_node_upnp.unwrap_or(false),
+                )
+                .await?;
+            println!(
+                "{} private node VMs are now routed through {}",
+                shard.private_node_vms.len(),
+                shard.gateway.name
+            );
+            Ok(())
+        }
         Commands::Network(NetworkCommands::ChurnCommands(churn_cmds)) => {
             let (name, provider) = match &churn_cmds {
                 ChurnCommands::FixedInterval { name, provider, .. } => (name, provider),
@@ -2290,6 +5127,134 @@ async fn main() -> Result<()> {
             }
             Ok(())
         }
+        Commands::Network(NetworkCommands::Heal {
+            forks,
+            name,
+            provider,
+        }) => {
+            let testnet_deployer = TestnetDeployBuilder::default()
+                .ansible_forks(forks)
+                .environment_name(&name)
+                .provider(provider)
+                .build()?;
+            testnet_deployer.init().await?;
+            let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+            inventory_service.setup_environment_inventory(&name)?;
+
+            testnet_deployer.heal_network()?;
+            println!("The partition has been removed");
+            Ok(())
+        }
+        Commands::Network(NetworkCommands::Partition {
+            forks,
+            groups,
+            name,
+            provider,
+        }) => {
+            let testnet_deployer = TestnetDeployBuilder::default()
+                .ansible_forks(forks)
+                .environment_name(&name)
+                .provider(provider)
+                .build()?;
+            testnet_deployer.init().await?;
+            let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+            inventory_service.setup_environment_inventory(&name)?;
+
+            let partition = testnet_deployer.partition_network(groups)?;
+            println!("The node VMs have been partitioned:");
+            partition.print();
+            Ok(())
+        }
+        Commands::Network(NetworkCommands::PeerFilter {
+            allow,
+            clear,
+            custom_inventory,
+            deny,
+            forks,
+            interval,
+            name,
+            node_type,
+            provider,
+            skip_restart,
+        }) => {
+            if !clear && allow.is_none() && deny.is_none() {
+                return Err(eyre!("No peer filter was specified").suggestion(
+                    "Use --allow or --deny to apply a filter, or --clear to remove it",
+                ));
+            }
+
+            let testnet_deployer = TestnetDeployBuilder::default()
+                .ansible_forks(forks)
+                .environment_name(&name)
+                .provider(provider)
+                .build()?;
+            let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+            let inventory = inventory_service
+                .generate_or_retrieve_inventory(&name, true, None)
+                .await?;
+            if inventory.is_empty() {
+                return Err(eyre!("The {name} environment does not exist"));
+            }
+
+            let custom_inventory = if let Some(custom_inventory) = custom_inventory {
+                let custom_vms = get_custom_inventory(&inventory, &custom_inventory)?;
+                Some(custom_vms)
+            } else {
+                None
+            };
+            let allowed_peer_ips = get_peer_ips(&inventory, &allow.unwrap_or_default())?;
+            let denied_peer_ips = get_peer_ips(&inventory, &deny.unwrap_or_default())?;
+
+            testnet_deployer.apply_peer_filter(
+                node_type,
+                custom_inventory,
+                build_peer_filter_extra_vars_doc(
+                    allowed_peer_ips,
+                    denied_peer_ips,
+                    !skip_restart,
+                    interval,
+                ),
+            )?;
+            Ok(())
+        }
+        Commands::Network(NetworkCommands::Restart {
+            clear_caches,
+            forks,
+            interval,
+            max_failure_pct,
+            name,
+            provider,
+            reconvergence_timeout,
+            stage_delay,
+        }) => {
+            let testnet_deployer = TestnetDeployBuilder::default()
+                .ansible_forks(forks)
+                .environment_name(&name)
+                .provider(provider)
+                .build()?;
+            let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+            let inventory = inventory_service
+                .generate_or_retrieve_inventory(&name, true, None)
+                .await?;
+            if inventory.is_empty() {
+                return Err(eyre!("The {name} environment does not exist"));
+            }
+
+            let report = testnet_deployer.restart_network(&NetworkRestartOptions {
+                clear_caches,
+                interval,
+                max_failure_pct,
+                reconvergence_timeout: Duration::from_secs(reconvergence_timeout),
+                stage_delay: Duration::from_secs(stage_delay),
+            })?;
+            report.print();
+            if !report.reconverged {
+                return Err(eyre!(
+                    "The network did not reconverge within {reconvergence_timeout} seconds"
+                ));
+            }
+            Ok(())
+        }
         Commands::Network(NetworkCommands::UpdateNodeLogLevel {
             concurrent_updates,
             log_level,
@@ -2301,13 +5266,62 @@ async fn main() -> Result<()> {
                     .suggestion("Please run the inventory command to generate it"));
             }
 
-            let inventory = DeploymentInventory::read(&inventory_path)?;
-            network_commands::update_node_log_levels(inventory, log_level, concurrent_updates)
-                .await?;
+            let inventory = DeploymentInventory::read(&inventory_path)?;
+            network_commands::update_node_log_levels(inventory, log_level, concurrent_updates)
+                .await?;
+
+            Ok(())
+        }
+        Commands::NetworkConditions(network_conditions_cmd) => match network_conditions_cmd {
+            NetworkConditionsCommands::Apply {
+                hosts,
+                jitter,
+                latency,
+                loss,
+                name,
+                provider,
+            } => {
+                let testnet_deployer = TestnetDeployBuilder::default()
+                    .environment_name(&name)
+                    .provider(provider)
+                    .build()?;
+                testnet_deployer.init().await?;
+                let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+                inventory_service.setup_environment_inventory(&name)?;
+
+                testnet_deployer.apply_network_conditions(
+                    &NetworkConditions {
+                        jitter,
+                        latency,
+                        loss,
+                    },
+                    hosts.as_deref(),
+                )?;
+                Ok(())
+            }
+            NetworkConditionsCommands::Clear {
+                hosts,
+                name,
+                provider,
+            } => {
+                let testnet_deployer = TestnetDeployBuilder::default()
+                    .environment_name(&name)
+                    .provider(provider)
+                    .build()?;
+                testnet_deployer.init().await?;
+                let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+                inventory_service.setup_environment_inventory(&name)?;
+
+                testnet_deployer.clear_network_conditions(hosts.as_deref())?;
+                Ok(())
+            }
+        },
+        Commands::Notify { flush, name } => {
+            if flush {
+                SlackNotifier::from_env()?.flush(&name).await?;
+                return Ok(());
+            }
 
-            Ok(())
-        }
-        Commands::Notify { name } => {
             let inventory_path = get_data_directory()?.join(format!("{name}-inventory.json"));
             if !inventory_path.exists() {
                 return Err(eyre!("There is no inventory for the {name} testnet")
@@ -2335,6 +5349,11 @@ async fn main() -> Result<()> {
             testnet_deployer.plan(None, &inventory.get_tfvars_filename())?;
             Ok(())
         }
+        Commands::Search { query } => {
+            let matches = search_cached_inventories(&query)?;
+            print_search_matches(&query, &matches);
+            Ok(())
+        }
         Commands::Setup {} => {
             setup_dotenv_file()?;
             Ok(())
@@ -2346,6 +5365,7 @@ async fn main() -> Result<()> {
             name,
             node_type,
             provider,
+            vms,
         } => {
             let testnet_deployer = TestnetDeployBuilder::default()
                 .ansible_forks(forks)
@@ -2367,6 +5387,8 @@ async fn main() -> Result<()> {
             let custom_inventory = if let Some(custom_inventory) = custom_inventory {
                 let custom_vms = get_custom_inventory(&inventory, &custom_inventory)?;
                 Some(custom_vms)
+            } else if let Some(pattern) = vms {
+                Some(get_node_vms_matching(&inventory, &pattern)?)
             } else {
                 None
             };
@@ -2410,10 +5432,179 @@ async fn main() -> Result<()> {
 
             Ok(())
         }
+        Commands::Rpc {
+            command,
+            name,
+            provider,
+            service,
+            vm,
+        } => {
+            let testnet_deployer = TestnetDeployBuilder::default()
+                .environment_name(&name)
+                .provider(provider)
+                .build()?;
+            let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+            let inventory = inventory_service
+                .generate_or_retrieve_inventory(&name, true, None)
+                .await?;
+            if inventory.is_empty() {
+                return Err(eyre!("The {name} environment does not exist"));
+            }
+
+            let response = testnet_deployer
+                .run_node_rpc_command(&inventory, &vm, &service, &command)
+                .await?;
+            println!("{}", serde_json::to_string_pretty(&response)?);
+            Ok(())
+        }
+        Commands::Replay {
+            name,
+            provider,
+            speed,
+            trace_path,
+            uploader_vm_name,
+        } => {
+            let entries = read_replay_trace(&trace_path)?;
+            let testnet_deployer = TestnetDeployBuilder::default()
+                .environment_name(&name)
+                .provider(provider)
+                .build()?;
+            let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+            let inventory = inventory_service
+                .generate_or_retrieve_inventory(&name, true, None)
+                .await?;
+            if inventory.is_empty() {
+                return Err(eyre!("The {name} environment does not exist"));
+            }
+
+            println!("Replaying {} operations against {name}", entries.len());
+            let report = replay_trace(
+                &inventory,
+                &testnet_deployer.ssh_client,
+                &entries,
+                &ReplayOptions {
+                    speed,
+                    uploader_vm_name,
+                },
+            )
+            .await?;
+            report.print();
+            if report.failed_count() > 0 {
+                return Err(eyre!(
+                    "{} operations failed while replaying the trace",
+                    report.failed_count()
+                ));
+            }
+            Ok(())
+        }
+        Commands::SmokeTest {
+            file_size_kb,
+            local_client_path,
+            name,
+            provider,
+        } => {
+            let testnet_deployer = TestnetDeployBuilder::default()
+                .environment_name(&name)
+                .provider(provider)
+                .build()?;
+            let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+            let inventory = inventory_service
+                .generate_or_retrieve_inventory(&name, true, None)
+                .await?;
+            if inventory.is_empty() {
+                return Err(eyre!("The {name} environment does not exist"));
+            }
+
+            let report = run_smoke_test(
+                &inventory,
+                &testnet_deployer.ssh_client,
+                &SmokeTestOptions {
+                    file_size_kb,
+                    local_client_path,
+                },
+            )
+            .await?;
+            report.print();
+            if !report.hash_matched {
+                return Err(eyre!("The smoke test failed for the {name} environment"));
+            }
+            Ok(())
+        }
+        Commands::Snapshot(SnapshotCommands::Create {
+            interval,
+            leave_stopped,
+            name,
+            provider,
+        }) => {
+            let testnet_deployer = TestnetDeployBuilder::default()
+                .environment_name(&name)
+                .provider(provider)
+                .build()?;
+            let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+            let inventory = inventory_service
+                .generate_or_retrieve_inventory(&name, true, None)
+                .await?;
+            if inventory.is_empty() {
+                return Err(eyre!("The {name} environment does not exist"));
+            }
+
+            let manifest = testnet_deployer
+                .create_snapshot(
+                    &inventory,
+                    &SnapshotOptions {
+                        interval,
+                        leave_stopped,
+                    },
+                )
+                .await?;
+            manifest.print();
+            Ok(())
+        }
+        Commands::Snapshot(SnapshotCommands::List { name }) => {
+            let testnet_deployer = TestnetDeployBuilder::default()
+                .environment_name(name.as_deref().unwrap_or("snapshots"))
+                .build()?;
+            let ids = testnet_deployer.list_snapshots(name.as_deref()).await?;
+            if ids.is_empty() {
+                println!("There are no snapshots");
+            }
+            for id in ids.iter() {
+                println!("{id}");
+            }
+            Ok(())
+        }
+        Commands::Snapshot(SnapshotCommands::Restore {
+            id,
+            interval,
+            name,
+            provider,
+        }) => {
+            let testnet_deployer = TestnetDeployBuilder::default()
+                .environment_name(&name)
+                .provider(provider)
+                .build()?;
+            let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+            let inventory = inventory_service
+                .generate_or_retrieve_inventory(&name, true, None)
+                .await?;
+            if inventory.is_empty() {
+                return Err(eyre!("The {name} environment does not exist"));
+            }
+
+            let manifest = testnet_deployer
+                .restore_snapshot(&inventory, &id, interval)
+                .await?;
+            println!("Restored the snapshot below to {name}");
+            manifest.print();
+            Ok(())
+        }
         Commands::Status {
             forks,
+            max_failure_pct,
             name,
             provider,
+            publish_status,
+            slo,
         } => {
             let testnet_deployer = TestnetDeployBuilder::default()
                 .ansible_forks(forks)
@@ -2432,7 +5623,113 @@ async fn main() -> Result<()> {
                 return Err(eyre!("The {name} environment does not exist"));
             }
 
-            testnet_deployer.status()?;
+            let registries = testnet_deployer.status()?;
+            if slo {
+                let s3_repository = S3Repository {};
+                let mut uptime_history = UptimeHistory::retrieve(&s3_repository, &name).await?;
+                uptime_history
+                    .samples
+                    .push(UptimeSample::from_registries(&registries));
+                uptime_history.save(&s3_repository, &name).await?;
+                uptime_history.print_report();
+            }
+
+            let failure_pct = print_health_summary(&registries);
+            reconcile_node_counts(&registries, &inventory.environment_details).print();
+            if publish_status {
+                EnvironmentStatus::from_registries(&name, &registries)
+                    .publish(&S3Repository {})
+                    .await?;
+            }
+            if let Some(max_failure_pct) = max_failure_pct {
+                if failure_pct > max_failure_pct {
+                    return Err(eyre!(
+                        "{failure_pct:.2}% of the nodes are not running, which exceeds the maximum \
+                        of {max_failure_pct:.2}%"
+                    ));
+                }
+            }
+            Ok(())
+        }
+        Commands::Restart {
+            custom_inventory,
+            forks,
+            interval,
+            name,
+            node_type,
+            provider,
+            vm_batch_size,
+            vms,
+        } => {
+            let testnet_deployer = TestnetDeployBuilder::default()
+                .ansible_forks(forks)
+                .environment_name(&name)
+                .provider(provider)
+                .build()?;
+            let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+            let inventory = inventory_service
+                .generate_or_retrieve_inventory(&name, true, None)
+                .await?;
+            if inventory.is_empty() {
+                return Err(eyre!("The {name} environment does not exist"));
+            }
+
+            let custom_inventory = if let Some(custom_inventory) = custom_inventory {
+                let custom_vms = get_custom_inventory(&inventory, &custom_inventory)?;
+                Some(custom_vms)
+            } else if let Some(pattern) = vms {
+                Some(get_node_vms_matching(&inventory, &pattern)?)
+            } else {
+                None
+            };
+
+            testnet_deployer.restart(interval, node_type, custom_inventory, vm_batch_size)?;
+
+            Ok(())
+        }
+        Commands::RestartPolicy {
+            custom_inventory,
+            forks,
+            name,
+            node_type,
+            provider,
+            restart,
+            restart_sec,
+            start_limit_burst,
+        } => {
+            let restart_policy = RestartPolicy {
+                restart,
+                restart_sec,
+                start_limit_burst,
+            };
+            if restart_policy.is_empty() {
+                return Err(
+                    eyre!("At least one of the restart policy values must be supplied")
+                        .suggestion("Use --restart, --restart-sec or --start-limit-burst"),
+                );
+            }
+
+            let testnet_deployer = TestnetDeployBuilder::default()
+                .ansible_forks(forks)
+                .environment_name(&name)
+                .provider(provider)
+                .build()?;
+            let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+            let inventory = inventory_service
+                .generate_or_retrieve_inventory(&name, true, None)
+                .await?;
+            if inventory.is_empty() {
+                return Err(eyre!("The {name} environment does not exist"));
+            }
+
+            let custom_inventory = if let Some(custom_inventory) = custom_inventory {
+                let custom_vms = get_custom_inventory(&inventory, &custom_inventory)?;
+                Some(custom_vms)
+            } else {
+                None
+            };
+
+            testnet_deployer.apply_restart_policy(&restart_policy, node_type, custom_inventory)?;
             Ok(())
         }
         Commands::Stop {
@@ -2443,6 +5740,7 @@ async fn main() -> Result<()> {
             name,
             node_type,
             provider,
+            vms,
         } => {
             // Use a large number of forks for retrieving the inventory from a large deployment.
             // Then if a smaller number of forks is specified, we will recreate the deployer
@@ -2468,6 +5766,8 @@ async fn main() -> Result<()> {
             let custom_inventory = if let Some(custom_inventory) = custom_inventory {
                 let custom_vms = get_custom_inventory(&inventory, &custom_inventory)?;
                 Some(custom_vms)
+            } else if let Some(pattern) = vms {
+                Some(get_node_vms_matching(&inventory, &pattern)?)
             } else {
                 None
             };
@@ -2511,6 +5811,42 @@ async fn main() -> Result<()> {
 
             Ok(())
         }
+        Commands::Reap {
+            dry_run,
+            grace_period_hours,
+            provider,
+        } => {
+            let report = reap_expired_environments(&ReapOptions {
+                dry_run,
+                grace_period: Duration::from_secs(grace_period_hours * 60 * 60),
+                provider,
+            })
+            .await?;
+            report.print();
+            if !report.failed.is_empty() {
+                return Err(eyre!(
+                    "Failed to destroy {} expired environments",
+                    report.failed.len()
+                ));
+            }
+            Ok(())
+        }
+        Commands::Cost {
+            estimate,
+            name,
+            provider,
+        } => {
+            let testnet_deployer = TestnetDeployBuilder::default()
+                .environment_name(&name)
+                .provider(provider)
+                .build()?;
+            testnet_deployer.get_environment_spend().await?.print(&name);
+            if estimate {
+                testnet_deployer.terraform_runner.init()?;
+                testnet_deployer.estimate_infra_cost().await?.print();
+            }
+            Ok(())
+        }
         Commands::ConfigureSwapfile {
             name,
             provider,
@@ -2537,16 +5873,140 @@ async fn main() -> Result<()> {
                 Some(build_swapfile_extra_vars_doc(size)?),
             )?;
 
-            if peer_cache {
-                ansible_runner.run_playbook(
-                    AnsiblePlaybook::ConfigureSwapfile,
-                    AnsibleInventoryType::PeerCacheNodes,
-                    Some(build_swapfile_extra_vars_doc(size)?),
-                )?;
+            if peer_cache {
+                ansible_runner.run_playbook(
+                    AnsiblePlaybook::ConfigureSwapfile,
+                    AnsibleInventoryType::PeerCacheNodes,
+                    Some(build_swapfile_extra_vars_doc(size)?),
+                )?;
+            }
+
+            Ok(())
+        }
+        Commands::SupportBundle { name, output_dir } => {
+            let output_dir = output_dir.unwrap_or_else(|| PathBuf::from("."));
+            let path = create_support_bundle(name.as_deref(), &output_dir).await?;
+            println!("Created the support bundle at {}", path.to_string_lossy());
+            Ok(())
+        }
+        Commands::Sysstat(sysstat_cmd) => match sysstat_cmd {
+            SysstatCommands::Retrieve {
+                name,
+                output,
+                provider,
+            } => {
+                let testnet_deployer = TestnetDeployBuilder::default()
+                    .environment_name(&name)
+                    .provider(provider)
+                    .build()?;
+                testnet_deployer.init().await?;
+                let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+                inventory_service.setup_environment_inventory(&name)?;
+
+                let output =
+                    output.unwrap_or_else(|| PathBuf::from(format!("{name}-sysstat.parquet")));
+                let sample_count = testnet_deployer.retrieve_sysstat_samples(&output)?;
+                println!("Wrote {sample_count} samples to {}", output.display());
+                Ok(())
+            }
+            SysstatCommands::Start {
+                duration,
+                name,
+                provider,
+            } => {
+                let testnet_deployer = TestnetDeployBuilder::default()
+                    .environment_name(&name)
+                    .provider(provider)
+                    .build()?;
+                testnet_deployer.init().await?;
+                let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+                inventory_service.setup_environment_inventory(&name)?;
+
+                testnet_deployer.start_sysstat_collection(Duration::from_secs(duration))?;
+                Ok(())
+            }
+            SysstatCommands::Stop { name, provider } => {
+                let testnet_deployer = TestnetDeployBuilder::default()
+                    .environment_name(&name)
+                    .provider(provider)
+                    .build()?;
+                testnet_deployer.init().await?;
+                let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+                inventory_service.setup_environment_inventory(&name)?;
+
+                testnet_deployer.stop_sysstat_collection()?;
+                Ok(())
             }
+        },
+        Commands::Throttle {
+            clear,
+            name,
+            provider,
+            rate,
+            vms,
+        } => {
+            let testnet_deployer = TestnetDeployBuilder::default()
+                .environment_name(&name)
+                .provider(provider)
+                .build()?;
+            testnet_deployer.init().await?;
+            let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+            inventory_service.setup_environment_inventory(&name)?;
 
+            if clear {
+                testnet_deployer.clear_bandwidth_throttle(vms.as_deref())?;
+            } else {
+                let rate = rate.ok_or_eyre("The --rate argument must be used")?;
+                testnet_deployer.throttle_bandwidth(vms.as_deref(), &rate)?;
+            }
             Ok(())
         }
+        Commands::Trends(trends_cmd) => {
+            let bucket_name =
+                get_run_summary_bucket_name().ok_or(Error::RunSummaryBucketNameNotSupplied)?;
+            match trends_cmd {
+                TrendsCommands::Record {
+                    error_signature_count,
+                    name,
+                    upload_throughput,
+                } => {
+                    if error_signature_count.is_none() && upload_throughput.is_none() {
+                        return Err(eyre!("There are no metrics to record").suggestion(
+                            "Use the --error-signature-count or --upload-throughput arguments",
+                        ));
+                    }
+                    let summary = record_run_metrics(
+                        &S3Repository {},
+                        &bucket_name,
+                        &name,
+                        upload_throughput,
+                        error_signature_count,
+                    )
+                    .await?;
+                    println!(
+                        "Recorded the metrics against the {} run of {name}",
+                        summary.timestamp
+                    );
+                    Ok(())
+                }
+                TrendsCommands::Report {
+                    limit,
+                    release,
+                    threshold,
+                } => {
+                    let mut summaries = get_run_summaries(&S3Repository {}, &bucket_name).await?;
+                    if let Some(release) = release {
+                        summaries.retain(|s| s.release == release);
+                    }
+                    if let Some(limit) = limit {
+                        let skip = summaries.len().saturating_sub(limit);
+                        summaries.drain(..skip);
+                    }
+                    print_trend_report(&summaries, threshold);
+                    Ok(())
+                }
+            }
+        }
         Commands::Upgrade {
             ansible_verbose,
             custom_inventory,
@@ -2641,7 +6101,15 @@ async fn main() -> Result<()> {
                 None
             };
 
-            testnet_deployer.upgrade_antctl(version.parse()?, node_type, custom_inventory)?;
+            testnet_deployer.upgrade_antctl(
+                version.parse()?,
+                inventory
+                    .environment_details
+                    .architecture
+                    .unwrap_or_default(),
+                node_type,
+                custom_inventory,
+            )?;
             Ok(())
         }
         Commands::UpgradeNodeTelegrafConfig {
@@ -2697,6 +6165,151 @@ async fn main() -> Result<()> {
             Ok(())
         }
         Commands::Uploaders(uploaders_cmd) => match uploaders_cmd {
+            UploadersCommands::Backpressure {
+                clear,
+                duration,
+                interval,
+                max_disk_usage_pct,
+                max_failure_pct,
+                name,
+                provider,
+                reduced_uploaders_per_vm,
+            } => {
+                let testnet_deployer = TestnetDeployBuilder::default()
+                    .environment_name(&name)
+                    .provider(provider)
+                    .build()?;
+                let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+                let inventory = inventory_service
+                    .generate_or_retrieve_inventory(&name, true, None)
+                    .await?;
+                if inventory.uploader_vms.is_empty() {
+                    return Err(eyre!("The {name} environment does not have any uploaders"));
+                }
+
+                if clear {
+                    testnet_deployer.set_uploader_concurrency(None)?;
+                    println!("Removed the limit on the uploaders");
+                    return Ok(());
+                }
+                testnet_deployer.run_upload_backpressure(&UploadBackpressureOptions {
+                    duration: duration.map(|mins| Duration::from_secs(mins * 60)),
+                    interval: Duration::from_secs(interval),
+                    max_disk_usage_pct,
+                    max_failure_pct,
+                    reduced_uploaders_per_vm,
+                })?;
+                Ok(())
+            }
+            UploadersCommands::Deploy {
+                autonomi_version,
+                funding_wallet_secret_key,
+                name,
+                provider,
+                regions,
+                uploads_per_vm,
+                vm_count,
+            } => {
+                if vm_count == 0 || uploads_per_vm == 0 {
+                    return Err(eyre!(
+                        "The VM count and the uploads per VM must be greater than zero"
+                    ));
+                }
+                let autonomi_version =
+                    get_version_from_option(autonomi_version, &ReleaseType::Ant).await?;
+
+                let testnet_deployer = TestnetDeployBuilder::default()
+                    .environment_name(&name)
+                    .provider(provider)
+                    .build()?;
+                testnet_deployer.init().await?;
+
+                let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+                let mut inventory = inventory_service
+                    .generate_or_retrieve_inventory(&name, true, None)
+                    .await?;
+                if inventory.is_empty() {
+                    return Err(eyre!("The '{}' environment does not exist", name));
+                }
+                if !inventory.uploader_vms.is_empty() {
+                    return Err(eyre!(
+                        "The '{name}' environment already has {} uploader VMs",
+                        inventory.uploader_vms.len()
+                    )
+                    .suggestion("Use the 'uploaders upscale' command to add more uploaders"));
+                }
+
+                if let Some(regions) = regions {
+                    // The regions are recorded so they are used when the infrastructure is
+                    // updated and when the latencies are reported.
+                    inventory.environment_details.uploader_regions = Some(regions);
+                    write_environment_details(
+                        &testnet_deployer.s3_repository,
+                        &name,
+                        &inventory.environment_details,
+                    )
+                    .await?;
+                }
+
+                println!(
+                    "Deploying {vm_count} uploader VMs with {uploads_per_vm} uploaders each..."
+                );
+                testnet_deployer
+                    .upscale_uploaders(&UpscaleOptions {
+                        ansible_verbose: false,
+                        current_inventory: inventory,
+                        desired_auditor_vm_count: None,
+                        desired_node_count: None,
+                        desired_node_vm_count: None,
+                        desired_peer_cache_node_count: None,
+                        desired_peer_cache_node_vm_count: None,
+                        desired_private_node_count: None,
+                        desired_private_node_vm_count: None,
+                        desired_uploader_vm_count: Some(vm_count),
+                        desired_uploaders_count: Some(uploads_per_vm),
+                        downloaders_count: 0,
+                        funding_wallet_secret_key,
+                        gas_amount: None,
+                        max_archived_log_files: 1,
+                        max_log_files: 1,
+                        infra_only: false,
+                        interval: Duration::from_millis(2000),
+                        plan: false,
+                        provision_only: false,
+                        provision_parallelism: None,
+                        provision_retries: 0,
+                        public_rpc: false,
+                        rpc_proxy_acme_email: None,
+                        safe_version: Some(autonomi_version.to_string()),
+                    })
+                    .await?;
+
+                let inventory = inventory_service
+                    .generate_or_retrieve_inventory(&name, true, None)
+                    .await?;
+                inventory.print_report(false)?;
+                inventory.save()?;
+
+                Ok(())
+            }
+            UploadersCommands::LatencyReport { name, provider } => {
+                let testnet_deployer = TestnetDeployBuilder::default()
+                    .environment_name(&name)
+                    .provider(provider)
+                    .build()?;
+                let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+                let inventory = inventory_service
+                    .generate_or_retrieve_inventory(&name, true, None)
+                    .await?;
+                if inventory.uploader_vms.is_empty() {
+                    return Err(eyre!("The '{name}' environment has no uploader VMs"));
+                }
+
+                let report =
+                    testnet_deployer.get_client_latency_report(&inventory.environment_details)?;
+                report.print();
+                Ok(())
+            }
             UploadersCommands::Start { name, provider } => {
                 let testnet_deployer = TestnetDeployBuilder::default()
                     .environment_name(&name)
@@ -2757,6 +6370,14 @@ async fn main() -> Result<()> {
                 let mut extra_vars = ExtraVarsDocBuilder::default();
                 extra_vars.add_variable("testnet_name", &name);
                 extra_vars.add_variable("ant_version", &version.to_string());
+                extra_vars.add_variable(
+                    "target_triple",
+                    inventory
+                        .environment_details
+                        .architecture
+                        .unwrap_or_default()
+                        .target_triple(),
+                );
                 ansible_runner.run_playbook(
                     AnsiblePlaybook::UpgradeUploaders,
                     AnsibleInventoryType::Uploaders,
@@ -2826,7 +6447,10 @@ async fn main() -> Result<()> {
                         interval: Duration::from_millis(2000),
                         plan,
                         provision_only,
+                        provision_parallelism: None,
+                        provision_retries: 0,
                         public_rpc: false,
+                        rpc_proxy_acme_email: None,
                         safe_version: Some(autonomi_version),
                     })
                     .await?;
@@ -2885,7 +6509,10 @@ async fn main() -> Result<()> {
             max_log_files,
             plan,
             provider,
+            provision_parallelism,
+            provision_retries,
             public_rpc,
+            rpc_tls_email,
             safe_version,
             antnode_version,
             antnode_manager_version,
@@ -2936,6 +6563,11 @@ async fn main() -> Result<()> {
                             "Cannot override versions when the deployment uses BuildFromSource"
                         ));
                     }
+                    BinaryOption::Local { .. } => {
+                        return Err(eyre!(
+                            "Cannot override versions when the deployment uses local binaries"
+                        ));
+                    }
                 }
             }
 
@@ -2961,7 +6593,10 @@ async fn main() -> Result<()> {
                     infra_only,
                     plan,
                     provision_only: false,
+                    provision_parallelism,
+                    provision_retries,
                     public_rpc,
+                    rpc_proxy_acme_email: rpc_tls_email,
                     safe_version,
                 })
                 .await?;
@@ -2999,6 +6634,43 @@ async fn main() -> Result<()> {
 
             Ok(())
         }
+        Commands::UpdateEnv {
+            custom_inventory,
+            env_variables,
+            forks,
+            interval,
+            name,
+            node_type,
+            provider,
+        } => {
+            let testnet_deployer = TestnetDeployBuilder::default()
+                .ansible_forks(forks)
+                .environment_name(&name)
+                .provider(provider)
+                .build()?;
+            let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+            let inventory = inventory_service
+                .generate_or_retrieve_inventory(&name, true, None)
+                .await?;
+            if inventory.is_empty() {
+                return Err(eyre!("The {name} environment does not exist"));
+            }
+
+            let custom_inventory = if let Some(custom_inventory) = custom_inventory {
+                let custom_vms = get_custom_inventory(&inventory, &custom_inventory)?;
+                Some(custom_vms)
+            } else {
+                None
+            };
+
+            testnet_deployer.update_node_env(
+                env_variables,
+                interval,
+                node_type,
+                custom_inventory,
+            )?;
+            Ok(())
+        }
         Commands::UpdatePeer {
             custom_inventory,
             name,
@@ -3146,7 +6818,250 @@ async fn main() -> Result<()> {
             }
             Ok(())
         }
+        Commands::WireGuard(wireguard_cmd) => match wireguard_cmd {
+            WireGuardCommands::Setup { name, provider } => {
+                let testnet_deployer = TestnetDeployBuilder::default()
+                    .environment_name(&name)
+                    .provider(provider)
+                    .build()?;
+                testnet_deployer.init().await?;
+                let inventory_service = DeploymentInventoryService::from(&testnet_deployer);
+                inventory_service.setup_environment_inventory(&name)?;
+
+                let config_path = testnet_deployer.setup_wireguard()?;
+                println!(
+                    "Bring the overlay up on this machine with: sudo wg-quick up {}",
+                    config_path.display()
+                );
+                println!("Then use --over-wireguard to manage the VMs over the overlay, and 'firewall apply --ssh-over-wireguard true' to close SSH on the public IPs.");
+                Ok(())
+            }
+        },
+    }
+}
+
+/// Commands that change the state of an environment, which must hold the environment lock.
+fn is_operational_command(command: &Commands) -> bool {
+    matches!(
+        command,
+        Commands::Bootstrap { .. }
+            | Commands::Chaos(_)
+            | Commands::Clean { .. }
+            | Commands::ConfigureSwapfile { .. }
+            | Commands::Deploy { .. }
+            | Commands::Downloaders(_)
+            | Commands::Downscale { .. }
+            | Commands::ExtendVolumeSize { .. }
+            | Commands::Faucet(FaucetCommands::Fund { .. })
+            | Commands::Faucet(FaucetCommands::FundUploaders { .. })
+            | Commands::Faucet(FaucetCommands::Start { .. })
+            | Commands::Faucet(FaucetCommands::Stop { .. })
+            | Commands::Firewall(FirewallCommands::Apply { .. })
+            | Commands::Funds(_)
+            | Commands::Genesis(_)
+            | Commands::Logs(LogCommands::Cleanup { .. })
+            | Commands::Logs(LogCommands::Rm { .. })
+            | Commands::Logstash(_)
+            | Commands::Monitoring(_)
+            | Commands::NatGateway(_)
+            | Commands::Network(_)
+            | Commands::NetworkConditions(_)
+            | Commands::ResetToNNodes { .. }
+            | Commands::Restart { .. }
+            | Commands::RestartPolicy { .. }
+            | Commands::Snapshot(SnapshotCommands::Create { .. })
+            | Commands::Snapshot(SnapshotCommands::Restore { .. })
+            | Commands::Start { .. }
+            | Commands::StartTelegraf { .. }
+            | Commands::Stop { .. }
+            | Commands::StopTelegraf { .. }
+            | Commands::Sysstat(_)
+            | Commands::Throttle { .. }
+            | Commands::UpdateEnv { .. }
+            | Commands::UpdatePeer { .. }
+            | Commands::Upgrade { .. }
+            | Commands::UpgradeAntctl { .. }
+            | Commands::UpgradeNodeTelegrafConfig { .. }
+            | Commands::UpgradeUploaderTelegrafConfig { .. }
+            | Commands::Uploaders(_)
+            | Commands::Upscale { .. }
+            | Commands::WireGuard(_)
+    )
+}
+
+/// Post the outcome of a command to Slack.
+///
+/// The error is not included in the message, because it can contain secrets.
+async fn notify_command_result(
+    name: &str,
+    command: &str,
+    result: &Result<()>,
+    elapsed: Duration,
+) -> Result<()> {
+    let event = match result {
+        Ok(()) => NotificationEvent::new(
+            name,
+            EventSeverity::Info,
+            format!(
+                "'{command}' succeeded against {name} in {}s",
+                elapsed.as_secs()
+            ),
+        ),
+        Err(_) => NotificationEvent::new(
+            name,
+            EventSeverity::Failure,
+            format!(
+                ":x: '{command}' failed against {name} after {}s. See the run log for details.",
+                elapsed.as_secs()
+            ),
+        ),
+    };
+    SlackNotifier::from_env()?.notify(event).await?;
+    Ok(())
+}
+
+/// Remove the environment lock and the Terraform state lock for an environment.
+async fn force_unlock(args: &[String], name: &str) -> Result<()> {
+    let s3_repository = S3Repository {};
+    match EnvironmentLock::force_unlock(&s3_repository, name).await? {
+        Some(holder) => println!("Removed the lock for {name} held by {holder}"),
+        None => println!("The {name} environment was not locked"),
+    }
+
+    let provider = match get_arg_value(args, "--provider", None) {
+        Some(provider) => parse_provider(&provider)?,
+        None => CloudProvider::DigitalOcean,
+    };
+    let testnet_deployer = TestnetDeployBuilder::default()
+        .environment_name(name)
+        .provider(provider)
+        .build()?;
+    testnet_deployer
+        .terraform_runner
+        .force_unlock_state(&s3_repository, name)
+        .await?;
+    println!("Removed the Terraform state lock for {name}");
+    Ok(())
+}
+
+/// Describe the command being run by its subcommand names, e.g., 'network churn fixed-interval'.
+///
+/// The arguments are left out because they can contain secrets.
+fn describe_command(args: &[String]) -> String {
+    args.iter()
+        .skip(1)
+        .take_while(|arg| !arg.starts_with('-'))
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Get the value of the environment name argument from the raw command line arguments.
+fn get_name_arg(args: &[String]) -> Option<String> {
+    get_arg_value(args, "--name", Some("-n"))
+}
+
+/// Get the value of an argument from the raw command line arguments, before they are parsed.
+fn get_arg_value(args: &[String], long: &str, short: Option<&str>) -> Option<String> {
+    args.iter().enumerate().find_map(|(i, arg)| {
+        if let Some(value) = arg.strip_prefix(&format!("{long}=")) {
+            return Some(value.to_string());
+        }
+        if arg == long || Some(arg.as_str()) == short {
+            return args.get(i + 1).cloned();
+        }
+        None
+    })
+}
+
+fn is_missing_name_error(err: &clap::Error) -> bool {
+    err.kind() == clap::error::ErrorKind::MissingRequiredArgument
+        && err.to_string().contains("--name")
+}
+
+/// Prompt for an environment from those that have a cached inventory.
+///
+/// This is used when a command requiring an environment name is run without one, to avoid having
+/// to remember or type the name, which is how commands end up running against the wrong testnet.
+fn pick_environment() -> Result<String> {
+    let environments = get_cached_environment_names()?;
+    if environments.is_empty() {
+        return Err(eyre!("No environment name was supplied").suggestion(
+            "There are no cached inventories to choose from. Use the --name argument.",
+        ));
+    }
+    let name = Select::new("Select an environment:", environments)
+        .with_help_message("Environments are listed with the most recently used first")
+        .prompt()?;
+    Ok(name)
+}
+
+/// The role is read from the `TESTNET_DEPLOY_ROLE` variable, which would typically be set in the
+/// `.env` file distributed with the observer credentials. Any value other than `observer` allows all
+/// commands.
+fn is_observer() -> bool {
+    env::var(ROLE_ENV_VAR)
+        .map(|role| role.eq_ignore_ascii_case("observer"))
+        .unwrap_or(false)
+}
+
+/// Users in the observer role can only run the commands that don't change an environment.
+///
+/// Commands that are otherwise read-only are refused if they are used with an argument that
+/// writes to S3, like `status --slo`, as are the global arguments that remove locks or post to
+/// Slack.
+fn ensure_command_permitted(opt: &Opt) -> Result<()> {
+    if !is_observer() {
+        return Ok(());
+    }
+
+    if opt.force_unlock || opt.notify {
+        return Err(
+            eyre!("This argument is not permitted in the observer role").suggestion(format!(
+                "The {ROLE_ENV_VAR} variable is set to 'observer', so --force-unlock and --notify \
+                cannot be used."
+            )),
+        );
+    }
+
+    let is_read_only = matches!(
+        &opt.command,
+        Commands::Cost { .. }
+            | Commands::Inventory {
+                network_contacts_bucket: None,
+                network_contacts_file_name: None,
+                ..
+            }
+            | Commands::Plan { .. }
+            | Commands::Search { .. }
+            | Commands::Snapshot(SnapshotCommands::List { .. })
+            | Commands::Status {
+                publish_status: false,
+                slo: false,
+                ..
+            }
+            | Commands::SupportBundle { .. }
+            | Commands::Trends(TrendsCommands::Report { .. })
+            | Commands::Logs(
+                LogCommands::Copy { .. }
+                    | LogCommands::Get { .. }
+                    | LogCommands::Reassemble { .. }
+                    | LogCommands::Rg { .. }
+                    | LogCommands::Rsync { .. }
+            )
+    );
+    if !is_read_only {
+        return Err(
+            eyre!("This command is not permitted in the observer role").suggestion(format!(
+                "The {ROLE_ENV_VAR} variable is set to 'observer'. Only the 'cost', 'inventory', \
+                'plan', 'search', 'snapshot list', 'status', 'support-bundle', 'trends report' and \
+                read-only 'logs' commands can be used, and 'inventory' and 'status' cannot be used \
+                with the arguments that publish to S3. The role does not replace read-only \
+                credentials."
+            )),
+        );
     }
+    Ok(())
 }
 
 /// Get the binary option for the deployment.
@@ -3248,6 +7163,23 @@ fn print_with_banner(s: &str) {
     println!("{}\n{}\n{}", banner, s, banner);
 }
 
+/// The nodes cannot dial the bootstrap peer without its peer ID, so the multiaddr must include it.
+fn parse_bootstrap_peer(val: &str) -> Result<String> {
+    let addr: Multiaddr = val
+        .parse()
+        .map_err(|err| eyre!("'{val}' is not a valid multiaddr: {err}"))?;
+    if !addr
+        .iter()
+        .any(|protocol| matches!(protocol, Protocol::P2p(_)))
+    {
+        return Err(eyre!(
+            "The bootstrap peer must include the peer ID, e.g., \
+            /ip4/<ip>/udp/<port>/quic-v1/p2p/<peer id>"
+        ));
+    }
+    Ok(val.to_string())
+}
+
 pub fn parse_provider(val: &str) -> Result<CloudProvider> {
     match val {
         "aws" => Ok(CloudProvider::Aws),
@@ -3277,7 +7209,19 @@ fn parse_environment_variables(env_var: &str) -> Result<(String, String)> {
             "Environment variable must be in the format KEY=VALUE or KEY=INNER_KEY=VALUE.\nMultiple key-value pairs can be given with a comma between them."
         ));
     }
-    Ok((parts[0].to_string(), parts[1].to_string()))
+    let (name, value) = (parts[0].to_string(), parts[1].to_string());
+    validate_env_variable(&name, &value).map_err(|err| {
+        eyre!(err).suggestion(
+            "Multiple variables are separated by commas, so values cannot contain a comma",
+        )
+    })?;
+    if !is_known_node_env_variable(&name) {
+        println!(
+            "WARNING: '{name}' is not a known antnode environment variable. It will still be set."
+        );
+    }
+    redact::register_env_variable(&name, &value);
+    Ok((name, value))
 }
 
 async fn get_version_from_option(
@@ -3324,6 +7268,54 @@ fn get_custom_inventory(
     Ok(custom_vms)
 }
 
+/// Get the node VMs whose names match a regular expression.
+///
+/// The custom inventory addresses each VM by its public IP, so the private node VMs can't be
+/// selected this way.
+fn get_node_vms_matching(
+    inventory: &DeploymentInventory,
+    pattern: &str,
+) -> Result<Vec<VirtualMachine>> {
+    let regex = Regex::new(pattern)?;
+    let vms = inventory
+        .peer_cache_node_vms
+        .iter()
+        .chain(inventory.genesis_vm.iter())
+        .chain(inventory.node_vms.iter())
+        .map(|node_vm| node_vm.vm.clone())
+        .filter(|vm| regex.is_match(&vm.name))
+        .collect::<Vec<_>>();
+    if vms.is_empty() {
+        return Err(eyre!("No node VMs in the inventory match '{pattern}'"));
+    }
+
+    debug!("Node VMs matching '{pattern}':");
+    for vm in &vms {
+        debug!("  {} - {}", vm.name, vm.public_ip_addr);
+    }
+    Ok(vms)
+}
+
+/// Resolve a list of peers, given as VM names or IP addresses, to the public IP addresses.
+fn get_peer_ips(inventory: &DeploymentInventory, peers: &[String]) -> Result<Vec<String>> {
+    let vm_list = inventory.vm_list();
+    peers
+        .iter()
+        .map(|peer| {
+            if let Ok(ip) = peer.parse::<IpAddr>() {
+                return Ok(ip.to_string());
+            }
+            vm_list
+                .iter()
+                .find(|vm| &vm.name == peer)
+                .map(|vm| vm.public_ip_addr.to_string())
+                .ok_or_eyre(format!(
+                    "{peer} is not an IP address or a VM in the inventory for this environment"
+                ))
+        })
+        .collect()
+}
+
 fn build_fund_faucet_extra_vars_doc(
     genesis_ip: &IpAddr,
     genesis_multiaddr: &str,
@@ -3340,6 +7332,59 @@ fn build_swapfile_extra_vars_doc(size: u16) -> Result<String> {
     Ok(extra_vars.build())
 }
 
+fn parse_host_entry(val: &str) -> Result<(String, IpAddr)> {
+    let (hostname, ip) = val
+        .split_once('=')
+        .ok_or_else(|| eyre!("A host entry must be in the format HOSTNAME=IP"))?;
+    if hostname.is_empty() || hostname.contains(char::is_whitespace) {
+        return Err(eyre!("The hostname '{hostname}' is invalid"));
+    }
+    let ip = ip
+        .parse::<IpAddr>()
+        .map_err(|_| eyre!("The IP address '{ip}' for '{hostname}' is invalid"))?;
+    Ok((hostname.to_string(), ip))
+}
+
+/// Parse a TTL such as '30m', '48h' or '7d'.
+fn parse_ttl(val: &str) -> Result<Duration> {
+    let invalid = || eyre!("The TTL must be a number followed by 'm', 'h' or 'd', e.g., 48h");
+    if !val.is_ascii() {
+        return Err(invalid());
+    }
+    let (count, unit) = val.split_at(val.len().saturating_sub(1));
+    let count = count.parse::<u64>().map_err(|_| invalid())?;
+    let secs = match unit {
+        "m" => count * 60,
+        "h" => count * 60 * 60,
+        "d" => count * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    if secs == 0 {
+        return Err(eyre!("The TTL must be greater than zero"));
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+fn parse_timestamp(val: &str) -> Result<DateTime<Utc>> {
+    Ok(DateTime::parse_from_rfc3339(val)
+        .map_err(|_| eyre!("The time must be in RFC 3339 format, e.g., 2024-11-20T14:30:00Z"))?
+        .with_timezone(&Utc))
+}
+
+/// Split the node VMs into generic and private node VMs, placing the given percentage of them
+/// behind the NAT gateway.
+fn split_node_vms(node_vm_count: u16, private_percent: u8) -> (Option<u16>, Option<u16>) {
+    let mut private_node_vm_count =
+        (node_vm_count as f64 * private_percent as f64 / 100.0).round() as u16;
+    if private_percent > 0 && node_vm_count > 0 {
+        private_node_vm_count = private_node_vm_count.max(1);
+    }
+    (
+        Some(node_vm_count - private_node_vm_count),
+        Some(private_node_vm_count),
+    )
+}
+
 fn parse_chunk_size(val: &str) -> Result<u64> {
     let size = val.parse::<u64>()?;
     if size == 0 {
@@ -0,0 +1,205 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    error::{Error, Result},
+    run_log::{self, CommandRunRecord},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tracing::debug;
+
+/// If set, the metrics are written to this file, for collection by the node exporter's textfile
+/// collector.
+pub const METRICS_FILE_ENV_VAR: &str = "TESTNET_DEPLOY_METRICS_FILE";
+/// If set, the metrics are pushed to the Prometheus Pushgateway at this URL.
+pub const PUSHGATEWAY_URL_ENV_VAR: &str = "TESTNET_DEPLOY_PUSHGATEWAY_URL";
+
+/// Counters that accumulate across runs of the deployer on this machine.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct MetricsState {
+    /// The number of failed external commands, keyed by the binary, e.g., 'terraform'.
+    failures_by_class: BTreeMap<String, u64>,
+    /// The number of runs, keyed by the command and then the result.
+    runs: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+impl MetricsState {
+    fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let data = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Record the outcome of a run and export the deployer's metrics.
+///
+/// Nothing is done unless either of the metrics file or Pushgateway variables are set.
+pub async fn export_metrics(
+    command: &str,
+    succeeded: bool,
+    run_duration: Duration,
+    active_environments: usize,
+) -> Result<()> {
+    let metrics_file = std::env::var(METRICS_FILE_ENV_VAR).ok();
+    let pushgateway_url = std::env::var(PUSHGATEWAY_URL_ENV_VAR).ok();
+    if metrics_file.is_none() && pushgateway_url.is_none() {
+        return Ok(());
+    }
+
+    let records = run_log::get_records();
+    let state_path = get_state_path()?;
+    let mut state = MetricsState::load(&state_path)?;
+    let result = if succeeded { "success" } else { "failure" };
+    *state
+        .runs
+        .entry(command.to_string())
+        .or_default()
+        .entry(result.to_string())
+        .or_default() += 1;
+    for record in records.iter().filter(|r| !r.succeeded()) {
+        *state
+            .failures_by_class
+            .entry(get_binary_name(record))
+            .or_default() += 1;
+    }
+    state.save(&state_path)?;
+
+    let text = render(&state, command, run_duration, &records, active_environments);
+    if let Some(path) = metrics_file {
+        // Write then rename, so the collector never reads a partially written file.
+        let temp_path = format!("{path}.tmp");
+        std::fs::write(&temp_path, &text)?;
+        std::fs::rename(&temp_path, &path)?;
+        debug!("Wrote metrics to {path}");
+    }
+    if let Some(url) = pushgateway_url {
+        let url = format!("{}/metrics/job/testnet_deploy", url.trim_end_matches('/'));
+        let response = reqwest::Client::new().put(&url).body(text).send().await?;
+        if !response.status().is_success() {
+            return Err(Error::MetricsPushFailed(response.status().to_string()));
+        }
+        debug!("Pushed metrics to {url}");
+    }
+    Ok(())
+}
+
+fn render(
+    state: &MetricsState,
+    command: &str,
+    run_duration: Duration,
+    records: &[CommandRunRecord],
+    active_environments: usize,
+) -> String {
+    let mut text = String::new();
+
+    let _ = writeln!(
+        text,
+        "# HELP testnet_deploy_runs_total The number of runs of each command, by result."
+    );
+    let _ = writeln!(text, "# TYPE testnet_deploy_runs_total counter");
+    for (command, results) in state.runs.iter() {
+        for (result, count) in results.iter() {
+            let _ = writeln!(
+                text,
+                "testnet_deploy_runs_total{{command=\"{command}\",result=\"{result}\"}} {count}"
+            );
+        }
+    }
+
+    let _ = writeln!(
+        text,
+        "# HELP testnet_deploy_failures_total The number of failed external commands, by binary."
+    );
+    let _ = writeln!(text, "# TYPE testnet_deploy_failures_total counter");
+    for (class, count) in state.failures_by_class.iter() {
+        let _ = writeln!(
+            text,
+            "testnet_deploy_failures_total{{class=\"{class}\"}} {count}"
+        );
+    }
+
+    let _ = writeln!(
+        text,
+        "# HELP testnet_deploy_last_run_duration_seconds The duration of the last run of a command."
+    );
+    let _ = writeln!(
+        text,
+        "# TYPE testnet_deploy_last_run_duration_seconds gauge"
+    );
+    let _ = writeln!(
+        text,
+        "testnet_deploy_last_run_duration_seconds{{command=\"{command}\"}} {}",
+        run_duration.as_secs_f64()
+    );
+
+    let _ = writeln!(
+        text,
+        "# HELP testnet_deploy_stage_duration_seconds The duration of each stage of the last run."
+    );
+    let _ = writeln!(text, "# TYPE testnet_deploy_stage_duration_seconds gauge");
+    // Only the Terraform and Ansible runs are treated as stages. Other commands, like SSH, are
+    // per host and would produce too many series.
+    let mut stage_durations: BTreeMap<String, f64> = BTreeMap::new();
+    for record in records.iter().filter(|r| {
+        matches!(
+            get_binary_name(r).as_str(),
+            "ansible-playbook" | "terraform" | "tofu"
+        )
+    }) {
+        *stage_durations
+            .entry(record.description.replace('"', "'"))
+            .or_default() += record.duration.as_secs_f64();
+    }
+    for (stage, seconds) in stage_durations.iter() {
+        let _ = writeln!(
+            text,
+            "testnet_deploy_stage_duration_seconds{{command=\"{command}\",stage=\"{stage}\"}} {seconds}"
+        );
+    }
+
+    let _ = writeln!(
+        text,
+        "# HELP testnet_deploy_active_environments The number of environments known to this machine."
+    );
+    let _ = writeln!(text, "# TYPE testnet_deploy_active_environments gauge");
+    let _ = writeln!(
+        text,
+        "testnet_deploy_active_environments {active_environments}"
+    );
+
+    text
+}
+
+fn get_binary_name(record: &CommandRunRecord) -> String {
+    Path::new(&record.binary)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| record.binary.clone())
+}
+
+fn get_state_path() -> Result<PathBuf> {
+    let path = dirs_next::data_dir()
+        .ok_or_else(|| Error::CouldNotRetrieveDataDirectory)?
+        .join("safe")
+        .join("testnet-deploy");
+    if !path.exists() {
+        std::fs::create_dir_all(&path)?;
+    }
+    Ok(path.join("metrics.json"))
+}
@@ -0,0 +1,392 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    ansible::{
+        extra_vars::{self, ExtraVarsDocBuilder},
+        inventory::AnsibleInventoryType,
+        AnsiblePlaybook,
+    },
+    error::{Error, Result},
+    get_environment_details,
+    inventory::VirtualMachine,
+    write_environment_details, NatType, TestnetDeployer,
+};
+use serde::Serialize;
+use std::{collections::BTreeMap, net::IpAddr};
+use tracing::debug;
+
+/// The ports from here up are divided between the private node VMs for a full cone NAT. The
+/// gateway's own ephemeral ports are below this.
+const FULL_CONE_PORT_RANGE_START: u16 = 20000;
+const FULL_CONE_PORT_RANGE_END: u16 = 65000;
+const MAX_FULL_CONE_PORTS_PER_VM: u16 = 1000;
+/// Each node on a VM uses a port, so a VM needs at least this many.
+const MIN_FULL_CONE_PORTS_PER_VM: u16 = 100;
+
+/// A NAT gateway along with the private node VMs that are routed through it.
+#[derive(Clone, Debug)]
+pub struct NatGatewayShard {
+    pub gateway: VirtualMachine,
+    pub private_node_vms: Vec<VirtualMachine>,
+}
+
+impl NatGatewayShard {
+    pub fn get_private_ips(&self) -> Vec<String> {
+        self.private_node_vms
+            .iter()
+            .map(|vm| vm.private_ip_addr.to_string())
+            .collect()
+    }
+}
+
+/// The local ports a private node VM uses, which the gateway forwards to it for a full cone NAT.
+#[derive(Clone, Debug, Serialize)]
+pub struct NatPortRange {
+    pub private_ip: IpAddr,
+    pub start: u16,
+    pub end: u16,
+}
+
+/// Give each private node VM its own range of local ports.
+///
+/// A gateway cannot tell which private node an unsolicited packet is for from the port alone,
+/// unless the nodes use distinct ports. The nodes bind to ephemeral ports, so restricting the
+/// ephemeral range on each VM to its own range, with the source ports preserved by the gateway,
+/// lets the gateway forward any packet arriving on a port to the VM that owns it.
+///
+/// The ranges are assigned over all the private node VMs, sorted by name, so they are distinct
+/// whichever gateway a VM is routed through, including after a failover.
+pub fn get_full_cone_port_ranges(private_node_vms: &[VirtualMachine]) -> Result<Vec<NatPortRange>> {
+    let mut vms = private_node_vms.to_vec();
+    vms.sort_by(|a, b| a.name.cmp(&b.name));
+    if vms.is_empty() {
+        return Ok(Vec::new());
+    }
+    let ports_per_vm = ((FULL_CONE_PORT_RANGE_END - FULL_CONE_PORT_RANGE_START) as usize
+        / vms.len())
+    .min(MAX_FULL_CONE_PORTS_PER_VM as usize) as u16;
+    if ports_per_vm < MIN_FULL_CONE_PORTS_PER_VM {
+        return Err(Error::TooManyVmsForFullConeNat(vms.len()));
+    }
+    Ok(vms
+        .iter()
+        .enumerate()
+        .map(|(i, vm)| {
+            let start = FULL_CONE_PORT_RANGE_START + i as u16 * ports_per_vm;
+            NatPortRange {
+                private_ip: vm.private_ip_addr,
+                start,
+                end: start + ports_per_vm - 1,
+            }
+        })
+        .collect())
+}
+
+/// Split the private node VMs between the NAT gateways.
+///
+/// A VM stays on the gateway recorded for it in `assignment`, if that gateway still exists. The
+/// other VMs, e.g., those added by an upscale, are assigned in name order to the gateway with the
+/// fewest VMs, so with no assignment the VMs are spread over the gateways in turn. A gateway with
+/// no VMs assigned still has a shard, so it can be used as the target of a failover.
+pub fn shard_private_node_vms(
+    mut gateways: Vec<VirtualMachine>,
+    mut private_node_vms: Vec<VirtualMachine>,
+    assignment: &BTreeMap<String, String>,
+) -> Result<Vec<NatGatewayShard>> {
+    if gateways.is_empty() {
+        return Err(Error::EmptyInventory(AnsibleInventoryType::NatGateway));
+    }
+    gateways.sort_by(|a, b| a.name.cmp(&b.name));
+    private_node_vms.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut shards = gateways
+        .into_iter()
+        .map(|gateway| NatGatewayShard {
+            gateway,
+            private_node_vms: Vec::new(),
+        })
+        .collect::<Vec<_>>();
+    let mut unassigned_vms = Vec::new();
+    for vm in private_node_vms {
+        let shard = assignment.get(&vm.name).and_then(|gateway_name| {
+            shards
+                .iter_mut()
+                .find(|shard| &shard.gateway.name == gateway_name)
+        });
+        match shard {
+            Some(shard) => shard.private_node_vms.push(vm),
+            None => unassigned_vms.push(vm),
+        }
+    }
+    for vm in unassigned_vms {
+        if let Some(shard) = shards
+            .iter_mut()
+            .min_by_key(|shard| shard.private_node_vms.len())
+        {
+            shard.private_node_vms.push(vm);
+        }
+    }
+    Ok(shards)
+}
+
+/// Get the name of the gateway each private node VM is routed through, keyed by the VM name.
+pub fn get_shard_assignment(shards: &[NatGatewayShard]) -> BTreeMap<String, String> {
+    shards
+        .iter()
+        .flat_map(|shard| {
+            shard
+                .private_node_vms
+                .iter()
+                .map(|vm| (vm.name.clone(), shard.gateway.name.clone()))
+        })
+        .collect()
+}
+
+pub fn print_shards(shards: &[NatGatewayShard]) {
+    for (i, shard) in shards.iter().enumerate() {
+        println!(
+            "Shard {} via {} ({}): {} private node VMs",
+            i + 1,
+            shard.gateway.name,
+            shard.gateway.public_ip_addr,
+            shard.private_node_vms.len()
+        );
+        for vm in shard.private_node_vms.iter() {
+            println!("  {}: {}", vm.name, vm.private_ip_addr);
+        }
+    }
+}
+
+impl TestnetDeployer {
+    /// Get the NAT gateway shards for the environment, using the assignment recorded in the
+    /// environment details, which includes any failover.
+    ///
+    /// Private node VMs without a recorded gateway are assigned one, but the assignment is only
+    /// recorded by `record_nat_gateway_shards`.
+    pub async fn get_nat_gateway_shards(&self) -> Result<Vec<NatGatewayShard>> {
+        let environment_details =
+            get_environment_details(&self.environment_name, &self.s3_repository).await?;
+        let ansible_runner = &self.ansible_provisioner.ansible_runner;
+        shard_private_node_vms(
+            ansible_runner.get_inventory(AnsibleInventoryType::NatGateway, true)?,
+            ansible_runner.get_inventory(AnsibleInventoryType::PrivateNodes, true)?,
+            &environment_details.nat_gateway_shards.unwrap_or_default(),
+        )
+    }
+
+    /// Record the gateway each private node VM is routed through in the environment details.
+    pub async fn record_nat_gateway_shards(&self, shards: &[NatGatewayShard]) -> Result<()> {
+        let mut environment_details =
+            get_environment_details(&self.environment_name, &self.s3_repository).await?;
+        environment_details.nat_gateway_shards = Some(get_shard_assignment(shards));
+        write_environment_details(
+            &self.s3_repository,
+            &self.environment_name,
+            &environment_details,
+        )
+        .await
+    }
+
+    /// Route a shard of private node VMs through another NAT gateway.
+    ///
+    /// This simulates the upstream router of a home node being replaced. The new gateway is
+    /// configured to masquerade the shard's traffic, then the default route on each VM in the shard
+    /// is changed to point at it. The nodes keep running throughout, so their behaviour as their
+    /// external address changes can be observed.
+    ///
+    /// If a target gateway is not specified, the first other gateway that is healthy is used. The
+    /// first gateway is also the SSH jump host for all the private node VMs, so its forwarding,
+    /// rather than SSH, is what should be broken to simulate a failure.
+    ///
+    /// The new assignment is recorded, so it is kept when the private nodes are provisioned again.
+    pub async fn failover_nat_gateway(
+        &self,
+        shard_number: usize,
+        target_gateway_name: Option<&str>,
+        nat_type: NatType,
+        enable_upnp: bool,
+    ) -> Result<NatGatewayShard> {
+        let mut shards = self.get_nat_gateway_shards().await?;
+        print_shards(&shards);
+        if shard_number == 0 || shard_number > shards.len() {
+            return Err(Error::InvalidNatGatewayShard(shard_number, shards.len()));
+        }
+        let shard = &shards[shard_number - 1];
+        if shard.private_node_vms.is_empty() {
+            return Err(Error::EmptyInventory(AnsibleInventoryType::PrivateNodes));
+        }
+
+        let target = match target_gateway_name {
+            Some(name) => shards
+                .iter()
+                .map(|shard| &shard.gateway)
+                .find(|gateway| gateway.name == name)
+                .ok_or_else(|| Error::NatGatewayNotFound(name.to_string()))?
+                .clone(),
+            None => {
+                let mut healthy_gateway = None;
+                for gateway in shards
+                    .iter()
+                    .map(|shard| &shard.gateway)
+                    .filter(|gateway| gateway.name != shard.gateway.name)
+                {
+                    if self.is_nat_gateway_healthy(gateway).await {
+                        healthy_gateway = Some(gateway.clone());
+                        break;
+                    }
+                }
+                healthy_gateway.ok_or(Error::NoHealthyNatGateway)?
+            }
+        };
+        if target.name == shard.gateway.name {
+            return Err(Error::NatGatewayAlreadyRoutesShard(target.name));
+        }
+
+        println!(
+            "Moving {} private node VMs from {} to {}...",
+            shard.private_node_vms.len(),
+            shard.gateway.name,
+            target.name
+        );
+        let port_ranges = match nat_type {
+            NatType::FullCone => get_full_cone_port_ranges(
+                &shards
+                    .iter()
+                    .flat_map(|shard| shard.private_node_vms.clone())
+                    .collect::<Vec<_>>(),
+            )?,
+            NatType::Restricted | NatType::Symmetric => Vec::new(),
+        };
+        let ansible_runner = &self.ansible_provisioner.ansible_runner;
+        ansible_runner.run_playbook_with_retries(
+            AnsiblePlaybook::NatGateway,
+            AnsibleInventoryType::NatGateway,
+            Some(extra_vars::build_nat_gateway_extra_vars_doc(
+                &self.environment_name,
+                shard.get_private_ips(),
+                nat_type,
+                &port_ranges,
+                enable_upnp,
+            )?),
+            std::slice::from_ref(&target),
+            0,
+        )?;
+
+        let mut extra_vars = ExtraVarsDocBuilder::default();
+        extra_vars.add_variable(
+            "nat_gateway_private_ip_eth1",
+            &target.private_ip_addr.to_string(),
+        );
+        ansible_runner.run_playbook_with_retries(
+            AnsiblePlaybook::PrivateNodeRoute,
+            AnsibleInventoryType::PrivateNodes,
+            Some(extra_vars.build()),
+            &shard.private_node_vms,
+            0,
+        )?;
+
+        let moved_vms = std::mem::take(&mut shards[shard_number - 1].private_node_vms);
+        if let Some(target_shard) = shards
+            .iter_mut()
+            .find(|shard| shard.gateway.name == target.name)
+        {
+            target_shard
+                .private_node_vms
+                .extend(moved_vms.iter().cloned());
+        }
+        self.record_nat_gateway_shards(&shards).await?;
+
+        Ok(NatGatewayShard {
+            gateway: target,
+            private_node_vms: moved_vms,
+        })
+    }
+
+    /// A gateway is considered healthy if it can be reached and is forwarding packets.
+    async fn is_nat_gateway_healthy(&self, gateway: &VirtualMachine) -> bool {
+        match self
+            .ssh_client
+            .execute(
+                &gateway.public_ip_addr,
+                &self.cloud_provider.get_ssh_user(),
+                "sysctl -n net.ipv4.ip_forward",
+            )
+            .await
+            .and_then(|output| output.into_stdout_lines())
+        {
+            Ok(output) => output.iter().any(|line| line.trim() == "1"),
+            Err(err) => {
+                debug!("The NAT gateway {} is not reachable: {err}", gateway.name);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color_eyre::Result;
+    use std::net::Ipv4Addr;
+
+    fn get_vms(count: usize) -> Vec<VirtualMachine> {
+        (1..=count)
+            .map(|i| VirtualMachine {
+                id: i as u64,
+                name: format!("alpha-private-node-{i:03}"),
+                public_ip_addr: IpAddr::V4(Ipv4Addr::new(10, 0, 0, i as u8)),
+                private_ip_addr: IpAddr::V4(Ipv4Addr::new(10, 1, (i / 256) as u8, i as u8)),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_get_full_cone_port_ranges_with_no_vms() -> Result<()> {
+        let ranges = get_full_cone_port_ranges(&[])?;
+        assert!(ranges.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_full_cone_port_ranges_caps_the_ports_per_vm() -> Result<()> {
+        let mut vms = get_vms(3);
+        vms.reverse();
+        let ranges = get_full_cone_port_ranges(&vms)?;
+
+        assert_eq!(3, ranges.len());
+        // The ranges are assigned in name order, regardless of the order of the input.
+        assert_eq!(vms[2].private_ip_addr, ranges[0].private_ip);
+        assert_eq!(20000, ranges[0].start);
+        assert_eq!(20999, ranges[0].end);
+        assert_eq!(vms[1].private_ip_addr, ranges[1].private_ip);
+        assert_eq!(21000, ranges[1].start);
+        assert_eq!(21999, ranges[1].end);
+        assert_eq!(vms[0].private_ip_addr, ranges[2].private_ip);
+        assert_eq!(22000, ranges[2].start);
+        assert_eq!(22999, ranges[2].end);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_full_cone_port_ranges_splits_the_range_between_many_vms() -> Result<()> {
+        let ranges = get_full_cone_port_ranges(&get_vms(300))?;
+
+        assert_eq!(300, ranges.len());
+        assert_eq!(20000, ranges[0].start);
+        assert_eq!(20149, ranges[0].end);
+        assert_eq!(64850, ranges[299].start);
+        assert_eq!(64999, ranges[299].end);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_full_cone_port_ranges_with_too_many_vms() -> Result<()> {
+        let result = get_full_cone_port_ranges(&get_vms(451));
+        assert!(matches!(result, Err(Error::TooManyVmsForFullConeNat(451))));
+        Ok(())
+    }
+}
@@ -17,7 +17,9 @@ use color_eyre::{
 use futures::StreamExt;
 use libp2p::PeerId;
 use rand::Rng;
-use std::{collections::BTreeSet, net::SocketAddr, time::Duration};
+use std::{
+    collections::BTreeSet, fs::File, io::Write, net::SocketAddr, path::PathBuf, time::Duration,
+};
 use tonic::{transport::Channel, Request};
 
 const MAX_CONCURRENT_RPC_REQUESTS: usize = 10;
@@ -28,8 +30,47 @@ struct DaemonRpcClient {
     rpc: AntCtlClient<Channel>,
 }
 
+/// Records each churn action with a timestamp, so the restarts can be correlated with the node
+/// logs and metrics afterwards.
+///
+/// The log is written to `logs/<name>/churn-<timestamp>.log`, alongside any logs retrieved from the
+/// environment.
+struct ChurnLog {
+    file: File,
+    path: PathBuf,
+}
+
+impl ChurnLog {
+    fn create(name: &str) -> Result<Self> {
+        let dir = std::env::current_dir()?.join("logs").join(name);
+        std::fs::create_dir_all(&dir)?;
+        let timestamp = chrono::Utc::now().format("%Y%m%dT%H%M%S");
+        let path = dir.join(format!("churn-{timestamp}.log"));
+        Ok(Self {
+            file: File::create(&path)?,
+            path,
+        })
+    }
+
+    fn record_restart(
+        &mut self,
+        daemon_endpoint: &SocketAddr,
+        node_service_number: u32,
+        peer_id: &PeerId,
+    ) -> Result<()> {
+        writeln!(
+            self.file,
+            "{} restarted safenode-{node_service_number} @ {} (PeerId: {peer_id})",
+            chrono::Utc::now().to_rfc3339(),
+            daemon_endpoint.ip()
+        )?;
+        Ok(())
+    }
+}
+
 /// Perform fixed interval churn in the network by restarting nodes.
 /// This causes concurrent_churns nodes per vm to churn at a time.
+/// Door nodes are excluded, since clients rely on them as a stable entry point.
 pub async fn perform_fixed_interval_network_churn(
     inventory: DeploymentInventory,
     sleep_interval: Duration,
@@ -46,13 +87,16 @@ pub async fn perform_fixed_interval_network_churn(
             inventory
                 .peer_cache_node_vms
                 .iter()
+                .filter(|node_vm| !inventory.is_door_node_vm(&node_vm.vm))
                 .filter_map(|node_vm| node_vm.safenodemand_endpoint)
                 .map(|endpoint| (inventory.peer_cache_node_count(), endpoint)),
         )
         .collect::<BTreeSet<_>>();
 
     let max_churn_cycles = std::cmp::max(max_churn_cycles, 1);
+    let mut churn_log = ChurnLog::create(&inventory.name)?;
     println!("===== Configurations =====");
+    println!("Recording each restart to {}", churn_log.path.display());
 
     let mut n_cycles = 0;
     while n_cycles < max_churn_cycles {
@@ -68,6 +112,7 @@ pub async fn perform_fixed_interval_network_churn(
             for (peer_id, node_service_number) in nodes_to_churn {
                 // we don't call restart concurrently as the daemon does not handle concurrent node registry reads/writes.
                 restart_node(peer_id, retain_peer_id, &mut daemon_client).await?;
+                churn_log.record_restart(daemon_endpoint, node_service_number, &peer_id)?;
 
                 println!(
                     "safenode-{node_service_number:?}.service has been restarted. PeerId: {peer_id:?}"
@@ -106,17 +151,19 @@ pub async fn perform_random_interval_network_churn(
             inventory
                 .peer_cache_node_vms
                 .iter()
+                .filter(|node_vm| !inventory.is_door_node_vm(&node_vm.vm))
                 .filter_map(|node_vm| node_vm.safenodemand_endpoint),
         )
         .collect::<BTreeSet<_>>();
 
     let max_churn_cycles = std::cmp::max(max_churn_cycles, 1);
+    let mut churn_log = ChurnLog::create(&inventory.name)?;
     let mut n_cycles = 0;
 
     // print the time to churn all these nodes
     {
         let total_num_nodes = inventory.peers().len() - 1;
-        let n_timeframes_to_churn_all_nodes = if total_num_nodes % churn_count > 0 {
+        let n_timeframes_to_churn_all_nodes = if !total_num_nodes.is_multiple_of(churn_count) {
             total_num_nodes / churn_count + 1
         } else {
             total_num_nodes / churn_count
@@ -126,6 +173,7 @@ pub async fn perform_random_interval_network_churn(
         println!("===== Configurations =====");
         println!("Initializing churn of {churn_count} nodes every {time_frame:?}.");
         println!("This can take {total_time_per_cycle:?} for all {total_num_nodes:?} node. We perform {max_churn_cycles} such churn cycle(s)");
+        println!("Recording each restart to {}", churn_log.path.display());
     }
 
     while n_cycles < max_churn_cycles {
@@ -165,6 +213,7 @@ pub async fn perform_random_interval_network_churn(
                 };
 
                 restart_node(*peer_id, retain_peer_id, &mut daemon_client).await?;
+                churn_log.record_restart(daemon_endpoint, *node_service_number, peer_id)?;
                 println!(
                     "safenode-{node_service_number:?}.service @ {daemon_endpoint:?} has been restarted. PeerId: {peer_id:?}"
                 );
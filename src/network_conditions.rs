@@ -0,0 +1,145 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    ansible::{
+        extra_vars::ExtraVarsDocBuilder,
+        inventory::{generate_custom_environment_inventory, AnsibleInventoryType},
+        AnsiblePlaybook,
+    },
+    error::{Error, Result},
+    inventory::VirtualMachine,
+    TestnetDeployer,
+};
+use regex::Regex;
+
+/// The WAN conditions to simulate on a set of VMs, using the netem queueing discipline.
+#[derive(Clone, Debug, Default)]
+pub struct NetworkConditions {
+    /// The variation in the latency, e.g., '20ms'. Only applies if a latency is also used.
+    pub jitter: Option<String>,
+    /// The added latency for outgoing packets, e.g., '200ms'.
+    pub latency: Option<String>,
+    /// The percentage of outgoing packets to drop, e.g., '2%'.
+    pub loss: Option<String>,
+}
+
+impl NetworkConditions {
+    pub fn is_empty(&self) -> bool {
+        self.latency.is_none() && self.loss.is_none()
+    }
+
+    /// Build the arguments for `tc qdisc add ... netem`.
+    pub fn to_netem_args(&self) -> Result<String> {
+        let time_regex = Regex::new(r"^\d+(\.\d+)?(us|ms|s)$")?;
+        let percent_regex = Regex::new(r"^\d+(\.\d+)?%$")?;
+
+        let mut args = Vec::new();
+        if let Some(latency) = &self.latency {
+            if !time_regex.is_match(latency) {
+                return Err(Error::InvalidNetworkCondition(format!(
+                    "the latency '{latency}' must be a time, e.g., '200ms'"
+                )));
+            }
+            args.push(format!("delay {latency}"));
+            if let Some(jitter) = &self.jitter {
+                if !time_regex.is_match(jitter) {
+                    return Err(Error::InvalidNetworkCondition(format!(
+                        "the jitter '{jitter}' must be a time, e.g., '20ms'"
+                    )));
+                }
+                args.push(jitter.clone());
+            }
+        }
+        if let Some(loss) = &self.loss {
+            if !percent_regex.is_match(loss) {
+                return Err(Error::InvalidNetworkCondition(format!(
+                    "the loss '{loss}' must be a percentage, e.g., '2%'"
+                )));
+            }
+            args.push(format!("loss {loss}"));
+        }
+        Ok(args.join(" "))
+    }
+}
+
+impl TestnetDeployer {
+    /// Apply the network conditions to the node VMs whose names match the pattern, or all the node
+    /// VMs if no pattern is supplied.
+    ///
+    /// Any conditions previously applied to those VMs are replaced.
+    pub fn apply_network_conditions(
+        &self,
+        conditions: &NetworkConditions,
+        hosts_pattern: Option<&str>,
+    ) -> Result<()> {
+        if conditions.is_empty() {
+            return Err(Error::InvalidNetworkCondition(
+                "at least one of the latency or loss must be supplied".to_string(),
+            ));
+        }
+        let mut extra_vars = ExtraVarsDocBuilder::default();
+        extra_vars.add_variable("netem_args", &conditions.to_netem_args()?);
+        self.run_network_conditions_playbook(hosts_pattern, extra_vars.build())
+    }
+
+    /// Remove any network conditions from the node VMs whose names match the pattern, or all the
+    /// node VMs if no pattern is supplied.
+    pub fn clear_network_conditions(&self, hosts_pattern: Option<&str>) -> Result<()> {
+        let mut extra_vars = ExtraVarsDocBuilder::default();
+        extra_vars.add_variable("clear_network_conditions", "true");
+        self.run_network_conditions_playbook(hosts_pattern, extra_vars.build())
+    }
+
+    fn run_network_conditions_playbook(
+        &self,
+        hosts_pattern: Option<&str>,
+        extra_vars: String,
+    ) -> Result<()> {
+        let vms = self.get_network_condition_vms(hosts_pattern)?;
+        println!(
+            "Running the network conditions playbook against {} VMs",
+            vms.len()
+        );
+        generate_custom_environment_inventory(
+            &vms,
+            &self.environment_name,
+            &self
+                .ansible_provisioner
+                .ansible_runner
+                .working_directory_path
+                .join("inventory"),
+        )?;
+        self.ansible_provisioner.ansible_runner.run_playbook(
+            AnsiblePlaybook::NetworkConditions,
+            AnsibleInventoryType::Custom,
+            Some(extra_vars),
+        )?;
+        Ok(())
+    }
+
+    fn get_network_condition_vms(
+        &self,
+        hosts_pattern: Option<&str>,
+    ) -> Result<Vec<VirtualMachine>> {
+        let vms = self.ansible_provisioner.get_all_node_inventory()?;
+        let vms = match hosts_pattern {
+            Some(pattern) => {
+                let regex = Regex::new(pattern)?;
+                vms.into_iter()
+                    .filter(|vm| regex.is_match(&vm.name))
+                    .collect::<Vec<_>>()
+            }
+            None => vms,
+        };
+        if vms.is_empty() {
+            return Err(Error::InvalidNetworkCondition(
+                "no node VMs match the hosts pattern".to_string(),
+            ));
+        }
+        Ok(vms)
+    }
+}
@@ -0,0 +1,125 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{error::Result, status_badge::EnvironmentStatus, NodeType, TestnetDeployer};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// How often the node status is retrieved while waiting for the network to reconverge.
+const RECONVERGENCE_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+pub struct NetworkRestartOptions {
+    /// Remove the bootstrap caches while the nodes are stopped. Keys and data are always kept.
+    pub clear_caches: bool,
+    /// The interval between starting or stopping each node on a VM.
+    pub interval: Duration,
+    /// The network is considered to have reconverged when the percentage of nodes that are not
+    /// running is at or below this value.
+    pub max_failure_pct: f64,
+    /// Give up waiting for the network to reconverge after this long.
+    pub reconvergence_timeout: Duration,
+    /// The time to wait after each node type has been started, before starting the next.
+    pub stage_delay: Duration,
+}
+
+pub struct NetworkRestartReport {
+    pub elapsed: Duration,
+    pub failure_pct: f64,
+    pub reconverged: bool,
+    pub running_nodes: usize,
+    pub total_nodes: usize,
+}
+
+impl NetworkRestartReport {
+    pub fn print(&self) {
+        println!("======================");
+        println!("Network Restart Report");
+        println!("======================");
+        println!(
+            "{}/{} nodes running ({:.2}% not running)",
+            self.running_nodes, self.total_nodes, self.failure_pct
+        );
+        println!("Elapsed: {}s", self.elapsed.as_secs());
+        if self.reconverged {
+            println!("The network reconverged");
+        } else {
+            println!("The network did not reconverge");
+        }
+    }
+}
+
+impl TestnetDeployer {
+    /// Restart every node in the network, keeping the same identities.
+    ///
+    /// All the nodes are stopped, with the genesis node last. The nodes are then started again a
+    /// node type at a time, with the genesis node first, then the peer cache nodes, then the
+    /// generic and private nodes. Since the services are only stopped and started, each node keeps
+    /// its secret key and record store.
+    ///
+    /// Once all the nodes have been started, the node status is polled until enough nodes are
+    /// running, or the timeout is reached.
+    pub fn restart_network(&self, options: &NetworkRestartOptions) -> Result<NetworkRestartReport> {
+        let start_time = Instant::now();
+
+        println!("Stopping all nodes...");
+        for node_type in [
+            NodeType::Private,
+            NodeType::Generic,
+            NodeType::PeerCache,
+            NodeType::Genesis,
+        ] {
+            self.stop(options.interval, Some(node_type), None, None)?;
+        }
+
+        if options.clear_caches {
+            println!("Clearing the bootstrap caches...");
+            self.ansible_provisioner.clear_node_caches()?;
+        }
+
+        let stages = [
+            NodeType::Genesis,
+            NodeType::PeerCache,
+            NodeType::Generic,
+            NodeType::Private,
+        ];
+        for (i, node_type) in stages.iter().enumerate() {
+            println!("Starting {node_type:?} nodes...");
+            self.start(options.interval, Some(node_type.clone()), None)?;
+            if i < stages.len() - 1 {
+                debug!(
+                    "Waiting {}s before starting the next node type",
+                    options.stage_delay.as_secs()
+                );
+                std::thread::sleep(options.stage_delay);
+            }
+        }
+
+        println!("Waiting for the network to reconverge...");
+        let wait_start = Instant::now();
+        loop {
+            self.ansible_provisioner.status()?;
+            let registries = self.get_all_node_registries()?;
+            let status = EnvironmentStatus::from_registries(&self.environment_name, &registries);
+            println!(
+                "{}/{} nodes running ({:.2}% not running)",
+                status.running_nodes, status.total_nodes, status.failure_pct
+            );
+
+            let reconverged =
+                status.running_nodes > 0 && status.failure_pct <= options.max_failure_pct;
+            if reconverged || wait_start.elapsed() >= options.reconvergence_timeout {
+                return Ok(NetworkRestartReport {
+                    elapsed: start_time.elapsed(),
+                    failure_pct: status.failure_pct,
+                    reconverged,
+                    running_nodes: status.running_nodes,
+                    total_nodes: status.total_nodes,
+                });
+            }
+            std::thread::sleep(RECONVERGENCE_POLL_INTERVAL);
+        }
+    }
+}
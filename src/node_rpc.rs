@@ -0,0 +1,131 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    artifact_repository::ArtifactKind,
+    error::{Error, Result},
+    inventory::{DeploymentInventory, VirtualMachine},
+    rpc_client::{parse_output, NodeInfo},
+    TestnetDeployer,
+};
+use serde::Serialize;
+use std::net::SocketAddr;
+use tracing::debug;
+
+/// The result of an RPC command against a node, in the form it is printed.
+#[derive(Clone, Debug, Serialize)]
+pub struct NodeRpcResponse {
+    pub command: Vec<String>,
+    /// Whether the RPC client was run from the deployer, rather than on the VM.
+    pub direct: bool,
+    /// The parsed output of the `info` command.
+    pub info: Option<NodeInfo>,
+    pub output: Vec<String>,
+    pub rpc_address: SocketAddr,
+    pub service_name: String,
+    pub vm_name: String,
+}
+
+impl TestnetDeployer {
+    /// Run an RPC client command against one of the node services in the environment.
+    ///
+    /// The VM can be referred to by its full name or without the environment prefix, e.g.,
+    /// `node-3`. The RPC address of the service is read from the node registry on the VM.
+    ///
+    /// When the nodes were deployed with a public RPC address, the RPC client is run from the
+    /// deployer. Otherwise the RPC address is only reachable from the VM itself, so the client is
+    /// run there, and it is installed first if the VM does not have it.
+    pub async fn run_node_rpc_command(
+        &self,
+        inventory: &DeploymentInventory,
+        vm_name: &str,
+        service_name: &str,
+        command: &[String],
+    ) -> Result<NodeRpcResponse> {
+        let vm = self.find_node_vm(inventory, vm_name)?;
+        let ssh_user = self.cloud_provider.get_ssh_user();
+        let ip_addr = vm.public_ip_addr;
+        let rpc_address = self
+            .ssh_client
+            .execute(
+                &ip_addr,
+                &ssh_user,
+                &format!(
+                    "jq -r '.nodes[] | select(.service_name == \"{service_name}\") | .rpc_socket_addr' /var/antctl/node_registry.json"
+                ),
+            )
+            .await?
+            .into_stdout_lines()?
+            .first()
+            .and_then(|line| line.trim().parse::<SocketAddr>().ok())
+            .ok_or_else(|| Error::NodeServiceNotFound(service_name.to_string(), vm.name.clone()))?;
+        debug!(
+            "The RPC address of {service_name} on {} is {rpc_address}",
+            vm.name
+        );
+
+        let direct = !rpc_address.ip().is_loopback() && !rpc_address.ip().is_unspecified();
+        let output = if direct {
+            self.rpc_client.run(rpc_address, command)?
+        } else {
+            let archive_url = format!(
+                "{}/antnode_rpc_client-latest-{}.tar.gz",
+                inventory
+                    .environment_details
+                    .artifact_repository
+                    .clone()
+                    .unwrap_or_default()
+                    .build()
+                    .get_base_url(ArtifactKind::RpcClient),
+                inventory
+                    .environment_details
+                    .architecture
+                    .unwrap_or_default()
+                    .target_triple()
+            );
+            self.ssh_client
+                .execute(
+                    &ip_addr,
+                    &ssh_user,
+                    &format!(
+                        "command -v antnode_rpc_client > /dev/null || curl -sSL {archive_url} | sudo tar -xz -C /usr/local/bin; antnode_rpc_client {rpc_address} {}",
+                        command.join(" ")
+                    ),
+                )
+                .await?
+                .into_stdout_lines()?
+        };
+
+        let info = if command.first().is_some_and(|command| command == "info") {
+            Some(parse_output(output.clone())?)
+        } else {
+            None
+        };
+        Ok(NodeRpcResponse {
+            command: command.to_vec(),
+            direct,
+            info,
+            output,
+            rpc_address,
+            service_name: service_name.to_string(),
+            vm_name: vm.name,
+        })
+    }
+
+    fn find_node_vm(
+        &self,
+        inventory: &DeploymentInventory,
+        vm_name: &str,
+    ) -> Result<VirtualMachine> {
+        let prefixed_name = format!("{}-{vm_name}", self.environment_name);
+        inventory
+            .node_vm_list()
+            .into_iter()
+            .map(|node_vm| node_vm.vm)
+            .find(|vm| vm.name == vm_name || vm.name == prefixed_name)
+            .ok_or_else(|| Error::VmNotFound(vm_name.to_string()))
+    }
+}
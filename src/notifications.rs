@@ -0,0 +1,484 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{fs::File, io::Write, path::PathBuf, time::Duration};
+use tracing::debug;
+
+/// The window used in digest mode when `SLACK_DIGEST_WINDOW_MINS` is not set.
+pub const DEFAULT_DIGEST_WINDOW: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum EventSeverity {
+    Info,
+    Failure,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct NotificationEvent {
+    pub environment_name: String,
+    pub message: String,
+    pub severity: EventSeverity,
+    /// The time of the event, in the form `%Y-%m-%dT%H:%M:%SZ`.
+    pub timestamp: String,
+}
+
+impl NotificationEvent {
+    pub fn new(environment_name: &str, severity: EventSeverity, message: String) -> Self {
+        Self {
+            environment_name: environment_name.to_string(),
+            message,
+            severity,
+            timestamp: chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NotificationMode {
+    /// Post a message for every event.
+    Immediate,
+    /// Collect the events for each environment and post a single summary once the window has
+    /// passed. Failures are still posted immediately.
+    Digest { window: Duration },
+}
+
+impl NotificationMode {
+    /// Read the mode from the `SLACK_NOTIFICATION_MODE` variable, which can be `immediate` or
+    /// `digest`. The default is `immediate`.
+    ///
+    /// The window for digest mode is read from `SLACK_DIGEST_WINDOW_MINS`.
+    pub fn from_env() -> Result<Self> {
+        let mode = std::env::var("SLACK_NOTIFICATION_MODE").unwrap_or_default();
+        match mode.to_lowercase().as_str() {
+            "" | "immediate" => Ok(NotificationMode::Immediate),
+            "digest" => {
+                let window = match std::env::var("SLACK_DIGEST_WINDOW_MINS") {
+                    Ok(mins) => Duration::from_secs(
+                        mins.parse::<u64>()
+                            .map_err(|_| Error::InvalidDigestWindow(mins.clone()))?
+                            * 60,
+                    ),
+                    Err(_) => DEFAULT_DIGEST_WINDOW,
+                };
+                Ok(NotificationMode::Digest { window })
+            }
+            _ => Err(Error::InvalidNotificationMode(mode)),
+        }
+    }
+}
+
+/// The shape of the payload posted to a webhook.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum WebhookFormat {
+    /// A Discord webhook, which takes the message as `content`.
+    Discord,
+    /// Post the summary itself as JSON, for a webhook that does its own formatting.
+    Json,
+    /// A Slack incoming webhook, which takes the message as `text`.
+    #[default]
+    Slack,
+}
+
+impl WebhookFormat {
+    pub fn parse_from_str(val: &str) -> Result<Self> {
+        match val {
+            "discord" => Ok(WebhookFormat::Discord),
+            "json" => Ok(WebhookFormat::Json),
+            "slack" => Ok(WebhookFormat::Slack),
+            _ => Err(Error::InvalidWebhookFormat(val.to_string())),
+        }
+    }
+}
+
+/// Where to post the summary of a deployment when it finishes.
+#[derive(Clone, Debug)]
+pub struct NotificationConfig {
+    pub format: WebhookFormat,
+    pub webhook_url: String,
+}
+
+impl NotificationConfig {
+    pub async fn notify_deploy(&self, summary: &DeploySummary) -> Result<()> {
+        let payload = match self.format {
+            WebhookFormat::Discord => json!({ "content": summary.render() }),
+            WebhookFormat::Json => serde_json::to_value(summary)?,
+            WebhookFormat::Slack => json!({ "text": summary.render() }),
+        };
+        reqwest::Client::new()
+            .post(&self.webhook_url)
+            .json(&payload)
+            .send()
+            .await?
+            .error_for_status()?;
+        println!("Posted the deployment summary to the webhook");
+        Ok(())
+    }
+}
+
+/// The outcome of a deployment, as it is reported to a webhook.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DeploySummary {
+    pub duration_secs: u64,
+    pub environment_name: String,
+    /// The phases that were started but did not complete.
+    pub failed_phases: Vec<String>,
+    pub genesis_multiaddr: Option<String>,
+    /// The number of nodes running at the end of the deployment, if it got far enough to count
+    /// them.
+    pub peer_count: Option<usize>,
+    pub succeeded: bool,
+}
+
+impl DeploySummary {
+    pub fn new(environment_name: &str) -> Self {
+        Self {
+            environment_name: environment_name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut message = if self.succeeded {
+            format!("*Deployment of {} succeeded*\n", self.environment_name)
+        } else {
+            format!(":x: *Deployment of {} failed*\n", self.environment_name)
+        };
+        message.push_str(&format!(
+            "Duration: {}\n",
+            crate::run_log::format_duration(Duration::from_secs(self.duration_secs))
+        ));
+        if let Some(peer_count) = self.peer_count {
+            message.push_str(&format!("Running nodes: {peer_count}\n"));
+        }
+        if let Some(genesis_multiaddr) = &self.genesis_multiaddr {
+            message.push_str(&format!("Genesis: `{genesis_multiaddr}`\n"));
+        }
+        if !self.failed_phases.is_empty() {
+            message.push_str(&format!(
+                "Failed phases: {}\n",
+                self.failed_phases.join(", ")
+            ));
+        }
+        message
+    }
+}
+
+/// The events waiting to be posted in the next digest for an environment.
+///
+/// These are saved in the data directory, so events from the separate runs of a pipeline end up in
+/// the same digest.
+#[derive(Default, Deserialize, Serialize)]
+struct PendingDigest {
+    events: Vec<NotificationEvent>,
+    /// The time the first pending event was recorded, as a Unix timestamp.
+    window_started_at: Option<i64>,
+}
+
+pub struct SlackNotifier {
+    pub mode: NotificationMode,
+    webhook_url: String,
+}
+
+impl SlackNotifier {
+    pub fn from_env() -> Result<Self> {
+        let webhook_url =
+            std::env::var("SLACK_WEBHOOK_URL").map_err(|_| Error::SlackWebhookUrlNotSupplied)?;
+        Ok(Self {
+            mode: NotificationMode::from_env()?,
+            webhook_url,
+        })
+    }
+
+    /// Post the event, or add it to the pending digest for its environment.
+    ///
+    /// In digest mode, the digest is posted when an event arrives after the window has passed.
+    pub async fn notify(&self, event: NotificationEvent) -> Result<()> {
+        let window = match self.mode {
+            NotificationMode::Immediate => return self.post(&event.message).await,
+            NotificationMode::Digest { .. } if event.severity == EventSeverity::Failure => {
+                return self.post(&event.message).await;
+            }
+            NotificationMode::Digest { window } => window,
+        };
+
+        let environment_name = event.environment_name.clone();
+        let mut digest = read_pending_digest(&environment_name)?;
+        let now = chrono::Utc::now().timestamp();
+        let window_started_at = *digest.window_started_at.get_or_insert(now);
+        digest.events.push(event);
+        if now - window_started_at >= window.as_secs() as i64 {
+            self.post(&render_digest(&environment_name, &digest.events))
+                .await?;
+            digest = PendingDigest::default();
+        } else {
+            debug!(
+                "Added event to the digest for {environment_name}, which has {} events",
+                digest.events.len()
+            );
+        }
+        write_pending_digest(&environment_name, &digest)
+    }
+
+    /// Post the pending digest for an environment, regardless of the window.
+    ///
+    /// This should be used at the end of a pipeline, so the last events are not left waiting.
+    pub async fn flush(&self, environment_name: &str) -> Result<()> {
+        let digest = read_pending_digest(environment_name)?;
+        if digest.events.is_empty() {
+            println!("There are no pending events for {environment_name}");
+            return Ok(());
+        }
+        self.post(&render_digest(environment_name, &digest.events))
+            .await?;
+        write_pending_digest(environment_name, &PendingDigest::default())
+    }
+
+    async fn post(&self, message: &str) -> Result<()> {
+        let payload = json!({
+            "text": message,
+        });
+        reqwest::Client::new()
+            .post(&self.webhook_url)
+            .json(&payload)
+            .send()
+            .await?
+            .error_for_status()?;
+        println!("Posted notification to Slack");
+        Ok(())
+    }
+}
+
+fn render_digest(environment_name: &str, events: &[NotificationEvent]) -> String {
+    let mut message = format!("*Digest for {environment_name}*: {} events\n", events.len());
+    for event in events.iter() {
+        match event.message.split_once('\n') {
+            // Multi-line messages, like the deployment details, are quoted in full.
+            Some(_) => {
+                message.push_str(&format!("• {}\n", event.timestamp));
+                for line in event.message.lines() {
+                    message.push_str(&format!("> {line}\n"));
+                }
+            }
+            None => message.push_str(&format!("• {}: {}\n", event.timestamp, event.message)),
+        }
+    }
+    message
+}
+
+fn get_pending_digest_path(environment_name: &str) -> Result<PathBuf> {
+    let path = dirs_next::data_dir()
+        .ok_or_else(|| Error::CouldNotRetrieveDataDirectory)?
+        .join("safe")
+        .join("testnet-deploy");
+    if !path.exists() {
+        std::fs::create_dir_all(&path)?;
+    }
+    Ok(path.join(format!("{environment_name}-notification-digest.json")))
+}
+
+fn read_pending_digest(environment_name: &str) -> Result<PendingDigest> {
+    let path = get_pending_digest_path(environment_name)?;
+    if !path.exists() {
+        return Ok(PendingDigest::default());
+    }
+    let data = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+fn write_pending_digest(environment_name: &str, digest: &PendingDigest) -> Result<()> {
+    let path = get_pending_digest_path(environment_name)?;
+    if digest.events.is_empty() {
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        return Ok(());
+    }
+    let mut file = File::create(path)?;
+    file.write_all(serde_json::to_string_pretty(digest)?.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color_eyre::Result;
+    use httpmock::prelude::*;
+
+    fn get_event(timestamp: &str, message: &str) -> NotificationEvent {
+        NotificationEvent {
+            environment_name: "alpha".to_string(),
+            message: message.to_string(),
+            severity: EventSeverity::Info,
+            timestamp: timestamp.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render_digest_lists_each_event() -> Result<()> {
+        let events = vec![
+            get_event("2024-05-01T10:00:00Z", "Upscaled the nodes"),
+            get_event("2024-05-01T10:05:00Z", "Started the uploaders"),
+        ];
+        assert_eq!(
+            "*Digest for alpha*: 2 events\n\
+             • 2024-05-01T10:00:00Z: Upscaled the nodes\n\
+             • 2024-05-01T10:05:00Z: Started the uploaders\n",
+            render_digest("alpha", &events)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_digest_quotes_multi_line_messages() -> Result<()> {
+        let events = vec![get_event(
+            "2024-05-01T10:00:00Z",
+            "Deployed alpha\nNodes: 25",
+        )];
+        assert_eq!(
+            "*Digest for alpha*: 1 events\n\
+             • 2024-05-01T10:00:00Z\n\
+             > Deployed alpha\n\
+             > Nodes: 25\n",
+            render_digest("alpha", &events)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_pending_digest_round_trips_through_json() -> Result<()> {
+        let digest = PendingDigest {
+            events: vec![get_event("2024-05-01T10:00:00Z", "Upscaled the nodes")],
+            window_started_at: Some(1714557600),
+        };
+        let restored: PendingDigest = serde_json::from_str(&serde_json::to_string(&digest)?)?;
+        assert_eq!(Some(1714557600), restored.window_started_at);
+        assert_eq!(1, restored.events.len());
+        assert_eq!("Upscaled the nodes", restored.events[0].message);
+        assert_eq!(EventSeverity::Info, restored.events[0].severity);
+        Ok(())
+    }
+
+    fn get_summary(succeeded: bool) -> DeploySummary {
+        DeploySummary {
+            duration_secs: 754,
+            environment_name: "alpha".to_string(),
+            failed_phases: if succeeded {
+                Vec::new()
+            } else {
+                vec!["Provision".to_string(), "Uploaders".to_string()]
+            },
+            genesis_multiaddr: succeeded.then(|| "/ip4/10.0.0.1/udp/12000/quic-v1".to_string()),
+            peer_count: succeeded.then_some(25),
+            succeeded,
+        }
+    }
+
+    #[test]
+    fn test_parse_webhook_format() -> Result<()> {
+        assert_eq!(
+            WebhookFormat::Discord,
+            WebhookFormat::parse_from_str("discord")?
+        );
+        assert_eq!(WebhookFormat::Json, WebhookFormat::parse_from_str("json")?);
+        assert_eq!(
+            WebhookFormat::Slack,
+            WebhookFormat::parse_from_str("slack")?
+        );
+        assert!(matches!(
+            WebhookFormat::parse_from_str("teams"),
+            Err(Error::InvalidWebhookFormat(format)) if format == "teams"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_deploy_summary() -> Result<()> {
+        assert_eq!(
+            "*Deployment of alpha succeeded*\n\
+             Duration: 12m34s\n\
+             Running nodes: 25\n\
+             Genesis: `/ip4/10.0.0.1/udp/12000/quic-v1`\n",
+            get_summary(true).render()
+        );
+        assert_eq!(
+            ":x: *Deployment of alpha failed*\n\
+             Duration: 12m34s\n\
+             Failed phases: Provision, Uploaders\n",
+            get_summary(false).render()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_notify_deploy_posts_the_payload_for_the_format() -> Result<()> {
+        let summary = get_summary(true);
+        let server = MockServer::start();
+        let slack_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/slack")
+                .json_body(json!({ "text": summary.render() }));
+            then.status(200);
+        });
+        let discord_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/discord")
+                .json_body(json!({ "content": summary.render() }));
+            then.status(200);
+        });
+        let json_mock = server.mock(|when, then| {
+            when.method(POST).path("/json").json_body(json!({
+                "duration_secs": 754,
+                "environment_name": "alpha",
+                "failed_phases": [],
+                "genesis_multiaddr": "/ip4/10.0.0.1/udp/12000/quic-v1",
+                "peer_count": 25,
+                "succeeded": true,
+            }));
+            then.status(200);
+        });
+
+        for (format, path) in [
+            (WebhookFormat::Slack, "/slack"),
+            (WebhookFormat::Discord, "/discord"),
+            (WebhookFormat::Json, "/json"),
+        ] {
+            NotificationConfig {
+                format,
+                webhook_url: server.url(path),
+            }
+            .notify_deploy(&summary)
+            .await?;
+        }
+
+        slack_mock.assert();
+        discord_mock.assert();
+        json_mock.assert();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_notify_deploy_returns_an_error_when_the_webhook_fails() -> Result<()> {
+        let server = MockServer::start();
+        let webhook_mock = server.mock(|when, then| {
+            when.method(POST).path("/webhook");
+            then.status(500);
+        });
+
+        let result = NotificationConfig {
+            format: WebhookFormat::Slack,
+            webhook_url: server.url("/webhook"),
+        }
+        .notify_deploy(&get_summary(false))
+        .await;
+
+        assert!(result.is_err());
+        webhook_mock.assert();
+        Ok(())
+    }
+}
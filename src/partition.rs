@@ -0,0 +1,129 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    ansible::{
+        extra_vars::ExtraVarsDocBuilder,
+        inventory::{generate_custom_environment_inventory, AnsibleInventoryType},
+        AnsiblePlaybook,
+    },
+    error::{Error, Result},
+    inventory::VirtualMachine,
+    TestnetDeployer,
+};
+use tracing::debug;
+
+/// The node VMs split into groups which cannot reach each other.
+#[derive(Clone, Debug)]
+pub struct Partition {
+    pub groups: Vec<Vec<VirtualMachine>>,
+}
+
+impl Partition {
+    /// Split the VMs into the given number of groups.
+    ///
+    /// The VMs are sorted by name and assigned to the groups in turn, so each group gets a mix of
+    /// the node types and the same split is produced for the same VMs.
+    pub fn new(mut vms: Vec<VirtualMachine>, group_count: usize) -> Result<Self> {
+        if group_count < 2 || group_count > vms.len() {
+            return Err(Error::InvalidPartitionGroupCount(group_count, vms.len()));
+        }
+        vms.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut groups = vec![Vec::new(); group_count];
+        for (i, vm) in vms.into_iter().enumerate() {
+            groups[i % group_count].push(vm);
+        }
+        Ok(Self { groups })
+    }
+
+    /// Get the IP addresses of all the VMs outside the given group.
+    ///
+    /// Both the public and private addresses are used, since nodes can connect to each other over
+    /// the VPC.
+    pub fn get_blocked_ips(&self, group_index: usize) -> Vec<String> {
+        self.groups
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != group_index)
+            .flat_map(|(_, group)| group.iter())
+            .flat_map(|vm| {
+                [
+                    vm.public_ip_addr.to_string(),
+                    vm.private_ip_addr.to_string(),
+                ]
+            })
+            .collect()
+    }
+
+    pub fn print(&self) {
+        for (i, group) in self.groups.iter().enumerate() {
+            println!("Group {} ({} VMs):", i + 1, group.len());
+            for vm in group.iter() {
+                println!("  {}: {}", vm.name, vm.public_ip_addr);
+            }
+        }
+    }
+}
+
+impl TestnetDeployer {
+    /// Split the node VMs into groups and apply iptables rules so that the VMs in each group cannot
+    /// reach the VMs in any other group.
+    ///
+    /// The private nodes are not included, because they can only be reached through the NAT
+    /// gateway.
+    pub fn partition_network(&self, group_count: usize) -> Result<Partition> {
+        let partition = Partition::new(self.get_partition_vms()?, group_count)?;
+        for (i, group) in partition.groups.iter().enumerate() {
+            debug!("Applying the partition to group {}", i + 1);
+            let mut extra_vars = ExtraVarsDocBuilder::default();
+            extra_vars.add_list_variable("partition_blocked_ips", partition.get_blocked_ips(i));
+            self.run_partition_playbook(group, extra_vars.build())?;
+        }
+        Ok(partition)
+    }
+
+    /// Remove the partition rules from all the node VMs.
+    pub fn heal_network(&self) -> Result<()> {
+        let vms = self.get_partition_vms()?;
+        let mut extra_vars = ExtraVarsDocBuilder::default();
+        extra_vars.add_list_variable("partition_blocked_ips", Vec::new());
+        self.run_partition_playbook(&vms, extra_vars.build())
+    }
+
+    fn run_partition_playbook(&self, vms: &[VirtualMachine], extra_vars: String) -> Result<()> {
+        generate_custom_environment_inventory(
+            vms,
+            &self.environment_name,
+            &self
+                .ansible_provisioner
+                .ansible_runner
+                .working_directory_path
+                .join("inventory"),
+        )?;
+        self.ansible_provisioner.ansible_runner.run_playbook(
+            AnsiblePlaybook::Partition,
+            AnsibleInventoryType::Custom,
+            Some(extra_vars),
+        )?;
+        Ok(())
+    }
+
+    fn get_partition_vms(&self) -> Result<Vec<VirtualMachine>> {
+        let mut vms = Vec::new();
+        for inventory_type in [
+            AnsibleInventoryType::Genesis,
+            AnsibleInventoryType::PeerCacheNodes,
+            AnsibleInventoryType::Nodes,
+        ] {
+            vms.extend(
+                self.ansible_provisioner
+                    .ansible_runner
+                    .get_inventory(inventory_type, false)?,
+            );
+        }
+        Ok(vms)
+    }
+}
@@ -0,0 +1,172 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    digital_ocean::{DigitalOceanClient, DIGITAL_OCEAN_API_BASE_URL, DIGITAL_OCEAN_API_PAGE_SIZE},
+    error::Error,
+    CloudProvider,
+};
+use aws_credential_types::provider::ProvideCredentials;
+use colored::Colorize;
+use std::time::{Duration, SystemTime};
+use tracing::debug;
+
+/// A deployment can take around 40 minutes, and the logs and inventory are uploaded at the end, so
+/// credentials must remain valid for at least this long.
+pub const DEFAULT_MIN_CREDENTIAL_VALIDITY: Duration = Duration::from_secs(60 * 60);
+/// S3 rejects requests whose signature is more than 15 minutes away from its own clock. The limit
+/// here leaves a margin for the clock drifting further during the deployment.
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(5 * 60);
+const WARN_CLOCK_SKEW: Duration = Duration::from_secs(60);
+const S3_ENDPOINT: &str = "https://s3.eu-west-2.amazonaws.com";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PreflightSeverity {
+    Warning,
+    Fatal,
+}
+
+#[derive(Clone, Debug)]
+pub struct PreflightIssue {
+    pub message: String,
+    pub severity: PreflightSeverity,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PreflightReport {
+    pub issues: Vec<PreflightIssue>,
+}
+
+impl PreflightReport {
+    pub fn has_fatal_issues(&self) -> bool {
+        self.issues
+            .iter()
+            .any(|issue| issue.severity == PreflightSeverity::Fatal)
+    }
+
+    pub fn print(&self) {
+        for issue in self.issues.iter() {
+            match issue.severity {
+                PreflightSeverity::Warning => {
+                    println!("{} {}", "WARNING:".yellow(), issue.message)
+                }
+                PreflightSeverity::Fatal => println!("{} {}", "ERROR:".red(), issue.message),
+            }
+        }
+    }
+
+    fn warn(&mut self, message: String) {
+        self.issues.push(PreflightIssue {
+            message,
+            severity: PreflightSeverity::Warning,
+        });
+    }
+
+    fn fail(&mut self, message: String) {
+        self.issues.push(PreflightIssue {
+            message,
+            severity: PreflightSeverity::Fatal,
+        });
+    }
+}
+
+/// Check the local clock and the credentials before starting a long running command.
+///
+/// Either problem only shows up when the logs or inventory are uploaded at the end of a
+/// deployment, so it is much cheaper to find them before anything is created.
+pub async fn run_preflight_checks(
+    provider: CloudProvider,
+    min_credential_validity: Duration,
+) -> PreflightReport {
+    let mut report = PreflightReport::default();
+    check_clock_skew(&mut report).await;
+    check_aws_credentials(&mut report, min_credential_validity).await;
+    if matches!(provider, CloudProvider::DigitalOcean) {
+        check_digital_ocean_token(&mut report).await;
+    }
+    report
+}
+
+/// Compare the local clock with the `Date` header returned by S3.
+async fn check_clock_skew(report: &mut PreflightReport) {
+    let response = match reqwest::Client::new().head(S3_ENDPOINT).send().await {
+        Ok(response) => response,
+        Err(err) => {
+            report.warn(format!("Could not check the local clock against S3: {err}"));
+            return;
+        }
+    };
+    let Some(date) = response
+        .headers()
+        .get("date")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| chrono::DateTime::parse_from_rfc2822(value).ok())
+    else {
+        report.warn("Could not check the local clock: S3 did not return a date".to_string());
+        return;
+    };
+
+    let skew = (chrono::Utc::now() - date.with_timezone(&chrono::Utc))
+        .num_seconds()
+        .unsigned_abs();
+    debug!("The local clock is {skew}s away from S3");
+    if skew > MAX_CLOCK_SKEW.as_secs() {
+        report.fail(format!(
+            "The local clock is {skew}s away from the S3 clock. Requests to S3 will be rejected. \
+            Synchronise the clock, e.g., with NTP."
+        ));
+    } else if skew > WARN_CLOCK_SKEW.as_secs() {
+        report.warn(format!("The local clock is {skew}s away from the S3 clock"));
+    }
+}
+
+async fn check_aws_credentials(report: &mut PreflightReport, min_validity: Duration) {
+    let conf = aws_config::from_env().region("eu-west-2").load().await;
+    let Some(provider) = conf.credentials_provider() else {
+        report.fail("No AWS credentials provider is configured".to_string());
+        return;
+    };
+    let credentials = match provider.provide_credentials().await {
+        Ok(credentials) => credentials,
+        Err(err) => {
+            report.fail(format!("Could not load the AWS credentials: {err}"));
+            return;
+        }
+    };
+
+    // Long-lived access keys have no expiry. Temporary credentials, e.g., from STS or SSO, do.
+    let Some(expiry) = credentials.expiry() else {
+        return;
+    };
+    match expiry.duration_since(SystemTime::now()) {
+        Err(_) => report.fail("The AWS credentials have expired".to_string()),
+        Ok(remaining) if remaining < min_validity => report.fail(format!(
+            "The AWS credentials expire in {} minutes, which is not long enough to complete the \
+            command. Refresh them before running it.",
+            remaining.as_secs() / 60
+        )),
+        Ok(remaining) => debug!("The AWS credentials expire in {}s", remaining.as_secs()),
+    }
+}
+
+async fn check_digital_ocean_token(report: &mut PreflightReport) {
+    let Ok(access_token) = std::env::var("DO_PAT") else {
+        report.fail(Error::CloudProviderCredentialsNotSupplied("DO_PAT".to_string()).to_string());
+        return;
+    };
+    let client = DigitalOceanClient {
+        base_url: DIGITAL_OCEAN_API_BASE_URL.to_string(),
+        access_token,
+        page_size: DIGITAL_OCEAN_API_PAGE_SIZE,
+    };
+    match client.check_access_token().await {
+        Ok(()) => {}
+        Err(Error::DigitalOceanUnauthorized) => report.fail(
+            "The DO_PAT token was rejected. It may have expired or been revoked.".to_string(),
+        ),
+        Err(err) => report.warn(format!("Could not check the DO_PAT token: {err}")),
+    }
+}
@@ -0,0 +1,203 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    error::{Error, Result},
+    get_environment_details,
+    lock::EnvironmentLock,
+    notifications::{EventSeverity, NotificationEvent, SlackNotifier},
+    CleanOptions, CloudProvider, TestnetDeployBuilder,
+};
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+use tracing::debug;
+
+pub struct ReapOptions {
+    /// Only report the environments that would be destroyed.
+    pub dry_run: bool,
+    /// Post a warning for environments that will expire within this period.
+    pub grace_period: Duration,
+    pub provider: CloudProvider,
+}
+
+#[derive(Default)]
+pub struct ReapReport {
+    pub destroyed: Vec<String>,
+    pub dry_run: bool,
+    /// The environments that will expire within the grace period, with their expiry time.
+    pub expiring: Vec<(String, DateTime<Utc>)>,
+    pub failed: Vec<(String, String)>,
+    /// Expired environments that were skipped because another run held their lock.
+    pub locked: Vec<String>,
+}
+
+impl ReapReport {
+    pub fn print(&self) {
+        for (name, expires_at) in self.expiring.iter() {
+            println!(
+                "{name} expires at {}",
+                expires_at.format("%Y-%m-%d %H:%M:%S UTC")
+            );
+        }
+        for name in self.locked.iter() {
+            println!("{name} has expired, but it is locked by another run, so it was skipped");
+        }
+        for (name, err) in self.failed.iter() {
+            println!("Failed to destroy {name}: {err}");
+        }
+        println!(
+            "{} {} expired environments. {} will expire within the grace period.",
+            if self.dry_run {
+                "Would destroy"
+            } else {
+                "Destroyed"
+            },
+            self.destroyed.len(),
+            self.expiring.len()
+        );
+    }
+}
+
+/// Get the expiry, as a Unix timestamp, of an environment deployed now with the given TTL.
+pub fn get_expiry_timestamp(ttl: Duration) -> i64 {
+    Utc::now().timestamp() + ttl.as_secs() as i64
+}
+
+/// Destroy every environment whose TTL has passed.
+///
+/// All the Terraform workspaces are checked, and an environment is considered expired if the
+/// expiry recorded in its details is in the past. Environments deployed without a TTL are never
+/// reaped. The owners of environments that will expire within the grace period are warned through
+/// Slack, if `SLACK_WEBHOOK_URL` is set, so they have a chance to redeploy or extend them.
+///
+/// An expired environment that is locked by another run is skipped, to be picked up by the next
+/// run of the reaper.
+pub async fn reap_expired_environments(options: &ReapOptions) -> Result<ReapReport> {
+    // The deployer is not used for a particular environment here, but it requires a name.
+    let testnet_deployer = TestnetDeployBuilder::default()
+        .environment_name("reaper")
+        .provider(options.provider)
+        .build()?;
+    testnet_deployer.terraform_runner.init()?;
+    let workspaces = testnet_deployer.terraform_runner.workspace_list()?;
+    let notifier = SlackNotifier::from_env().ok();
+
+    let now = Utc::now();
+    let mut report = ReapReport {
+        dry_run: options.dry_run,
+        ..Default::default()
+    };
+    for name in workspaces.iter().filter(|name| *name != "default") {
+        let details = match get_environment_details(name, &testnet_deployer.s3_repository).await {
+            Ok(details) => details,
+            Err(err) => {
+                debug!("Could not retrieve the details for {name}: {err}");
+                continue;
+            }
+        };
+        let Some(expires_at) = details
+            .expires_at
+            .and_then(|timestamp| DateTime::<Utc>::from_timestamp(timestamp, 0))
+        else {
+            continue;
+        };
+
+        if expires_at > now {
+            let remaining = (expires_at - now).to_std().unwrap_or_default();
+            if remaining <= options.grace_period {
+                notify(
+                    &notifier,
+                    name,
+                    EventSeverity::Info,
+                    format!(
+                        ":hourglass: {name} expires in {} and will then be destroyed",
+                        crate::run_log::format_duration(remaining)
+                    ),
+                )
+                .await;
+                report.expiring.push((name.clone(), expires_at));
+            }
+            continue;
+        }
+
+        println!(
+            "{name} expired at {}",
+            expires_at.format("%Y-%m-%d %H:%M:%S UTC")
+        );
+        if options.dry_run {
+            report.destroyed.push(name.clone());
+            continue;
+        }
+        let lock = match EnvironmentLock::acquire(
+            &testnet_deployer.s3_repository,
+            name,
+            "reap",
+            false,
+            false,
+        )
+        .await
+        {
+            Ok(lock) => lock,
+            Err(Error::EnvironmentLocked { .. }) => {
+                report.locked.push(name.clone());
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        match destroy_environment(name, options.provider).await {
+            Ok(()) => {
+                notify(
+                    &notifier,
+                    name,
+                    EventSeverity::Info,
+                    format!("{name} reached the end of its TTL and was destroyed"),
+                )
+                .await;
+                report.destroyed.push(name.clone());
+            }
+            Err(err) => {
+                notify(
+                    &notifier,
+                    name,
+                    EventSeverity::Failure,
+                    format!(":x: {name} reached the end of its TTL, but it could not be destroyed"),
+                )
+                .await;
+                report.failed.push((name.clone(), err.to_string()));
+            }
+        }
+        if let Err(err) = lock.release().await {
+            println!("Failed to release the environment lock for {name}: {err}");
+        }
+    }
+    Ok(report)
+}
+
+async fn destroy_environment(name: &str, provider: CloudProvider) -> Result<()> {
+    let testnet_deployer = TestnetDeployBuilder::default()
+        .environment_name(name)
+        .provider(provider)
+        .build()?;
+    testnet_deployer.clean(&CleanOptions::default()).await
+}
+
+async fn notify(
+    notifier: &Option<SlackNotifier>,
+    name: &str,
+    severity: EventSeverity,
+    message: String,
+) {
+    println!("{message}");
+    let Some(notifier) = notifier else {
+        return;
+    };
+    if let Err(err) = notifier
+        .notify(NotificationEvent::new(name, severity, message))
+        .await
+    {
+        println!("Failed to send the notification: {err}");
+    }
+}
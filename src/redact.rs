@@ -0,0 +1,110 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use regex::RegexBuilder;
+use std::sync::Mutex;
+use tracing::{debug, warn};
+
+/// A regular expression for the names of the environment variables whose values should be
+/// redacted. The match is case insensitive.
+pub const REDACT_PATTERN_ENV_VAR: &str = "TESTNET_DEPLOY_REDACT_PATTERN";
+const DEFAULT_REDACT_PATTERN: &str = "key|secret|token|password|credential";
+const REDACTED: &str = "[REDACTED]";
+
+/// The values that must not appear in any output. They are still passed intact to provisioning;
+/// only the output is masked.
+static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Register the value of an environment variable as a secret if its name matches the redaction
+/// pattern.
+pub fn register_env_variable(name: &str, value: &str) {
+    let pattern = std::env::var(REDACT_PATTERN_ENV_VAR)
+        .unwrap_or_else(|_| DEFAULT_REDACT_PATTERN.to_string());
+    let regex = match RegexBuilder::new(&pattern).case_insensitive(true).build() {
+        Ok(regex) => regex,
+        Err(err) => {
+            // Redact everything rather than risk leaking a secret because of a typo.
+            warn!("Invalid redaction pattern '{pattern}': {err}. All values will be redacted.");
+            register_secret(value);
+            return;
+        }
+    };
+    if regex.is_match(name) {
+        debug!("The value of {name} will be redacted from the output");
+        register_secret(value);
+    }
+}
+
+pub fn register_secret(value: &str) {
+    if value.is_empty() {
+        return;
+    }
+    if let Ok(mut secrets) = SECRETS.lock() {
+        if !secrets.iter().any(|secret| secret == value) {
+            secrets.push(value.to_string());
+        }
+    }
+}
+
+/// Replace any registered secret in the text.
+pub fn redact(text: &str) -> String {
+    let Ok(secrets) = SECRETS.lock() else {
+        return text.to_string();
+    };
+    let mut text = text.to_string();
+    for secret in secrets.iter() {
+        if text.contains(secret.as_str()) {
+            text = text.replace(secret.as_str(), REDACTED);
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color_eyre::Result;
+
+    // The secrets are global, so each test uses values that are distinct from the others.
+
+    #[test]
+    fn test_redact_replaces_every_occurrence_of_a_secret() -> Result<()> {
+        register_secret("hunter2-redact-test");
+        assert_eq!(
+            "--password [REDACTED] --confirm [REDACTED]",
+            redact("--password hunter2-redact-test --confirm hunter2-redact-test")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_redact_leaves_text_without_secrets_unchanged() -> Result<()> {
+        register_secret("not-in-the-text-redact-test");
+        assert_eq!(
+            "ansible-playbook site.yml",
+            redact("ansible-playbook site.yml")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_secret_ignores_empty_values() -> Result<()> {
+        register_secret("");
+        assert_eq!("nothing to hide", redact("nothing to hide"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_env_variable_only_registers_matching_names() -> Result<()> {
+        register_env_variable("SN_API_TOKEN", "token-value-redact-test");
+        register_env_variable("ANT_LOG", "log-value-redact-test");
+        assert_eq!(
+            "[REDACTED] log-value-redact-test",
+            redact("token-value-redact-test log-value-redact-test")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,194 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::error::{Error, Result};
+use ant_releases::{AntReleaseRepoActions, ReleaseType};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+const RELEASES_URL: &str = "https://api.github.com/repos/maidsafe/autonomi/releases";
+
+/// A stream of releases that a versioned deployment can follow, rather than pinning each binary.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum ReleaseChannel {
+    /// The latest version of each binary, regardless of which release it was part of.
+    Latest,
+    Nightly,
+    /// Release candidates, which are published ahead of a stable release.
+    Rc,
+    Stable,
+}
+
+impl ReleaseChannel {
+    pub fn parse_from_str(val: &str) -> Result<Self> {
+        match val {
+            "latest" => Ok(ReleaseChannel::Latest),
+            "nightly" => Ok(ReleaseChannel::Nightly),
+            "rc" => Ok(ReleaseChannel::Rc),
+            "stable" => Ok(ReleaseChannel::Stable),
+            _ => Err(Error::InvalidReleaseChannel(val.to_string())),
+        }
+    }
+
+    /// The prefix of the tags of the GitHub releases on the channel, e.g., `stable-2025.1.2.3`.
+    fn tag_prefix(&self) -> Option<&'static str> {
+        match self {
+            ReleaseChannel::Latest => None,
+            ReleaseChannel::Nightly => Some("nightly-"),
+            ReleaseChannel::Rc => Some("rc-"),
+            ReleaseChannel::Stable => Some("stable-"),
+        }
+    }
+}
+
+impl std::fmt::Display for ReleaseChannel {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReleaseChannel::Latest => write!(f, "latest"),
+            ReleaseChannel::Nightly => write!(f, "nightly"),
+            ReleaseChannel::Rc => write!(f, "rc"),
+            ReleaseChannel::Stable => write!(f, "stable"),
+        }
+    }
+}
+
+/// The versions a channel resolved to when an environment was deployed.
+///
+/// This is recorded in the environment details, so the deployment can be reproduced after the
+/// channel has moved on.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ResolvedRelease {
+    pub ant_version: Version,
+    pub antctl_version: Version,
+    pub antnode_version: Version,
+    pub channel: ReleaseChannel,
+    /// The tag of the GitHub release the versions were taken from. There is no tag for the
+    /// `latest` channel.
+    pub tag: Option<String>,
+}
+
+impl ResolvedRelease {
+    pub fn print(&self) {
+        match &self.tag {
+            Some(tag) => println!("Release channel: {} ({tag})", self.channel),
+            None => println!("Release channel: {}", self.channel),
+        }
+        println!("ant version: {}", self.ant_version);
+        println!("antctl version: {}", self.antctl_version);
+        println!("antnode version: {}", self.antnode_version);
+    }
+}
+
+#[derive(Deserialize)]
+struct GitHubRelease {
+    body: Option<String>,
+    draft: bool,
+    tag_name: String,
+}
+
+/// Resolve a channel to a concrete version of each binary.
+///
+/// The `latest` channel uses the latest version of each binary from the release repository. The
+/// other channels use the most recent GitHub release whose tag is on the channel, with the
+/// versions taken from the release notes.
+pub async fn resolve_release_channel(channel: ReleaseChannel) -> Result<ResolvedRelease> {
+    let Some(tag_prefix) = channel.tag_prefix() else {
+        return resolve_latest().await;
+    };
+
+    let response = reqwest::Client::new()
+        .get(RELEASES_URL)
+        .query(&[("per_page", "100")])
+        .header("User-Agent", "testnet-deploy")
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(Error::ReleaseChannelResolutionFailed {
+            channel: channel.to_string(),
+            error: format!("{status}: {}", response.text().await?),
+        });
+    }
+    let releases = response.json::<Vec<GitHubRelease>>().await?;
+
+    // The releases are returned with the most recent first.
+    let release = releases
+        .into_iter()
+        .find(|release| !release.draft && release.tag_name.starts_with(tag_prefix))
+        .ok_or_else(|| Error::ReleaseChannelResolutionFailed {
+            channel: channel.to_string(),
+            error: format!("there are no releases tagged '{tag_prefix}*'"),
+        })?;
+    debug!("Resolving the {channel} channel from {}", release.tag_name);
+
+    let body = release.body.unwrap_or_default();
+    let get_version = |bin_name: &str| {
+        parse_binary_version(&body, bin_name).ok_or_else(|| Error::ReleaseChannelResolutionFailed {
+            channel: channel.to_string(),
+            error: format!(
+                "the notes for {} do not contain a version for {bin_name}",
+                release.tag_name
+            ),
+        })
+    };
+    Ok(ResolvedRelease {
+        ant_version: get_version("ant")?,
+        antctl_version: get_version("antctl")?,
+        antnode_version: get_version("antnode")?,
+        channel,
+        tag: Some(release.tag_name.clone()),
+    })
+}
+
+async fn resolve_latest() -> Result<ResolvedRelease> {
+    let release_repo = <dyn AntReleaseRepoActions>::default_config();
+    Ok(ResolvedRelease {
+        ant_version: get_latest_version(&*release_repo, ReleaseType::Ant).await?,
+        antctl_version: get_latest_version(&*release_repo, ReleaseType::AntCtl).await?,
+        antnode_version: get_latest_version(&*release_repo, ReleaseType::AntNode).await?,
+        channel: ReleaseChannel::Latest,
+        tag: None,
+    })
+}
+
+async fn get_latest_version(
+    release_repo: &dyn AntReleaseRepoActions,
+    release_type: ReleaseType,
+) -> Result<Version> {
+    let version = release_repo
+        .get_latest_version(&release_type)
+        .await
+        .map_err(|err| Error::ReleaseChannelResolutionFailed {
+            channel: ReleaseChannel::Latest.to_string(),
+            error: format!("could not get the latest version of {release_type}: {err}"),
+        })?;
+    Version::parse(&version.to_string()).map_err(|err| Error::ReleaseChannelResolutionFailed {
+        channel: ReleaseChannel::Latest.to_string(),
+        error: err.to_string(),
+    })
+}
+
+/// Find the version of a binary in release notes.
+///
+/// The notes list the binaries in forms like `` * `antnode`: v0.3.1 `` or `| antnode | 0.3.1 |`,
+/// so the version is the first token after the binary name that parses as one.
+fn parse_binary_version(body: &str, bin_name: &str) -> Option<Version> {
+    for line in body.lines() {
+        let mut tokens = line
+            .split(|c: char| c.is_whitespace() || c == '|' || c == ':')
+            .map(|token| token.trim_matches(|c| matches!(c, '`' | '*' | '-' | ',')))
+            .filter(|token| !token.is_empty());
+        if tokens.by_ref().any(|token| token == bin_name) {
+            if let Some(version) =
+                tokens.find_map(|token| Version::parse(token.trim_start_matches('v')).ok())
+            {
+                return Some(version);
+            }
+        }
+    }
+    None
+}
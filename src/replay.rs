@@ -0,0 +1,323 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    error::{Error, Result},
+    inventory::DeploymentInventory,
+    ssh::{SshClient, DEFAULT_COMMAND_TIMEOUT},
+};
+use serde::Deserialize;
+use std::{collections::HashSet, path::Path, time::Duration};
+use tracing::debug;
+
+/// The uploader user whose wallet is used to pay for the uploads on the remote VM.
+const REMOTE_UPLOADER_USER: &str = "ant1";
+
+/// A client operation in a recorded trace.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ReplayOperation {
+    /// Download a file, either from an address, or one that was uploaded earlier in the trace.
+    Download {
+        address: Option<String>,
+        upload_id: Option<String>,
+    },
+    /// Upload a file of random data of the given size.
+    ///
+    /// The `id` allows a later download in the trace to refer to the file, since the addresses
+    /// in the recorded trace will not exist on the testnet.
+    Upload { id: Option<String>, size_kb: u64 },
+}
+
+/// An entry in a recorded trace, which is a JSON object on each line, e.g.:
+///
+/// `{"offset_ms": 1500, "op": "upload", "id": "photo", "size_kb": 2048}`
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ReplayEntry {
+    /// The time of the operation, relative to the start of the trace.
+    pub offset_ms: u64,
+    #[serde(flatten)]
+    pub operation: ReplayOperation,
+}
+
+/// Parse a trace of client operations, one JSON object per line. Blank lines are ignored.
+///
+/// The entries are sorted by their offset, and every download must refer to either an address or
+/// an upload that comes before it. Since the values are used in a script, addresses and IDs can
+/// only contain letters, digits, '-' and '_'.
+pub fn parse_replay_trace(contents: &str) -> Result<Vec<ReplayEntry>> {
+    let mut entries = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: ReplayEntry =
+            serde_json::from_str(line).map_err(|err| Error::ReplayTraceParseError {
+                line: index + 1,
+                error: err.to_string(),
+            })?;
+        entries.push(entry);
+    }
+    entries.sort_by_key(|e| e.offset_ms);
+
+    let is_safe = |value: &str| {
+        !value.is_empty()
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    };
+    let mut upload_ids = HashSet::new();
+    for entry in entries.iter() {
+        let values = match &entry.operation {
+            ReplayOperation::Download { address, upload_id } => [address, upload_id],
+            ReplayOperation::Upload { id, .. } => [id, &None],
+        };
+        if let Some(value) = values.into_iter().flatten().find(|v| !is_safe(v)) {
+            return Err(Error::ReplayTraceInvalid(format!(
+                "'{value}' at {}ms contains characters that are not allowed",
+                entry.offset_ms
+            )));
+        }
+
+        match &entry.operation {
+            ReplayOperation::Download { address, upload_id } => match (address, upload_id) {
+                (Some(_), None) => {}
+                (None, Some(upload_id)) if upload_ids.contains(upload_id) => {}
+                (None, Some(upload_id)) => {
+                    return Err(Error::ReplayTraceInvalid(format!(
+                        "the download at {}ms refers to '{upload_id}' before it is uploaded",
+                        entry.offset_ms
+                    )))
+                }
+                _ => {
+                    return Err(Error::ReplayTraceInvalid(format!(
+                        "the download at {}ms must have either an address or an upload_id",
+                        entry.offset_ms
+                    )))
+                }
+            },
+            ReplayOperation::Upload { id, .. } => {
+                if let Some(id) = id {
+                    upload_ids.insert(id.clone());
+                }
+            }
+        }
+    }
+    Ok(entries)
+}
+
+pub struct ReplayOptions {
+    /// The trace is replayed this many times faster than it was recorded.
+    pub speed: f64,
+    /// Run the client on this uploader VM rather than the first one.
+    pub uploader_vm_name: Option<String>,
+}
+
+/// The outcome of a single operation from the trace.
+#[derive(Clone, Debug)]
+pub struct ReplayResult {
+    pub address: Option<String>,
+    pub duration: Duration,
+    pub offset_ms: u64,
+    pub op: String,
+    pub succeeded: bool,
+}
+
+pub struct ReplayReport {
+    pub client_location: String,
+    pub results: Vec<ReplayResult>,
+}
+
+impl ReplayReport {
+    pub fn failed_count(&self) -> usize {
+        self.results.iter().filter(|r| !r.succeeded).count()
+    }
+
+    pub fn print(&self) {
+        println!("Trace replayed from {}", self.client_location);
+        for result in self.results.iter() {
+            println!(
+                "{:>8}ms {:<8} {:<6} {:.2}s {}",
+                result.offset_ms,
+                result.op,
+                if result.succeeded { "OK" } else { "FAILED" },
+                result.duration.as_secs_f64(),
+                result.address.as_deref().unwrap_or_default()
+            );
+        }
+        println!(
+            "{} operations: {} succeeded, {} failed",
+            self.results.len(),
+            self.results.len() - self.failed_count(),
+            self.failed_count()
+        );
+    }
+}
+
+/// Replay a trace of client operations against the environment, from an uploader VM.
+///
+/// The operations are run in order, each one starting at its offset in the trace, scaled by the
+/// speed. If an operation is still running when the next one is due, the next one starts as soon
+/// as it finishes. The genesis multiaddr from the inventory is used as the contact peer.
+pub async fn replay_trace(
+    inventory: &DeploymentInventory,
+    ssh_client: &SshClient,
+    entries: &[ReplayEntry],
+    options: &ReplayOptions,
+) -> Result<ReplayReport> {
+    if options.speed <= 0.0 {
+        return Err(Error::InvalidReplaySpeed);
+    }
+    let uploader_vm = match &options.uploader_vm_name {
+        Some(vm_name) => inventory
+            .uploader_vms
+            .iter()
+            .find(|vm| &vm.vm.name == vm_name)
+            .ok_or_else(|| {
+                Error::ReplayFailed(format!("there is no uploader VM named {vm_name}"))
+            })?,
+        None => inventory.uploader_vms.first().ok_or_else(|| {
+            Error::ReplayFailed("there are no uploader VMs to run the client on".to_string())
+        })?,
+    };
+    let peer = inventory.genesis_multiaddr.clone().ok_or_else(|| {
+        Error::ReplayFailed("the genesis multiaddr is not in the inventory".to_string())
+    })?;
+    let mut client_args = vec!["--peer".to_string(), peer, "--testnet".to_string()];
+    if let Some(network_id) = inventory.environment_details.network_id {
+        client_args.push("--network-id".to_string());
+        client_args.push(network_id.to_string());
+    }
+
+    let script = generate_replay_script(entries, &client_args.join(" "), options.speed);
+    let temp_dir = tempfile::tempdir()?;
+    let script_path = temp_dir.path().join("replay.sh");
+    std::fs::write(&script_path, script)?;
+    debug!(
+        "Replaying {} operations from {}",
+        entries.len(),
+        uploader_vm.vm.name
+    );
+    // The script runs for as long as the trace, so the timeout has to allow for that.
+    let trace_duration = entries
+        .last()
+        .map(|entry| Duration::from_millis((entry.offset_ms as f64 / options.speed) as u64))
+        .unwrap_or_default();
+    let output = ssh_client
+        .execute_script(
+            &uploader_vm.vm.public_ip_addr,
+            &inventory.ssh_user,
+            &script_path,
+            Some(trace_duration + DEFAULT_COMMAND_TIMEOUT),
+        )
+        .await?
+        .into_stdout_lines()?;
+
+    Ok(ReplayReport {
+        client_location: uploader_vm.vm.name.clone(),
+        results: parse_replay_output(&output),
+    })
+}
+
+/// Generate the script that runs the operations on the VM.
+///
+/// Each operation reports a `REPLAY_RESULT=<offset>,<op>,<succeeded>,<duration ms>,<address>`
+/// line.
+fn generate_replay_script(entries: &[ReplayEntry], client_args: &str, speed: f64) -> String {
+    let mut script = format!(
+        r#"#!/usr/bin/env bash
+set -o pipefail
+eval "$(sudo grep '^export ' /home/{REMOTE_UPLOADER_USER}/.profile)"
+work_dir=$(mktemp -d)
+trap 'rm -rf "$work_dir"' EXIT
+declare -A uploads
+replay_start=$(date +%s%3N)
+
+wait_until() {{
+  local delay=$((replay_start + $1 - $(date +%s%3N)))
+  if [ "$delay" -gt 0 ]; then
+    sleep "$((delay / 1000)).$(printf '%03d' $((delay % 1000)))"
+  fi
+}}
+
+upload() {{
+  local offset=$1 size_kb=$2 id=$3
+  dd if=/dev/urandom of="$work_dir/upload" bs=1K count="$size_kb" iflag=fullblock &> /dev/null
+  local started=$(date +%s%3N)
+  local output succeeded=false address=""
+  if output=$(ant {client_args} file upload "$work_dir/upload" 2>&1); then
+    address=$(echo "$output" | grep -oP 'At address: \K\S+')
+    [ -n "$address" ] && succeeded=true
+  fi
+  [ -n "$id" ] && uploads[$id]=$address
+  echo "REPLAY_RESULT=$offset,upload,$succeeded,$(($(date +%s%3N) - started)),$address"
+}}
+
+download() {{
+  local offset=$1 address=$2
+  local started=$(date +%s%3N)
+  local succeeded=false
+  if [ -n "$address" ] && ant {client_args} file download "$address" "$work_dir/download" > /dev/null 2>&1; then
+    succeeded=true
+  fi
+  rm -rf "$work_dir/download"
+  echo "REPLAY_RESULT=$offset,download,$succeeded,$(($(date +%s%3N) - started)),$address"
+}}
+
+"#
+    );
+
+    for entry in entries {
+        let scaled_offset_ms = (entry.offset_ms as f64 / speed).round() as u64;
+        script.push_str(&format!("wait_until {scaled_offset_ms}\n"));
+        match &entry.operation {
+            ReplayOperation::Download { address, upload_id } => {
+                let address = match (address, upload_id) {
+                    (Some(address), _) => address.clone(),
+                    (None, Some(upload_id)) => format!("${{uploads[{upload_id}]}}"),
+                    (None, None) => String::new(),
+                };
+                script.push_str(&format!("download {} \"{address}\"\n", entry.offset_ms));
+            }
+            ReplayOperation::Upload { id, size_kb } => {
+                script.push_str(&format!(
+                    "upload {} {size_kb} '{}'\n",
+                    entry.offset_ms,
+                    id.as_deref().unwrap_or_default()
+                ));
+            }
+        }
+    }
+    script
+}
+
+fn parse_replay_output(output: &[String]) -> Vec<ReplayResult> {
+    output
+        .iter()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line
+                .trim()
+                .strip_prefix("REPLAY_RESULT=")?
+                .split(',')
+                .collect();
+            if fields.len() != 5 {
+                return None;
+            }
+            Some(ReplayResult {
+                address: Some(fields[4].to_string()).filter(|a| !a.is_empty()),
+                duration: Duration::from_millis(fields[3].parse().ok()?),
+                offset_ms: fields[0].parse().ok()?,
+                op: fields[1].to_string(),
+                succeeded: fields[2] == "true",
+            })
+        })
+        .collect()
+}
+
+/// Read and parse a trace file.
+pub fn read_replay_trace(path: &Path) -> Result<Vec<ReplayEntry>> {
+    parse_replay_trace(&std::fs::read_to_string(path)?)
+}
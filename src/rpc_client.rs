@@ -5,8 +5,10 @@
 // Please see the LICENSE file for more details.
 
 use crate::{error::Result, run_external_command};
+use serde::Serialize;
 use std::{net::SocketAddr, path::PathBuf};
 
+#[derive(Clone, Debug, Serialize)]
 pub struct NodeInfo {
     pub endpoint: String,
     pub peer_id: String,
@@ -41,6 +43,19 @@ impl RpcClient {
 
         parse_output(output)
     }
+
+    /// Run any of the RPC client's commands against a node and return its output.
+    pub fn run(&self, rpc_address: SocketAddr, args: &[String]) -> Result<Vec<String>> {
+        let mut command_args = vec![rpc_address.to_string()];
+        command_args.extend(args.iter().cloned());
+        run_external_command(
+            self.binary_path.clone(),
+            self.working_directory_path.clone(),
+            command_args,
+            true,
+            false,
+        )
+    }
 }
 
 pub fn parse_output(output: Vec<String>) -> Result<NodeInfo> {
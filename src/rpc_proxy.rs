@@ -0,0 +1,59 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::error::{Error, Result};
+use rand::{distributions::Alphanumeric, Rng};
+use std::{net::IpAddr, path::PathBuf};
+
+/// The proxy for a node listens on its RPC port plus this offset. This must match
+/// `rpc_proxy_port_offset` in the `rpc_proxy` role.
+pub const RPC_PROXY_PORT_OFFSET: u16 = 2000;
+
+const AUTH_TOKEN_LENGTH: usize = 32;
+
+pub fn generate_auth_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(AUTH_TOKEN_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+/// The name the certificate for a VM is issued for.
+///
+/// Let's Encrypt does not issue certificates for IP addresses, so sslip.io is used to resolve a
+/// name to the public IP of the VM.
+pub fn get_rpc_proxy_hostname(ip_addr: &IpAddr) -> String {
+    format!("{}.sslip.io", ip_addr.to_string().replace('.', "-"))
+}
+
+/// The auth token is kept in the data directory, alongside the inventory, rather than in the
+/// environment details, which are publicly readable.
+pub fn get_auth_token_path(environment_name: &str) -> Result<PathBuf> {
+    let path = dirs_next::data_dir()
+        .ok_or_else(|| Error::CouldNotRetrieveDataDirectory)?
+        .join("safe")
+        .join("testnet-deploy");
+    if !path.exists() {
+        std::fs::create_dir_all(&path)?;
+    }
+    Ok(path.join(format!("{environment_name}-rpc-auth-token")))
+}
+
+pub fn save_auth_token(environment_name: &str, auth_token: &str) -> Result<PathBuf> {
+    let path = get_auth_token_path(environment_name)?;
+    std::fs::write(&path, auth_token)?;
+    Ok(path)
+}
+
+/// Returns `None` if the environment was not deployed from this machine.
+pub fn read_auth_token(environment_name: &str) -> Result<Option<String>> {
+    let path = get_auth_token_path(environment_name)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(std::fs::read_to_string(path)?.trim().to_string()))
+}
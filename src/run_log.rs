@@ -0,0 +1,287 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{debug, error};
+
+/// Every external command run by the deployer (Terraform, Ansible, SSH, rsync etc.) is recorded
+/// here, so we can produce a summary at the end of a run without each call site having to time
+/// itself.
+static RUN_LOG: Mutex<Vec<CommandRunRecord>> = Mutex::new(Vec::new());
+
+#[derive(Clone, Debug)]
+pub struct CommandRunRecord {
+    pub binary: String,
+    /// A short description of the command, e.g., the Terraform subcommand or the playbook name.
+    pub description: String,
+    pub duration: Duration,
+    /// The exit code of the process. This will be `None` if the process could not be spawned or
+    /// was terminated by a signal.
+    pub exit_code: Option<i32>,
+    /// The number of consecutive failed runs of the same command that preceded this one.
+    pub retries: u32,
+}
+
+impl CommandRunRecord {
+    pub fn succeeded(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// Used to time an external command and record it in the run log when it completes.
+pub struct CommandTimer {
+    binary: String,
+    description: String,
+    start: Instant,
+}
+
+impl CommandTimer {
+    pub fn start(binary: &str, args: &[String]) -> Self {
+        Self {
+            binary: binary.to_string(),
+            description: describe_args(binary, args),
+            start: Instant::now(),
+        }
+    }
+
+    pub fn finish(self, exit_code: Option<i32>) {
+        record(
+            self.binary,
+            self.description,
+            self.start.elapsed(),
+            exit_code,
+        );
+    }
+}
+
+fn record(binary: String, description: String, duration: Duration, exit_code: Option<i32>) {
+    let mut run_log = match RUN_LOG.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let retries = run_log
+        .iter()
+        .rev()
+        .take_while(|r| r.binary == binary && r.description == description && !r.succeeded())
+        .count() as u32;
+    let record = CommandRunRecord {
+        binary,
+        description,
+        duration,
+        exit_code,
+        retries,
+    };
+    if record.succeeded() {
+        debug!(
+            "{} ({}) completed in {} with exit code 0 (retries: {})",
+            record.binary,
+            record.description,
+            format_duration(record.duration),
+            record.retries
+        );
+    } else {
+        error!(
+            "{} ({}) failed after {} with exit code {:?} (retries: {})",
+            record.binary,
+            record.description,
+            format_duration(record.duration),
+            record.exit_code,
+            record.retries
+        );
+    }
+    run_log.push(record);
+}
+
+/// Get a copy of all the commands that have been recorded so far.
+pub fn get_records() -> Vec<CommandRunRecord> {
+    match RUN_LOG.lock() {
+        Ok(guard) => guard.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    }
+}
+
+/// Print a summary of all the external commands that were run.
+///
+/// Nothing is printed if no commands were run.
+pub fn print_summary() {
+    let records = get_records();
+    if records.is_empty() {
+        return;
+    }
+
+    let total: Duration = records.iter().map(|r| r.duration).sum();
+    let failed = records.iter().filter(|r| !r.succeeded()).count();
+    progress!("===========");
+    progress!("Run Summary");
+    progress!("===========");
+    for record in records.iter() {
+        let status = match record.exit_code {
+            Some(0) => "OK".to_string(),
+            Some(code) => format!("FAILED ({code})"),
+            None => "FAILED".to_string(),
+        };
+        if record.retries > 0 {
+            progress!(
+                "{}: {} [{}, retry {}]",
+                record.description,
+                format_duration(record.duration),
+                status,
+                record.retries
+            );
+        } else {
+            progress!(
+                "{}: {} [{}]",
+                record.description,
+                format_duration(record.duration),
+                status
+            );
+        }
+    }
+    progress!(
+        "Ran {} commands ({} failed) in {}",
+        records.len(),
+        failed,
+        format_duration(total)
+    );
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let total_seconds = duration.as_secs();
+    let minutes = total_seconds / 60;
+    let seconds = total_seconds % 60;
+    format!("{minutes}m{seconds:02}s")
+}
+
+/// Produce a short description of a command from its arguments.
+///
+/// Most of the arguments for our commands are flags and paths, which are not useful in a summary,
+/// so we try to pick out the part that identifies what was being done.
+fn describe_args(binary: &str, args: &[String]) -> String {
+    let binary_name = std::path::Path::new(binary)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| binary.to_string());
+    match binary_name.as_str() {
+        "ansible-playbook" => match args.iter().rev().find(|a| a.ends_with(".yml")) {
+            Some(playbook) => format!("{binary_name} {playbook}"),
+            None => binary_name.clone(),
+        },
+        "terraform" | "tofu" => {
+            let subcommand = args
+                .iter()
+                .take_while(|a| !a.starts_with('-'))
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(" ");
+            format!("{binary_name} {subcommand}")
+        }
+        "ssh" | "scp" | "rsync" => match get_remote_target(&binary_name, args) {
+            Some(target) => format!("{binary_name} {target}"),
+            None => binary_name.clone(),
+        },
+        _ => match args.first() {
+            Some(first) => format!("{binary_name} {first}"),
+            None => binary_name.clone(),
+        },
+    }
+}
+
+/// Find the remote host in the arguments for `ssh`, `scp` or `rsync`.
+///
+/// The options are skipped, along with the values of those that take one, so that an option like
+/// `-o ProxyCommand=ssh -W %h:%p user@gateway` is not mistaken for the host. For `ssh`, the host is
+/// the first remaining argument. For `scp` and `rsync`, it is the first that names a remote path.
+fn get_remote_target<'a>(binary_name: &str, args: &'a [String]) -> Option<&'a String> {
+    let options_with_value: &[&str] = match binary_name {
+        "ssh" => &[
+            "-B", "-b", "-c", "-D", "-E", "-e", "-F", "-I", "-i", "-J", "-L", "-l", "-m", "-O",
+            "-o", "-P", "-p", "-Q", "-R", "-S", "-W", "-w",
+        ],
+        "scp" => &["-c", "-D", "-F", "-i", "-J", "-l", "-o", "-P", "-S", "-X"],
+        _ => &["-B", "-e", "-f", "-M", "-T"],
+    };
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if options_with_value.contains(&arg.as_str()) {
+            args.next();
+        } else if !arg.starts_with('-') {
+            positional.push(arg);
+        }
+    }
+    match binary_name {
+        "ssh" => positional.first().copied(),
+        _ => positional
+            .into_iter()
+            .find(|arg| arg.contains('@') || arg.contains(':')),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color_eyre::Result;
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_describe_args_skips_the_proxy_command_for_ssh() -> Result<()> {
+        let args = to_args(&[
+            "-i",
+            "/home/user/.ssh/id_rsa",
+            "-q",
+            "-o",
+            "ProxyCommand=ssh -i /home/user/.ssh/id_rsa -W %h:%p root@10.0.0.1",
+            "root@10.0.1.5",
+            "systemctl",
+            "status",
+        ]);
+        assert_eq!("ssh root@10.0.1.5", describe_args("ssh", &args));
+        Ok(())
+    }
+
+    #[test]
+    fn test_describe_args_uses_the_remote_path_for_scp_and_rsync() -> Result<()> {
+        let args = to_args(&[
+            "-i",
+            "/home/user/.ssh/id_rsa",
+            "-o",
+            "StrictHostKeyChecking=no",
+            "/tmp/script.sh",
+            "root@10.0.1.5:/tmp/script.sh",
+        ]);
+        assert_eq!(
+            "scp root@10.0.1.5:/tmp/script.sh",
+            describe_args("/usr/bin/scp", &args)
+        );
+
+        let args = to_args(&[
+            "--archive",
+            "--filter=+ *.log*",
+            "-e",
+            "ssh -i /home/user/.ssh/id_rsa -l root -o ProxyCommand=ssh -W %h:%p root@10.0.0.1",
+            "10.0.1.5:/mnt/antnode-storage/log/",
+            "logs/node-1",
+        ]);
+        assert_eq!(
+            "rsync 10.0.1.5:/mnt/antnode-storage/log/",
+            describe_args("rsync", &args)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_describe_args_uses_the_binary_name_when_there_is_no_host() -> Result<()> {
+        let args = to_args(&["-o", "ProxyCommand=ssh -W %h:%p root@10.0.0.1"]);
+        assert_eq!("ssh", describe_args("ssh", &args));
+        Ok(())
+    }
+}
@@ -14,12 +14,34 @@ use tokio_stream::StreamExt;
 #[derive(Clone)]
 pub struct S3Repository {}
 
+/// An object in a bucket, as it is listed.
+#[derive(Clone, Debug)]
+pub struct S3Object {
+    pub key: String,
+    /// The time the object was last modified, as a Unix timestamp in seconds.
+    pub last_modified: Option<i64>,
+    pub size: i64,
+}
+
 impl S3Repository {
     pub async fn upload_file(
         &self,
         bucket_name: &str,
         file_path: &Path,
         public: bool,
+    ) -> Result<()> {
+        self.upload_file_with_content_type(bucket_name, file_path, public, None)
+            .await
+    }
+
+    /// Upload a file with an explicit content type, which is needed for objects that are viewed
+    /// directly in a browser, such as images.
+    pub async fn upload_file_with_content_type(
+        &self,
+        bucket_name: &str,
+        file_path: &Path,
+        public: bool,
+        content_type: Option<&str>,
     ) -> Result<()> {
         let conf = aws_config::from_env().region("eu-west-2").load().await;
         let client = Client::new(&conf);
@@ -29,7 +51,7 @@ impl S3Repository {
             .to_str()
             .ok_or_else(|| Error::FilenameNotRetrieved)?;
 
-        println!("Uploading {} to bucket {}", object_key, bucket_name);
+        progress!("Uploading {} to bucket {}", object_key, bucket_name);
 
         let mut file = tokio::fs::File::open(file_path).await?;
         let mut contents = Vec::new();
@@ -43,14 +65,61 @@ impl S3Repository {
         if public {
             req = req.acl(ObjectCannedAcl::PublicRead);
         }
+        if let Some(content_type) = content_type {
+            req = req.content_type(content_type);
+        }
         req.send().await.map_err(|_| {
             Error::PutS3ObjectError(object_key.to_string(), bucket_name.to_string())
         })?;
 
-        println!("{} has been uploaded to {}", object_key, bucket_name);
+        progress!("{} has been uploaded to {}", object_key, bucket_name);
         Ok(())
     }
 
+    /// Write an object only if there is no object with the same key, returning whether it was
+    /// written.
+    ///
+    /// The check is made by S3 as part of the write, using `If-None-Match`, so when two writers
+    /// race for the same key, only one of them succeeds.
+    pub async fn put_object_if_absent(
+        &self,
+        bucket_name: &str,
+        object_key: &str,
+        contents: Vec<u8>,
+    ) -> Result<bool> {
+        let conf = aws_config::from_env().region("eu-west-2").load().await;
+        let client = Client::new(&conf);
+        let result = client
+            .put_object()
+            .bucket(bucket_name)
+            .key(object_key)
+            .body(contents.into())
+            .customize()
+            .await
+            .map_err(|_| Error::PutS3ObjectError(object_key.to_string(), bucket_name.to_string()))?
+            .mutate_request(|req| {
+                req.headers_mut()
+                    .insert("If-None-Match", "*".parse().expect("a valid header value"));
+            })
+            .send()
+            .await;
+        match result {
+            Ok(_) => Ok(true),
+            Err(err) => {
+                let err = err.into_service_error();
+                match err.code() {
+                    // A concurrent conditional write for the same key can also be rejected with
+                    // a conflict, in which case the other writer is the one that succeeds.
+                    Some("PreconditionFailed") | Some("ConditionalRequestConflict") => Ok(false),
+                    _ => Err(Error::PutS3ObjectError(
+                        object_key.to_string(),
+                        bucket_name.to_string(),
+                    )),
+                }
+            }
+        }
+    }
+
     pub async fn download_object(
         &self,
         bucket_name: &str,
@@ -73,8 +142,37 @@ impl S3Repository {
         let conf = aws_config::from_env().region("eu-west-2").load().await;
         let client = Client::new(&conf);
         tokio::fs::create_dir_all(dest_path).await?;
-        self.list_and_retrieve(&client, bucket_name, folder_path, &dest_path.to_path_buf())
-            .await?;
+        self.list_and_retrieve(
+            &client,
+            bucket_name,
+            folder_path,
+            &dest_path.to_path_buf(),
+            None,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Download the objects in a folder that were last modified within a window, given as a pair
+    /// of Unix timestamps in seconds.
+    pub async fn download_folder_modified_within(
+        &self,
+        bucket_name: &str,
+        folder_path: &str,
+        dest_path: &Path,
+        modified_window: (i64, i64),
+    ) -> Result<()> {
+        let conf = aws_config::from_env().region("eu-west-2").load().await;
+        let client = Client::new(&conf);
+        tokio::fs::create_dir_all(dest_path).await?;
+        self.list_and_retrieve(
+            &client,
+            bucket_name,
+            folder_path,
+            &dest_path.to_path_buf(),
+            Some(modified_window),
+        )
+        .await?;
         Ok(())
     }
 
@@ -94,6 +192,46 @@ impl S3Repository {
         Ok(())
     }
 
+    /// List every object in a bucket whose key begins with the prefix, including those in
+    /// subfolders.
+    pub async fn list_objects(&self, bucket_name: &str, prefix: &str) -> Result<Vec<S3Object>> {
+        let conf = aws_config::from_env().region("eu-west-2").load().await;
+        let client = Client::new(&conf);
+        let mut objects = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            let output = client
+                .list_objects_v2()
+                .bucket(bucket_name)
+                .prefix(prefix)
+                .set_continuation_token(continuation_token)
+                .send()
+                .await
+                .map_err(|err| Error::ListS3ObjectsError {
+                    prefix: prefix.to_string(),
+                    error: err.meta().message().unwrap_or_default().to_string(),
+                })?;
+            objects.extend(
+                output
+                    .contents
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|object| {
+                        Some(S3Object {
+                            key: object.key?,
+                            last_modified: object.last_modified.map(|time| time.secs()),
+                            size: object.size,
+                        })
+                    }),
+            );
+            continuation_token = output.next_continuation_token;
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+        Ok(objects)
+    }
+
     pub async fn folder_exists(&self, bucket_name: &str, folder_path: &str) -> Result<bool> {
         let conf = aws_config::from_env().region("eu-west-2").load().await;
 
@@ -117,6 +255,54 @@ impl S3Repository {
         Ok(!output.contents().unwrap_or_default().is_empty())
     }
 
+    pub async fn object_exists(&self, bucket_name: &str, object_key: &str) -> Result<bool> {
+        let conf = aws_config::from_env().region("eu-west-2").load().await;
+        let client = Client::new(&conf);
+        match client
+            .head_object()
+            .bucket(bucket_name)
+            .key(object_key)
+            .send()
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(err) => {
+                let err = err.into_service_error();
+                if err.is_not_found() {
+                    Ok(false)
+                } else {
+                    Err(Error::HeadS3ObjectError {
+                        object_key: object_key.to_string(),
+                        error: err.meta().message().unwrap_or_default().to_string(),
+                    })
+                }
+            }
+        }
+    }
+
+    /// Copy an object to another key in the same bucket. The copy is publicly readable.
+    pub async fn copy_object(
+        &self,
+        bucket_name: &str,
+        source_key: &str,
+        dest_key: &str,
+    ) -> Result<()> {
+        let conf = aws_config::from_env().region("eu-west-2").load().await;
+        let client = Client::new(&conf);
+        client
+            .copy_object()
+            .bucket(bucket_name)
+            .copy_source(format!("{bucket_name}/{source_key}"))
+            .key(dest_key)
+            .acl(ObjectCannedAcl::PublicRead)
+            .send()
+            .await
+            .map_err(|_| {
+                Error::CopyS3ObjectError(source_key.to_string(), bucket_name.to_string())
+            })?;
+        Ok(())
+    }
+
     #[async_recursion]
     async fn list_and_retrieve(
         &self,
@@ -124,6 +310,7 @@ impl S3Repository {
         bucket_name: &str,
         prefix: &str,
         root_path: &PathBuf,
+        modified_window: Option<(i64, i64)>,
     ) -> Result<(), Error> {
         let output = client
             .list_objects_v2()
@@ -141,18 +328,30 @@ impl S3Repository {
         if let Some(common_prefixes) = output.common_prefixes {
             for cp in common_prefixes {
                 let next_prefix = cp.prefix.unwrap();
-                self.list_and_retrieve(client, bucket_name, &next_prefix, root_path)
-                    .await?;
+                self.list_and_retrieve(
+                    client,
+                    bucket_name,
+                    &next_prefix,
+                    root_path,
+                    modified_window,
+                )
+                .await?;
             }
         }
 
         if let Some(objects) = output.contents {
             for object in objects {
+                if let Some((from, to)) = modified_window {
+                    let modified = object.last_modified.map(|time| time.secs());
+                    if !modified.is_some_and(|modified| modified >= from && modified <= to) {
+                        continue;
+                    }
+                }
                 let object_key = object.key.unwrap();
                 let mut dest_file_path = root_path.clone();
                 dest_file_path.push(&object_key);
                 if dest_file_path.exists() {
-                    println!("Has already been retrieved in a previous sync.");
+                    progress!("Has already been retrieved in a previous sync.");
                     continue;
                 }
                 self.retrieve_object(client, bucket_name, &object_key, &dest_file_path)
@@ -209,7 +408,7 @@ impl S3Repository {
         object_key: &str,
         dest_path: &PathBuf,
     ) -> Result<()> {
-        println!("Retrieving {object_key} from S3...");
+        progress!("Retrieving {object_key} from S3...");
         let mut resp = client
             .get_object()
             .bucket(bucket_name)
@@ -236,7 +435,7 @@ impl S3Repository {
             file.write_all(&bytes).await?;
         }
 
-        println!("Saved at {}", dest_path.to_string_lossy());
+        progress!("Saved at {}", dest_path.to_string_lossy());
         Ok(())
     }
 
@@ -246,7 +445,7 @@ impl S3Repository {
         bucket_name: &str,
         object_key: &str,
     ) -> Result<()> {
-        println!("Deleting {object_key} from S3...");
+        progress!("Deleting {object_key} from S3...");
         client
             .delete_object()
             .bucket(bucket_name)
@@ -0,0 +1,149 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::inventory::{
+    get_cached_environment_names, get_data_directory, DeploymentInventory, NodeVirtualMachine,
+    VirtualMachine,
+};
+use color_eyre::Result;
+use std::net::IpAddr;
+use tracing::debug;
+
+/// A resource in a cached inventory that matched a search.
+#[derive(Clone, Debug)]
+pub struct SearchMatch {
+    pub environment_name: String,
+    /// What matched, e.g., `public IP` or `peer ID`.
+    pub field: &'static str,
+    pub value: String,
+    /// The VM the resource belongs to, if it belongs to one.
+    pub vm_name: Option<String>,
+}
+
+/// Search every cached inventory for a resource.
+///
+/// If the query is an IP address, it must match the public or private address of a VM exactly.
+/// Otherwise it is matched, ignoring case, against any part of the VM names, the peer IDs of the
+/// nodes, and the wallet and rewards addresses.
+///
+/// Only the cached inventories are searched, so an environment whose inventory has not been
+/// generated on this machine will not be found.
+pub fn search_cached_inventories(query: &str) -> Result<Vec<SearchMatch>> {
+    let query = query.trim();
+    let mut matches = Vec::new();
+    for name in get_cached_environment_names()? {
+        let path = get_data_directory()?.join(format!("{name}-inventory.json"));
+        let inventory = match DeploymentInventory::read(&path) {
+            Ok(inventory) => inventory,
+            Err(err) => {
+                // An inventory written by an older version of the tool may not deserialize.
+                debug!("Skipping the {name} inventory: {err}");
+                continue;
+            }
+        };
+        matches.extend(search_inventory(&inventory, query));
+    }
+    Ok(matches)
+}
+
+fn search_inventory(inventory: &DeploymentInventory, query: &str) -> Vec<SearchMatch> {
+    let mut matches = Vec::new();
+    let mut add = |field: &'static str, value: String, vm_name: Option<&str>| {
+        matches.push(SearchMatch {
+            environment_name: inventory.name.clone(),
+            field,
+            value,
+            vm_name: vm_name.map(|name| name.to_string()),
+        });
+    };
+
+    let node_vms: Vec<&NodeVirtualMachine> = inventory
+        .genesis_vm
+        .iter()
+        .chain(inventory.peer_cache_node_vms.iter())
+        .chain(inventory.node_vms.iter())
+        .chain(inventory.private_node_vms.iter())
+        .collect();
+    let vms: Vec<&VirtualMachine> = node_vms
+        .iter()
+        .map(|node_vm| &node_vm.vm)
+        .chain(
+            inventory
+                .uploader_vms
+                .iter()
+                .map(|uploader_vm| &uploader_vm.vm),
+        )
+        .chain(inventory.nat_gateway_vm.iter())
+        .chain(inventory.misc_vms.iter())
+        .collect();
+
+    if let Ok(ip_addr) = query.parse::<IpAddr>() {
+        for vm in vms.iter() {
+            if vm.public_ip_addr == ip_addr {
+                add("public IP", ip_addr.to_string(), Some(vm.name.as_str()));
+            }
+            if vm.private_ip_addr == ip_addr {
+                add("private IP", ip_addr.to_string(), Some(vm.name.as_str()));
+            }
+        }
+        return matches;
+    }
+
+    let query = query.to_lowercase();
+    let is_match = |value: &str| value.to_lowercase().contains(&query);
+    for vm in vms.iter() {
+        if is_match(&vm.name) {
+            add("VM name", vm.name.clone(), Some(vm.name.as_str()));
+        }
+    }
+    for node_vm in node_vms.iter() {
+        for peer_id in node_vm.rpc_endpoint.keys() {
+            if is_match(peer_id) {
+                add("peer ID", peer_id.clone(), Some(node_vm.vm.name.as_str()));
+            }
+        }
+    }
+    for uploader_vm in inventory.uploader_vms.iter() {
+        for wallet in uploader_vm.wallet_public_key.values() {
+            if is_match(wallet) {
+                add(
+                    "uploader wallet",
+                    wallet.clone(),
+                    Some(uploader_vm.vm.name.as_str()),
+                );
+            }
+        }
+    }
+    let details = &inventory.environment_details;
+    if is_match(&details.rewards_address) {
+        add("rewards address", details.rewards_address.clone(), None);
+    }
+    if let Some(address) = &details.funding_wallet_address {
+        if is_match(address) {
+            add("funding wallet", address.clone(), None);
+        }
+    }
+    matches
+}
+
+pub fn print_search_matches(query: &str, matches: &[SearchMatch]) {
+    if matches.is_empty() {
+        println!("Nothing in the cached inventories matched '{query}'");
+        return;
+    }
+    for search_match in matches.iter() {
+        match &search_match.vm_name {
+            Some(vm_name) => println!(
+                "{}: {} {} on {vm_name}",
+                search_match.environment_name, search_match.field, search_match.value
+            ),
+            None => println!(
+                "{}: {} {}",
+                search_match.environment_name, search_match.field, search_match.value
+            ),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    error::{Error, Result},
+    inventory::DeploymentNodeRegistries,
+    s3::S3Repository,
+};
+use ant_service_management::ServiceStatus;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs::File, io::Write};
+use tracing::debug;
+
+/// The bucket where the uptime history for each environment is stored.
+pub const UPTIME_BUCKET: &str = "sn-testnet-uptime";
+
+/// The node availability on a single VM at the time a sample was taken.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct VmUptimeSample {
+    pub running_nodes: usize,
+    pub total_nodes: usize,
+    pub vm_name: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UptimeSample {
+    /// The time the sample was taken, in the form `%Y-%m-%dT%H:%M:%SZ`.
+    pub timestamp: String,
+    pub vms: Vec<VmUptimeSample>,
+}
+
+impl UptimeSample {
+    /// Take a sample from the node registries retrieved by the `status` command.
+    ///
+    /// Nodes that have been removed are not counted. Any node that is not running is counted as
+    /// unavailable, as is every node on a VM whose registry could not be retrieved.
+    pub fn from_registries(registries: &[DeploymentNodeRegistries]) -> Self {
+        let mut vms = Vec::new();
+        for deployment_registries in registries.iter() {
+            for (vm_name, registry) in deployment_registries.retrieved_registries.iter() {
+                let nodes = registry
+                    .nodes
+                    .iter()
+                    .filter(|node| !matches!(node.status, ServiceStatus::Removed));
+                let total_nodes = nodes.clone().count();
+                let running_nodes = nodes
+                    .filter(|node| matches!(node.status, ServiceStatus::Running))
+                    .count();
+                vms.push(VmUptimeSample {
+                    running_nodes,
+                    total_nodes,
+                    vm_name: vm_name.clone(),
+                });
+            }
+            for vm_name in deployment_registries.failed_vms.iter() {
+                vms.push(VmUptimeSample {
+                    running_nodes: 0,
+                    total_nodes: 1,
+                    vm_name: vm_name.clone(),
+                });
+            }
+        }
+        Self {
+            timestamp: chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            vms,
+        }
+    }
+}
+
+/// The uptime samples taken over the lifetime of an environment.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct UptimeHistory {
+    pub samples: Vec<UptimeSample>,
+}
+
+impl UptimeHistory {
+    /// Retrieve the history for an environment.
+    ///
+    /// An empty history is returned if none has been recorded yet.
+    pub async fn retrieve(s3_repository: &S3Repository, environment_name: &str) -> Result<Self> {
+        let temp_file = tempfile::NamedTempFile::new()?;
+        match s3_repository
+            .download_object(
+                UPTIME_BUCKET,
+                &get_object_key(environment_name),
+                temp_file.path(),
+            )
+            .await
+        {
+            Ok(_) => {
+                let content = std::fs::read_to_string(temp_file.path())?;
+                Ok(serde_json::from_str(&content)?)
+            }
+            Err(Error::GetS3ObjectError(_, _)) => {
+                debug!("No uptime history found for {environment_name}");
+                Ok(Self::default())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    pub async fn save(&self, s3_repository: &S3Repository, environment_name: &str) -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join(get_object_key(environment_name));
+        let mut file = File::create(&path)?;
+        let json = serde_json::to_string(self)?;
+        file.write_all(json.as_bytes())?;
+        s3_repository
+            .upload_file(UPTIME_BUCKET, &path, false)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete(s3_repository: &S3Repository, environment_name: &str) -> Result<()> {
+        s3_repository
+            .delete_object(UPTIME_BUCKET, &get_object_key(environment_name))
+            .await
+    }
+
+    /// Get the availability percentage for each VM, across all the samples.
+    pub fn get_vm_availability(&self) -> BTreeMap<String, f64> {
+        let mut totals: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+        for sample in self.samples.iter() {
+            for vm in sample.vms.iter() {
+                let entry = totals.entry(vm.vm_name.clone()).or_default();
+                entry.0 += vm.running_nodes;
+                entry.1 += vm.total_nodes;
+            }
+        }
+        totals
+            .into_iter()
+            .map(|(vm_name, (running, total))| (vm_name, get_percentage(running, total)))
+            .collect()
+    }
+
+    /// Get the availability percentage for the whole fleet, across all the samples.
+    pub fn get_fleet_availability(&self) -> f64 {
+        let (running, total) = self
+            .samples
+            .iter()
+            .flat_map(|sample| sample.vms.iter())
+            .fold((0, 0), |(running, total), vm| {
+                (running + vm.running_nodes, total + vm.total_nodes)
+            });
+        get_percentage(running, total)
+    }
+
+    pub fn print_report(&self) {
+        println!("==================");
+        println!("Availability (SLO)");
+        println!("==================");
+        let (Some(first), Some(last)) = (self.samples.first(), self.samples.last()) else {
+            println!("No uptime samples have been recorded");
+            return;
+        };
+        println!(
+            "{} samples between {} and {}",
+            self.samples.len(),
+            first.timestamp,
+            last.timestamp
+        );
+        for (vm_name, availability) in self.get_vm_availability().iter() {
+            println!("{vm_name}: {availability:.2}%");
+        }
+        println!("Fleet: {:.2}%", self.get_fleet_availability());
+    }
+}
+
+fn get_object_key(environment_name: &str) -> String {
+    format!("{environment_name}-uptime.json")
+}
+
+fn get_percentage(running: usize, total: usize) -> f64 {
+    if total == 0 {
+        return 100.0;
+    }
+    running as f64 / total as f64 * 100.0
+}
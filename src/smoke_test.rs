@@ -0,0 +1,244 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    error::{Error, Result},
+    inventory::DeploymentInventory,
+    run_external_command,
+    ssh::SshClient,
+};
+use rand::RngCore;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tracing::debug;
+
+/// The uploader user whose wallet is used to pay for the upload on the remote VM.
+const REMOTE_UPLOADER_USER: &str = "ant1";
+
+pub struct SmokeTestOptions {
+    pub file_size_kb: u64,
+    /// Run the client on this machine, using the `ant` binary at this path, rather than on an
+    /// uploader VM. The wallet is taken from the `SECRET_KEY` variable in the local environment.
+    pub local_client_path: Option<PathBuf>,
+}
+
+pub struct SmokeTestReport {
+    pub address: String,
+    pub client_location: String,
+    pub download_duration: Duration,
+    pub file_size_kb: u64,
+    pub hash_matched: bool,
+    pub upload_duration: Duration,
+}
+
+impl SmokeTestReport {
+    pub fn print(&self) {
+        println!("Smoke test run from {}", self.client_location);
+        println!("File size: {}KB", self.file_size_kb);
+        println!("Address: {}", self.address);
+        println!("Upload time: {:.2}s", self.upload_duration.as_secs_f64());
+        println!(
+            "Download time: {:.2}s",
+            self.download_duration.as_secs_f64()
+        );
+        if self.hash_matched {
+            println!("The downloaded file matched the uploaded file");
+        } else {
+            println!("The downloaded file did NOT match the uploaded file");
+        }
+    }
+}
+
+/// Upload a file of random data to the network, download it again, and verify its hash.
+///
+/// The genesis multiaddr from the inventory is used as the contact peer. By default the client
+/// runs on the first uploader VM, using the wallet of its first uploader.
+pub async fn run_smoke_test(
+    inventory: &DeploymentInventory,
+    ssh_client: &SshClient,
+    options: &SmokeTestOptions,
+) -> Result<SmokeTestReport> {
+    let peer = inventory.genesis_multiaddr.clone().ok_or_else(|| {
+        Error::SmokeTestFailed("the genesis multiaddr is not in the inventory".to_string())
+    })?;
+    let mut client_args = vec!["--peer".to_string(), peer, "--testnet".to_string()];
+    if let Some(network_id) = inventory.environment_details.network_id {
+        client_args.push("--network-id".to_string());
+        client_args.push(network_id.to_string());
+    }
+
+    match &options.local_client_path {
+        Some(client_path) => run_local(client_path, &client_args, options.file_size_kb),
+        None => run_remote(inventory, ssh_client, &client_args, options.file_size_kb).await,
+    }
+}
+
+fn run_local(
+    client_path: &Path,
+    client_args: &[String],
+    file_size_kb: u64,
+) -> Result<SmokeTestReport> {
+    let temp_dir = tempfile::tempdir()?;
+    let upload_path = temp_dir.path().join("upload");
+    let download_path = temp_dir.path().join("download");
+
+    let mut data = vec![0u8; (file_size_kb * 1024) as usize];
+    rand::thread_rng().fill_bytes(&mut data);
+    std::fs::File::create(&upload_path)?.write_all(&data)?;
+    let original_hash = Sha256::digest(&data);
+
+    let mut args = client_args.to_vec();
+    args.extend([
+        "file".to_string(),
+        "upload".to_string(),
+        upload_path.to_string_lossy().to_string(),
+    ]);
+    let started = Instant::now();
+    let output = run_external_command(
+        client_path.to_path_buf(),
+        temp_dir.path().to_path_buf(),
+        args,
+        true,
+        false,
+    )?;
+    let upload_duration = started.elapsed();
+    let address = get_uploaded_address(&output)?;
+    debug!("Uploaded smoke test file to {address}");
+
+    let mut args = client_args.to_vec();
+    args.extend([
+        "file".to_string(),
+        "download".to_string(),
+        address.clone(),
+        download_path.to_string_lossy().to_string(),
+    ]);
+    let started = Instant::now();
+    run_external_command(
+        client_path.to_path_buf(),
+        temp_dir.path().to_path_buf(),
+        args,
+        true,
+        false,
+    )?;
+    let download_duration = started.elapsed();
+    let downloaded_hash = Sha256::digest(std::fs::read(&download_path)?);
+
+    Ok(SmokeTestReport {
+        address,
+        client_location: "this machine".to_string(),
+        download_duration,
+        file_size_kb,
+        hash_matched: original_hash == downloaded_hash,
+        upload_duration,
+    })
+}
+
+async fn run_remote(
+    inventory: &DeploymentInventory,
+    ssh_client: &SshClient,
+    client_args: &[String],
+    file_size_kb: u64,
+) -> Result<SmokeTestReport> {
+    let uploader_vm = inventory.uploader_vms.first().ok_or_else(|| {
+        Error::SmokeTestFailed(
+            "there are no uploader VMs to run the client on; use a local client instead"
+                .to_string(),
+        )
+    })?;
+    let client_args = client_args.join(" ");
+    let script = format!(
+        r#"#!/usr/bin/env bash
+set -eo pipefail
+eval "$(sudo grep '^export ' /home/{REMOTE_UPLOADER_USER}/.profile)"
+work_dir=$(mktemp -d)
+trap 'rm -rf "$work_dir"' EXIT
+dd if=/dev/urandom of="$work_dir/upload" bs=1K count={file_size_kb} iflag=fullblock &> /dev/null
+original_hash=$(sha256sum "$work_dir/upload" | cut -d' ' -f1)
+
+started=$(date +%s%3N)
+output=$(ant {client_args} file upload "$work_dir/upload" 2>&1)
+echo "SMOKE_TEST_UPLOAD_MS=$(($(date +%s%3N) - started))"
+address=$(echo "$output" | grep -oP 'At address: \K\S+')
+echo "SMOKE_TEST_ADDRESS=$address"
+
+started=$(date +%s%3N)
+ant {client_args} file download "$address" "$work_dir/download" > /dev/null 2>&1
+echo "SMOKE_TEST_DOWNLOAD_MS=$(($(date +%s%3N) - started))"
+downloaded_hash=$(sha256sum "$work_dir/download" | cut -d' ' -f1)
+if [ "$original_hash" == "$downloaded_hash" ]; then
+  echo "SMOKE_TEST_HASH_MATCH=true"
+else
+  echo "SMOKE_TEST_HASH_MATCH=false"
+fi
+"#
+    );
+
+    let temp_dir = tempfile::tempdir()?;
+    let script_path = temp_dir.path().join("smoke_test.sh");
+    std::fs::write(&script_path, script)?;
+    ssh_client
+        .upload_file(
+            &uploader_vm.vm.public_ip_addr,
+            &inventory.ssh_user,
+            &script_path,
+            "/tmp/smoke_test.sh",
+        )
+        .await?;
+    let output = ssh_client
+        .execute(
+            &uploader_vm.vm.public_ip_addr,
+            &inventory.ssh_user,
+            "bash /tmp/smoke_test.sh",
+        )
+        .await?;
+    if !output.success() {
+        return Err(Error::SmokeTestFailed(format!(
+            "the script failed on {}: {}",
+            uploader_vm.vm.name,
+            output.stderr.trim()
+        )));
+    }
+    let output = output.stdout_lines();
+
+    let get_value = |key: &str| -> Result<String> {
+        output
+            .iter()
+            .find_map(|line| line.strip_prefix(&format!("{key}=")))
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| Error::SmokeTestFailed(format!("the {key} value was not reported")))
+    };
+    let get_duration = |key: &str| -> Result<Duration> {
+        get_value(key)?
+            .parse::<u64>()
+            .map(Duration::from_millis)
+            .map_err(|_| Error::SmokeTestFailed(format!("the {key} value is not a number")))
+    };
+
+    Ok(SmokeTestReport {
+        address: get_value("SMOKE_TEST_ADDRESS")?,
+        client_location: uploader_vm.vm.name.clone(),
+        download_duration: get_duration("SMOKE_TEST_DOWNLOAD_MS")?,
+        file_size_kb,
+        hash_matched: get_value("SMOKE_TEST_HASH_MATCH")? == "true",
+        upload_duration: get_duration("SMOKE_TEST_UPLOAD_MS")?,
+    })
+}
+
+fn get_uploaded_address(output: &[String]) -> Result<String> {
+    let re = Regex::new(r"At address: (\S+)")?;
+    output
+        .iter()
+        .find_map(|line| re.captures(line).map(|captures| captures[1].to_string()))
+        .ok_or_else(|| {
+            Error::SmokeTestFailed("could not obtain the address of the uploaded file".to_string())
+        })
+}
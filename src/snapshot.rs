@@ -0,0 +1,233 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    ansible::{extra_vars::ExtraVarsDocBuilder, inventory::AnsibleInventoryType, AnsiblePlaybook},
+    error::{Error, Result},
+    inventory::DeploymentInventory,
+    TestnetDeployer,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, time::Duration};
+
+pub const SNAPSHOT_BUCKET: &str = "sn-testnet-snapshots";
+const SNAPSHOT_BUCKET_REGION: &str = "eu-west-2";
+
+/// A record of the data directory archives taken from an environment at a point in time.
+///
+/// The manifest is stored as `<id>.json` at the root of the bucket, and the archives under
+/// `<id>/`, with one archive per VM.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SnapshotManifest {
+    /// The names of the archives, which are the VM names without the environment name, e.g.,
+    /// `node-3`.
+    pub archives: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub environment_name: String,
+    pub id: String,
+    pub node_count: usize,
+}
+
+impl SnapshotManifest {
+    pub fn print(&self) {
+        println!(
+            "{}: {} VMs and {} nodes from {}, taken at {}",
+            self.id,
+            self.archives.len(),
+            self.node_count,
+            self.environment_name,
+            self.created_at.format("%Y-%m-%d %H:%M:%S UTC")
+        );
+    }
+}
+
+pub struct SnapshotOptions {
+    pub interval: Duration,
+    /// Leave the nodes stopped after the snapshot is taken, rather than starting them again.
+    pub leave_stopped: bool,
+}
+
+impl TestnetDeployer {
+    /// Take a snapshot of the data directories of every node VM in the environment.
+    ///
+    /// The nodes are stopped while their data is archived, so the snapshot is consistent for
+    /// each VM, but the VMs are not all stopped at the same moment.
+    pub async fn create_snapshot(
+        &self,
+        inventory: &DeploymentInventory,
+        options: &SnapshotOptions,
+    ) -> Result<SnapshotManifest> {
+        let id = format!("{}-{}", inventory.name, Utc::now().format("%Y%m%d%H%M%S"));
+        let mut extra_vars = get_snapshot_extra_vars(&inventory.name, &id, options.interval)?;
+        extra_vars.add_variable("leave_stopped", &options.leave_stopped.to_string());
+        let extra_vars = extra_vars.build();
+
+        println!("Creating the {id} snapshot...");
+        for inventory_type in AnsibleInventoryType::iter_node_type() {
+            self.ansible_provisioner.ansible_runner.run_playbook(
+                AnsiblePlaybook::SnapshotCreate,
+                inventory_type,
+                Some(extra_vars.clone()),
+            )?;
+        }
+
+        let node_vms = get_node_vms(inventory);
+        let manifest = SnapshotManifest {
+            archives: node_vms
+                .iter()
+                .map(|(name, _)| get_archive_name(&inventory.name, name))
+                .collect(),
+            created_at: Utc::now(),
+            environment_name: inventory.name.clone(),
+            id: id.clone(),
+            node_count: node_vms.iter().map(|(_, count)| count).sum(),
+        };
+        let temp_dir = tempfile::tempdir()?;
+        let manifest_path = temp_dir.path().join(format!("{id}.json"));
+        std::fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+        self.s3_repository
+            .upload_file(SNAPSHOT_BUCKET, &manifest_path, false)
+            .await?;
+        Ok(manifest)
+    }
+
+    /// Restore a snapshot to the environment, which can be a different environment from the one
+    /// it was taken from.
+    ///
+    /// The archive for each VM is matched by name, so the environment should have been deployed
+    /// with the same number of VMs and nodes as the one the snapshot was taken from. The VMs that
+    /// have no archive, and the archives that have no VM, are reported and skipped.
+    pub async fn restore_snapshot(
+        &self,
+        inventory: &DeploymentInventory,
+        id: &str,
+        interval: Duration,
+    ) -> Result<SnapshotManifest> {
+        let manifest = self.get_snapshot(id).await?;
+
+        let node_vms = get_node_vms(inventory);
+        let archives = manifest.archives.iter().collect::<HashSet<_>>();
+        let vm_archives = node_vms
+            .iter()
+            .map(|(name, _)| get_archive_name(&inventory.name, name))
+            .collect::<Vec<_>>();
+        for archive in vm_archives.iter() {
+            if !archives.contains(archive) {
+                println!("The snapshot has no archive for {archive}, so its data will be kept");
+            }
+        }
+        for archive in manifest.archives.iter() {
+            if !vm_archives.contains(archive) {
+                println!("There is no VM for the {archive} archive, so it will not be restored");
+            }
+        }
+        let node_count = node_vms.iter().map(|(_, count)| count).sum::<usize>();
+        if node_count != manifest.node_count {
+            println!(
+                "Warning: the snapshot has {} nodes, but {} has {node_count}",
+                manifest.node_count, inventory.name
+            );
+        }
+
+        let extra_vars = get_snapshot_extra_vars(&inventory.name, id, interval)?.build();
+        println!("Restoring the {id} snapshot to {}...", inventory.name);
+        for inventory_type in AnsibleInventoryType::iter_node_type() {
+            self.ansible_provisioner.ansible_runner.run_playbook(
+                AnsiblePlaybook::SnapshotRestore,
+                inventory_type,
+                Some(extra_vars.clone()),
+            )?;
+        }
+        Ok(manifest)
+    }
+
+    pub async fn get_snapshot(&self, id: &str) -> Result<SnapshotManifest> {
+        let key = format!("{id}.json");
+        if !self
+            .s3_repository
+            .object_exists(SNAPSHOT_BUCKET, &key)
+            .await?
+        {
+            return Err(Error::SnapshotNotFound(id.to_string()));
+        }
+        let temp_dir = tempfile::tempdir()?;
+        let manifest_path = temp_dir.path().join(&key);
+        self.s3_repository
+            .download_object(SNAPSHOT_BUCKET, &key, &manifest_path)
+            .await?;
+        Ok(serde_json::from_str(&std::fs::read_to_string(
+            manifest_path,
+        )?)?)
+    }
+
+    /// List the snapshots, optionally only those taken from an environment, oldest first.
+    pub async fn list_snapshots(&self, environment_name: Option<&str>) -> Result<Vec<String>> {
+        let mut ids = self
+            .s3_repository
+            .list_objects(SNAPSHOT_BUCKET, environment_name.unwrap_or_default())
+            .await?
+            .into_iter()
+            .filter_map(|object| {
+                if object.key.contains('/') {
+                    return None;
+                }
+                object.key.strip_suffix(".json").map(|id| id.to_string())
+            })
+            // The prefix would also match other environments whose names begin with this one.
+            .filter(|id| match environment_name {
+                Some(name) => id
+                    .strip_prefix(&format!("{name}-"))
+                    .is_some_and(|timestamp| timestamp.chars().all(|c| c.is_ascii_digit())),
+                None => true,
+            })
+            .collect::<Vec<_>>();
+        // The IDs end with the time, so for a single environment this is chronological.
+        ids.sort();
+        Ok(ids)
+    }
+}
+
+fn get_snapshot_extra_vars(
+    environment_name: &str,
+    id: &str,
+    interval: Duration,
+) -> Result<ExtraVarsDocBuilder> {
+    let (Ok(access_key_id), Ok(secret_access_key)) = (
+        std::env::var("AWS_ACCESS_KEY_ID"),
+        std::env::var("AWS_SECRET_ACCESS_KEY"),
+    ) else {
+        return Err(Error::AwsCredentialsNotSupplied);
+    };
+    let mut extra_vars = ExtraVarsDocBuilder::default();
+    extra_vars.add_variable("aws_access_key_id", &access_key_id);
+    extra_vars.add_variable("aws_region", SNAPSHOT_BUCKET_REGION);
+    extra_vars.add_variable("aws_secret_access_key", &secret_access_key);
+    extra_vars.add_variable("environment_name", environment_name);
+    extra_vars.add_variable("interval", &interval.as_millis().to_string());
+    extra_vars.add_variable("snapshot_s3_uri", &format!("s3://{SNAPSHOT_BUCKET}/{id}/"));
+    Ok(extra_vars)
+}
+
+/// The names of the VMs that run nodes, with the number of nodes on each.
+fn get_node_vms(inventory: &DeploymentInventory) -> Vec<(String, usize)> {
+    inventory
+        .genesis_vm
+        .iter()
+        .chain(inventory.peer_cache_node_vms.iter())
+        .chain(inventory.node_vms.iter())
+        .chain(inventory.private_node_vms.iter())
+        .map(|vm| (vm.vm.name.clone(), vm.node_count))
+        .collect()
+}
+
+/// The archive name used by the playbooks, which is the VM name without the environment name.
+fn get_archive_name(environment_name: &str, vm_name: &str) -> String {
+    vm_name
+        .strip_prefix(&format!("{environment_name}-"))
+        .unwrap_or(vm_name)
+        .to_string()
+}
@@ -7,14 +7,37 @@
 use crate::{
     error::{Error, Result},
     inventory::VirtualMachine,
-    run_external_command,
+    redact, run_log,
 };
-use log::debug;
+use async_trait::async_trait;
+use rand::Rng;
+use russh::{
+    client::{self, Handle},
+    ChannelMsg, Disconnect,
+};
+use russh_keys::{key, load_secret_key};
 use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
     net::IpAddr,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+use tokio::{
+    runtime::{Handle as RuntimeHandle, RuntimeFlavor},
+    sync::{Mutex, Semaphore},
 };
+use tracing::{debug, warn};
+
+/// How long a command run through the native client can take when `SSH_COMMAND_TIMEOUT_SECS` is
+/// not set.
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// How long an idle session is kept open when `SSH_CONTROL_PERSIST_SECS` is not set.
+pub const DEFAULT_CONTROL_PERSIST: Duration = Duration::from_secs(10 * 60);
+/// The number of SSH sessions that can be in use at once when `SSH_MAX_CONCURRENCY` is not set.
+pub const DEFAULT_MAX_CONCURRENCY: usize = 20;
 
 #[derive(Clone, Debug)]
 pub struct RoutedVms {
@@ -22,17 +45,244 @@ pub struct RoutedVms {
     gateway: IpAddr,
 }
 
+/// The file cloud-init writes when it has finished running the user data.
+const CLOUD_INIT_FINISHED_PATH: &str = "/var/lib/cloud/instance/boot-finished";
+
+/// Controls how long to wait for SSH to become available on a new VM.
+///
+/// A connection is attempted up to `max_attempts` times, sleeping for the interval plus a random
+/// jitter between attempts, so that many VMs created at the same time don't retry in lockstep. If
+/// `timeout` is set, the wait also gives up once that much time has passed, whichever comes first.
+#[derive(Clone, Debug)]
+pub struct SshRetryPolicy {
+    /// The timeout for each connection attempt.
+    pub connect_timeout: Duration,
+    pub interval: Duration,
+    /// The maximum random delay added to the interval.
+    pub jitter: Duration,
+    pub max_attempts: u32,
+    /// The overall time allowed for the wait.
+    pub timeout: Option<Duration>,
+    /// Only consider the VM available once cloud-init has finished, so that provisioning doesn't
+    /// start while the user data is still running.
+    pub wait_for_cloud_init: bool,
+}
+
+impl Default for SshRetryPolicy {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(5),
+            interval: Duration::from_secs(5),
+            jitter: Duration::from_secs(2),
+            max_attempts: 10,
+            timeout: None,
+            wait_for_cloud_init: false,
+        }
+    }
+}
+
+impl SshRetryPolicy {
+    /// Read the policy from the environment, using the default for any value that is not set.
+    ///
+    /// The variables are `SSH_CONNECT_TIMEOUT_SECS`, `SSH_RETRY_INTERVAL_SECS`,
+    /// `SSH_RETRY_JITTER_MS`, `SSH_RETRY_MAX_ATTEMPTS`, `SSH_RETRY_TIMEOUT_SECS`, and
+    /// `SSH_WAIT_FOR_CLOUD_INIT`, which enables the probe when it is `true` or `1`.
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            connect_timeout: Duration::from_secs(read_env_number(
+                "SSH_CONNECT_TIMEOUT_SECS",
+                default.connect_timeout.as_secs(),
+            )),
+            interval: Duration::from_secs(read_env_number(
+                "SSH_RETRY_INTERVAL_SECS",
+                default.interval.as_secs(),
+            )),
+            jitter: Duration::from_millis(read_env_number(
+                "SSH_RETRY_JITTER_MS",
+                default.jitter.as_millis() as u64,
+            )),
+            max_attempts: read_env_number("SSH_RETRY_MAX_ATTEMPTS", default.max_attempts as u64)
+                as u32,
+            timeout: match read_env_number("SSH_RETRY_TIMEOUT_SECS", 0) {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            wait_for_cloud_init: std::env::var("SSH_WAIT_FOR_CLOUD_INIT")
+                .map(|value| value == "true" || value == "1")
+                .unwrap_or(default.wait_for_cloud_init),
+        }
+    }
+
+    fn get_delay(&self) -> Duration {
+        let jitter_ms = self.jitter.as_millis() as u64;
+        if jitter_ms == 0 {
+            return self.interval;
+        }
+        self.interval + Duration::from_millis(rand::thread_rng().gen_range(0..=jitter_ms))
+    }
+}
+
+/// The output of a command run through the native client.
+#[derive(Clone, Debug, Default)]
+pub struct CommandOutput {
+    /// This is `None` if the command was killed by a signal.
+    pub exit_status: Option<u32>,
+    pub stderr: String,
+    pub stdout: String,
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool {
+        self.exit_status == Some(0)
+    }
+
+    pub fn stdout_lines(&self) -> Vec<String> {
+        self.stdout.lines().map(|line| line.to_string()).collect()
+    }
+
+    /// Get the stdout lines, or an error with the stderr if the command did not succeed.
+    ///
+    /// This is for callers that treat a non-zero exit status as a failure.
+    pub fn into_stdout_lines(self) -> Result<Vec<String>> {
+        if !self.success() {
+            return Err(Error::SshCommandFailed(format!(
+                "the command exited with {}: {}",
+                self.exit_status
+                    .map(|status| status.to_string())
+                    .unwrap_or_else(|| "a signal".to_string()),
+                self.stderr.trim()
+            )));
+        }
+        Ok(self.stdout_lines())
+    }
+}
+
+/// The output of a command before it is decoded, which is needed for copying files.
+#[derive(Default)]
+struct RawOutput {
+    exit_status: Option<u32>,
+    stderr: Vec<u8>,
+    stdout: Vec<u8>,
+}
+
+impl RawOutput {
+    fn into_command_output(self) -> CommandOutput {
+        CommandOutput {
+            exit_status: self.exit_status,
+            stderr: String::from_utf8_lossy(&self.stderr).to_string(),
+            stdout: String::from_utf8_lossy(&self.stdout).to_string(),
+        }
+    }
+}
+
+/// A session with a host, along with the session with the gateway it is routed through, which has
+/// to be kept open for as long as the tunnel is in use.
+struct NativeSession {
+    gateway: Option<Handle<AcceptHostKey>>,
+    handle: Handle<AcceptHostKey>,
+}
+
+impl NativeSession {
+    async fn close(self) {
+        let _ = self
+            .handle
+            .disconnect(Disconnect::ByApplication, "", "en")
+            .await;
+        if let Some(gateway) = self.gateway {
+            let _ = gateway
+                .disconnect(Disconnect::ByApplication, "", "en")
+                .await;
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.handle.is_closed()
+            || self
+                .gateway
+                .as_ref()
+                .is_some_and(|gateway| gateway.is_closed())
+    }
+}
+
+/// A session that is kept open after its command has completed, so a later command on the same
+/// host can reuse it.
+struct IdleSession {
+    last_used: Instant,
+    session: NativeSession,
+}
+
+/// Host keys are not checked, because the VMs are recreated with new keys on every deploy.
+struct AcceptHostKey;
+
+#[async_trait]
+impl client::Handler for AcceptHostKey {
+    type Error = russh::Error;
+
+    async fn check_server_key(
+        &mut self,
+        _server_public_key: &key::PublicKey,
+    ) -> std::result::Result<bool, Self::Error> {
+        Ok(true)
+    }
+}
+
 #[derive(Clone)]
 pub struct SshClient {
+    /// The time allowed for a command, including connecting.
+    pub command_timeout: Duration,
+    /// Keep the session with each host open for this long after it was last used, so later
+    /// commands reuse it rather than opening a new connection. `None` disables the reuse.
+    pub control_persist: Option<Duration>,
+    /// The WireGuard overlay address of each VM, keyed by its public IP address. When a VM has
+    /// one, connections use it rather than the public IP.
+    pub overlay_addresses: Arc<RwLock<BTreeMap<IpAddr, IpAddr>>>,
     pub private_key_path: PathBuf,
+    /// How to wait for SSH to become available on new VMs.
+    pub retry_policy: SshRetryPolicy,
     /// The list of VMs that are routed through a gateway.
     pub routed_vms: Arc<RwLock<Option<RoutedVms>>>,
+    /// The runtime the client was created on. It runs the sessions for the blocking calls that
+    /// are made from threads outside the runtime, like the rayon pool.
+    runtime: Option<RuntimeHandle>,
+    /// Limits the sessions in use at once, across all the clones of a client.
+    session_limiter: Arc<Semaphore>,
+    /// The idle sessions, keyed by the public IP address of the VM and the user.
+    sessions: Arc<Mutex<HashMap<(IpAddr, String), IdleSession>>>,
 }
 impl SshClient {
+    /// Create a client, reading the connection settings from the environment.
+    ///
+    /// `SSH_CONTROL_PERSIST_SECS` sets how long an idle session is kept open for reuse, and `0`
+    /// disables the reuse. `SSH_MAX_CONCURRENCY` limits the sessions in use at once.
+    /// `SSH_COMMAND_TIMEOUT_SECS` sets the timeout for each command. See
+    /// [`SshRetryPolicy::from_env`] for the variables for waiting on SSH.
     pub fn new(private_key_path: PathBuf) -> SshClient {
+        let control_persist = match read_env_number(
+            "SSH_CONTROL_PERSIST_SECS",
+            DEFAULT_CONTROL_PERSIST.as_secs(),
+        ) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        };
+        let max_concurrency =
+            read_env_number("SSH_MAX_CONCURRENCY", DEFAULT_MAX_CONCURRENCY as u64) as usize;
+        let command_timeout = Duration::from_secs(read_env_number(
+            "SSH_COMMAND_TIMEOUT_SECS",
+            DEFAULT_COMMAND_TIMEOUT.as_secs(),
+        ));
         SshClient {
+            command_timeout,
+            control_persist,
+            overlay_addresses: Arc::new(RwLock::new(BTreeMap::new())),
             private_key_path,
+            retry_policy: SshRetryPolicy::from_env(),
             routed_vms: Arc::new(RwLock::new(None)),
+            runtime: RuntimeHandle::try_current()
+                .ok()
+                .filter(|handle| handle.runtime_flavor() == RuntimeFlavor::MultiThread),
+            session_limiter: Arc::new(Semaphore::new(max_concurrency.max(1))),
+            sessions: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -42,7 +292,7 @@ impl SshClient {
         self.routed_vms
             .write()
             .map_err(|err| {
-                log::error!("Failed to set routed VMs: {err}");
+                tracing::error!("Failed to set routed VMs: {err}");
                 Error::SshSettingsRwLockError
             })?
             .replace(RoutedVms { vms, gateway });
@@ -52,77 +302,112 @@ impl SshClient {
         Ok(())
     }
 
-    pub fn get_private_key_path(&self) -> PathBuf {
-        self.private_key_path.clone()
+    /// Connect to the VMs on their WireGuard overlay addresses.
+    /// This updates all the copies of the `SshClient` that have been cloned.
+    pub fn set_overlay_addresses(&self, addresses: BTreeMap<IpAddr, IpAddr>) -> Result<()> {
+        *self.overlay_addresses.write().map_err(|err| {
+            tracing::error!("Failed to set overlay addresses: {err}");
+            Error::SshSettingsRwLockError
+        })? = addresses;
+        debug!("Overlay addresses have been set.");
+        Ok(())
     }
 
-    pub fn wait_for_ssh_availability(&self, ip_address: &IpAddr, user: &str) -> Result<()> {
-        let mut args = vec![
-            "-i".to_string(),
-            self.private_key_path.to_string_lossy().to_string(),
-            "-q".to_string(),
-            "-o".to_string(),
-            "BatchMode=yes".to_string(),
-            "-o".to_string(),
-            "ConnectTimeout=5".to_string(),
-            "-o".to_string(),
-            "StrictHostKeyChecking=no".to_string(),
-        ];
-        let routed_vm_read = self.routed_vms.read().map_err(|err| {
-            log::error!("Failed to read routed VMs: {err}");
+    /// The address to connect to for a VM, which is its overlay address if it has one.
+    fn get_connect_address(&self, ip_address: &IpAddr) -> Result<IpAddr> {
+        let overlay_addresses = self.overlay_addresses.read().map_err(|err| {
+            tracing::error!("Failed to read overlay addresses: {err}");
             Error::SshSettingsRwLockError
         })?;
-        if let Some((vm, gateway)) = routed_vm_read.as_ref().and_then(|routed_vms| {
+        Ok(*overlay_addresses.get(ip_address).unwrap_or(ip_address))
+    }
+
+    /// The private IP address of a VM and the gateway it is reached through, if it is routed.
+    fn get_route(&self, ip_address: &IpAddr) -> Result<Option<(IpAddr, IpAddr)>> {
+        let routed_vms = self.routed_vms.read().map_err(|err| {
+            tracing::error!("Failed to read routed VMs: {err}");
+            Error::SshSettingsRwLockError
+        })?;
+        Ok(routed_vms.as_ref().and_then(|routed_vms| {
             routed_vms
                 .vms
                 .iter()
                 .find(|vm| vm.public_ip_addr == *ip_address)
-                .map(|vm| (vm, routed_vms.gateway))
-        }) {
-            println!(
-                "Checking for SSH availability at {} ({ip_address}) via gateway {}...",
-                vm.private_ip_addr, gateway
-            );
-            args.push("-o".to_string());
-            args.push(format!(
-                "ProxyCommand=ssh -i {} -W %h:%p {}@{}",
-                self.private_key_path.to_string_lossy(),
-                user,
-                gateway
-            ));
-            args.push(format!("{user}@{}", vm.private_ip_addr));
+                .map(|vm| (vm.private_ip_addr, routed_vms.gateway))
+        }))
+    }
+
+    pub fn get_private_key_path(&self) -> PathBuf {
+        self.private_key_path.clone()
+    }
+
+    pub fn set_retry_policy(&mut self, retry_policy: SshRetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    /// Wait for SSH to become available on a VM, according to the retry policy.
+    ///
+    /// If the policy waits for cloud-init, a successful connection is only enough once the
+    /// cloud-init finished marker exists on the VM.
+    pub fn wait_for_ssh_availability(&self, ip_address: &IpAddr, user: &str) -> Result<()> {
+        let policy = &self.retry_policy;
+        match self.get_route(ip_address)? {
+            Some((private_ip_addr, gateway)) => progress!(
+                "Checking for SSH availability at {private_ip_addr} ({ip_address}) via gateway {}...",
+                self.get_connect_address(&gateway)?
+            ),
+            None => progress!(
+                "Checking for SSH availability at {}...",
+                self.get_connect_address(ip_address)?
+            ),
+        }
+        let command = if policy.wait_for_cloud_init {
+            format!("test -f {CLOUD_INIT_FINISHED_PATH}")
         } else {
-            println!("Checking for SSH availability at {ip_address}...");
-            args.push(format!("{user}@{ip_address}"));
-        }
-        args.push("bash".to_string());
-        args.push("--version".to_string());
-
-        let mut retries = 0;
-        let max_retries = 10;
-        while retries < max_retries {
-            let result = run_external_command(
-                PathBuf::from("ssh"),
-                std::env::current_dir()?,
-                args.clone(),
-                false,
-                false,
-            );
-            if result.is_ok() {
-                println!("SSH is available.");
-                return Ok(());
+            "bash --version".to_string()
+        };
+
+        let started = Instant::now();
+        let mut attempts = 0;
+        while attempts < policy.max_attempts {
+            match self.block_on(self.check_availability(ip_address, user, &command))? {
+                Ok(true) => {
+                    progress!("SSH is available.");
+                    return Ok(());
+                }
+                Ok(false) => {}
+                Err(err) => debug!("The SSH availability check on {ip_address} failed: {err}"),
+            }
+
+            attempts += 1;
+            if policy.wait_for_cloud_init {
+                progress!(
+                    "SSH is unavailable or cloud-init has not finished after {attempts} attempts."
+                );
             } else {
-                retries += 1;
-                println!("SSH is still unavailable after {retries} attempts.");
-                println!("Will sleep for 5 seconds then retry.");
-                std::thread::sleep(std::time::Duration::from_secs(5));
+                progress!("SSH is still unavailable after {attempts} attempts.");
+            }
+            let delay = policy.get_delay();
+            if policy
+                .timeout
+                .is_some_and(|timeout| started.elapsed() + delay > timeout)
+            {
+                progress!("The time allowed for SSH to become available has been exceeded.");
+                return Err(Error::SshUnavailable);
+            }
+            if attempts < policy.max_attempts {
+                progress!("Will sleep for {}ms then retry.", delay.as_millis());
+                std::thread::sleep(delay);
             }
         }
 
-        println!("The maximum number of connection retry attempts has been exceeded.");
+        progress!("The maximum number of connection retry attempts has been exceeded.");
         Err(Error::SshUnavailable)
     }
 
+    /// Run a command on a VM, blocking until it completes, and return the lines of its stdout.
+    ///
+    /// A non-zero exit status is an error. The output is printed unless it is suppressed.
     pub fn run_command(
         &self,
         ip_address: &IpAddr,
@@ -130,66 +415,86 @@ impl SshClient {
         command: &str,
         suppress_output: bool,
     ) -> Result<Vec<String>> {
-        let command_args: Vec<String> = command.split_whitespace().map(String::from).collect();
-        let mut args = vec![
-            "-i".to_string(),
-            self.private_key_path.to_string_lossy().to_string(),
-            "-q".to_string(),
-            "-o".to_string(),
-            "BatchMode=yes".to_string(),
-            "-o".to_string(),
-            "ConnectTimeout=30".to_string(),
-            "-o".to_string(),
-            "StrictHostKeyChecking=no".to_string(),
-        ];
-        let routed_vm_read = self.routed_vms.read().map_err(|err| {
-            log::error!("Failed to read routed VMs: {err}");
-            Error::SshSettingsRwLockError
-        })?;
+        let output = self.execute_blocking(ip_address, user, command)?;
+        if !suppress_output {
+            print_output(&output);
+        }
+        output.into_stdout_lines()
+    }
 
-        if let Some((vm, gateway)) = routed_vm_read.as_ref().and_then(|routed_vms| {
-            routed_vms
-                .vms
+    /// Run a command on each of the VMs at the same time, up to the concurrency limit, blocking
+    /// until they have all completed, and return the result for each VM in the same order.
+    pub fn run_command_on_vms(
+        &self,
+        vms: &[VirtualMachine],
+        user: &str,
+        command: &str,
+        suppress_output: bool,
+    ) -> Vec<(VirtualMachine, Result<Vec<String>>)> {
+        match self.block_on(self.execute_on_vms(vms, user, command)) {
+            Ok(results) => results
+                .into_iter()
+                .map(|(vm, result)| {
+                    let result = result.and_then(|output| {
+                        if !suppress_output {
+                            print_output(&output);
+                        }
+                        output.into_stdout_lines()
+                    });
+                    (vm, result)
+                })
+                .collect(),
+            Err(err) => vms
                 .iter()
-                .find(|vm| vm.public_ip_addr == *ip_address)
-                .map(|vm| (vm, routed_vms.gateway))
-        }) {
-            debug!(
-                "Running command '{}' on {} ({ip_address}) via gateway {gateway}...",
-                command, vm.private_ip_addr
-            );
-            args.push("-o".to_string());
-            args.push(format!(
-                "ProxyCommand=ssh -i {} -W %h:%p {user}@{gateway}",
-                self.private_key_path.to_string_lossy(),
-            ));
-            args.push(format!("{user}@{}", vm.private_ip_addr));
-        } else {
-            debug!(
-                "Running command '{}' on {}@{}...",
-                command, user, ip_address
-            );
-            args.push(format!("{user}@{ip_address}"));
+                .cloned()
+                .map(|vm| (vm, Err(Error::SshCommandFailed(err.to_string()))))
+                .collect(),
         }
-        args.extend(command_args);
+    }
 
-        let output = run_external_command(
-            PathBuf::from("ssh"),
-            std::env::current_dir()?,
-            args,
-            suppress_output,
-            false,
-        )?;
-        Ok(output)
+    /// Run a command on a VM, blocking until it completes.
+    ///
+    /// This is [`SshClient::execute`] for callers that are not async. It can be called on the
+    /// runtime or from other threads.
+    pub fn execute_blocking(
+        &self,
+        ip_address: &IpAddr,
+        user: &str,
+        command: &str,
+    ) -> Result<CommandOutput> {
+        self.block_on(self.execute(ip_address, user, command))?
     }
 
-    pub fn run_script(
+    /// Run a command on a VM.
+    ///
+    /// The stdout and stderr are captured separately, and the command fails with a timeout error
+    /// if it has not completed within the command timeout. A non-zero exit status is not treated
+    /// as an error; it is returned in the output. VMs routed through a gateway are reached through
+    /// a tunnel from the gateway session.
+    pub async fn execute(
         &self,
-        ip_address: IpAddr,
+        ip_address: &IpAddr,
         user: &str,
-        script: PathBuf,
-        suppress_output: bool,
-    ) -> Result<Vec<String>> {
+        command: &str,
+    ) -> Result<CommandOutput> {
+        debug!("Running command '{command}' on {user}@{ip_address}");
+        Ok(self
+            .run_native(ip_address, user, command, None, self.command_timeout)
+            .await?
+            .into_command_output())
+    }
+
+    /// Copy a script to a VM and run it with `bash`.
+    ///
+    /// The command timeout applies unless a longer timeout is given, for scripts that are expected
+    /// to run for a long time.
+    pub async fn execute_script(
+        &self,
+        ip_address: &IpAddr,
+        user: &str,
+        script: &Path,
+        timeout: Option<Duration>,
+    ) -> Result<CommandOutput> {
         let file_name = script
             .file_name()
             .ok_or_else(|| {
@@ -197,56 +502,446 @@ impl SshClient {
             })?
             .to_string_lossy()
             .to_string();
-        let args = vec![
-            "-i".to_string(),
-            self.private_key_path.to_string_lossy().to_string(),
-            "-q".to_string(),
-            "-o".to_string(),
-            "BatchMode=yes".to_string(),
-            "-o".to_string(),
-            "ConnectTimeout=30".to_string(),
-            "-o".to_string(),
-            "StrictHostKeyChecking=no".to_string(),
-            script.to_string_lossy().to_string(),
-            format!("{}@{}:/tmp/{}", user, ip_address, file_name),
-        ];
-        run_external_command(
-            PathBuf::from("scp"),
-            std::env::current_dir()?,
-            args,
-            suppress_output,
-            false,
+        let remote_path = format!("/tmp/{file_name}");
+        self.upload_file(ip_address, user, script, &remote_path)
+            .await?;
+        debug!("Running script {remote_path} on {user}@{ip_address}");
+        Ok(self
+            .run_native(
+                ip_address,
+                user,
+                &format!("bash {}", quote_for_shell(&remote_path)),
+                None,
+                timeout.unwrap_or(self.command_timeout),
+            )
+            .await?
+            .into_command_output())
+    }
+
+    /// Run a command on each of the VMs concurrently, up to the concurrency limit, and return the
+    /// output for each VM in the same order.
+    pub async fn execute_on_vms(
+        &self,
+        vms: &[VirtualMachine],
+        user: &str,
+        command: &str,
+    ) -> Vec<(VirtualMachine, Result<CommandOutput>)> {
+        let results = futures::future::join_all(
+            vms.iter()
+                .map(|vm| self.execute(&vm.public_ip_addr, user, command)),
         )
-        .map_err(|e| {
-            Error::SshCommandFailed(format!(
-                "Failed to copy script file to remote host {ip_address:?}: {e}"
-            ))
-        })?;
+        .await;
+        vms.iter().cloned().zip(results).collect()
+    }
+
+    /// Copy a local file to a path on a VM.
+    pub async fn upload_file(
+        &self,
+        ip_address: &IpAddr,
+        user: &str,
+        local_path: &Path,
+        remote_path: &str,
+    ) -> Result<()> {
+        let contents = tokio::fs::read(local_path).await?;
+        debug!("Uploading {local_path:?} to {user}@{ip_address}:{remote_path}");
+        let output = self
+            .run_native(
+                ip_address,
+                user,
+                &format!("cat > {}", quote_for_shell(remote_path)),
+                Some(&contents),
+                self.command_timeout,
+            )
+            .await?;
+        if output.exit_status != Some(0) {
+            return Err(Error::SshCommandFailed(format!(
+                "Failed to upload {local_path:?} to {ip_address}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
 
-        let args = vec![
-            "-i".to_string(),
-            self.private_key_path.to_string_lossy().to_string(),
-            "-q".to_string(),
-            "-o".to_string(),
-            "BatchMode=yes".to_string(),
-            "-o".to_string(),
-            "ConnectTimeout=30".to_string(),
-            "-o".to_string(),
-            "StrictHostKeyChecking=no".to_string(),
-            format!("{user}@{ip_address}"),
-            "bash".to_string(),
-            format!("/tmp/{file_name}"),
-        ];
-        let output = run_external_command(
-            PathBuf::from("ssh"),
-            std::env::current_dir()?,
-            args,
-            suppress_output,
-            false,
+    /// Copy a file from a VM to a local path.
+    ///
+    /// The file is read with `sudo`, so files owned by root, like the node data, can be copied.
+    pub async fn download_file(
+        &self,
+        ip_address: &IpAddr,
+        user: &str,
+        remote_path: &str,
+        local_path: &Path,
+    ) -> Result<()> {
+        debug!("Downloading {user}@{ip_address}:{remote_path} to {local_path:?}");
+        let output = self
+            .run_native(
+                ip_address,
+                user,
+                &format!("sudo cat {}", quote_for_shell(remote_path)),
+                None,
+                self.command_timeout,
+            )
+            .await?;
+        if output.exit_status != Some(0) {
+            return Err(Error::SshCommandFailed(format!(
+                "Failed to download {remote_path} from {ip_address}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        tokio::fs::write(local_path, &output.stdout).await?;
+        Ok(())
+    }
+
+    /// Run a future to completion for the blocking calls.
+    ///
+    /// On a thread of a multi-threaded runtime, the thread is handed over to blocking while the
+    /// future runs. Elsewhere the runtime the client was created on runs it. If there is none, a
+    /// runtime is created on a separate thread, because this thread may be running a
+    /// current-thread runtime, which cannot be blocked.
+    fn block_on<F>(&self, future: F) -> Result<F::Output>
+    where
+        F: Future + Send,
+        F::Output: Send,
+    {
+        if let Ok(handle) = RuntimeHandle::try_current() {
+            if handle.runtime_flavor() == RuntimeFlavor::MultiThread {
+                return Ok(tokio::task::block_in_place(|| handle.block_on(future)));
+            }
+        } else if let Some(runtime) = &self.runtime {
+            return Ok(runtime.block_on(future));
+        }
+
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let runtime = tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()?;
+                    Ok(runtime.block_on(future))
+                })
+                .join()
+                .unwrap_or_else(|_| {
+                    Err(Error::SshCommandFailed(
+                        "The thread running the SSH session panicked".to_string(),
+                    ))
+                })
+        })
+    }
+
+    /// Connect to a VM and run a command that checks whether it is ready, within the connect
+    /// timeout of the retry policy.
+    async fn check_availability(
+        &self,
+        ip_address: &IpAddr,
+        user: &str,
+        command: &str,
+    ) -> Result<bool> {
+        let _permit = self
+            .session_limiter
+            .acquire()
+            .await
+            .map_err(|_| Error::SshSessionLimiterClosed)?;
+        let connect_timeout = self
+            .retry_policy
+            .connect_timeout
+            .max(Duration::from_secs(1));
+        let session = tokio::time::timeout(connect_timeout, self.connect(ip_address, user))
+            .await
+            .map_err(|_| Error::SshCommandTimedOut {
+                ip_address: *ip_address,
+                timeout_secs: connect_timeout.as_secs(),
+            })??;
+        let result = tokio::time::timeout(
+            self.command_timeout,
+            run_on_session(&session.handle, command, None),
         )
-        .map_err(|e| {
-            Error::SshCommandFailed(format!("Failed to execute command on remote host: {e}"))
-        })?;
-        Ok(output)
+        .await;
+        let is_available = matches!(result, Ok(Ok(ref output)) if output.exit_status == Some(0));
+        self.put_session(ip_address, user, session, is_available)
+            .await;
+        Ok(is_available)
+    }
+
+    async fn run_native(
+        &self,
+        ip_address: &IpAddr,
+        user: &str,
+        command: &str,
+        stdin: Option<&[u8]>,
+        timeout: Duration,
+    ) -> Result<RawOutput> {
+        let _permit = self
+            .session_limiter
+            .acquire()
+            .await
+            .map_err(|_| Error::SshSessionLimiterClosed)?;
+        let timer = run_log::CommandTimer::start(
+            "ssh",
+            &[format!("{user}@{ip_address}"), command.to_string()],
+        );
+        let result = tokio::time::timeout(timeout, async {
+            let session = self.get_session(ip_address, user).await?;
+            let result = run_on_session(&session.handle, command, stdin).await;
+            self.put_session(ip_address, user, session, result.is_ok())
+                .await;
+            result
+        })
+        .await
+        .map_err(|_| Error::SshCommandTimedOut {
+            ip_address: *ip_address,
+            timeout_secs: timeout.as_secs(),
+        })
+        .and_then(|result| result);
+        timer.finish(
+            result
+                .as_ref()
+                .ok()
+                .and_then(|output| output.exit_status)
+                .map(|status| status as i32),
+        );
+        result
+    }
+
+    /// Take the idle session with a host, if one can be reused, or open a new one.
+    ///
+    /// Idle sessions that have expired or were closed by the other end are closed along the way.
+    async fn get_session(&self, ip_address: &IpAddr, user: &str) -> Result<NativeSession> {
+        if let Some(control_persist) = self.control_persist {
+            let mut sessions = self.sessions.lock().await;
+            let expired = sessions
+                .iter()
+                .filter(|(_, idle)| {
+                    idle.last_used.elapsed() > control_persist || idle.session.is_closed()
+                })
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>();
+            for key in expired {
+                if let Some(idle) = sessions.remove(&key) {
+                    idle.session.close().await;
+                }
+            }
+            if let Some(idle) = sessions.remove(&(*ip_address, user.to_string())) {
+                debug!("Reusing the session with {user}@{ip_address}");
+                return Ok(idle.session);
+            }
+        }
+        self.connect(ip_address, user).await
+    }
+
+    /// Keep a session for reuse, if reuse is enabled and the session is still usable, or close it.
+    async fn put_session(
+        &self,
+        ip_address: &IpAddr,
+        user: &str,
+        session: NativeSession,
+        is_usable: bool,
+    ) {
+        if self.control_persist.is_none() || !is_usable || session.is_closed() {
+            session.close().await;
+            return;
+        }
+        let mut sessions = self.sessions.lock().await;
+        let displaced = sessions.insert(
+            (*ip_address, user.to_string()),
+            IdleSession {
+                last_used: Instant::now(),
+                session,
+            },
+        );
+        if let Some(displaced) = displaced {
+            displaced.session.close().await;
+        }
+    }
+
+    async fn connect(&self, ip_address: &IpAddr, user: &str) -> Result<NativeSession> {
+        let config = Arc::new(client::Config::default());
+        let key_pair = Arc::new(load_secret_key(&self.private_key_path, None)?);
+
+        let mut session = match self.get_route(ip_address)? {
+            Some((private_ip_addr, gateway)) => {
+                let gateway = self.get_connect_address(&gateway)?;
+                let mut gateway_handle =
+                    client::connect(config.clone(), (gateway, 22), AcceptHostKey).await?;
+                authenticate(&mut gateway_handle, user, key_pair.clone(), &gateway).await?;
+                let channel = gateway_handle
+                    .channel_open_direct_tcpip(private_ip_addr.to_string(), 22, "127.0.0.1", 0)
+                    .await?;
+                let handle =
+                    client::connect_stream(config, channel.into_stream(), AcceptHostKey).await?;
+                NativeSession {
+                    gateway: Some(gateway_handle),
+                    handle,
+                }
+            }
+            None => NativeSession {
+                gateway: None,
+                handle: client::connect(
+                    config,
+                    (self.get_connect_address(ip_address)?, 22),
+                    AcceptHostKey,
+                )
+                .await?,
+            },
+        };
+
+        authenticate(&mut session.handle, user, key_pair, ip_address).await?;
+        Ok(session)
+    }
+}
+
+async fn authenticate(
+    handle: &mut Handle<AcceptHostKey>,
+    user: &str,
+    key_pair: Arc<key::KeyPair>,
+    ip_address: &IpAddr,
+) -> Result<()> {
+    if !handle.authenticate_publickey(user, key_pair).await? {
+        return Err(Error::SshAuthenticationFailed(format!(
+            "{user}@{ip_address}"
+        )));
+    }
+    Ok(())
+}
+
+/// Run a command on a new channel, optionally writing data to its stdin, and collect its output.
+async fn run_on_session(
+    handle: &Handle<AcceptHostKey>,
+    command: &str,
+    stdin: Option<&[u8]>,
+) -> Result<RawOutput> {
+    let mut channel = handle.channel_open_session().await?;
+    channel.exec(true, command).await?;
+    if let Some(stdin) = stdin {
+        channel.data(stdin).await?;
+        channel.eof().await?;
+    }
+
+    let mut output = RawOutput::default();
+    while let Some(msg) = channel.wait().await {
+        match msg {
+            ChannelMsg::Data { ref data } => output.stdout.extend_from_slice(data),
+            // Extended data of type 1 is stderr.
+            ChannelMsg::ExtendedData { ref data, ext: 1 } => output.stderr.extend_from_slice(data),
+            ChannelMsg::ExitStatus { exit_status } => output.exit_status = Some(exit_status),
+            _ => {}
+        }
+    }
+    Ok(output)
+}
+
+/// Print the output of a command in the same way as the output of the other external commands.
+fn print_output(output: &CommandOutput) {
+    for line in output.stdout.lines() {
+        progress!("{}", redact::redact(line));
+    }
+    for line in output.stderr.lines() {
+        eprintln!("{}", redact::redact(line));
+    }
+}
+
+/// Quote a value so the remote shell passes it to the command as a single argument, whatever
+/// spaces or quotes it contains.
+fn quote_for_shell(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn read_env_number(name: &str, default: u64) -> u64 {
+    match std::env::var(name) {
+        Ok(value) => value.parse().unwrap_or_else(|_| {
+            warn!("Ignoring {name}={value}, which is not a number. Using {default}.");
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color_eyre::{eyre::eyre, Result};
+
+    #[test]
+    fn test_quote_for_shell_passes_the_value_as_one_argument() -> Result<()> {
+        for value in [
+            "/tmp/plain.sh",
+            "/tmp/with space.sh",
+            "/tmp/it's.sh",
+            "/tmp/$(touch injected); echo 'done'",
+        ] {
+            let output = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(format!("printf '%s' {}", quote_for_shell(value)))
+                .output()?;
+            assert_eq!(value, String::from_utf8(output.stdout)?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_into_stdout_lines_returns_the_stdout_when_the_command_succeeds() -> Result<()> {
+        let output = CommandOutput {
+            exit_status: Some(0),
+            stderr: "warning\n".to_string(),
+            stdout: "first\nsecond\n".to_string(),
+        };
+        assert_eq!(
+            vec!["first".to_string(), "second".to_string()],
+            output.into_stdout_lines()?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_into_stdout_lines_returns_an_error_with_the_stderr_when_the_command_fails() -> Result<()>
+    {
+        let output = CommandOutput {
+            exit_status: Some(2),
+            stderr: "no such file\n".to_string(),
+            stdout: String::new(),
+        };
+        match output.into_stdout_lines() {
+            Err(Error::SshCommandFailed(message)) => {
+                assert_eq!("the command exited with 2: no such file", message)
+            }
+            other => return Err(eyre!("Expected SshCommandFailed, got {other:?}")),
+        }
+
+        let output = CommandOutput {
+            exit_status: None,
+            ..Default::default()
+        };
+        assert!(matches!(
+            output.into_stdout_lines(),
+            Err(Error::SshCommandFailed(message)) if message.contains("a signal")
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_block_on_runs_the_future_outside_a_runtime() -> Result<()> {
+        let client = SshClient::new(PathBuf::from("/nonexistent/id_rsa"));
+        assert_eq!(42, client.block_on(async { 42 })?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_block_on_runs_the_future_on_a_current_thread_runtime() -> Result<()> {
+        let client = SshClient::new(PathBuf::from("/nonexistent/id_rsa"));
+        assert_eq!(42, client.block_on(async { 42 })?);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_block_on_runs_the_future_on_a_multi_threaded_runtime() -> Result<()> {
+        let client = SshClient::new(PathBuf::from("/nonexistent/id_rsa"));
+        let from_other_thread = client.clone();
+        assert_eq!(42, client.block_on(async { 42 })?);
+        assert_eq!(
+            42,
+            std::thread::spawn(move || from_other_thread.block_on(async { 42 }))
+                .join()
+                .map_err(|_| eyre!("The thread panicked"))??
+        );
+        Ok(())
     }
 }
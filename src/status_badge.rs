@@ -0,0 +1,217 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{error::Result, inventory::DeploymentNodeRegistries, s3::S3Repository};
+use ant_service_management::ServiceStatus;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs::File, io::Write};
+
+/// The public bucket where the status summary and badge for each environment are published.
+pub const STATUS_BUCKET: &str = "sn-testnet-status";
+
+/// The environment is considered degraded when more than this percentage of nodes are not running.
+const DEGRADED_FAILURE_PCT: f64 = 5.0;
+/// The environment is considered down when more than this percentage of nodes are not running.
+const DOWN_FAILURE_PCT: f64 = 50.0;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthState {
+    Up,
+    Degraded,
+    Down,
+}
+
+impl HealthState {
+    pub fn from_failure_pct(failure_pct: f64, running_nodes: usize) -> Self {
+        if running_nodes == 0 || failure_pct > DOWN_FAILURE_PCT {
+            HealthState::Down
+        } else if failure_pct > DEGRADED_FAILURE_PCT {
+            HealthState::Degraded
+        } else {
+            HealthState::Up
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HealthState::Up => "up",
+            HealthState::Degraded => "degraded",
+            HealthState::Down => "down",
+        }
+    }
+
+    fn colour(&self) -> &'static str {
+        match self {
+            HealthState::Up => "#4c1",
+            HealthState::Degraded => "#dfb317",
+            HealthState::Down => "#e05d44",
+        }
+    }
+}
+
+/// A small summary of the health of an environment, which is published so dashboards and READMEs
+/// can show it without needing credentials for the tool.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EnvironmentStatus {
+    /// The most common `antnode` version among the running nodes.
+    pub antnode_version: Option<String>,
+    pub environment_name: String,
+    pub failure_pct: f64,
+    pub running_nodes: usize,
+    pub state: HealthState,
+    pub total_nodes: usize,
+    /// The time the status was taken, in the form `%Y-%m-%dT%H:%M:%SZ`.
+    pub updated_at: String,
+}
+
+impl EnvironmentStatus {
+    /// Build the status from the node registries retrieved by the `status` command.
+    ///
+    /// As with the health summary, nodes that have been removed are not counted, and each VM
+    /// whose registry could not be retrieved is counted as a single failed node.
+    pub fn from_registries(
+        environment_name: &str,
+        registries: &[DeploymentNodeRegistries],
+    ) -> Self {
+        let mut running_nodes = 0;
+        let mut total_nodes = 0;
+        let mut versions: HashMap<String, usize> = HashMap::new();
+        for deployment_registries in registries.iter() {
+            for (_, registry) in deployment_registries.retrieved_registries.iter() {
+                for node in registry.nodes.iter() {
+                    match node.status {
+                        ServiceStatus::Removed => continue,
+                        ServiceStatus::Running => {
+                            running_nodes += 1;
+                            *versions.entry(node.version.to_string()).or_default() += 1;
+                        }
+                        _ => {}
+                    }
+                    total_nodes += 1;
+                }
+            }
+            total_nodes += deployment_registries.failed_vms.len();
+        }
+
+        let failure_pct = if total_nodes == 0 {
+            0.0
+        } else {
+            (total_nodes - running_nodes) as f64 / total_nodes as f64 * 100.0
+        };
+        let antnode_version = versions
+            .into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+            .map(|(version, _)| version);
+        Self {
+            antnode_version,
+            environment_name: environment_name.to_string(),
+            failure_pct,
+            running_nodes,
+            state: HealthState::from_failure_pct(failure_pct, running_nodes),
+            total_nodes,
+            updated_at: chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+        }
+    }
+
+    /// Render a badge in the same style as the shields.io flat badges, e.g.
+    /// `alpha | up 1990/2000 v0.3.1`.
+    pub fn render_badge(&self) -> String {
+        let label = escape_xml(&self.environment_name);
+        let mut message = format!(
+            "{} {}/{}",
+            self.state.as_str(),
+            self.running_nodes,
+            self.total_nodes
+        );
+        if let Some(version) = &self.antnode_version {
+            message.push_str(&format!(" v{version}"));
+        }
+        let message = escape_xml(&message);
+
+        // There are no font metrics available here, so the width is approximated from the number
+        // of characters, which is good enough for the short strings on the badge.
+        let label_width = label.chars().count() * 7 + 10;
+        let message_width = message.chars().count() * 7 + 10;
+        let width = label_width + message_width;
+        let label_x = label_width / 2;
+        let message_x = label_width + message_width / 2;
+        format!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{label}: {message}">
+  <title>{label}: {message}</title>
+  <rect width="{label_width}" height="20" fill="#555"/>
+  <rect x="{label_width}" width="{message_width}" height="20" fill="{colour}"/>
+  <g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11">
+    <text x="{label_x}" y="14">{label}</text>
+    <text x="{message_x}" y="14">{message}</text>
+  </g>
+</svg>
+"##,
+            colour = self.state.colour(),
+        )
+    }
+
+    /// Publish the status as JSON, along with the badge, to the public status bucket.
+    ///
+    /// The objects are overwritten each time, so they always reflect the latest `status` run.
+    pub async fn publish(&self, s3_repository: &S3Repository) -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+
+        let json_path = temp_dir
+            .path()
+            .join(get_json_object_key(&self.environment_name));
+        let mut file = File::create(&json_path)?;
+        file.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        s3_repository
+            .upload_file_with_content_type(
+                STATUS_BUCKET,
+                &json_path,
+                true,
+                Some("application/json"),
+            )
+            .await?;
+
+        let badge_path = temp_dir
+            .path()
+            .join(get_badge_object_key(&self.environment_name));
+        std::fs::write(&badge_path, self.render_badge())?;
+        s3_repository
+            .upload_file_with_content_type(STATUS_BUCKET, &badge_path, true, Some("image/svg+xml"))
+            .await?;
+
+        println!(
+            "Published status for {}: https://{STATUS_BUCKET}.s3.eu-west-2.amazonaws.com/{}",
+            self.environment_name,
+            get_json_object_key(&self.environment_name)
+        );
+        Ok(())
+    }
+
+    pub async fn delete(s3_repository: &S3Repository, environment_name: &str) -> Result<()> {
+        s3_repository
+            .delete_object(STATUS_BUCKET, &get_json_object_key(environment_name))
+            .await?;
+        s3_repository
+            .delete_object(STATUS_BUCKET, &get_badge_object_key(environment_name))
+            .await
+    }
+}
+
+fn get_json_object_key(environment_name: &str) -> String {
+    format!("{environment_name}-status.json")
+}
+
+fn get_badge_object_key(environment_name: &str) -> String {
+    format!("{environment_name}-status.svg")
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,205 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    error::{Error, Result},
+    get_environment_details,
+    logging::get_run_log_directory,
+    redact::redact,
+    run_external_command,
+    s3::S3Repository,
+};
+use flate2::{write::GzEncoder, Compression};
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+use tracing::debug;
+
+/// The number of the most recent run logs for the environment that are included.
+const MAX_RUN_LOGS: usize = 5;
+/// The variables from the `.env` file whose values are included. The values of any others are
+/// replaced, since they are likely to be credentials.
+const SAFE_CONFIG_VARS: [&str; 7] = [
+    "AWS_DEFAULT_REGION",
+    "SLACK_DIGEST_WINDOW_MINS",
+    "SLACK_NOTIFICATION_MODE",
+    "SN_TESTNET_DEV_SECURITY_GROUP_ID",
+    "SN_TESTNET_DEV_SUBNET_ID",
+    "TERRAFORM_STATE_BUCKET_NAME",
+    "TESTNET_DEPLOY_ROLE",
+];
+const LAST_ERROR_FILE_NAME: &str = "last-error.txt";
+
+/// Save the error from a failed run, so it can be included in the next support bundle.
+///
+/// The error is redacted before it is written.
+pub fn save_last_error(command: &str, error: &str) -> Result<()> {
+    let path = get_data_dir()?.join(LAST_ERROR_FILE_NAME);
+    let mut file = File::create(path)?;
+    writeln!(
+        file,
+        "{} '{command}' failed:",
+        chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ")
+    )?;
+    writeln!(file, "{}", redact(error))?;
+    Ok(())
+}
+
+/// Collect the information needed to diagnose a problem with the deployer into a single archive,
+/// which can be attached to an issue.
+///
+/// The archive contains the tool versions, the `.env` configuration with the values of anything
+/// that could be a credential replaced, the error from the last failed run, and, if an environment
+/// is named, its recent run logs, environment details and provisioning report. All the text is
+/// passed through the redaction used for the terminal output.
+pub async fn create_support_bundle(
+    environment_name: Option<&str>,
+    output_dir: &Path,
+) -> Result<PathBuf> {
+    let temp_dir = tempfile::tempdir()?;
+    let bundle_dir = temp_dir.path();
+
+    std::fs::write(bundle_dir.join("versions.txt"), get_versions())?;
+    if let Some(config) = get_redacted_config()? {
+        std::fs::write(bundle_dir.join("config.env"), config)?;
+    }
+    let last_error_path = get_data_dir()?.join(LAST_ERROR_FILE_NAME);
+    if last_error_path.exists() {
+        copy_redacted(&last_error_path, &bundle_dir.join(LAST_ERROR_FILE_NAME))?;
+    }
+
+    if let Some(name) = environment_name {
+        let run_logs_dir = bundle_dir.join("run-logs");
+        std::fs::create_dir_all(&run_logs_dir)?;
+        for log_dir in get_recent_run_log_dirs(name)? {
+            let Some(timestamp) = log_dir.file_name() else {
+                continue;
+            };
+            let dest_dir = run_logs_dir.join(timestamp);
+            std::fs::create_dir_all(&dest_dir)?;
+            for entry in std::fs::read_dir(&log_dir)? {
+                let path = entry?.path();
+                if path.is_file() {
+                    copy_redacted(&path, &dest_dir.join(path.file_name().unwrap_or_default()))?;
+                }
+            }
+        }
+
+        match get_environment_details(name, &S3Repository {}).await {
+            Ok(details) => std::fs::write(
+                bundle_dir.join("environment-details.json"),
+                redact(&serde_json::to_string_pretty(&details)?),
+            )?,
+            Err(err) => {
+                // The problem being reported could be that the environment was never created.
+                debug!("Could not retrieve the environment details for {name}: {err}");
+                std::fs::write(
+                    bundle_dir.join("environment-details.txt"),
+                    format!("The environment details could not be retrieved: {err}\n"),
+                )?;
+            }
+        }
+
+        let report_path = get_data_dir()?.join(format!("{name}-provisioning-report.json"));
+        if report_path.exists() {
+            copy_redacted(&report_path, &bundle_dir.join("provisioning-report.json"))?;
+        }
+    }
+
+    if !output_dir.exists() {
+        std::fs::create_dir_all(output_dir)?;
+    }
+    let archive_path = output_dir.join(format!(
+        "testnet-deploy-support-{}-{}.tar.gz",
+        environment_name.unwrap_or("general"),
+        chrono::Utc::now().format("%Y%m%dT%H%M%S")
+    ));
+    let encoder = GzEncoder::new(File::create(&archive_path)?, Compression::default());
+    let mut archive = tar::Builder::new(encoder);
+    archive.append_dir_all("support-bundle", bundle_dir)?;
+    archive.into_inner()?.finish()?;
+    Ok(archive_path)
+}
+
+fn get_versions() -> String {
+    let mut versions = format!("testnet-deploy: {}\n", env!("CARGO_PKG_VERSION"));
+    for (binary, args) in [
+        ("terraform", vec!["version"]),
+        ("ansible", vec!["--version"]),
+    ] {
+        let output = run_external_command(
+            PathBuf::from(binary),
+            std::env::current_dir().unwrap_or_default(),
+            args.into_iter().map(|arg| arg.to_string()).collect(),
+            true,
+            true,
+        );
+        match output {
+            Ok(lines) => versions.push_str(&format!("{binary}:\n{}\n", lines.join("\n"))),
+            Err(err) => versions.push_str(&format!("{binary}: unavailable ({err})\n")),
+        }
+    }
+    versions
+}
+
+/// Read the `.env` file in the current directory, replacing the values of all but the variables
+/// known to be safe.
+fn get_redacted_config() -> Result<Option<String>> {
+    let path = Path::new(".env");
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut config = String::new();
+    for line in std::fs::read_to_string(path)?.lines() {
+        let line = line.trim();
+        match line.split_once('=') {
+            Some((name, value)) if !line.starts_with('#') => {
+                let name = name.trim();
+                if SAFE_CONFIG_VARS.contains(&name) {
+                    config.push_str(&format!("{name}={}\n", redact(value)));
+                } else {
+                    config.push_str(&format!("{name}=[REDACTED]\n"));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(Some(config))
+}
+
+fn get_recent_run_log_dirs(environment_name: &str) -> Result<Vec<PathBuf>> {
+    let dir = get_run_log_directory(environment_name)?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut log_dirs = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+    // The directories are named by timestamp, so they sort chronologically.
+    log_dirs.sort();
+    let skip = log_dirs.len().saturating_sub(MAX_RUN_LOGS);
+    Ok(log_dirs.into_iter().skip(skip).collect())
+}
+
+fn copy_redacted(src: &Path, dest: &Path) -> Result<()> {
+    let contents = std::fs::read(src)?;
+    std::fs::write(dest, redact(&String::from_utf8_lossy(&contents)))?;
+    Ok(())
+}
+
+fn get_data_dir() -> Result<PathBuf> {
+    let path = dirs_next::data_dir()
+        .ok_or_else(|| Error::CouldNotRetrieveDataDirectory)?
+        .join("safe")
+        .join("testnet-deploy");
+    if !path.exists() {
+        std::fs::create_dir_all(&path)?;
+    }
+    Ok(path)
+}
@@ -0,0 +1,257 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    ansible::{
+        extra_vars::ExtraVarsDocBuilder,
+        inventory::{generate_custom_environment_inventory, AnsibleInventoryType},
+        AnsiblePlaybook,
+    },
+    error::{Error, Result},
+    TestnetDeployer,
+};
+use chrono::NaiveDateTime;
+use parquet::{
+    basic::Compression,
+    data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type},
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::parser::parse_message_type,
+};
+use std::{fs::File, path::Path, sync::Arc, time::Duration};
+use tracing::{debug, warn};
+
+/// The file the samples are written to on each VM. This must match `sysstat_data_path` in the
+/// `sysstat` role.
+pub const SYSSTAT_DATA_PATH: &str = "/var/log/sysstat-perf/sa-perf";
+
+/// The reports extracted from the samples: CPU, memory, block devices and network interfaces.
+const SADF_REPORT_ARGS: &str = "-u -r -d -n DEV";
+
+/// The columns in the `sadf` output that identify a device rather than holding a value.
+const SADF_DEVICE_COLUMNS: [&str; 3] = ["CPU", "DEV", "IFACE"];
+
+const SYSSTAT_PARQUET_SCHEMA: &str = "
+    message sysstat_sample {
+        REQUIRED BYTE_ARRAY vm_name (UTF8);
+        REQUIRED INT64 timestamp (TIMESTAMP(MILLIS, true));
+        REQUIRED BYTE_ARRAY metric (UTF8);
+        OPTIONAL BYTE_ARRAY device (UTF8);
+        REQUIRED DOUBLE value;
+    }
+";
+
+/// A single value from a sysstat sample, e.g., the `%user` CPU time for one second on one VM.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SysstatSample {
+    /// The CPU, block device or network interface the value is for, if the metric has one.
+    pub device: Option<String>,
+    pub metric: String,
+    /// The time of the sample, in milliseconds since the epoch.
+    pub timestamp_millis: i64,
+    pub value: f64,
+    pub vm_name: String,
+}
+
+/// Parse the output of `sadf -d`, which is a series of semicolon-separated reports, each preceded
+/// by a header line beginning with '#'.
+///
+/// Every value is turned into its own sample, so the reports can be merged into a single table.
+pub fn parse_sadf_output(vm_name: &str, lines: &[String]) -> Vec<SysstatSample> {
+    let mut samples = Vec::new();
+    let mut header: Vec<String> = Vec::new();
+    for line in lines.iter().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+        if let Some(header_line) = line.strip_prefix('#') {
+            header = header_line
+                .trim()
+                .split(';')
+                .map(|c| c.to_string())
+                .collect();
+            continue;
+        }
+
+        // The first three columns are the hostname, interval and timestamp.
+        let fields: Vec<&str> = line.split(';').collect();
+        if header.len() != fields.len() || fields.len() < 4 {
+            continue;
+        }
+        let Ok(timestamp) =
+            NaiveDateTime::parse_from_str(fields[2].trim_end_matches(" UTC"), "%Y-%m-%d %H:%M:%S")
+        else {
+            continue;
+        };
+        let timestamp_millis = timestamp.and_utc().timestamp_millis();
+        let (device, first_value_index) = if SADF_DEVICE_COLUMNS.contains(&header[3].as_str()) {
+            (Some(fields[3].to_string()), 4)
+        } else {
+            (None, 3)
+        };
+        for (column, field) in header.iter().zip(fields.iter()).skip(first_value_index) {
+            if let Ok(value) = field.parse::<f64>() {
+                samples.push(SysstatSample {
+                    device: device.clone(),
+                    metric: column.clone(),
+                    timestamp_millis,
+                    value,
+                    vm_name: vm_name.to_string(),
+                });
+            }
+        }
+    }
+    samples
+}
+
+/// Write the samples to a parquet file, with one row per sample.
+pub fn write_sysstat_parquet(samples: &[SysstatSample], path: &Path) -> Result<()> {
+    let schema = Arc::new(parse_message_type(SYSSTAT_PARQUET_SCHEMA)?);
+    let properties = Arc::new(
+        WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build(),
+    );
+    let mut writer = SerializedFileWriter::new(File::create(path)?, schema, properties)?;
+    let mut row_group = writer.next_row_group()?;
+
+    let vm_names: Vec<ByteArray> = samples
+        .iter()
+        .map(|s| ByteArray::from(s.vm_name.as_str()))
+        .collect();
+    let timestamps: Vec<i64> = samples.iter().map(|s| s.timestamp_millis).collect();
+    let metrics: Vec<ByteArray> = samples
+        .iter()
+        .map(|s| ByteArray::from(s.metric.as_str()))
+        .collect();
+    let devices: Vec<ByteArray> = samples
+        .iter()
+        .filter_map(|s| s.device.as_deref().map(ByteArray::from))
+        .collect();
+    let device_def_levels: Vec<i16> = samples
+        .iter()
+        .map(|s| i16::from(s.device.is_some()))
+        .collect();
+    let values: Vec<f64> = samples.iter().map(|s| s.value).collect();
+
+    let mut column_index = 0;
+    while let Some(mut column) = row_group.next_column()? {
+        match column_index {
+            0 => {
+                column
+                    .typed::<ByteArrayType>()
+                    .write_batch(&vm_names, None, None)?;
+            }
+            1 => {
+                column
+                    .typed::<Int64Type>()
+                    .write_batch(&timestamps, None, None)?;
+            }
+            2 => {
+                column
+                    .typed::<ByteArrayType>()
+                    .write_batch(&metrics, None, None)?;
+            }
+            3 => {
+                column.typed::<ByteArrayType>().write_batch(
+                    &devices,
+                    Some(&device_def_levels),
+                    None,
+                )?;
+            }
+            _ => {
+                column
+                    .typed::<DoubleType>()
+                    .write_batch(&values, None, None)?;
+            }
+        }
+        column.close()?;
+        column_index += 1;
+    }
+    row_group.close()?;
+    writer.close()?;
+    Ok(())
+}
+
+impl TestnetDeployer {
+    /// Start collecting sysstat samples every second on all the node VMs, for the given duration.
+    ///
+    /// The collection stops by itself when the duration has elapsed. Any samples from a previous
+    /// collection are removed.
+    pub fn start_sysstat_collection(&self, duration: Duration) -> Result<()> {
+        if duration.as_secs() == 0 {
+            return Err(Error::InvalidSysstatDuration);
+        }
+        let mut extra_vars = ExtraVarsDocBuilder::default();
+        extra_vars.add_variable("sysstat_duration_secs", &duration.as_secs().to_string());
+        self.run_sysstat_playbook(extra_vars.build())
+    }
+
+    /// Stop the sysstat collection on all the node VMs before its duration has elapsed.
+    ///
+    /// The samples collected so far are kept, so they can still be retrieved.
+    pub fn stop_sysstat_collection(&self) -> Result<()> {
+        let mut extra_vars = ExtraVarsDocBuilder::default();
+        extra_vars.add_variable("stop_sysstat", "true");
+        self.run_sysstat_playbook(extra_vars.build())
+    }
+
+    /// Retrieve the sysstat samples from all the node VMs and merge them into a single parquet
+    /// file.
+    ///
+    /// VMs whose samples cannot be retrieved are skipped, with a warning. Returns the number of
+    /// samples written.
+    pub fn retrieve_sysstat_samples(&self, output_path: &Path) -> Result<usize> {
+        let vms = self.ansible_provisioner.get_all_node_inventory()?;
+        debug!("Retrieving the sysstat samples from {} VMs", vms.len());
+        let results = self.ssh_client.run_command_on_vms(
+            &vms,
+            "root",
+            &format!("sadf -d {SYSSTAT_DATA_PATH} -- {SADF_REPORT_ARGS}"),
+            true,
+        );
+        let mut samples = Vec::new();
+        for (vm, result) in results {
+            let lines = match result {
+                Ok(lines) => lines,
+                Err(err) => {
+                    warn!(
+                        "Failed to retrieve the sysstat samples from {}: {err}",
+                        vm.name
+                    );
+                    println!(
+                        "Skipping {}: the sysstat samples could not be retrieved",
+                        vm.name
+                    );
+                    continue;
+                }
+            };
+            samples.extend(parse_sadf_output(&vm.name, &lines));
+        }
+
+        if samples.is_empty() {
+            return Err(Error::NoSysstatSamples);
+        }
+        write_sysstat_parquet(&samples, output_path)?;
+        Ok(samples.len())
+    }
+
+    fn run_sysstat_playbook(&self, extra_vars: String) -> Result<()> {
+        let vms = self.ansible_provisioner.get_all_node_inventory()?;
+        println!("Running the sysstat playbook against {} VMs", vms.len());
+        generate_custom_environment_inventory(
+            &vms,
+            &self.environment_name,
+            &self
+                .ansible_provisioner
+                .ansible_runner
+                .working_directory_path
+                .join("inventory"),
+        )?;
+        self.ansible_provisioner.ansible_runner.run_playbook(
+            AnsiblePlaybook::Sysstat,
+            AnsibleInventoryType::Custom,
+            Some(extra_vars),
+        )?;
+        Ok(())
+    }
+}
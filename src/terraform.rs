@@ -5,16 +5,30 @@
 // Please see the LICENSE file for more details.
 
 use crate::{
+    ansible::inventory::AnsibleInventoryType,
     error::{Error, Result},
-    is_binary_on_path, run_external_command, CloudProvider,
+    inventory::VirtualMachine,
+    is_binary_on_path, run_external_command,
+    s3::S3Repository,
+    CloudProvider,
 };
-use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, path::PathBuf};
+use semver::Version;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{collections::HashMap, net::IpAddr, path::PathBuf};
+use tracing::debug;
 
 #[derive(Clone)]
 pub struct TerraformRunner {
     pub binary_path: PathBuf,
+    /// When set, `apply` will run `plan` instead, so no changes are made.
+    pub dry_run: bool,
+    /// Providers downloaded by `init` are kept here, so they can be reused by every working
+    /// directory and environment, rather than downloaded from the registry each time.
+    pub plugin_cache_dir: Option<PathBuf>,
     pub provider: CloudProvider,
+    /// A network mirror to install providers from, in place of `registry.terraform.io`. The URL
+    /// must use HTTPS and end with a slash.
+    pub provider_mirror_url: Option<String>,
     pub working_directory_path: PathBuf,
     pub state_bucket_name: String,
 }
@@ -33,10 +47,21 @@ impl TerraformRunner {
                 return Err(Error::ToolBinaryNotFound(bin_name));
             }
         }
+        let plugin_cache_dir = match std::env::var("TERRAFORM_PLUGIN_CACHE_DIR") {
+            Ok(dir) => Some(PathBuf::from(dir)),
+            Err(_) => dirs_next::data_dir().map(|dir| {
+                dir.join("safe")
+                    .join("testnet-deploy")
+                    .join("terraform-plugin-cache")
+            }),
+        };
         let runner = TerraformRunner {
             binary_path,
+            dry_run: false,
+            plugin_cache_dir,
             working_directory_path: working_directory,
             provider,
+            provider_mirror_url: std::env::var("TERRAFORM_PROVIDER_MIRROR_URL").ok(),
             state_bucket_name: state_bucket_name.to_string(),
         };
         Ok(runner)
@@ -47,7 +72,26 @@ impl TerraformRunner {
         vars: Vec<(String, String)>,
         tfvars_filename: Option<String>,
     ) -> Result<()> {
+        self.apply_with_replace(vars, tfvars_filename, &[])
+    }
+
+    /// Apply, forcing the resources at the given addresses to be destroyed and recreated, e.g.,
+    /// `digitalocean_droplet.genesis_bootstrap[0]`.
+    pub fn apply_with_replace(
+        &self,
+        vars: Vec<(String, String)>,
+        tfvars_filename: Option<String>,
+        replace_addresses: &[String],
+    ) -> Result<()> {
+        if self.dry_run {
+            println!("Dry run: running terraform plan rather than apply");
+            return self.plan(Some(vars), tfvars_filename);
+        }
+
         let mut args = vec!["apply".to_string(), "-auto-approve".to_string()];
+        for address in replace_addresses.iter() {
+            args.push(format!("-replace={address}"));
+        }
         if let Some(tfvars_filename) = tfvars_filename {
             args.push(format!("-var-file={}", tfvars_filename));
         }
@@ -90,6 +134,69 @@ impl TerraformRunner {
         Ok(())
     }
 
+    /// Run a refresh-only plan, which compares the real infrastructure with the state, and return
+    /// the resources that have changed outside of Terraform.
+    pub fn plan_drift(
+        &self,
+        vars: Vec<(String, String)>,
+        tfvars_filename: Option<String>,
+    ) -> Result<Vec<ResourceDrift>> {
+        let plan_dir = tempfile::tempdir()?;
+        let plan_path = plan_dir.path().join("drift.tfplan");
+        let mut args = vec![
+            "plan".to_string(),
+            "-refresh-only".to_string(),
+            "-detailed-exitcode".to_string(),
+            "-input=false".to_string(),
+            format!("-out={}", plan_path.to_string_lossy()),
+        ];
+        if let Some(tfvars_filename) = tfvars_filename {
+            args.push(format!("-var-file={}", tfvars_filename));
+        }
+        for var in vars.iter() {
+            args.push("-var".to_string());
+            args.push(format!("{}={}", var.0, var.1));
+        }
+
+        // With `-detailed-exitcode`, an exit code of 2 means the plan succeeded and there are
+        // changes, which here means there is drift.
+        match run_external_command(
+            self.binary_path.clone(),
+            self.working_directory_path.clone(),
+            args,
+            false,
+            false,
+        ) {
+            Ok(_) => return Ok(Vec::new()),
+            Err(Error::ExternalCommandRunFailed { exit_status, .. })
+                if exit_status.code() == Some(2) => {}
+            Err(err) => return Err(err),
+        }
+
+        let output = run_external_command(
+            self.binary_path.clone(),
+            self.working_directory_path.clone(),
+            vec![
+                "show".to_string(),
+                "-json".to_string(),
+                plan_path.to_string_lossy().to_string(),
+            ],
+            true,
+            false,
+        )?;
+        let output = output.first().ok_or(Error::TerraformShowFailed)?;
+        let plan: PlanOutput = serde_json::from_str(output)?;
+        Ok(plan
+            .resource_drift
+            .into_iter()
+            .map(|change| ResourceDrift {
+                actions: change.change.actions,
+                address: change.address,
+                resource_type: change.resource_type,
+            })
+            .collect())
+    }
+
     pub fn destroy(
         &self,
         vars: Option<Vec<(String, String)>>,
@@ -116,10 +223,17 @@ impl TerraformRunner {
     }
 
     pub fn init(&self) -> Result<()> {
+        self.write_cli_config()?;
+        // The lock file prevents two runs against the same workspace from writing the state at the
+        // same time. Older versions reject the setting with an error that doesn't mention the
+        // version, so the version is checked first.
+        self.ensure_state_locking_supported()?;
         let args = vec![
             "init".to_string(),
             "-backend-config".to_string(),
             format!("bucket={}", self.state_bucket_name),
+            "-backend-config".to_string(),
+            "use_lockfile=true".to_string(),
         ];
         run_external_command(
             self.binary_path.clone(),
@@ -131,6 +245,137 @@ impl TerraformRunner {
         Ok(())
     }
 
+    /// Check the installed Terraform supports locking the state with `use_lockfile`.
+    fn ensure_state_locking_supported(&self) -> Result<()> {
+        let output = run_external_command(
+            self.binary_path.clone(),
+            self.working_directory_path.clone(),
+            vec!["version".to_string(), "-json".to_string()],
+            true,
+            false,
+        )?;
+        let version = get_terraform_version(&output.join("\n"))?;
+        if !is_state_locking_supported(&version) {
+            return Err(Error::TerraformVersionUnsupported {
+                version,
+                minimum: MIN_STATE_LOCKING_VERSION.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Remove the state lock for a workspace.
+    ///
+    /// The lock is left behind when a run is killed while it is applying or destroying, and every
+    /// subsequent run for the workspace will then fail to acquire it.
+    pub async fn force_unlock_state(
+        &self,
+        s3_repository: &S3Repository,
+        workspace: &str,
+    ) -> Result<()> {
+        // Workspaces other than the default are stored under the `env:` prefix.
+        let key = format!("env:/{workspace}/{}.tflock", self.get_backend_state_key()?);
+        debug!("Removing the state lock at {key}");
+        s3_repository
+            .delete_object(&self.state_bucket_name, &key)
+            .await
+    }
+
+    /// Read the state key from the `s3` backend block in the configuration.
+    fn get_backend_state_key(&self) -> Result<String> {
+        let main_path = self.working_directory_path.join("main.tf");
+        let contents = std::fs::read_to_string(&main_path)?;
+        contents
+            .lines()
+            .skip_while(|line| !line.trim_start().starts_with("backend \"s3\""))
+            .find_map(|line| {
+                let (name, value) = line.split_once('=')?;
+                if name.trim() != "key" {
+                    return None;
+                }
+                Some(value.trim().trim_matches('"').to_string())
+            })
+            .ok_or(Error::TerraformBackendKeyNotFound(main_path))
+    }
+
+    /// Write a CLI configuration file with the plugin cache and provider mirror settings, then
+    /// point Terraform at it using `TF_CLI_CONFIG_FILE`.
+    ///
+    /// The variable is set for this process, so it applies to every subsequent Terraform command,
+    /// as well as `init`. Nothing is written if neither setting is used.
+    fn write_cli_config(&self) -> Result<()> {
+        if self.plugin_cache_dir.is_none() && self.provider_mirror_url.is_none() {
+            return Ok(());
+        }
+
+        let mut config = String::new();
+        if let Some(plugin_cache_dir) = &self.plugin_cache_dir {
+            std::fs::create_dir_all(plugin_cache_dir)?;
+            config.push_str(&format!(
+                "plugin_cache_dir = \"{}\"\n",
+                plugin_cache_dir.to_string_lossy()
+            ));
+            // The lock files in the repository do not have checksums for every platform, which
+            // would otherwise prevent the cache from being used.
+            config.push_str("plugin_cache_may_break_dependency_lock_file = true\n");
+        }
+        if let Some(mirror_url) = &self.provider_mirror_url {
+            if !mirror_url.starts_with("https://") || !mirror_url.ends_with('/') {
+                return Err(Error::InvalidTerraformProviderMirrorUrl(
+                    mirror_url.to_string(),
+                ));
+            }
+            config.push_str(&format!(
+                r#"provider_installation {{
+  network_mirror {{
+    url = "{mirror_url}"
+  }}
+}}
+"#
+            ));
+        }
+
+        let config_path = dirs_next::data_dir()
+            .ok_or_else(|| Error::CouldNotRetrieveDataDirectory)?
+            .join("safe")
+            .join("testnet-deploy")
+            .join("terraform.rc");
+        if let Some(parent) = config_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&config_path, config)?;
+        debug!("Terraform CLI configuration written to {config_path:?}");
+        std::env::set_var("TF_CLI_CONFIG_FILE", config_path);
+        Ok(())
+    }
+
+    /// Read the outputs of the workspace for the environment into `T`.
+    ///
+    /// Each output becomes a field of `T`, with the name of the output as the field name. The
+    /// outputs are only written to the state on an apply, so an environment that has not been
+    /// applied since an output was added will not have it.
+    pub fn output<T: DeserializeOwned>(&self, name: &str) -> Result<T> {
+        self.workspace_select(name)?;
+
+        let output = run_external_command(
+            self.binary_path.clone(),
+            self.working_directory_path.clone(),
+            vec!["output".to_string(), "-json".to_string()],
+            true,
+            false,
+        )?;
+        if output.is_empty() {
+            return Err(Error::TerraformOutputFailed);
+        }
+
+        let outputs: HashMap<String, OutputValue> = serde_json::from_str(&output.join("\n"))?;
+        let values = outputs
+            .into_iter()
+            .map(|(name, output)| (name, output.value))
+            .collect::<serde_json::Map<_, _>>();
+        Ok(serde_json::from_value(serde_json::Value::Object(values))?)
+    }
+
     pub fn show(&self, name: &str) -> Result<Vec<TerraformResource>> {
         self.workspace_select(name)?;
 
@@ -148,6 +393,37 @@ impl TerraformRunner {
         Ok(show_output.values.root_module.resources)
     }
 
+    /// Remove resources from the state without destroying them, so Terraform no longer manages
+    /// them.
+    pub fn state_rm(&self, addresses: &[String]) -> Result<()> {
+        let mut args = vec!["state".to_string(), "rm".to_string()];
+        args.extend(addresses.iter().cloned());
+        run_external_command(
+            self.binary_path.clone(),
+            self.working_directory_path.clone(),
+            args,
+            false,
+            false,
+        )?;
+        Ok(())
+    }
+
+    /// Read the value of a variable from a tfvars file in the working directory.
+    ///
+    /// Only simple `name = value` assignments are supported, which is all the tfvars files use.
+    /// Returns `None` if the file does not exist or does not set the variable.
+    pub fn get_tfvars_variable(&self, tfvars_filename: &str, name: &str) -> Result<Option<String>> {
+        let path = self.working_directory_path.join(tfvars_filename);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = std::fs::read_to_string(path)?;
+        Ok(contents.lines().find_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            (key.trim() == name).then(|| value.trim().trim_matches('"').to_string())
+        }))
+    }
+
     pub fn workspace_delete(&self, name: &str) -> Result<()> {
         run_external_command(
             self.binary_path.clone(),
@@ -211,6 +487,92 @@ struct Output {
     values: Values,
 }
 
+#[derive(Deserialize)]
+struct OutputValue {
+    value: serde_json::Value,
+}
+
+/// The VMs in each group of an environment, from the outputs of the Digital Ocean configuration.
+#[derive(Clone, Debug, Deserialize)]
+pub struct InfraOutputs {
+    pub artifact_proxy_vms: Vec<TerraformVm>,
+    pub auditor_vms: Vec<TerraformVm>,
+    pub build_vms: Vec<TerraformVm>,
+    pub downloader_vms: Vec<TerraformVm>,
+    pub evm_node_vms: Vec<TerraformVm>,
+    pub genesis_vms: Vec<TerraformVm>,
+    pub monitoring_vms: Vec<TerraformVm>,
+    pub nat_gateway_vms: Vec<TerraformVm>,
+    pub node_vms: Vec<TerraformVm>,
+    pub peer_cache_node_vms: Vec<TerraformVm>,
+    pub private_node_vms: Vec<TerraformVm>,
+    pub uploader_vms: Vec<TerraformVm>,
+}
+
+impl InfraOutputs {
+    /// Get the VMs for an inventory type, in the same form as the Ansible inventory.
+    ///
+    /// Returns `None` for the inventory types that are not created by the testnet configuration.
+    pub fn get_vms(
+        &self,
+        inventory_type: &AnsibleInventoryType,
+    ) -> Option<Result<Vec<VirtualMachine>>> {
+        let vms = match inventory_type {
+            AnsibleInventoryType::ArtifactProxy => &self.artifact_proxy_vms,
+            AnsibleInventoryType::Auditor => &self.auditor_vms,
+            AnsibleInventoryType::Build => &self.build_vms,
+            AnsibleInventoryType::Downloaders => &self.downloader_vms,
+            AnsibleInventoryType::EvmNodes => &self.evm_node_vms,
+            AnsibleInventoryType::Genesis => &self.genesis_vms,
+            AnsibleInventoryType::Monitoring => &self.monitoring_vms,
+            AnsibleInventoryType::NatGateway => &self.nat_gateway_vms,
+            AnsibleInventoryType::Nodes => &self.node_vms,
+            AnsibleInventoryType::PeerCacheNodes => &self.peer_cache_node_vms,
+            AnsibleInventoryType::PrivateNodes | AnsibleInventoryType::PrivateNodesStatic => {
+                &self.private_node_vms
+            }
+            AnsibleInventoryType::Uploaders => &self.uploader_vms,
+            AnsibleInventoryType::Custom | AnsibleInventoryType::Logstash => return None,
+        };
+        Some(vms.iter().map(VirtualMachine::try_from).collect())
+    }
+}
+
+/// A droplet, as it is described by the outputs.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TerraformVm {
+    /// Terraform represents the droplet ID as a string.
+    pub id: String,
+    pub name: String,
+    pub private_ip: String,
+    pub public_ip: String,
+    pub vpc_uuid: String,
+}
+
+impl TryFrom<&TerraformVm> for VirtualMachine {
+    type Error = Error;
+
+    fn try_from(vm: &TerraformVm) -> Result<Self> {
+        let invalid = |field: &str, value: &str| Error::InvalidTerraformVmOutput {
+            field: field.to_string(),
+            name: vm.name.clone(),
+            value: value.to_string(),
+        };
+        Ok(VirtualMachine {
+            id: vm.id.parse().map_err(|_| invalid("id", &vm.id))?,
+            name: vm.name.clone(),
+            private_ip_addr: vm
+                .private_ip
+                .parse::<IpAddr>()
+                .map_err(|_| invalid("private IP", &vm.private_ip))?,
+            public_ip_addr: vm
+                .public_ip
+                .parse::<IpAddr>()
+                .map_err(|_| invalid("public IP", &vm.public_ip))?,
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct Values {
     root_module: Module,
@@ -232,3 +594,150 @@ pub struct TerraformResource {
     pub values: HashMap<String, serde_json::Value>,
     pub sensitive_values: HashMap<String, serde_json::Value>,
 }
+
+#[derive(Deserialize)]
+struct PlanOutput {
+    #[serde(default)]
+    resource_drift: Vec<PlannedResourceChange>,
+}
+
+#[derive(Deserialize)]
+struct PlannedResourceChange {
+    address: String,
+    change: PlannedChange,
+    #[serde(rename = "type")]
+    resource_type: String,
+}
+
+#[derive(Deserialize)]
+struct PlannedChange {
+    actions: Vec<String>,
+}
+
+/// A resource that has changed outside of Terraform.
+#[derive(Clone, Debug, Serialize)]
+pub struct ResourceDrift {
+    /// The actions describe how the real resource differs from the state, e.g., `delete` for a VM
+    /// that no longer exists, or `update` for one that was resized.
+    pub actions: Vec<String>,
+    pub address: String,
+    pub resource_type: String,
+}
+
+/// The first version that supports the `use_lockfile` setting for the `s3` backend.
+const MIN_STATE_LOCKING_VERSION: Version = Version::new(1, 10, 0);
+
+/// Read the version from the output of `terraform version -json`.
+fn get_terraform_version(output: &str) -> Result<String> {
+    #[derive(Deserialize)]
+    struct VersionOutput {
+        terraform_version: String,
+    }
+    let output: VersionOutput = serde_json::from_str(output)?;
+    Ok(output.terraform_version)
+}
+
+fn is_state_locking_supported(version: &str) -> bool {
+    // A version that cannot be parsed is treated as unsupported, so the error shows what it was.
+    Version::parse(version.trim_start_matches('v'))
+        .map(|version| {
+            Version::new(version.major, version.minor, version.patch) >= MIN_STATE_LOCKING_VERSION
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color_eyre::Result;
+    use std::path::Path;
+
+    fn get_runner(working_directory_path: &Path) -> TerraformRunner {
+        TerraformRunner {
+            binary_path: PathBuf::from("terraform"),
+            dry_run: false,
+            plugin_cache_dir: None,
+            provider: CloudProvider::DigitalOcean,
+            provider_mirror_url: None,
+            working_directory_path: working_directory_path.to_path_buf(),
+            state_bucket_name: "maidsafe-org-infra-tfstate".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_get_terraform_version() -> Result<()> {
+        let output = r#"{"terraform_version":"1.9.8","platform":"linux_amd64","provider_selections":{},"terraform_outdated":true}"#;
+        assert_eq!("1.9.8", get_terraform_version(output)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_state_locking_supported() -> Result<()> {
+        assert!(is_state_locking_supported("1.10.0"));
+        assert!(is_state_locking_supported("1.11.4"));
+        // A pre-release of the first supported version is accepted.
+        assert!(is_state_locking_supported("1.10.0-beta1"));
+        assert!(!is_state_locking_supported("1.9.8"));
+        assert!(!is_state_locking_supported("0.15.5"));
+        assert!(!is_state_locking_supported("not a version"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_backend_state_key() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        std::fs::write(
+            temp_dir.path().join("main.tf"),
+            r#"
+terraform {
+  required_providers {
+    digitalocean = {
+      source  = "digitalocean/digitalocean"
+      version = "~> 2.0"
+    }
+  }
+  backend "s3" {
+    key = "sn-testnet-tool-digital-ocean.tfstate"
+  }
+}
+"#,
+        )?;
+        let key = get_runner(temp_dir.path()).get_backend_state_key()?;
+        assert_eq!("sn-testnet-tool-digital-ocean.tfstate", key);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_backend_state_key_ignores_keys_before_the_backend_block() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        std::fs::write(
+            temp_dir.path().join("main.tf"),
+            r#"
+variable "key" {
+  key = "not-the-state-key"
+}
+terraform {
+  backend "s3" {
+    bucket = "maidsafe-org-infra-tfstate"
+    key    = "sn-testnet-tool-aws.tfstate"
+  }
+}
+"#,
+        )?;
+        let key = get_runner(temp_dir.path()).get_backend_state_key()?;
+        assert_eq!("sn-testnet-tool-aws.tfstate", key);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_backend_state_key_without_a_backend_block() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        std::fs::write(
+            temp_dir.path().join("main.tf"),
+            "resource \"digitalocean_droplet\" \"node\" {\n  name = \"node\"\n}\n",
+        )?;
+        let result = get_runner(temp_dir.path()).get_backend_state_key();
+        assert!(matches!(result, Err(Error::TerraformBackendKeyNotFound(_))));
+        Ok(())
+    }
+}
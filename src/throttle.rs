@@ -0,0 +1,130 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    ansible::{
+        extra_vars::ExtraVarsDocBuilder,
+        inventory::{generate_custom_environment_inventory, AnsibleInventoryType},
+        AnsiblePlaybook,
+    },
+    error::{Error, Result},
+    inventory::VirtualMachine,
+    TestnetDeployer,
+};
+use regex::Regex;
+
+/// Parse a bandwidth rate, e.g., '10mbit', into kilobits per second, which is the unit used by
+/// wondershaper.
+pub fn parse_rate_kbit(rate: &str) -> Result<u64> {
+    let regex = Regex::new(r"^(\d+)(kbit|mbit|gbit)$")?;
+    let lowercase_rate = rate.to_lowercase();
+    let captures = regex
+        .captures(&lowercase_rate)
+        .ok_or_else(|| Error::InvalidBandwidthRate(rate.to_string()))?;
+    let value: u64 = captures[1]
+        .parse()
+        .map_err(|_| Error::InvalidBandwidthRate(rate.to_string()))?;
+    let kbit = match &captures[2] {
+        "kbit" => value,
+        "mbit" => value * 1000,
+        _ => value * 1000 * 1000,
+    };
+    if kbit == 0 {
+        return Err(Error::InvalidBandwidthRate(rate.to_string()));
+    }
+    Ok(kbit)
+}
+
+/// Expand a VM selector into the names of the VMs it refers to.
+///
+/// The selector is a comma-separated list of VM names without the environment prefix, where each
+/// name can use an inclusive range, e.g., 'node-[5:10],peer-cache-node-1'.
+pub fn expand_vm_selector(environment_name: &str, selector: &str) -> Result<Vec<String>> {
+    let range_regex = Regex::new(r"^(.+)\[(\d+):(\d+)\]$")?;
+    let mut names = Vec::new();
+    for part in selector.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        match range_regex.captures(part) {
+            Some(captures) => {
+                let start: u16 = captures[2]
+                    .parse()
+                    .map_err(|_| Error::InvalidVmSelector(selector.to_string()))?;
+                let end: u16 = captures[3]
+                    .parse()
+                    .map_err(|_| Error::InvalidVmSelector(selector.to_string()))?;
+                if start > end {
+                    return Err(Error::InvalidVmSelector(selector.to_string()));
+                }
+                for index in start..=end {
+                    names.push(format!("{environment_name}-{}{index}", &captures[1]));
+                }
+            }
+            None => names.push(format!("{environment_name}-{part}")),
+        }
+    }
+    if names.is_empty() {
+        return Err(Error::InvalidVmSelector(selector.to_string()));
+    }
+    Ok(names)
+}
+
+impl TestnetDeployer {
+    /// Limit the upload and download bandwidth of the selected node VMs, using wondershaper.
+    ///
+    /// Any limit previously applied to those VMs is replaced. The throttle uses the root qdisc, so
+    /// it also replaces any network conditions applied to the same VMs.
+    pub fn throttle_bandwidth(&self, vm_selector: Option<&str>, rate: &str) -> Result<()> {
+        let rate_kbit = parse_rate_kbit(rate)?;
+        let mut extra_vars = ExtraVarsDocBuilder::default();
+        extra_vars.add_variable("throttle_rate_kbit", &rate_kbit.to_string());
+        self.run_throttle_playbook(vm_selector, extra_vars.build())
+    }
+
+    /// Remove the bandwidth limit from the selected node VMs.
+    pub fn clear_bandwidth_throttle(&self, vm_selector: Option<&str>) -> Result<()> {
+        let mut extra_vars = ExtraVarsDocBuilder::default();
+        extra_vars.add_variable("clear_throttle", "true");
+        self.run_throttle_playbook(vm_selector, extra_vars.build())
+    }
+
+    fn run_throttle_playbook(&self, vm_selector: Option<&str>, extra_vars: String) -> Result<()> {
+        let vms = self.get_throttle_vms(vm_selector)?;
+        println!("Running the throttle playbook against {} VMs", vms.len());
+        generate_custom_environment_inventory(
+            &vms,
+            &self.environment_name,
+            &self
+                .ansible_provisioner
+                .ansible_runner
+                .working_directory_path
+                .join("inventory"),
+        )?;
+        self.ansible_provisioner.ansible_runner.run_playbook(
+            AnsiblePlaybook::Throttle,
+            AnsibleInventoryType::Custom,
+            Some(extra_vars),
+        )?;
+        Ok(())
+    }
+
+    fn get_throttle_vms(&self, vm_selector: Option<&str>) -> Result<Vec<VirtualMachine>> {
+        let vms = self.ansible_provisioner.get_all_node_inventory()?;
+        let Some(vm_selector) = vm_selector else {
+            return Ok(vms);
+        };
+
+        let names = expand_vm_selector(&self.environment_name, vm_selector)?;
+        if let Some(missing) = names
+            .iter()
+            .find(|name| !vms.iter().any(|vm| &vm.name == *name))
+        {
+            return Err(Error::VmNotFound(missing.clone()));
+        }
+        Ok(vms
+            .into_iter()
+            .filter(|vm| names.contains(&vm.name))
+            .collect())
+    }
+}
@@ -0,0 +1,351 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    error::{Error, Result},
+    run_log,
+    s3::S3Repository,
+    BinaryOption,
+};
+use serde::{Deserialize, Serialize};
+use std::{fs::File, io::Write, time::Duration};
+use tracing::debug;
+
+/// The bucket where a summary of each pipeline run is stored. Summaries are only recorded if it
+/// is set.
+pub const RUN_SUMMARY_BUCKET_ENV_VAR: &str = "RUN_SUMMARY_BUCKET_NAME";
+
+/// A summary of a single deployment run, used to build up trends across releases.
+///
+/// The upload throughput and error signature count are not known by the deployer itself. They
+/// are recorded against the summary by later stages of a pipeline, using `trends record`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RunSummary {
+    pub command_count: usize,
+    pub deploy_duration_secs: u64,
+    pub environment_name: String,
+    pub error_signature_count: Option<u64>,
+    pub failed_command_count: usize,
+    pub release: String,
+    /// The time the run completed, in the form `%Y%m%dT%H%M%S`, so summaries sort by time.
+    pub timestamp: String,
+    pub upload_throughput_mb_per_sec: Option<f64>,
+}
+
+impl RunSummary {
+    /// Build a summary of a deployment that took `deploy_duration`, with the external commands
+    /// recorded during this run.
+    pub fn new(
+        environment_name: &str,
+        binary_option: &BinaryOption,
+        deploy_duration: Duration,
+    ) -> Self {
+        let records = run_log::get_records();
+        Self {
+            command_count: records.len(),
+            deploy_duration_secs: deploy_duration.as_secs(),
+            environment_name: environment_name.to_string(),
+            error_signature_count: None,
+            failed_command_count: records.iter().filter(|r| !r.succeeded()).count(),
+            release: get_release_name(binary_option),
+            timestamp: chrono::Utc::now().format("%Y%m%dT%H%M%S").to_string(),
+            upload_throughput_mb_per_sec: None,
+        }
+    }
+
+    pub fn get_object_key(&self) -> String {
+        format!("{}-{}.json", self.timestamp, self.environment_name)
+    }
+}
+
+/// The regressions found when comparing a run with the run that preceded it.
+#[derive(Clone, Debug)]
+pub struct Regression {
+    pub metric: String,
+    pub previous: String,
+    pub current: String,
+}
+
+/// Returns `None` if the bucket for the run summaries has not been configured.
+pub fn get_run_summary_bucket_name() -> Option<String> {
+    std::env::var(RUN_SUMMARY_BUCKET_ENV_VAR)
+        .ok()
+        .filter(|name| !name.is_empty())
+}
+
+pub async fn upload_run_summary(
+    s3_repository: &S3Repository,
+    bucket_name: &str,
+    summary: &RunSummary,
+) -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join(summary.get_object_key());
+    let mut file = File::create(&path)?;
+    let json = serde_json::to_string(summary)?;
+    file.write_all(json.as_bytes())?;
+    s3_repository.upload_file(bucket_name, &path, false).await?;
+    Ok(())
+}
+
+/// Retrieve all the run summaries from S3, ordered from oldest to newest.
+pub async fn get_run_summaries(
+    s3_repository: &S3Repository,
+    bucket_name: &str,
+) -> Result<Vec<RunSummary>> {
+    let temp_dir = tempfile::tempdir()?;
+    s3_repository
+        .download_folder(bucket_name, "", temp_dir.path())
+        .await?;
+
+    let mut summaries = Vec::new();
+    for entry in walkdir::WalkDir::new(temp_dir.path())
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+    {
+        let contents = std::fs::read_to_string(entry.path())?;
+        match serde_json::from_str::<RunSummary>(&contents) {
+            Ok(summary) => summaries.push(summary),
+            Err(err) => debug!("Skipping {}: {err}", entry.path().to_string_lossy()),
+        }
+    }
+    summaries.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+    Ok(summaries)
+}
+
+/// Record the upload throughput and error signature count against the most recent run of an
+/// environment, replacing the summary in S3.
+///
+/// Only the values that are supplied are changed.
+pub async fn record_run_metrics(
+    s3_repository: &S3Repository,
+    bucket_name: &str,
+    environment_name: &str,
+    upload_throughput_mb_per_sec: Option<f64>,
+    error_signature_count: Option<u64>,
+) -> Result<RunSummary> {
+    let mut summary = get_run_summaries(s3_repository, bucket_name)
+        .await?
+        .into_iter()
+        .rev()
+        .find(|summary| summary.environment_name == environment_name)
+        .ok_or_else(|| Error::RunSummaryNotFound(environment_name.to_string()))?;
+    if upload_throughput_mb_per_sec.is_some() {
+        summary.upload_throughput_mb_per_sec = upload_throughput_mb_per_sec;
+    }
+    if error_signature_count.is_some() {
+        summary.error_signature_count = error_signature_count;
+    }
+    upload_run_summary(s3_repository, bucket_name, &summary).await?;
+    Ok(summary)
+}
+
+/// Compare a run with the one before it.
+///
+/// Durations and throughput are flagged when they are worse by more than `threshold_percent`.
+/// Any increase in failed commands or error signatures is flagged.
+pub fn find_regressions(
+    previous: &RunSummary,
+    current: &RunSummary,
+    threshold_percent: u8,
+) -> Vec<Regression> {
+    let threshold = threshold_percent as f64 / 100.0;
+    let mut regressions = Vec::new();
+
+    if previous.deploy_duration_secs > 0
+        && current.deploy_duration_secs as f64
+            > previous.deploy_duration_secs as f64 * (1.0 + threshold)
+    {
+        regressions.push(Regression {
+            metric: "deploy time".to_string(),
+            previous: run_log::format_duration(Duration::from_secs(previous.deploy_duration_secs)),
+            current: run_log::format_duration(Duration::from_secs(current.deploy_duration_secs)),
+        });
+    }
+    if current.failed_command_count > previous.failed_command_count {
+        regressions.push(Regression {
+            metric: "failed commands".to_string(),
+            previous: previous.failed_command_count.to_string(),
+            current: current.failed_command_count.to_string(),
+        });
+    }
+    if let (Some(previous_count), Some(current_count)) = (
+        previous.error_signature_count,
+        current.error_signature_count,
+    ) {
+        if current_count > previous_count {
+            regressions.push(Regression {
+                metric: "error signatures".to_string(),
+                previous: previous_count.to_string(),
+                current: current_count.to_string(),
+            });
+        }
+    }
+    if let (Some(previous_throughput), Some(current_throughput)) = (
+        previous.upload_throughput_mb_per_sec,
+        current.upload_throughput_mb_per_sec,
+    ) {
+        if current_throughput < previous_throughput * (1.0 - threshold) {
+            regressions.push(Regression {
+                metric: "upload throughput".to_string(),
+                previous: format!("{previous_throughput:.2} MB/s"),
+                current: format!("{current_throughput:.2} MB/s"),
+            });
+        }
+    }
+    regressions
+}
+
+/// Print a trend report for the given summaries, which should be ordered from oldest to newest.
+pub fn print_trend_report(summaries: &[RunSummary], threshold_percent: u8) {
+    println!("============");
+    println!("Trend Report");
+    println!("============");
+    if summaries.is_empty() {
+        println!("No run summaries are available");
+        return;
+    }
+
+    println!(
+        "{:<16} {:<24} {:<24} {:>10} {:>8} {:>8} {:>12}",
+        "Timestamp", "Environment", "Release", "Deploy", "Failed", "Errors", "Upload MB/s"
+    );
+    for summary in summaries.iter() {
+        println!(
+            "{:<16} {:<24} {:<24} {:>10} {:>8} {:>8} {:>12}",
+            summary.timestamp,
+            summary.environment_name,
+            summary.release,
+            run_log::format_duration(Duration::from_secs(summary.deploy_duration_secs)),
+            summary.failed_command_count,
+            summary
+                .error_signature_count
+                .map(|c| c.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            summary
+                .upload_throughput_mb_per_sec
+                .map(|t| format!("{t:.2}"))
+                .unwrap_or_else(|| "-".to_string()),
+        );
+    }
+
+    let mut found_regression = false;
+    for pair in summaries.windows(2) {
+        let regressions = find_regressions(&pair[0], &pair[1], threshold_percent);
+        if regressions.is_empty() {
+            continue;
+        }
+        if !found_regression {
+            println!();
+            println!("Regressions:");
+            found_regression = true;
+        }
+        for regression in regressions.iter() {
+            println!(
+                "{} ({}): {} went from {} to {}",
+                pair[1].timestamp,
+                pair[1].release,
+                regression.metric,
+                regression.previous,
+                regression.current
+            );
+        }
+    }
+    if !found_regression {
+        println!();
+        println!("No regressions detected");
+    }
+}
+
+fn get_release_name(binary_option: &BinaryOption) -> String {
+    match binary_option {
+        BinaryOption::BuildFromSource {
+            repo_owner, branch, ..
+        } => format!("{repo_owner}/{branch}"),
+        BinaryOption::Local { .. } => "local".to_string(),
+        BinaryOption::Versioned {
+            antnode_version, ..
+        } => antnode_version.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color_eyre::Result;
+
+    fn get_summary(deploy_duration_secs: u64) -> RunSummary {
+        RunSummary {
+            command_count: 10,
+            deploy_duration_secs,
+            environment_name: "alpha".to_string(),
+            error_signature_count: Some(2),
+            failed_command_count: 0,
+            release: "0.1.0".to_string(),
+            timestamp: "20240101T000000".to_string(),
+            upload_throughput_mb_per_sec: Some(10.0),
+        }
+    }
+
+    #[test]
+    fn test_find_regressions_with_no_changes() -> Result<()> {
+        let regressions = find_regressions(&get_summary(600), &get_summary(600), 10);
+        assert!(regressions.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_regressions_ignores_changes_within_the_threshold() -> Result<()> {
+        let previous = get_summary(600);
+        let mut current = get_summary(660);
+        current.upload_throughput_mb_per_sec = Some(9.0);
+        let regressions = find_regressions(&previous, &current, 10);
+        assert!(regressions.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_regressions_flags_every_worse_metric() -> Result<()> {
+        let previous = get_summary(600);
+        let mut current = get_summary(661);
+        current.error_signature_count = Some(3);
+        current.failed_command_count = 1;
+        current.upload_throughput_mb_per_sec = Some(8.0);
+
+        let regressions = find_regressions(&previous, &current, 10);
+        let metrics = regressions
+            .iter()
+            .map(|r| r.metric.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "deploy time",
+                "failed commands",
+                "error signatures",
+                "upload throughput"
+            ],
+            metrics
+        );
+        assert_eq!("1", regressions[1].current);
+        assert_eq!("10.00 MB/s", regressions[3].previous);
+        assert_eq!("8.00 MB/s", regressions[3].current);
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_regressions_skips_metrics_that_were_not_recorded() -> Result<()> {
+        let mut previous = get_summary(0);
+        previous.error_signature_count = None;
+        previous.upload_throughput_mb_per_sec = None;
+        let mut current = get_summary(600);
+        current.error_signature_count = Some(5);
+        current.upload_throughput_mb_per_sec = Some(1.0);
+
+        let regressions = find_regressions(&previous, &current, 10);
+        assert!(regressions.is_empty());
+        Ok(())
+    }
+}
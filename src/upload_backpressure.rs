@@ -0,0 +1,163 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    ansible::{extra_vars::ExtraVarsDocBuilder, inventory::AnsibleInventoryType, AnsiblePlaybook},
+    error::Result,
+    status_badge::EnvironmentStatus,
+    TestnetDeployer,
+};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// The mount point of the volumes the node data is stored on.
+const NODE_STORAGE_PATH: &str = "/mnt/antnode-storage";
+
+pub struct UploadBackpressureOptions {
+    /// Stop checking after this long, removing any limit that is in place. If not set, the checks
+    /// run until the process is stopped.
+    pub duration: Option<Duration>,
+    /// The time between each check of the network.
+    pub interval: Duration,
+    /// The network is under pressure when the disk usage on any node VM is above this percentage.
+    pub max_disk_usage_pct: u8,
+    /// The network is under pressure when the percentage of nodes that are not running is above
+    /// this value.
+    pub max_failure_pct: f64,
+    /// The number of uploader services that keep uploading on each VM while the network is under
+    /// pressure.
+    pub reduced_uploaders_per_vm: u16,
+}
+
+/// The signals used to decide whether the uploaders should back off.
+#[derive(Clone, Debug)]
+pub struct NetworkPressure {
+    pub failure_pct: f64,
+    /// The highest disk usage on any of the node VMs, if it could be obtained from any of them.
+    pub max_disk_usage_pct: Option<u8>,
+}
+
+impl NetworkPressure {
+    pub fn is_over(&self, options: &UploadBackpressureOptions) -> bool {
+        self.failure_pct > options.max_failure_pct
+            || self
+                .max_disk_usage_pct
+                .is_some_and(|usage| usage > options.max_disk_usage_pct)
+    }
+}
+
+impl TestnetDeployer {
+    /// Check the network at an interval and reduce the number of active uploaders while it is
+    /// struggling, so a load test degrades gracefully rather than burying the network.
+    ///
+    /// When the node failure rate or disk usage crosses its threshold, the uploaders on each VM
+    /// above the reduced count pause between uploads. Once both signals are back under their
+    /// thresholds, all the uploaders resume.
+    ///
+    /// If the process is stopped while the uploaders are limited, the limit stays in place until
+    /// it is removed with `set_uploader_concurrency(None)`.
+    pub fn run_upload_backpressure(&self, options: &UploadBackpressureOptions) -> Result<()> {
+        let start_time = Instant::now();
+        let mut is_limited = false;
+        loop {
+            let pressure = self.get_network_pressure()?;
+            let is_over = pressure.is_over(options);
+            println!(
+                "{}: {:.2}% of nodes not running, highest disk usage {}",
+                chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+                pressure.failure_pct,
+                pressure
+                    .max_disk_usage_pct
+                    .map(|usage| format!("{usage}%"))
+                    .unwrap_or_else(|| "unknown".to_string())
+            );
+
+            if is_over && !is_limited {
+                println!(
+                    "The network is under pressure. Reducing to {} uploaders per VM.",
+                    options.reduced_uploaders_per_vm
+                );
+                self.set_uploader_concurrency(Some(options.reduced_uploaders_per_vm))?;
+                is_limited = true;
+            } else if !is_over && is_limited {
+                println!("The network has recovered. Resuming all uploaders.");
+                self.set_uploader_concurrency(None)?;
+                is_limited = false;
+            }
+
+            if let Some(duration) = options.duration {
+                if start_time.elapsed() >= duration {
+                    if is_limited {
+                        self.set_uploader_concurrency(None)?;
+                    }
+                    return Ok(());
+                }
+            }
+            std::thread::sleep(options.interval);
+        }
+    }
+
+    /// Obtain the node failure rate and the highest disk usage across the node VMs.
+    pub fn get_network_pressure(&self) -> Result<NetworkPressure> {
+        self.ansible_provisioner.status()?;
+        let registries = self.get_all_node_registries()?;
+        let status = EnvironmentStatus::from_registries(&self.environment_name, &registries);
+
+        let mut node_vms = Vec::new();
+        for inventory_type in AnsibleInventoryType::iter_node_type() {
+            node_vms.extend(
+                self.ansible_provisioner
+                    .ansible_runner
+                    .get_inventory(inventory_type, true)?,
+            );
+        }
+        let ssh_user = self.cloud_provider.get_ssh_user();
+        let max_disk_usage_pct = node_vms
+            .par_iter()
+            .filter_map(|vm| {
+                let output = self
+                    .ssh_client
+                    .run_command(
+                        &vm.public_ip_addr,
+                        &ssh_user,
+                        &format!("df --output=pcent {NODE_STORAGE_PATH}"),
+                        true,
+                    )
+                    .inspect_err(|err| {
+                        debug!("Could not obtain the disk usage on {}: {err}", vm.name)
+                    })
+                    .ok()?;
+                output
+                    .iter()
+                    .filter_map(|line| line.trim().trim_end_matches('%').parse::<u8>().ok())
+                    .next()
+            })
+            .max();
+
+        Ok(NetworkPressure {
+            failure_pct: status.failure_pct,
+            max_disk_usage_pct,
+        })
+    }
+
+    /// Limit the number of uploader services that upload on each VM, or remove the limit.
+    ///
+    /// The services are not stopped. Those above the limit pause between uploads, so they resume
+    /// without being restarted when the limit is removed.
+    pub fn set_uploader_concurrency(&self, max_active_per_vm: Option<u16>) -> Result<()> {
+        let mut extra_vars = ExtraVarsDocBuilder::default();
+        if let Some(max_active) = max_active_per_vm {
+            extra_vars.add_variable("upload_max_active", &max_active.to_string());
+        }
+        self.ansible_provisioner.ansible_runner.run_playbook(
+            AnsiblePlaybook::UploadConcurrency,
+            AnsibleInventoryType::Uploaders,
+            Some(extra_vars.build()),
+        )?;
+        Ok(())
+    }
+}
@@ -7,13 +7,14 @@
 use crate::{
     ansible::{inventory::AnsibleInventoryType, provisioning::ProvisionOptions},
     error::{Error, Result},
-    get_bootstrap_cache_url, get_genesis_multiaddr, get_multiaddr, DeploymentInventory,
-    DeploymentType, InfraRunOptions, NodeType, TestnetDeployer,
+    get_bootstrap_cache_url, get_genesis_multiaddr, get_multiaddr, rpc_proxy,
+    write_environment_details, DeploymentInventory, DeploymentType, InfraRunOptions, NodeType,
+    TestnetDeployer,
 };
 use colored::Colorize;
 use evmlib::common::U256;
-use log::debug;
 use std::{collections::HashSet, time::Duration};
+use tracing::debug;
 
 #[derive(Clone)]
 pub struct UpscaleOptions {
@@ -36,7 +37,13 @@ pub struct UpscaleOptions {
     pub max_archived_log_files: u16,
     pub max_log_files: u16,
     pub plan: bool,
+    /// Provision the node VMs in batches of this size, rather than all of them in a single run.
+    pub provision_parallelism: Option<usize>,
+    /// The number of times to re-run the node playbooks against the hosts that failed.
+    pub provision_retries: u8,
     pub public_rpc: bool,
+    /// Only used if the environment was deployed with the RPC proxy.
+    pub rpc_proxy_acme_email: Option<String>,
     pub safe_version: Option<String>,
     pub provision_only: bool,
 }
@@ -120,6 +127,14 @@ impl TestnetDeployer {
         debug!("Using {desired_private_node_count} for desired private node count");
 
         if options.plan {
+            // The gateways are not part of the upscale, but the count must be supplied, otherwise
+            // the plan would show any beyond the first being destroyed.
+            let nat_gateway_count = self
+                .ansible_provisioner
+                .ansible_runner
+                .get_inventory(AnsibleInventoryType::NatGateway, false)?
+                .len()
+                .max(1);
             let vars = vec![
                 (
                     "peer_cache_node_vm_count".to_string(),
@@ -153,6 +168,10 @@ impl TestnetDeployer {
                     "setup_nat_gateway".to_string(),
                     (desired_private_node_vm_count > 0).to_string(),
                 ),
+                (
+                    "nat_gateway_count".to_string(),
+                    nat_gateway_count.to_string(),
+                ),
             ];
             self.plan(Some(vars), &options.current_inventory.get_tfvars_filename())?;
             return Ok(());
@@ -179,9 +198,41 @@ impl TestnetDeployer {
             return Ok(());
         }
 
+        // The private nodes are not provisioned during an upscale, so their count is retained.
+        let mut environment_details = options.current_inventory.environment_details.clone();
+        environment_details.node_count = Some(desired_node_count);
+        if !is_bootstrap_deploy {
+            environment_details.peer_cache_node_count = Some(desired_peer_cache_node_count);
+        }
+        write_environment_details(
+            &self.s3_repository,
+            &options.current_inventory.name,
+            &environment_details,
+        )
+        .await?;
+
         let provision_options = ProvisionOptions {
+            architecture: options
+                .current_inventory
+                .environment_details
+                .architecture
+                .unwrap_or_default(),
+            artifact_proxy_url: self.ansible_provisioner.get_artifact_proxy_url()?,
+            artifact_repository: options
+                .current_inventory
+                .environment_details
+                .artifact_repository
+                .clone()
+                .unwrap_or_default(),
             binary_option: options.current_inventory.binary_option.clone(),
+            build_cache_key: None,
             chunk_size: None,
+            door_node_vms: options
+                .current_inventory
+                .get_door_node_vms()
+                .into_iter()
+                .map(|(_, node_vm)| node_vm.vm.clone())
+                .collect(),
             downloaders_count: options.downloaders_count,
             env_variables: None,
             evm_network: options
@@ -206,12 +257,22 @@ impl TestnetDeployer {
                 .clone(),
             funding_wallet_secret_key: options.funding_wallet_secret_key.clone(),
             interval: options.interval,
+            join_rate: None,
             log_format: None,
-            logstash_details: None,
             name: options.current_inventory.name.clone(),
             nat_gateway: None,
+            nat_type: options
+                .current_inventory
+                .environment_details
+                .nat_type
+                .unwrap_or_default(),
             network_id: options.current_inventory.environment_details.network_id,
             node_count: desired_node_count,
+            node_reachability: options
+                .current_inventory
+                .environment_details
+                .node_reachability
+                .unwrap_or_default(),
             max_archived_log_files: options.max_archived_log_files,
             max_log_files: options.max_log_files,
             output_inventory_dir_path: self
@@ -219,8 +280,20 @@ impl TestnetDeployer {
                 .join("ansible")
                 .join("inventory"),
             peer_cache_node_count: desired_peer_cache_node_count,
+            peer_cache_node_reachability: options
+                .current_inventory
+                .environment_details
+                .peer_cache_node_reachability
+                .unwrap_or_default(),
             private_node_count: desired_private_node_count,
+            private_node_upnp: options
+                .current_inventory
+                .environment_details
+                .private_node_upnp
+                .unwrap_or(false),
             private_node_vms: Vec::new(),
+            provision_parallelism: options.provision_parallelism,
+            provision_retries: options.provision_retries,
             public_rpc: options.public_rpc,
             rewards_address: options
                 .current_inventory
@@ -228,20 +301,35 @@ impl TestnetDeployer {
                 .rewards_address
                 .clone(),
             ant_version: options.safe_version.clone(),
+            telemetry: None,
             uploaders_count: options.desired_uploaders_count,
             gas_amount: options.gas_amount,
         };
         let mut node_provision_failed = false;
 
+        // The token is read before any provisioning, so a missing token doesn't leave the new
+        // VMs half set up.
+        let rpc_proxy_auth_token =
+            if options.current_inventory.environment_details.rpc_proxy == Some(true) {
+                let name = &options.current_inventory.name;
+                Some(
+                    rpc_proxy::read_auth_token(name)?
+                        .ok_or_else(|| Error::RpcProxyAuthTokenNotFound(name.clone()))?,
+                )
+            } else {
+                None
+            };
+
         let (initial_multiaddr, initial_ip_addr) = if is_bootstrap_deploy {
-            get_multiaddr(&self.ansible_provisioner.ansible_runner, &self.ssh_client).map_err(
-                |err| {
+            get_multiaddr(&self.ansible_provisioner.ansible_runner, &self.ssh_client)
+                .await
+                .map_err(|err| {
                     println!("Failed to get node multiaddr {err:?}");
                     err
-                },
-            )?
+                })?
         } else {
             get_genesis_multiaddr(&self.ansible_provisioner.ansible_runner, &self.ssh_client)
+                .await
                 .map_err(|err| {
                     println!("Failed to get genesis multiaddr {err:?}");
                     err
@@ -268,7 +356,7 @@ impl TestnetDeployer {
                     println!("Provisioned Peer Cache nodes");
                 }
                 Err(err) => {
-                    log::error!("Failed to provision Peer Cache nodes: {err}");
+                    tracing::error!("Failed to provision Peer Cache nodes: {err}");
                     node_provision_failed = true;
                 }
             }
@@ -290,11 +378,28 @@ impl TestnetDeployer {
                 println!("Provisioned normal nodes");
             }
             Err(err) => {
-                log::error!("Failed to provision normal nodes: {err}");
+                tracing::error!("Failed to provision normal nodes: {err}");
                 node_provision_failed = true;
             }
         }
 
+        if let Some(auth_token) = &rpc_proxy_auth_token {
+            self.ansible_provisioner
+                .print_ansible_run_banner("Provision RPC Proxy");
+            match self
+                .ansible_provisioner
+                .provision_rpc_proxy(auth_token, options.rpc_proxy_acme_email.as_deref())
+            {
+                Ok(()) => {
+                    println!("Provisioned the RPC proxy on the node VMs");
+                }
+                Err(err) => {
+                    tracing::error!("Failed to provision the RPC proxy: {err}");
+                    node_provision_failed = true;
+                }
+            }
+        }
+
         if should_provision_private_nodes {
             println!("Private node provisioning will be skipped during upscale");
             // TODO: Reenable this after examining and fixing the problems.
@@ -336,7 +441,7 @@ impl TestnetDeployer {
             //             println!("Provisioned private nodes");
             //         }
             //         Err(err) => {
-            //             log::error!("Failed to provision private nodes: {err}");
+            //             tracing::error!("Failed to provision private nodes: {err}");
             //             node_provision_failed = true;
             //         }
             //     }
@@ -395,10 +500,24 @@ impl TestnetDeployer {
         debug!("Using {desired_uploader_vm_count} for desired uploader VM count");
 
         if options.plan {
-            let vars = vec![(
-                "uploader_vm_count".to_string(),
-                desired_uploader_vm_count.to_string(),
-            )];
+            // The gateways are not part of the upscale, but the count must be supplied, otherwise
+            // the plan would show any beyond the first being destroyed.
+            let nat_gateway_count = self
+                .ansible_provisioner
+                .ansible_runner
+                .get_inventory(AnsibleInventoryType::NatGateway, false)?
+                .len()
+                .max(1);
+            let vars = vec![
+                (
+                    "uploader_vm_count".to_string(),
+                    desired_uploader_vm_count.to_string(),
+                ),
+                (
+                    "nat_gateway_count".to_string(),
+                    nat_gateway_count.to_string(),
+                ),
+            ];
             self.plan(Some(vars), &options.current_inventory.get_tfvars_filename())?;
             return Ok(());
         }
@@ -424,6 +543,7 @@ impl TestnetDeployer {
 
         let (initial_multiaddr, initial_ip_addr) =
             get_genesis_multiaddr(&self.ansible_provisioner.ansible_runner, &self.ssh_client)
+                .await
                 .map_err(|err| {
                     println!("Failed to get genesis multiaddr {err:?}");
                     err
@@ -432,8 +552,22 @@ impl TestnetDeployer {
         debug!("Retrieved initial peer {initial_multiaddr} and initial network contacts {initial_network_contacts_url}");
 
         let provision_options = ProvisionOptions {
+            architecture: options
+                .current_inventory
+                .environment_details
+                .architecture
+                .unwrap_or_default(),
+            artifact_proxy_url: self.ansible_provisioner.get_artifact_proxy_url()?,
+            artifact_repository: options
+                .current_inventory
+                .environment_details
+                .artifact_repository
+                .clone()
+                .unwrap_or_default(),
             binary_option: options.current_inventory.binary_option.clone(),
+            build_cache_key: None,
             chunk_size: None,
+            door_node_vms: Vec::new(),
             downloaders_count: options.downloaders_count,
             env_variables: None,
             evm_data_payments_address: options
@@ -458,12 +592,22 @@ impl TestnetDeployer {
                 .clone(),
             funding_wallet_secret_key: options.funding_wallet_secret_key.clone(),
             interval: options.interval,
+            join_rate: None,
             log_format: None,
-            logstash_details: None,
             name: options.current_inventory.name.clone(),
             nat_gateway: None,
+            nat_type: options
+                .current_inventory
+                .environment_details
+                .nat_type
+                .unwrap_or_default(),
             network_id: options.current_inventory.environment_details.network_id,
             node_count: 0,
+            node_reachability: options
+                .current_inventory
+                .environment_details
+                .node_reachability
+                .unwrap_or_default(),
             max_archived_log_files: options.max_archived_log_files,
             max_log_files: options.max_log_files,
             output_inventory_dir_path: self
@@ -471,8 +615,20 @@ impl TestnetDeployer {
                 .join("ansible")
                 .join("inventory"),
             peer_cache_node_count: 0,
+            peer_cache_node_reachability: options
+                .current_inventory
+                .environment_details
+                .peer_cache_node_reachability
+                .unwrap_or_default(),
             private_node_count: 0,
+            private_node_upnp: options
+                .current_inventory
+                .environment_details
+                .private_node_upnp
+                .unwrap_or(false),
             private_node_vms: Vec::new(),
+            provision_parallelism: None,
+            provision_retries: 0,
             public_rpc: options.public_rpc,
             rewards_address: options
                 .current_inventory
@@ -480,6 +636,7 @@ impl TestnetDeployer {
                 .rewards_address
                 .clone(),
             ant_version: options.safe_version.clone(),
+            telemetry: None,
             uploaders_count: options.desired_uploaders_count,
             gas_amount: options.gas_amount,
         };
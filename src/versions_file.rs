@@ -0,0 +1,160 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    deploy::DeployOptions,
+    error::{Error, Result},
+    Architecture, BinaryOption,
+};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// The source a branch build was made from.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PinnedBuild {
+    pub antnode_features: Option<String>,
+    pub branch: String,
+    pub chunk_size: Option<u64>,
+    /// The commit that was built. This is only missing if the commit could not be determined,
+    /// e.g., when a deployment was resumed after the build had completed.
+    pub commit_sha: Option<String>,
+    pub network_keys: Option<(String, String, String, String)>,
+    pub repo_owner: String,
+}
+
+/// The exact binaries an environment was deployed with.
+///
+/// The file can be supplied to a later deployment, to recreate the environment with the same
+/// binaries, e.g., when bisecting a regression.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct VersionsFile {
+    pub ant_version: Option<Version>,
+    pub antctl_version: Option<Version>,
+    pub antnode_version: Option<Version>,
+    pub architecture: Architecture,
+    /// Set if the binaries were built from a branch, rather than using released versions.
+    pub build: Option<PinnedBuild>,
+    /// The time of the deployment, in the form `%Y-%m-%dT%H:%M:%SZ`.
+    pub created_at: String,
+    pub environment_name: String,
+}
+
+impl VersionsFile {
+    /// Capture the binaries used by a deployment.
+    ///
+    /// Returns `None` for local binaries, which can't be reproduced from a file.
+    pub fn new(options: &DeployOptions, commit_sha: Option<String>) -> Option<Self> {
+        let mut versions = Self {
+            ant_version: None,
+            antctl_version: None,
+            antnode_version: None,
+            architecture: options.architecture,
+            build: None,
+            created_at: chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            environment_name: options.name.clone(),
+        };
+        match &options.binary_option {
+            BinaryOption::BuildFromSource {
+                antnode_features,
+                branch,
+                network_keys,
+                repo_owner,
+            } => {
+                versions.build = Some(PinnedBuild {
+                    antnode_features: antnode_features.clone(),
+                    branch: branch.clone(),
+                    chunk_size: options.chunk_size,
+                    commit_sha,
+                    network_keys: network_keys.clone(),
+                    repo_owner: repo_owner.clone(),
+                });
+            }
+            BinaryOption::Local { .. } => return None,
+            BinaryOption::Versioned {
+                ant_version,
+                antctl_version,
+                antnode_version,
+            } => {
+                versions.ant_version = ant_version.clone();
+                versions.antctl_version = Some(antctl_version.clone());
+                versions.antnode_version = Some(antnode_version.clone());
+            }
+        }
+        Some(versions)
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path)?;
+        serde_json::from_str(&data)
+            .map_err(|err| Error::VersionsFileInvalid(format!("{}: {err}", path.display())))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// The binary option that deploys the same binaries.
+    pub fn get_binary_option(&self) -> Result<BinaryOption> {
+        if let Some(build) = &self.build {
+            return Ok(BinaryOption::BuildFromSource {
+                antnode_features: build.antnode_features.clone(),
+                branch: build.branch.clone(),
+                network_keys: build.network_keys.clone(),
+                repo_owner: build.repo_owner.clone(),
+            });
+        }
+        match (&self.antctl_version, &self.antnode_version) {
+            (Some(antctl_version), Some(antnode_version)) => Ok(BinaryOption::Versioned {
+                ant_version: self.ant_version.clone(),
+                antctl_version: antctl_version.clone(),
+                antnode_version: antnode_version.clone(),
+            }),
+            _ => Err(Error::VersionsFileInvalid(
+                "it must contain either a build or the antctl and antnode versions".to_string(),
+            )),
+        }
+    }
+
+    pub fn print(&self) {
+        println!("Binaries from the {} deployment:", self.environment_name);
+        if let Some(build) = &self.build {
+            println!(
+                "{}/{} at {}",
+                build.repo_owner,
+                build.branch,
+                build
+                    .commit_sha
+                    .as_deref()
+                    .unwrap_or("the head of the branch")
+            );
+            return;
+        }
+        if let Some(version) = &self.ant_version {
+            println!("ant: {version}");
+        }
+        if let Some(version) = &self.antctl_version {
+            println!("antctl: {version}");
+        }
+        if let Some(version) = &self.antnode_version {
+            println!("antnode: {version}");
+        }
+    }
+}
+
+/// The versions file for an environment is written to the data directory, alongside its
+/// inventory.
+pub fn get_versions_file_path(environment_name: &str) -> Result<PathBuf> {
+    let path = dirs_next::data_dir()
+        .ok_or_else(|| Error::CouldNotRetrieveDataDirectory)?
+        .join("safe")
+        .join("testnet-deploy");
+    if !path.exists() {
+        std::fs::create_dir_all(&path)?;
+    }
+    Ok(path.join(format!("{environment_name}-versions.json")))
+}
@@ -0,0 +1,293 @@
+// Copyright (c) 2023, MaidSafe.
+// All rights reserved.
+//
+// This SAFE Network Software is licensed under the BSD-3-Clause license.
+// Please see the LICENSE file for more details.
+
+use crate::{
+    ansible::{
+        extra_vars::ExtraVarsDocBuilder,
+        inventory::{generate_custom_environment_inventory, AnsibleInventoryType},
+        AnsiblePlaybook,
+    },
+    error::{Error, Result},
+    inventory::VirtualMachine,
+    TestnetDeployer,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::Write,
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// This must match `wireguard_port` in the `wireguard` role.
+pub const WIREGUARD_PORT: u16 = 51820;
+/// When this is set, SSH and Ansible connect to the VMs on their overlay addresses.
+pub const WIREGUARD_OVERLAY_ENV_VAR: &str = "WIREGUARD_OVERLAY";
+/// The overlay uses 10.200.0.0/16. The operator is always the first address.
+pub const OVERLAY_OPERATOR_ADDRESS: Ipv4Addr = Ipv4Addr::new(10, 200, 0, 1);
+/// Linux limits interface names to 15 characters.
+const MAX_INTERFACE_NAME_LENGTH: usize = 15;
+
+/// The WireGuard overlay for an environment.
+///
+/// Each VM has a private key that is generated on the VM, and a peer entry for every other VM and
+/// the operator, so management traffic can use the overlay addresses rather than the public IPs.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct WireGuardOverlay {
+    /// The overlay address of each VM, keyed by its public IP address.
+    pub addresses: BTreeMap<IpAddr, Ipv4Addr>,
+    pub operator_public_key: String,
+    /// The public key of each VM, keyed by its public IP address.
+    pub public_keys: BTreeMap<IpAddr, String>,
+}
+
+impl WireGuardOverlay {
+    /// Load the overlay for an environment, or start a new one if it doesn't have one yet.
+    pub fn load(environment_name: &str) -> Result<Self> {
+        let path = get_wireguard_dir_path(environment_name)?.join("overlay.json");
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, environment_name: &str) -> Result<()> {
+        let dir_path = get_wireguard_dir_path(environment_name)?;
+        std::fs::create_dir_all(&dir_path)?;
+        std::fs::write(
+            dir_path.join("overlay.json"),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+
+    /// Give an overlay address to each VM that doesn't have one.
+    ///
+    /// VMs keep their addresses between runs, so the overlay can be extended after an upscale
+    /// without the existing addresses changing. VMs that no longer exist are removed.
+    pub fn assign_addresses(&mut self, vms: &[VirtualMachine]) {
+        let public_ips = vms
+            .iter()
+            .map(|vm| vm.public_ip_addr)
+            .collect::<HashSet<_>>();
+        self.addresses.retain(|ip, _| public_ips.contains(ip));
+        self.public_keys.retain(|ip, _| public_ips.contains(ip));
+
+        let mut used = self.addresses.values().copied().collect::<HashSet<_>>();
+        let mut next_host = u32::from(OVERLAY_OPERATOR_ADDRESS) + 1;
+        for vm in vms.iter() {
+            if self.addresses.contains_key(&vm.public_ip_addr) {
+                continue;
+            }
+            while used.contains(&Ipv4Addr::from(next_host)) {
+                next_host += 1;
+            }
+            let address = Ipv4Addr::from(next_host);
+            used.insert(address);
+            self.addresses.insert(vm.public_ip_addr, address);
+        }
+    }
+
+    /// Write the configuration for the machine running the deployer.
+    ///
+    /// The operator connects to every VM, and keeps the connections alive, since it is usually
+    /// behind NAT and the VMs can't connect to it.
+    pub fn write_operator_config(&self, private_key: &str, path: &Path) -> Result<()> {
+        let mut config = format!(
+            "[Interface]\nAddress = {OVERLAY_OPERATOR_ADDRESS}/16\nPrivateKey = {private_key}\n"
+        );
+        for (public_ip, address) in self.addresses.iter() {
+            let Some(public_key) = self.public_keys.get(public_ip) else {
+                continue;
+            };
+            config.push_str(&format!(
+                "\n[Peer]\nPublicKey = {public_key}\nAllowedIPs = {address}/32\n\
+                Endpoint = {public_ip}:{WIREGUARD_PORT}\nPersistentKeepalive = 25\n"
+            ));
+        }
+        write_private_file(path, &config)
+    }
+
+    /// The address to connect to each VM on, keyed by its public IP address.
+    pub fn get_management_addresses(&self) -> BTreeMap<IpAddr, IpAddr> {
+        self.addresses
+            .iter()
+            .map(|(public_ip, address)| (*public_ip, IpAddr::V4(*address)))
+            .collect()
+    }
+
+    pub fn print(&self, vms: &[VirtualMachine]) {
+        println!("Operator: {OVERLAY_OPERATOR_ADDRESS}");
+        for vm in vms.iter() {
+            if let Some(address) = self.addresses.get(&vm.public_ip_addr) {
+                println!("{}: {address}", vm.name);
+            }
+        }
+    }
+}
+
+/// The keys and configuration for an environment's overlay are kept in the data directory,
+/// alongside its inventory. The directory is created when the overlay is set up.
+pub fn get_wireguard_dir_path(environment_name: &str) -> Result<PathBuf> {
+    let path = dirs_next::data_dir()
+        .ok_or_else(|| Error::CouldNotRetrieveDataDirectory)?
+        .join("safe")
+        .join("testnet-deploy")
+        .join(format!("{environment_name}-wireguard"));
+    Ok(path)
+}
+
+/// The interface is named after the environment, so the overlays for several environments can be
+/// up at the same time.
+pub fn get_operator_config_path(environment_name: &str) -> Result<PathBuf> {
+    let interface_name = format!("wg-{}", environment_name.to_lowercase())
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .take(MAX_INTERFACE_NAME_LENGTH)
+        .collect::<String>();
+    Ok(get_wireguard_dir_path(environment_name)?.join(format!("{interface_name}.conf")))
+}
+
+/// Get the private key of the operator for an environment, generating it the first time.
+///
+/// Returns the private and public keys.
+pub fn get_operator_key_pair(environment_name: &str) -> Result<(String, String)> {
+    let path = get_wireguard_dir_path(environment_name)?.join("operator.key");
+    let private_key = if path.exists() {
+        std::fs::read_to_string(&path)?.trim().to_string()
+    } else {
+        let private_key = run_wg(&["genkey"], None)?;
+        write_private_file(&path, &private_key)?;
+        private_key
+    };
+    let public_key = run_wg(&["pubkey"], Some(&private_key))?;
+    Ok((private_key, public_key))
+}
+
+fn run_wg(args: &[&str], input: Option<&str>) -> Result<String> {
+    let mut child = Command::new("wg")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|_| Error::ToolBinaryNotFound("wg".to_string()))?;
+    if let Some(input) = input {
+        child
+            .stdin
+            .take()
+            .ok_or_else(|| Error::WireGuardKeyGenerationFailed("stdin unavailable".to_string()))?
+            .write_all(input.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(Error::WireGuardKeyGenerationFailed(
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn write_private_file(path: &Path, contents: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, contents)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut permissions = std::fs::metadata(path)?.permissions();
+        permissions.set_mode(0o600); // rw-------
+        std::fs::set_permissions(path, permissions)?;
+    }
+    Ok(())
+}
+
+impl TestnetDeployer {
+    /// Join every VM with a public IP, and the machine running the deployer, to a WireGuard
+    /// overlay.
+    ///
+    /// The private nodes are not included, since they can't be reached directly. Running this
+    /// again, e.g., after an upscale, adds the new VMs and keeps the existing addresses and keys.
+    ///
+    /// Returns the path of the configuration for the machine running the deployer, which is
+    /// brought up with `wg-quick`.
+    pub fn setup_wireguard(&self) -> Result<PathBuf> {
+        let mut vms = Vec::new();
+        for inventory_type in [
+            AnsibleInventoryType::ArtifactProxy,
+            AnsibleInventoryType::Build,
+            AnsibleInventoryType::EvmNodes,
+            AnsibleInventoryType::Genesis,
+            AnsibleInventoryType::Monitoring,
+            AnsibleInventoryType::NatGateway,
+            AnsibleInventoryType::Nodes,
+            AnsibleInventoryType::PeerCacheNodes,
+            AnsibleInventoryType::Uploaders,
+        ] {
+            vms.extend(
+                self.ansible_provisioner
+                    .ansible_runner
+                    .get_inventory(inventory_type, false)?,
+            );
+        }
+        if vms.is_empty() {
+            return Err(Error::EnvironmentDoesNotExist(
+                self.environment_name.clone(),
+            ));
+        }
+
+        let (operator_private_key, operator_public_key) =
+            get_operator_key_pair(&self.environment_name)?;
+        let mut overlay = WireGuardOverlay::load(&self.environment_name)?;
+        overlay.operator_public_key = operator_public_key;
+        overlay.assign_addresses(&vms);
+
+        let public_keys_path =
+            get_wireguard_dir_path(&self.environment_name)?.join("public_keys.json");
+        let mut extra_vars = ExtraVarsDocBuilder::default();
+        extra_vars.add_serde_value(
+            "wireguard_addresses",
+            serde_json::to_value(&overlay.addresses)?,
+        );
+        extra_vars.add_variable(
+            "wireguard_operator_address",
+            &OVERLAY_OPERATOR_ADDRESS.to_string(),
+        );
+        extra_vars.add_variable(
+            "wireguard_operator_public_key",
+            &overlay.operator_public_key,
+        );
+        extra_vars.add_variable(
+            "wireguard_public_keys_path",
+            &public_keys_path.to_string_lossy(),
+        );
+
+        println!("Running the wireguard playbook against {} VMs", vms.len());
+        generate_custom_environment_inventory(
+            &vms,
+            &self.environment_name,
+            &self
+                .ansible_provisioner
+                .ansible_runner
+                .working_directory_path
+                .join("inventory"),
+        )?;
+        self.ansible_provisioner.ansible_runner.run_playbook(
+            AnsiblePlaybook::WireGuard,
+            AnsibleInventoryType::Custom,
+            Some(extra_vars.build()),
+        )?;
+
+        overlay.public_keys = serde_json::from_str(&std::fs::read_to_string(&public_keys_path)?)?;
+        overlay.save(&self.environment_name)?;
+        let config_path = get_operator_config_path(&self.environment_name)?;
+        overlay.write_operator_config(&operator_private_key, &config_path)?;
+        overlay.print(&vms);
+        Ok(config_path)
+    }
+}